@@ -0,0 +1,182 @@
+//! Client-side flow of an integrator: create the stake user of an owner,
+//! stake, refresh as time passes, claim the rewards, then unstake,
+//! each step a transaction built from the `instruction` builders and signed
+//! as it would be for RPC. It runs on the in-process ledger of `common`, with
+//! the pool made by `Ledger::create_pool`:
+//!
+//! ```bash
+//! $ cargo run --example client_flow
+//! ```
+
+mod common;
+
+use borsh::BorshDeserialize;
+use common::Ledger;
+use reward_pool::{
+    instruction::{
+        claim, claim_if_any, create_stake_user_idempotent, refresh, stake, unstake,
+        with_global_config,
+    },
+    pda::{find_pool_authority_address, find_stake_user_address},
+    state::{StakeUser, DAILY_TS},
+};
+use solana_program::{
+    entrypoint::ProgramResult, native_token::LAMPORTS_PER_SOL, program_error::ProgramError,
+};
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Decimals of the stake mint, which the reward mint mirrors
+const DECIMALS: u8 = 6;
+/// One whole token in raw amounts
+const TOKEN: u64 = 10u64.pow(DECIMALS as u32);
+
+/// Amounts claimed and held at the end of the flow
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub claimed: u64,
+    pub claim_again: ProgramResult,
+    pub rewards: u64,
+    pub unstaked: u64,
+}
+
+/// Stake an amount for a day in a pool rewarding one reward token a day per
+/// token staked, then claim and unstake it all
+pub fn run(ledger: &mut Ledger, amount: u64) -> Result<Outcome, ProgramError> {
+    let program_id = reward_pool::id();
+    let payer = Keypair::new();
+    let owner = Keypair::new();
+    ledger.airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL);
+
+    let stake_mint = ledger.create_mint(&payer, DECIMALS);
+    let pool = ledger.create_pool(&payer, &stake_mint, 1, 1);
+    let stake_pool = ledger.pool(&pool);
+    let source = ledger.create_token_account(&payer, &stake_mint, &owner.pubkey(), amount);
+    let reward_account =
+        ledger.create_token_account(&payer, &stake_pool.reward_mint, &owner.pubkey(), 0);
+
+    // the stake user is a PDA of the pool and owner, created by whoever pays
+    // for it, so creating it on every first stake is safe
+    let (stake_user, _) = find_stake_user_address(&program_id, &pool, &owner.pubkey());
+    ledger.send(
+        &[
+            create_stake_user_idempotent(program_id, pool, owner.pubkey(), payer.pubkey())?,
+            with_global_config(
+                program_id,
+                stake(
+                    program_id,
+                    pool,
+                    &stake_pool,
+                    stake_user,
+                    owner.pubkey(),
+                    owner.pubkey(),
+                    source,
+                    stake_mint,
+                    amount,
+                )?,
+            ),
+        ],
+        &payer,
+        &[&owner],
+    )?;
+
+    // anyone may refresh, accruing the rewards owed up to now
+    ledger.warp(DAILY_TS as i64);
+    ledger.send(&[refresh(program_id, pool, vec![stake_user])?], &payer, &[])?;
+    let owed = ledger.unpack::<StakeUser>(&stake_user).reward_owed;
+    println!("owed {} after a day", owed);
+
+    // claims report the amount they paid as return data, which simulating
+    // ClaimIfAny also previews without failing when nothing is owed
+    let (authority, _) = find_pool_authority_address(&program_id, &pool);
+    ledger.send(
+        &[with_global_config(
+            program_id,
+            claim_if_any(
+                program_id,
+                pool,
+                stake_user,
+                owner.pubkey(),
+                authority,
+                stake_pool.reward_mint,
+                reward_account,
+            )?,
+        )],
+        &payer,
+        &[&owner],
+    )?;
+    let claimed = match ledger.return_data() {
+        Some((return_program_id, data)) if return_program_id == program_id => {
+            u64::try_from_slice(&data)?
+        }
+        _ => 0,
+    };
+    println!("claimed {}", claimed);
+
+    // nothing is owed anymore, so Claim fails where ClaimIfAny would not
+    let claim_again = ledger.send(
+        &[with_global_config(
+            program_id,
+            claim(
+                program_id,
+                pool,
+                stake_user,
+                owner.pubkey(),
+                authority,
+                stake_pool.reward_mint,
+                reward_account,
+            )?,
+        )],
+        &payer,
+        &[&owner],
+    );
+
+    ledger.send(
+        &[with_global_config(
+            program_id,
+            unstake(
+                program_id,
+                pool,
+                stake_user,
+                authority,
+                owner.pubkey(),
+                stake_pool.reserved,
+                source,
+                amount,
+            )?,
+        )],
+        &payer,
+        &[&owner],
+    )?;
+
+    Ok(Outcome {
+        claimed,
+        claim_again,
+        rewards: ledger.token_balance(&reward_account),
+        unstaked: ledger.token_balance(&source),
+    })
+}
+
+fn main() -> Result<(), ProgramError> {
+    let outcome = run(&mut Ledger::new(), 100 * TOKEN)?;
+    println!("{:?}", outcome);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reward_pool::error::CustomError;
+
+    #[test]
+    fn test_client_flow() {
+        assert_eq!(
+            run(&mut Ledger::new(), 100 * TOKEN),
+            Ok(Outcome {
+                claimed: 100 * TOKEN,
+                claim_again: Err(CustomError::InsufficientClaimAmount.into()),
+                rewards: 100 * TOKEN,
+                unstaked: 100 * TOKEN,
+            })
+        );
+    }
+}
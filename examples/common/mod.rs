@@ -0,0 +1,487 @@
+//! In-process ledger the examples run their transactions on, so they execute
+//! under `cargo test` without a validator. It runs the reward pool processor,
+//! the token programs, a system program covering what the examples call and
+//! any example program natively, with syscall stubs routing cross-program
+//! invocations between them. Signatures are verified, fees and compute are
+//! not.
+//!
+//! Shared by the examples through `mod common;`, each using only part of it.
+
+#![allow(dead_code)]
+
+use std::{cell::RefCell, collections::HashMap};
+
+use reward_pool::{
+    instruction::{create_stake_pool, InitData},
+    pda::find_pool_authority_address,
+    state::{Pool, DAILY_TS},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::{ProcessInstruction, ProgramResult, SUCCESS},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack},
+    program_stubs::{self, SyscallStubs},
+    program_utils::limited_deserialize,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::{self, SystemInstruction},
+    system_program, sysvar,
+};
+use solana_sdk::{
+    account::{create_account_for_test, Account},
+    hash::Hash,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+thread_local! {
+    static PROGRAMS: RefCell<HashMap<Pubkey, ProcessInstruction>> = RefCell::new(HashMap::new());
+    /// Programs of the instruction being processed, the caller of the last
+    /// one being the one before
+    static INVOKE_STACK: RefCell<Vec<Pubkey>> = const { RefCell::new(Vec::new()) };
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+struct LedgerSyscallStubs;
+
+impl SyscallStubs for LedgerSyscallStubs {
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CLOCK.with(|clock| clock.borrow().clone());
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        INVOKE_STACK.with(|stack| stack.borrow().len() as u64)
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        let program_id = INVOKE_STACK.with(|stack| *stack.borrow().last().unwrap());
+        RETURN_DATA.with(|return_data| {
+            *return_data.borrow_mut() = (!data.is_empty()).then(|| (program_id, data.to_vec()))
+        });
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    /// Pass the accounts of the instruction on from the caller's, signed by
+    /// the caller's signers and the addresses its seeds derive
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = INVOKE_STACK.with(|stack| *stack.borrow().last().unwrap());
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let mut callee_account_infos = vec![];
+        for meta in &instruction.accounts {
+            let account_info = account_infos
+                .iter()
+                .find(|account_info| *account_info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_signer && !account_info.is_signer && !signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if meta.is_writable && !account_info.is_writable {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let mut callee_account_info = account_info.clone();
+            callee_account_info.is_signer = meta.is_signer;
+            callee_account_info.is_writable = meta.is_writable;
+            callee_account_infos.push(callee_account_info);
+        }
+        process_instruction(
+            &instruction.program_id,
+            &callee_account_infos,
+            &instruction.data,
+        )
+    }
+}
+
+fn process_instruction(
+    program_id: &Pubkey,
+    account_infos: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let process = PROGRAMS
+        .with(|programs| programs.borrow().get(program_id).copied())
+        .ok_or(ProgramError::IncorrectProgramId)?;
+    INVOKE_STACK.with(|stack| stack.borrow_mut().push(*program_id));
+    let result = process(program_id, account_infos, data);
+    INVOKE_STACK.with(|stack| stack.borrow_mut().pop());
+    result
+}
+
+/// The system program instructions the examples and the programs they call
+/// use. Allocations replace the account data with a new zeroed buffer, which
+/// is leaked, as account infos only borrow their data.
+fn process_system_instruction(
+    _program_id: &Pubkey,
+    account_infos: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let transfer = |from: &AccountInfo, to: &AccountInfo, lamports: u64| {
+        if !from.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let from_lamports = from.lamports();
+        **from.try_borrow_mut_lamports()? = from_lamports
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        **to.try_borrow_mut_lamports()? += lamports;
+        Ok(())
+    };
+    let allocate = |account: &AccountInfo, space: u64| {
+        if !account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if !account.data_is_empty() || !system_program::check_id(account.owner) {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        *account.try_borrow_mut_data()? = Box::leak(vec![0; space as usize].into_boxed_slice());
+        Ok(())
+    };
+    let assign = |account: &AccountInfo, owner: &Pubkey| {
+        if !account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        account.assign(owner);
+        Ok(())
+    };
+
+    match limited_deserialize(data, 1024).map_err(|_| ProgramError::InvalidInstructionData)? {
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            if account_infos[1].lamports() != 0 {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            transfer(&account_infos[0], &account_infos[1], lamports)?;
+            allocate(&account_infos[1], space)?;
+            assign(&account_infos[1], &owner)
+        }
+        SystemInstruction::Transfer { lamports } => {
+            transfer(&account_infos[0], &account_infos[1], lamports)
+        }
+        SystemInstruction::Allocate { space } => allocate(&account_infos[0], space),
+        SystemInstruction::Assign { owner } => assign(&account_infos[0], &owner),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Accounts and programs of the examples, processing transactions one at a
+/// time and keeping their changes only when all instructions succeed
+pub struct Ledger {
+    accounts: HashMap<Pubkey, Account>,
+}
+
+impl Ledger {
+    /// Ledger with the reward pool program at its declared id, the token
+    /// programs, the system program and the clock and rent sysvars
+    pub fn new() -> Self {
+        static STUBS: std::sync::Once = std::sync::Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(LedgerSyscallStubs));
+        });
+
+        let mut ledger = Self {
+            accounts: HashMap::new(),
+        };
+        ledger.add_program(system_program::id(), process_system_instruction);
+        ledger.add_program(spl_token::id(), spl_token::processor::Processor::process);
+        ledger.add_program(
+            spl_token_2022::id(),
+            spl_token_2022::processor::Processor::process,
+        );
+        ledger.add_program(reward_pool::id(), reward_pool::processor::process);
+        ledger.set_clock(Clock::default());
+        ledger.accounts.insert(
+            sysvar::rent::id(),
+            create_account_for_test(&Rent::default()),
+        );
+        ledger
+    }
+
+    /// Add a native program, like an example's own
+    pub fn add_program(&mut self, program_id: Pubkey, process: ProcessInstruction) {
+        PROGRAMS.with(|programs| programs.borrow_mut().insert(program_id, process));
+    }
+
+    fn set_clock(&mut self, clock: Clock) {
+        self.accounts
+            .insert(sysvar::clock::id(), create_account_for_test(&clock));
+        CLOCK.with(|current| *current.borrow_mut() = clock);
+    }
+
+    /// Move the clock forward
+    pub fn warp(&mut self, seconds: i64) {
+        let mut clock = CLOCK.with(|clock| clock.borrow().clone());
+        clock.unix_timestamp += seconds;
+        clock.slot += seconds as u64 * 5 / 2;
+        self.set_clock(clock);
+    }
+
+    pub fn airdrop(&mut self, pubkey: &Pubkey, lamports: u64) {
+        self.accounts.entry(*pubkey).or_default().lamports += lamports;
+    }
+
+    /// Blockhash transactions are signed with, never expiring
+    pub fn latest_blockhash(&self) -> Hash {
+        Hash::default()
+    }
+
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&Account> {
+        self.accounts.get(pubkey)
+    }
+
+    /// State of an account, panicking when missing or invalid
+    pub fn unpack<T: Pack + IsInitialized>(&self, pubkey: &Pubkey) -> T {
+        T::unpack(&self.accounts[pubkey].data).unwrap()
+    }
+
+    /// Return data of the last transaction
+    pub fn return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    /// Verify and process a transaction
+    pub fn process_transaction(&mut self, transaction: &Transaction) -> ProgramResult {
+        transaction
+            .verify()
+            .map_err(|_| ProgramError::MissingRequiredSignature)?;
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = None);
+
+        let message = &transaction.message;
+        let keys = &message.account_keys;
+        let mut accounts = keys
+            .iter()
+            .map(|key| self.accounts.get(key).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let updated_accounts = {
+            let account_infos = keys
+                .iter()
+                .zip(accounts.iter_mut())
+                .enumerate()
+                .map(|(index, (key, account))| {
+                    AccountInfo::new(
+                        key,
+                        message.is_signer(index),
+                        message.is_writable(index),
+                        &mut account.lamports,
+                        &mut account.data,
+                        &account.owner,
+                        account.executable,
+                        account.rent_epoch,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            for instruction in &message.instructions {
+                let instruction_account_infos = instruction
+                    .accounts
+                    .iter()
+                    .map(|index| account_infos[*index as usize].clone())
+                    .collect::<Vec<_>>();
+                process_instruction(
+                    &keys[instruction.program_id_index as usize],
+                    &instruction_account_infos,
+                    &instruction.data,
+                )?;
+            }
+
+            account_infos
+                .iter()
+                .map(|account_info| Account {
+                    lamports: account_info.lamports(),
+                    data: account_info.data.borrow().to_vec(),
+                    owner: *account_info.owner,
+                    executable: account_info.executable,
+                    rent_epoch: account_info.rent_epoch,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (key, account) in keys.iter().zip(updated_accounts) {
+            if account.lamports == 0 {
+                self.accounts.remove(key);
+            } else {
+                self.accounts.insert(*key, account);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sign with the payer and the other signers and process
+    pub fn send(
+        &mut self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        signers: &[&Keypair],
+    ) -> ProgramResult {
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &all_signers,
+            self.latest_blockhash(),
+        );
+        self.process_transaction(&transaction)
+    }
+
+    /// Mint of the token program with the payer as mint authority
+    pub fn create_mint(&mut self, payer: &Keypair, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        self.send(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &mint.pubkey(),
+                    Rent::default().minimum_balance(Mint::LEN),
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &payer.pubkey(),
+                    None,
+                    decimals,
+                )
+                .unwrap(),
+            ],
+            payer,
+            &[&mint],
+        )
+        .unwrap();
+        mint.pubkey()
+    }
+
+    /// Token account of an owner, funded with an amount minted by the payer
+    /// when given one
+    pub fn create_token_account(
+        &mut self,
+        payer: &Keypair,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let account = Keypair::new();
+        let mut instructions = vec![
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &account.pubkey(),
+                Rent::default().minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+        if amount > 0 {
+            instructions.push(
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    mint,
+                    &account.pubkey(),
+                    &payer.pubkey(),
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+            );
+        }
+        self.send(&instructions, payer, &[&account]).unwrap();
+        account.pubkey()
+    }
+
+    pub fn token_balance(&self, pubkey: &Pubkey) -> u64 {
+        self.unpack::<TokenAccount>(pubkey).amount
+    }
+
+    /// Pool of a stake mint administered by the payer, minting rewards of a
+    /// new reward mint at a daily rate per token staked. The reserve and
+    /// reward mint accounts are only allocated, CreatePool initializes them.
+    pub fn create_pool(
+        &mut self,
+        payer: &Keypair,
+        stake_mint: &Pubkey,
+        reward_numerator: u64,
+        reward_denominator: u64,
+    ) -> Pubkey {
+        let pool = Keypair::new();
+        let reserve = Keypair::new();
+        let reward_mint = Keypair::new();
+        let (authority, bump_seed) =
+            find_pool_authority_address(&reward_pool::id(), &pool.pubkey());
+        let create_account = |pubkey: &Pubkey, space: usize, owner: &Pubkey| {
+            system_instruction::create_account(
+                &payer.pubkey(),
+                pubkey,
+                Rent::default().minimum_balance(space),
+                space as u64,
+                owner,
+            )
+        };
+
+        self.send(
+            &[
+                create_account(&pool.pubkey(), Pool::LEN, &reward_pool::id()),
+                create_account(&reserve.pubkey(), TokenAccount::LEN, &spl_token::id()),
+                create_account(&reward_mint.pubkey(), Mint::LEN, &spl_token::id()),
+                create_stake_pool(
+                    reward_pool::id(),
+                    pool.pubkey(),
+                    authority,
+                    *stake_mint,
+                    reserve.pubkey(),
+                    reward_mint.pubkey(),
+                    payer.pubkey(),
+                    payer.pubkey(),
+                    payer.pubkey(),
+                    InitData {
+                        bump_seed,
+                        reward_numerator,
+                        reward_denominator,
+                        reward_period: DAILY_TS,
+                        reject_freeze_authority: true,
+                    },
+                )
+                .unwrap(),
+            ],
+            payer,
+            &[&pool, &reserve, &reward_mint],
+        )
+        .unwrap();
+        pool.pubkey()
+    }
+
+    pub fn pool(&self, pubkey: &Pubkey) -> Pool {
+        self.unpack(pubkey)
+    }
+}
@@ -0,0 +1,455 @@
+//! On-chain consumer program staking in a reward pool through cross-program
+//! invocations. A vault PDA of this program, `["vault", pool]`, owns a stake
+//! user of the pool: Deposit moves a depositor's tokens into the vault's token
+//! account and stakes them, and Harvest claims the vault's rewards into its
+//! reward token account, both signing for the vault with its seeds.
+//!
+//! Deployed as its own crate, it depends on `reward-pool` with the
+//! `no-entrypoint` feature and declares `entrypoint!(process_instruction)`.
+//! Here it runs natively on the in-process ledger of `common`:
+//!
+//! ```bash
+//! $ cargo run --example staking_vault
+//! ```
+
+mod common;
+
+use common::Ledger;
+use reward_pool::{
+    instruction::{claim, create_stake_user_idempotent, stake, with_global_config},
+    pda::{find_global_config_address, find_pool_authority_address, find_stake_user_address},
+    state::{Pool, DAILY_TS},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    native_token::LAMPORTS_PER_SOL,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    sysvar::clock,
+};
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+
+/// Id of the example program on the ledger
+const VAULT_PROGRAM_ID: Pubkey = Pubkey::new_from_array([7u8; 32]);
+
+/// Instructions of the vault, tagged by their first byte
+pub enum VaultInstruction {
+    /// Transfer an amount from the depositor into the vault token account and
+    /// stake it
+    ///
+    /// 0. `[writable]` Pool
+    /// 1. `[writable]` Stake user of the vault
+    /// 2. `[]` Vault
+    /// 3. `[writable]` Vault token account of the stake mint
+    /// 4. `[signer]` Depositor
+    /// 5. `[writable]` Depositor token account of the stake mint
+    /// 6. `[writable]` Pool reserve
+    /// 7. `[]` Clock sysvar
+    /// 8. `[]` Token program
+    /// 9. `[]` Global config of the reward pool program
+    /// 10. `[]` Reward pool program
+    Deposit { amount: u64 },
+    /// Claim the rewards of the vault into its reward token account
+    ///
+    /// 0. `[writable]` Pool
+    /// 1. `[writable]` Stake user of the vault
+    /// 2. `[]` Vault
+    /// 3. `[]` Pool authority
+    /// 4. `[writable]` Reward mint
+    /// 5. `[writable]` Vault token account of the reward mint
+    /// 6. `[]` Clock sysvar
+    /// 7. `[]` Token program
+    /// 8. `[]` Global config of the reward pool program
+    /// 9. `[]` Reward pool program
+    Harvest,
+}
+
+impl VaultInstruction {
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        match data.split_first() {
+            Some((0, rest)) if rest.len() == 8 => Ok(Self::Deposit {
+                amount: u64::from_le_bytes(*arrayref::array_ref![rest, 0, 8]),
+            }),
+            Some((1, [])) => Ok(Self::Harvest),
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        match self {
+            Self::Deposit { amount } => [&[0], &amount.to_le_bytes()[..]].concat(),
+            Self::Harvest => vec![1],
+        }
+    }
+}
+
+pub fn find_vault_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vault", pool.as_ref()], program_id)
+}
+
+/// Vault account checked against the pool, with its bump seed
+fn check_vault(
+    program_id: &Pubkey,
+    stake_pool_info: &AccountInfo,
+    vault_info: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (vault, bump_seed) = find_vault_address(program_id, stake_pool_info.key);
+    if *vault_info.key != vault {
+        msg!("Vault {} is not the vault of the pool", vault_info.key);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(bump_seed)
+}
+
+/// Token account owned by the vault
+fn check_vault_token_account(
+    vault_info: &AccountInfo,
+    token_account_info: &AccountInfo,
+) -> ProgramResult {
+    if *token_account_info.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = TokenAccount::unpack(&token_account_info.data.borrow())?;
+    if token_account.owner != *vault_info.key {
+        msg!(
+            "Token account {} is not the vault's",
+            token_account_info.key
+        );
+        return Err(ProgramError::IllegalOwner);
+    }
+    Ok(())
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let vault_info = next_account_info(account_info_iter)?;
+    let bump_seed = check_vault(program_id, stake_pool_info, vault_info)?;
+    let vault_seeds: &[&[u8]] = &[b"vault", stake_pool_info.key.as_ref(), &[bump_seed]];
+
+    // the reward pool program checks the pool and stake user itself, and that
+    // the vault owns the stake user
+    if *stake_pool_info.owner != reward_pool::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+
+    match VaultInstruction::unpack(instruction_data)? {
+        VaultInstruction::Deposit { amount } => {
+            let vault_token_info = next_account_info(account_info_iter)?;
+            let depositor_info = next_account_info(account_info_iter)?;
+            let source_info = next_account_info(account_info_iter)?;
+            let reserve_info = next_account_info(account_info_iter)?;
+            let clock_info = next_account_info(account_info_iter)?;
+            let token_program_info = next_account_info(account_info_iter)?;
+            let global_config_info = next_account_info(account_info_iter)?;
+            let reward_pool_program_info = next_account_info(account_info_iter)?;
+            check_vault_token_account(vault_info, vault_token_info)?;
+            if *token_program_info.key != spl_token::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+
+            // the depositor's signature carries over to the token program
+            invoke(
+                &spl_token::instruction::transfer(
+                    token_program_info.key,
+                    source_info.key,
+                    vault_token_info.key,
+                    depositor_info.key,
+                    &[],
+                    amount,
+                )?,
+                &[
+                    source_info.clone(),
+                    vault_token_info.clone(),
+                    depositor_info.clone(),
+                    token_program_info.clone(),
+                ],
+            )?;
+
+            // the vault is both the stake owner and the transfer authority of
+            // its token account, signed for with its seeds
+            let instruction = with_global_config(
+                reward_pool::id(),
+                stake(
+                    reward_pool::id(),
+                    *stake_pool_info.key,
+                    &stake_pool,
+                    *stake_user_info.key,
+                    *vault_info.key,
+                    *vault_info.key,
+                    *vault_token_info.key,
+                    stake_pool.stake_token_mint,
+                    amount,
+                )?,
+            );
+            invoke_signed(
+                &instruction,
+                &[
+                    stake_pool_info.clone(),
+                    stake_user_info.clone(),
+                    vault_info.clone(),
+                    vault_token_info.clone(),
+                    reserve_info.clone(),
+                    clock_info.clone(),
+                    token_program_info.clone(),
+                    global_config_info.clone(),
+                    reward_pool_program_info.clone(),
+                ],
+                &[vault_seeds],
+            )
+        }
+        VaultInstruction::Harvest => {
+            let authority_info = next_account_info(account_info_iter)?;
+            let reward_mint_info = next_account_info(account_info_iter)?;
+            let destination_info = next_account_info(account_info_iter)?;
+            let clock_info = next_account_info(account_info_iter)?;
+            let token_program_info = next_account_info(account_info_iter)?;
+            let global_config_info = next_account_info(account_info_iter)?;
+            let reward_pool_program_info = next_account_info(account_info_iter)?;
+            check_vault_token_account(vault_info, destination_info)?;
+
+            let instruction = with_global_config(
+                reward_pool::id(),
+                claim(
+                    reward_pool::id(),
+                    *stake_pool_info.key,
+                    *stake_user_info.key,
+                    *vault_info.key,
+                    *authority_info.key,
+                    *reward_mint_info.key,
+                    *destination_info.key,
+                )?,
+            );
+            invoke_signed(
+                &instruction,
+                &[
+                    stake_pool_info.clone(),
+                    stake_user_info.clone(),
+                    vault_info.clone(),
+                    authority_info.clone(),
+                    reward_mint_info.clone(),
+                    destination_info.clone(),
+                    clock_info.clone(),
+                    token_program_info.clone(),
+                    global_config_info.clone(),
+                    reward_pool_program_info.clone(),
+                ],
+                &[vault_seeds],
+            )
+        }
+    }
+}
+
+/// Build a Deposit instruction
+pub fn deposit(
+    program_id: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    stake_pool: &Pool,
+    vault_token_pubkey: &Pubkey,
+    depositor_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, stake_pool_pubkey);
+    let (stake_user, _) = find_stake_user_address(&reward_pool::id(), stake_pool_pubkey, &vault);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*stake_pool_pubkey, false),
+            AccountMeta::new(stake_user, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(*vault_token_pubkey, false),
+            AccountMeta::new_readonly(*depositor_pubkey, true),
+            AccountMeta::new(*source_pubkey, false),
+            AccountMeta::new(stake_pool.reserved, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(find_global_config_address(&reward_pool::id()).0, false),
+            AccountMeta::new_readonly(reward_pool::id(), false),
+        ],
+        data: VaultInstruction::Deposit { amount }.pack(),
+    }
+}
+
+/// Build a Harvest instruction
+pub fn harvest(
+    program_id: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    stake_pool: &Pool,
+    destination_pubkey: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, stake_pool_pubkey);
+    let (stake_user, _) = find_stake_user_address(&reward_pool::id(), stake_pool_pubkey, &vault);
+    let (authority, _) = find_pool_authority_address(&reward_pool::id(), stake_pool_pubkey);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*stake_pool_pubkey, false),
+            AccountMeta::new(stake_user, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new_readonly(authority, false),
+            AccountMeta::new(stake_pool.reward_mint, false),
+            AccountMeta::new(*destination_pubkey, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(find_global_config_address(&reward_pool::id()).0, false),
+            AccountMeta::new_readonly(reward_pool::id(), false),
+        ],
+        data: VaultInstruction::Harvest.pack(),
+    }
+}
+
+/// Vault of a new pool with a depositor holding stake tokens
+struct Setup {
+    ledger: Ledger,
+    payer: Keypair,
+    pool: Pubkey,
+    stake_pool: Pool,
+    vault_token: Pubkey,
+    vault_rewards: Pubkey,
+    depositor: Keypair,
+    source: Pubkey,
+}
+
+impl Setup {
+    fn new(deposit_tokens: u64) -> Self {
+        let mut ledger = Ledger::new();
+        ledger.add_program(VAULT_PROGRAM_ID, process_instruction);
+        let payer = Keypair::new();
+        let depositor = Keypair::new();
+        ledger.airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL);
+
+        let stake_mint = ledger.create_mint(&payer, 0);
+        let pool = ledger.create_pool(&payer, &stake_mint, 1, 1);
+        let stake_pool = ledger.pool(&pool);
+        let (vault, _) = find_vault_address(&VAULT_PROGRAM_ID, &pool);
+        let vault_token = ledger.create_token_account(&payer, &stake_mint, &vault, 0);
+        let vault_rewards = ledger.create_token_account(&payer, &stake_pool.reward_mint, &vault, 0);
+        let source =
+            ledger.create_token_account(&payer, &stake_mint, &depositor.pubkey(), deposit_tokens);
+
+        // the stake user owner doesn't sign its creation, so the vault's is
+        // created by the client rather than through the vault program
+        ledger
+            .send(
+                &[
+                    create_stake_user_idempotent(reward_pool::id(), pool, vault, payer.pubkey())
+                        .unwrap(),
+                ],
+                &payer,
+                &[],
+            )
+            .unwrap();
+
+        Setup {
+            ledger,
+            payer,
+            pool,
+            stake_pool,
+            vault_token,
+            vault_rewards,
+            depositor,
+            source,
+        }
+    }
+
+    fn deposit(&mut self, amount: u64) -> ProgramResult {
+        let instruction = deposit(
+            &VAULT_PROGRAM_ID,
+            &self.pool,
+            &self.stake_pool,
+            &self.vault_token,
+            &self.depositor.pubkey(),
+            &self.source,
+            amount,
+        );
+        self.ledger
+            .send(&[instruction], &self.payer, &[&self.depositor])
+    }
+
+    fn harvest(&mut self, destination: &Pubkey) -> ProgramResult {
+        let instruction = harvest(&VAULT_PROGRAM_ID, &self.pool, &self.stake_pool, destination);
+        self.ledger.send(&[instruction], &self.payer, &[])
+    }
+}
+
+fn main() -> ProgramResult {
+    let mut setup = Setup::new(1_000);
+    setup.deposit(1_000)?;
+    setup.ledger.warp(DAILY_TS as i64);
+    setup.harvest(&setup.vault_rewards.clone())?;
+    println!(
+        "vault staked 1000 and harvested {} after a day",
+        setup.ledger.token_balance(&setup.vault_rewards)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reward_pool::state::StakeUser;
+
+    #[test]
+    fn test_staking_vault() {
+        let mut setup = Setup::new(1_000);
+        setup.deposit(400).unwrap();
+        setup.deposit(600).unwrap();
+        let (vault, _) = find_vault_address(&VAULT_PROGRAM_ID, &setup.pool);
+        let (stake_user, _) = find_stake_user_address(&reward_pool::id(), &setup.pool, &vault);
+        assert_eq!(
+            setup.ledger.unpack::<StakeUser>(&stake_user).stake_amount,
+            1_000
+        );
+        assert_eq!(
+            setup.ledger.token_balance(&setup.stake_pool.reserved),
+            1_000
+        );
+        assert_eq!(setup.ledger.token_balance(&setup.source), 0);
+
+        setup.ledger.warp(DAILY_TS as i64);
+        setup.harvest(&setup.vault_rewards.clone()).unwrap();
+        assert_eq!(setup.ledger.token_balance(&setup.vault_rewards), 1_000);
+
+        // rewards only go to the vault
+        let depositor_rewards = setup.ledger.create_token_account(
+            &setup.payer,
+            &setup.stake_pool.reward_mint,
+            &setup.depositor.pubkey(),
+            0,
+        );
+        assert_eq!(
+            setup.harvest(&depositor_rewards),
+            Err(ProgramError::IllegalOwner)
+        );
+
+        // the vault only signs for its own pool
+        let other_pool =
+            setup
+                .ledger
+                .create_pool(&setup.payer, &setup.stake_pool.stake_token_mint, 1, 1);
+        let mut instruction = harvest(
+            &VAULT_PROGRAM_ID,
+            &setup.pool,
+            &setup.stake_pool,
+            &setup.vault_rewards,
+        );
+        instruction.accounts[0].pubkey = other_pool;
+        assert_eq!(
+            setup.ledger.send(&[instruction], &setup.payer, &[]),
+            Err(ProgramError::InvalidSeeds)
+        );
+    }
+}
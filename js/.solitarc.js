@@ -0,0 +1,25 @@
+// Generates the TypeScript client from the shank annotations of the program:
+//   yarn api:gen
+const path = require('path');
+
+const programDir = path.join(__dirname, '..');
+const idlDir = path.join(__dirname, 'idl');
+const sdkDir = path.join(__dirname, 'src', 'generated');
+const binaryInstallDir = path.join(__dirname, '.crates');
+
+module.exports = {
+  idlGenerator: 'shank',
+  programName: 'reward_pool',
+  programId: '93aGsq9eqwJnUSmqgLr9kdnmBbccBsEkWBmCnWAyGuzn',
+  idlDir,
+  sdkDir,
+  binaryInstallDir,
+  programDir,
+  // Shank numbers instructions from 0, the v1 tags start at 0x1
+  idlHook: (idl) => {
+    idl.instructions.forEach((ix) => {
+      ix.discriminant.value += 1;
+    });
+    return idl;
+  },
+};
@@ -26,6 +26,13 @@ do
     solana airdrop 1
 done
 
+# upgrades are redeploy-only: accounts of an earlier layout aren't migrated
+program_id=$(solana-keygen pubkey target/deploy/reward_pool-keypair.json)
+if solana program show "$program_id" > /dev/null 2>&1; then
+    echo "Program $program_id is already deployed, deploy to a new address instead" >&2
+    exit 1
+fi
+
 solana deploy target/deploy/reward_pool.so target/deploy/reward_pool-keypair.json
 
 exit 0
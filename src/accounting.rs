@@ -0,0 +1,349 @@
+//! Accounting statements of a pool for finance teams and tax reporting, built
+//! on the replay of its transaction history by `history::Reconstruction`.
+//! Statements per stake user cover the principal staked and the rewards
+//! accrued, claimed and burned on claim as fees, statements per period the
+//! same for the whole pool over consecutive periods of a fixed length. Periods
+//! are aligned on multiples of their length since the Unix epoch, so daily
+//! periods are UTC days.
+//!
+//! Rewards accrued are those paid out, burned and still owed, the rewards
+//! owed accrued up to the end of the statement. Amounts are raw token amounts.
+//! Replays from a known state of the pool only account for the fees from then
+//! on, while the rewards claimed of stake users are over their lifetime.
+
+use std::collections::HashMap;
+
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    error::CustomError,
+    history::{HistoricalTransaction, Reconstruction, ReplayError},
+    simulator::Simulator,
+};
+
+/// Statement of a stake user at the end of the books
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserStatement {
+    pub owner: Pubkey,
+    /// Primary stake tokens staked
+    pub principal: u64,
+    /// Rewards accrued over the lifetime of the stake user
+    pub accrued: u64,
+    /// Reward tokens paid out
+    pub claimed: u64,
+    /// Reward tokens the claim burn of the pool took from claims
+    pub fees: u64,
+}
+
+/// Statement of the pool over a period
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeriodStatement {
+    pub start: UnixTimestamp,
+    pub end: UnixTimestamp,
+    /// Primary stake tokens staked in the pool at the end of the period
+    pub principal: u64,
+    /// Rewards accrued to all stake users over the period
+    pub accrued: u64,
+    /// Reward tokens paid out over the period
+    pub claimed: u64,
+    /// Reward tokens burned on claim over the period
+    pub fees: u64,
+}
+
+/// Statements of the books closed at a timestamp
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statements {
+    /// Stake users by owner
+    pub users: Vec<UserStatement>,
+    /// Periods oldest first, the last one ending at the close
+    pub periods: Vec<PeriodStatement>,
+}
+
+/// Running totals of the pool at a timestamp
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Totals {
+    principal: u64,
+    accrued: u64,
+    claimed: u64,
+    fees: u64,
+}
+
+impl Totals {
+    fn at(simulator: &Simulator, timestamp: UnixTimestamp) -> Result<Self, ProgramError> {
+        let projection = simulator.project(timestamp)?;
+        Ok(Self {
+            principal: projection.total_staked,
+            accrued: projection
+                .paid_out
+                .checked_add(projection.burned)
+                .and_then(|accrued| accrued.checked_add(projection.reward_owed))
+                .ok_or(CustomError::CalculationFailure)?,
+            claimed: projection.paid_out,
+            fees: projection.burned,
+        })
+    }
+
+    /// Statement of the period between two totals
+    fn since(&self, start: &Totals, period: (UnixTimestamp, UnixTimestamp)) -> PeriodStatement {
+        PeriodStatement {
+            start: period.0,
+            end: period.1,
+            principal: self.principal,
+            // rewards owed never shrink but by being paid out or burned
+            accrued: self.accrued.saturating_sub(start.accrued),
+            claimed: self.claimed.saturating_sub(start.claimed),
+            fees: self.fees.saturating_sub(start.fees),
+        }
+    }
+}
+
+/// Books of a pool, kept by replaying its transactions oldest first
+#[derive(Clone, Debug)]
+pub struct Books {
+    reconstruction: Reconstruction,
+    /// Length of the periods in seconds
+    period: UnixTimestamp,
+    /// Start of the period being kept and the totals at it, `None` before
+    /// the pool is known
+    open: Option<(UnixTimestamp, Totals)>,
+    periods: Vec<PeriodStatement>,
+    /// Claim burn of the stake users by owner
+    fees: HashMap<Pubkey, u64>,
+    /// Entries of the reconstruction already accounted for
+    accounted: usize,
+}
+
+impl Books {
+    /// Books of a replay, in periods of a number of seconds
+    pub fn new(
+        reconstruction: Reconstruction,
+        period: UnixTimestamp,
+    ) -> Result<Self, ProgramError> {
+        if period <= 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(Self {
+            accounted: reconstruction.entries.len(),
+            reconstruction,
+            period,
+            open: None,
+            periods: vec![],
+            fees: HashMap::new(),
+        })
+    }
+
+    /// Replay the instructions of the program in a transaction, closing the
+    /// periods which ended before it
+    pub fn replay(&mut self, transaction: &HistoricalTransaction) -> Result<(), ReplayError> {
+        let failed = |error| ReplayError::Failed(transaction.signature, error);
+        let start = transaction.block_time.div_euclid(self.period) * self.period;
+        if let (None, Some(simulator)) = (self.open, self.reconstruction.simulator()) {
+            self.open = Some((start, Totals::at(simulator, start).map_err(failed)?));
+        }
+        self.close_periods(transaction.block_time).map_err(failed)?;
+
+        self.reconstruction.replay(transaction)?;
+        // pools created by the transaction start with nothing accrued
+        if self.open.is_none() && self.reconstruction.simulator().is_some() {
+            self.open = Some((start, Totals::default()));
+        }
+        for entry in &self.reconstruction.entries[self.accounted..] {
+            *self.fees.entry(entry.owner).or_default() += entry.burned;
+        }
+        self.accounted = self.reconstruction.entries.len();
+        Ok(())
+    }
+
+    /// Replay the books are kept from, with the instructions it skipped
+    pub fn reconstruction(&self) -> &Reconstruction {
+        &self.reconstruction
+    }
+
+    /// Close the books at a timestamp, with a last period ending at it. The
+    /// rewards owed are accrued up to it.
+    pub fn close(mut self, timestamp: UnixTimestamp) -> Result<Statements, ProgramError> {
+        let simulator = match self.reconstruction.simulator() {
+            Some(simulator) => simulator.clone(),
+            None => {
+                return Ok(Statements {
+                    users: vec![],
+                    periods: vec![],
+                })
+            }
+        };
+        self.close_periods(timestamp)?;
+        if let Some((start, totals)) = self.open {
+            if timestamp > start {
+                self.periods
+                    .push(Totals::at(&simulator, timestamp)?.since(&totals, (start, timestamp)));
+            }
+        }
+
+        let users = simulator
+            .project(timestamp)?
+            .users
+            .into_iter()
+            .map(|user| {
+                let fees = self.fees.get(&user.owner).copied().unwrap_or_default();
+                Ok(UserStatement {
+                    owner: user.owner,
+                    principal: user.stake_amount,
+                    accrued: user
+                        .total_claimed
+                        .checked_add(fees)
+                        .and_then(|accrued| accrued.checked_add(user.reward_owed))
+                        .ok_or(CustomError::CalculationFailure)?,
+                    claimed: user.total_claimed,
+                    fees,
+                })
+            })
+            .collect::<Result<_, ProgramError>>()?;
+        Ok(Statements {
+            users,
+            periods: self.periods,
+        })
+    }
+
+    /// Close the periods ending by a timestamp
+    fn close_periods(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
+        let simulator = match self.reconstruction.simulator() {
+            Some(simulator) => simulator,
+            None => return Ok(()),
+        };
+        while let Some((start, totals)) = self.open {
+            let end = start + self.period;
+            if end > timestamp {
+                break;
+            }
+            let closing = Totals::at(simulator, end)?;
+            self.periods.push(closing.since(&totals, (start, end)));
+            self.open = Some((end, closing));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::HistoricalInstruction,
+        instruction::{claim, stake},
+        pda::{find_pool_authority_address, find_stake_user_address},
+        state::{Pool, DAILY_TS},
+    };
+    use solana_program::instruction::Instruction;
+    use solana_sdk::signature::Signature;
+
+    const DAY: UnixTimestamp = DAILY_TS as UnixTimestamp;
+
+    fn transaction(block_time: UnixTimestamp, instruction: Instruction) -> HistoricalTransaction {
+        HistoricalTransaction {
+            signature: Signature::new_unique(),
+            slot: block_time as u64,
+            block_time,
+            instructions: vec![HistoricalInstruction {
+                program_id: instruction.program_id,
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|account| account.pubkey)
+                    .collect(),
+                data: instruction.data,
+            }],
+            logs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_books() {
+        let program_id = crate::id();
+        let pool_pubkey = Pubkey::new_unique();
+        let alice = Pubkey::new_unique();
+        // 1/10 of the stake a day, a tenth of claims burned
+        let pool = Pool {
+            is_initialized: true,
+            stake_token_mint: Pubkey::new_unique(),
+            reserved: Pubkey::new_unique(),
+            reward_mint: Pubkey::new_unique(),
+            reward_numerator: 1,
+            reward_denominator: 10,
+            reward_period: DAILY_TS,
+            claim_burn_bps: 1_000,
+            ..Pool::default()
+        };
+        let reconstruction = Reconstruction::from_state(
+            program_id,
+            Simulator::new(pool_pubkey, pool.clone(), vec![], 0),
+        );
+        let mut books = Books::new(reconstruction, DAY).unwrap();
+        assert_eq!(
+            Books::new(books.reconstruction().clone(), 0).err(),
+            Some(ProgramError::InvalidArgument)
+        );
+
+        let (stake_user, _) = find_stake_user_address(&program_id, &pool_pubkey, &alice);
+        let (authority, _) = find_pool_authority_address(&program_id, &pool_pubkey);
+        books
+            .replay(&transaction(
+                DAY / 2,
+                stake(
+                    program_id,
+                    pool_pubkey,
+                    &pool,
+                    stake_user,
+                    alice,
+                    alice,
+                    Pubkey::new_unique(),
+                    pool.stake_token_mint,
+                    1_000,
+                )
+                .unwrap(),
+            ))
+            .unwrap();
+        // a day and a half of accrual, 150, claimed with 15 burned
+        books
+            .replay(&transaction(
+                2 * DAY,
+                claim(
+                    program_id,
+                    pool_pubkey,
+                    stake_user,
+                    alice,
+                    authority,
+                    pool.reward_mint,
+                    Pubkey::new_unique(),
+                )
+                .unwrap(),
+            ))
+            .unwrap();
+
+        let statements = books.close(2 * DAY + DAY / 2).unwrap();
+        assert_eq!(
+            statements.users,
+            vec![UserStatement {
+                owner: alice,
+                principal: 1_000,
+                accrued: 200,
+                claimed: 135,
+                fees: 15,
+            }]
+        );
+        let period = |start, end, accrued, claimed, fees| PeriodStatement {
+            start,
+            end,
+            principal: 1_000,
+            accrued,
+            claimed,
+            fees,
+        };
+        assert_eq!(
+            statements.periods,
+            vec![
+                period(0, DAY, 50, 0, 0),
+                period(DAY, 2 * DAY, 100, 0, 0),
+                period(2 * DAY, 2 * DAY + DAY / 2, 50, 135, 15),
+            ]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Recurring refresh and claim threads on the Clockwork automation network.
+//!
+//! Threads replay a fixed list of instructions on a schedule, signed only by
+//! the thread and paid for by the worker running it, so they suit RefreshBatch,
+//! which needs no signer and takes its stake users in a fixed order, and
+//! ClaimFor, which pays out to the owner's associated token account whoever
+//! calls it. The payer funding the thread need not be its authority.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{
+    governance::InstructionData,
+    instruction::{refresh_batch, MAX_REFRESH_BATCH},
+};
+
+/// Clockwork thread program
+pub const THREAD_PROGRAM_ID: Pubkey = pubkey!("CLoCKyJ6DXBJqqu2VWx9RLbgnwwR6BMHHuyasVmfMzBh");
+
+/// Leading variants of a thread trigger, pool threads running on cron
+/// schedules
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum Trigger {
+    /// Run when the data of an account changes
+    Account {
+        address: Pubkey,
+        offset: u64,
+        size: u64,
+    },
+    /// Run on a cron schedule, skipping missed runs if `skippable`
+    Cron { schedule: String, skippable: bool },
+}
+
+/// Arguments of the thread program's `thread_create`. Thread instructions have
+/// the Borsh layout of governance proposal instructions.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ThreadCreateArgs {
+    /// Lamports moved into the thread to pay its workers
+    pub amount: u64,
+    pub id: Vec<u8>,
+    pub instructions: Vec<InstructionData>,
+    pub trigger: Trigger,
+}
+
+/// Find the thread of an authority by id
+pub fn find_thread_address(authority_pubkey: &Pubkey, id: &[u8]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"thread", authority_pubkey.as_ref(), id],
+        &THREAD_PROGRAM_ID,
+    )
+}
+
+/// RefreshBatch instructions covering the stake users of a pool, in batches
+/// of `MAX_REFRESH_BATCH`
+pub fn refresh_instructions(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    mut stake_user_pubkeys: Vec<Pubkey>,
+) -> Result<Vec<Instruction>, ProgramError> {
+    stake_user_pubkeys.sort();
+    stake_user_pubkeys.dedup();
+    stake_user_pubkeys
+        .chunks(MAX_REFRESH_BATCH)
+        .map(|batch| refresh_batch(program_id, stake_pool_pubkey, batch.to_vec()))
+        .collect()
+}
+
+/// Register a thread of the authority running the instructions on a cron
+/// schedule, e.g. from `refresh_instructions` or `claim_for`, funded by the
+/// payer with `amount` lamports
+pub fn create_thread(
+    authority_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    id: &[u8],
+    schedule: &str,
+    amount: u64,
+    instructions: Vec<Instruction>,
+) -> Instruction {
+    let (thread_pubkey, _) = find_thread_address(&authority_pubkey, id);
+    let args = ThreadCreateArgs {
+        amount,
+        id: id.to_vec(),
+        instructions: instructions
+            .into_iter()
+            .map(InstructionData::from)
+            .collect(),
+        trigger: Trigger::Cron {
+            schedule: schedule.to_string(),
+            skippable: true,
+        },
+    };
+    // anchor instruction discriminator
+    let mut data = hash(b"global:thread_create").to_bytes()[..8].to_vec();
+    // writing to a vector can't fail
+    args.serialize(&mut data).unwrap();
+
+    Instruction {
+        program_id: THREAD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(authority_pubkey, true),
+            AccountMeta::new(payer_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(thread_pubkey, false),
+        ],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_refresh_thread() {
+        let program_id = Pubkey::new_unique();
+        let stake_pool = Pubkey::new_unique();
+        let mut stake_users: Vec<Pubkey> = (0..MAX_REFRESH_BATCH + 1)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        stake_users.push(stake_users[0]);
+
+        let instructions =
+            refresh_instructions(program_id, stake_pool, stake_users.clone()).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].accounts.len(), 2 + MAX_REFRESH_BATCH);
+        assert_eq!(instructions[1].accounts.len(), 2 + 1);
+        let refreshed: Vec<Pubkey> = instructions
+            .iter()
+            .flat_map(|instruction| instruction.accounts[2..].iter().map(|meta| meta.pubkey))
+            .collect();
+        assert!(refreshed.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(refreshed.len(), MAX_REFRESH_BATCH + 1);
+
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let thread = create_thread(
+            authority,
+            payer,
+            b"refresh",
+            "0 0 * * * * *",
+            1_000_000,
+            instructions.clone(),
+        );
+        assert_eq!(
+            thread.accounts[3].pubkey,
+            find_thread_address(&authority, b"refresh").0
+        );
+        let args = ThreadCreateArgs::try_from_slice(&thread.data[8..]).unwrap();
+        assert_eq!(args.id, b"refresh");
+        assert_eq!(
+            args.instructions[1],
+            InstructionData::from(instructions[1].clone())
+        );
+        assert_eq!(
+            args.trigger,
+            Trigger::Cron {
+                schedule: "0 0 * * * * *".to_string(),
+                skippable: true
+            }
+        );
+    }
+}
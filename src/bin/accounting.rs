@@ -0,0 +1,409 @@
+//! Accounting export of a pool for finance teams and tax reporting, built
+//! with the `accounting` feature
+//!
+//! ```text
+//! accounting --url <RPC_URL> --program <PROGRAM_ID> --pool <POOL>
+//!     [--report users|periods] [--period <SECONDS>] [--until <UNIX_TIMESTAMP>]
+//!     [--format json|csv]
+//! ```
+//!
+//! The successful transactions of the pool up to `--until`, now by default,
+//! are fetched from the oldest on and kept in `accounting::Books`. The users
+//! report has the principal staked and the rewards accrued, claimed and burned
+//! on claim as fees of each stake user; the periods report has the same for
+//! the pool per period, a day by default, the last one ending at `--until`.
+//! Principal is in stake tokens and rewards in reward tokens, written in whole
+//! tokens with the decimals of their mints. Like the `history` binary, it
+//! needs an RPC node keeping the history of the pool back to CreatePool.
+
+use std::{
+    env,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reward_pool::{
+    accounting::{Books, PeriodStatement, UserStatement},
+    client::{format_reward_amount, format_stake_amount},
+    history::{HistoricalInstruction, HistoricalTransaction, Reconstruction},
+    instruction::InstructionType,
+    pda::find_global_config_address,
+    state::{GlobalConfig, Pool, DAILY_TS},
+};
+use serde_json::{json, Value};
+use solana_sdk::{
+    bs58, program_pack::Pack, pubkey::Pubkey, signature::Signature,
+    transaction::VersionedTransaction,
+};
+
+/// Signatures per `getSignaturesForAddress` call, the RPC limit
+const SIGNATURES_PER_CALL: usize = 1_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Report {
+    Users,
+    Periods,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+struct Config {
+    url: String,
+    program_id: Pubkey,
+    pool: Pubkey,
+    report: Report,
+    period: i64,
+    until: Option<i64>,
+    format: Format,
+}
+
+impl Config {
+    fn from_args() -> Result<Self> {
+        let mut url = None;
+        let mut program_id = None;
+        let mut pool = None;
+        let mut report = Report::Users;
+        let mut period = DAILY_TS as i64;
+        let mut until = None;
+        let mut format = Format::Json;
+
+        let mut args = env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .with_context(|| format!("missing value of {}", flag))?;
+            match flag.as_str() {
+                "--url" => url = Some(value),
+                "--program" => program_id = Some(Pubkey::from_str(&value)?),
+                "--pool" => pool = Some(Pubkey::from_str(&value)?),
+                "--report" => {
+                    report = match value.as_str() {
+                        "users" => Report::Users,
+                        "periods" => Report::Periods,
+                        _ => bail!("unknown report {}", value),
+                    }
+                }
+                "--period" => period = value.parse()?,
+                "--until" => until = Some(value.parse()?),
+                "--format" => {
+                    format = match value.as_str() {
+                        "json" => Format::Json,
+                        "csv" => Format::Csv,
+                        _ => bail!("unknown format {}", value),
+                    }
+                }
+                _ => bail!("unknown argument {}", flag),
+            }
+        }
+
+        Ok(Config {
+            url: url.context("--url is required")?,
+            program_id: program_id.context("--program is required")?,
+            pool: pool.context("--pool is required")?,
+            report,
+            period,
+            until,
+            format,
+        })
+    }
+}
+
+/// Minimal JSON-RPC client of the calls the export makes
+struct Rpc {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Rpc {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("{} failed: {}", method, error);
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self
+            .call(
+                "getAccountInfo",
+                json!([pubkey.to_string(), {"encoding": "base64"}]),
+            )
+            .await?;
+        match result["value"]["data"][0].as_str() {
+            Some(data) => Ok(Some(STANDARD.decode(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Signatures of the successful transactions of an address, oldest first
+    async fn signatures(&self, address: &Pubkey) -> Result<Vec<Signature>> {
+        let mut signatures = vec![];
+        let mut before: Option<String> = None;
+        loop {
+            let result = self
+                .call(
+                    "getSignaturesForAddress",
+                    json!([
+                        address.to_string(),
+                        {"limit": SIGNATURES_PER_CALL, "before": before, "commitment": "confirmed"}
+                    ]),
+                )
+                .await?;
+            let page = result
+                .as_array()
+                .context("getSignaturesForAddress returned no signatures")?;
+            for status in page {
+                let signature = status["signature"].as_str().context("signature missing")?;
+                before = Some(signature.to_string());
+                if status["err"].is_null() {
+                    signatures.push(Signature::from_str(signature)?);
+                }
+            }
+            if page.len() < SIGNATURES_PER_CALL {
+                break;
+            }
+        }
+        signatures.reverse();
+        Ok(signatures)
+    }
+
+    async fn transaction(&self, signature: &Signature) -> Result<HistoricalTransaction> {
+        let result = self
+            .call(
+                "getTransaction",
+                json!([
+                    signature.to_string(),
+                    {"encoding": "base64", "maxSupportedTransactionVersion": 0, "commitment": "confirmed"}
+                ]),
+            )
+            .await?;
+        let data = result["transaction"][0]
+            .as_str()
+            .with_context(|| format!("transaction {} not found", signature))?;
+        let transaction: VersionedTransaction = bincode::deserialize(&STANDARD.decode(data)?)?;
+        let meta = &result["meta"];
+
+        // the keys of address lookup tables follow the static keys, writable
+        // ones first
+        let mut account_keys = transaction.message.static_account_keys().to_vec();
+        for loaded in ["writable", "readonly"] {
+            for key in meta["loadedAddresses"][loaded]
+                .as_array()
+                .unwrap_or(&vec![])
+            {
+                account_keys.push(Pubkey::from_str(key.as_str().unwrap_or_default())?);
+            }
+        }
+        let inner = meta["innerInstructions"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let mut instructions = vec![];
+        for (index, instruction) in transaction.message.instructions().iter().enumerate() {
+            let accounts: Vec<usize> = instruction
+                .accounts
+                .iter()
+                .map(|&index| usize::from(index))
+                .collect();
+            instructions.push(resolve(
+                &account_keys,
+                usize::from(instruction.program_id_index),
+                &accounts,
+                instruction.data.clone(),
+            )?);
+            let invoked = inner
+                .iter()
+                .filter(|inner| inner["index"].as_u64() == Some(index as u64))
+                .flat_map(|inner| {
+                    inner["instructions"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default()
+                });
+            for instruction in invoked {
+                let accounts: Vec<usize> = instruction["accounts"]
+                    .as_array()
+                    .context("inner instruction accounts missing")?
+                    .iter()
+                    .map(|index| index.as_u64().unwrap_or(u64::MAX) as usize)
+                    .collect();
+                let data =
+                    bs58::decode(instruction["data"].as_str().unwrap_or_default()).into_vec()?;
+                instructions.push(resolve(
+                    &account_keys,
+                    instruction["programIdIndex"].as_u64().unwrap_or(u64::MAX) as usize,
+                    &accounts,
+                    data,
+                )?);
+            }
+        }
+
+        Ok(HistoricalTransaction {
+            signature: *signature,
+            slot: result["slot"].as_u64().unwrap_or_default(),
+            block_time: result["blockTime"]
+                .as_i64()
+                .with_context(|| format!("transaction {} has no block time", signature))?,
+            instructions,
+            logs: meta["logMessages"]
+                .as_array()
+                .map(|logs| {
+                    logs.iter()
+                        .filter_map(|log| log.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Instruction with its accounts resolved from indexes into the account keys
+fn resolve(
+    account_keys: &[Pubkey],
+    program_id_index: usize,
+    accounts: &[usize],
+    data: Vec<u8>,
+) -> Result<HistoricalInstruction> {
+    let key = |index: usize| {
+        account_keys
+            .get(index)
+            .copied()
+            .with_context(|| format!("account {} out of range", index))
+    };
+    Ok(HistoricalInstruction {
+        program_id: key(program_id_index)?,
+        accounts: accounts
+            .iter()
+            .map(|&index| key(index))
+            .collect::<Result<_>>()?,
+        data,
+    })
+}
+
+/// Fields of a user statement, amounts in whole tokens
+fn user_fields(stake_pool: &Pool, user: &UserStatement) -> Vec<(&'static str, String)> {
+    vec![
+        ("owner", user.owner.to_string()),
+        ("principal", format_stake_amount(stake_pool, user.principal)),
+        ("accrued", format_reward_amount(stake_pool, user.accrued)),
+        ("claimed", format_reward_amount(stake_pool, user.claimed)),
+        ("fees", format_reward_amount(stake_pool, user.fees)),
+    ]
+}
+
+/// Fields of a period statement, amounts in whole tokens
+fn period_fields(stake_pool: &Pool, period: &PeriodStatement) -> Vec<(&'static str, String)> {
+    vec![
+        ("start", period.start.to_string()),
+        ("end", period.end.to_string()),
+        (
+            "principal",
+            format_stake_amount(stake_pool, period.principal),
+        ),
+        ("accrued", format_reward_amount(stake_pool, period.accrued)),
+        ("claimed", format_reward_amount(stake_pool, period.claimed)),
+        ("fees", format_reward_amount(stake_pool, period.fees)),
+    ]
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let rpc = Rpc {
+        client: reqwest::Client::new(),
+        url: config.url.clone(),
+    };
+    let until = match config.until {
+        Some(until) => until,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+    };
+
+    let stake_pool = match rpc.account_data(&config.pool).await? {
+        Some(data) => Pool::unpack(&data)?,
+        None => bail!("pool {} not found", config.pool),
+    };
+    let (global_config, _) = find_global_config_address(&config.program_id);
+    let default_claim_burn_bps = match rpc.account_data(&global_config).await? {
+        Some(data) => GlobalConfig::unpack(&data)?.default_claim_burn_bps,
+        None => GlobalConfig::default().default_claim_burn_bps,
+    };
+    let mut books = Books::new(
+        Reconstruction::new(config.program_id, config.pool, default_claim_burn_bps),
+        config.period,
+    )?;
+    for signature in rpc.signatures(&config.pool).await? {
+        let transaction = rpc.transaction(&signature).await?;
+        if transaction.block_time > until {
+            break;
+        }
+        books.replay(&transaction)?;
+    }
+    for skipped in &books.reconstruction().skipped {
+        match InstructionType::unpack(&skipped.data) {
+            Ok(instruction) => eprintln!("skipped {}: {:?}", skipped.signature, instruction),
+            Err(_) => eprintln!("skipped {}", skipped.signature),
+        }
+    }
+
+    let statements = books.close(until)?;
+    let rows: Vec<Vec<(&'static str, String)>> = match config.report {
+        Report::Users => statements
+            .users
+            .iter()
+            .map(|user| user_fields(&stake_pool, user))
+            .collect(),
+        Report::Periods => statements
+            .periods
+            .iter()
+            .map(|period| period_fields(&stake_pool, period))
+            .collect(),
+    };
+    match config.format {
+        Format::Json => {
+            let rows: Vec<Value> = rows
+                .into_iter()
+                .map(|fields| {
+                    Value::Object(
+                        fields
+                            .into_iter()
+                            .map(|(name, value)| (name.to_string(), Value::String(value)))
+                            .collect(),
+                    )
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        }
+        Format::Csv => {
+            let header = match config.report {
+                Report::Users => "owner,principal,accrued,claimed,fees",
+                Report::Periods => "start,end,principal,accrued,claimed,fees",
+            };
+            println!("{}", header);
+            for fields in rows {
+                let values: Vec<String> = fields.into_iter().map(|(_, value)| value).collect();
+                println!("{}", values.join(","));
+            }
+        }
+    }
+    Ok(())
+}
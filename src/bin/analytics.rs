@@ -0,0 +1,385 @@
+//! Reporting tool aggregating the pools of the program and their stake users
+//! over RPC, built with the `analytics` feature
+//!
+//! ```text
+//! analytics --url <RPC_URL> --program <PROGRAM_ID> [--pool <POOL>] [--format json|csv]
+//! ```
+//!
+//! For each pool it reports the value locked, the emission run-rate, the
+//! distribution of the stakes and the rewards owed to stakers as of now. Run
+//! rates and liabilities are in reward tokens, or in quote units for
+//! oracle-priced pools. Reward tokens are raw amounts, interest-bearing reward
+//! mints come with the multiplier turning them into UI amounts.
+
+use std::{
+    collections::HashMap,
+    env,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reward_pool::{
+    client::pool_filters,
+    interest, math,
+    state::{Pool, StakeUser, DAILY_TS},
+};
+use serde_json::{json, Value};
+use solana_rpc_client_api::filter::RpcFilterType;
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+
+/// Largest stakes whose share of the pool is reported
+const TOP_STAKERS: usize = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+struct Config {
+    url: String,
+    program_id: Pubkey,
+    pool: Option<Pubkey>,
+    format: Format,
+}
+
+impl Config {
+    fn from_args() -> Result<Self> {
+        let mut url = None;
+        let mut program_id = None;
+        let mut pool = None;
+        let mut format = Format::Json;
+
+        let mut args = env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .with_context(|| format!("missing value of {}", flag))?;
+            match flag.as_str() {
+                "--url" => url = Some(value),
+                "--program" => program_id = Some(Pubkey::from_str(&value)?),
+                "--pool" => pool = Some(Pubkey::from_str(&value)?),
+                "--format" => {
+                    format = match value.as_str() {
+                        "json" => Format::Json,
+                        "csv" => Format::Csv,
+                        _ => bail!("unknown format {}", value),
+                    }
+                }
+                _ => bail!("unknown argument {}", flag),
+            }
+        }
+
+        Ok(Config {
+            url: url.context("--url is required")?,
+            program_id: program_id.context("--program is required")?,
+            pool,
+            format,
+        })
+    }
+}
+
+/// Minimal JSON-RPC client of the calls the report makes
+struct Rpc {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Rpc {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("{} failed: {}", method, error);
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let result = self
+            .call(
+                "getProgramAccounts",
+                json!([
+                    program_id.to_string(),
+                    {"encoding": "base64", "filters": serde_json::to_value(filters)?}
+                ]),
+            )
+            .await?;
+        result
+            .as_array()
+            .context("getProgramAccounts returned no accounts")?
+            .iter()
+            .map(|keyed| {
+                let pubkey = Pubkey::from_str(keyed["pubkey"].as_str().unwrap_or_default())?;
+                let data = keyed["account"]["data"][0]
+                    .as_str()
+                    .with_context(|| format!("account {} data is not base64", pubkey))?;
+                Ok((pubkey, STANDARD.decode(data)?))
+            })
+            .collect()
+    }
+
+    /// Data of accounts, `None` for the missing ones
+    async fn multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut accounts = vec![];
+        // the RPC limit of accounts per call
+        for chunk in pubkeys.chunks(100) {
+            let keys: Vec<String> = chunk.iter().map(Pubkey::to_string).collect();
+            let result = self
+                .call("getMultipleAccounts", json!([keys, {"encoding": "base64"}]))
+                .await?;
+            for account in result["value"]
+                .as_array()
+                .context("getMultipleAccounts returned no accounts")?
+            {
+                accounts.push(match account["data"][0].as_str() {
+                    Some(data) => Some(STANDARD.decode(data)?),
+                    None => None,
+                });
+            }
+        }
+        Ok(accounts)
+    }
+}
+
+/// Figures of one pool
+struct PoolReport {
+    pool: Pubkey,
+    stake_token_mint: Pubkey,
+    reward_mint: Pubkey,
+    /// Factor the UI amounts of an interest-bearing reward mint are scaled up
+    /// by as of now
+    reward_ui_multiplier: Option<f64>,
+    deprecated: bool,
+    /// Primary stake tokens staked, the value locked besides the additional
+    /// stake mints
+    total_staked: u64,
+    /// Additional stake mints with their staked amounts
+    stake_mints: Vec<(Pubkey, u64)>,
+    protocol_owned: u64,
+    stake_users: u64,
+    stakers: u64,
+    /// Rewards accruing per day at the current rate and stakes
+    daily_emission: u64,
+    /// Rewards owed to all stake users as of now, accrued or not, and
+    /// streamed rewards not withdrawn yet
+    pending_liabilities: u64,
+    median_stake: u64,
+    p90_stake: u64,
+    max_stake: u64,
+    /// Share of the accruing stake held by the `TOP_STAKERS` largest stakers
+    top_stakers_share_bps: u64,
+}
+
+impl PoolReport {
+    fn new(
+        pool: Pubkey,
+        stake_pool: &Pool,
+        stake_users: &[StakeUser],
+        reward_ui_multiplier: Option<f64>,
+        now: i64,
+    ) -> Result<Self> {
+        let (numerator, denominator) = stake_pool.current_rate();
+        let mut stakes = vec![];
+        let mut daily_emission = 0u64;
+        let mut pending_liabilities = 0u64;
+        for stake_user in stake_users {
+            pending_liabilities = pending_liabilities
+                .saturating_add(stake_user.pending_reward(stake_pool, now)?)
+                .saturating_add(stake_user.stream_balance);
+            if !stake_user.has_stake() {
+                continue;
+            }
+            let stake = stake_user.accruing_stake(stake_pool)?;
+            let daily = math::accrue(
+                stake,
+                numerator,
+                denominator,
+                DAILY_TS,
+                stake_pool.reward_period,
+            )
+            .unwrap_or(u64::MAX);
+            daily_emission = daily_emission.saturating_add(daily);
+            stakes.push(stake);
+        }
+        stakes.sort_unstable();
+
+        let total: u128 = stakes.iter().map(|&stake| stake as u128).sum();
+        let top: u128 = stakes
+            .iter()
+            .rev()
+            .take(TOP_STAKERS)
+            .map(|&stake| stake as u128)
+            .sum();
+        let quantile = |q: usize| match stakes.len() {
+            0 => 0,
+            len => stakes[(len - 1) * q / 100],
+        };
+
+        Ok(Self {
+            pool,
+            stake_token_mint: stake_pool.stake_token_mint,
+            reward_mint: stake_pool.reward_mint,
+            reward_ui_multiplier,
+            deprecated: stake_pool.deprecated,
+            total_staked: stake_pool.total_staked,
+            stake_mints: stake_pool
+                .stake_mints
+                .iter()
+                .filter(|stake_mint| !stake_mint.is_empty())
+                .map(|stake_mint| (stake_mint.mint, stake_mint.total_staked))
+                .collect(),
+            protocol_owned: stake_pool.protocol_owned,
+            stake_users: stake_users.len() as u64,
+            stakers: stakes.len() as u64,
+            daily_emission,
+            pending_liabilities,
+            median_stake: quantile(50),
+            p90_stake: quantile(90),
+            max_stake: stakes.last().copied().unwrap_or_default(),
+            top_stakers_share_bps: (top * math::BPS_DENOMINATOR as u128)
+                .checked_div(total)
+                .unwrap_or_default() as u64,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "pool": self.pool.to_string(),
+            "stake_token_mint": self.stake_token_mint.to_string(),
+            "reward_mint": self.reward_mint.to_string(),
+            "reward_ui_multiplier": self.reward_ui_multiplier,
+            "deprecated": self.deprecated,
+            "total_staked": self.total_staked,
+            "stake_mints": self
+                .stake_mints
+                .iter()
+                .map(|(mint, total_staked)| json!({
+                    "mint": mint.to_string(),
+                    "total_staked": total_staked,
+                }))
+                .collect::<Vec<_>>(),
+            "protocol_owned": self.protocol_owned,
+            "stake_users": self.stake_users,
+            "stakers": self.stakers,
+            "daily_emission": self.daily_emission,
+            "pending_liabilities": self.pending_liabilities,
+            "median_stake": self.median_stake,
+            "p90_stake": self.p90_stake,
+            "max_stake": self.max_stake,
+            "top_stakers_share_bps": self.top_stakers_share_bps,
+        })
+    }
+
+    const CSV_HEADER: &'static str = "pool,stake_token_mint,reward_mint,reward_ui_multiplier,\
+        deprecated,total_staked,protocol_owned,stake_users,stakers,daily_emission,pending_liabilities,median_stake,\
+        p90_stake,max_stake,top_stakers_share_bps";
+
+    /// CSV row, leaving out the additional stake mints
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.pool,
+            self.stake_token_mint,
+            self.reward_mint,
+            self.reward_ui_multiplier
+                .map(|multiplier| multiplier.to_string())
+                .unwrap_or_default(),
+            self.deprecated,
+            self.total_staked,
+            self.protocol_owned,
+            self.stake_users,
+            self.stakers,
+            self.daily_emission,
+            self.pending_liabilities,
+            self.median_stake,
+            self.p90_stake,
+            self.max_stake,
+            self.top_stakers_share_bps,
+        )
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let rpc = Rpc {
+        client: reqwest::Client::new(),
+        url: config.url.clone(),
+    };
+
+    let mut pools: Vec<(Pubkey, Pool)> = rpc
+        .program_accounts(&config.program_id, pool_filters())
+        .await?
+        .into_iter()
+        .filter(|(pubkey, _)| config.pool.is_none_or(|pool| pool == *pubkey))
+        .filter_map(|(pubkey, data)| Some((pubkey, Pool::unpack(&data).ok()?)))
+        .collect();
+    pools.sort_by_key(|(pubkey, _)| *pubkey);
+
+    let mut stake_users: HashMap<Pubkey, Vec<StakeUser>> = HashMap::new();
+    for (_, data) in rpc
+        .program_accounts(
+            &config.program_id,
+            vec![RpcFilterType::DataSize(StakeUser::LEN as u64)],
+        )
+        .await?
+    {
+        if let Ok(stake_user) = StakeUser::unpack(&data) {
+            stake_users
+                .entry(stake_user.pool_pubkey)
+                .or_default()
+                .push(stake_user);
+        }
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let reward_mints: Vec<Pubkey> = pools
+        .iter()
+        .map(|(_, stake_pool)| stake_pool.reward_mint)
+        .collect();
+    let reward_mint_data = rpc.multiple_accounts(&reward_mints).await?;
+    let reports = pools
+        .iter()
+        .zip(reward_mint_data)
+        .map(|((pubkey, stake_pool), reward_mint_data)| {
+            let users = stake_users.get(pubkey).map_or(&[][..], Vec::as_slice);
+            let reward_ui_multiplier = reward_mint_data
+                .and_then(|data| interest::interest_multiplier(&data, now).ok())
+                .flatten();
+            PoolReport::new(*pubkey, stake_pool, users, reward_ui_multiplier, now)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match config.format {
+        Format::Json => {
+            let reports: Vec<Value> = reports.iter().map(PoolReport::to_json).collect();
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        }
+        Format::Csv => {
+            println!("{}", PoolReport::CSV_HEADER);
+            for report in &reports {
+                println!("{}", report.to_csv());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,640 @@
+//! Localnet demo for new integrators, built with the `demo` feature. Against a
+//! local validator it deploys the program unless it is already there, creates
+//! a stake mint, a pool rewarding its stakers daily and two stake users, then
+//! runs them through a full stake, refresh and claim cycle, printing each
+//! step.
+//!
+//! ```text
+//! solana-test-validator
+//! cargo build-bpf
+//! demo [--url <RPC_URL>] [--keypair <PATH>] [--program <SO_PATH>]
+//!      [--program-keypair <PATH>]
+//! ```
+//!
+//! The program is deployed at the address of `--program-keypair`, by default
+//! the keypair `cargo build-bpf` generates, with the `solana` CLI. The payer
+//! is airdropped SOL when short of it.
+
+use std::{env, process::Command, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reward_pool::{
+    client::{
+        create_pool_account, format_reward_amount, format_stake_amount, parse_stake_amount,
+        send_with_retries, simulate, with_compute_budget, ComputeBudget, RetryPolicy,
+        TransactionRpc,
+    },
+    instruction::{
+        claim, claim_if_any, create_stake_pool, create_stake_user_idempotent, refresh, stake,
+        with_global_config, InitData,
+    },
+    pda::{find_global_config_address, find_pool_authority_address, find_stake_user_address},
+    state::{GlobalConfig, Pool, StakeUser, DAILY_TS},
+};
+use serde_json::{json, Value};
+use solana_rpc_client_api::response::RpcSimulateTransactionResult;
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+/// Decimals of the demo stake mint, mirrored by the reward mint
+const STAKE_DECIMALS: u8 = 6;
+/// Balance below which the payer is airdropped
+const MIN_PAYER_BALANCE: u64 = 5 * LAMPORTS_PER_SOL;
+/// Stake users of the demo with the amounts they stake, in whole tokens
+const STAKERS: [(&str, &str); 2] = [("alice", "100"), ("bob", "250.5")];
+/// Wait between staking and refreshing for rewards to accrue
+const ACCRUAL_WAIT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Config {
+    url: String,
+    keypair_path: String,
+    program_path: String,
+    program_keypair_path: String,
+}
+
+impl Config {
+    fn from_args() -> Result<Self> {
+        let mut config = Config {
+            url: "http://localhost:8899".to_string(),
+            keypair_path: format!("{}/.config/solana/id.json", env::var("HOME")?),
+            program_path: "target/deploy/reward_pool.so".to_string(),
+            program_keypair_path: "target/deploy/reward_pool-keypair.json".to_string(),
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .with_context(|| format!("missing value of {}", flag))?;
+            match flag.as_str() {
+                "--url" => config.url = value,
+                "--keypair" => config.keypair_path = value,
+                "--program" => config.program_path = value,
+                "--program-keypair" => config.program_keypair_path = value,
+                _ => bail!("unknown argument {}", flag),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Minimal JSON-RPC client of the calls the demo makes
+struct Rpc {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Rpc {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("{} failed: {}", method, error);
+        }
+        Ok(response["result"].take())
+    }
+
+    /// Account info at the confirmed commitment, null if missing
+    async fn account(&self, pubkey: &Pubkey) -> Result<Value> {
+        let mut result = self
+            .call(
+                "getAccountInfo",
+                json!([pubkey.to_string(), {"encoding": "base64", "commitment": "confirmed"}]),
+            )
+            .await?;
+        Ok(result["value"].take())
+    }
+
+    async fn balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        self.call(
+            "getBalance",
+            json!([pubkey.to_string(), {"commitment": "confirmed"}]),
+        )
+        .await?["value"]
+            .as_u64()
+            .context("getBalance returned no balance")
+    }
+
+    async fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> Result<Signature> {
+        let result = self
+            .call("requestAirdrop", json!([pubkey.to_string(), lamports]))
+            .await?;
+        Ok(Signature::from_str(
+            result
+                .as_str()
+                .context("requestAirdrop returned no signature")?,
+        )?)
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<RpcSimulateTransactionResult> {
+        let encoded = STANDARD.encode(bincode::serialize(&transaction)?);
+        let mut result = self
+            .call(
+                "simulateTransaction",
+                json!([encoded, {
+                    "encoding": "base64",
+                    "sigVerify": false,
+                    "replaceRecentBlockhash": true,
+                    "commitment": "confirmed",
+                }]),
+            )
+            .await?;
+        Ok(serde_json::from_value(result["value"].take())?)
+    }
+}
+
+impl TransactionRpc for Rpc {
+    type Error = anyhow::Error;
+
+    async fn latest_blockhash(&self) -> Result<(Hash, u64)> {
+        let result = self
+            .call("getLatestBlockhash", json!([{"commitment": "confirmed"}]))
+            .await?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .context("getLatestBlockhash returned no blockhash")?;
+        let last_valid_block_height = result["value"]["lastValidBlockHeight"]
+            .as_u64()
+            .context("getLatestBlockhash returned no block height")?;
+        Ok((Hash::from_str(blockhash)?, last_valid_block_height))
+    }
+
+    async fn block_height(&self) -> Result<u64> {
+        self.call("getBlockHeight", json!([{"commitment": "confirmed"}]))
+            .await?
+            .as_u64()
+            .context("getBlockHeight returned no block height")
+    }
+
+    async fn account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let account = self.account(pubkey).await?;
+        if account.is_null() {
+            return Ok(None);
+        }
+        let data = account["data"][0]
+            .as_str()
+            .context("account data is not base64")?;
+        Ok(Some(STANDARD.decode(data)?))
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<()> {
+        let encoded = STANDARD.encode(bincode::serialize(transaction)?);
+        self.call(
+            "sendTransaction",
+            json!([encoded, {"encoding": "base64", "preflightCommitment": "confirmed"}]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// `None` until the transaction is confirmed, then whether it succeeded
+    async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<Result<(), TransactionError>>> {
+        let result = self
+            .call("getSignatureStatuses", json!([[signature.to_string()]]))
+            .await?;
+        let status = &result["value"][0];
+        if status.is_null() || status["confirmationStatus"].as_str() == Some("processed") {
+            return Ok(None);
+        }
+        Ok(Some(match &status["err"] {
+            Value::Null => Ok(()),
+            err => Err(serde_json::from_value(err.clone())?),
+        }))
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Stake user of the demo with its token accounts
+struct Staker {
+    name: &'static str,
+    owner: Keypair,
+    stake_user: Pubkey,
+    reward_account: Pubkey,
+}
+
+struct Demo {
+    rpc: Rpc,
+    payer: Keypair,
+    program_id: Pubkey,
+    rent: Rent,
+}
+
+impl Demo {
+    /// Send the instructions, paid by the payer and signed by it and the
+    /// other signers
+    async fn send(
+        &self,
+        instructions: Vec<Instruction>,
+        signers: &[&Keypair],
+    ) -> Result<Signature> {
+        let instructions =
+            with_compute_budget(&self.program_id, instructions, ComputeBudget::default());
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        Ok(send_with_retries(
+            &self.rpc,
+            &instructions,
+            &self.payer.pubkey(),
+            &all_signers,
+            RetryPolicy::default(),
+        )
+        .await?)
+    }
+
+    async fn account_data(&self, pubkey: &Pubkey) -> Result<Vec<u8>> {
+        self.rpc
+            .account_data(pubkey)
+            .await?
+            .with_context(|| format!("account {} not found", pubkey))
+    }
+
+    async fn fund_payer(&self) -> Result<()> {
+        let payer = self.payer.pubkey();
+        if self.rpc.balance(&payer).await? >= MIN_PAYER_BALANCE {
+            return Ok(());
+        }
+        let signature = self
+            .rpc
+            .request_airdrop(&payer, 2 * MIN_PAYER_BALANCE)
+            .await?;
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            if let Some(status) = self.rpc.signature_status(&signature).await? {
+                status?;
+                break;
+            }
+        }
+        println!(
+            "airdropped {} SOL to {}",
+            2 * MIN_PAYER_BALANCE / LAMPORTS_PER_SOL,
+            payer
+        );
+        Ok(())
+    }
+
+    /// Deploy the program with the `solana` CLI unless it is already
+    /// deployed
+    async fn deploy(&self, config: &Config) -> Result<()> {
+        let account = self.rpc.account(&self.program_id).await?;
+        if account["executable"].as_bool() == Some(true) {
+            println!("program {} already deployed", self.program_id);
+            return Ok(());
+        }
+        let status = Command::new("solana")
+            .args([
+                "program",
+                "deploy",
+                "--url",
+                &config.url,
+                "--keypair",
+                &config.keypair_path,
+                "--program-id",
+                &config.program_keypair_path,
+                &config.program_path,
+            ])
+            .status()
+            .context("running the solana CLI")?;
+        if !status.success() {
+            bail!(
+                "deploying {} failed, build it with cargo build-bpf",
+                config.program_path
+            );
+        }
+        println!("deployed program {}", self.program_id);
+        Ok(())
+    }
+
+    /// Token account of the owner, created by the payer
+    fn create_token_account(
+        &self,
+        account: &Keypair,
+        mint: &Pubkey,
+        owner: &Pubkey,
+    ) -> Result<Vec<Instruction>> {
+        Ok(vec![
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                self.rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )?,
+        ])
+    }
+
+    /// Stake mint without freeze authority, minted by the payer
+    async fn create_stake_mint(&self) -> Result<Pubkey> {
+        let mint = Keypair::new();
+        self.send(
+            vec![
+                system_instruction::create_account(
+                    &self.payer.pubkey(),
+                    &mint.pubkey(),
+                    self.rent.minimum_balance(Mint::LEN),
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &self.payer.pubkey(),
+                    None,
+                    STAKE_DECIMALS,
+                )?,
+            ],
+            &[&mint],
+        )
+        .await?;
+        println!("created stake mint {}", mint.pubkey());
+        Ok(mint.pubkey())
+    }
+
+    /// Pool administered by the payer, rewarding a reward token a day per
+    /// token staked
+    async fn create_pool(&self, stake_mint: &Pubkey) -> Result<(Pubkey, Pool)> {
+        let pool = Keypair::new();
+        let reserve = Keypair::new();
+        let reward_mint = Keypair::new();
+        let (authority, bump_seed) = find_pool_authority_address(&self.program_id, &pool.pubkey());
+        let global_config = self
+            .rpc
+            .account_data(&find_global_config_address(&self.program_id).0)
+            .await?
+            .map(|data| GlobalConfig::unpack(&data))
+            .transpose()?;
+        // the treasury only matters with a pool creation fee
+        let treasury =
+            global_config.map_or(self.payer.pubkey(), |global_config| global_config.treasury);
+
+        let payer = self.payer.pubkey();
+        self.send(
+            vec![
+                create_pool_account(&self.program_id, &payer, &pool.pubkey(), &self.rent),
+                system_instruction::create_account(
+                    &payer,
+                    &reserve.pubkey(),
+                    self.rent.minimum_balance(TokenAccount::LEN),
+                    TokenAccount::LEN as u64,
+                    &spl_token::id(),
+                ),
+                system_instruction::create_account(
+                    &payer,
+                    &reward_mint.pubkey(),
+                    self.rent.minimum_balance(Mint::LEN),
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                create_stake_pool(
+                    self.program_id,
+                    pool.pubkey(),
+                    authority,
+                    *stake_mint,
+                    reserve.pubkey(),
+                    reward_mint.pubkey(),
+                    payer,
+                    payer,
+                    treasury,
+                    InitData {
+                        bump_seed,
+                        reward_numerator: 1,
+                        reward_denominator: 1,
+                        reward_period: DAILY_TS,
+                        reject_freeze_authority: true,
+                    },
+                )?,
+            ],
+            &[&pool, &reserve, &reward_mint],
+        )
+        .await?;
+
+        let stake_pool = Pool::unpack(&self.account_data(&pool.pubkey()).await?)?;
+        println!(
+            "created pool {} rewarding {} a day per token staked",
+            pool.pubkey(),
+            stake_pool.reward_mint
+        );
+        Ok((pool.pubkey(), stake_pool))
+    }
+
+    /// Stake user of a new owner staking an amount minted to it, with a
+    /// reward token account to claim into
+    async fn stake(
+        &self,
+        pool: &Pubkey,
+        stake_pool: &Pool,
+        name: &'static str,
+        amount: &str,
+    ) -> Result<Staker> {
+        let owner = Keypair::new();
+        let source = Keypair::new();
+        let reward_account = Keypair::new();
+        let (stake_user, _) = find_stake_user_address(&self.program_id, pool, &owner.pubkey());
+        let amount = parse_stake_amount(stake_pool, amount)?;
+
+        let mut instructions =
+            self.create_token_account(&source, &stake_pool.stake_token_mint, &owner.pubkey())?;
+        instructions.extend(self.create_token_account(
+            &reward_account,
+            &stake_pool.reward_mint,
+            &owner.pubkey(),
+        )?);
+        instructions.push(spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &stake_pool.stake_token_mint,
+            &source.pubkey(),
+            &self.payer.pubkey(),
+            &[],
+            amount,
+        )?);
+        instructions.push(create_stake_user_idempotent(
+            self.program_id,
+            *pool,
+            owner.pubkey(),
+            self.payer.pubkey(),
+        )?);
+        instructions.push(with_global_config(
+            self.program_id,
+            stake(
+                self.program_id,
+                *pool,
+                stake_pool,
+                stake_user,
+                owner.pubkey(),
+                owner.pubkey(),
+                source.pubkey(),
+                stake_pool.stake_token_mint,
+                amount,
+            )?,
+        ));
+        let signature = self
+            .send(instructions, &[&owner, &source, &reward_account])
+            .await?;
+        println!(
+            "{} staked {} in {}",
+            name,
+            format_stake_amount(stake_pool, amount),
+            signature
+        );
+        Ok(Staker {
+            name,
+            owner,
+            stake_user,
+            reward_account: reward_account.pubkey(),
+        })
+    }
+
+    async fn refresh(&self, pool: &Pubkey, stake_pool: &Pool, stakers: &[Staker]) -> Result<()> {
+        let stake_users = stakers.iter().map(|staker| staker.stake_user).collect();
+        let signature = self
+            .send(vec![refresh(self.program_id, *pool, stake_users)?], &[])
+            .await?;
+        println!("refreshed stake users in {}", signature);
+        for staker in stakers {
+            let stake_user = StakeUser::unpack(&self.account_data(&staker.stake_user).await?)?;
+            println!(
+                "{} is owed {}",
+                staker.name,
+                format_reward_amount(stake_pool, stake_user.reward_owed)
+            );
+        }
+        Ok(())
+    }
+
+    /// Preview the claim of a staker by simulation, then claim
+    async fn claim(&self, pool: &Pubkey, stake_pool: &Pool, staker: &Staker) -> Result<()> {
+        let (authority, _) = find_pool_authority_address(&self.program_id, pool);
+        let owner = staker.owner.pubkey();
+        let preview = with_global_config(
+            self.program_id,
+            claim_if_any(
+                self.program_id,
+                *pool,
+                staker.stake_user,
+                owner,
+                authority,
+                stake_pool.reward_mint,
+                staker.reward_account,
+            )?,
+        );
+        let simulation = simulate(
+            |transaction| self.rpc.simulate_transaction(transaction),
+            &self.program_id,
+            Transaction::new_unsigned(solana_sdk::message::Message::new(
+                &[preview],
+                Some(&self.payer.pubkey()),
+            )),
+        )
+        .await?;
+        let previewed = simulation
+            .claimed_amount()
+            .ok_or_else(|| anyhow!("claim preview failed: {:?}", simulation.error))?;
+        println!(
+            "{} would claim {}, using {} compute units",
+            staker.name,
+            format_reward_amount(stake_pool, previewed),
+            simulation.units_consumed.unwrap_or_default()
+        );
+
+        let signature = self
+            .send(
+                vec![with_global_config(
+                    self.program_id,
+                    claim(
+                        self.program_id,
+                        *pool,
+                        staker.stake_user,
+                        owner,
+                        authority,
+                        stake_pool.reward_mint,
+                        staker.reward_account,
+                    )?,
+                )],
+                &[&staker.owner],
+            )
+            .await?;
+        let balance =
+            TokenAccount::unpack(&self.account_data(&staker.reward_account).await?)?.amount;
+        println!(
+            "{} claimed and holds {} in {}",
+            staker.name,
+            format_reward_amount(stake_pool, balance),
+            signature
+        );
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let payer = read_keypair_file(&config.keypair_path)
+        .map_err(|err| anyhow!("reading {}: {}", config.keypair_path, err))?;
+    let program_id = read_keypair_file(&config.program_keypair_path)
+        .map_err(|err| anyhow!("reading {}: {}", config.program_keypair_path, err))?
+        .pubkey();
+    let demo = Demo {
+        rpc: Rpc {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+        },
+        payer,
+        program_id,
+        rent: Rent::default(),
+    };
+
+    demo.fund_payer().await?;
+    demo.deploy(&config).await?;
+    let stake_mint = demo.create_stake_mint().await?;
+    let (pool, stake_pool) = demo.create_pool(&stake_mint).await?;
+
+    let mut stakers = vec![];
+    for (name, amount) in STAKERS {
+        stakers.push(demo.stake(&pool, &stake_pool, name, amount).await?);
+    }
+    println!("waiting {}s for rewards to accrue", ACCRUAL_WAIT.as_secs());
+    tokio::time::sleep(ACCRUAL_WAIT).await;
+    demo.refresh(&pool, &stake_pool, &stakers).await?;
+    for staker in &stakers {
+        demo.claim(&pool, &stake_pool, staker).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,472 @@
+//! Keeper refreshing the stake users of a pool and claiming their rewards into
+//! their associated token accounts, built with the `keeper` feature. Pools
+//! with a rate controller are rebalanced too.
+//!
+//! ```text
+//! keeper --url <RPC_URL> --keypair <PATH> --program <PROGRAM_ID> --pool <POOL>
+//!        [--claim-min <AMOUNT>] [--priority-fee <MICRO_LAMPORTS>]
+//!        [--compute-unit-limit <UNITS>] [--retries <COUNT>] [--interval <SECONDS>]
+//! ```
+//!
+//! Without `--claim-min` only Refresh is sent. Without `--priority-fee` the
+//! median recent prioritization fee of the pool is paid. Without
+//! `--compute-unit-limit` the limit is estimated for each transaction. With `--interval` the
+//! keeper runs as a daemon, starting a round every interval.
+
+use std::{
+    env,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reward_pool::{
+    client::{
+        refresh_chunks, send_with_retries, stake_users_of_pool_filters, with_compute_budget,
+        ComputeBudget, RetryPolicy, TransactionRpc,
+    },
+    instruction::{claim_for, rebalance_rate, with_global_config, with_price_oracle},
+    pda::find_associated_token_address,
+    state::{Pool, StakeUser},
+};
+use serde_json::{json, Value};
+use solana_rpc_client_api::filter::RpcFilterType;
+use solana_sdk::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+/// ClaimFor instructions sent per transaction
+const CLAIMS_PER_TRANSACTION: usize = 5;
+/// Accounts fetched per `getMultipleAccounts` call
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+/// Offset of the state byte of token accounts, after the mint, owner, amount
+/// and delegate
+const TOKEN_ACCOUNT_STATE_OFFSET: usize = 108;
+/// State byte of frozen token accounts
+const TOKEN_ACCOUNT_FROZEN: u8 = 2;
+
+struct Config {
+    url: String,
+    keypair_path: String,
+    program_id: Pubkey,
+    pool: Pubkey,
+    claim_min: Option<u64>,
+    priority_fee: Option<u64>,
+    compute_unit_limit: Option<u32>,
+    retries: usize,
+    interval: Option<Duration>,
+}
+
+impl Config {
+    fn from_args() -> Result<Self> {
+        let mut url = None;
+        let mut keypair_path = None;
+        let mut program_id = None;
+        let mut pool = None;
+        let mut config = Config {
+            url: String::new(),
+            keypair_path: String::new(),
+            program_id: Pubkey::default(),
+            pool: Pubkey::default(),
+            claim_min: None,
+            priority_fee: None,
+            compute_unit_limit: None,
+            retries: 3,
+            interval: None,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .with_context(|| format!("missing value of {}", flag))?;
+            match flag.as_str() {
+                "--url" => url = Some(value),
+                "--keypair" => keypair_path = Some(value),
+                "--program" => program_id = Some(Pubkey::from_str(&value)?),
+                "--pool" => pool = Some(Pubkey::from_str(&value)?),
+                "--claim-min" => config.claim_min = Some(value.parse()?),
+                "--priority-fee" => config.priority_fee = Some(value.parse()?),
+                "--compute-unit-limit" => config.compute_unit_limit = Some(value.parse()?),
+                "--retries" => config.retries = value.parse()?,
+                "--interval" => config.interval = Some(Duration::from_secs(value.parse()?)),
+                _ => bail!("unknown argument {}", flag),
+            }
+        }
+
+        config.url = url.context("--url is required")?;
+        config.keypair_path = keypair_path.context("--keypair is required")?;
+        config.program_id = program_id.context("--program is required")?;
+        config.pool = pool.context("--pool is required")?;
+        Ok(config)
+    }
+}
+
+/// Minimal JSON-RPC client of the calls the keeper makes
+struct Rpc {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Rpc {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("{} failed: {}", method, error);
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self
+            .call(
+                "getAccountInfo",
+                json!([pubkey.to_string(), {"encoding": "base64"}]),
+            )
+            .await?;
+        decode_account_data(&result["value"])
+    }
+
+    /// Whether each token account exists and isn't frozen, so transfers into
+    /// it can succeed
+    async fn token_accounts_payable(&self, pubkeys: &[Pubkey]) -> Result<Vec<bool>> {
+        let mut payable = Vec::with_capacity(pubkeys.len());
+        for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let keys: Vec<String> = chunk.iter().map(Pubkey::to_string).collect();
+            let data_slice = json!({"offset": TOKEN_ACCOUNT_STATE_OFFSET, "length": 1});
+            let result = self
+                .call(
+                    "getMultipleAccounts",
+                    json!([keys, {"encoding": "base64", "dataSlice": data_slice}]),
+                )
+                .await?;
+            let values = result["value"]
+                .as_array()
+                .context("getMultipleAccounts returned no accounts")?;
+            for value in values {
+                let state = decode_account_data(value)?;
+                payable.push(
+                    matches!(state, Some(state) if state.first() != Some(&TOKEN_ACCOUNT_FROZEN)),
+                );
+            }
+        }
+        Ok(payable)
+    }
+
+    async fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let result = self
+            .call(
+                "getProgramAccounts",
+                json!([
+                    program_id.to_string(),
+                    {"encoding": "base64", "filters": serde_json::to_value(filters)?}
+                ]),
+            )
+            .await?;
+        result
+            .as_array()
+            .context("getProgramAccounts returned no accounts")?
+            .iter()
+            .map(|keyed| {
+                let pubkey = Pubkey::from_str(keyed["pubkey"].as_str().unwrap_or_default())?;
+                let data = decode_account_data(&keyed["account"])?
+                    .with_context(|| format!("account {} has no data", pubkey))?;
+                Ok((pubkey, data))
+            })
+            .collect()
+    }
+
+    /// Median of the recent prioritization fees of transactions writing the
+    /// accounts, in micro-lamports per compute unit
+    async fn priority_fee(&self, pubkeys: &[Pubkey]) -> Result<u64> {
+        let keys: Vec<String> = pubkeys.iter().map(Pubkey::to_string).collect();
+        let result = self
+            .call("getRecentPrioritizationFees", json!([keys]))
+            .await?;
+        let mut fees: Vec<u64> = result
+            .as_array()
+            .context("getRecentPrioritizationFees returned no fees")?
+            .iter()
+            .filter_map(|fee| fee["prioritizationFee"].as_u64())
+            .collect();
+        fees.sort_unstable();
+        Ok(fees.get(fees.len() / 2).copied().unwrap_or_default())
+    }
+}
+
+impl TransactionRpc for Rpc {
+    type Error = anyhow::Error;
+
+    async fn latest_blockhash(&self) -> Result<(Hash, u64)> {
+        let result = self
+            .call("getLatestBlockhash", json!([{"commitment": "confirmed"}]))
+            .await?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .context("getLatestBlockhash returned no blockhash")?;
+        let last_valid_block_height = result["value"]["lastValidBlockHeight"]
+            .as_u64()
+            .context("getLatestBlockhash returned no block height")?;
+        Ok((Hash::from_str(blockhash)?, last_valid_block_height))
+    }
+
+    async fn block_height(&self) -> Result<u64> {
+        self.call("getBlockHeight", json!([{"commitment": "confirmed"}]))
+            .await?
+            .as_u64()
+            .context("getBlockHeight returned no block height")
+    }
+
+    async fn account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        Rpc::account_data(self, pubkey).await
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<()> {
+        let encoded = STANDARD.encode(bincode::serialize(transaction)?);
+        self.call(
+            "sendTransaction",
+            json!([encoded, {"encoding": "base64", "maxRetries": 0}]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// `None` until the transaction is confirmed, then whether it succeeded
+    async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<Result<(), TransactionError>>> {
+        let result = self
+            .call("getSignatureStatuses", json!([[signature.to_string()]]))
+            .await?;
+        let status = &result["value"][0];
+        if status.is_null() || status["confirmationStatus"].as_str() == Some("processed") {
+            return Ok(None);
+        }
+        Ok(Some(match &status["err"] {
+            Value::Null => Ok(()),
+            err => Err(serde_json::from_value(err.clone())?),
+        }))
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+fn decode_account_data(account: &Value) -> Result<Option<Vec<u8>>> {
+    if account.is_null() {
+        return Ok(None);
+    }
+    let data = account["data"][0]
+        .as_str()
+        .context("account data is not base64")?;
+    Ok(Some(STANDARD.decode(data)?))
+}
+
+struct Keeper {
+    rpc: Rpc,
+    config: Config,
+    payer: Keypair,
+}
+
+impl Keeper {
+    /// Sign and send the instructions after the compute budget instructions,
+    /// with a fresh blockhash on each attempt
+    async fn send(&self, instructions: &[Instruction], priority_fee: u64) -> Result<Signature> {
+        let instructions = with_compute_budget(
+            &self.config.program_id,
+            instructions.to_vec(),
+            ComputeBudget {
+                unit_limit: self.config.compute_unit_limit,
+                unit_price: Some(priority_fee),
+            },
+        );
+        Ok(send_with_retries(
+            &self.rpc,
+            &instructions,
+            &self.payer.pubkey(),
+            &[&self.payer],
+            RetryPolicy {
+                max_attempts: self.config.retries + 1,
+                ..RetryPolicy::default()
+            },
+        )
+        .await?)
+    }
+
+    /// Send the instructions in transactions of `per_transaction`, logging
+    /// failed transactions without stopping the round
+    async fn send_all(
+        &self,
+        action: &str,
+        instructions: &[Instruction],
+        per_transaction: usize,
+        priority_fee: u64,
+    ) {
+        for batch in instructions.chunks(per_transaction) {
+            match self.send(batch, priority_fee).await {
+                Ok(signature) => println!("{} {} in {}", action, batch.len(), signature),
+                Err(err) => eprintln!("{} {} failed: {:#}", action, batch.len(), err),
+            }
+        }
+    }
+
+    async fn run_round(&self) -> Result<()> {
+        let Config {
+            program_id, pool, ..
+        } = self.config;
+        let stake_pool = Pool::unpack(
+            &self
+                .rpc
+                .account_data(&pool)
+                .await?
+                .with_context(|| format!("pool {} not found", pool))?,
+        )?;
+        let stake_users: Vec<(Pubkey, StakeUser)> = self
+            .rpc
+            .program_accounts(&program_id, stake_users_of_pool_filters(&pool))
+            .await?
+            .into_iter()
+            .filter_map(|(pubkey, data)| Some((pubkey, StakeUser::unpack(&data).ok()?)))
+            .collect();
+        let priority_fee = match self.config.priority_fee {
+            Some(priority_fee) => priority_fee,
+            None => self.rpc.priority_fee(&[pool]).await?,
+        };
+
+        // the clock of the cluster may lag, the program refuses early rebalances
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        if stake_pool.is_rebalance_due(now) {
+            let rebalance = [rebalance_rate(program_id, pool)?];
+            self.send_all("rebalanced rate", &rebalance, 1, priority_fee)
+                .await;
+        }
+
+        let staked: Vec<Pubkey> = stake_users
+            .iter()
+            .filter(|(_, stake_user)| stake_user.has_stake())
+            .map(|(pubkey, _)| *pubkey)
+            .collect();
+        let refreshes = refresh_chunks(program_id, pool, staked)?;
+        self.send_all("refreshed batches", &refreshes, 1, priority_fee)
+            .await;
+
+        if let Some(claim_min) = self.config.claim_min {
+            let claims = self
+                .claims(&stake_pool, stake_users, claim_min.max(1))
+                .await?;
+            self.send_all("claimed for", &claims, CLAIMS_PER_TRANSACTION, priority_fee)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// ClaimFor instructions of the stake users owed at least `claim_min` as
+    /// of now, whose owner has an associated token account for the reward mint
+    /// that isn't frozen
+    async fn claims(
+        &self,
+        stake_pool: &Pool,
+        stake_users: Vec<(Pubkey, StakeUser)>,
+        claim_min: u64,
+    ) -> Result<Vec<Instruction>> {
+        let Config {
+            program_id, pool, ..
+        } = self.config;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let owed: Vec<(Pubkey, Pubkey)> = stake_users
+            .into_iter()
+            .filter_map(|(pubkey, stake_user)| {
+                let pending = stake_user.pending_reward(stake_pool, now).ok()?;
+                (pending >= claim_min).then_some((pubkey, stake_user.owner))
+            })
+            .collect();
+        let destinations: Vec<Pubkey> = owed
+            .iter()
+            .map(|(_, owner)| {
+                find_associated_token_address(owner, &stake_pool.reward_mint, &spl_token::id()).0
+            })
+            .collect();
+        let payable = self.rpc.token_accounts_payable(&destinations).await?;
+
+        let authority = Pool::authority(&program_id, &pool).0;
+        let reward_source = if stake_pool.is_vault_funded() {
+            stake_pool.reward_vault
+        } else {
+            stake_pool.reward_mint
+        };
+        owed.into_iter()
+            .zip(payable)
+            .filter(|(_, payable)| *payable)
+            .map(|((stake_user, owner), _)| {
+                let mut instruction = claim_for(
+                    program_id,
+                    pool,
+                    stake_user,
+                    owner,
+                    authority,
+                    reward_source,
+                    stake_pool.reward_mint,
+                )?;
+                if stake_pool.is_oracle_priced() {
+                    instruction = with_price_oracle(instruction, stake_pool.price_oracle)?;
+                }
+                if stake_pool.is_vault_funded() && stake_pool.claim_burn_bps > 0 {
+                    instruction
+                        .accounts
+                        .push(AccountMeta::new(stake_pool.reward_mint, false));
+                }
+                Ok(with_global_config(program_id, instruction))
+            })
+            .collect()
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let payer = read_keypair_file(&config.keypair_path)
+        .map_err(|err| anyhow!("reading {}: {}", config.keypair_path, err))?;
+    let keeper = Keeper {
+        rpc: Rpc {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+        },
+        config,
+        payer,
+    };
+
+    match keeper.config.interval {
+        None => keeper.run_round().await,
+        Some(interval) => loop {
+            if let Err(err) = keeper.run_round().await {
+                eprintln!("round failed: {:#}", err);
+            }
+            tokio::time::sleep(interval).await;
+        },
+    }
+}
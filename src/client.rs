@@ -0,0 +1,1870 @@
+//! Off-chain helpers for clients and indexers, behind the `client` feature
+
+use std::{future::Future, time::Duration};
+
+use arrayref::array_ref;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::BorshDeserialize;
+use num_traits::FromPrimitive;
+
+use solana_program::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    message::Message,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use solana_rpc_client_api::{
+    filter::{Memcmp, RpcFilterType},
+    response::RpcSimulateTransactionResult,
+};
+use solana_sdk::{
+    signature::Signature,
+    signer::{signers::Signers, SignerError},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token_2022::{
+    extension::{interest_bearing_mint, ExtensionType},
+    offchain::{resolve_extra_transfer_account_metas, AccountDataResult, AccountFetchError},
+    state::Mint,
+};
+
+use thiserror::Error;
+
+use crate::{
+    error::CustomError,
+    event::{Event, EVENT_LOG_PREFIX},
+    instruction::{
+        create_stake_user_idempotent, refresh, stake, with_global_config, with_hook,
+        with_instructions_sysvar, InstructionType, PoolInfo, RefreshCounts,
+    },
+    math,
+    pda::{find_pool_authority_address, find_stake_user_address},
+    state::{
+        BonusCampaign, DistributionReport, LoyaltyTier, Pool, PoolSnapshot, StakeSnapshot,
+        StakeUser,
+    },
+};
+
+/// Stake users a Refresh instruction takes so that its transaction, paid by a
+/// single signer, fits the 1232 byte packet limit with room left for a compute
+/// budget instruction. Each stake user costs 33 bytes: its address and its
+/// account index.
+pub const MAX_REFRESH_ACCOUNTS_PER_IX: usize = 28;
+
+/// Pools a routed deposit is split across so that its transaction, paid by
+/// the owner, fits the 1232 byte packet limit with room left for a compute
+/// budget instruction. Legs with hook accounts or top-level-only pools take
+/// more room.
+pub const MAX_ROUTE_LEGS: usize = 6;
+
+/// Size of nonce accounts
+pub const NONCE_ACCOUNT_LEN: usize = 80;
+
+/// Offset of the authority of nonce accounts, after the version and state
+const NONCE_AUTHORITY_OFFSET: usize = 8;
+
+/// Offset of the durable nonce of nonce accounts, after the authority
+const NONCE_OFFSET: usize = 40;
+
+/// Program setting the compute unit limit and price of a transaction
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("ComputeBudget111111111111111111111111111111");
+
+/// Most compute units a transaction may use
+pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+
+/// Compute units estimated for an instruction of another program, what the
+/// runtime grants an instruction without a limit set
+pub const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u32 = 200_000;
+
+/// Compute units estimated for an instruction of the program besides its
+/// accounts
+const INSTRUCTION_COMPUTE_UNITS: u32 = 20_000;
+
+/// Compute units estimated per account of an instruction of the program,
+/// covering the stake users a Refresh accrues and calls into token programs
+const ACCOUNT_COMPUTE_UNITS: u32 = 8_000;
+
+/// Compute units a compute budget instruction uses
+const COMPUTE_BUDGET_INSTRUCTION_UNITS: u32 = 150;
+
+/// `getProgramAccounts` filters matching all stake pools
+pub fn pool_filters() -> Vec<RpcFilterType> {
+    vec![RpcFilterType::DataSize(Pool::LEN as u64)]
+}
+
+/// `getProgramAccounts` filters matching stake pools administered by `admin`
+pub fn pools_of_admin_filters(admin: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(Pool::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            Pool::ADMIN_OFFSET,
+            admin.as_ref(),
+        )),
+    ]
+}
+
+/// `getProgramAccounts` filters matching stake pools of a staking token mint
+pub fn pools_of_stake_mint_filters(stake_token_mint: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(Pool::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            Pool::STAKE_TOKEN_MINT_OFFSET,
+            stake_token_mint.as_ref(),
+        )),
+    ]
+}
+
+/// `getProgramAccounts` filters matching all stake users of a pool
+pub fn stake_users_of_pool_filters(stake_pool: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(StakeUser::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            StakeUser::POOL_PUBKEY_OFFSET,
+            stake_pool.as_ref(),
+        )),
+    ]
+}
+
+/// `getProgramAccounts` filters matching the stake users of a pool at a
+/// loyalty tier, as of their last accrual
+pub fn stake_users_at_tier_filters(stake_pool: &Pubkey, tier: LoyaltyTier) -> Vec<RpcFilterType> {
+    let mut filters = stake_users_of_pool_filters(stake_pool);
+    filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+        StakeUser::TIER_OFFSET,
+        &[tier as u8],
+    )));
+    filters
+}
+
+/// `getProgramAccounts` filters matching all stake users of a wallet
+pub fn stake_users_of_owner_filters(owner: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(StakeUser::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            StakeUser::OWNER_OFFSET,
+            owner.as_ref(),
+        )),
+    ]
+}
+
+/// `getProgramAccounts` filters matching all snapshots of a pool
+pub fn snapshots_of_pool_filters(stake_pool: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(PoolSnapshot::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            PoolSnapshot::POOL_PUBKEY_OFFSET,
+            stake_pool.as_ref(),
+        )),
+    ]
+}
+
+/// `getProgramAccounts` filters matching all distribution reports of a pool,
+/// its emissions history
+pub fn distribution_reports_of_pool_filters(stake_pool: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(DistributionReport::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            DistributionReport::POOL_PUBKEY_OFFSET,
+            stake_pool.as_ref(),
+        )),
+    ]
+}
+
+/// Raw amount of a mint of `decimals` written in whole tokens, like `"12.5"`.
+/// Digits more precise than the mint, signs, exponents and separators are
+/// rejected rather than rounded away.
+pub fn parse_amount(amount: &str, decimals: u8) -> Result<u64, ProgramError> {
+    let (whole, fraction) = match amount.split_once('.') {
+        Some((whole, fraction)) if !fraction.is_empty() => (whole, fraction),
+        Some(_) => return Err(ProgramError::InvalidArgument),
+        None => (amount, ""),
+    };
+    if whole.is_empty()
+        || fraction.len() > decimals as usize
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    format!(
+        "{}{}{:0<3$}",
+        whole,
+        fraction,
+        "",
+        decimals as usize - fraction.len()
+    )
+    .parse()
+    .map_err(|_| ProgramError::InvalidArgument)
+}
+
+/// Raw amount of a mint of `decimals` in whole tokens, without trailing
+/// zeros, e.g. `"12.5"` for 12_500_000 of a 6 decimal mint
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    let digits = format!("{:0>1$}", amount, decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    match fraction.trim_end_matches('0') {
+        "" => whole.to_string(),
+        fraction => format!("{}.{}", whole, fraction),
+    }
+}
+
+/// Raw amount of the primary stake token of a pool written in whole tokens
+pub fn parse_stake_amount(stake_pool: &Pool, amount: &str) -> Result<u64, ProgramError> {
+    parse_amount(amount, stake_pool.stake_decimals)
+}
+
+/// Raw amount of the primary stake token of a pool in whole tokens
+pub fn format_stake_amount(stake_pool: &Pool, amount: u64) -> String {
+    format_amount(amount, stake_pool.stake_decimals)
+}
+
+/// Raw amount of the reward token of a pool written in whole tokens
+pub fn parse_reward_amount(stake_pool: &Pool, amount: &str) -> Result<u64, ProgramError> {
+    parse_amount(amount, stake_pool.reward_decimals)
+}
+
+/// Raw amount of the reward token of a pool in whole tokens
+pub fn format_reward_amount(stake_pool: &Pool, amount: u64) -> String {
+    format_amount(amount, stake_pool.reward_decimals)
+}
+
+/// Lamports keeping a stake pool account rent-exempt
+pub fn minimum_balance_for_pool(rent: &Rent) -> u64 {
+    rent.minimum_balance(Pool::LEN)
+}
+
+/// Lamports keeping a stake user account rent-exempt
+pub fn minimum_balance_for_stake_user(rent: &Rent) -> u64 {
+    rent.minimum_balance(StakeUser::LEN)
+}
+
+/// Lamports keeping a pool snapshot account rent-exempt
+pub fn minimum_balance_for_pool_snapshot(rent: &Rent) -> u64 {
+    rent.minimum_balance(PoolSnapshot::LEN)
+}
+
+/// Lamports keeping a stake snapshot account rent-exempt
+pub fn minimum_balance_for_stake_snapshot(rent: &Rent) -> u64 {
+    rent.minimum_balance(StakeSnapshot::LEN)
+}
+
+/// Lamports keeping a bonus campaign account of `leaf_count` recipients
+/// rent-exempt
+pub fn minimum_balance_for_bonus_campaign(rent: &Rent, leaf_count: u64) -> u64 {
+    rent.minimum_balance(BonusCampaign::account_len(leaf_count))
+}
+
+/// Create the account of a stake pool, sized and funded for CreatePool
+pub fn create_pool_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    rent: &Rent,
+) -> Instruction {
+    create_program_account(program_id, payer_pubkey, stake_pool_pubkey, Pool::LEN, rent)
+}
+
+/// Create the account of a stake user, sized and funded for CreateStakeUser
+pub fn create_stake_user_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    stake_user_pubkey: &Pubkey,
+    rent: &Rent,
+) -> Instruction {
+    create_program_account(
+        program_id,
+        payer_pubkey,
+        stake_user_pubkey,
+        StakeUser::LEN,
+        rent,
+    )
+}
+
+/// Create the account of a pool snapshot, sized and funded for SnapshotPool
+pub fn create_pool_snapshot_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    pool_snapshot_pubkey: &Pubkey,
+    rent: &Rent,
+) -> Instruction {
+    create_program_account(
+        program_id,
+        payer_pubkey,
+        pool_snapshot_pubkey,
+        PoolSnapshot::LEN,
+        rent,
+    )
+}
+
+/// Create the account of a stake snapshot, sized and funded for
+/// CreateStakeSnapshot
+pub fn create_stake_snapshot_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    stake_snapshot_pubkey: &Pubkey,
+    rent: &Rent,
+) -> Instruction {
+    create_program_account(
+        program_id,
+        payer_pubkey,
+        stake_snapshot_pubkey,
+        StakeSnapshot::LEN,
+        rent,
+    )
+}
+
+/// Create the account of a bonus campaign of `leaf_count` recipients, sized
+/// and funded for PublishBonus
+pub fn create_bonus_campaign_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    bonus_campaign_pubkey: &Pubkey,
+    leaf_count: u64,
+    rent: &Rent,
+) -> Instruction {
+    create_program_account(
+        program_id,
+        payer_pubkey,
+        bonus_campaign_pubkey,
+        BonusCampaign::account_len(leaf_count),
+        rent,
+    )
+}
+
+/// Create and initialize an interest-bearing Token-2022 reward mint accruing
+/// `rate` basis points a year, minted by the pool authority, for CreatePool
+/// of the pool at `stake_pool_pubkey`
+#[allow(clippy::too_many_arguments)]
+pub fn create_interest_bearing_reward_mint(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    reward_mint_pubkey: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    rate_authority_pubkey: Option<Pubkey>,
+    rate: i16,
+    decimals: u8,
+    rent: &Rent,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let space =
+        ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::InterestBearingConfig])?;
+    let (authority_pubkey, _) = find_pool_authority_address(program_id, stake_pool_pubkey);
+    Ok(vec![
+        create_program_account(
+            &spl_token_2022::id(),
+            payer_pubkey,
+            reward_mint_pubkey,
+            space,
+            rent,
+        ),
+        interest_bearing_mint::instruction::initialize(
+            &spl_token_2022::id(),
+            reward_mint_pubkey,
+            rate_authority_pubkey,
+            rate,
+        )?,
+        spl_token_2022::instruction::initialize_mint2(
+            &spl_token_2022::id(),
+            reward_mint_pubkey,
+            &authority_pubkey,
+            None,
+            decimals,
+        )?,
+    ])
+}
+
+/// Rent-exempt account of `space` bytes owned by the program
+fn create_program_account(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    account_pubkey: &Pubkey,
+    space: usize,
+    rent: &Rent,
+) -> Instruction {
+    system_instruction::create_account(
+        payer_pubkey,
+        account_pubkey,
+        rent.minimum_balance(space),
+        space as u64,
+        program_id,
+    )
+}
+
+/// Accounts of the transfer hook of a Token-2022 stake mint moving `amount`
+/// from `source_pubkey` to `destination_pubkey`, for `with_stake_mint`. They
+/// are resolved from the hook's validation account with
+/// `fetch_account_data`, e.g. backed by RPC, and are empty for mints without
+/// a transfer hook. Unstakes transfer out of the reserve on the authority of
+/// the pool.
+pub async fn resolve_transfer_hook_accounts<F, Fut>(
+    fetch_account_data: F,
+    stake_mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Result<Vec<AccountMeta>, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let mut transfer = spl_token_2022::instruction::transfer_checked(
+        &spl_token_2022::id(),
+        source_pubkey,
+        stake_mint_pubkey,
+        destination_pubkey,
+        authority_pubkey,
+        &[],
+        amount,
+        decimals,
+    )?;
+    let transfer_accounts = transfer.accounts.len();
+    resolve_extra_transfer_account_metas(&mut transfer, fetch_account_data, stake_mint_pubkey)
+        .await?;
+    Ok(transfer.accounts.split_off(transfer_accounts))
+}
+
+/// Refresh instructions covering the stake users, in chunks of
+/// `MAX_REFRESH_ACCOUNTS_PER_IX`
+pub fn refresh_chunks(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    mut stake_user_pubkeys: Vec<Pubkey>,
+) -> Result<Vec<Instruction>, ProgramError> {
+    stake_user_pubkeys.sort();
+    stake_user_pubkeys.dedup();
+    stake_user_pubkeys
+        .chunks(MAX_REFRESH_ACCOUNTS_PER_IX)
+        .map(|chunk| refresh(program_id, stake_pool_pubkey, chunk.to_vec()))
+        .collect()
+}
+
+/// Messages of one Refresh instruction each, covering the stake users, for
+/// the payer to sign and send, after compute budget instructions if given
+pub fn refresh_messages(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkeys: Vec<Pubkey>,
+    payer_pubkey: &Pubkey,
+    compute_budget: Option<ComputeBudget>,
+) -> Result<Vec<Message>, ProgramError> {
+    Ok(
+        refresh_chunks(program_id, stake_pool_pubkey, stake_user_pubkeys)?
+            .into_iter()
+            .map(|instruction| {
+                let instructions = match compute_budget {
+                    Some(compute_budget) => {
+                        with_compute_budget(&program_id, vec![instruction], compute_budget)
+                    }
+                    None => vec![instruction],
+                };
+                Message::new(&instructions, Some(payer_pubkey))
+            })
+            .collect(),
+    )
+}
+
+/// Compute budget of a transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    /// Compute units the transaction may use, estimated from its
+    /// instructions if `None`
+    pub unit_limit: Option<u32>,
+    /// Priority fee in micro-lamports per compute unit, none if `None`
+    pub unit_price: Option<u64>,
+}
+
+/// Instruction setting the compute unit limit of its transaction
+pub fn set_compute_unit_limit(units: u32) -> Instruction {
+    let mut data = vec![2];
+    data.extend_from_slice(&units.to_le_bytes());
+    Instruction::new_with_bytes(COMPUTE_BUDGET_PROGRAM_ID, &data, vec![])
+}
+
+/// Instruction setting the priority fee of its transaction, in
+/// micro-lamports per compute unit
+pub fn set_compute_unit_price(micro_lamports: u64) -> Instruction {
+    let mut data = vec![3];
+    data.extend_from_slice(&micro_lamports.to_le_bytes());
+    Instruction::new_with_bytes(COMPUTE_BUDGET_PROGRAM_ID, &data, vec![])
+}
+
+/// Compute units a transaction of the instructions may use, with headroom.
+/// Instructions of the program are estimated from their accounts, so a
+/// Refresh grows with its stake users; those of other programs get what the
+/// runtime grants them by default.
+pub fn estimate_compute_units(program_id: &Pubkey, instructions: &[Instruction]) -> u32 {
+    instructions
+        .iter()
+        .map(|instruction| {
+            if instruction.program_id == *program_id {
+                INSTRUCTION_COMPUTE_UNITS.saturating_add(
+                    ACCOUNT_COMPUTE_UNITS.saturating_mul(instruction.accounts.len() as u32),
+                )
+            } else if instruction.program_id == COMPUTE_BUDGET_PROGRAM_ID {
+                COMPUTE_BUDGET_INSTRUCTION_UNITS
+            } else {
+                DEFAULT_INSTRUCTION_COMPUTE_UNITS
+            }
+        })
+        .fold(0u32, u32::saturating_add)
+        .min(MAX_COMPUTE_UNITS)
+}
+
+/// The instructions after compute budget instructions setting the unit
+/// limit, estimated unless given, and the unit price if given
+pub fn with_compute_budget(
+    program_id: &Pubkey,
+    instructions: Vec<Instruction>,
+    compute_budget: ComputeBudget,
+) -> Vec<Instruction> {
+    let mut budgeted = vec![];
+    let unit_limit = compute_budget.unit_limit.unwrap_or_else(|| {
+        let budget_instructions = 1 + compute_budget.unit_price.is_some() as u32;
+        estimate_compute_units(program_id, &instructions)
+            .saturating_add(budget_instructions * COMPUTE_BUDGET_INSTRUCTION_UNITS)
+            .min(MAX_COMPUTE_UNITS)
+    });
+    budgeted.push(set_compute_unit_limit(unit_limit));
+    if let Some(unit_price) = compute_budget.unit_price {
+        budgeted.push(set_compute_unit_price(unit_price));
+    }
+    budgeted.extend(instructions);
+    budgeted
+}
+
+/// Pool a routed deposit stakes a share of
+#[derive(Clone, Debug)]
+pub struct RouteLeg {
+    pub stake_pool_pubkey: Pubkey,
+    pub stake_pool: Pool,
+    /// Weight of the pool in the split, relative to the other legs
+    pub weight: u64,
+    /// Accounts the hook program of the pool expects, if it has one
+    pub hook_accounts: Vec<AccountMeta>,
+}
+
+/// Split an amount proportionally to the weights, rounding down and adding
+/// the remainder to the first leg of non-zero weight
+pub fn split_by_weights(amount: u64, weights: &[u64]) -> Result<Vec<u64>, ProgramError> {
+    let total = weights
+        .iter()
+        .try_fold(0u64, |total, &weight| total.checked_add(weight))
+        .ok_or(ProgramError::InvalidArgument)?;
+    if total == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut parts = weights
+        .iter()
+        .map(|&weight| math::mul_div(amount, weight, total))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ProgramError::InvalidArgument)?;
+    let remainder = amount - parts.iter().sum::<u64>();
+    if let Some(first) = weights.iter().position(|&weight| weight != 0) {
+        parts[first] += remainder;
+    }
+    Ok(parts)
+}
+
+/// Instructions staking a deposit across several pools by weight in one
+/// transaction, each leg creating the stake user of the owner at its program
+/// address if needed, paid by the payer. Legs getting nothing are left out;
+/// all pools must accept the mint of the source.
+#[allow(clippy::too_many_arguments)]
+pub fn route_stake(
+    program_id: Pubkey,
+    legs: &[RouteLeg],
+    user_transfer_authority_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    stake_mint_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    if legs.len() > MAX_ROUTE_LEGS {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let weights: Vec<u64> = legs.iter().map(|leg| leg.weight).collect();
+    let parts = split_by_weights(amount, &weights)?;
+
+    let mut instructions = vec![];
+    for (leg, part) in legs.iter().zip(parts) {
+        if part == 0 {
+            continue;
+        }
+        let (stake_user_pubkey, _) =
+            find_stake_user_address(&program_id, &leg.stake_pool_pubkey, &stake_owner_pubkey);
+        instructions.push(create_stake_user_idempotent(
+            program_id,
+            leg.stake_pool_pubkey,
+            stake_owner_pubkey,
+            payer_pubkey,
+        )?);
+        let mut instruction = stake(
+            program_id,
+            leg.stake_pool_pubkey,
+            &leg.stake_pool,
+            stake_user_pubkey,
+            user_transfer_authority_pubkey,
+            stake_owner_pubkey,
+            source_pubkey,
+            stake_mint_pubkey,
+            part,
+        )?;
+        if leg.stake_pool.hook_program != Pubkey::default() {
+            instruction = with_hook(
+                instruction,
+                leg.stake_pool.hook_program,
+                leg.hook_accounts.clone(),
+            );
+        }
+        if leg.stake_pool.top_level_only {
+            instruction = with_instructions_sysvar(instruction);
+        }
+        instructions.push(with_global_config(program_id, instruction));
+    }
+    Ok(instructions)
+}
+
+/// How a transaction is resent until it lands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Transactions signed with a fresh blockhash before giving up, each once
+    /// the blockhash of the previous one expired
+    pub max_attempts: usize,
+    /// Status polls of a transaction before giving up on it, resending it at
+    /// each
+    pub max_polls: usize,
+    /// Wait before each status poll
+    pub poll_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, polled every second for longer than a blockhash lives
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            max_polls: 90,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// RPC calls landing transactions, over the RPC client of the caller's
+/// choice. Statuses and block heights should be read at the same commitment.
+pub trait TransactionRpc {
+    type Error;
+
+    /// Latest blockhash with the last block height transactions signed with
+    /// it are valid at
+    fn latest_blockhash(&self) -> impl Future<Output = Result<(Hash, u64), Self::Error>>;
+
+    /// Current block height
+    fn block_height(&self) -> impl Future<Output = Result<u64, Self::Error>>;
+
+    /// Data of an account, `None` if it doesn't exist
+    fn account_data(
+        &self,
+        pubkey: &Pubkey,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, Self::Error>>;
+
+    /// Send a signed transaction
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Outcome of a transaction, `None` until it is confirmed
+    fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> impl Future<Output = Result<Option<Result<(), TransactionError>>, Self::Error>>;
+
+    /// Wait for a duration
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+/// Errors of sending a transaction until it lands
+#[derive(Debug, Error, PartialEq)]
+pub enum SendError<E> {
+    #[error("RPC failed: {0}")]
+    Rpc(E),
+    #[error("signing failed: {0}")]
+    Signing(#[from] SignerError),
+    #[error("transaction {0} failed: {1}")]
+    Failed(Signature, TransactionError),
+    /// Resending with a fresh blockhash could land the instructions twice
+    #[error("transaction {0} still unconfirmed")]
+    Unconfirmed(Signature),
+    #[error("transactions expired unconfirmed after {0} attempts")]
+    Expired(usize),
+    #[error("nonce account missing, uninitialized or of another authority")]
+    InvalidNonceAccount,
+    /// The nonce was advanced by another transaction
+    #[error("nonce advanced without transaction {0}")]
+    NonceAdvanced(Signature),
+}
+
+/// Authority and durable nonce of an initialized nonce account
+pub fn parse_nonce_account(data: &[u8]) -> Option<(Pubkey, Hash)> {
+    // current version, initialized
+    if data.len() != NONCE_ACCOUNT_LEN || data[..NONCE_AUTHORITY_OFFSET] != [1, 0, 0, 0, 1, 0, 0, 0]
+    {
+        return None;
+    }
+    Some((
+        Pubkey::new_from_array(*array_ref![data, NONCE_AUTHORITY_OFFSET, 32]),
+        Hash::new_from_array(*array_ref![data, NONCE_OFFSET, 32]),
+    ))
+}
+
+/// Message of the instructions after advancing a durable nonce, valid until
+/// the nonce is advanced instead of for the lifetime of a blockhash
+pub fn durable_nonce_message(
+    instructions: &[Instruction],
+    payer_pubkey: &Pubkey,
+    nonce_account_pubkey: &Pubkey,
+    nonce_authority_pubkey: &Pubkey,
+    nonce: Hash,
+) -> Message {
+    let mut message = Message::new_with_nonce(
+        instructions.to_vec(),
+        Some(payer_pubkey),
+        nonce_account_pubkey,
+        nonce_authority_pubkey,
+    );
+    message.recent_blockhash = nonce;
+    message
+}
+
+/// Send the instructions, like a stake or claim, paid by the payer and signed
+/// by the signers, until they land. Each attempt signs with a fresh blockhash
+/// and is resent at every status poll; the next one starts only once the
+/// blockhash expired, so the instructions never land twice.
+pub async fn send_with_retries<R, T>(
+    rpc: &R,
+    instructions: &[Instruction],
+    payer_pubkey: &Pubkey,
+    signers: &T,
+    policy: RetryPolicy,
+) -> Result<Signature, SendError<R::Error>>
+where
+    R: TransactionRpc,
+    T: Signers + ?Sized,
+{
+    let message = Message::new(instructions, Some(payer_pubkey));
+    for _ in 0..policy.max_attempts {
+        let (blockhash, last_valid_block_height) =
+            rpc.latest_blockhash().await.map_err(SendError::Rpc)?;
+        let mut transaction = Transaction::new_unsigned(message.clone());
+        transaction.try_sign(signers, blockhash)?;
+        if let Some(signature) = confirm(
+            rpc,
+            &transaction,
+            Expiry::BlockHeight(last_valid_block_height),
+            policy,
+        )
+        .await?
+        {
+            return Ok(signature);
+        }
+    }
+    Err(SendError::Expired(policy.max_attempts))
+}
+
+/// Send the instructions after advancing the durable nonce of a nonce
+/// account, so the transaction stays valid until it lands, e.g. when queued
+/// behind many others. Signed once and resent at every status poll, it never
+/// lands twice, and sending the instructions again after `Unconfirmed` signs
+/// the same transaction as long as the nonce wasn't advanced.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_with_nonce<R, T>(
+    rpc: &R,
+    instructions: &[Instruction],
+    payer_pubkey: &Pubkey,
+    nonce_account_pubkey: &Pubkey,
+    nonce_authority_pubkey: &Pubkey,
+    signers: &T,
+    policy: RetryPolicy,
+) -> Result<Signature, SendError<R::Error>>
+where
+    R: TransactionRpc,
+    T: Signers + ?Sized,
+{
+    let nonce = match rpc
+        .account_data(nonce_account_pubkey)
+        .await
+        .map_err(SendError::Rpc)?
+        .as_deref()
+        .and_then(parse_nonce_account)
+    {
+        Some((authority, nonce)) if authority == *nonce_authority_pubkey => nonce,
+        _ => return Err(SendError::InvalidNonceAccount),
+    };
+    let message = durable_nonce_message(
+        instructions,
+        payer_pubkey,
+        nonce_account_pubkey,
+        nonce_authority_pubkey,
+        nonce,
+    );
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.try_sign(signers, nonce)?;
+    confirm(
+        rpc,
+        &transaction,
+        Expiry::Nonce(*nonce_account_pubkey, nonce),
+        policy,
+    )
+    .await?
+    .ok_or(SendError::NonceAdvanced(transaction.signatures[0]))
+}
+
+/// What invalidates a transaction that didn't land
+enum Expiry {
+    /// Block height past the last one its blockhash is valid at
+    BlockHeight(u64),
+    /// Nonce account and nonce advanced by it
+    Nonce(Pubkey, Hash),
+}
+
+/// Send a transaction at every status poll until it is confirmed, `None` if
+/// it expired without landing. Errors of resending are ignored, as a
+/// transaction having landed since fails to send again.
+async fn confirm<R: TransactionRpc>(
+    rpc: &R,
+    transaction: &Transaction,
+    expiry: Expiry,
+    policy: RetryPolicy,
+) -> Result<Option<Signature>, SendError<R::Error>> {
+    let signature = transaction.signatures[0];
+    let status = |status: Result<(), TransactionError>| {
+        status
+            .map(|()| Some(signature))
+            .map_err(|err| SendError::Failed(signature, err))
+    };
+    for poll in 0..policy.max_polls {
+        let sent = rpc.send_transaction(transaction).await;
+        if poll == 0 {
+            sent.map_err(SendError::Rpc)?;
+        }
+        rpc.sleep(policy.poll_interval).await;
+        if let Some(confirmed) = rpc
+            .signature_status(&signature)
+            .await
+            .map_err(SendError::Rpc)?
+        {
+            return status(confirmed);
+        }
+
+        let expired = match expiry {
+            Expiry::BlockHeight(last_valid_block_height) => {
+                rpc.block_height().await.map_err(SendError::Rpc)? > last_valid_block_height
+            }
+            Expiry::Nonce(nonce_account_pubkey, nonce) => {
+                let data = rpc
+                    .account_data(&nonce_account_pubkey)
+                    .await
+                    .map_err(SendError::Rpc)?;
+                data.as_deref()
+                    .and_then(parse_nonce_account)
+                    .is_none_or(|(_, current)| current != nonce)
+            }
+        };
+        if expired {
+            // it may have landed between the status and the expiry
+            return match rpc
+                .signature_status(&signature)
+                .await
+                .map_err(SendError::Rpc)?
+            {
+                Some(confirmed) => status(confirmed),
+                None => Ok(None),
+            };
+        }
+    }
+    Err(SendError::Unconfirmed(signature))
+}
+
+/// Return data of an instruction of the program, decoded
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReturnValue {
+    /// Rewards claimed by Claim, ClaimIfAny, ClaimFor or ClaimAndStakeInto,
+    /// or withdrawn by WithdrawFromStream
+    Amount(u64),
+    /// Rewards claimed by each claim of HarvestAll
+    Amounts(Vec<u64>),
+    /// Stake users updated and skipped by Refresh or RefreshBatch
+    RefreshCounts(RefreshCounts),
+    PoolInfo(PoolInfo),
+    /// Return data of another program, or not decoded, with the program
+    /// setting it
+    Raw(Pubkey, Vec<u8>),
+}
+
+/// Error of a simulated transaction
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimulationError {
+    /// Error of an instruction of the program, by index
+    Program(u8, CustomError),
+    Transaction(TransactionError),
+}
+
+/// Decoded outcome of a simulated transaction, for previews before signing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Simulation {
+    /// Why the transaction would fail, if it would
+    pub error: Option<SimulationError>,
+    /// Return data of the transaction, that of the last instruction setting
+    /// it
+    pub return_value: Option<ReturnValue>,
+    /// Events of the program, leaving out those of other schema versions
+    pub events: Vec<Event>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+impl Simulation {
+    /// Decode the result of simulating a message with instructions of the
+    /// program
+    pub fn decode(
+        program_id: &Pubkey,
+        message: &Message,
+        result: RpcSimulateTransactionResult,
+    ) -> Self {
+        let instructions: Vec<(&Pubkey, &[u8])> = message
+            .instructions
+            .iter()
+            .map(|instruction| {
+                (
+                    instruction.program_id(&message.account_keys),
+                    &instruction.data[..],
+                )
+            })
+            .collect();
+
+        let error = result.err.map(|err| match err {
+            TransactionError::InstructionError(index, InstructionError::Custom(code))
+                if instructions
+                    .get(index as usize)
+                    .is_some_and(|(id, _)| *id == program_id) =>
+            {
+                match CustomError::from_u32(code) {
+                    Some(error) => SimulationError::Program(index, error),
+                    None => SimulationError::Transaction(err),
+                }
+            }
+            err => SimulationError::Transaction(err),
+        });
+
+        let return_value = result.return_data.and_then(|return_data| {
+            let setter = return_data.program_id.parse::<Pubkey>().ok()?;
+            let data = STANDARD.decode(&return_data.data.0).ok()?;
+            // the last instruction of the program that sets return data
+            let instruction = instructions
+                .iter()
+                .rev()
+                .filter(|(id, _)| *id == program_id)
+                .filter_map(|(_, data)| InstructionType::unpack(data).ok())
+                .find(sets_return_data);
+            Some(
+                instruction
+                    .filter(|_| setter == *program_id)
+                    .and_then(|instruction| decode_return_data(&instruction, &data))
+                    .unwrap_or(ReturnValue::Raw(setter, data)),
+            )
+        });
+
+        let logs = result.logs.unwrap_or_default();
+        Self {
+            error,
+            return_value,
+            events: program_events(program_id, &logs),
+            units_consumed: result.units_consumed,
+            logs,
+        }
+    }
+
+    /// Rewards a claim would pay, for claim previews
+    pub fn claimed_amount(&self) -> Option<u64> {
+        match self.return_value {
+            Some(ReturnValue::Amount(amount)) if self.error.is_none() => Some(amount),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an instruction of the program sets return data
+fn sets_return_data(instruction: &InstructionType) -> bool {
+    matches!(
+        instruction,
+        InstructionType::Claim
+            | InstructionType::ClaimIfAny
+            | InstructionType::ClaimFor
+            | InstructionType::ClaimAndStakeInto
+            | InstructionType::WithdrawFromStream
+            | InstructionType::HarvestAll(_)
+            | InstructionType::Refresh
+            | InstructionType::RefreshBatch
+            | InstructionType::GetPoolInfo
+    )
+}
+
+/// Return data of an instruction of the program, `None` if it sets none or
+/// it doesn't decode
+fn decode_return_data(instruction: &InstructionType, data: &[u8]) -> Option<ReturnValue> {
+    match instruction {
+        InstructionType::Claim
+        | InstructionType::ClaimIfAny
+        | InstructionType::ClaimFor
+        | InstructionType::ClaimAndStakeInto
+        | InstructionType::WithdrawFromStream => {
+            u64::try_from_slice(data).ok().map(ReturnValue::Amount)
+        }
+        InstructionType::HarvestAll(_) => Vec::<u64>::try_from_slice(data)
+            .ok()
+            .map(ReturnValue::Amounts),
+        InstructionType::Refresh | InstructionType::RefreshBatch => {
+            RefreshCounts::try_from_slice(data)
+                .ok()
+                .map(ReturnValue::RefreshCounts)
+        }
+        // newer programs may append fields
+        InstructionType::GetPoolInfo => PoolInfo::deserialize(&mut &data[..])
+            .ok()
+            .map(ReturnValue::PoolInfo),
+        _ => None,
+    }
+}
+
+/// Events logged by the program itself, following the invocations in the
+/// logs so that those of other programs are left out
+pub fn program_events(program_id: &Pubkey, logs: &[String]) -> Vec<Event> {
+    let invoke_prefix = format!("Program {} invoke", program_id);
+    let mut invocations: Vec<bool> = vec![];
+    let mut events = vec![];
+    for line in logs {
+        if let Some(invoked) = line.strip_prefix("Program ") {
+            if invoked.contains(" invoke [") {
+                invocations.push(line.starts_with(&invoke_prefix));
+                continue;
+            }
+            if invoked.ends_with(" success") || invoked.contains(" failed: ") {
+                invocations.pop();
+                continue;
+            }
+        }
+        if invocations.last() == Some(&true) && line.starts_with(EVENT_LOG_PREFIX) {
+            if let Some(Ok(event)) = Event::from_log(line) {
+                events.push(event);
+            }
+        }
+    }
+    events
+}
+
+/// Simulate a transaction with `simulate_transaction`, e.g. over RPC, and
+/// decode its outcome. Signatures aren't needed if the simulation skips
+/// verifying them.
+pub async fn simulate<F, Fut, E>(
+    simulate_transaction: F,
+    program_id: &Pubkey,
+    transaction: Transaction,
+) -> Result<Simulation, E>
+where
+    F: FnOnce(Transaction) -> Fut,
+    Fut: Future<Output = Result<RpcSimulateTransactionResult, E>>,
+{
+    let message = transaction.message.clone();
+    let result = simulate_transaction(transaction).await?;
+    Ok(Simulation::decode(program_id, &message, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{InstructionType, StakeData};
+    use serde_json::{json, Value};
+    use solana_program::{
+        program_utils::limited_deserialize, system_instruction::SystemInstruction, system_program,
+    };
+    use solana_sdk::{
+        account::{AccountSharedData, ReadableAccount},
+        account_utils::StateMut,
+        nonce::state::{Data, DurableNonce, State, Versions},
+        signature::{Keypair, Signer},
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        task::{Context, Poll, Waker},
+    };
+
+    /// Largest serialized transaction, in bytes
+    const PACKET_DATA_SIZE: usize = 1232;
+
+    #[test]
+    fn test_stake_users_of_pool_filters() {
+        let stake_pool = Pubkey::new_unique();
+        let stake_user = StakeUser {
+            pool_pubkey: stake_pool,
+            ..StakeUser::default()
+        };
+        let mut packed = [0u8; StakeUser::LEN];
+        StakeUser::pack_into_slice(&stake_user, &mut packed);
+
+        for filter in stake_users_of_pool_filters(&stake_pool) {
+            match filter {
+                RpcFilterType::DataSize(size) => assert_eq!(size, packed.len() as u64),
+                RpcFilterType::Memcmp(memcmp) => assert!(memcmp.bytes_match(&packed)),
+                _ => unreachable!(),
+            }
+        }
+        assert!(!Memcmp::new_base58_encoded(
+            StakeUser::POOL_PUBKEY_OFFSET,
+            Pubkey::new_unique().as_ref()
+        )
+        .bytes_match(&packed));
+    }
+
+    #[test]
+    fn test_amounts() {
+        assert_eq!(parse_amount("12.5", 6), Ok(12_500_000));
+        assert_eq!(parse_amount("12", 6), Ok(12_000_000));
+        assert_eq!(parse_amount("0.000001", 6), Ok(1));
+        assert_eq!(parse_amount("007", 0), Ok(7));
+        assert_eq!(parse_amount("18446744073709551615", 0), Ok(u64::MAX));
+        assert_eq!(parse_amount("18446744073.709551615", 9), Ok(u64::MAX));
+        for invalid in [
+            "",
+            ".",
+            "12.",
+            ".5",
+            "0.0000001",
+            "-1",
+            "+1",
+            "1e3",
+            "1,000",
+            " 1",
+            "1.2.3",
+            "18446744073709551616",
+            "١",
+        ] {
+            assert!(parse_amount(invalid, 6).is_err(), "{}", invalid);
+        }
+        assert_eq!(parse_amount("1.5", 0), Err(ProgramError::InvalidArgument));
+
+        assert_eq!(format_amount(12_500_000, 6), "12.5");
+        assert_eq!(format_amount(12_000_000, 6), "12");
+        assert_eq!(format_amount(1, 6), "0.000001");
+        assert_eq!(format_amount(0, 6), "0");
+        assert_eq!(format_amount(7, 0), "7");
+        assert_eq!(format_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(format_amount(1, 30), format!("0.{}1", "0".repeat(29)));
+        for amount in [0, 1, 999, 1_000_000, 123_456_789, u64::MAX] {
+            assert_eq!(parse_amount(&format_amount(amount, 6), 6), Ok(amount));
+        }
+
+        let stake_pool = Pool {
+            stake_decimals: 9,
+            reward_decimals: 2,
+            ..Pool::default()
+        };
+        assert_eq!(parse_stake_amount(&stake_pool, "1.5"), Ok(1_500_000_000));
+        assert_eq!(parse_reward_amount(&stake_pool, "1.5"), Ok(150));
+        assert_eq!(format_stake_amount(&stake_pool, 1_500_000_000), "1.5");
+        assert_eq!(format_reward_amount(&stake_pool, 150), "1.5");
+    }
+
+    #[test]
+    fn test_create_accounts() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let rent = Rent::default();
+        let created = |instruction: Instruction| match limited_deserialize(&instruction.data, 1024)
+            .unwrap()
+        {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                assert_eq!(owner, program_id);
+                assert_eq!(instruction.accounts[0].pubkey, payer);
+                (lamports, space as usize)
+            }
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            created(create_pool_account(
+                &program_id,
+                &payer,
+                &Pubkey::new_unique(),
+                &rent
+            )),
+            (minimum_balance_for_pool(&rent), Pool::LEN)
+        );
+        assert_eq!(
+            created(create_stake_user_account(
+                &program_id,
+                &payer,
+                &Pubkey::new_unique(),
+                &rent
+            )),
+            (minimum_balance_for_stake_user(&rent), StakeUser::LEN)
+        );
+        assert_eq!(
+            created(create_bonus_campaign_account(
+                &program_id,
+                &payer,
+                &Pubkey::new_unique(),
+                9,
+                &rent
+            )),
+            (
+                minimum_balance_for_bonus_campaign(&rent, 9),
+                BonusCampaign::LEN + 2
+            )
+        );
+    }
+
+    #[test]
+    fn test_refresh_messages_fit_packet() {
+        let program_id = Pubkey::new_unique();
+        let stake_pool = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let stake_users: Vec<Pubkey> = (0..MAX_REFRESH_ACCOUNTS_PER_IX * 2 + 1)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+
+        let messages =
+            refresh_messages(program_id, stake_pool, stake_users.clone(), &payer, None).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0].instructions[0].accounts.len(),
+            2 + MAX_REFRESH_ACCOUNTS_PER_IX
+        );
+        assert_eq!(messages[2].instructions[0].accounts.len(), 2 + 1);
+
+        // the compute unit limit and price
+        let compute_budget = ComputeBudget {
+            unit_limit: None,
+            unit_price: Some(10_000),
+        };
+        let transaction_size = |message: &Message| {
+            // signature count and payer signature
+            1 + 64 + message.serialize().len()
+        };
+        let messages = refresh_messages(
+            program_id,
+            stake_pool,
+            stake_users.clone(),
+            &payer,
+            Some(compute_budget),
+        )
+        .unwrap();
+        assert_eq!(messages[0].instructions.len(), 3);
+        assert!(transaction_size(&messages[0]) <= PACKET_DATA_SIZE);
+
+        let oversized = refresh(
+            program_id,
+            stake_pool,
+            stake_users[..MAX_REFRESH_ACCOUNTS_PER_IX + 1].to_vec(),
+        )
+        .unwrap();
+        let message = Message::new(
+            &with_compute_budget(&program_id, vec![oversized], compute_budget),
+            Some(&payer),
+        );
+        assert!(transaction_size(&message) > PACKET_DATA_SIZE);
+    }
+
+    #[test]
+    fn test_compute_budget() {
+        use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+        assert_eq!(
+            set_compute_unit_limit(300_000),
+            ComputeBudgetInstruction::set_compute_unit_limit(300_000)
+        );
+        assert_eq!(
+            set_compute_unit_price(10_000),
+            ComputeBudgetInstruction::set_compute_unit_price(10_000)
+        );
+
+        let program_id = Pubkey::new_unique();
+        let stake_pool = Pubkey::new_unique();
+        let stake_users: Vec<Pubkey> = (0..MAX_REFRESH_ACCOUNTS_PER_IX)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        let small = refresh(program_id, stake_pool, stake_users[..1].to_vec()).unwrap();
+        let large = refresh(program_id, stake_pool, stake_users).unwrap();
+        // Refresh grows with its stake users, well within a transaction
+        let small_units = estimate_compute_units(&program_id, std::slice::from_ref(&small));
+        let large_units = estimate_compute_units(&program_id, std::slice::from_ref(&large));
+        assert!(small_units < large_units);
+        assert!(large_units < MAX_COMPUTE_UNITS);
+        assert_eq!(
+            estimate_compute_units(&program_id, &vec![large.clone(); 10]),
+            MAX_COMPUTE_UNITS
+        );
+        let transfer = system_instruction::transfer(&program_id, &stake_pool, 1);
+        assert_eq!(
+            estimate_compute_units(&program_id, &[transfer]),
+            DEFAULT_INSTRUCTION_COMPUTE_UNITS
+        );
+
+        let instructions =
+            with_compute_budget(&program_id, vec![small.clone()], ComputeBudget::default());
+        assert_eq!(
+            instructions,
+            vec![
+                set_compute_unit_limit(small_units + COMPUTE_BUDGET_INSTRUCTION_UNITS),
+                small.clone()
+            ]
+        );
+        let instructions = with_compute_budget(
+            &program_id,
+            vec![small.clone()],
+            ComputeBudget {
+                unit_limit: Some(50_000),
+                unit_price: Some(10_000),
+            },
+        );
+        assert_eq!(
+            instructions,
+            vec![
+                set_compute_unit_limit(50_000),
+                set_compute_unit_price(10_000),
+                small
+            ]
+        );
+    }
+
+    #[test]
+    fn test_route_stake() {
+        assert_eq!(split_by_weights(100, &[1, 1, 1]), Ok(vec![34, 33, 33]));
+        assert_eq!(split_by_weights(10, &[0, 3, 1]), Ok(vec![0, 8, 2]));
+        assert_eq!(split_by_weights(1, &[0, 1, 1]), Ok(vec![0, 1, 0]));
+        assert_eq!(
+            split_by_weights(1, &[0, 0]),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            split_by_weights(1, &[u64::MAX, 1]),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let stake_mint = Pubkey::new_unique();
+        let legs: Vec<RouteLeg> = (0..MAX_ROUTE_LEGS as u64)
+            .map(|weight| RouteLeg {
+                stake_pool_pubkey: Pubkey::new_unique(),
+                stake_pool: Pool {
+                    stake_token_mint: stake_mint,
+                    reserved: Pubkey::new_unique(),
+                    ..Pool::default()
+                },
+                weight,
+                hook_accounts: vec![],
+            })
+            .collect();
+
+        let instructions = route_stake(
+            program_id, &legs, owner, owner, source, stake_mint, owner, 15,
+        )
+        .unwrap();
+        // the zero-weight leg is left out
+        assert_eq!(instructions.len(), 2 * (MAX_ROUTE_LEGS - 1));
+        let staked: u64 = instructions
+            .iter()
+            .filter_map(
+                |instruction| match InstructionType::unpack(&instruction.data) {
+                    Ok(InstructionType::Stake(StakeData { amount, .. })) => {
+                        let stake_pool = instruction.accounts[0].pubkey;
+                        let (stake_user, _) =
+                            find_stake_user_address(&program_id, &stake_pool, &owner);
+                        assert_eq!(instruction.accounts[1].pubkey, stake_user);
+                        Some(amount)
+                    }
+                    _ => None,
+                },
+            )
+            .sum();
+        assert_eq!(staked, 15);
+
+        let legs: Vec<RouteLeg> = legs
+            .into_iter()
+            .map(|leg| RouteLeg { weight: 1, ..leg })
+            .collect();
+        let instructions = route_stake(
+            program_id, &legs, owner, owner, source, stake_mint, owner, 100,
+        )
+        .unwrap();
+        // with the compute unit limit and price
+        let instructions = with_compute_budget(
+            &program_id,
+            instructions,
+            ComputeBudget {
+                unit_limit: None,
+                unit_price: Some(10_000),
+            },
+        );
+        let message = Message::new(&instructions, Some(&owner));
+        // signature count and payer signature
+        assert!(1 + 64 + message.serialize().len() <= PACKET_DATA_SIZE);
+
+        let mut legs = legs;
+        legs.push(legs[0].clone());
+        assert_eq!(
+            route_stake(program_id, &legs, owner, owner, source, stake_mint, owner, 100),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    /// Cluster confirming the transactions sent to it in the order sent,
+    /// one block a poll
+    struct MockRpc {
+        block_height: Cell<u64>,
+        /// Distinct transactions dropped before the others land
+        dropped: usize,
+        outcome: Result<(), TransactionError>,
+        /// Transactions sent, with resends
+        sent: RefCell<Vec<Transaction>>,
+        statuses: RefCell<HashMap<Signature, Result<(), TransactionError>>>,
+        nonce_account: RefCell<Vec<u8>>,
+        /// Block height another transaction advances the nonce at
+        nonce_advanced_at: Option<u64>,
+    }
+
+    impl MockRpc {
+        fn new(dropped: usize, outcome: Result<(), TransactionError>) -> Self {
+            Self {
+                block_height: Cell::new(0),
+                dropped,
+                outcome,
+                sent: RefCell::new(vec![]),
+                statuses: RefCell::new(HashMap::new()),
+                nonce_account: RefCell::new(vec![]),
+                nonce_advanced_at: None,
+            }
+        }
+
+        fn distinct_sent(&self) -> Vec<Signature> {
+            let mut signatures: Vec<Signature> = vec![];
+            for transaction in self.sent.borrow().iter() {
+                if !signatures.contains(&transaction.signatures[0]) {
+                    signatures.push(transaction.signatures[0]);
+                }
+            }
+            signatures
+        }
+
+        fn advance_nonce(&self) {
+            let (authority, _) = parse_nonce_account(&self.nonce_account.borrow()).unwrap();
+            *self.nonce_account.borrow_mut() = nonce_account_data(&authority, None);
+        }
+    }
+
+    impl TransactionRpc for MockRpc {
+        type Error = ();
+
+        async fn latest_blockhash(&self) -> Result<(Hash, u64), ()> {
+            Ok((Hash::new_unique(), self.block_height.get() + 5))
+        }
+
+        async fn block_height(&self) -> Result<u64, ()> {
+            Ok(self.block_height.get())
+        }
+
+        async fn account_data(&self, _pubkey: &Pubkey) -> Result<Option<Vec<u8>>, ()> {
+            Ok(Some(self.nonce_account.borrow().clone()))
+        }
+
+        async fn send_transaction(&self, transaction: &Transaction) -> Result<(), ()> {
+            let signature = transaction.signatures[0];
+            self.sent.borrow_mut().push(transaction.clone());
+            let position = self
+                .distinct_sent()
+                .iter()
+                .position(|sent| *sent == signature)
+                .unwrap();
+            if position < self.dropped || self.statuses.borrow().contains_key(&signature) {
+                return Ok(());
+            }
+            if transaction.message.recent_blockhash
+                == parse_nonce_account(&self.nonce_account.borrow())
+                    .map(|(_, nonce)| nonce)
+                    .unwrap_or_default()
+            {
+                self.advance_nonce();
+            }
+            self.statuses
+                .borrow_mut()
+                .insert(signature, self.outcome.clone());
+            Ok(())
+        }
+
+        async fn signature_status(
+            &self,
+            signature: &Signature,
+        ) -> Result<Option<Result<(), TransactionError>>, ()> {
+            Ok(self.statuses.borrow().get(signature).cloned())
+        }
+
+        async fn sleep(&self, _duration: Duration) {
+            self.block_height.set(self.block_height.get() + 1);
+            if self.nonce_advanced_at == Some(self.block_height.get()) {
+                self.advance_nonce();
+            }
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    /// Nonce account of an authority, with the durable nonce of a new
+    /// blockhash unless given
+    fn nonce_account_data(authority: &Pubkey, durable_nonce: Option<DurableNonce>) -> Vec<u8> {
+        let mut account = AccountSharedData::new(1, NONCE_ACCOUNT_LEN, &system_program::id());
+        let durable_nonce =
+            durable_nonce.unwrap_or_else(|| DurableNonce::from_blockhash(&Hash::new_unique()));
+        let data = Data::new(*authority, durable_nonce, 5_000);
+        account
+            .set_state(&Versions::new(State::Initialized(data)))
+            .unwrap();
+        account.data().to_vec()
+    }
+
+    #[test]
+    fn test_send_with_retries() {
+        let payer = Keypair::new();
+        let instructions = [system_instruction::transfer(
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            1,
+        )];
+        let policy = RetryPolicy::default();
+
+        // the first transaction expires unconfirmed, the second one lands
+        let rpc = MockRpc::new(1, Ok(()));
+        let signature = block_on(send_with_retries(
+            &rpc,
+            &instructions,
+            &payer.pubkey(),
+            &[&payer],
+            policy,
+        ))
+        .unwrap();
+        let sent = rpc.distinct_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(signature, sent[1]);
+        assert_eq!(rpc.statuses.borrow().len(), 1);
+        // the first one was resent until its blockhash expired
+        let first_sends = rpc
+            .sent
+            .borrow()
+            .iter()
+            .filter(|transaction| transaction.signatures[0] == sent[0])
+            .count();
+        assert_eq!(first_sends, 6);
+
+        let rpc = MockRpc::new(policy.max_attempts, Ok(()));
+        assert_eq!(
+            block_on(send_with_retries(
+                &rpc,
+                &instructions,
+                &payer.pubkey(),
+                &[&payer],
+                policy
+            )),
+            Err(SendError::Expired(policy.max_attempts))
+        );
+
+        let rpc = MockRpc::new(0, Err(TransactionError::InsufficientFundsForFee));
+        let result = block_on(send_with_retries(
+            &rpc,
+            &instructions,
+            &payer.pubkey(),
+            &[&payer],
+            policy,
+        ));
+        assert_eq!(
+            result,
+            Err(SendError::Failed(
+                rpc.distinct_sent()[0],
+                TransactionError::InsufficientFundsForFee
+            ))
+        );
+
+        // giving up before the blockhash expired
+        let rpc = MockRpc::new(1, Ok(()));
+        let result = block_on(send_with_retries(
+            &rpc,
+            &instructions,
+            &payer.pubkey(),
+            &[&payer],
+            RetryPolicy {
+                max_polls: 3,
+                ..policy
+            },
+        ));
+        assert_eq!(result, Err(SendError::Unconfirmed(rpc.distinct_sent()[0])));
+        assert_eq!(rpc.distinct_sent().len(), 1);
+
+        // a missing signer
+        assert!(matches!(
+            block_on(send_with_retries(
+                &rpc,
+                &instructions,
+                &payer.pubkey(),
+                &[&Keypair::new()],
+                policy
+            )),
+            Err(SendError::Signing(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_with_nonce() {
+        let payer = Keypair::new();
+        let nonce_account = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce = *durable_nonce.as_hash();
+        let data = nonce_account_data(&payer.pubkey(), Some(durable_nonce));
+        assert_eq!(data.len(), NONCE_ACCOUNT_LEN);
+        assert_eq!(parse_nonce_account(&data), Some((payer.pubkey(), nonce)));
+        assert_eq!(parse_nonce_account(&data[..NONCE_ACCOUNT_LEN - 1]), None);
+        let uninitialized = AccountSharedData::new(1, NONCE_ACCOUNT_LEN, &system_program::id());
+        assert_eq!(parse_nonce_account(uninitialized.data()), None);
+
+        let instructions = [system_instruction::transfer(
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            1,
+        )];
+        let message = durable_nonce_message(
+            &instructions,
+            &payer.pubkey(),
+            &nonce_account,
+            &payer.pubkey(),
+            nonce,
+        );
+        assert_eq!(message.recent_blockhash, nonce);
+        // advancing the nonce comes first
+        assert_eq!(message.instructions.len(), 2);
+        assert_eq!(
+            message.instructions[0].program_id(&message.account_keys),
+            &system_program::id()
+        );
+        let policy = RetryPolicy::default();
+        let send = |rpc: &MockRpc, policy| {
+            block_on(send_with_nonce(
+                rpc,
+                &instructions,
+                &payer.pubkey(),
+                &nonce_account,
+                &payer.pubkey(),
+                &[&payer],
+                policy,
+            ))
+        };
+
+        // landing advances the nonce
+        let rpc = MockRpc::new(0, Ok(()));
+        *rpc.nonce_account.borrow_mut() = data.clone();
+        let signature = send(&rpc, policy).unwrap();
+        assert_eq!(rpc.distinct_sent(), vec![signature]);
+        assert_ne!(
+            parse_nonce_account(&rpc.nonce_account.borrow()),
+            Some((payer.pubkey(), nonce))
+        );
+
+        // sending again after giving up signs the same transaction
+        let rpc = MockRpc::new(1, Ok(()));
+        *rpc.nonce_account.borrow_mut() = data.clone();
+        let unconfirmed = send(
+            &rpc,
+            RetryPolicy {
+                max_polls: 3,
+                ..policy
+            },
+        );
+        let signature = rpc.distinct_sent()[0];
+        assert_eq!(unconfirmed, Err(SendError::Unconfirmed(signature)));
+        assert_eq!(
+            send(
+                &rpc,
+                RetryPolicy {
+                    max_polls: 3,
+                    ..policy
+                }
+            ),
+            unconfirmed
+        );
+        assert_eq!(rpc.distinct_sent(), vec![signature]);
+
+        let rpc = MockRpc {
+            nonce_advanced_at: Some(2),
+            ..MockRpc::new(1, Ok(()))
+        };
+        *rpc.nonce_account.borrow_mut() = data.clone();
+        assert_eq!(send(&rpc, policy), Err(SendError::NonceAdvanced(signature)));
+
+        let rpc = MockRpc::new(0, Ok(()));
+        *rpc.nonce_account.borrow_mut() =
+            nonce_account_data(&Pubkey::new_unique(), Some(durable_nonce));
+        assert_eq!(send(&rpc, policy), Err(SendError::InvalidNonceAccount));
+    }
+
+    #[test]
+    fn test_simulation() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let stake_pool = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let claim = Instruction::new_with_bytes(
+            program_id,
+            &InstructionType::ClaimIfAny.pack(),
+            vec![AccountMeta::new(stake_pool, false)],
+        );
+        let message = Message::new(&[set_compute_unit_limit(100_000), claim], Some(&payer));
+        let event = Event::Deprecate {
+            pool: stake_pool,
+            successor: Pubkey::default(),
+        };
+        let event_log = format!("{}{}", EVENT_LOG_PREFIX, STANDARD.encode(event.encode()));
+        let other_event_log = format!(
+            "{}{}",
+            EVENT_LOG_PREFIX,
+            STANDARD.encode(
+                Event::Deprecate {
+                    pool: Pubkey::new_unique(),
+                    successor: Pubkey::default(),
+                }
+                .encode()
+            )
+        );
+        let logs = vec![
+            format!("Program {} invoke [1]", COMPUTE_BUDGET_PROGRAM_ID),
+            format!("Program {} success", COMPUTE_BUDGET_PROGRAM_ID),
+            format!("Program {} invoke [1]", program_id),
+            format!("Program {} invoke [2]", other_program),
+            other_event_log.clone(),
+            format!("Program {} success", other_program),
+            event_log,
+            format!(
+                "Program {} consumed 12345 of 99850 compute units",
+                program_id
+            ),
+            format!("Program {} success", program_id),
+            other_event_log,
+        ];
+        let result = |err: Value, return_program: &Pubkey, return_data: &[u8]| {
+            serde_json::from_value::<RpcSimulateTransactionResult>(json!({
+                "err": err,
+                "logs": logs,
+                "accounts": null,
+                "unitsConsumed": 12_495,
+                "returnData": {
+                    "programId": return_program.to_string(),
+                    "data": [STANDARD.encode(return_data), "base64"],
+                },
+            }))
+            .unwrap()
+        };
+
+        let simulation = Simulation::decode(
+            &program_id,
+            &message,
+            result(Value::Null, &program_id, &500u64.to_le_bytes()),
+        );
+        assert_eq!(simulation.error, None);
+        assert_eq!(simulation.return_value, Some(ReturnValue::Amount(500)));
+        assert_eq!(simulation.claimed_amount(), Some(500));
+        // only the event of the program itself
+        assert_eq!(simulation.events, vec![event]);
+        assert_eq!(simulation.units_consumed, Some(12_495));
+        assert_eq!(simulation.logs.len(), 10);
+
+        let simulation = Simulation::decode(
+            &program_id,
+            &message,
+            result(
+                json!({"InstructionError": [1, {"Custom": CustomError::InsufficientClaimAmount as u32}]}),
+                &program_id,
+                &[],
+            ),
+        );
+        assert_eq!(
+            simulation.error,
+            Some(SimulationError::Program(
+                1,
+                CustomError::InsufficientClaimAmount
+            ))
+        );
+        assert_eq!(
+            simulation.return_value,
+            Some(ReturnValue::Raw(program_id, vec![]))
+        );
+        assert_eq!(simulation.claimed_amount(), None);
+
+        // errors and return data of other programs are left as they are
+        let simulation = Simulation::decode(
+            &program_id,
+            &message,
+            result(
+                json!({"InstructionError": [0, {"Custom": 1}]}),
+                &other_program,
+                &[1, 2],
+            ),
+        );
+        assert_eq!(
+            simulation.error,
+            Some(SimulationError::Transaction(
+                TransactionError::InstructionError(0, InstructionError::Custom(1))
+            ))
+        );
+        assert_eq!(
+            simulation.return_value,
+            Some(ReturnValue::Raw(other_program, vec![1, 2]))
+        );
+
+        let refresh = refresh(program_id, stake_pool, vec![Pubkey::new_unique()]).unwrap();
+        let counts = RefreshCounts {
+            updated: 1,
+            skipped: 0,
+        };
+        let return_data = borsh::to_vec(&counts).unwrap();
+        let simulation = block_on(simulate(
+            |transaction: Transaction| async move {
+                assert_eq!(transaction.message.instructions.len(), 1);
+                Ok::<_, ()>(result(Value::Null, &program_id, &return_data))
+            },
+            &program_id,
+            Transaction::new_unsigned(Message::new(&[refresh], Some(&payer))),
+        ))
+        .unwrap();
+        assert_eq!(
+            simulation.return_value,
+            Some(ReturnValue::RefreshCounts(counts))
+        );
+    }
+}
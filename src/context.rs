@@ -0,0 +1,1253 @@
+//! Typed account contexts, parsing and validating the accounts of an
+//! instruction in one place so handlers only deal with checked accounts
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use spl_token::state::Account;
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{
+    error::CustomError,
+    metadata::NftMetadata,
+    pda::{
+        find_associated_token_address, find_boost_escrow_authority_address, find_metadata_address,
+        TOKEN_METADATA_PROGRAM_ID,
+    },
+    processor::{
+        assert_admin, assert_field, assert_key, assert_not_frozen, assert_owner, assert_role,
+        assert_signer, reject_account, unpack_mint, unpack_token_account,
+    },
+    state::{
+        ClaimSplit, Pool, RewardMode, Role, StakeUser, BOOST_ESCROW_SEED, POOL_FLAG_NFT_BOOST,
+    },
+};
+
+/// Pool and stake user accounts, checked to be owned by the program and to
+/// belong together
+fn load_pool_and_user(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stake_pool_info: &AccountInfo,
+    stake_user_info: &AccountInfo,
+) -> Result<(Pool, StakeUser), ProgramError> {
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+
+    let stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+
+    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    Ok((stake_pool, stake_user))
+}
+
+/// Pool and stake user accounts, checked to belong together and to be signed
+/// for by the stake user owner
+fn load_stake_user(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stake_pool_info: &AccountInfo,
+    stake_user_info: &AccountInfo,
+    stake_owner_info: &AccountInfo,
+) -> Result<(Pool, StakeUser), ProgramError> {
+    let (stake_pool, stake_user) =
+        load_pool_and_user(program_id, accounts, stake_pool_info, stake_user_info)?;
+    assert_key(
+        accounts,
+        stake_owner_info,
+        &stake_user.owner,
+        CustomError::InvalidStakeOwner,
+    )?;
+    assert_signer(accounts, stake_owner_info)?;
+    Ok((stake_pool, stake_user))
+}
+
+/// Pool authority checked against the bump seed of the pool
+fn assert_pool_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stake_pool_info: &AccountInfo,
+    stake_pool: &Pool,
+    stake_pool_authority_info: &AccountInfo,
+) -> Result<(), ProgramError> {
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &stake_pool.authority_address(program_id, stake_pool_info.key)?,
+        CustomError::InvalidPoolAuthority,
+    )
+}
+
+/// Token account of a stake mint of the pool, not frozen
+fn load_stake_token_account(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Result<Account, ProgramError> {
+    let token_account = unpack_token_account(account_info, token_program_id)?;
+    assert_field(
+        accounts,
+        account_info,
+        "mint",
+        mint,
+        &token_account.mint,
+        CustomError::InvalidTokenMint,
+    )?;
+    assert_not_frozen(accounts, account_info, &token_account)?;
+    Ok(token_account)
+}
+
+/// Stake mint held by a reserve of the pool, with its slot among the
+/// additional stake mints, `None` for the primary reserve
+fn load_reserve_mint(
+    accounts: &[AccountInfo],
+    reserve_info: &AccountInfo,
+    stake_pool: &Pool,
+) -> Result<(Option<usize>, Pubkey), ProgramError> {
+    if let Some(index) = stake_pool.stake_mint_index(reserve_info.key) {
+        return Ok((Some(index), stake_pool.stake_mints[index].mint));
+    }
+    assert_key(
+        accounts,
+        reserve_info,
+        &stake_pool.reserved,
+        CustomError::InvalidTokenAccount,
+    )?;
+    Ok((None, stake_pool.stake_token_mint))
+}
+
+/// Reward accounts a claim pays out through
+pub struct RewardAccounts<'a, 'b> {
+    pub stake_pool_authority_info: &'a AccountInfo<'b>,
+    /// Reward token mint, or reward token vault for vault-funded pools
+    pub reward_source_info: &'a AccountInfo<'b>,
+    pub reward_token_info: &'a AccountInfo<'b>,
+    /// Price account of oracle-priced pools, preceding the burn mint
+    pub price_oracle_info: Option<&'a AccountInfo<'b>>,
+    /// Reward token mint, vault-funded pools burning on claim
+    pub burn_mint_info: Option<&'a AccountInfo<'b>>,
+}
+
+impl<'a, 'b> RewardAccounts<'a, 'b> {
+    /// Trailing reward accounts of `Stake` and `Unstake` with auto-claim, in
+    /// the order pool authority, reward source, reward token, price oracle and
+    /// burn mint
+    fn load_trailing(
+        program_id: &Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+        account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+        stake_pool_info: &AccountInfo,
+        stake_pool: &Pool,
+        token_program_info: &AccountInfo,
+    ) -> Result<Self, ProgramError> {
+        let rewards = Self {
+            stake_pool_authority_info: next_account_info(account_info_iter)?,
+            reward_source_info: next_account_info(account_info_iter)?,
+            reward_token_info: next_account_info(account_info_iter)?,
+            price_oracle_info: next_price_oracle(account_info_iter, stake_pool)?,
+            burn_mint_info: next_account_info(account_info_iter).ok(),
+        };
+        rewards.check(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_pool,
+            token_program_info,
+        )?;
+        Ok(rewards)
+    }
+
+    fn check(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        stake_pool_info: &AccountInfo,
+        stake_pool: &Pool,
+        token_program_info: &AccountInfo,
+    ) -> Result<(), ProgramError> {
+        check_reward_token(
+            accounts,
+            self.reward_token_info,
+            stake_pool,
+            self.stake_pool_authority_info,
+            token_program_info,
+        )?;
+        match stake_pool.reward_mode {
+            RewardMode::MintToUser => assert_key(
+                accounts,
+                self.reward_source_info,
+                &stake_pool.reward_mint,
+                CustomError::InvalidTokenMint,
+            )?,
+            RewardMode::TransferFromVault => assert_key(
+                accounts,
+                self.reward_source_info,
+                &stake_pool.reward_vault,
+                CustomError::InvalidTokenAccount,
+            )?,
+        }
+        assert_pool_authority(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_pool,
+            self.stake_pool_authority_info,
+        )?;
+        if let Some(price_oracle_info) = self.price_oracle_info {
+            assert_key(
+                accounts,
+                price_oracle_info,
+                &stake_pool.price_oracle,
+                CustomError::InvalidPriceOracle,
+            )?;
+        }
+        if let Some(burn_mint_info) = self.burn_mint_info {
+            assert_key(
+                accounts,
+                burn_mint_info,
+                &stake_pool.reward_mint,
+                CustomError::InvalidTokenMint,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Token account of the reward mint a claim pays out into, which must not be
+/// frozen
+fn check_reward_token(
+    accounts: &[AccountInfo],
+    reward_token_info: &AccountInfo,
+    stake_pool: &Pool,
+    stake_pool_authority_info: &AccountInfo,
+    token_program_info: &AccountInfo,
+) -> Result<(), ProgramError> {
+    let reward_token = unpack_token_account(reward_token_info, token_program_info.key)?;
+    assert_not_frozen(accounts, reward_token_info, &reward_token)?;
+    if reward_token_info.owner == stake_pool_authority_info.key {
+        return Err(reject_account(
+            accounts,
+            reward_token_info,
+            "owned by the pool authority",
+            CustomError::InvalidAccountOwner,
+        ));
+    }
+    assert_field(
+        accounts,
+        reward_token_info,
+        "mint",
+        &stake_pool.reward_mint,
+        &reward_token.mint,
+        CustomError::InvalidTokenMint,
+    )
+}
+
+/// Price account of an oracle-priced pool, the next account
+fn next_price_oracle<'a, 'b>(
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    stake_pool: &Pool,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    if stake_pool.is_oracle_priced() {
+        next_account_info(account_info_iter).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Accounts of `Stake` and `StakeWithDelegate`
+pub struct StakeAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    pub user_transfer_authority_info: &'a AccountInfo<'b>,
+    /// `None` when staking on the delegate approval alone
+    pub stake_owner_info: Option<&'a AccountInfo<'b>>,
+    pub source_info: &'a AccountInfo<'b>,
+    pub reserve_info: &'a AccountInfo<'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+    pub source_token: Account,
+    /// Slot of the additional stake mint staked, `None` for the primary one
+    pub mint_index: Option<usize>,
+    /// Set when auto-claiming
+    pub rewards: Option<RewardAccounts<'a, 'b>>,
+}
+
+impl<'a, 'b> StakeAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+        auto_claim: bool,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let reserve_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+        let (source_token, mint_index) = load_stake_transfer_accounts(
+            accounts,
+            &stake_pool,
+            source_info,
+            reserve_info,
+            token_program_info,
+        )?;
+        let rewards = if auto_claim {
+            Some(RewardAccounts::load_trailing(
+                program_id,
+                accounts,
+                account_info_iter,
+                stake_pool_info,
+                &stake_pool,
+                token_program_info,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            user_transfer_authority_info,
+            stake_owner_info: Some(stake_owner_info),
+            source_info,
+            reserve_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+            source_token,
+            mint_index,
+            rewards,
+        })
+    }
+
+    /// Accounts of `StakeWithDelegate`, where the owner doesn't sign. The
+    /// source must belong to the stake user owner and be approved to the
+    /// signing delegate, so tokens only ever move into the owner's position.
+    pub fn load_delegated(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let delegate_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let reserve_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) =
+            load_pool_and_user(program_id, accounts, stake_pool_info, stake_user_info)?;
+        assert_signer(accounts, delegate_info)?;
+        let (source_token, mint_index) = load_stake_transfer_accounts(
+            accounts,
+            &stake_pool,
+            source_info,
+            reserve_info,
+            token_program_info,
+        )?;
+        assert_field(
+            accounts,
+            source_info,
+            "owner",
+            &stake_user.owner,
+            &source_token.owner,
+            CustomError::InvalidStakeOwner,
+        )?;
+        if source_token.delegate != COption::Some(*delegate_info.key) {
+            return Err(reject_account(
+                accounts,
+                source_info,
+                "not approved to the delegate",
+                CustomError::InvalidDelegate,
+            ));
+        }
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            user_transfer_authority_info: delegate_info,
+            stake_owner_info: None,
+            source_info,
+            reserve_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+            source_token,
+            mint_index,
+            rewards: None,
+        })
+    }
+}
+
+/// Source and reserve token accounts of a stake, returning the source and
+/// the slot of the stake mint
+fn load_stake_transfer_accounts(
+    accounts: &[AccountInfo],
+    stake_pool: &Pool,
+    source_info: &AccountInfo,
+    reserve_info: &AccountInfo,
+    token_program_info: &AccountInfo,
+) -> Result<(Account, Option<usize>), ProgramError> {
+    let (mint_index, mint) = load_reserve_mint(accounts, reserve_info, stake_pool)?;
+    let source_token =
+        load_stake_token_account(accounts, source_info, &mint, token_program_info.key)?;
+    load_stake_token_account(accounts, reserve_info, &mint, token_program_info.key)?;
+    Ok((source_token, mint_index))
+}
+
+/// Accounts of `Unstake`
+pub struct UnstakeAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    pub stake_pool_authority_info: &'a AccountInfo<'b>,
+    pub stake_owner_info: &'a AccountInfo<'b>,
+    pub reserve_info: &'a AccountInfo<'b>,
+    pub destination_info: &'a AccountInfo<'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+    pub reserve_token: Account,
+    /// Slot of the additional stake mint unstaked, `None` for the primary one
+    pub mint_index: Option<usize>,
+    /// Set when auto-claiming
+    pub rewards: Option<RewardAccounts<'a, 'b>>,
+}
+
+impl<'a, 'b> UnstakeAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+        auto_claim: bool,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let stake_pool_authority_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+        let reserve_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+        assert_pool_authority(
+            program_id,
+            accounts,
+            stake_pool_info,
+            &stake_pool,
+            stake_pool_authority_info,
+        )?;
+        let (mint_index, mint) = load_reserve_mint(accounts, reserve_info, &stake_pool)?;
+        let reserve_token =
+            load_stake_token_account(accounts, reserve_info, &mint, token_program_info.key)?;
+        load_stake_token_account(accounts, destination_info, &mint, token_program_info.key)?;
+        let rewards = if auto_claim {
+            Some(RewardAccounts::load_trailing(
+                program_id,
+                accounts,
+                account_info_iter,
+                stake_pool_info,
+                &stake_pool,
+                token_program_info,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            stake_pool_authority_info,
+            stake_owner_info,
+            reserve_info,
+            destination_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+            reserve_token,
+            mint_index,
+            rewards,
+        })
+    }
+
+    pub fn authority_signer_seeds(&self) -> [&[u8]; 2] {
+        Pool::authority_seeds(self.stake_pool_info.key, &self.stake_pool.bump_seed)
+    }
+}
+
+/// Paired token accounts of `StakePair` and `UnstakePair`, following the
+/// accounts of `Stake` and `Unstake` respectively
+pub struct PairedAccounts<'a, 'b> {
+    /// Paired token user account, source of stakes and destination of unstakes
+    pub user_info: &'a AccountInfo<'b>,
+    pub reserve_info: &'a AccountInfo<'b>,
+    pub user_token: Account,
+    pub reserve_token: Account,
+}
+
+impl<'a, 'b> PairedAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        stake_pool: &Pool,
+        token_program_info: &AccountInfo,
+        unstaking: bool,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter().skip(8);
+        let first_info = next_account_info(account_info_iter)?;
+        let second_info = next_account_info(account_info_iter)?;
+        let (user_info, reserve_info) = if unstaking {
+            (second_info, first_info)
+        } else {
+            (first_info, second_info)
+        };
+
+        if !stake_pool.is_pair_pool() {
+            return Err(CustomError::PairedStakeRequired.into());
+        }
+        let paired_mint = &stake_pool.stake_mints[0];
+        assert_key(
+            accounts,
+            reserve_info,
+            &paired_mint.reserve,
+            CustomError::InvalidTokenAccount,
+        )?;
+        let user_token = load_stake_token_account(
+            accounts,
+            user_info,
+            &paired_mint.mint,
+            token_program_info.key,
+        )?;
+        let reserve_token = load_stake_token_account(
+            accounts,
+            reserve_info,
+            &paired_mint.mint,
+            token_program_info.key,
+        )?;
+
+        Ok(Self {
+            user_info,
+            reserve_info,
+            user_token,
+            reserve_token,
+        })
+    }
+}
+
+/// Escrow of the boost NFT of a stake user, the associated token account of
+/// its boost escrow authority
+fn assert_boost_escrow(
+    accounts: &[AccountInfo],
+    escrow_info: &AccountInfo,
+    escrow_authority: &Pubkey,
+    nft_mint: &Pubkey,
+    token_program_info: &AccountInfo,
+) -> Result<Account, ProgramError> {
+    let (escrow_pubkey, _) =
+        find_associated_token_address(escrow_authority, nft_mint, token_program_info.key);
+    assert_key(
+        accounts,
+        escrow_info,
+        &escrow_pubkey,
+        CustomError::InvalidTokenAccount,
+    )?;
+    unpack_token_account(escrow_info, token_program_info.key)
+}
+
+/// Accounts of `RegisterBoost`
+pub struct RegisterBoostAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    pub stake_owner_info: &'a AccountInfo<'b>,
+    pub nft_mint_info: &'a AccountInfo<'b>,
+    pub source_info: &'a AccountInfo<'b>,
+    pub escrow_info: &'a AccountInfo<'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+}
+
+impl<'a, 'b> RegisterBoostAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+        let nft_mint_info = next_account_info(account_info_iter)?;
+        let metadata_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+
+        let nft_mint = unpack_mint(nft_mint_info, token_program_info.key)?;
+        if nft_mint.decimals != 0 || nft_mint.supply != 1 {
+            return Err(reject_account(
+                accounts,
+                nft_mint_info,
+                "not an NFT mint",
+                CustomError::InvalidTokenMint,
+            ));
+        }
+        assert_owner(accounts, metadata_info, &TOKEN_METADATA_PROGRAM_ID)?;
+        assert_key(
+            accounts,
+            metadata_info,
+            &find_metadata_address(nft_mint_info.key).0,
+            CustomError::InvalidCollection,
+        )?;
+        let metadata = NftMetadata::unpack(&metadata_info.data.borrow())?;
+        if !stake_pool.has_flags(POOL_FLAG_NFT_BOOST)
+            || !metadata.is_verified_in(&stake_pool.boost_collection)
+        {
+            return Err(reject_account(
+                accounts,
+                metadata_info,
+                "not verified in the boost collection",
+                CustomError::InvalidCollection,
+            ));
+        }
+
+        load_stake_token_account(
+            accounts,
+            source_info,
+            nft_mint_info.key,
+            token_program_info.key,
+        )?;
+        let (escrow_authority, _) =
+            find_boost_escrow_authority_address(program_id, stake_user_info.key);
+        assert_boost_escrow(
+            accounts,
+            escrow_info,
+            &escrow_authority,
+            nft_mint_info.key,
+            token_program_info,
+        )?;
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+            nft_mint_info,
+            source_info,
+            escrow_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+        })
+    }
+}
+
+/// Accounts of `UnregisterBoost`
+pub struct UnregisterBoostAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    pub escrow_authority_info: &'a AccountInfo<'b>,
+    pub escrow_info: &'a AccountInfo<'b>,
+    pub destination_info: &'a AccountInfo<'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+    pub escrow_authority_bump_seed: u8,
+}
+
+impl<'a, 'b> UnregisterBoostAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let escrow_authority_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+        let (escrow_authority, escrow_authority_bump_seed) =
+            find_boost_escrow_authority_address(program_id, stake_user_info.key);
+        assert_key(
+            accounts,
+            escrow_authority_info,
+            &escrow_authority,
+            CustomError::InvalidBoost,
+        )?;
+        if !stake_user.has_boost() {
+            return Err(CustomError::InvalidBoost.into());
+        }
+        assert_boost_escrow(
+            accounts,
+            escrow_info,
+            escrow_authority_info.key,
+            &stake_user.boost_mint,
+            token_program_info,
+        )?;
+        load_stake_token_account(
+            accounts,
+            destination_info,
+            &stake_user.boost_mint,
+            token_program_info.key,
+        )?;
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            escrow_authority_info,
+            escrow_info,
+            destination_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+            escrow_authority_bump_seed,
+        })
+    }
+
+    pub fn escrow_authority_signer_seeds(&self) -> [&[u8]; 3] {
+        [
+            self.stake_user_info.key.as_ref(),
+            BOOST_ESCROW_SEED,
+            std::slice::from_ref(&self.escrow_authority_bump_seed),
+        ]
+    }
+}
+
+/// Accounts of `MintBadge`
+pub struct MintBadgeAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    pub stake_pool_authority_info: &'a AccountInfo<'b>,
+    pub badge_mint_info: &'a AccountInfo<'b>,
+    pub badge_token_info: &'a AccountInfo<'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+}
+
+impl<'a, 'b> MintBadgeAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let stake_pool_authority_info = next_account_info(account_info_iter)?;
+        let badge_mint_info = next_account_info(account_info_iter)?;
+        let badge_token_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let (stake_pool, stake_user) =
+            load_pool_and_user(program_id, accounts, stake_pool_info, stake_user_info)?;
+        assert_pool_authority(
+            program_id,
+            accounts,
+            stake_pool_info,
+            &stake_pool,
+            stake_pool_authority_info,
+        )?;
+        if stake_pool.badge_mint == Pubkey::default() {
+            return Err(CustomError::InvalidBadgeMint.into());
+        }
+        assert_key(
+            accounts,
+            badge_mint_info,
+            &stake_pool.badge_mint,
+            CustomError::InvalidBadgeMint,
+        )?;
+        assert_key(
+            accounts,
+            token_program_info,
+            &spl_token_2022::id(),
+            CustomError::InvalidBadgeMint,
+        )?;
+        assert_owner(accounts, badge_token_info, token_program_info.key)?;
+        let badge_token_data = badge_token_info.data.borrow();
+        let badge_token =
+            StateWithExtensions::<spl_token_2022::state::Account>::unpack(&badge_token_data)
+                .map_err(|_| CustomError::InvalidTokenAccount)?
+                .base;
+        assert_field(
+            accounts,
+            badge_token_info,
+            "mint",
+            &stake_pool.badge_mint,
+            &badge_token.mint,
+            CustomError::InvalidTokenMint,
+        )?;
+        assert_field(
+            accounts,
+            badge_token_info,
+            "owner",
+            &stake_user.owner,
+            &badge_token.owner,
+            CustomError::InvalidStakeOwner,
+        )?;
+        drop(badge_token_data);
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            stake_pool_authority_info,
+            badge_mint_info,
+            badge_token_info,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+        })
+    }
+
+    pub fn authority_signer_seeds(&self) -> [&[u8]; 2] {
+        Pool::authority_seeds(self.stake_pool_info.key, &self.stake_pool.bump_seed)
+    }
+}
+
+/// Accounts of `Claim`, `ClaimIfAny` and `ClaimFor`
+pub struct ClaimAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub stake_user_info: &'a AccountInfo<'b>,
+    /// `None` when claiming for the owner without their signature
+    pub stake_owner_info: Option<&'a AccountInfo<'b>>,
+    pub rewards: RewardAccounts<'a, 'b>,
+    pub clock: Clock,
+    pub token_program_info: &'a AccountInfo<'b>,
+    pub stake_pool: Pool,
+    pub stake_user: StakeUser,
+    /// Claim split paying shares of the claim, when given
+    pub claim_split: Option<ClaimSplitAccounts<'a, 'b>>,
+}
+
+impl<'a, 'b> ClaimAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let (accounts, claim_split_accounts) = split_claim_split_accounts(program_id, accounts);
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+
+        let pool_and_user = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+        Self::load_rest(
+            program_id,
+            accounts,
+            account_info_iter,
+            stake_pool_info,
+            stake_user_info,
+            Some(stake_owner_info),
+            pool_and_user,
+            claim_split_accounts,
+        )
+    }
+
+    /// Accounts of `ClaimFor`, where the owner doesn't sign and the reward
+    /// token account must be the associated token account of the owner
+    pub fn load_for_owner(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let (accounts, claim_split_accounts) = split_claim_split_accounts(program_id, accounts);
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+
+        let pool_and_user =
+            load_pool_and_user(program_id, accounts, stake_pool_info, stake_user_info)?;
+        let ctx = Self::load_rest(
+            program_id,
+            accounts,
+            account_info_iter,
+            stake_pool_info,
+            stake_user_info,
+            None,
+            pool_and_user,
+            claim_split_accounts,
+        )?;
+
+        let (associated_token_pubkey, _) = find_associated_token_address(
+            &ctx.stake_user.owner,
+            &ctx.stake_pool.reward_mint,
+            ctx.token_program_info.key,
+        );
+        assert_key(
+            accounts,
+            ctx.rewards.reward_token_info,
+            &associated_token_pubkey,
+            CustomError::InvalidTokenAccount,
+        )?;
+        let reward_token =
+            unpack_token_account(ctx.rewards.reward_token_info, ctx.token_program_info.key)?;
+        assert_field(
+            accounts,
+            ctx.rewards.reward_token_info,
+            "owner",
+            &ctx.stake_user.owner,
+            &reward_token.owner,
+            CustomError::InvalidStakeOwner,
+        )?;
+        Ok(ctx)
+    }
+
+    /// Accounts following the stake owner, shared by both layouts, and the
+    /// claim split split off them, if any
+    #[allow(clippy::too_many_arguments)]
+    fn load_rest(
+        program_id: &Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+        account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+        stake_pool_info: &'a AccountInfo<'b>,
+        stake_user_info: &'a AccountInfo<'b>,
+        stake_owner_info: Option<&'a AccountInfo<'b>>,
+        (stake_pool, stake_user): (Pool, StakeUser),
+        claim_split_accounts: &'a [AccountInfo<'b>],
+    ) -> Result<Self, ProgramError> {
+        let stake_pool_authority_info = next_account_info(account_info_iter)?;
+        let reward_source_info = next_account_info(account_info_iter)?;
+        let reward_token_info = next_account_info(account_info_iter)?;
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let rewards = RewardAccounts {
+            stake_pool_authority_info,
+            reward_source_info,
+            reward_token_info,
+            price_oracle_info: next_price_oracle(account_info_iter, &stake_pool)?,
+            burn_mint_info: next_account_info(account_info_iter).ok(),
+        };
+        rewards.check(
+            program_id,
+            accounts,
+            stake_pool_info,
+            &stake_pool,
+            token_program_info,
+        )?;
+        // the shares of a claim split are owed on every claim
+        if stake_user.claim_split && claim_split_accounts.is_empty() {
+            return Err(reject_account(
+                accounts,
+                stake_user_info,
+                "has a claim split the claim doesn't take",
+                CustomError::InvalidClaimSplit,
+            ));
+        }
+        let claim_split = ClaimSplitAccounts::load(
+            claim_split_accounts,
+            stake_user_info,
+            &stake_pool,
+            stake_pool_authority_info,
+            token_program_info,
+        )?;
+
+        Ok(Self {
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+            rewards,
+            clock,
+            token_program_info,
+            stake_pool,
+            stake_user,
+            claim_split,
+        })
+    }
+}
+
+/// Claim split of a stake user and the token accounts of its shares in
+/// order, trailing the accounts of a claim
+pub struct ClaimSplitAccounts<'a, 'b> {
+    pub destination_infos: &'a [AccountInfo<'b>],
+    pub claim_split: ClaimSplit,
+}
+
+impl<'a, 'b> ClaimSplitAccounts<'a, 'b> {
+    /// Claim split split off a claim, `None` when not given
+    fn load(
+        accounts: &'a [AccountInfo<'b>],
+        stake_user_info: &AccountInfo,
+        stake_pool: &Pool,
+        stake_pool_authority_info: &AccountInfo,
+        token_program_info: &AccountInfo,
+    ) -> Result<Option<Self>, ProgramError> {
+        let (claim_split_info, destination_infos) = match accounts.split_first() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let claim_split = ClaimSplit::unpack(&claim_split_info.data.borrow())?;
+        if claim_split.stake_user_pubkey != *stake_user_info.key {
+            return Err(reject_account(
+                accounts,
+                claim_split_info,
+                "not the claim split of the stake user",
+                CustomError::InvalidClaimSplit,
+            ));
+        }
+        if destination_infos.len() != claim_split.used_shares().count() {
+            return Err(reject_account(
+                accounts,
+                claim_split_info,
+                "not followed by the destinations of its shares",
+                CustomError::InvalidClaimSplit,
+            ));
+        }
+        for (share, destination_info) in claim_split.used_shares().zip(destination_infos) {
+            assert_key(
+                accounts,
+                destination_info,
+                &share.destination,
+                CustomError::InvalidClaimSplit,
+            )?;
+            check_reward_token(
+                accounts,
+                destination_info,
+                stake_pool,
+                stake_pool_authority_info,
+                token_program_info,
+            )?;
+        }
+
+        Ok(Some(Self {
+            destination_infos,
+            claim_split,
+        }))
+    }
+}
+
+/// Split the accounts of a claim into its own accounts and the claim split
+/// with the token accounts of its shares. The claim split is the first
+/// account after the stake user the program owns with its size, which no
+/// other account of a claim has.
+fn split_claim_split_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> (&'a [AccountInfo<'b>], &'a [AccountInfo<'b>]) {
+    let position = accounts.iter().skip(2).position(|account_info| {
+        account_info.owner == program_id && account_info.data_len() == ClaimSplit::LEN
+    });
+    match position {
+        Some(position) => accounts.split_at(position + 2),
+        None => (accounts, &[]),
+    }
+}
+
+/// Accounts of `ClaimAndStakeInto`, the claim paying out into the reserve of
+/// the target pool, which can't have a hook program
+pub struct ClaimAndStakeAccounts<'a, 'b> {
+    pub claim: ClaimAccounts<'a, 'b>,
+    pub target_pool_info: &'a AccountInfo<'b>,
+    pub target_stake_user_info: &'a AccountInfo<'b>,
+    pub target_pool: Pool,
+    pub target_stake_user: StakeUser,
+}
+
+impl<'a, 'b> ClaimAndStakeAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let stake_user_info = next_account_info(account_info_iter)?;
+        let stake_owner_info = next_account_info(account_info_iter)?;
+        let target_pool_info = next_account_info(account_info_iter)?;
+        let target_stake_user_info = next_account_info(account_info_iter)?;
+
+        if target_pool_info.key == stake_pool_info.key {
+            return Err(reject_account(
+                accounts,
+                target_pool_info,
+                "same pool as the claim",
+                CustomError::InvalidTargetPool,
+            ));
+        }
+        let pool_and_user = load_stake_user(
+            program_id,
+            accounts,
+            stake_pool_info,
+            stake_user_info,
+            stake_owner_info,
+        )?;
+        let (target_pool, target_stake_user) = load_stake_user(
+            program_id,
+            accounts,
+            target_pool_info,
+            target_stake_user_info,
+            stake_owner_info,
+        )?;
+        let claim = ClaimAccounts::load_rest(
+            program_id,
+            accounts,
+            account_info_iter,
+            stake_pool_info,
+            stake_user_info,
+            Some(stake_owner_info),
+            pool_and_user,
+            &[],
+        )?;
+        assert_field(
+            accounts,
+            target_pool_info,
+            "stake token mint",
+            &claim.stake_pool.reward_mint,
+            &target_pool.stake_token_mint,
+            CustomError::InvalidTokenMint,
+        )?;
+        if claim.stake_pool.is_streamed() {
+            return Err(reject_account(
+                accounts,
+                stake_pool_info,
+                "claims are streamed",
+                CustomError::RewardsStreamed,
+            ));
+        }
+        // the hook accounts trailing the instruction belong to the source pool
+        if target_pool.hook_program != Pubkey::default() {
+            return Err(reject_account(
+                accounts,
+                target_pool_info,
+                "target pool has a hook program",
+                CustomError::InvalidTargetPool,
+            ));
+        }
+        if target_pool.is_pair_pool() {
+            return Err(reject_account(
+                accounts,
+                target_pool_info,
+                "target pool is a pair pool",
+                CustomError::PairedStakeRequired,
+            ));
+        }
+        assert_key(
+            accounts,
+            claim.rewards.reward_token_info,
+            &target_pool.reserved,
+            CustomError::InvalidTokenAccount,
+        )?;
+
+        Ok(Self {
+            claim,
+            target_pool_info,
+            target_stake_user_info,
+            target_pool,
+            target_stake_user,
+        })
+    }
+}
+
+/// Leading accounts of pool admin instructions, followed by the
+/// instruction-specific `remaining` accounts
+pub struct AdminAccounts<'a, 'b> {
+    pub stake_pool_info: &'a AccountInfo<'b>,
+    pub admin_info: &'a AccountInfo<'b>,
+    pub remaining: &'a [AccountInfo<'b>],
+    pub stake_pool: Pool,
+}
+
+impl<'a, 'b> AdminAccounts<'a, 'b> {
+    pub fn load(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        Self::load_with(accounts, program_id, None)
+    }
+
+    /// Load the accounts of an instruction the admin or the holder of a role
+    /// can sign
+    pub fn load_role(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+        role: Role,
+    ) -> Result<Self, ProgramError> {
+        Self::load_with(accounts, program_id, Some(role))
+    }
+
+    fn load_with(
+        accounts: &'a [AccountInfo<'b>],
+        program_id: &Pubkey,
+        role: Option<Role>,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let stake_pool_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+
+        assert_owner(accounts, stake_pool_info, program_id)?;
+        let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+        match role {
+            Some(role) => assert_role(accounts, &stake_pool, role, admin_info)?,
+            None => assert_admin(accounts, &stake_pool, admin_info)?,
+        }
+
+        Ok(Self {
+            stake_pool_info,
+            admin_info,
+            remaining: account_info_iter.as_slice(),
+            stake_pool,
+        })
+    }
+}
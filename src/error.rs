@@ -6,28 +6,29 @@ use solana_program::{
 };
 use thiserror::Error;
 
+use crate::math::MathError;
+
+/// Program errors, numbered in stable ranges per category. New variants go
+/// at the end of their category so existing codes never shift.
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum CustomError {
+    // Instruction errors, 0..100
     #[error("Incorrect program instruction")]
-    IncorrectInstruction,
+    IncorrectInstruction = 0,
     #[error("Instruction unpack is failed")]
     InstructionUnpackError,
+
+    // Arithmetic errors, 100..200
     #[error("CalculationFailure")]
-    CalculationFailure,
-    #[error("Insufficient liquidity available")]
-    InsufficientLiquidity,
-    #[error("Insufficient claim amount")]
-    InsufficientClaimAmount,
+    CalculationFailure = 100,
+
+    // Account validation errors, 200..300
     #[error("Lamport balance below rent-exempt threshold")]
-    NotRentExempt,
+    NotRentExempt = 200,
     #[error("Stake pool is already initialized")]
     AlreadyInUse,
     #[error("Input account owner is not the program address")]
     InvalidAccountOwner,
-    #[error("Token initialize account failed")]
-    TokenInitializeAccountFailed,
-    #[error("Token initialize mint failed")]
-    TokenInitializeMintFailed,
     #[error("Pool authority is invalid")]
     InvalidPoolAuthority,
     #[error("Input token mint account is not valid")]
@@ -44,12 +45,134 @@ pub enum CustomError {
     InvalidSigner,
     #[error("Invalid stake user owner")]
     InvalidStakeOwner,
-    #[error("Insufficient funds")]
-    InsufficientFunds,
+    #[error("Fee vault is invalid")]
+    InvalidFeeVault,
+    #[error("Pool admin is invalid")]
+    InvalidAdmin,
+    #[error("Target pool is invalid")]
+    InvalidTargetPool,
+    #[error("Hook program is invalid or missing")]
+    InvalidHookProgram,
+    #[error("Price oracle account is invalid")]
+    InvalidPriceOracle,
+    #[error("NFT is not verified in the boost collection")]
+    InvalidCollection,
+    #[error("Badge mint is not a non-transferable mint of the pool")]
+    InvalidBadgeMint,
+    #[error("Stake snapshot is invalid or finalized")]
+    InvalidSnapshot,
+    #[error("Bonus campaign or its vault is invalid")]
+    InvalidBonusCampaign,
+    #[error("Global config account is invalid")]
+    InvalidGlobalConfig,
+    #[error("Treasury is not the one of the global config")]
+    InvalidTreasury,
+    #[error("Stake user is not at the program address of its owner")]
+    InvalidStakeUser,
+    #[error("Distribution report is not the pool's for its epoch or an earlier one")]
+    InvalidDistributionReport,
+    #[error("Claim split is not the stake user's or its destinations don't match")]
+    InvalidClaimSplit,
+    #[error("Account has the layout of the first release, which isn't migrated")]
+    LegacyAccountLayout,
+    #[error("Token program is neither spl-token nor Token-2022")]
+    InvalidTokenProgram,
+
+    // Token program errors, 300..400
+    #[error("Token initialize account failed")]
+    TokenInitializeAccountFailed = 300,
+    #[error("Token initialize mint failed")]
+    TokenInitializeMintFailed,
     #[error("Token transfer failed")]
     TokenTransferFailed,
     #[error("Token mint to failed")]
     TokenMintToFailed,
+    #[error("Token burn failed")]
+    TokenBurnFailed,
+    #[error("Token set authority failed")]
+    TokenSetAuthorityFailed,
+
+    // Pool state and parameter errors, 400..500
+    #[error("Insufficient liquidity available")]
+    InsufficientLiquidity = 400,
+    #[error("Insufficient claim amount")]
+    InsufficientClaimAmount,
+    #[error("Insufficient funds")]
+    InsufficientFunds,
+    #[error("Stake pool is deprecated")]
+    PoolDeprecated,
+    #[error("Basis points value is out of range")]
+    InvalidBasisPoints,
+    #[error("Reward period is not supported")]
+    InvalidRewardPeriod,
+    #[error("Rate checkpoint table is full")]
+    RateCheckpointsFull,
+    #[error("Epoch is already running or over")]
+    InvalidEpoch,
+    #[error("Pool invariant violated")]
+    InvariantViolated,
+    #[error("Oracle price is stale or not trading")]
+    StalePrice,
+    #[error("Oracle price confidence is too wide")]
+    PriceConfidenceTooWide,
+    #[error("Stake mint table is full")]
+    StakeMintsFull,
+    #[error("Pair ratio is invalid or can no longer be set")]
+    InvalidPairRatio,
+    #[error("Pair pools stake and unstake both legs together")]
+    PairedStakeRequired,
+    #[error("Stake user boost is already registered or missing")]
+    InvalidBoost,
+    #[error("Tiers are not ordered")]
+    InvalidTiers,
+    #[error("Stake user still has a stake, rewards owed, a boost or a stream")]
+    StakeUserNotEmpty,
+    #[error("A stake snapshot is being built or not due yet")]
+    SnapshotPending,
+    #[error("Bonus proof is invalid")]
+    InvalidProof,
+    #[error("Bonus is already claimed")]
+    BonusClaimed,
+    #[error("Refresh batch is too long or not in increasing order")]
+    InvalidRefreshBatch,
+    #[error("Stake pool only accepts top-level calls or its integrators")]
+    CallerNotAllowed,
+    #[error("Stake user accrual timestamp is not in the future")]
+    NothingToRepair,
+    #[error("Reward budget of the pool is exhausted")]
+    RewardBudgetExhausted,
+    #[error("Reward mint supply reached the pool's maximum")]
+    RewardSupplyCapReached,
+    #[error("Stake pool is not deprecated")]
+    PoolNotDeprecated,
+    #[error("Stake pool is of a type this program doesn't support")]
+    UnsupportedPoolType,
+    #[error("Claims of the stake pool are streamed")]
+    RewardsStreamed,
+    #[error("Token account is frozen")]
+    AccountFrozen,
+    #[error("Stake pool has no rate controller or was rebalanced this reward period")]
+    RebalanceNotDue,
+    #[error("Stake exceeds the maximum stake per user of the pool")]
+    StakeCapExceeded,
+    #[error("Stake user was active within the collection delay of the pool")]
+    StakeUserNotStale,
+    #[error("Rent vault is not the one of the stake pool")]
+    InvalidRentVault,
+    #[error("Stakes and claims of all pools are paused")]
+    ProtocolPaused,
+    #[error("Reward rate above the cap of the global config")]
+    RewardRateTooHigh,
+    #[error("Reward rate has a zero denominator")]
+    InvalidRewardRate,
+    #[error("Claim shares are not ordered, repeat a destination or exceed the claim")]
+    InvalidClaimShares,
+    #[error("Lock tiers are not ordered by duration and bonus")]
+    InvalidLockTiers,
+    #[error("Stake is locked")]
+    StakeLocked,
+    #[error("Lock tier is missing or would shorten the lock or lower its bonus")]
+    InvalidRelock,
 }
 
 impl From<CustomError> for ProgramError {
@@ -58,6 +181,18 @@ impl From<CustomError> for ProgramError {
     }
 }
 
+impl From<MathError> for CustomError {
+    fn from(_: MathError) -> Self {
+        CustomError::CalculationFailure
+    }
+}
+
+impl From<MathError> for ProgramError {
+    fn from(e: MathError) -> Self {
+        CustomError::from(e).into()
+    }
+}
+
 impl<T> DecodeError<T> for CustomError {
     fn type_of() -> &'static str {
         "Reward pool error"
@@ -107,6 +242,119 @@ impl PrintProgramError for CustomError {
             CustomError::InsufficientFunds => msg!("Error: Insufficient funds"),
             CustomError::TokenTransferFailed => msg!("Error: Token transfer failed"),
             CustomError::TokenMintToFailed => msg!("Error: Token mint to failed"),
+            CustomError::InvalidFeeVault => msg!("Error: Fee vault is invalid"),
+            CustomError::InvalidAdmin => msg!("Error: Pool admin is invalid"),
+            CustomError::InvalidTargetPool => msg!("Error: Target pool is invalid"),
+            CustomError::InvalidHookProgram => msg!("Error: Hook program is invalid or missing"),
+            CustomError::PoolDeprecated => msg!("Error: Stake pool is deprecated"),
+            CustomError::InvalidBasisPoints => msg!("Error: Basis points value is out of range"),
+            CustomError::TokenBurnFailed => msg!("Error: Token burn failed"),
+            CustomError::TokenSetAuthorityFailed => msg!("Error: Token set authority failed"),
+            CustomError::InvalidRewardPeriod => msg!("Error: Reward period is not supported"),
+            CustomError::RateCheckpointsFull => msg!("Error: Rate checkpoint table is full"),
+            CustomError::InvalidEpoch => msg!("Error: Epoch is already running or over"),
+            CustomError::InvariantViolated => msg!("Error: Pool invariant violated"),
+            CustomError::InvalidPriceOracle => msg!("Error: Price oracle account is invalid"),
+            CustomError::InvalidCollection => {
+                msg!("Error: NFT is not verified in the boost collection")
+            }
+            CustomError::InvalidBadgeMint => {
+                msg!("Error: Badge mint is not a non-transferable mint of the pool")
+            }
+            CustomError::InvalidSnapshot => msg!("Error: Stake snapshot is invalid or finalized"),
+            CustomError::InvalidBonusCampaign => {
+                msg!("Error: Bonus campaign or its vault is invalid")
+            }
+            CustomError::InvalidGlobalConfig => msg!("Error: Global config account is invalid"),
+            CustomError::InvalidTreasury => {
+                msg!("Error: Treasury is not the one of the global config")
+            }
+            CustomError::InvalidStakeUser => {
+                msg!("Error: Stake user is not at the program address of its owner")
+            }
+            CustomError::InvalidDistributionReport => {
+                msg!("Error: Distribution report is not the pool's for its epoch or an earlier one")
+            }
+            CustomError::InvalidClaimSplit => {
+                msg!("Error: Claim split is not the stake user's or its destinations don't match")
+            }
+            CustomError::LegacyAccountLayout => {
+                msg!("Error: Account has the layout of the first release, which isn't migrated")
+            }
+            CustomError::InvalidTokenProgram => {
+                msg!("Error: Token program is neither spl-token nor Token-2022")
+            }
+            CustomError::StalePrice => msg!("Error: Oracle price is stale or not trading"),
+            CustomError::PriceConfidenceTooWide => {
+                msg!("Error: Oracle price confidence is too wide")
+            }
+            CustomError::StakeMintsFull => msg!("Error: Stake mint table is full"),
+            CustomError::InvalidPairRatio => {
+                msg!("Error: Pair ratio is invalid or can no longer be set")
+            }
+            CustomError::PairedStakeRequired => {
+                msg!("Error: Pair pools stake and unstake both legs together")
+            }
+            CustomError::InvalidBoost => {
+                msg!("Error: Stake user boost is already registered or missing")
+            }
+            CustomError::InvalidTiers => msg!("Error: Tiers are not ordered"),
+            CustomError::StakeUserNotEmpty => {
+                msg!("Error: Stake user still has a stake, rewards owed, a boost or a stream")
+            }
+            CustomError::SnapshotPending => {
+                msg!("Error: A stake snapshot is being built or not due yet")
+            }
+            CustomError::InvalidProof => msg!("Error: Bonus proof is invalid"),
+            CustomError::BonusClaimed => msg!("Error: Bonus is already claimed"),
+            CustomError::InvalidRefreshBatch => {
+                msg!("Error: Refresh batch is too long or not in increasing order")
+            }
+            CustomError::CallerNotAllowed => {
+                msg!("Error: Stake pool only accepts top-level calls or its integrators")
+            }
+            CustomError::NothingToRepair => {
+                msg!("Error: Stake user accrual timestamp is not in the future")
+            }
+            CustomError::RewardBudgetExhausted => {
+                msg!("Error: Reward budget of the pool is exhausted")
+            }
+            CustomError::RewardSupplyCapReached => {
+                msg!("Error: Reward mint supply reached the pool's maximum")
+            }
+            CustomError::PoolNotDeprecated => msg!("Error: Stake pool is not deprecated"),
+            CustomError::UnsupportedPoolType => {
+                msg!("Error: Stake pool is of a type this program doesn't support")
+            }
+            CustomError::RewardsStreamed => msg!("Error: Claims of the stake pool are streamed"),
+            CustomError::AccountFrozen => msg!("Error: Token account is frozen"),
+            CustomError::RebalanceNotDue => msg!(
+                "Error: Stake pool has no rate controller or was rebalanced this reward period"
+            ),
+            CustomError::StakeCapExceeded => {
+                msg!("Error: Stake exceeds the maximum stake per user of the pool")
+            }
+            CustomError::StakeUserNotStale => {
+                msg!("Error: Stake user was active within the collection delay of the pool")
+            }
+            CustomError::InvalidRentVault => {
+                msg!("Error: Rent vault is not the one of the stake pool")
+            }
+            CustomError::ProtocolPaused => msg!("Error: Stakes and claims of all pools are paused"),
+            CustomError::RewardRateTooHigh => {
+                msg!("Error: Reward rate above the cap of the global config")
+            }
+            CustomError::InvalidRewardRate => msg!("Error: Reward rate has a zero denominator"),
+            CustomError::InvalidClaimShares => msg!(
+                "Error: Claim shares are not ordered, repeat a destination or exceed the claim"
+            ),
+            CustomError::InvalidLockTiers => {
+                msg!("Error: Lock tiers are not ordered by duration and bonus")
+            }
+            CustomError::StakeLocked => msg!("Error: Stake is locked"),
+            CustomError::InvalidRelock => {
+                msg!("Error: Lock tier is missing or would shorten the lock or lower its bonus")
+            }
         }
     }
 }
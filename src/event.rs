@@ -0,0 +1,147 @@
+//! Events logged by the program for indexers, as `Program data:` log lines
+//! holding the schema version byte followed by the Borsh-encoded event.
+//!
+//! Events only ever gain variants at the end within a schema version. Changing
+//! the fields of an existing event bumps `EVENT_SCHEMA_VERSION`.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::error::CustomError;
+
+/// Version of the event encoding, first byte of each event
+pub const EVENT_SCHEMA_VERSION: u8 = 1;
+
+/// Prefix of the log lines events are emitted as
+pub const EVENT_LOG_PREFIX: &str = "Program data: ";
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Invariant {
+    /// Reserve balance below the tracked stakes
+    ReserveBalance,
+    /// Reserve has a delegate
+    ReserveDelegate,
+    /// Reward vault is not owned by the pool authority
+    VaultOwner,
+    /// Reward vault has a delegate
+    VaultDelegate,
+    /// Reward mint authority is not the pool authority
+    MintAuthority,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum Event {
+    FundRewards {
+        pool: Pubkey,
+        funder: Pubkey,
+        amount: u64,
+    },
+    Sweep {
+        pool: Pubkey,
+        source: Pubkey,
+        destination: Pubkey,
+        amount: u64,
+    },
+    /// Reserve surplus credited to a stake user, or protocol-owned if none
+    SyncReserve {
+        pool: Pubkey,
+        stake_user: Option<Pubkey>,
+        amount: u64,
+    },
+    Deprecate {
+        pool: Pubkey,
+        successor: Pubkey,
+    },
+    InvariantViolated {
+        pool: Pubkey,
+        invariant: Invariant,
+    },
+    /// Accrual timestamp of a stake user in the future clamped by the admin
+    StakeUserRepaired {
+        pool: Pubkey,
+        stake_user: Pubkey,
+        last_update: i64,
+        repaired_to: i64,
+    },
+    /// Mint authority of the reward mint handed over by a deprecated pool,
+    /// default if revoked
+    RewardMintReleased {
+        pool: Pubkey,
+        reward_mint: Pubkey,
+        new_authority: Pubkey,
+    },
+    /// Reward rate moved toward the target TVL by the rate controller
+    RateRebalanced {
+        pool: Pubkey,
+        total_staked: u64,
+        reward_numerator: u64,
+        reward_denominator: u64,
+    },
+}
+
+impl Event {
+    /// Encode the event with the current schema version
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![EVENT_SCHEMA_VERSION];
+        // writing to a vector can't fail
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    /// Decode an event, rejecting unknown schema versions
+    pub fn decode(data: &[u8]) -> Result<Self, ProgramError> {
+        let (&version, rest) = data
+            .split_first()
+            .ok_or(CustomError::InstructionUnpackError)?;
+        if version != EVENT_SCHEMA_VERSION {
+            return Err(CustomError::InstructionUnpackError.into());
+        }
+        Self::try_from_slice(rest).map_err(|_| CustomError::InstructionUnpackError.into())
+    }
+
+    /// Decode an event from a transaction log line, `None` for other lines
+    pub fn from_log(line: &str) -> Option<Result<Self, ProgramError>> {
+        let data = line.strip_prefix(EVENT_LOG_PREFIX)?;
+        Some(
+            STANDARD
+                .decode(data)
+                .map_err(|_| CustomError::InstructionUnpackError.into())
+                .and_then(|data| Self::decode(&data)),
+        )
+    }
+
+    /// Log the event
+    pub fn emit(&self) {
+        sol_log_data(&[&self.encode()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_encoding() {
+        let event = Event::SyncReserve {
+            pool: Pubkey::new_unique(),
+            stake_user: None,
+            amount: 500,
+        };
+
+        let data = event.encode();
+        assert_eq!(data[0], EVENT_SCHEMA_VERSION);
+        assert_eq!(Event::decode(&data).unwrap(), event);
+
+        let line = format!("{}{}", EVENT_LOG_PREFIX, STANDARD.encode(&data));
+        assert_eq!(Event::from_log(&line).unwrap().unwrap(), event);
+        assert!(Event::from_log("Program log: Instruction: Stake").is_none());
+
+        let mut data = data;
+        data[0] = EVENT_SCHEMA_VERSION + 1;
+        assert_eq!(
+            Event::decode(&data),
+            Err(CustomError::InstructionUnpackError.into())
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Pools administered by an spl-governance (Realms) DAO.
+//!
+//! Admin instructions only need the admin to sign, which a governance signs
+//! with `invoke_signed` when executing a proposal, so they work unchanged with
+//! a governance or its native treasury as admin. Hand an existing pool over
+//! with `set_admin`, then insert admin instructions into proposal transactions
+//! as `InstructionData`, the Borsh layout spl-governance stores them in.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+/// Account of an instruction stored in a proposal transaction
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AccountMetaData {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Instruction stored in a proposal transaction, executed by the governance
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct InstructionData {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMetaData>,
+    pub data: Vec<u8>,
+}
+
+impl InstructionData {
+    /// Borsh encoding, as inserted into proposals
+    pub fn encode(&self) -> Vec<u8> {
+        // writing to a vector can't fail
+        borsh::to_vec(self).unwrap()
+    }
+}
+
+impl From<Instruction> for InstructionData {
+    fn from(instruction: Instruction) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .into_iter()
+                .map(|meta| AccountMetaData {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data,
+        }
+    }
+}
+
+impl From<InstructionData> for Instruction {
+    fn from(instruction: InstructionData) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .into_iter()
+                .map(|meta| AccountMeta {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data,
+        }
+    }
+}
+
+/// Admin instructions as stored in a proposal transaction of the governance
+/// administering the pool
+pub fn proposal_instructions(instructions: Vec<Instruction>) -> Vec<InstructionData> {
+    instructions
+        .into_iter()
+        .map(InstructionData::from)
+        .collect()
+}
+
+/// Find the governance of a realm over a governed account, or over a seed for
+/// governances created without one
+pub fn find_governance_address(
+    governance_program_id: &Pubkey,
+    realm_pubkey: &Pubkey,
+    governance_seed: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"account-governance",
+            realm_pubkey.as_ref(),
+            governance_seed.as_ref(),
+        ],
+        governance_program_id,
+    )
+}
+
+/// Find the native SOL treasury of a governance, which signs proposal
+/// instructions along with the governance
+pub fn find_native_treasury_address(
+    governance_program_id: &Pubkey,
+    governance_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"native-treasury", governance_pubkey.as_ref()],
+        governance_program_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_data() {
+        let instruction = Instruction {
+            program_id: Pubkey::new_from_array([1; 32]),
+            accounts: vec![
+                AccountMeta::new(Pubkey::new_from_array([2; 32]), false),
+                AccountMeta::new_readonly(Pubkey::new_from_array([3; 32]), true),
+            ],
+            data: vec![7, 8],
+        };
+
+        let encoded = InstructionData::from(instruction.clone()).encode();
+        let mut expected = vec![1; 32];
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(&[2; 32]);
+        expected.extend_from_slice(&[0, 1]);
+        expected.extend_from_slice(&[3; 32]);
+        expected.extend_from_slice(&[1, 0]);
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(&[7, 8]);
+        assert_eq!(encoded, expected);
+
+        let decoded = InstructionData::try_from_slice(&encoded).unwrap();
+        assert_eq!(Instruction::from(decoded), instruction);
+    }
+}
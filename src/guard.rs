@@ -0,0 +1,83 @@
+//! Caller checks of top-level-only pools, keeping wrapper programs from
+//! sandwiching stakes and unstakes inside their own instructions.
+//!
+//! Stake and Unstake of such pools, paired ones included, and claims staked
+//! into them take the instructions sysvar as their last account, which isn't forwarded to hooks, and only run when the top-level
+//! instruction of the transaction is one of this program or of an integrator
+//! program the pool allows. Integrators must call the program directly, so
+//! they can't be used to smuggle in unknown wrappers further down the stack.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::instructions::{self, load_current_index_checked, load_instruction_at_checked},
+};
+
+use crate::{error::CustomError, state::Pool};
+
+/// Split the instructions sysvar off the accounts of an instruction on a
+/// top-level-only pool, where it is the last account. The pool is the first
+/// account.
+pub fn split_instructions_sysvar<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<(&'a [AccountInfo<'b>], Option<&'a AccountInfo<'b>>), ProgramError> {
+    split_instructions_sysvar_at(program_id, accounts, 0)
+}
+
+/// Split the instructions sysvar off the accounts of an instruction staking
+/// into the pool at the given account index, such as the target pool of
+/// ClaimAndStakeInto
+pub fn split_instructions_sysvar_at<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    stake_pool_index: usize,
+) -> Result<(&'a [AccountInfo<'b>], Option<&'a AccountInfo<'b>>), ProgramError> {
+    let top_level_only = match accounts.get(stake_pool_index) {
+        Some(stake_pool_info) if stake_pool_info.owner == program_id => {
+            Pool::unpack_top_level_only(&stake_pool_info.data.borrow())?
+        }
+        _ => false,
+    };
+    if !top_level_only {
+        return Ok((accounts, None));
+    }
+
+    match accounts.split_last() {
+        Some((last, rest)) if *last.key == instructions::id() => Ok((rest, Some(last))),
+        _ => {
+            msg!("Instructions sysvar missing");
+            Err(CustomError::CallerNotAllowed.into())
+        }
+    }
+}
+
+/// Check that a top-level-only pool is called at the top level or directly
+/// by one of its integrators
+pub fn assert_caller(
+    program_id: &Pubkey,
+    stake_pool: &Pool,
+    instructions_info: Option<&AccountInfo>,
+) -> ProgramResult {
+    if !stake_pool.top_level_only {
+        return Ok(());
+    }
+    let instructions_info = instructions_info.ok_or(CustomError::CallerNotAllowed)?;
+
+    let current = load_current_index_checked(instructions_info)?;
+    let caller = load_instruction_at_checked(current as usize, instructions_info)?.program_id;
+    if caller != *program_id && !stake_pool.is_integrator(&caller) {
+        msg!("Caller program {} not allowed", caller);
+        return Err(CustomError::CallerNotAllowed.into());
+    }
+    // the top-level program is the direct caller only one invocation down
+    if get_stack_height() > TRANSACTION_LEVEL_STACK_HEIGHT + 1 {
+        msg!("Called through {} by another program", caller);
+        return Err(CustomError::CallerNotAllowed.into());
+    }
+    Ok(())
+}
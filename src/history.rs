@@ -0,0 +1,829 @@
+//! Reconstruction of the rewards accrued by the stake users of a pool from its
+//! transaction history, for audits and accounting exports when events weren't
+//! indexed live. The successful transactions of the pool are replayed oldest
+//! first through the `simulator`, from the instructions of the program they
+//! executed and the events they logged, at their block times.
+//!
+//! Replays start at CreatePool, or from a known state of the pool. Stakes,
+//! unstakes, claims and refreshes of the primary stake mint are replayed along
+//! with the admin instructions changing the rate, the reward budget and the
+//! parameters of accrual and claims, RebalanceRate and RepairUser through the
+//! events they log. Other instructions of the pool which may change accrual,
+//! like epoch rates, additional stake mints, boosts or merged positions, are
+//! reported as skipped rather than guessed at, so the reconstruction is exact
+//! for pools which never ran them.
+
+use std::collections::{HashMap, VecDeque};
+
+use solana_program::{
+    clock::{Slot, UnixTimestamp},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solana_sdk::signature::Signature;
+use thiserror::Error;
+
+use crate::{
+    client::program_events,
+    event::Event,
+    instruction::{
+        ClaimBurnData, DeprecateData, HarvestAllData, InitData, InstructionType,
+        MaxRewardSupplyData, MaxStakePerUserData, RateData, RewardBudgetData, RoundingData,
+        StakeData, StreamDurationData, WarmUpData,
+    },
+    math::Rounding,
+    pda::find_stake_user_address,
+    simulator::{Operation, Simulator, Step},
+    state::{Pool, RewardMode},
+};
+
+/// Instruction a transaction executed, with its accounts resolved
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoricalInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+}
+
+/// Successful transaction of the pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoricalTransaction {
+    pub signature: Signature,
+    pub slot: Slot,
+    pub block_time: UnixTimestamp,
+    /// Instructions in execution order, the inner instructions right after
+    /// the instruction invoking them
+    pub instructions: Vec<HistoricalInstruction>,
+    pub logs: Vec<String>,
+}
+
+/// What a stake user did, or had done, at an entry of the timeline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Stake(u64),
+    Unstake(u64),
+    /// Claim paying out an amount of reward tokens
+    Claim(u64),
+    Refresh,
+    /// RepairUser moving the last update of the stake user back
+    Repair,
+}
+
+/// State of a stake user after an action, with the rewards owed accrued up to
+/// the action
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccrualEntry {
+    pub signature: Signature,
+    pub slot: Slot,
+    pub timestamp: UnixTimestamp,
+    pub owner: Pubkey,
+    pub action: Action,
+    pub stake_amount: u64,
+    pub reward_owed: u64,
+    pub total_claimed: u64,
+    /// Reward tokens the claim burn of the pool took from a claim, zero for
+    /// other actions
+    pub burned: u64,
+}
+
+/// Instruction of the pool the replay left out
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedInstruction {
+    pub signature: Signature,
+    pub data: Vec<u8>,
+}
+
+/// Transaction the replay stopped at, with why
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum ReplayError {
+    #[error("transaction {0} is older than the one before")]
+    OutOfOrder(Signature),
+    #[error("transaction {0} uses the pool before CreatePool")]
+    PoolNotCreated(Signature),
+    #[error("transaction {0} uses stake user {1} whose owner is unknown")]
+    UnknownStakeUser(Signature, Pubkey),
+    #[error("replaying transaction {0} failed: {1}")]
+    Failed(Signature, ProgramError),
+}
+
+/// Replay of the transaction history of a pool
+#[derive(Clone, Debug)]
+pub struct Reconstruction {
+    program_id: Pubkey,
+    pool_pubkey: Pubkey,
+    /// Claim burn the pool is created with, the default of the global config
+    default_claim_burn_bps: u16,
+    simulator: Option<Simulator>,
+    /// Owners of the stake users by address
+    owners: HashMap<Pubkey, Pubkey>,
+    block_time: Option<UnixTimestamp>,
+    /// Stake users after each of their actions, oldest first
+    pub entries: Vec<AccrualEntry>,
+    pub skipped: Vec<SkippedInstruction>,
+}
+
+impl Reconstruction {
+    /// Replay of a pool from its CreatePool transaction on
+    pub fn new(program_id: Pubkey, pool_pubkey: Pubkey, default_claim_burn_bps: u16) -> Self {
+        Self {
+            program_id,
+            pool_pubkey,
+            default_claim_burn_bps,
+            simulator: None,
+            owners: HashMap::new(),
+            block_time: None,
+            entries: vec![],
+            skipped: vec![],
+        }
+    }
+
+    /// Replay of a pool from a known state on, its stake users at the
+    /// addresses derived from their owners
+    pub fn from_state(program_id: Pubkey, simulator: Simulator) -> Self {
+        let mut reconstruction = Self::new(program_id, simulator.pool_pubkey, 0);
+        reconstruction.owners = simulator
+            .users
+            .keys()
+            .map(|owner| {
+                let (stake_user, _) =
+                    find_stake_user_address(&program_id, &simulator.pool_pubkey, owner);
+                (stake_user, *owner)
+            })
+            .collect();
+        reconstruction.simulator = Some(simulator);
+        reconstruction
+    }
+
+    /// Pool and stake users as of the last transaction replayed, `None`
+    /// before CreatePool
+    pub fn simulator(&self) -> Option<&Simulator> {
+        self.simulator.as_ref()
+    }
+
+    /// Replay the instructions of the program in a transaction. A failed
+    /// replay may leave the reconstruction partially updated.
+    pub fn replay(&mut self, transaction: &HistoricalTransaction) -> Result<(), ReplayError> {
+        if self
+            .block_time
+            .is_some_and(|block_time| transaction.block_time < block_time)
+        {
+            return Err(ReplayError::OutOfOrder(transaction.signature));
+        }
+        self.block_time = Some(transaction.block_time);
+
+        let mut rebalances = VecDeque::new();
+        let mut repairs = VecDeque::new();
+        for event in program_events(&self.program_id, &transaction.logs) {
+            match event {
+                Event::RateRebalanced {
+                    pool,
+                    reward_numerator,
+                    reward_denominator,
+                    ..
+                } if pool == self.pool_pubkey => {
+                    rebalances.push_back((reward_numerator, reward_denominator))
+                }
+                Event::StakeUserRepaired {
+                    pool,
+                    stake_user,
+                    repaired_to,
+                    ..
+                } if pool == self.pool_pubkey => repairs.push_back((stake_user, repaired_to)),
+                _ => {}
+            }
+        }
+
+        for instruction in &transaction.instructions {
+            if instruction.program_id != self.program_id {
+                continue;
+            }
+            self.replay_instruction(transaction, instruction, &mut rebalances, &mut repairs)?;
+        }
+        Ok(())
+    }
+
+    fn replay_instruction(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        instruction: &HistoricalInstruction,
+        rebalances: &mut VecDeque<(u64, u64)>,
+        repairs: &mut VecDeque<(Pubkey, UnixTimestamp)>,
+    ) -> Result<(), ReplayError> {
+        let signature = transaction.signature;
+        let accounts = instruction.accounts.as_slice();
+        // the program rejects data it can't decode, so no such instruction
+        // succeeded
+        let decoded = match InstructionType::unpack(&instruction.data) {
+            Ok(decoded) => decoded,
+            Err(_) => return Ok(()),
+        };
+        // runs of claim accounts, each starting with its pool
+        if let InstructionType::HarvestAll(HarvestAllData { account_counts }) = &decoded {
+            let mut rest = accounts;
+            for &count in account_counts {
+                let (run, next) = rest.split_at(usize::from(count).min(rest.len()));
+                if run.first() == Some(&self.pool_pubkey) {
+                    if let Some(owner) = run.get(2) {
+                        self.claim(transaction, *owner)?;
+                    }
+                }
+                rest = next;
+            }
+            return Ok(());
+        }
+        if accounts.first() != Some(&self.pool_pubkey) {
+            return Ok(());
+        }
+        let account = |index: usize| {
+            accounts.get(index).copied().ok_or(ReplayError::Failed(
+                signature,
+                ProgramError::NotEnoughAccountKeys,
+            ))
+        };
+
+        match decoded {
+            InstructionType::CreatePool(init_data) => {
+                self.create_pool(transaction, accounts, init_data)
+            }
+            InstructionType::CreateStakeUser
+            | InstructionType::CreateStakeUserIdempotent
+            | InstructionType::CreateSponsoredStakeUser => {
+                self.owners.insert(account(1)?, account(2)?);
+                Ok(())
+            }
+            InstructionType::Stake(StakeData { amount, auto_claim }) => {
+                let owner = account(3)?;
+                self.owners.insert(account(1)?, owner);
+                self.stake(
+                    transaction,
+                    instruction,
+                    owner,
+                    account(5)?,
+                    amount,
+                    auto_claim,
+                )
+            }
+            InstructionType::StakeWithDelegate(StakeData { amount, .. }) => {
+                let owner = self.owner(signature, account(1)?)?;
+                self.stake(transaction, instruction, owner, account(4)?, amount, false)
+            }
+            InstructionType::Unstake(StakeData { amount, auto_claim }) => {
+                let owner = account(3)?;
+                self.owners.insert(account(1)?, owner);
+                self.unstake(
+                    transaction,
+                    instruction,
+                    owner,
+                    account(4)?,
+                    amount,
+                    auto_claim,
+                )
+            }
+            InstructionType::Claim | InstructionType::ClaimIfAny => {
+                self.claim(transaction, account(2)?)
+            }
+            InstructionType::ClaimFor => {
+                let owner = self.owner(signature, account(1)?)?;
+                self.claim(transaction, owner)
+            }
+            InstructionType::Refresh | InstructionType::RefreshBatch => {
+                self.refresh(transaction, accounts.get(2..).unwrap_or_default())
+            }
+            InstructionType::SetRewardRate(RateData {
+                reward_numerator,
+                reward_denominator,
+            }) => self.apply(
+                transaction,
+                Operation::SetRewardRate {
+                    reward_numerator,
+                    reward_denominator,
+                },
+            ),
+            InstructionType::RebalanceRate => match rebalances.pop_front() {
+                Some((reward_numerator, reward_denominator))
+                    if self.pool_mut(signature)?.current_rate()
+                        != (reward_numerator, reward_denominator) =>
+                {
+                    self.apply(
+                        transaction,
+                        Operation::SetRewardRate {
+                            reward_numerator,
+                            reward_denominator,
+                        },
+                    )
+                }
+                _ => Ok(()),
+            },
+            InstructionType::AddRewardBudget(RewardBudgetData { amount }) => {
+                self.apply(transaction, Operation::AddRewardBudget { amount })
+            }
+            InstructionType::RepairUser => match repairs.pop_front() {
+                Some((stake_user, repaired_to)) => {
+                    self.repair(transaction, stake_user, repaired_to)
+                }
+                None => Ok(()),
+            },
+            InstructionType::SetRounding(RoundingData { rounding }) => {
+                self.pool_mut(signature)?.rounding =
+                    Rounding::from_u8(rounding).unwrap_or_default();
+                Ok(())
+            }
+            InstructionType::SetClaimBurn(ClaimBurnData { claim_burn_bps }) => {
+                self.pool_mut(signature)?.claim_burn_bps = claim_burn_bps;
+                Ok(())
+            }
+            InstructionType::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply }) => {
+                self.pool_mut(signature)?.max_reward_supply = max_reward_supply;
+                Ok(())
+            }
+            InstructionType::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user }) => {
+                self.pool_mut(signature)?.max_stake_per_user = max_stake_per_user;
+                Ok(())
+            }
+            InstructionType::SetStreamDuration(StreamDurationData { stream_duration }) => {
+                self.pool_mut(signature)?.stream_duration = stream_duration;
+                Ok(())
+            }
+            InstructionType::SetWarmUp(WarmUpData {
+                warm_up_duration,
+                warm_up_rate_bps,
+            }) => {
+                let pool = self.pool_mut(signature)?;
+                pool.warm_up_duration = warm_up_duration;
+                pool.warm_up_rate_bps = warm_up_rate_bps;
+                Ok(())
+            }
+            InstructionType::Deprecate(DeprecateData { successor }) => {
+                let pool = self.pool_mut(signature)?;
+                pool.deprecated = true;
+                pool.successor = successor;
+                Ok(())
+            }
+            // moving tokens or reading state without changing accrual
+            InstructionType::FundRewards(_)
+            | InstructionType::Sweep(_)
+            | InstructionType::SnapshotPool
+            | InstructionType::FinalizeDistributionReport
+            | InstructionType::VerifyInvariants
+            | InstructionType::GetPoolInfo
+            | InstructionType::CreateStakeSnapshot(_)
+            | InstructionType::UpdateStakeSnapshot(_)
+            | InstructionType::WithdrawFromStream
+            | InstructionType::ReleaseRewardMint(_)
+            | InstructionType::FundRentVault(_)
+            | InstructionType::WithdrawRentVault(_)
+            | InstructionType::SetAdmin(_)
+            | InstructionType::SetRole(_)
+            | InstructionType::SetClaimSplit(_)
+            | InstructionType::SetHookProgram(_)
+            | InstructionType::SetTopLevelOnly(_)
+            | InstructionType::SetGcDelay(_)
+            | InstructionType::SetRateController(_)
+            | InstructionType::SetBadgeMint(_)
+            | InstructionType::MintBadge
+            | InstructionType::PublishBonus(_)
+            | InstructionType::ClaimBonus(_) => Ok(()),
+            _ => {
+                self.skip(signature, instruction);
+                Ok(())
+            }
+        }
+    }
+
+    fn create_pool(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        accounts: &[Pubkey],
+        init_data: InitData,
+    ) -> Result<(), ReplayError> {
+        let account = |index: usize| accounts.get(index).copied().unwrap_or_default();
+        let mut pool = Pool {
+            is_initialized: true,
+            bump_seed: init_data.bump_seed,
+            stake_token_mint: account(2),
+            reserved: account(3),
+            reward_mint: account(4),
+            reward_numerator: init_data.reward_numerator,
+            reward_denominator: init_data.reward_denominator,
+            reward_period: init_data.reward_period,
+            claim_burn_bps: self.default_claim_burn_bps,
+            admin: account(7),
+            ..Pool::default()
+        };
+        if let Some(reward_vault) = accounts.get(12) {
+            pool.reward_mode = RewardMode::TransferFromVault;
+            pool.reward_vault = *reward_vault;
+        }
+        self.simulator = Some(Simulator::new(
+            self.pool_pubkey,
+            pool,
+            vec![],
+            transaction.block_time,
+        ));
+        Ok(())
+    }
+
+    /// Stake, first claiming like the program with auto-claim
+    fn stake(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        instruction: &HistoricalInstruction,
+        owner: Pubkey,
+        reserve: Pubkey,
+        amount: u64,
+        auto_claim: bool,
+    ) -> Result<(), ReplayError> {
+        // only the primary stake mint is simulated
+        if reserve != self.pool_mut(transaction.signature)?.reserved {
+            self.skip(transaction.signature, instruction);
+            return Ok(());
+        }
+        if auto_claim {
+            self.claim(transaction, owner)?;
+        }
+        self.apply(transaction, Operation::Stake { owner, amount })?;
+        self.record(transaction, owner, Action::Stake(amount), 0);
+        Ok(())
+    }
+
+    fn unstake(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        instruction: &HistoricalInstruction,
+        owner: Pubkey,
+        reserve: Pubkey,
+        amount: u64,
+        auto_claim: bool,
+    ) -> Result<(), ReplayError> {
+        // only the primary stake mint is simulated
+        if reserve != self.pool_mut(transaction.signature)?.reserved {
+            self.skip(transaction.signature, instruction);
+            return Ok(());
+        }
+        if auto_claim {
+            self.claim(transaction, owner)?;
+        }
+        self.apply(transaction, Operation::Unstake { owner, amount })?;
+        self.record(transaction, owner, Action::Unstake(amount), 0);
+        Ok(())
+    }
+
+    fn claim(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        owner: Pubkey,
+    ) -> Result<(), ReplayError> {
+        // ClaimIfAny of a stake user which never staked pays nothing
+        let simulator = self.simulator_mut(transaction.signature)?;
+        if !simulator.users.contains_key(&owner) {
+            return Ok(());
+        }
+        let burned_before = simulator.burned;
+        let paid = self.apply_paying(transaction, Operation::Claim { owner })?;
+        let burned = self.simulator_mut(transaction.signature)?.burned - burned_before;
+        self.record(transaction, owner, Action::Claim(paid), burned);
+        Ok(())
+    }
+
+    fn refresh(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        stake_users: &[Pubkey],
+    ) -> Result<(), ReplayError> {
+        let owners: Vec<Pubkey> = stake_users
+            .iter()
+            .filter_map(|stake_user| self.owners.get(stake_user).copied())
+            .collect();
+        self.apply(
+            transaction,
+            Operation::RefreshUsers {
+                owners: owners.clone(),
+            },
+        )?;
+        for owner in owners {
+            let refreshed = self
+                .simulator
+                .as_ref()
+                .and_then(|simulator| simulator.users.get(&owner))
+                .is_some_and(|stake_user| stake_user.has_stake());
+            if refreshed {
+                self.record(transaction, owner, Action::Refresh, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn repair(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        stake_user: Pubkey,
+        repaired_to: UnixTimestamp,
+    ) -> Result<(), ReplayError> {
+        let owner = self.owner(transaction.signature, stake_user)?;
+        let simulator = self.simulator_mut(transaction.signature)?;
+        match simulator.users.get_mut(&owner) {
+            Some(stake_user) => stake_user.last_update = repaired_to,
+            None => return Ok(()),
+        }
+        self.record(transaction, owner, Action::Repair, 0);
+        Ok(())
+    }
+
+    fn apply(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        operation: Operation,
+    ) -> Result<(), ReplayError> {
+        self.apply_paying(transaction, operation).map(|_| ())
+    }
+
+    /// Apply an operation at the block time, returning the reward tokens paid
+    /// out
+    fn apply_paying(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        operation: Operation,
+    ) -> Result<u64, ReplayError> {
+        let signature = transaction.signature;
+        self.simulator_mut(signature)?
+            .apply(&Step {
+                timestamp: transaction.block_time,
+                operation,
+            })
+            .map_err(|error| ReplayError::Failed(signature, error))
+    }
+
+    fn record(
+        &mut self,
+        transaction: &HistoricalTransaction,
+        owner: Pubkey,
+        action: Action,
+        burned: u64,
+    ) {
+        let stake_user = match self
+            .simulator
+            .as_ref()
+            .and_then(|simulator| simulator.users.get(&owner))
+        {
+            Some(stake_user) => stake_user,
+            None => return,
+        };
+        self.entries.push(AccrualEntry {
+            signature: transaction.signature,
+            slot: transaction.slot,
+            timestamp: transaction.block_time,
+            owner,
+            action,
+            stake_amount: stake_user.stake_amount,
+            reward_owed: stake_user.reward_owed,
+            total_claimed: stake_user.total_claimed,
+            burned,
+        });
+    }
+
+    fn skip(&mut self, signature: Signature, instruction: &HistoricalInstruction) {
+        self.skipped.push(SkippedInstruction {
+            signature,
+            data: instruction.data.clone(),
+        });
+    }
+
+    fn owner(&self, signature: Signature, stake_user: Pubkey) -> Result<Pubkey, ReplayError> {
+        self.owners
+            .get(&stake_user)
+            .copied()
+            .ok_or(ReplayError::UnknownStakeUser(signature, stake_user))
+    }
+
+    fn simulator_mut(&mut self, signature: Signature) -> Result<&mut Simulator, ReplayError> {
+        self.simulator
+            .as_mut()
+            .ok_or(ReplayError::PoolNotCreated(signature))
+    }
+
+    fn pool_mut(&mut self, signature: Signature) -> Result<&mut Pool, ReplayError> {
+        self.simulator_mut(signature)
+            .map(|simulator| &mut simulator.pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::EVENT_LOG_PREFIX,
+        instruction::{
+            claim, claim_for, create_pool_with_accounts, create_stake_user, rebalance_rate,
+            refresh, stake,
+        },
+        pda::find_pool_authority_address,
+        state::DAILY_TS,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use solana_program::{instruction::Instruction, rent::Rent};
+
+    const DAY: UnixTimestamp = DAILY_TS as UnixTimestamp;
+
+    fn transaction(
+        block_time: UnixTimestamp,
+        instructions: Vec<Instruction>,
+        events: &[Event],
+    ) -> HistoricalTransaction {
+        let program_id = crate::id();
+        let mut logs = vec![format!("Program {} invoke [1]", program_id)];
+        logs.extend(
+            events
+                .iter()
+                .map(|event| format!("{}{}", EVENT_LOG_PREFIX, STANDARD.encode(event.encode()))),
+        );
+        logs.push(format!("Program {} success", program_id));
+        HistoricalTransaction {
+            signature: Signature::new_unique(),
+            slot: block_time as Slot,
+            block_time,
+            instructions: instructions
+                .into_iter()
+                .map(|instruction| HistoricalInstruction {
+                    program_id: instruction.program_id,
+                    accounts: instruction
+                        .accounts
+                        .iter()
+                        .map(|account| account.pubkey)
+                        .collect(),
+                    data: instruction.data,
+                })
+                .collect(),
+            logs,
+        }
+    }
+
+    #[test]
+    fn test_reconstruction() {
+        let program_id = crate::id();
+        let pool = Pubkey::new_unique();
+        let (authority, bump_seed) = find_pool_authority_address(&program_id, &pool);
+        let alice = Pubkey::new_unique();
+        let alice_stake_user = Pubkey::new_unique();
+        let mut reconstruction = Reconstruction::new(program_id, pool, 0);
+
+        let create_pool = create_pool_with_accounts(
+            program_id,
+            Pubkey::new_unique(),
+            pool,
+            authority,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            InitData {
+                bump_seed,
+                reward_numerator: 1,
+                reward_denominator: 10,
+                reward_period: DAILY_TS,
+                reject_freeze_authority: false,
+            },
+            &Rent::default(),
+        )
+        .unwrap();
+        reconstruction
+            .replay(&transaction(0, create_pool, &[]))
+            .unwrap();
+        let stake_pool = reconstruction.simulator().unwrap().pool.clone();
+        assert_eq!(stake_pool.reward_mode, RewardMode::MintToUser);
+
+        reconstruction
+            .replay(&transaction(
+                0,
+                vec![
+                    create_stake_user(program_id, pool, alice_stake_user, alice).unwrap(),
+                    stake(
+                        program_id,
+                        pool,
+                        &stake_pool,
+                        alice_stake_user,
+                        alice,
+                        alice,
+                        Pubkey::new_unique(),
+                        stake_pool.stake_token_mint,
+                        1_000,
+                    )
+                    .unwrap(),
+                ],
+                &[],
+            ))
+            .unwrap();
+
+        // the rate a rebalance set comes from its event, 100 a day then 200
+        reconstruction
+            .replay(&transaction(
+                DAY,
+                vec![rebalance_rate(program_id, pool).unwrap()],
+                &[Event::RateRebalanced {
+                    pool,
+                    total_staked: 1_000,
+                    reward_numerator: 1,
+                    reward_denominator: 5,
+                }],
+            ))
+            .unwrap();
+        reconstruction
+            .replay(&transaction(
+                2 * DAY,
+                vec![
+                    refresh(
+                        program_id,
+                        pool,
+                        vec![alice_stake_user, Pubkey::new_unique()],
+                    )
+                    .unwrap(),
+                    claim(
+                        program_id,
+                        pool,
+                        alice_stake_user,
+                        alice,
+                        authority,
+                        stake_pool.reward_mint,
+                        Pubkey::new_unique(),
+                    )
+                    .unwrap(),
+                ],
+                &[],
+            ))
+            .unwrap();
+
+        // instructions changing accrual in ways not replayed are reported
+        let sync_reserve = HistoricalInstruction {
+            program_id,
+            accounts: vec![pool],
+            data: InstructionType::SyncReserve.pack(),
+        };
+        let mut other_pool = transaction(2 * DAY, vec![], &[]);
+        other_pool.instructions = vec![
+            sync_reserve.clone(),
+            HistoricalInstruction {
+                accounts: vec![Pubkey::new_unique()],
+                ..sync_reserve.clone()
+            },
+        ];
+        reconstruction.replay(&other_pool).unwrap();
+
+        let entries: Vec<(UnixTimestamp, Pubkey, Action, u64, u64, u64)> = reconstruction
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.timestamp,
+                    entry.owner,
+                    entry.action,
+                    entry.stake_amount,
+                    entry.reward_owed,
+                    entry.total_claimed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (0, alice, Action::Stake(1_000), 1_000, 0, 0),
+                (2 * DAY, alice, Action::Refresh, 1_000, 300, 0),
+                (2 * DAY, alice, Action::Claim(300), 1_000, 0, 300),
+            ]
+        );
+        assert_eq!(
+            reconstruction.skipped,
+            vec![SkippedInstruction {
+                signature: other_pool.signature,
+                data: sync_reserve.data,
+            }]
+        );
+
+        let unknown = Pubkey::new_unique();
+        let claim_unknown = transaction(
+            3 * DAY,
+            vec![claim_for(
+                program_id,
+                pool,
+                unknown,
+                alice,
+                authority,
+                Pubkey::new_unique(),
+                stake_pool.reward_mint,
+            )
+            .unwrap()],
+            &[],
+        );
+        assert_eq!(
+            reconstruction.replay(&claim_unknown),
+            Err(ReplayError::UnknownStakeUser(
+                claim_unknown.signature,
+                unknown
+            ))
+        );
+        let earlier = transaction(DAY, vec![], &[]);
+        assert_eq!(
+            reconstruction.replay(&earlier),
+            Err(ReplayError::OutOfOrder(earlier.signature))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Interface of the hook program a pool may register, called after stakes,
+//! unstakes and claims to run partner logic such as loyalty points.
+//!
+//! The hook is invoked with the stake pool and the stake user, read-only,
+//! followed by the accounts passed after the hook program in the outer
+//! instruction, none of them signing. Its data is the interface version byte
+//! followed by the Borsh-encoded `HookData`. A failing hook fails the outer
+//! instruction, so pools should only register hooks they trust.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{error::CustomError, processor::reject_account, state::Pool};
+
+/// Version of the hook data encoding, first byte of the hook instruction data
+pub const HOOK_INTERFACE_VERSION: u8 = 1;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum HookAction {
+    Stake { amount: u64 },
+    Unstake { amount: u64 },
+    Claim { amount: u64 },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct HookData {
+    pub action: HookAction,
+    /// Owner of the stake user
+    pub owner: Pubkey,
+    /// Stake amount of the stake user after the action
+    pub stake_amount: u64,
+    /// Unix timestamp of the action
+    pub timestamp: i64,
+}
+
+impl HookData {
+    /// Encode the hook data with the current interface version
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![HOOK_INTERFACE_VERSION];
+        // writing to a vector can't fail
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    /// Decode hook data, rejecting unknown interface versions
+    pub fn decode(data: &[u8]) -> Result<Self, ProgramError> {
+        let (&version, rest) = data
+            .split_first()
+            .ok_or(CustomError::InstructionUnpackError)?;
+        if version != HOOK_INTERFACE_VERSION {
+            return Err(CustomError::InstructionUnpackError.into());
+        }
+        Self::try_from_slice(rest).map_err(|_| CustomError::InstructionUnpackError.into())
+    }
+}
+
+/// Split the accounts of an instruction on a pool with a hook into its own
+/// accounts and the hook accounts, starting with the hook program. The pool is
+/// the first account.
+pub fn split_hook_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<(&'a [AccountInfo<'b>], &'a [AccountInfo<'b>]), ProgramError> {
+    let hook_program = match accounts.first() {
+        Some(stake_pool_info) if stake_pool_info.owner == program_id => {
+            Pool::unpack_hook_program(&stake_pool_info.data.borrow())?
+        }
+        _ => None,
+    };
+    let hook_program = match hook_program {
+        Some(hook_program) => hook_program,
+        None => return Ok((accounts, &[])),
+    };
+
+    let position = accounts
+        .iter()
+        .position(|account_info| *account_info.key == hook_program)
+        .ok_or(CustomError::InvalidHookProgram)?;
+    Ok(accounts.split_at(position))
+}
+
+/// Call the hook program, if any, after an action
+pub fn invoke_hook<'a>(
+    hook_accounts: &[AccountInfo<'a>],
+    stake_pool_info: &AccountInfo<'a>,
+    stake_user_info: &AccountInfo<'a>,
+    data: HookData,
+) -> ProgramResult {
+    let (hook_program_info, extra_accounts) = match hook_accounts.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    if !hook_program_info.executable {
+        return Err(reject_account(
+            hook_accounts,
+            hook_program_info,
+            "not executable",
+            CustomError::InvalidHookProgram,
+        ));
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*stake_pool_info.key, false),
+        AccountMeta::new_readonly(*stake_user_info.key, false),
+    ];
+    // signatures of the outer instruction never carry over to the hook
+    accounts.extend(extra_accounts.iter().map(|account_info| AccountMeta {
+        pubkey: *account_info.key,
+        is_signer: false,
+        is_writable: account_info.is_writable,
+    }));
+
+    let mut account_infos = vec![stake_pool_info.clone(), stake_user_info.clone()];
+    account_infos.extend(hook_accounts.iter().cloned());
+
+    invoke(
+        &Instruction {
+            program_id: *hook_program_info.key,
+            accounts,
+            data: data.encode(),
+        },
+        &account_infos,
+    )
+}
@@ -1,17 +1,53 @@
 #![allow(clippy::too_many_arguments)]
 
-use crate::error::CustomError;
+use crate::{
+    error::CustomError,
+    pda::{
+        find_associated_token_address, find_bonus_authority_address,
+        find_boost_escrow_authority_address, find_claim_split_address,
+        find_distribution_report_address, find_fee_vault_address, find_global_config_address,
+        find_metadata_address, find_program_data_address, find_rent_vault_address,
+        find_stake_user_address,
+    },
+    state::{
+        ClaimShare, ClaimSplit, LockTier, Pool, Role, Rounding, StakeUser, Tier, MAX_CLAIM_SHARES,
+        MAX_INTEGRATORS, MAX_LOCK_TIERS, MAX_TIERS,
+    },
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use shank::ShankInstruction;
 use solana_program::{
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
-    pubkey::Pubkey,
-    sysvar::{clock, rent},
+    program_pack::Pack,
+    pubkey::{Pubkey, PUBKEY_BYTES},
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::{clock, instructions, rent},
+};
+
+use std::{
+    convert::{TryFrom, TryInto},
+    mem::size_of,
 };
 
-use std::{convert::TryInto, mem::size_of};
+/// First tag of the range reserved for v2 instructions. Tags below use the
+/// compact v1 layouts, v2 data is the tag, a little-endian `u16` payload length
+/// and a Borsh-encoded payload. Parameters are added to v2 payloads as trailing
+/// fields, which older programs skip, without breaking v1 clients.
+pub const V2_TAG_START: u8 = 0x80;
+
+/// Most stake users refreshed by one RefreshBatch, bounding its compute units
+pub const MAX_REFRESH_BATCH: usize = 16;
 
+/// Most claims bundled by one HarvestAll
+pub const MAX_HARVEST_CLAIMS: usize = 8;
+
+/// Instructions of the program. Stakes and claims, see `is_pausable`, take the
+/// global config as their last account besides the ones listed, appended by
+/// `with_global_config`.
 #[repr(C)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, ShankInstruction)]
 pub enum InstructionType {
     /// Create stake pool
     ///
@@ -19,32 +55,154 @@ pub enum InstructionType {
     /// 1. `[]` authority generated from bump_seed to mint reward
     /// 2. `[]` staking token mint
     /// 3. `[writable]` staking token reserve account
-    /// 4. `[wrtiable]` reward token mint
+    /// 4. `[wrtiable]` reward token mint, or a Token-2022 mint of the
+    ///    authority already initialized, e.g. interest-bearing
     /// 5. `[]` rent sysvar
     /// 6. `[]` token program id
+    /// 7. `[signer]` pool admin account
+    /// 8. `[writable, signer]` payer of the pool creation fee
+    /// 9. `[]` global config account, see `find_global_config_address`
+    /// 10. `[writable]` protocol treasury of the global config
+    /// 11. `[]` system program id
+    /// 12. `[writable]` optional reward token vault, makes the pool
+    ///     vault-funded with an already initialized reward token mint
+    #[account(
+        0,
+        writable,
+        name = "stake_pool",
+        desc = "Stake pool account to create"
+    )]
+    #[account(
+        1,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(2, name = "staking_token_mint", desc = "Staking token mint")]
+    #[account(
+        3,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(4, writable, name = "reward_token_mint", desc = "Reward token mint")]
+    #[account(5, name = "rent", desc = "Rent sysvar")]
+    #[account(6, name = "token_program", desc = "Token program")]
+    #[account(7, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        8,
+        writable,
+        signer,
+        name = "payer",
+        desc = "Payer of the pool creation fee"
+    )]
+    #[account(9, name = "global_config", desc = "Global config account")]
+    #[account(
+        10,
+        writable,
+        name = "treasury",
+        desc = "Protocol treasury of the global config"
+    )]
+    #[account(11, name = "system_program", desc = "System program")]
+    #[account(
+        12,
+        writable,
+        optional,
+        name = "reward_token_vault",
+        desc = "Reward token vault of vault-funded pools"
+    )]
     CreatePool(InitData),
     /// Create stake user
     ///
-    /// 0. `[]` stake pool account
+    /// 0. `[writable]` stake pool account
     /// 1. `[writable]` stake user account to create
     /// 2. `[signer]` stake user owner account
     /// 3. `[]` rent sysvar
-    /// 4. `[]` clock sysvar
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "stake_user",
+        desc = "Stake user account to create"
+    )]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(3, name = "rent", desc = "Rent sysvar")]
     CreateStakeUser,
     /// Stake token to the pool
     ///
-    /// 0. `[]` stake pool account
+    /// 0. `[writable]` stake pool account
     /// 1. `[writable]` stake user account
     /// 2. `[signer]` user transfer authority
     /// 3. `[signer]` stake user owner account
     /// 4. `[writable]` staking token user account
-    /// 5. `[writable]` staking token reserve account
+    /// 5. `[writable]` staking token reserve account of the staked mint
     /// 6. `[]` clock syavar
     /// 7. `[]` token program id
+    ///
+    /// With `auto_claim`, followed by the accounts paying out the rewards owed:
+    ///
+    /// 8. `[]` authority generated from bump_seed
+    /// 9. `[writable]` reward token mint, or reward token vault for vault-funded pools
+    /// 10. `[writable]` reward token account
+    /// 11. `[]` price account, oracle-priced pools
+    /// 12. `[writable]` reward token mint, vault-funded pools burning on claim or on Token-2022
+    ///
+    /// Token-2022 pools take the stake mint and its transfer hook accounts
+    /// before any hook accounts, see `with_stake_mint`.
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        signer,
+        name = "user_transfer_authority",
+        desc = "User transfer authority"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(4, writable, name = "source", desc = "Staking token user account")]
+    #[account(
+        5,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        optional,
+        name = "reward_authority",
+        desc = "Authority generated from bump_seed, when auto-claiming"
+    )]
+    #[account(
+        9,
+        writable,
+        optional,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools, when auto-claiming"
+    )]
+    #[account(
+        10,
+        writable,
+        optional,
+        name = "reward_token",
+        desc = "Reward token user account, when auto-claiming"
+    )]
+    #[account(
+        11,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools, when auto-claiming"
+    )]
+    #[account(
+        12,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022, when auto-claiming"
+    )]
     Stake(StakeData),
     /// Unstake token to the pool
     ///
-    /// 0. `[]` stake pool account
+    /// 0. `[writable]` stake pool account
     /// 1. `[writable]` stake user account
     /// 2. `[]` authority generated from bump_seed to mint reward
     /// 3. `[signer]` stake user owner account
@@ -52,157 +210,4369 @@ pub enum InstructionType {
     /// 5. `[writable]` staking token user account
     /// 6. `[]` clock ssyavar
     /// 7. `[]` token program id
+    ///
+    /// With `auto_claim`, followed by the accounts paying out the rewards owed:
+    ///
+    /// 8. `[]` authority generated from bump_seed
+    /// 9. `[writable]` reward token mint, or reward token vault for vault-funded pools
+    /// 10. `[writable]` reward token account
+    /// 11. `[]` price account, oracle-priced pools
+    /// 12. `[writable]` reward token mint, vault-funded pools burning on claim or on Token-2022
+    ///
+    /// Token-2022 pools take the stake mint and its transfer hook accounts
+    /// before any hook accounts, see `with_stake_mint`.
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        4,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(5, writable, name = "destination", desc = "Staking token user account")]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        optional,
+        name = "reward_authority",
+        desc = "Authority generated from bump_seed, when auto-claiming"
+    )]
+    #[account(
+        9,
+        writable,
+        optional,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools, when auto-claiming"
+    )]
+    #[account(
+        10,
+        writable,
+        optional,
+        name = "reward_token",
+        desc = "Reward token user account, when auto-claiming"
+    )]
+    #[account(
+        11,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools, when auto-claiming"
+    )]
+    #[account(
+        12,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022, when auto-claiming"
+    )]
     Unstake(StakeData),
     /// Calculate and Claim reward token owed
     ///
-    /// 0. `[]` stake pool account
+    /// 0. `[writable]` stake pool account
     /// 1. `[writable]` stake user account
     /// 2. `[signer]` stake owner account
-    /// 2. `[]` authorty generated from bump_seed to mint reward
-    /// 2. `[writable]` reward token mint
-    /// 3. `[writable]` reward token account
-    /// 4. `[]` clock sysvar
-    /// 5. `[]` token program id
+    /// 3. `[]` authorty generated from bump_seed to mint reward
+    /// 4. `[writable]` reward token mint, or reward token vault for vault-funded pools
+    /// 5. `[writable]` reward token account
+    /// 6. `[]` clock sysvar
+    /// 7. `[]` token program id
+    /// 8. `[]` price account, oracle-priced pools
+    /// 9. `[writable]` reward token mint, vault-funded pools burning on claim or on Token-2022
+    ///
+    /// The claim split of the stake user may follow, then the token accounts
+    /// of its shares in order, to pay the shares into them, see
+    /// `with_claim_split`.
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    #[account(5, writable, name = "reward_token", desc = "Reward token user account")]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools"
+    )]
+    #[account(
+        9,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022"
+    )]
     Claim,
-    /// Calculate reward token for stake users
+    /// Calculate reward token for stake users. The numbers of stake users
+    /// updated and skipped are set as return data, see `RefreshCounts`.
     ///
-    /// 0. `[]` stake pool account
+    /// 0. `[writable]` stake pool account
     /// 1. `[]` clock sysvar
     /// 2. `[writable]` array of staking user account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        name = "clock",
+        desc = "Clock sysvar, followed by the stake user accounts to refresh"
+    )]
     Refresh,
+    /// Donate rewards to the pool, open to anyone
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[writable, signer]` funder account
+    /// 2. `[writable]` reward token vault, or fee vault for minting pools
+    /// 3. `[]` token program id, or system program id for minting pools
+    /// 4. `[writable]` funder reward token account, vault-funded pools only
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, signer, name = "funder", desc = "Funder account")]
+    #[account(
+        2,
+        writable,
+        name = "vault",
+        desc = "Reward token vault, or fee vault for minting pools"
+    )]
+    #[account(
+        3,
+        name = "program",
+        desc = "Token program, or system program for minting pools"
+    )]
+    #[account(
+        4,
+        writable,
+        optional,
+        name = "source",
+        desc = "Funder reward token account of vault-funded pools"
+    )]
+    FundRewards(FundData),
+    /// Move tokens not backing stakes out of pool authority accounts
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or treasurer account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[writable]` token account owned by the pool authority
+    /// 4. `[writable]` destination token account
+    /// 5. `[]` token program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or treasurer account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        3,
+        writable,
+        name = "source",
+        desc = "Token account owned by the pool authority"
+    )]
+    #[account(4, writable, name = "destination", desc = "Destination token account")]
+    #[account(5, name = "token_program", desc = "Token program")]
+    Sweep(SweepData),
+    /// Reconcile tokens transferred straight to the reserve
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[]` staking token reserve account
+    /// 3. `[]` clock sysvar
+    /// 4. `[]` token program of the reserve
+    /// 5. `[writable]` optional stake user account credited with the surplus
+    ///    like a stake, otherwise the surplus is recorded as protocol-owned
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(3, name = "clock", desc = "Clock sysvar")]
+    #[account(4, name = "token_program", desc = "Token program of the reserve")]
+    #[account(
+        5,
+        writable,
+        optional,
+        name = "stake_user",
+        desc = "Stake user account credited with the surplus"
+    )]
+    SyncReserve,
+    /// Deprecate the pool, blocking new stakes and stake users
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or pauser account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or pauser account")]
+    Deprecate(DeprecateData),
+    /// Set the share of each claim to burn
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetClaimBurn(ClaimBurnData),
+    /// Schedule the reward rate of an upcoming epoch, switching the pool to
+    /// epoch-based reward accounting. The rate is capped by the global config.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or rate setter account
+    /// 2. `[]` clock sysvar
+    /// 3. `[]` global config account, see `find_global_config_address`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or rate setter account")]
+    #[account(2, name = "clock", desc = "Clock sysvar")]
+    #[account(3, name = "global_config", desc = "Global config account")]
+    SetEpochRate(EpochRateData),
+    /// Claim reward token owed like `Claim`, succeeding as a no-op when
+    /// nothing is owed. The claimed amount is set as return data.
+    ///
+    /// Accounts are the same as `Claim`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    #[account(5, writable, name = "reward_token", desc = "Reward token user account")]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools"
+    )]
+    #[account(
+        9,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022"
+    )]
+    ClaimIfAny,
+    /// Change the reward rate from now on, checkpointing the old rate so
+    /// accrual of users not yet refreshed stays correct. The rate is capped
+    /// by the global config.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or rate setter account
+    /// 2. `[]` clock sysvar
+    /// 3. `[]` global config account, see `find_global_config_address`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or rate setter account")]
+    #[account(2, name = "clock", desc = "Clock sysvar")]
+    #[account(3, name = "global_config", desc = "Global config account")]
+    SetRewardRate(RateData),
+    /// Record a timestamped snapshot of the pool accounting, open to anyone
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[writable]` pool snapshot account to create
+    /// 2. `[]` staking token reserve account
+    /// 3. `[]` reward token mint
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` clock sysvar
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "pool_snapshot",
+        desc = "Pool snapshot account to create"
+    )]
+    #[account(
+        2,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(3, name = "reward_token_mint", desc = "Reward token mint")]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    SnapshotPool,
+    /// Check the pool accounting against its token accounts, open to anyone.
+    /// Fails with an event logged per violated invariant.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[]` authority generated from bump_seed
+    /// 2. `[]` staking token reserve account
+    /// 3. `[]` reward token mint, or reward token vault for vault-funded pools
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        2,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(
+        3,
+        name = "reward_token",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    VerifyInvariants,
+    /// Stake token to the pool on a delegate approval alone, without the
+    /// stake user owner signing. The source must belong to the stake user
+    /// owner and be approved to the delegate, for custodial and smart-wallet
+    /// flows with a single signer.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` delegate of the source account
+    /// 3. `[writable]` staking token account of the stake user owner
+    /// 4. `[writable]` staking token reserve account
+    /// 5. `[]` clock sysvar
+    /// 6. `[]` token program
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "delegate", desc = "Delegate of the source account")]
+    #[account(
+        3,
+        writable,
+        name = "source",
+        desc = "Staking token account of the stake user owner"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    #[account(6, name = "token_program", desc = "Token program")]
+    StakeWithDelegate(StakeData),
+    /// Claim reward token owed on behalf of the stake user owner, open to
+    /// anyone. Rewards only go to the owner's associated token account for the
+    /// reward mint, so keepers can distribute to inactive users.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[writable]` reward token mint, or reward token vault for vault-funded pools
+    /// 4. `[writable]` associated reward token account of the stake user owner
+    /// 5. `[]` clock sysvar
+    /// 6. `[]` token program
+    /// 7. `[]` price account, oracle-priced pools
+    /// 8. `[writable]` reward token mint, vault-funded pools burning on claim or on Token-2022
+    ///
+    /// The claim split of the stake user and the token accounts of its shares
+    /// may follow like for `Claim`.
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        3,
+        writable,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "reward_token",
+        desc = "Associated reward token account of the stake user owner"
+    )]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    #[account(6, name = "token_program", desc = "Token program")]
+    #[account(
+        7,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools"
+    )]
+    #[account(
+        8,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022"
+    )]
+    ClaimFor,
+    /// Claim reward token owed and stake it into a target pool whose staking
+    /// token is the reward token. Rewards are paid out straight into the
+    /// target reserve and credited to the owner's stake user of the target pool.
+    /// Top-level-only target pools take the instructions sysvar last.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` stake user owner account, also owning the target stake user
+    /// 3. `[writable]` target stake pool account
+    /// 4. `[writable]` target stake user account
+    /// 5. `[]` authority generated from bump_seed
+    /// 6. `[writable]` reward token mint, or reward token vault for vault-funded pools
+    /// 7. `[writable]` staking token reserve account of the target pool
+    /// 8. `[]` clock sysvar
+    /// 9. `[]` token program
+    /// 10. `[]` price account, oracle-priced pools
+    /// 11. `[writable]` reward token mint, vault-funded pools burning on claim or on Token-2022
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        writable,
+        name = "target_pool",
+        desc = "Stake pool of the reward token"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "target_stake_user",
+        desc = "Stake user of the owner in the target pool"
+    )]
+    #[account(
+        5,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        6,
+        writable,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    #[account(
+        7,
+        writable,
+        name = "target_reserve",
+        desc = "Staking token reserve account of the target pool"
+    )]
+    #[account(8, name = "clock", desc = "Clock sysvar")]
+    #[account(9, name = "token_program", desc = "Token program")]
+    #[account(
+        10,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools"
+    )]
+    #[account(
+        11,
+        writable,
+        optional,
+        name = "burn_mint",
+        desc = "Reward token mint of vault-funded pools burning on claim or on Token-2022"
+    )]
+    ClaimAndStakeInto,
+    /// Register the program called after stakes, unstakes and claims, see the
+    /// `hook` module for its interface. The default pubkey removes the hook.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or upgrader account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or upgrader account")]
+    SetHookProgram(HookProgramData),
+    /// Price the pool's rewards in a quote currency, converted to reward
+    /// tokens at claim time from the given price account, checked to be a
+    /// Pyth price account. The default pubkey prices rewards in tokens again.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or upgrader account
+    /// 2. `[]` price account, unless clearing it
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or upgrader account")]
+    #[account(
+        2,
+        optional,
+        name = "price_oracle",
+        desc = "Price account, unless clearing it"
+    )]
+    SetPriceOracle(PriceOracleData),
+    /// Accept another stake mint with a weight toward reward accrual, making
+    /// the pool multi-mint. Stake and Unstake of the mint go through its
+    /// reserve, which is initialized here.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[]` stake token mint to add
+    /// 4. `[writable]` reserve token account of the mint to initialize
+    /// 5. `[]` rent sysvar
+    /// 6. `[]` token program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(3, name = "stake_mint", desc = "Stake token mint to add")]
+    #[account(
+        4,
+        writable,
+        name = "stake_mint_reserve",
+        desc = "Reserve token account of the mint to initialize"
+    )]
+    #[account(5, name = "rent", desc = "Rent sysvar")]
+    #[account(6, name = "token_program", desc = "Token program")]
+    AddStakeMint(StakeMintData),
+    /// Pair the primary stake token with the first additional stake mint in
+    /// a fixed ratio, making the pool a pair pool. Both legs are then staked
+    /// and unstaked together through StakePair and UnstakePair only. The
+    /// ratio is fixed once set, and only set before anything is staked.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetPairRatio(PairRatioData),
+    /// Stake an amount of the primary stake token of a pair pool, along with
+    /// the paired tokens the ratio requires, rounded up
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` user transfer authority of both source accounts
+    /// 3. `[signer]` stake user owner account
+    /// 4. `[writable]` staking token user account
+    /// 5. `[writable]` staking token reserve account
+    /// 6. `[]` clock sysvar
+    /// 7. `[]` token program id
+    /// 8. `[writable]` paired token user account
+    /// 9. `[writable]` paired token reserve account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        signer,
+        name = "user_transfer_authority",
+        desc = "User transfer authority of both source accounts"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(4, writable, name = "source", desc = "Staking token user account")]
+    #[account(
+        5,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        writable,
+        name = "paired_source",
+        desc = "Paired token user account"
+    )]
+    #[account(
+        9,
+        writable,
+        name = "paired_reserve",
+        desc = "Paired token reserve account"
+    )]
+    StakePair(PairStakeData),
+    /// Unstake an amount of the primary stake token of a pair pool, along
+    /// with the same share of the owner's paired tokens
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[signer]` stake user owner account
+    /// 4. `[writable]` staking token reserve account
+    /// 5. `[writable]` staking token user account
+    /// 6. `[]` clock sysvar
+    /// 7. `[]` token program id
+    /// 8. `[writable]` paired token reserve account
+    /// 9. `[writable]` paired token user account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        4,
+        writable,
+        name = "staking_token_reserve",
+        desc = "Staking token reserve account"
+    )]
+    #[account(5, writable, name = "destination", desc = "Staking token user account")]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        writable,
+        name = "paired_reserve",
+        desc = "Paired token reserve account"
+    )]
+    #[account(
+        9,
+        writable,
+        name = "paired_destination",
+        desc = "Paired token user account"
+    )]
+    UnstakePair(PairStakeData),
+    /// Boost the rewards of stakers escrowing an NFT verified in a collection,
+    /// by the given basis points of their accruing stake. Registered boosts
+    /// keep the basis points they were registered with. The default pubkey
+    /// stops new registrations.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetBoostCollection(BoostCollectionData),
+    /// Escrow an NFT of the pool's boost collection, boosting the owner's
+    /// rewards until unregistered. The escrow is the associated token account
+    /// for the NFT mint of the boost escrow authority of the stake user, see
+    /// `find_boost_escrow_authority_address`, out of reach of Sweep.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` stake user owner account, owning the NFT
+    /// 3. `[]` NFT mint
+    /// 4. `[]` Metaplex metadata account of the NFT
+    /// 5. `[writable]` NFT token account of the owner
+    /// 6. `[writable]` NFT escrow token account
+    /// 7. `[]` clock sysvar
+    /// 8. `[]` token program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(3, name = "nft_mint", desc = "NFT mint")]
+    #[account(
+        4,
+        name = "nft_metadata",
+        desc = "Metaplex metadata account of the NFT"
+    )]
+    #[account(5, writable, name = "source", desc = "NFT token account of the owner")]
+    #[account(6, writable, name = "escrow", desc = "NFT escrow token account")]
+    #[account(7, name = "clock", desc = "Clock sysvar")]
+    #[account(8, name = "token_program", desc = "Token program")]
+    RegisterBoost,
+    /// Return the escrowed boost NFT to the owner, ending the boost
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[]` boost escrow authority of the stake user
+    /// 3. `[signer]` stake user owner account
+    /// 4. `[writable]` NFT escrow token account
+    /// 5. `[writable]` NFT token account receiving the NFT
+    /// 6. `[]` clock sysvar
+    /// 7. `[]` token program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        name = "escrow_authority",
+        desc = "Boost escrow authority of the stake user"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(4, writable, name = "escrow", desc = "NFT escrow token account")]
+    #[account(
+        5,
+        writable,
+        name = "destination",
+        desc = "NFT token account receiving the NFT"
+    )]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    UnregisterBoost,
+    /// Set the tiers stakers reach by staking an amount for a duration, ordered
+    /// with empty tiers last. Each tier reached mints a badge token.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetTiers(TiersData),
+    /// Set the staking badge mint, a Token-2022 mint with the non-transferable
+    /// extension, no decimals and the pool authority as mint authority. The
+    /// default pubkey stops minting badges.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[]` badge mint, unless clearing it
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        optional,
+        name = "badge_mint",
+        desc = "Badge mint, unless clearing it"
+    )]
+    SetBadgeMint(BadgeMintData),
+    /// Mint the soulbound staking badge of a staker, and a badge token per tier
+    /// reached since. Anyone may call it, clients add it to the transaction of
+    /// the first stake. Succeeds as a no-op when nothing is due.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[writable]` badge mint
+    /// 4. `[writable]` badge token account of the stake user owner
+    /// 5. `[]` clock sysvar
+    /// 6. `[]` Token-2022 program
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(3, writable, name = "badge_mint", desc = "Badge mint")]
+    #[account(
+        4,
+        writable,
+        name = "badge_token",
+        desc = "Badge token account of the stake user owner"
+    )]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    #[account(6, name = "token_program", desc = "Token-2022 program")]
+    MintBadge,
+    /// Set the streak bonus, paid on top of the rewards of stakers who haven't
+    /// unstaked for the streak duration. Zero basis points disable it.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetStreakBonus(StreakBonusData),
+    /// Close a stake user without a stake, rewards owed or a boost, and its
+    /// claim split, if any, refunding their rent
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` stake user owner account
+    /// 3. `[writable]` destination of the rent lamports, any account but the
+    ///    stake user
+    /// 4. `[writable]` claim split account, required if the stake user has
+    ///    one, see `with_closed_claim_split`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        writable,
+        name = "destination",
+        desc = "Destination of the rent lamports"
+    )]
+    #[account(
+        4,
+        writable,
+        optional,
+        name = "claim_split",
+        desc = "Claim split account of the stake user"
+    )]
+    CloseStakeUser,
+    /// Start a merkle snapshot of the stakes held at a timestamp, not before
+    /// now, one snapshot being built at a time
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[writable]` stake snapshot account to create
+    /// 3. `[]` rent sysvar
+    /// 4. `[]` clock sysvar
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        writable,
+        name = "stake_snapshot",
+        desc = "Stake snapshot account to create"
+    )]
+    #[account(3, name = "rent", desc = "Rent sysvar")]
+    #[account(4, name = "clock", desc = "Clock sysvar")]
+    CreateStakeSnapshot(StakeSnapshotData),
+    /// Add the `(owner, stake)` leaves of stake users to the snapshot being
+    /// built, in increasing order of their addresses, once past its timestamp.
+    /// Finalizing computes the merkle root and ends the snapshot.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[writable]` stake snapshot account
+    /// 3. `[]` clock sysvar
+    /// 4. .. `[]` stake user accounts to add
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(2, writable, name = "stake_snapshot", desc = "Stake snapshot account")]
+    #[account(3, name = "clock", desc = "Clock sysvar")]
+    UpdateStakeSnapshot(StakeSnapshotUpdateData),
+    /// Publish a bonus campaign paying out the amounts of a merkle tree of
+    /// `(owner, amount)` leaves from a vault of reward tokens funded by the
+    /// pool. The campaign account holds a claimed bit per leaf after the
+    /// campaign.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[writable]` bonus campaign account to create
+    /// 3. `[]` reward token vault owned by the bonus authority of the campaign
+    /// 4. `[]` rent sysvar
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        writable,
+        name = "bonus_campaign",
+        desc = "Bonus campaign account to create"
+    )]
+    #[account(
+        3,
+        name = "bonus_vault",
+        desc = "Reward token vault owned by the bonus authority of the campaign"
+    )]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    PublishBonus(BonusData),
+    /// Claim the bonus of a leaf of a campaign with its merkle proof, once
+    ///
+    /// 0. `[writable]` bonus campaign account
+    /// 1. `[]` bonus authority of the campaign
+    /// 2. `[writable]` bonus vault
+    /// 3. `[signer]` owner of the leaf
+    /// 4. `[writable]` reward token account receiving the bonus
+    /// 5. `[]` token program
+    /// 6. `[]` reward token mint, required by Token-2022 vaults
+    #[account(0, writable, name = "bonus_campaign", desc = "Bonus campaign account")]
+    #[account(1, name = "bonus_authority", desc = "Bonus authority of the campaign")]
+    #[account(2, writable, name = "bonus_vault", desc = "Bonus vault")]
+    #[account(3, signer, name = "owner", desc = "Owner of the leaf")]
+    #[account(
+        4,
+        writable,
+        name = "reward_token",
+        desc = "Reward token account receiving the bonus"
+    )]
+    #[account(5, name = "token_program", desc = "Token program")]
+    #[account(
+        6,
+        optional,
+        name = "reward_mint",
+        desc = "Reward token mint, required by Token-2022 vaults"
+    )]
+    ClaimBonus(BonusClaimData),
+    /// Hand the pool over to a new admin, e.g. a governance executing
+    /// proposals
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetAdmin(AdminData),
+    /// Refresh a bounded batch of stake users for automation networks. No
+    /// account signs, so any fee payer or thread can run it, and the accounts
+    /// are fixed in order so a recurring thread replays the same instruction.
+    /// Closed stake users and stake users of other pools are skipped. The
+    /// counts are set as return data like Refresh.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[]` clock sysvar
+    /// 2. `[writable]` up to `MAX_REFRESH_BATCH` stake user accounts, in
+    ///    strictly increasing order of their addresses
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        name = "clock",
+        desc = "Clock sysvar, followed by the ordered stake user accounts to refresh"
+    )]
+    RefreshBatch,
+    /// Only accept Stake and Unstake, including StakePair, UnstakePair and
+    /// ClaimAndStakeInto into the pool, as top-level instructions, or called
+    /// by directly by the integrator programs listed, up to `MAX_INTEGRATORS`.
+    /// Callers then pass the instructions sysvar as their last account.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetTopLevelOnly(TopLevelOnlyData),
+    /// Clamp the accrual timestamp of a stake user left in the future, e.g.
+    /// by clock skew, back to the current time so it accrues again. Fails
+    /// unless the timestamp is in the future, and logs an event.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[writable]` stake user account
+    /// 3. `[]` clock sysvar
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(2, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(3, name = "clock", desc = "Clock sysvar")]
+    RepairUser,
+    /// Top up the reward budget of the pool. The first top-up caps claims,
+    /// minted or paid out of the vault, burned shares included, by the budget
+    /// for good; accrual stops while it is exhausted.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or treasurer account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or treasurer account")]
+    AddRewardBudget(RewardBudgetData),
+    /// Set the maximum supply of the reward mint claims may mint up to, zero
+    /// for none. Claims past it mint what is left and keep the rest owed.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetMaxRewardSupply(MaxRewardSupplyData),
+    /// Hand the mint authority of the reward mint over from the pool
+    /// authority of a deprecated pool, or revoke it, so the token isn't left
+    /// behind a dead pool. Logs an event.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[signer]` pool admin account
+    /// 2. `[]` authority generated from bump_seed
+    /// 3. `[writable]` reward token mint
+    /// 4. `[]` token program id
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    #[account(
+        2,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(3, writable, name = "reward_mint", desc = "Reward token mint")]
+    #[account(4, name = "token_program", desc = "Token program")]
+    ReleaseRewardMint(ReleaseRewardMintData),
+    /// Set the program-wide config, creating it on first use. Only the upgrade
+    /// authority of the program creates it, then only the config authority
+    /// updates it.
+    ///
+    /// 0. `[writable]` global config account, see `find_global_config_address`
+    /// 1. `[signer]` config authority, or the upgrade authority of the
+    ///    program creating the config
+    /// 2. `[writable, signer]` payer of the rent of a created config
+    /// 3. `[]` program data account of the program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` system program id
+    #[account(0, writable, name = "global_config", desc = "Global config account")]
+    #[account(
+        1,
+        signer,
+        name = "authority",
+        desc = "Config authority, or program upgrade authority creating the config"
+    )]
+    #[account(
+        2,
+        writable,
+        signer,
+        name = "payer",
+        desc = "Payer of the rent of a created config"
+    )]
+    #[account(3, name = "program_data", desc = "Program data account of the program")]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    #[account(5, name = "system_program", desc = "System program")]
+    SetGlobalConfig(GlobalConfigData),
+    /// Create the stake user of an owner at its program address, see
+    /// `find_stake_user_address`, succeeding as a no-op if it exists, so it
+    /// can be prepended to any stake. The owner doesn't sign, anyone may
+    /// create it.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account at its program address
+    /// 2. `[]` stake user owner account
+    /// 3. `[writable, signer]` payer of the rent of the stake user
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` system program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "stake_user",
+        desc = "Stake user account at its program address"
+    )]
+    #[account(2, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        writable,
+        signer,
+        name = "payer",
+        desc = "Payer of the rent of the stake user"
+    )]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    #[account(5, name = "system_program", desc = "System program")]
+    CreateStakeUserIdempotent,
+    /// Claim reward token owed from several pools of the same owner, each
+    /// like `ClaimIfAny`. The amounts claimed, one per claim in order, are set
+    /// as return data as a Borsh `Vec<u64>`. Claims paying into a frozen
+    /// reward token account are skipped, claiming zero.
+    ///
+    /// Accounts are those of up to `MAX_HARVEST_CLAIMS` `ClaimIfAny`
+    /// instructions one after the other, including their optional and hook
+    /// accounts, with the number of accounts of each in the instruction data.
+    /// The stake owner of every claim must be the same.
+    #[account(
+        0,
+        writable,
+        name = "stake_pool",
+        desc = "Stake pool account of the first claim"
+    )]
+    #[account(
+        1,
+        writable,
+        name = "stake_user",
+        desc = "Stake user account of the first claim, followed by the rest of the claims"
+    )]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    HarvestAll(HarvestAllData),
+    /// Set the key parameters of a pool as return data, see `PoolInfo`, for
+    /// programs reading them through CPI instead of the pool layout
+    ///
+    /// 0. `[]` stake pool account
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    GetPoolInfo,
+    /// Set the seconds over which claimed rewards stream to stakers, 0 to pay
+    /// claims out at once. Streams already open keep their schedule until
+    /// topped up.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetStreamDuration(StreamDurationData),
+    /// Withdraw the streamed rewards released so far to a reward token
+    /// account. The amount withdrawn is set as return data as a `u64`.
+    ///
+    /// Accounts are those of `Claim`, the burn mint left out.
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        name = "pool_authority",
+        desc = "Authority generated from bump_seed"
+    )]
+    #[account(
+        4,
+        writable,
+        name = "reward_source",
+        desc = "Reward token mint, or reward token vault of vault-funded pools"
+    )]
+    #[account(5, writable, name = "reward_token", desc = "Reward token user account")]
+    #[account(6, name = "clock", desc = "Clock sysvar")]
+    #[account(7, name = "token_program", desc = "Token program")]
+    #[account(
+        8,
+        optional,
+        name = "price_oracle",
+        desc = "Price account of oracle-priced pools"
+    )]
+    WithdrawFromStream,
+    /// Set the warm-up of new deposits, which accrue at a fraction of the
+    /// full rate for the warm-up duration. Zero seconds disable it, warm-ups
+    /// already running end as scheduled.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetWarmUp(WarmUpData),
+    /// Set the rate controller, which `RebalanceRate` steers the reward rate
+    /// toward a target TVL with. A zero target disables it. The largest
+    /// numerator is capped by the global config at the current denominator.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin or rate setter account
+    /// 2. `[]` global config account, see `find_global_config_address`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or rate setter account")]
+    #[account(2, name = "global_config", desc = "Global config account")]
+    SetRateController(RateControllerData),
+    /// Move the reward rate of a pool with a rate controller toward its
+    /// target TVL, once per reward period. Anyone can call it.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[]` clock sysvar
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, name = "clock", desc = "Clock sysvar")]
+    RebalanceRate,
+    /// Merge a stake user into another of the same owner and pool, both
+    /// accrued first, closing the merged one and its claim split, if any,
+    /// and refunding their rent. Its stake, rewards owed and boost move over;
+    /// it can't have a stream.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account to merge into
+    /// 2. `[writable]` stake user account to merge and close
+    /// 3. `[signer]` stake user owner account
+    /// 4. `[writable]` destination of the rent lamports
+    /// 5. `[]` clock sysvar
+    /// 6. `[writable]` claim split account of the merged stake user, required
+    ///    if it has one, see `with_closed_claim_split`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "stake_user",
+        desc = "Stake user account to merge into"
+    )]
+    #[account(
+        2,
+        writable,
+        name = "source_stake_user",
+        desc = "Stake user account to merge and close"
+    )]
+    #[account(3, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        4,
+        writable,
+        name = "destination",
+        desc = "Destination of the rent lamports"
+    )]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    #[account(
+        6,
+        writable,
+        optional,
+        name = "claim_split",
+        desc = "Claim split account of the merged stake user"
+    )]
+    MergePositions,
+    /// Set the maximum weighted stake of each stake user, zero for none.
+    /// Stakes above it don't have to unstake, they can't grow.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetMaxStakePerUser(MaxStakePerUserData),
+    /// Set the seconds after which anyone can close empty stake users, zero
+    /// to never close them
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetGcDelay(GcDelayData),
+    /// Close a stake user without a stake, rewards owed, a boost or a stream,
+    /// untouched for the pool's collection delay, and its claim split, if
+    /// any, refunding their rent to the protocol treasury. Anyone can call it.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[]` global config account
+    /// 3. `[writable]` protocol treasury of the global config
+    /// 4. `[]` clock sysvar
+    /// 5. `[writable]` claim split account, required if the stake user has
+    ///    one, see `with_closed_claim_split`
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, name = "global_config", desc = "Global config account")]
+    #[account(
+        3,
+        writable,
+        name = "treasury",
+        desc = "Protocol treasury of the global config"
+    )]
+    #[account(4, name = "clock", desc = "Clock sysvar")]
+    #[account(
+        5,
+        writable,
+        optional,
+        name = "claim_split",
+        desc = "Claim split account of the stake user"
+    )]
+    GcUser,
+    /// Top up the rent vault of the pool with lamports, see
+    /// `find_rent_vault_address`. Anyone can fund it.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[writable, signer]` funder account
+    /// 2. `[writable]` rent vault of the pool
+    /// 3. `[]` system program id
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, signer, name = "funder", desc = "Funder account")]
+    #[account(2, writable, name = "rent_vault", desc = "Rent vault of the pool")]
+    #[account(3, name = "system_program", desc = "System program")]
+    FundRentVault(FundData),
+    /// Withdraw lamports from the rent vault of the pool. The vault must keep
+    /// at least its rent exemption or be emptied.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[signer]` pool admin or treasurer account
+    /// 2. `[writable]` rent vault of the pool
+    /// 3. `[writable]` destination of the lamports
+    /// 4. `[]` system program id
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin or treasurer account")]
+    #[account(2, writable, name = "rent_vault", desc = "Rent vault of the pool")]
+    #[account(
+        3,
+        writable,
+        name = "destination",
+        desc = "Destination of the lamports"
+    )]
+    #[account(4, name = "system_program", desc = "System program")]
+    WithdrawRentVault(RentVaultWithdrawData),
+    /// Create the stake user of an owner at its program address with the rent
+    /// paid by the rent vault of the pool, so owners without lamports beyond
+    /// fees can stake. Its rent goes back to the vault when it is closed.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account at its program address
+    /// 2. `[signer]` stake user owner account
+    /// 3. `[writable]` rent vault of the pool
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` system program id
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "stake_user",
+        desc = "Stake user account at its program address"
+    )]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(3, writable, name = "rent_vault", desc = "Rent vault of the pool")]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    #[account(5, name = "system_program", desc = "System program")]
+    CreateSponsoredStakeUser,
+    /// Halt or resume stakes and claims of all pools at once, e.g. in answer
+    /// to a bug affecting every pool. Unstakes without auto-claim stay open.
+    ///
+    /// 0. `[writable]` global config account
+    /// 1. `[signer]` guardian or authority of the global config
+    #[account(0, writable, name = "global_config", desc = "Global config account")]
+    #[account(
+        1,
+        signer,
+        name = "guardian",
+        desc = "Guardian or authority of the global config"
+    )]
+    SetProtocolPause(ProtocolPauseData),
+    /// Delegate a role of the pool admin to a key, the default pubkey to take
+    /// it back. The admin keeps every power and alone hands over the pool or
+    /// changes roles.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetRole(RoleData),
+    /// Set how accrual rounds rewards from then on. Rounding up pays out the
+    /// dust of the pool, claims staying capped by the reward budget.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetRounding(RoundingData),
+    /// Record the emissions of the pool in a report for the current epoch,
+    /// open to anyone once per epoch. Deltas are since the last report of the
+    /// pool, which must be given once there is one, or since the pool was
+    /// created.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` distribution report account of the current epoch, see
+    ///    `find_distribution_report_address`
+    /// 2. `[]` reward token mint
+    /// 3. `[writable, signer]` payer of the rent of the report
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` clock sysvar
+    /// 6. `[]` system program id
+    /// 7. `[]` last distribution report of the pool, if any
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(
+        1,
+        writable,
+        name = "distribution_report",
+        desc = "Distribution report account of the current epoch"
+    )]
+    #[account(2, name = "reward_token_mint", desc = "Reward token mint")]
+    #[account(
+        3,
+        writable,
+        signer,
+        name = "payer",
+        desc = "Payer of the rent of the report"
+    )]
+    #[account(4, name = "rent", desc = "Rent sysvar")]
+    #[account(5, name = "clock", desc = "Clock sysvar")]
+    #[account(6, name = "system_program", desc = "System program")]
+    #[account(
+        7,
+        optional,
+        name = "previous_report",
+        desc = "Last distribution report of the pool"
+    )]
+    FinalizeDistributionReport,
+    /// Set the destinations the claims of a stake user are split between,
+    /// creating its claim split on first use. Shares are in basis points of
+    /// each claim, ordered with empty shares last, and the rest of a claim
+    /// goes to its reward token account. Every claim of the stake user then
+    /// takes the claim split, see `with_claim_split`, and closing the stake
+    /// user closes it, see `with_closed_claim_split`.
+    ///
+    /// 0. `[]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` stake owner account
+    /// 3. `[writable]` claim split account, see `find_claim_split_address`
+    /// 4. `[writable, signer]` payer of the rent of the claim split
+    /// 5. `[]` rent sysvar
+    /// 6. `[]` system program id
+    #[account(0, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(
+        3,
+        writable,
+        name = "claim_split",
+        desc = "Claim split account of the stake user"
+    )]
+    #[account(
+        4,
+        writable,
+        signer,
+        name = "payer",
+        desc = "Payer of the rent of the claim split"
+    )]
+    #[account(5, name = "rent", desc = "Rent sysvar")]
+    #[account(6, name = "system_program", desc = "System program")]
+    SetClaimSplit(ClaimSplitData),
+    /// Set the locks stakers can take, ordered by duration with empty tiers
+    /// last, longer locks earning no lower bonus. Pools with a lock tier are
+    /// locked pools. Running locks keep their end and bonus.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[signer]` pool admin account
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin account")]
+    SetLockTiers(LockTiersData),
+    /// Lock the stake of a stake user for a lock tier of a locked pool from
+    /// now, or extend its running lock without unstaking, optionally moving
+    /// up to a tier with a higher bonus. A running lock can't end sooner or
+    /// drop to a lower bonus. Rewards owed so far accrue under the previous
+    /// lock first. Locked stakes can't be unstaked or merged away until the
+    /// lock ends or the pool is deprecated.
+    ///
+    /// 0. `[writable]` stake pool account
+    /// 1. `[writable]` stake user account
+    /// 2. `[signer]` stake user owner account
+    /// 3. `[]` clock sysvar
+    #[account(0, writable, name = "stake_pool", desc = "Stake pool account")]
+    #[account(1, writable, name = "stake_user", desc = "Stake user account")]
+    #[account(2, signer, name = "stake_owner", desc = "Stake user owner account")]
+    #[account(3, name = "clock", desc = "Clock sysvar")]
+    Relock(RelockData),
 }
 
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct InitData {
     /// bump_seed to generate pool authority
     pub bump_seed: u8,
-    /// Daily reward numerator
+    /// Reward numerator per reward period
     pub reward_numerator: u64,
-    /// Daily reward denominator
+    /// Reward denominator per reward period
     pub reward_denominator: u64,
+    /// Reward period in seconds, hourly, daily or weekly
+    pub reward_period: u64,
+    /// Reject a stake mint with a freeze authority, which could freeze the
+    /// reserve and lock the stakes in
+    pub reject_freeze_authority: bool,
 }
 
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct StakeData {
     /// Amount to stake
     pub amount: u64,
+    /// Also claim the rewards owed, through reward accounts following the
+    /// instruction accounts. Encoded as a trailing byte, omitted when unset.
+    pub auto_claim: bool,
 }
 
-impl InstructionType {
-    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&tag, rest) = input
-            .split_first()
-            .ok_or(CustomError::IncorrectInstruction)?;
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct FundData {
+    /// Amount of reward tokens or lamports to donate
+    pub amount: u64,
+}
 
-        Ok(match tag {
-            0x1 => {
-                let (bump_seed, rest) = unpack_u8(rest)?;
-                let (reward_numerator, rest) = unpack_u64(rest)?;
-                let (reward_denominator, _) = unpack_u64(rest)?;
-                Self::CreatePool(InitData {
-                    bump_seed,
-                    reward_numerator,
-                    reward_denominator,
-                })
-            }
-            0x2 => Self::CreateStakeUser,
-            0x3 => {
-                let (amount, _) = unpack_u64(rest)?;
-                Self::Stake(StakeData { amount })
-            }
-            0x4 => {
-                let (amount, _) = unpack_u64(rest)?;
-                Self::Unstake(StakeData { amount })
-            }
-            0x5 => Self::Claim,
-            0x6 => Self::Refresh,
-            _ => return Err(CustomError::IncorrectInstruction.into()),
-        })
-    }
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SweepData {
+    /// Amount of tokens to sweep
+    pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct DeprecateData {
+    /// Pool replacing the deprecated one, default pubkey if none
+    pub successor: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ClaimBurnData {
+    /// Share of each claim to burn, in basis points
+    pub claim_burn_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct EpochRateData {
+    /// Upcoming epoch the rate applies from
+    pub epoch: u64,
+    /// Reward numerator per reward period
+    pub reward_numerator: u64,
+    /// Reward denominator per reward period
+    pub reward_denominator: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RateData {
+    /// Reward numerator per reward period
+    pub reward_numerator: u64,
+    /// Reward denominator per reward period
+    pub reward_denominator: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct HookProgramData {
+    /// Program called after stakes, unstakes and claims, default if none
+    pub hook_program: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct PriceOracleData {
+    /// Price account of the reward token in the quote currency, default if none
+    pub price_oracle: Pubkey,
+    /// Maximum age in seconds of the price
+    pub max_price_age: u64,
+    /// Maximum confidence interval, in basis points of the price
+    pub max_price_conf_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct StakeMintData {
+    /// Weight of the mint toward accrual, in basis points of the primary
+    /// stake token
+    pub weight_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct PairRatioData {
+    /// Paired tokens per `pair_denominator` primary stake tokens
+    pub pair_numerator: u64,
+    /// Primary stake tokens the numerator applies to
+    pub pair_denominator: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct PairStakeData {
+    /// Amount of the primary stake token, the paired amount follows the ratio
+    pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BoostCollectionData {
+    /// Verified collection whose NFTs boost rewards, default if none
+    pub boost_collection: Pubkey,
+    /// Reward boost, in basis points of the accruing stake
+    pub boost_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TiersData {
+    /// Tiers ordered with empty tiers last
+    pub tiers: [Tier; MAX_TIERS],
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ClaimSplitData {
+    /// Shares ordered with empty shares last
+    pub shares: [ClaimShare; MAX_CLAIM_SHARES],
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct LockTiersData {
+    /// Lock tiers ordered by duration with empty tiers last
+    pub lock_tiers: [LockTier; MAX_LOCK_TIERS],
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RelockData {
+    /// Slot of the lock tier of the pool to lock for
+    pub lock_tier: u8,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BadgeMintData {
+    /// Badge mint, default if none
+    pub badge_mint: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct StreakBonusData {
+    /// Seconds without unstaking before the bonus applies
+    pub streak_duration: u64,
+    /// Bonus on the rewards accrued past the streak duration
+    pub streak_bonus_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct StakeSnapshotData {
+    /// Timestamp to snapshot the stakes at
+    pub timestamp: i64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct StakeSnapshotUpdateData {
+    /// Whether to compute the root after adding the stake users
+    pub finalize: bool,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BonusData {
+    /// Merkle root of the `(owner, amount)` leaves
+    pub root: [u8; 32],
+    /// Number of leaves
+    pub leaf_count: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BonusClaimData {
+    /// Index of the leaf
+    pub index: u64,
+    /// Bonus amount of the leaf
+    pub amount: u64,
+    /// Sibling hashes from the leaf up, at most 32
+    pub proof: Vec<[u8; 32]>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AdminData {
+    /// New pool admin
+    pub admin: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TopLevelOnlyData {
+    /// Reject Stake and Unstake called by other programs
+    pub top_level_only: bool,
+    /// Programs still allowed to call them
+    pub integrators: Vec<Pubkey>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RewardBudgetData {
+    /// Reward tokens added to the budget
+    pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MaxRewardSupplyData {
+    /// Maximum supply of the reward mint, zero for none
+    pub max_reward_supply: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MaxStakePerUserData {
+    /// Maximum weighted stake of each stake user, zero for none
+    pub max_stake_per_user: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct GcDelayData {
+    /// Seconds after which empty stake users can be closed, zero for never
+    pub gc_delay: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RentVaultWithdrawData {
+    /// Lamports to withdraw
+    pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ProtocolPauseData {
+    /// Whether stakes and claims of all pools are halted
+    pub paused: bool,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RoleData {
+    /// Role to delegate, as a `Role`
+    pub role: u8,
+    /// Key holding the role, the default pubkey for none
+    pub holder: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RoundingData {
+    /// Rounding of reward accrual, as a `Rounding`
+    pub rounding: u8,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ReleaseRewardMintData {
+    /// New mint authority of the reward mint, default to revoke it
+    pub new_authority: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct GlobalConfigData {
+    /// Authority updating the config from now on
+    pub authority: Pubkey,
+    /// Protocol treasury collecting the fees
+    pub treasury: Pubkey,
+    /// Lamports paid to the treasury by creating a pool, zero for none
+    pub pool_creation_fee: u64,
+    /// Key pausing and unpausing stakes and claims of all pools
+    pub guardian: Pubkey,
+    /// Claim burn, in basis points, new pools start with
+    pub default_claim_burn_bps: u16,
+    /// Most rewards a pool may accrue per day, in basis points of the stake,
+    /// zero for no cap
+    pub max_daily_reward_bps: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct HarvestAllData {
+    /// Number of accounts of each claim, in order
+    pub account_counts: Vec<u8>,
+}
+
+/// Return data of Refresh and RefreshBatch, for keepers to check what their
+/// transactions did without reading back the stake users
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct RefreshCounts {
+    /// Stake users with stake whose reward owed was updated
+    pub updated: u32,
+    /// Stake users without stake, closed or of other pools
+    pub skipped: u32,
+}
+
+/// Return data of GetPoolInfo. Fields are only ever appended, so callers
+/// should decode it with `BorshDeserialize::deserialize`, which leaves the
+/// fields of newer programs unread.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct PoolInfo {
+    pub stake_token_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    /// How claims deliver reward tokens, as a `RewardMode`
+    pub reward_mode: u8,
+    /// Reward ratio currently applying
+    pub reward_numerator: u64,
+    pub reward_denominator: u64,
+    /// Period in seconds the reward ratio applies to
+    pub reward_period: u64,
+    /// Total primary stake tokens staked
+    pub total_staked: u64,
+    pub user_count: u64,
+    /// Deprecated pools take no new stakes
+    pub deprecated: bool,
+    /// Variant of the pool, as `POOL_FLAG_*` bits
+    pub pool_flags: u32,
+    /// Seconds over which claimed rewards stream, 0 for none
+    pub stream_duration: u64,
+    /// Decimals of the primary stake token and reward token mints
+    pub stake_decimals: u8,
+    pub reward_decimals: u8,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct StreamDurationData {
+    /// Seconds over which claimed rewards stream, 0 for none
+    pub stream_duration: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WarmUpData {
+    /// Seconds deposits accrue at the warm-up rate, 0 for none
+    pub warm_up_duration: u64,
+    /// Rate of deposits warming up, in basis points of the full rate
+    pub warm_up_rate_bps: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RateControllerData {
+    /// Primary stake to steer the rate toward, 0 to disable the controller
+    pub target_tvl: u64,
+    /// Bounds of the reward numerator, over the current reward denominator
+    pub min_reward_numerator: u64,
+    pub max_reward_numerator: u64,
+    /// Largest change of the reward numerator per rebalance
+    pub max_rate_step_bps: u16,
+}
+
+impl InstructionType {
+    /// Whether the instruction stakes or claims, which the guardian of the
+    /// global config can halt. Such instructions end with the global config.
+    pub fn is_pausable(&self) -> bool {
+        matches!(
+            self,
+            Self::Stake(_)
+                | Self::StakeWithDelegate(_)
+                | Self::StakePair(_)
+                | Self::Claim
+                | Self::ClaimIfAny
+                | Self::ClaimFor
+                | Self::HarvestAll(_)
+                | Self::ClaimAndStakeInto
+                | Self::WithdrawFromStream
+                | Self::ClaimBonus(_)
+                | Self::Unstake(StakeData {
+                    auto_claim: true,
+                    ..
+                })
+        )
+    }
+
+    /// Decode the data of an instruction, which must be its canonical
+    /// encoding, the one `pack` produces: payloads of exactly their length
+    /// and trailing flags only present when set
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input
+            .split_first()
+            .ok_or(CustomError::IncorrectInstruction)?;
+
+        let instruction = match tag {
+            0x1 => {
+                let (bump_seed, rest) = unpack_u8(rest)?;
+                let (reward_numerator, rest) = unpack_u64(rest)?;
+                let (reward_denominator, rest) = unpack_u64(rest)?;
+                let (reward_period, rest) = unpack_u64(rest)?;
+                let reject_freeze_authority = unpack_trailing_flag(rest)?;
+                Self::CreatePool(InitData {
+                    bump_seed,
+                    reward_numerator,
+                    reward_denominator,
+                    reward_period,
+                    reject_freeze_authority,
+                })
+            }
+            0x2 => Self::CreateStakeUser,
+            0x3 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let auto_claim = unpack_trailing_flag(rest)?;
+                Self::Stake(StakeData { amount, auto_claim })
+            }
+            0x4 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let auto_claim = unpack_trailing_flag(rest)?;
+                Self::Unstake(StakeData { amount, auto_claim })
+            }
+            0x5 => Self::Claim,
+            0x6 => Self::Refresh,
+            0x7 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::FundRewards(FundData { amount })
+            }
+            0x8 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::Sweep(SweepData { amount })
+            }
+            0x9 => Self::SyncReserve,
+            0xA => {
+                let (successor, _) = unpack_pubkey(rest)?;
+                Self::Deprecate(DeprecateData { successor })
+            }
+            0xB => {
+                let (claim_burn_bps, _) = unpack_u16(rest)?;
+                Self::SetClaimBurn(ClaimBurnData { claim_burn_bps })
+            }
+            0xC => {
+                let (epoch, rest) = unpack_u64(rest)?;
+                let (reward_numerator, rest) = unpack_u64(rest)?;
+                let (reward_denominator, _) = unpack_u64(rest)?;
+                Self::SetEpochRate(EpochRateData {
+                    epoch,
+                    reward_numerator,
+                    reward_denominator,
+                })
+            }
+            0xD => Self::ClaimIfAny,
+            0xE => {
+                let (reward_numerator, rest) = unpack_u64(rest)?;
+                let (reward_denominator, _) = unpack_u64(rest)?;
+                Self::SetRewardRate(RateData {
+                    reward_numerator,
+                    reward_denominator,
+                })
+            }
+            0xF => Self::SnapshotPool,
+            0x10 => Self::VerifyInvariants,
+            0x11 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let auto_claim = unpack_trailing_flag(rest)?;
+                Self::StakeWithDelegate(StakeData { amount, auto_claim })
+            }
+            0x12 => Self::ClaimFor,
+            0x13 => Self::ClaimAndStakeInto,
+            0x14 => {
+                let (hook_program, _) = unpack_pubkey(rest)?;
+                Self::SetHookProgram(HookProgramData { hook_program })
+            }
+            0x15 => {
+                let (price_oracle, rest) = unpack_pubkey(rest)?;
+                let (max_price_age, rest) = unpack_u64(rest)?;
+                let (max_price_conf_bps, _) = unpack_u16(rest)?;
+                Self::SetPriceOracle(PriceOracleData {
+                    price_oracle,
+                    max_price_age,
+                    max_price_conf_bps,
+                })
+            }
+            0x16 => {
+                let (weight_bps, _) = unpack_u16(rest)?;
+                Self::AddStakeMint(StakeMintData { weight_bps })
+            }
+            0x17 => {
+                let (pair_numerator, rest) = unpack_u64(rest)?;
+                let (pair_denominator, _) = unpack_u64(rest)?;
+                Self::SetPairRatio(PairRatioData {
+                    pair_numerator,
+                    pair_denominator,
+                })
+            }
+            0x18 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::StakePair(PairStakeData { amount })
+            }
+            0x19 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::UnstakePair(PairStakeData { amount })
+            }
+            0x1A => {
+                let (boost_collection, rest) = unpack_pubkey(rest)?;
+                let (boost_bps, _) = unpack_u16(rest)?;
+                Self::SetBoostCollection(BoostCollectionData {
+                    boost_collection,
+                    boost_bps,
+                })
+            }
+            0x1B => Self::RegisterBoost,
+            0x1C => Self::UnregisterBoost,
+            0x1D => {
+                let mut tiers = [Tier::default(); MAX_TIERS];
+                let mut rest = rest;
+                for tier in tiers.iter_mut() {
+                    let (min_stake, next) = unpack_u64(rest)?;
+                    let (min_duration, next) = unpack_u64(next)?;
+                    *tier = Tier {
+                        min_stake,
+                        min_duration,
+                    };
+                    rest = next;
+                }
+                Self::SetTiers(TiersData { tiers })
+            }
+            0x1E => {
+                let (badge_mint, _) = unpack_pubkey(rest)?;
+                Self::SetBadgeMint(BadgeMintData { badge_mint })
+            }
+            0x1F => Self::MintBadge,
+            0x20 => {
+                let (streak_duration, rest) = unpack_u64(rest)?;
+                let (streak_bonus_bps, _) = unpack_u16(rest)?;
+                Self::SetStreakBonus(StreakBonusData {
+                    streak_duration,
+                    streak_bonus_bps,
+                })
+            }
+            0x21 => Self::CloseStakeUser,
+            0x22 => {
+                let (timestamp, _) = unpack_u64(rest)?;
+                Self::CreateStakeSnapshot(StakeSnapshotData {
+                    timestamp: timestamp as i64,
+                })
+            }
+            0x23 => {
+                let finalize = unpack_trailing_flag(rest)?;
+                Self::UpdateStakeSnapshot(StakeSnapshotUpdateData { finalize })
+            }
+            0x24 => {
+                let (root, rest) = unpack_pubkey(rest)?;
+                let (leaf_count, _) = unpack_u64(rest)?;
+                Self::PublishBonus(BonusData {
+                    root: root.to_bytes(),
+                    leaf_count,
+                })
+            }
+            0x25 => {
+                let (index, rest) = unpack_u64(rest)?;
+                let (amount, rest) = unpack_u64(rest)?;
+                let (proof_len, mut rest) = unpack_u8(rest)?;
+                if proof_len > 32 {
+                    return Err(CustomError::InstructionUnpackError.into());
+                }
+                let mut proof = Vec::with_capacity(proof_len as usize);
+                for _ in 0..proof_len {
+                    let (node, next) = unpack_pubkey(rest)?;
+                    proof.push(node.to_bytes());
+                    rest = next;
+                }
+                Self::ClaimBonus(BonusClaimData {
+                    index,
+                    amount,
+                    proof,
+                })
+            }
+            0x26 => {
+                let (admin, _) = unpack_pubkey(rest)?;
+                Self::SetAdmin(AdminData { admin })
+            }
+            0x27 => Self::RefreshBatch,
+            0x28 => {
+                let (top_level_only, rest) = unpack_u8(rest)?;
+                let (count, mut rest) = unpack_u8(rest)?;
+                if top_level_only > 1 || count as usize > MAX_INTEGRATORS {
+                    return Err(CustomError::InstructionUnpackError.into());
+                }
+                let mut integrators = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (integrator, next) = unpack_pubkey(rest)?;
+                    integrators.push(integrator);
+                    rest = next;
+                }
+                Self::SetTopLevelOnly(TopLevelOnlyData {
+                    top_level_only: top_level_only == 1,
+                    integrators,
+                })
+            }
+            0x29 => Self::RepairUser,
+            0x2A => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::AddRewardBudget(RewardBudgetData { amount })
+            }
+            0x2B => {
+                let (max_reward_supply, _) = unpack_u64(rest)?;
+                Self::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply })
+            }
+            0x2C => {
+                let (new_authority, _) = unpack_pubkey(rest)?;
+                Self::ReleaseRewardMint(ReleaseRewardMintData { new_authority })
+            }
+            0x2D => {
+                let (authority, rest) = unpack_pubkey(rest)?;
+                let (treasury, rest) = unpack_pubkey(rest)?;
+                let (pool_creation_fee, rest) = unpack_u64(rest)?;
+                let (guardian, rest) = unpack_pubkey(rest)?;
+                let (default_claim_burn_bps, rest) = unpack_u16(rest)?;
+                let (max_daily_reward_bps, _) = unpack_u64(rest)?;
+                Self::SetGlobalConfig(GlobalConfigData {
+                    authority,
+                    treasury,
+                    pool_creation_fee,
+                    guardian,
+                    default_claim_burn_bps,
+                    max_daily_reward_bps,
+                })
+            }
+            0x2E => Self::CreateStakeUserIdempotent,
+            0x2F => {
+                let (count, rest) = unpack_u8(rest)?;
+                let count = usize::from(count);
+                if count == 0 || count > MAX_HARVEST_CLAIMS || rest.len() < count {
+                    return Err(CustomError::InstructionUnpackError.into());
+                }
+                Self::HarvestAll(HarvestAllData {
+                    account_counts: rest[..count].to_vec(),
+                })
+            }
+            0x30 => Self::GetPoolInfo,
+            0x31 => {
+                let (stream_duration, _) = unpack_u64(rest)?;
+                Self::SetStreamDuration(StreamDurationData { stream_duration })
+            }
+            0x32 => Self::WithdrawFromStream,
+            0x33 => {
+                let (warm_up_duration, rest) = unpack_u64(rest)?;
+                let (warm_up_rate_bps, _) = unpack_u16(rest)?;
+                Self::SetWarmUp(WarmUpData {
+                    warm_up_duration,
+                    warm_up_rate_bps,
+                })
+            }
+            0x34 => {
+                let (target_tvl, rest) = unpack_u64(rest)?;
+                let (min_reward_numerator, rest) = unpack_u64(rest)?;
+                let (max_reward_numerator, rest) = unpack_u64(rest)?;
+                let (max_rate_step_bps, _) = unpack_u16(rest)?;
+                Self::SetRateController(RateControllerData {
+                    target_tvl,
+                    min_reward_numerator,
+                    max_reward_numerator,
+                    max_rate_step_bps,
+                })
+            }
+            0x35 => Self::RebalanceRate,
+            0x36 => Self::MergePositions,
+            0x37 => {
+                let (max_stake_per_user, _) = unpack_u64(rest)?;
+                Self::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user })
+            }
+            0x38 => {
+                let (gc_delay, _) = unpack_u64(rest)?;
+                Self::SetGcDelay(GcDelayData { gc_delay })
+            }
+            0x39 => Self::GcUser,
+            0x3A => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::FundRentVault(FundData { amount })
+            }
+            0x3B => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::WithdrawRentVault(RentVaultWithdrawData { amount })
+            }
+            0x3C => Self::CreateSponsoredStakeUser,
+            0x3D => {
+                let (paused, _) = unpack_u8(rest)?;
+                if paused > 1 {
+                    return Err(CustomError::InstructionUnpackError.into());
+                }
+                Self::SetProtocolPause(ProtocolPauseData {
+                    paused: paused == 1,
+                })
+            }
+            0x3E => {
+                let (role, rest) = unpack_u8(rest)?;
+                let (holder, _) = unpack_pubkey(rest)?;
+                Self::SetRole(RoleData { role, holder })
+            }
+            0x3F => {
+                let (rounding, _) = unpack_u8(rest)?;
+                Self::SetRounding(RoundingData { rounding })
+            }
+            0x40 => Self::FinalizeDistributionReport,
+            0x41 => {
+                let mut shares = [ClaimShare::default(); MAX_CLAIM_SHARES];
+                let mut rest = rest;
+                for share in shares.iter_mut() {
+                    let (destination, next) = unpack_pubkey(rest)?;
+                    let (bps, next) = unpack_u16(next)?;
+                    *share = ClaimShare { destination, bps };
+                    rest = next;
+                }
+                Self::SetClaimSplit(ClaimSplitData { shares })
+            }
+            0x42 => {
+                let mut lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+                let mut rest = rest;
+                for lock_tier in lock_tiers.iter_mut() {
+                    let (duration, next) = unpack_u64(rest)?;
+                    let (bonus_bps, next) = unpack_u16(next)?;
+                    *lock_tier = LockTier {
+                        duration,
+                        bonus_bps,
+                    };
+                    rest = next;
+                }
+                Self::SetLockTiers(LockTiersData { lock_tiers })
+            }
+            0x43 => {
+                let (lock_tier, _) = unpack_u8(rest)?;
+                Self::Relock(RelockData { lock_tier })
+            }
+            _ => return Err(CustomError::IncorrectInstruction.into()),
+        };
+        // clients across languages agree byte for byte on one encoding
+        if instruction.pack() != input {
+            return Err(CustomError::InstructionUnpackError.into());
+        }
+        Ok(instruction)
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match *self {
+            Self::CreatePool(InitData {
+                bump_seed,
+                reward_numerator,
+                reward_denominator,
+                reward_period,
+                reject_freeze_authority,
+            }) => {
+                buf.push(0x1);
+                buf.extend_from_slice(&bump_seed.to_le_bytes());
+                buf.extend_from_slice(&reward_numerator.to_le_bytes());
+                buf.extend_from_slice(&reward_denominator.to_le_bytes());
+                buf.extend_from_slice(&reward_period.to_le_bytes());
+                if reject_freeze_authority {
+                    buf.push(1);
+                }
+            }
+            Self::CreateStakeUser => {
+                buf.push(0x2);
+            }
+            Self::Stake(StakeData { amount, auto_claim }) => {
+                buf.push(0x3);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                if auto_claim {
+                    buf.push(1);
+                }
+            }
+            Self::Unstake(StakeData { amount, auto_claim }) => {
+                buf.push(0x4);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                if auto_claim {
+                    buf.push(1);
+                }
+            }
+            Self::Claim => {
+                buf.push(0x5);
+            }
+            Self::Refresh => {
+                buf.push(0x6);
+            }
+            Self::FundRewards(FundData { amount }) => {
+                buf.push(0x7);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Sweep(SweepData { amount }) => {
+                buf.push(0x8);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SyncReserve => {
+                buf.push(0x9);
+            }
+            Self::Deprecate(DeprecateData { successor }) => {
+                buf.push(0xA);
+                buf.extend_from_slice(successor.as_ref());
+            }
+            Self::SetClaimBurn(ClaimBurnData { claim_burn_bps }) => {
+                buf.push(0xB);
+                buf.extend_from_slice(&claim_burn_bps.to_le_bytes());
+            }
+            Self::SetEpochRate(EpochRateData {
+                epoch,
+                reward_numerator,
+                reward_denominator,
+            }) => {
+                buf.push(0xC);
+                buf.extend_from_slice(&epoch.to_le_bytes());
+                buf.extend_from_slice(&reward_numerator.to_le_bytes());
+                buf.extend_from_slice(&reward_denominator.to_le_bytes());
+            }
+            Self::ClaimIfAny => {
+                buf.push(0xD);
+            }
+            Self::SetRewardRate(RateData {
+                reward_numerator,
+                reward_denominator,
+            }) => {
+                buf.push(0xE);
+                buf.extend_from_slice(&reward_numerator.to_le_bytes());
+                buf.extend_from_slice(&reward_denominator.to_le_bytes());
+            }
+            Self::SnapshotPool => {
+                buf.push(0xF);
+            }
+            Self::VerifyInvariants => {
+                buf.push(0x10);
+            }
+            Self::StakeWithDelegate(StakeData { amount, auto_claim }) => {
+                buf.push(0x11);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                if auto_claim {
+                    buf.push(1);
+                }
+            }
+            Self::ClaimFor => {
+                buf.push(0x12);
+            }
+            Self::ClaimAndStakeInto => {
+                buf.push(0x13);
+            }
+            Self::SetHookProgram(HookProgramData { hook_program }) => {
+                buf.push(0x14);
+                buf.extend_from_slice(hook_program.as_ref());
+            }
+            Self::SetPriceOracle(PriceOracleData {
+                price_oracle,
+                max_price_age,
+                max_price_conf_bps,
+            }) => {
+                buf.push(0x15);
+                buf.extend_from_slice(price_oracle.as_ref());
+                buf.extend_from_slice(&max_price_age.to_le_bytes());
+                buf.extend_from_slice(&max_price_conf_bps.to_le_bytes());
+            }
+            Self::AddStakeMint(StakeMintData { weight_bps }) => {
+                buf.push(0x16);
+                buf.extend_from_slice(&weight_bps.to_le_bytes());
+            }
+            Self::SetPairRatio(PairRatioData {
+                pair_numerator,
+                pair_denominator,
+            }) => {
+                buf.push(0x17);
+                buf.extend_from_slice(&pair_numerator.to_le_bytes());
+                buf.extend_from_slice(&pair_denominator.to_le_bytes());
+            }
+            Self::StakePair(PairStakeData { amount }) => {
+                buf.push(0x18);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::UnstakePair(PairStakeData { amount }) => {
+                buf.push(0x19);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetBoostCollection(BoostCollectionData {
+                boost_collection,
+                boost_bps,
+            }) => {
+                buf.push(0x1A);
+                buf.extend_from_slice(boost_collection.as_ref());
+                buf.extend_from_slice(&boost_bps.to_le_bytes());
+            }
+            Self::RegisterBoost => buf.push(0x1B),
+            Self::UnregisterBoost => buf.push(0x1C),
+            Self::SetTiers(TiersData { tiers }) => {
+                buf.push(0x1D);
+                for tier in tiers.iter() {
+                    buf.extend_from_slice(&tier.min_stake.to_le_bytes());
+                    buf.extend_from_slice(&tier.min_duration.to_le_bytes());
+                }
+            }
+            Self::SetBadgeMint(BadgeMintData { badge_mint }) => {
+                buf.push(0x1E);
+                buf.extend_from_slice(badge_mint.as_ref());
+            }
+            Self::MintBadge => buf.push(0x1F),
+            Self::SetStreakBonus(StreakBonusData {
+                streak_duration,
+                streak_bonus_bps,
+            }) => {
+                buf.push(0x20);
+                buf.extend_from_slice(&streak_duration.to_le_bytes());
+                buf.extend_from_slice(&streak_bonus_bps.to_le_bytes());
+            }
+            Self::CloseStakeUser => buf.push(0x21),
+            Self::CreateStakeSnapshot(StakeSnapshotData { timestamp }) => {
+                buf.push(0x22);
+                buf.extend_from_slice(&timestamp.to_le_bytes());
+            }
+            Self::UpdateStakeSnapshot(StakeSnapshotUpdateData { finalize }) => {
+                buf.push(0x23);
+                buf.push(finalize as u8);
+            }
+            Self::PublishBonus(BonusData { root, leaf_count }) => {
+                buf.push(0x24);
+                buf.extend_from_slice(&root);
+                buf.extend_from_slice(&leaf_count.to_le_bytes());
+            }
+            Self::ClaimBonus(BonusClaimData {
+                index,
+                amount,
+                ref proof,
+            }) => {
+                buf.push(0x25);
+                buf.extend_from_slice(&index.to_le_bytes());
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.push(proof.len() as u8);
+                for node in proof.iter() {
+                    buf.extend_from_slice(node);
+                }
+            }
+            Self::SetAdmin(AdminData { admin }) => {
+                buf.push(0x26);
+                buf.extend_from_slice(admin.as_ref());
+            }
+            Self::RefreshBatch => {
+                buf.push(0x27);
+            }
+            Self::SetTopLevelOnly(TopLevelOnlyData {
+                top_level_only,
+                ref integrators,
+            }) => {
+                buf.push(0x28);
+                buf.push(top_level_only as u8);
+                buf.push(integrators.len() as u8);
+                for integrator in integrators.iter() {
+                    buf.extend_from_slice(integrator.as_ref());
+                }
+            }
+            Self::RepairUser => {
+                buf.push(0x29);
+            }
+            Self::AddRewardBudget(RewardBudgetData { amount }) => {
+                buf.push(0x2A);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply }) => {
+                buf.push(0x2B);
+                buf.extend_from_slice(&max_reward_supply.to_le_bytes());
+            }
+            Self::ReleaseRewardMint(ReleaseRewardMintData { new_authority }) => {
+                buf.push(0x2C);
+                buf.extend_from_slice(new_authority.as_ref());
+            }
+            Self::SetGlobalConfig(GlobalConfigData {
+                authority,
+                treasury,
+                pool_creation_fee,
+                guardian,
+                default_claim_burn_bps,
+                max_daily_reward_bps,
+            }) => {
+                buf.push(0x2D);
+                buf.extend_from_slice(authority.as_ref());
+                buf.extend_from_slice(treasury.as_ref());
+                buf.extend_from_slice(&pool_creation_fee.to_le_bytes());
+                buf.extend_from_slice(guardian.as_ref());
+                buf.extend_from_slice(&default_claim_burn_bps.to_le_bytes());
+                buf.extend_from_slice(&max_daily_reward_bps.to_le_bytes());
+            }
+            Self::CreateStakeUserIdempotent => {
+                buf.push(0x2E);
+            }
+            Self::HarvestAll(HarvestAllData { ref account_counts }) => {
+                buf.push(0x2F);
+                buf.push(account_counts.len() as u8);
+                buf.extend_from_slice(account_counts);
+            }
+            Self::GetPoolInfo => {
+                buf.push(0x30);
+            }
+            Self::SetStreamDuration(StreamDurationData { stream_duration }) => {
+                buf.push(0x31);
+                buf.extend_from_slice(&stream_duration.to_le_bytes());
+            }
+            Self::WithdrawFromStream => {
+                buf.push(0x32);
+            }
+            Self::SetWarmUp(WarmUpData {
+                warm_up_duration,
+                warm_up_rate_bps,
+            }) => {
+                buf.push(0x33);
+                buf.extend_from_slice(&warm_up_duration.to_le_bytes());
+                buf.extend_from_slice(&warm_up_rate_bps.to_le_bytes());
+            }
+            Self::SetRateController(RateControllerData {
+                target_tvl,
+                min_reward_numerator,
+                max_reward_numerator,
+                max_rate_step_bps,
+            }) => {
+                buf.push(0x34);
+                buf.extend_from_slice(&target_tvl.to_le_bytes());
+                buf.extend_from_slice(&min_reward_numerator.to_le_bytes());
+                buf.extend_from_slice(&max_reward_numerator.to_le_bytes());
+                buf.extend_from_slice(&max_rate_step_bps.to_le_bytes());
+            }
+            Self::RebalanceRate => {
+                buf.push(0x35);
+            }
+            Self::MergePositions => {
+                buf.push(0x36);
+            }
+            Self::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user }) => {
+                buf.push(0x37);
+                buf.extend_from_slice(&max_stake_per_user.to_le_bytes());
+            }
+            Self::SetGcDelay(GcDelayData { gc_delay }) => {
+                buf.push(0x38);
+                buf.extend_from_slice(&gc_delay.to_le_bytes());
+            }
+            Self::GcUser => {
+                buf.push(0x39);
+            }
+            Self::FundRentVault(FundData { amount }) => {
+                buf.push(0x3A);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::WithdrawRentVault(RentVaultWithdrawData { amount }) => {
+                buf.push(0x3B);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::CreateSponsoredStakeUser => {
+                buf.push(0x3C);
+            }
+            Self::SetProtocolPause(ProtocolPauseData { paused }) => {
+                buf.push(0x3D);
+                buf.push(paused as u8);
+            }
+            Self::SetRole(RoleData { role, holder }) => {
+                buf.push(0x3E);
+                buf.push(role);
+                buf.extend_from_slice(holder.as_ref());
+            }
+            Self::SetRounding(RoundingData { rounding }) => {
+                buf.push(0x3F);
+                buf.push(rounding);
+            }
+            Self::FinalizeDistributionReport => {
+                buf.push(0x40);
+            }
+            Self::SetClaimSplit(ClaimSplitData { shares }) => {
+                buf.push(0x41);
+                for share in shares.iter() {
+                    buf.extend_from_slice(share.destination.as_ref());
+                    buf.extend_from_slice(&share.bps.to_le_bytes());
+                }
+            }
+            Self::SetLockTiers(LockTiersData { lock_tiers }) => {
+                buf.push(0x42);
+                for lock_tier in lock_tiers.iter() {
+                    buf.extend_from_slice(&lock_tier.duration.to_le_bytes());
+                    buf.extend_from_slice(&lock_tier.bonus_bps.to_le_bytes());
+                }
+            }
+            Self::Relock(RelockData { lock_tier }) => {
+                buf.push(0x43);
+                buf.push(lock_tier);
+            }
+        }
+        buf
+    }
+}
+
+/// Encode a v2 instruction payload after its tag
+pub fn pack_v2<T: BorshSerialize>(tag: u8, payload: &T) -> Result<Vec<u8>, ProgramError> {
+    if tag < V2_TAG_START {
+        return Err(CustomError::IncorrectInstruction.into());
+    }
+    let payload = borsh::to_vec(payload).map_err(|_| CustomError::InstructionUnpackError)?;
+    let len = u16::try_from(payload.len()).map_err(|_| CustomError::InstructionUnpackError)?;
+
+    let mut buf = Vec::with_capacity(1 + 2 + payload.len());
+    buf.push(tag);
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&payload);
+    Ok(buf)
+}
+
+/// Decode a v2 instruction payload following its tag, skipping trailing
+/// fields of newer clients
+pub fn unpack_v2<T: BorshDeserialize>(input: &[u8]) -> Result<T, ProgramError> {
+    let (len, rest) = unpack_u16(input)?;
+    if rest.len() != usize::from(len) {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    T::deserialize(&mut &rest[..]).map_err(|_| CustomError::InstructionUnpackError.into())
+}
+
+fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+    if input.is_empty() {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    let (bytes, rest) = input.split_at(1);
+    let value = bytes
+        .get(..1)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u8::from_le_bytes)
+        .ok_or(CustomError::InstructionUnpackError)?;
+    Ok((value, rest))
+}
+
+/// Optional trailing flag byte, unset when absent for clients predating it
+fn unpack_trailing_flag(input: &[u8]) -> Result<bool, ProgramError> {
+    match input.first() {
+        None | Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        Some(_) => Err(CustomError::InstructionUnpackError.into()),
+    }
+}
+
+fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+    if input.len() < 2 {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    let (value, rest) = input.split_at(2);
+    let value = value
+        .get(..2)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or(CustomError::InstructionUnpackError)?;
+    Ok((value, rest))
+}
+
+fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    if input.len() < 8 {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    let (amount, rest) = input.split_at(8);
+    let amount = amount
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(CustomError::InstructionUnpackError)?;
+    Ok((amount, rest))
+}
+
+fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+    if input.len() < PUBKEY_BYTES {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    let (key, rest) = input.split_at(PUBKEY_BYTES);
+    let key = key
+        .try_into()
+        .map(Pubkey::new_from_array)
+        .map_err(|_| CustomError::InstructionUnpackError)?;
+    Ok((key, rest))
+}
+
+pub fn create_stake_pool(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool_authority_pubkey: Pubkey,
+    staking_token_mint_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    treasury_pubkey: Pubkey,
+    init_data: InitData,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CreatePool(init_data).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(stake_pool_authority_pubkey, false),
+        AccountMeta::new_readonly(staking_token_mint_pubkey, false),
+        AccountMeta::new(staking_token_reserve_pubkey, false),
+        AccountMeta::new(reward_token_mint_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(payer_pubkey, true),
+        AccountMeta::new_readonly(find_global_config_address(&program_id).0, false),
+        AccountMeta::new(treasury_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn create_vault_funded_stake_pool(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool_authority_pubkey: Pubkey,
+    staking_token_mint_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    reward_token_vault_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    treasury_pubkey: Pubkey,
+    init_data: InitData,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = create_stake_pool(
+        program_id,
+        stake_pool_pubkey,
+        stake_pool_authority_pubkey,
+        staking_token_mint_pubkey,
+        staking_token_reserve_pubkey,
+        reward_token_mint_pubkey,
+        admin_pubkey,
+        payer_pubkey,
+        treasury_pubkey,
+        init_data,
+    )?;
+    instruction
+        .accounts
+        .push(AccountMeta::new(reward_token_vault_pubkey, false));
+
+    Ok(instruction)
+}
+
+/// Create the pool, reserve and reward mint accounts rent-exempt, then the
+/// stake pool. The new accounts sign along with the payer and the admin.
+pub fn create_pool_with_accounts(
+    program_id: Pubkey,
+    payer_pubkey: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool_authority_pubkey: Pubkey,
+    staking_token_mint_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    treasury_pubkey: Pubkey,
+    init_data: InitData,
+    rent: &Rent,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(vec![
+        system_instruction::create_account(
+            &payer_pubkey,
+            &stake_pool_pubkey,
+            rent.minimum_balance(Pool::LEN),
+            Pool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer_pubkey,
+            &staking_token_reserve_pubkey,
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ),
+        system_instruction::create_account(
+            &payer_pubkey,
+            &reward_token_mint_pubkey,
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        create_stake_pool(
+            program_id,
+            stake_pool_pubkey,
+            stake_pool_authority_pubkey,
+            staking_token_mint_pubkey,
+            staking_token_reserve_pubkey,
+            reward_token_mint_pubkey,
+            admin_pubkey,
+            payer_pubkey,
+            treasury_pubkey,
+            init_data,
+        )?,
+    ])
+}
+
+pub fn create_stake_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CreateStakeUser.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn create_stake_user_idempotent(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CreateStakeUserIdempotent.pack();
+    let (stake_user_pubkey, _) =
+        find_stake_user_address(&program_id, &stake_pool_pubkey, &stake_owner_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, false),
+        AccountMeta::new(payer_pubkey, true),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Bundle claims of the same owner from several pools into one HarvestAll.
+/// The claims are built like `ClaimIfAny`, with any optional and hook
+/// accounts added.
+pub fn harvest_all(
+    program_id: Pubkey,
+    claims: Vec<Instruction>,
+) -> Result<Instruction, ProgramError> {
+    if claims.is_empty() || claims.len() > MAX_HARVEST_CLAIMS {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    let mut account_counts = Vec::with_capacity(claims.len());
+    let mut accounts = vec![];
+    for claim in claims {
+        let count =
+            u8::try_from(claim.accounts.len()).map_err(|_| CustomError::InstructionUnpackError)?;
+        account_counts.push(count);
+        accounts.extend(claim.accounts);
+    }
+    let data = InstructionType::HarvestAll(HarvestAllData { account_counts }).pack();
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn get_pool_info(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::GetPoolInfo.pack();
+
+    let accounts = vec![AccountMeta::new_readonly(stake_pool_pubkey, false)];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_stream_duration(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    stream_duration: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetStreamDuration(StreamDurationData { stream_duration }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn withdraw_from_stream(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::WithdrawFromStream.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_token_mint_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_warm_up(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    warm_up_duration: u64,
+    warm_up_rate_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetWarmUp(WarmUpData {
+        warm_up_duration,
+        warm_up_rate_bps,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_rate_controller(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    rate_controller_data: RateControllerData,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetRateController(rate_controller_data).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new_readonly(find_global_config_address(&program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn rebalance_rate(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::RebalanceRate.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn close_stake_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CloseStakeUser.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(destination_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_gc_delay(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    gc_delay: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetGcDelay(GcDelayData { gc_delay }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn gc_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    treasury_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::GcUser.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(find_global_config_address(&program_id).0, false),
+        AccountMeta::new(treasury_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn fund_rent_vault(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    funder_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::FundRentVault(FundData { amount }).pack();
+    let (rent_vault_pubkey, _) = find_rent_vault_address(&program_id, &stake_pool_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(funder_pubkey, true),
+        AccountMeta::new(rent_vault_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn withdraw_rent_vault(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::WithdrawRentVault(RentVaultWithdrawData { amount }).pack();
+    let (rent_vault_pubkey, _) = find_rent_vault_address(&program_id, &stake_pool_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(rent_vault_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn create_sponsored_stake_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CreateSponsoredStakeUser.pack();
+    let (stake_user_pubkey, _) =
+        find_stake_user_address(&program_id, &stake_pool_pubkey, &stake_owner_pubkey);
+    let (rent_vault_pubkey, _) = find_rent_vault_address(&program_id, &stake_pool_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(rent_vault_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn merge_positions(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    source_stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::MergePositions.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new(source_stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Create the stake user account rent-exempt, then the stake user. The stake
+/// user account signs along with the payer and the owner.
+pub fn create_stake_user_with_account(
+    program_id: Pubkey,
+    payer_pubkey: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    rent: &Rent,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(vec![
+        system_instruction::create_account(
+            &payer_pubkey,
+            &stake_user_pubkey,
+            rent.minimum_balance(StakeUser::LEN),
+            StakeUser::LEN as u64,
+            &program_id,
+        ),
+        create_stake_user(
+            program_id,
+            stake_pool_pubkey,
+            stake_user_pubkey,
+            stake_owner_pubkey,
+        )?,
+    ])
+}
+
+/// Stake tokens of a mint the pool accepts, into the reserve of the mint
+/// taken from the pool
+pub fn stake(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool: &Pool,
+    stake_user_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    stake_mint_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let destination_pubkey = stake_pool
+        .reserve_of(&stake_mint_pubkey)
+        .ok_or(CustomError::InvalidTokenMint)?;
+    let data = InstructionType::Stake(StakeData {
+        amount,
+        auto_claim: false,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Stake tokens of a mint the pool accepts as the delegate of the source,
+/// into the reserve of the mint taken from the pool
+pub fn stake_with_delegate(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool: &Pool,
+    stake_user_pubkey: Pubkey,
+    delegate_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    stake_mint_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let destination_pubkey = stake_pool
+        .reserve_of(&stake_mint_pubkey)
+        .ok_or(CustomError::InvalidTokenMint)?;
+    let data = InstructionType::StakeWithDelegate(StakeData {
+        amount,
+        auto_claim: false,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(delegate_pubkey, true),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn unstake(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::Unstake(StakeData {
+        amount,
+        auto_claim: false,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(staking_token_reserve_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Turn a `Stake` or `Unstake` instruction into one also claiming the rewards
+/// owed to `destination_pubkey`. `burn_mint_pubkey` is the reward token mint
+/// of vault-funded pools burning on claim or on Token-2022.
+pub fn with_auto_claim(
+    mut instruction: Instruction,
+    authority_pubkey: Pubkey,
+    reward_source_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    burn_mint_pubkey: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    instruction.data = match InstructionType::unpack(&instruction.data)? {
+        InstructionType::Stake(StakeData { amount, .. }) => InstructionType::Stake(StakeData {
+            amount,
+            auto_claim: true,
+        }),
+        InstructionType::Unstake(StakeData { amount, .. }) => InstructionType::Unstake(StakeData {
+            amount,
+            auto_claim: true,
+        }),
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    }
+    .pack();
+
+    instruction.accounts.extend([
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+    ]);
+    if let Some(burn_mint_pubkey) = burn_mint_pubkey {
+        instruction
+            .accounts
+            .push(AccountMeta::new(burn_mint_pubkey, false));
+    }
+    Ok(instruction)
+}
+
+/// Append the hook program of the pool and the accounts it expects to a
+/// `Stake`, `Unstake` or claim instruction, after any auto-claim accounts and
+/// the stake mint with its transfer hook accounts.
+pub fn with_hook(
+    mut instruction: Instruction,
+    hook_program_id: Pubkey,
+    hook_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(hook_program_id, false));
+    instruction.accounts.extend(hook_accounts);
+    instruction
+}
+
+/// Turn a `Stake`, `StakeWithDelegate` or `Unstake` into one on a Token-2022
+/// pool, appending the stake mint transferred and the accounts of its
+/// transfer hook, if any, before any hook accounts
+pub fn with_stake_mint(
+    mut instruction: Instruction,
+    stake_mint_pubkey: Pubkey,
+    transfer_hook_accounts: Vec<AccountMeta>,
+) -> Result<Instruction, ProgramError> {
+    match InstructionType::unpack(&instruction.data)? {
+        InstructionType::Stake(_)
+        | InstructionType::StakeWithDelegate(_)
+        | InstructionType::Unstake(_) => {}
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    }
+    for meta in &mut instruction.accounts {
+        if meta.pubkey == spl_token::id() {
+            meta.pubkey = spl_token_2022::id();
+        }
+    }
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(stake_mint_pubkey, false));
+    instruction.accounts.extend(transfer_hook_accounts);
+    Ok(instruction)
+}
+
+/// Append the instructions sysvar that Stake and Unstake of top-level-only
+/// pools, their paired variants and ClaimAndStakeInto into such pools check
+/// their caller with, after any auto-claim and hook accounts
+pub fn with_instructions_sysvar(mut instruction: Instruction) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(instructions::id(), false));
+    instruction
+}
+
+/// Append the global config that stakes and claims check the protocol pause
+/// of, see `InstructionType::is_pausable`. It is their last account, after
+/// the instructions sysvar of top-level-only pools.
+pub fn with_global_config(program_id: Pubkey, mut instruction: Instruction) -> Instruction {
+    instruction.accounts.push(AccountMeta::new_readonly(
+        find_global_config_address(&program_id).0,
+        false,
+    ));
+    instruction
+}
+
+/// Append the price account of an oracle-priced pool to a claim instruction,
+/// or to a `Stake` or `Unstake` with auto-claim, ahead of any burn mint.
+/// Claims take it last, so it goes on before a burn mint is appended.
+pub fn with_price_oracle(
+    mut instruction: Instruction,
+    price_oracle_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let position = match InstructionType::unpack(&instruction.data)? {
+        InstructionType::Stake(StakeData {
+            auto_claim: true, ..
+        })
+        | InstructionType::Unstake(StakeData {
+            auto_claim: true, ..
+        }) => 11,
+        InstructionType::Claim
+        | InstructionType::ClaimIfAny
+        | InstructionType::ClaimFor
+        | InstructionType::ClaimAndStakeInto => instruction.accounts.len(),
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    };
+    if position > instruction.accounts.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    instruction.accounts.insert(
+        position,
+        AccountMeta::new_readonly(price_oracle_pubkey, false),
+    );
+    Ok(instruction)
+}
+
+/// Point a `Claim`, `ClaimIfAny`, `WithdrawFromStream` or `ClaimBonus` at the
+/// token program of a Token-2022 reward mint, which owns its reward accounts.
+/// `ClaimFor` pays out to the spl-token associated account only.
+pub fn with_reward_token_program(
+    mut instruction: Instruction,
+    token_program_id: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    match InstructionType::unpack(&instruction.data)? {
+        InstructionType::Claim
+        | InstructionType::ClaimIfAny
+        | InstructionType::WithdrawFromStream
+        | InstructionType::ClaimBonus(_) => {}
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    }
+    for meta in &mut instruction.accounts {
+        if meta.pubkey == spl_token::id() {
+            meta.pubkey = token_program_id;
+        }
+    }
+    Ok(instruction)
+}
+
+pub fn claim(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::Claim.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_token_mint_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn claim_if_any(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::ClaimIfAny.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_token_mint_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Claim for the stake user owner into their associated reward token account
+pub fn claim_for(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_source_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::ClaimFor.pack();
+    let (destination_pubkey, _) = find_associated_token_address(
+        &stake_owner_pubkey,
+        &reward_token_mint_pubkey,
+        &spl_token::id(),
+    );
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn claim_and_stake_into(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    target_pool_pubkey: Pubkey,
+    target_stake_user_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_source_pubkey: Pubkey,
+    target_reserve_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::ClaimAndStakeInto.pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(target_pool_pubkey, false),
+        AccountMeta::new(target_stake_user_pubkey, false),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_source_pubkey, false),
+        AccountMeta::new(target_reserve_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn refresh(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkeys: Vec<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::Refresh.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    accounts.extend(
+        stake_user_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new(pubkey, false)),
+    );
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Refresh batch of stake users, sorted and deduplicated as the program
+/// expects
+pub fn refresh_batch(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    mut stake_user_pubkeys: Vec<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    stake_user_pubkeys.sort();
+    stake_user_pubkeys.dedup();
+    if stake_user_pubkeys.len() > MAX_REFRESH_BATCH {
+        return Err(CustomError::InvalidRefreshBatch.into());
+    }
+    let data = InstructionType::RefreshBatch.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    accounts.extend(
+        stake_user_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new(pubkey, false)),
+    );
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn fund_rewards(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    funder_pubkey: Pubkey,
+    reward_token_vault_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::FundRewards(FundData { amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(funder_pubkey, true),
+        AccountMeta::new(reward_token_vault_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(source_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn fund_fee_vault(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    funder_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::FundRewards(FundData { amount }).pack();
+    let (fee_vault_pubkey, _) = find_fee_vault_address(&program_id, &stake_pool_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(funder_pubkey, true),
+        AccountMeta::new(fee_vault_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn sweep(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::Sweep(SweepData { amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn sync_reserve(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    token_program_id: Pubkey,
+    stake_user_pubkey: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SyncReserve.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new_readonly(staking_token_reserve_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(token_program_id, false),
+    ];
+    if let Some(stake_user_pubkey) = stake_user_pubkey {
+        accounts.push(AccountMeta::new(stake_user_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn deprecate(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    successor: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::Deprecate(DeprecateData { successor }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_hook_program(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    hook_program: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetHookProgram(HookProgramData { hook_program }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_price_oracle(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    price_oracle_pubkey: Pubkey,
+    max_price_age: u64,
+    max_price_conf_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetPriceOracle(PriceOracleData {
+        price_oracle: price_oracle_pubkey,
+        max_price_age,
+        max_price_conf_bps,
+    })
+    .pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+    if price_oracle_pubkey != Pubkey::default() {
+        accounts.push(AccountMeta::new_readonly(price_oracle_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_stake_mint(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    stake_mint_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    weight_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::AddStakeMint(StakeMintData { weight_bps }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new_readonly(stake_mint_pubkey, false),
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_pair_ratio(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    pair_numerator: u64,
+    pair_denominator: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetPairRatio(PairRatioData {
+        pair_numerator,
+        pair_denominator,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stake_pair(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    paired_source_pubkey: Pubkey,
+    paired_destination_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::StakePair(PairStakeData { amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(paired_source_pubkey, false),
+        AccountMeta::new(paired_destination_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn unstake_pair(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    paired_source_pubkey: Pubkey,
+    paired_destination_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::UnstakePair(PairStakeData { amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(paired_source_pubkey, false),
+        AccountMeta::new(paired_destination_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_boost_collection(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    boost_collection: Pubkey,
+    boost_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetBoostCollection(BoostCollectionData {
+        boost_collection,
+        boost_bps,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Register an NFT for a reward boost, escrowed in the associated token
+/// account of `authority_pubkey`
+pub fn register_boost(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    nft_mint_pubkey: Pubkey,
+    source_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::RegisterBoost.pack();
+    let (metadata_pubkey, _) = find_metadata_address(&nft_mint_pubkey);
+    let (escrow_authority_pubkey, _) =
+        find_boost_escrow_authority_address(&program_id, &stake_user_pubkey);
+    let (escrow_pubkey, _) =
+        find_associated_token_address(&escrow_authority_pubkey, &nft_mint_pubkey, &spl_token::id());
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(nft_mint_pubkey, false),
+        AccountMeta::new_readonly(metadata_pubkey, false),
+        AccountMeta::new(source_pubkey, false),
+        AccountMeta::new(escrow_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn unregister_boost(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    stake_owner_pubkey: Pubkey,
+    nft_mint_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::UnregisterBoost.pack();
+    let (escrow_authority_pubkey, _) =
+        find_boost_escrow_authority_address(&program_id, &stake_user_pubkey);
+    let (escrow_pubkey, _) =
+        find_associated_token_address(&escrow_authority_pubkey, &nft_mint_pubkey, &spl_token::id());
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(escrow_authority_pubkey, false),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(escrow_pubkey, false),
+        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_tiers(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    tiers: [Tier; MAX_TIERS],
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetTiers(TiersData { tiers }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_badge_mint(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    badge_mint: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetBadgeMint(BadgeMintData { badge_mint }).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+    if badge_mint != Pubkey::default() {
+        accounts.push(AccountMeta::new_readonly(badge_mint, false));
+    }
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn mint_badge(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    badge_mint_pubkey: Pubkey,
+    badge_token_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::MintBadge.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(badge_mint_pubkey, false),
+        AccountMeta::new(badge_token_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(spl_token_2022::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_streak_bonus(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    streak_duration: u64,
+    streak_bonus_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetStreakBonus(StreakBonusData {
+        streak_duration,
+        streak_bonus_bps,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn create_stake_snapshot(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    stake_snapshot_pubkey: Pubkey,
+    timestamp: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::CreateStakeSnapshot(StakeSnapshotData { timestamp }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(stake_snapshot_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn update_stake_snapshot(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    stake_snapshot_pubkey: Pubkey,
+    stake_user_pubkeys: &[Pubkey],
+    finalize: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::UpdateStakeSnapshot(StakeSnapshotUpdateData { finalize }).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(stake_snapshot_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+    accounts.extend(
+        stake_user_pubkeys
+            .iter()
+            .map(|pubkey| AccountMeta::new_readonly(*pubkey, false)),
+    );
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn publish_bonus(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    bonus_campaign_pubkey: Pubkey,
+    bonus_vault_pubkey: Pubkey,
+    root: [u8; 32],
+    leaf_count: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::PublishBonus(BonusData { root, leaf_count }).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(bonus_campaign_pubkey, false),
+        AccountMeta::new_readonly(bonus_vault_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn claim_bonus(
+    program_id: Pubkey,
+    bonus_campaign_pubkey: Pubkey,
+    bonus_vault_pubkey: Pubkey,
+    owner_pubkey: Pubkey,
+    reward_token_pubkey: Pubkey,
+    index: u64,
+    amount: u64,
+    proof: Vec<[u8; 32]>,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::ClaimBonus(BonusClaimData {
+        index,
+        amount,
+        proof,
+    })
+    .pack();
+    let (bonus_authority_pubkey, _) =
+        find_bonus_authority_address(&program_id, &bonus_campaign_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new(bonus_campaign_pubkey, false),
+        AccountMeta::new_readonly(bonus_authority_pubkey, false),
+        AccountMeta::new(bonus_vault_pubkey, false),
+        AccountMeta::new_readonly(owner_pubkey, true),
+        AccountMeta::new(reward_token_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_admin(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    new_admin_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetAdmin(AdminData {
+        admin: new_admin_pubkey,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_top_level_only(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    top_level_only: bool,
+    integrators: Vec<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetTopLevelOnly(TopLevelOnlyData {
+        top_level_only,
+        integrators,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn repair_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    stake_user_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::RepairUser.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new(stake_user_pubkey, false),
+        AccountMeta::new_readonly(clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn add_reward_budget(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::AddRewardBudget(RewardBudgetData { amount }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_max_reward_supply(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    max_reward_supply: u64,
+) -> Result<Instruction, ProgramError> {
+    let data =
+        InstructionType::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_max_stake_per_user(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    max_stake_per_user: u64,
+) -> Result<Instruction, ProgramError> {
+    let data =
+        InstructionType::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn release_reward_mint(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    authority_pubkey: Pubkey,
+    reward_mint_pubkey: Pubkey,
+    new_authority: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::ReleaseRewardMint(ReleaseRewardMintData { new_authority }).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+        AccountMeta::new_readonly(authority_pubkey, false),
+        AccountMeta::new(reward_mint_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_global_config(
+    program_id: Pubkey,
+    authority_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    data: GlobalConfigData,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetGlobalConfig(data).pack();
+
+    let accounts = vec![
+        AccountMeta::new(find_global_config_address(&program_id).0, false),
+        AccountMeta::new_readonly(authority_pubkey, true),
+        AccountMeta::new(payer_pubkey, true),
+        AccountMeta::new_readonly(find_program_data_address(&program_id).0, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
 
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match *self {
-            Self::CreatePool(InitData {
-                bump_seed,
-                reward_numerator,
-                reward_denominator,
-            }) => {
-                buf.push(0x1);
-                buf.extend_from_slice(&bump_seed.to_le_bytes());
-                buf.extend_from_slice(&reward_numerator.to_le_bytes());
-                buf.extend_from_slice(&reward_denominator.to_le_bytes());
-            }
-            Self::CreateStakeUser => {
-                buf.push(0x2);
-            }
-            Self::Stake(StakeData { amount }) => {
-                buf.push(0x3);
-                buf.extend_from_slice(&amount.to_le_bytes());
-            }
-            Self::Unstake(StakeData { amount }) => {
-                buf.push(0x4);
-                buf.extend_from_slice(&amount.to_le_bytes());
-            }
-            Self::Claim => {
-                buf.push(0x5);
-            }
-            Self::Refresh => {
-                buf.push(0x6);
-            }
-        }
-        buf
-    }
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
 }
 
-fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
-    if input.is_empty() {
-        return Err(CustomError::InstructionUnpackError.into());
-    }
-    let (bytes, rest) = input.split_at(1);
-    let value = bytes
-        .get(..1)
-        .and_then(|slice| slice.try_into().ok())
-        .map(u8::from_le_bytes)
-        .ok_or(CustomError::InstructionUnpackError)?;
-    Ok((value, rest))
+pub fn set_role(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    role: Role,
+    holder_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetRole(RoleData {
+        role: role as u8,
+        holder: holder_pubkey,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
 }
 
-fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-    if input.len() < 8 {
-        return Err(CustomError::InstructionUnpackError.into());
-    }
-    let (amount, rest) = input.split_at(8);
-    let amount = amount
-        .get(..8)
-        .and_then(|slice| slice.try_into().ok())
-        .map(u64::from_le_bytes)
-        .ok_or(CustomError::InstructionUnpackError)?;
-    Ok((amount, rest))
+pub fn set_rounding(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    rounding: Rounding,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetRounding(RoundingData {
+        rounding: rounding as u8,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
 }
 
-pub fn create_stake_pool(
+/// Build a FinalizeDistributionReport for the current epoch, with the deltas
+/// since the report of `previous_epoch`, the last one of the pool, if any
+pub fn finalize_distribution_report(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
-    stake_pool_authority_pubkey: Pubkey,
-    staking_token_mint_pubkey: Pubkey,
-    staking_token_reserve_pubkey: Pubkey,
     reward_token_mint_pubkey: Pubkey,
-    init_data: InitData,
+    payer_pubkey: Pubkey,
+    epoch: u64,
+    previous_epoch: Option<u64>,
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::CreatePool(init_data).pack();
+    let data = InstructionType::FinalizeDistributionReport.pack();
+    let (report_pubkey, _) =
+        find_distribution_report_address(&program_id, &stake_pool_pubkey, epoch);
 
-    let accounts = vec![
-        AccountMeta::new(stake_pool_pubkey, true),
-        AccountMeta::new_readonly(stake_pool_authority_pubkey, false),
-        AccountMeta::new_readonly(staking_token_mint_pubkey, false),
-        AccountMeta::new(staking_token_reserve_pubkey, false),
-        AccountMeta::new(reward_token_mint_pubkey, false),
+    let mut accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new(report_pubkey, false),
+        AccountMeta::new_readonly(reward_token_mint_pubkey, false),
+        AccountMeta::new(payer_pubkey, true),
         AccountMeta::new_readonly(rent::id(), false),
-        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
     ];
+    if let Some(previous_epoch) = previous_epoch {
+        let (previous_pubkey, _) =
+            find_distribution_report_address(&program_id, &stake_pool_pubkey, previous_epoch);
+        accounts.push(AccountMeta::new_readonly(previous_pubkey, false));
+    }
 
     Ok(Instruction {
         program_id,
@@ -211,19 +4581,27 @@ pub fn create_stake_pool(
     })
 }
 
-pub fn create_stake_user(
+/// Build a SetClaimSplit, creating the claim split of the stake user on first
+/// use
+pub fn set_claim_split(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
     stake_user_pubkey: Pubkey,
     stake_owner_pubkey: Pubkey,
+    payer_pubkey: Pubkey,
+    shares: [ClaimShare; MAX_CLAIM_SHARES],
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::CreateStakeUser.pack();
+    let data = InstructionType::SetClaimSplit(ClaimSplitData { shares }).pack();
+    let (claim_split_pubkey, _) = find_claim_split_address(&program_id, &stake_user_pubkey);
 
     let accounts = vec![
         AccountMeta::new_readonly(stake_pool_pubkey, false),
         AccountMeta::new(stake_user_pubkey, false),
-        AccountMeta::new(stake_owner_pubkey, true),
+        AccountMeta::new_readonly(stake_owner_pubkey, true),
+        AccountMeta::new(claim_split_pubkey, false),
+        AccountMeta::new(payer_pubkey, true),
         AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
     ];
 
     Ok(Instruction {
@@ -233,27 +4611,42 @@ pub fn create_stake_user(
     })
 }
 
-pub fn stake(
+pub fn set_lock_tiers(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    lock_tiers: [LockTier; MAX_LOCK_TIERS],
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetLockTiers(LockTiersData { lock_tiers }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Build a Relock locking the stake of a stake user for the lock tier in
+/// slot `lock_tier` of the pool, or extending its lock
+pub fn relock(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
     stake_user_pubkey: Pubkey,
-    user_transfer_authority_pubkey: Pubkey,
     stake_owner_pubkey: Pubkey,
-    source_pubkey: Pubkey,
-    destination_pubkey: Pubkey,
-    amount: u64,
+    lock_tier: u8,
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::Stake(StakeData { amount }).pack();
+    let data = InstructionType::Relock(RelockData { lock_tier }).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(stake_pool_pubkey, false),
         AccountMeta::new(stake_user_pubkey, false),
-        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
         AccountMeta::new_readonly(stake_owner_pubkey, true),
-        AccountMeta::new(source_pubkey, false),
-        AccountMeta::new(destination_pubkey, false),
         AccountMeta::new_readonly(clock::id(), false),
-        AccountMeta::new_readonly(spl_token::id(), false),
     ];
 
     Ok(Instruction {
@@ -263,27 +4656,112 @@ pub fn stake(
     })
 }
 
-pub fn unstake(
+/// Append the claim split of a stake user and the token accounts of its
+/// shares to a `Claim`, `ClaimIfAny`, `ClaimFor` or `WithdrawFromStream`,
+/// paying the shares into them. Claims of stake users with a claim split
+/// fail without it. It goes on after any price account and burn mint, before
+/// any hook accounts and the global config.
+pub fn with_claim_split(
+    program_id: Pubkey,
+    mut instruction: Instruction,
+    claim_split: &ClaimSplit,
+) -> Result<Instruction, ProgramError> {
+    match InstructionType::unpack(&instruction.data)? {
+        InstructionType::Claim
+        | InstructionType::ClaimIfAny
+        | InstructionType::ClaimFor
+        | InstructionType::WithdrawFromStream => {}
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    }
+    let (claim_split_pubkey, _) =
+        find_claim_split_address(&program_id, &claim_split.stake_user_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(claim_split_pubkey, false));
+    instruction.accounts.extend(
+        claim_split
+            .used_shares()
+            .map(|share| AccountMeta::new(share.destination, false)),
+    );
+    Ok(instruction)
+}
+
+/// Append the claim split of the stake user a `CloseStakeUser`, `GcUser` or
+/// `MergePositions` closes, closing it along, which stake users with a claim
+/// split require
+pub fn with_closed_claim_split(
+    program_id: Pubkey,
+    mut instruction: Instruction,
+) -> Result<Instruction, ProgramError> {
+    let stake_user_index = match InstructionType::unpack(&instruction.data)? {
+        InstructionType::CloseStakeUser | InstructionType::GcUser => 1,
+        InstructionType::MergePositions => 2,
+        _ => return Err(CustomError::IncorrectInstruction.into()),
+    };
+    let stake_user_pubkey = instruction
+        .accounts
+        .get(stake_user_index)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?
+        .pubkey;
+    let (claim_split_pubkey, _) = find_claim_split_address(&program_id, &stake_user_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new(claim_split_pubkey, false));
+    Ok(instruction)
+}
+
+pub fn set_protocol_pause(
+    program_id: Pubkey,
+    guardian_pubkey: Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetProtocolPause(ProtocolPauseData { paused }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(find_global_config_address(&program_id).0, false),
+        AccountMeta::new_readonly(guardian_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_claim_burn(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
-    stake_user_pubkey: Pubkey,
-    authority_pubkey: Pubkey,
-    stake_owner_pubkey: Pubkey,
-    source_pubkey: Pubkey,
-    destination_pubkey: Pubkey,
-    amount: u64,
+    admin_pubkey: Pubkey,
+    claim_burn_bps: u16,
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::Unstake(StakeData { amount }).pack();
+    let data = InstructionType::SetClaimBurn(ClaimBurnData { claim_burn_bps }).pack();
 
     let accounts = vec![
         AccountMeta::new(stake_pool_pubkey, false),
-        AccountMeta::new(stake_user_pubkey, false),
-        AccountMeta::new_readonly(authority_pubkey, false),
-        AccountMeta::new_readonly(stake_owner_pubkey, true),
-        AccountMeta::new(source_pubkey, false),
-        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_epoch_rate(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    epoch_rate_data: EpochRateData,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::SetEpochRate(epoch_rate_data).pack();
+
+    let accounts = vec![
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
         AccountMeta::new_readonly(clock::id(), false),
-        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(find_global_config_address(&program_id).0, false),
     ];
 
     Ok(Instruction {
@@ -293,24 +4771,19 @@ pub fn unstake(
     })
 }
 
-pub fn claim(
+pub fn set_reward_rate(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
-    stake_user_pubkey: Pubkey,
-    authority_pubkey: Pubkey,
-    reward_token_mint_pubkey: Pubkey,
-    destination_pubkey: Pubkey,
+    admin_pubkey: Pubkey,
+    rate_data: RateData,
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::Claim.pack();
+    let data = InstructionType::SetRewardRate(rate_data).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(stake_pool_pubkey, false),
-        AccountMeta::new(stake_user_pubkey, false),
-        AccountMeta::new_readonly(authority_pubkey, false),
-        AccountMeta::new(reward_token_mint_pubkey, false),
-        AccountMeta::new(destination_pubkey, false),
+        AccountMeta::new(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(admin_pubkey, true),
         AccountMeta::new_readonly(clock::id(), false),
-        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(find_global_config_address(&program_id).0, false),
     ];
 
     Ok(Instruction {
@@ -320,23 +4793,46 @@ pub fn claim(
     })
 }
 
-pub fn refresh(
+pub fn snapshot_pool(
     program_id: Pubkey,
     stake_pool_pubkey: Pubkey,
-    stake_user_pubkeys: Vec<Pubkey>,
+    pool_snapshot_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    reward_token_mint_pubkey: Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = InstructionType::Refresh.pack();
+    let data = InstructionType::SnapshotPool.pack();
 
-    let mut accounts = vec![
+    let accounts = vec![
         AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new(pool_snapshot_pubkey, false),
+        AccountMeta::new_readonly(staking_token_reserve_pubkey, false),
+        AccountMeta::new_readonly(reward_token_mint_pubkey, false),
+        AccountMeta::new_readonly(rent::id(), false),
         AccountMeta::new_readonly(clock::id(), false),
     ];
 
-    accounts.extend(
-        stake_user_pubkeys
-            .into_iter()
-            .map(|pubkey| AccountMeta::new(pubkey, false)),
-    );
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn verify_invariants(
+    program_id: Pubkey,
+    stake_pool_pubkey: Pubkey,
+    stake_pool_authority_pubkey: Pubkey,
+    staking_token_reserve_pubkey: Pubkey,
+    reward_token_pubkey: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = InstructionType::VerifyInvariants.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(stake_pool_pubkey, false),
+        AccountMeta::new_readonly(stake_pool_authority_pubkey, false),
+        AccountMeta::new_readonly(staking_token_reserve_pubkey, false),
+        AccountMeta::new_readonly(reward_token_pubkey, false),
+    ];
 
     Ok(Instruction {
         program_id,
@@ -344,3 +4840,272 @@ pub fn refresh(
         data,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+    struct PayloadV1 {
+        amount: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+    struct PayloadV2 {
+        amount: u64,
+        lock_tier: u8,
+    }
+
+    #[test]
+    fn test_create_with_accounts() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let stake_user = Pubkey::new_unique();
+        let rent = Rent::default();
+
+        let instructions = create_stake_user_with_account(
+            program_id,
+            payer,
+            Pubkey::new_unique(),
+            stake_user,
+            payer,
+            &rent,
+        )
+        .unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(
+            instructions[0],
+            system_instruction::create_account(
+                &payer,
+                &stake_user,
+                rent.minimum_balance(StakeUser::LEN),
+                StakeUser::LEN as u64,
+                &program_id,
+            )
+        );
+        assert_eq!(instructions[1].accounts[1].pubkey, stake_user);
+
+        let instructions = create_pool_with_accounts(
+            program_id,
+            payer,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            payer,
+            Pubkey::new_unique(),
+            InitData {
+                bump_seed: 255,
+                reward_numerator: 1,
+                reward_denominator: 1_000,
+                reward_period: 86_400,
+                reject_freeze_authority: false,
+            },
+            &rent,
+        )
+        .unwrap();
+        assert_eq!(instructions.len(), 4);
+        assert!(instructions[..3]
+            .iter()
+            .all(|instruction| instruction.program_id == system_program::id()));
+        assert_eq!(instructions[3].program_id, program_id);
+    }
+
+    #[test]
+    fn test_v2_encoding() {
+        assert_eq!(
+            pack_v2(0x7F, &PayloadV1 { amount: 1 }),
+            Err(CustomError::IncorrectInstruction.into())
+        );
+
+        let data = pack_v2(V2_TAG_START, &PayloadV1 { amount: 1 }).unwrap();
+        assert_eq!(data[..3], [V2_TAG_START, 8, 0]);
+        assert_eq!(
+            unpack_v2::<PayloadV1>(&data[1..]).unwrap(),
+            PayloadV1 { amount: 1 }
+        );
+        assert_eq!(
+            unpack_v2::<PayloadV1>(&data[1..data.len() - 1]),
+            Err(CustomError::InstructionUnpackError.into())
+        );
+
+        // trailing fields of newer clients are skipped
+        let data = pack_v2(
+            V2_TAG_START,
+            &PayloadV2 {
+                amount: 2,
+                lock_tier: 3,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            unpack_v2::<PayloadV1>(&data[1..]).unwrap(),
+            PayloadV1 { amount: 2 }
+        );
+
+        // v2 tags are not v1 instructions
+        assert_eq!(
+            InstructionType::unpack(&data),
+            Err(CustomError::IncorrectInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_stake_auto_claim_flag() {
+        // clients predating the flag
+        let mut data = vec![0x3];
+        data.extend_from_slice(&500u64.to_le_bytes());
+        let legacy = InstructionType::unpack(&data).unwrap();
+        assert_eq!(
+            legacy,
+            InstructionType::Stake(StakeData {
+                amount: 500,
+                auto_claim: false,
+            })
+        );
+        assert_eq!(legacy.pack(), data);
+
+        let instruction = with_auto_claim(
+            Instruction {
+                program_id: Pubkey::new_unique(),
+                accounts: vec![],
+                data: data.clone(),
+            },
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(instruction.data[..9], data[..]);
+        assert_eq!(instruction.data[9], 1);
+        assert_eq!(instruction.accounts.len(), 3);
+
+        data.push(2);
+        assert_eq!(
+            InstructionType::unpack(&data),
+            Err(CustomError::InstructionUnpackError.into())
+        );
+    }
+    /// Canonical encoding of every instruction, for clients in other
+    /// languages to check theirs against
+    fn golden_vectors() -> Vec<(InstructionType, &'static str)> {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        vec![
+            (InstructionType::CreatePool(InitData { bump_seed: 255, reward_numerator: 1, reward_denominator: 1_000, reward_period: 86_400, reject_freeze_authority: true }), "01ff0100000000000000e803000000000000805101000000000001"),
+            (InstructionType::CreateStakeUser, "02"),
+            (InstructionType::Stake(StakeData { amount: 500, auto_claim: false }), "03f401000000000000"),
+            (InstructionType::Unstake(StakeData { amount: 500, auto_claim: true }), "04f40100000000000001"),
+            (InstructionType::Claim, "05"),
+            (InstructionType::Refresh, "06"),
+            (InstructionType::FundRewards(FundData { amount: 1_000 }), "07e803000000000000"),
+            (InstructionType::Sweep(SweepData { amount: 1_000 }), "08e803000000000000"),
+            (InstructionType::SyncReserve, "09"),
+            (InstructionType::Deprecate(DeprecateData { successor: key(1) }), "0a0101010101010101010101010101010101010101010101010101010101010101"),
+            (InstructionType::SetClaimBurn(ClaimBurnData { claim_burn_bps: 250 }), "0bfa00"),
+            (InstructionType::SetEpochRate(EpochRateData { epoch: 10, reward_numerator: 2, reward_denominator: 1_000 }), "0c0a000000000000000200000000000000e803000000000000"),
+            (InstructionType::ClaimIfAny, "0d"),
+            (InstructionType::SetRewardRate(RateData { reward_numerator: 2, reward_denominator: 1_000 }), "0e0200000000000000e803000000000000"),
+            (InstructionType::SnapshotPool, "0f"),
+            (InstructionType::VerifyInvariants, "10"),
+            (InstructionType::StakeWithDelegate(StakeData { amount: 500, auto_claim: false }), "11f401000000000000"),
+            (InstructionType::ClaimFor, "12"),
+            (InstructionType::ClaimAndStakeInto, "13"),
+            (InstructionType::SetHookProgram(HookProgramData { hook_program: key(2) }), "140202020202020202020202020202020202020202020202020202020202020202"),
+            (InstructionType::SetPriceOracle(PriceOracleData { price_oracle: key(3), max_price_age: 60, max_price_conf_bps: 200 }), "1503030303030303030303030303030303030303030303030303030303030303033c00000000000000c800"),
+            (InstructionType::AddStakeMint(StakeMintData { weight_bps: 5_000 }), "168813"),
+            (InstructionType::SetPairRatio(PairRatioData { pair_numerator: 1, pair_denominator: 10 }), "1701000000000000000a00000000000000"),
+            (InstructionType::StakePair(PairStakeData { amount: 500 }), "18f401000000000000"),
+            (InstructionType::UnstakePair(PairStakeData { amount: 500 }), "19f401000000000000"),
+            (InstructionType::SetBoostCollection(BoostCollectionData { boost_collection: key(4), boost_bps: 2_500 }), "1a0404040404040404040404040404040404040404040404040404040404040404c409"),
+            (InstructionType::RegisterBoost, "1b"),
+            (InstructionType::UnregisterBoost, "1c"),
+            (InstructionType::SetTiers(TiersData { tiers: [Tier { min_stake: 100, min_duration: 7 }, Tier { min_stake: 1_000, min_duration: 30 }, Tier::default()] }), "1d64000000000000000700000000000000e8030000000000001e0000000000000000000000000000000000000000000000"),
+            (InstructionType::SetBadgeMint(BadgeMintData { badge_mint: key(5) }), "1e0505050505050505050505050505050505050505050505050505050505050505"),
+            (InstructionType::MintBadge, "1f"),
+            (InstructionType::SetStreakBonus(StreakBonusData { streak_duration: 604_800, streak_bonus_bps: 500 }), "20803a090000000000f401"),
+            (InstructionType::CloseStakeUser, "21"),
+            (InstructionType::CreateStakeSnapshot(StakeSnapshotData { timestamp: 1_700_000_000 }), "2200f1536500000000"),
+            (InstructionType::UpdateStakeSnapshot(StakeSnapshotUpdateData { finalize: true }), "2301"),
+            (InstructionType::PublishBonus(BonusData { root: [6; 32], leaf_count: 4 }), "2406060606060606060606060606060606060606060606060606060606060606060400000000000000"),
+            (InstructionType::ClaimBonus(BonusClaimData { index: 1, amount: 50, proof: vec![[7; 32], [8; 32]] }), "25010000000000000032000000000000000207070707070707070707070707070707070707070707070707070707070707070808080808080808080808080808080808080808080808080808080808080808"),
+            (InstructionType::SetAdmin(AdminData { admin: key(9) }), "260909090909090909090909090909090909090909090909090909090909090909"),
+            (InstructionType::RefreshBatch, "27"),
+            (InstructionType::SetTopLevelOnly(TopLevelOnlyData { top_level_only: true, integrators: vec![key(10)] }), "2801010a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a"),
+            (InstructionType::RepairUser, "29"),
+            (InstructionType::AddRewardBudget(RewardBudgetData { amount: 1_000 }), "2ae803000000000000"),
+            (InstructionType::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply: 1_000_000 }), "2b40420f0000000000"),
+            (InstructionType::ReleaseRewardMint(ReleaseRewardMintData { new_authority: key(11) }), "2c0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+            (InstructionType::SetGlobalConfig(GlobalConfigData { authority: key(12), treasury: key(13), pool_creation_fee: 300, guardian: key(14), default_claim_burn_bps: 250, max_daily_reward_bps: 10 }), "2d0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d2c010000000000000e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0efa000a00000000000000"),
+            (InstructionType::CreateStakeUserIdempotent, "2e"),
+            (InstructionType::HarvestAll(HarvestAllData { account_counts: vec![9, 10] }), "2f02090a"),
+            (InstructionType::GetPoolInfo, "30"),
+            (InstructionType::SetStreamDuration(StreamDurationData { stream_duration: 2_592_000 }), "31008d270000000000"),
+            (InstructionType::WithdrawFromStream, "32"),
+            (InstructionType::SetWarmUp(WarmUpData { warm_up_duration: 86_400, warm_up_rate_bps: 2_500 }), "338051010000000000c409"),
+            (InstructionType::SetRateController(RateControllerData { target_tvl: 1_000, min_reward_numerator: 1, max_reward_numerator: 105, max_rate_step_bps: 2_000 }), "34e80300000000000001000000000000006900000000000000d007"),
+            (InstructionType::RebalanceRate, "35"),
+            (InstructionType::MergePositions, "36"),
+            (InstructionType::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user: 150 }), "379600000000000000"),
+            (InstructionType::SetGcDelay(GcDelayData { gc_delay: 2_592_000 }), "38008d270000000000"),
+            (InstructionType::GcUser, "39"),
+            (InstructionType::FundRentVault(FundData { amount: 1_000 }), "3ae803000000000000"),
+            (InstructionType::WithdrawRentVault(RentVaultWithdrawData { amount: 1_000 }), "3be803000000000000"),
+            (InstructionType::CreateSponsoredStakeUser, "3c"),
+            (InstructionType::SetProtocolPause(ProtocolPauseData { paused: true }), "3d01"),
+            (InstructionType::SetRole(RoleData { role: Role::Treasurer as u8, holder: key(15) }), "3e020f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f"),
+            (InstructionType::SetRounding(RoundingData { rounding: Rounding::Ceil as u8 }), "3f02"),
+            (InstructionType::FinalizeDistributionReport, "40"),
+            (InstructionType::SetClaimSplit(ClaimSplitData { shares: [ClaimShare { destination: key(16), bps: 2_500 }, ClaimShare { destination: key(17), bps: 1_000 }, ClaimShare::default(), ClaimShare::default()] }), "411010101010101010101010101010101010101010101010101010101010101010c4091111111111111111111111111111111111111111111111111111111111111111e8030000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"),
+            (InstructionType::SetLockTiers(LockTiersData { lock_tiers: [LockTier { duration: 2_592_000, bonus_bps: 1_000 }, LockTier { duration: 31_536_000, bonus_bps: 5_000 }, LockTier::default()] }), "42008d270000000000e8038033e10100000000881300000000000000000000"),
+            (InstructionType::Relock(RelockData { lock_tier: 1 }), "4301"),
+        ]
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_golden_vectors() {
+        let vectors = golden_vectors();
+        // one vector per tag
+        let tags: Vec<u8> = vectors.iter().map(|(_, hex)| from_hex(hex)[0]).collect();
+        assert_eq!(tags, (0x1..=0x43).collect::<Vec<u8>>());
+
+        for (instruction, hex) in vectors {
+            let data = from_hex(hex);
+            assert_eq!(instruction.pack(), data, "{:?}", instruction);
+            assert_eq!(InstructionType::unpack(&data).as_ref(), Ok(&instruction));
+
+            // trailing bytes are rejected
+            let mut trailing = data.clone();
+            trailing.push(0);
+            assert_eq!(
+                InstructionType::unpack(&trailing),
+                Err(CustomError::InstructionUnpackError.into()),
+                "{:?}",
+                instruction
+            );
+            // as are short payloads, or they decode to another instruction
+            if data.len() > 1 {
+                assert_ne!(
+                    InstructionType::unpack(&data[..data.len() - 1]).as_ref(),
+                    Ok(&instruction)
+                );
+            }
+        }
+
+        // flags are only encoded when set
+        let mut data = from_hex("03f401000000000000");
+        data.push(0);
+        assert_eq!(
+            InstructionType::unpack(&data),
+            Err(CustomError::InstructionUnpackError.into())
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Interest-bearing Token-2022 reward mints. Their raw amounts never change,
+//! interest only scales the UI amounts up over time, so the pool accrues,
+//! budgets and caps rewards in raw amounts as for any mint. Only amounts given
+//! in UI terms, like oracle prices, need scaling.
+
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError};
+use spl_token_2022::{
+    extension::{
+        interest_bearing_mint::InterestBearingConfig, BaseStateWithExtensions, ExtensionType,
+        StateWithExtensions,
+    },
+    state::Mint,
+};
+
+use crate::error::CustomError;
+
+/// Token-2022 mint extensions a reward mint may have. Others, like transfer
+/// fees or hooks, would make payouts differ from the amounts the pool
+/// accounts for.
+pub const SUPPORTED_REWARD_MINT_EXTENSIONS: [ExtensionType; 3] = [
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+];
+
+/// Check that a Token-2022 reward mint only has supported extensions
+pub fn check_reward_mint_extensions(mint_data: &[u8]) -> Result<(), ProgramError> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)
+        .map_err(|_| CustomError::InvalidTokenMint)?;
+    let extensions = mint
+        .get_extension_types()
+        .map_err(|_| CustomError::InvalidTokenMint)?;
+    if extensions
+        .iter()
+        .any(|extension| !SUPPORTED_REWARD_MINT_EXTENSIONS.contains(extension))
+    {
+        return Err(CustomError::InvalidTokenMint.into());
+    }
+    Ok(())
+}
+
+/// Factor the UI amounts of a mint are scaled up by at a time, `None` for
+/// mints without interest
+pub fn interest_multiplier(
+    mint_data: &[u8],
+    unix_timestamp: UnixTimestamp,
+) -> Result<Option<f64>, ProgramError> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)
+        .map_err(|_| CustomError::InvalidTokenMint)?;
+    let config = match mint.get_extension::<InterestBearingConfig>() {
+        Ok(config) => config,
+        Err(_) => return Ok(None),
+    };
+    // the UI amount of one raw unit without decimals is the multiplier itself
+    config
+        .amount_to_ui_amount(1, 0, unix_timestamp)
+        .and_then(|multiplier| multiplier.parse::<f64>().ok())
+        .filter(|multiplier| multiplier.is_finite() && *multiplier > 0.0)
+        .map(Some)
+        .ok_or_else(|| CustomError::CalculationFailure.into())
+}
+
+/// Raw amount worth an amount in UI terms, rounding down
+pub fn to_raw_amount(ui_amount: u64, multiplier: f64) -> u64 {
+    // float to integer casts saturate
+    (ui_amount as f64 / multiplier) as u64
+}
+
+/// Amount in UI terms worth a raw amount, rounding down
+pub fn to_ui_amount(raw_amount: u64, multiplier: f64) -> u64 {
+    (raw_amount as f64 * multiplier) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_pack::Pack;
+    use spl_token_2022::extension::StateWithExtensionsMut;
+
+    /// Seconds in the year of interest accrual of Token-2022
+    const SECONDS_PER_YEAR: i64 = 31_556_736;
+
+    fn interest_bearing_mint(rate: i16) -> Vec<u8> {
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&[
+            ExtensionType::InterestBearingConfig,
+        ])
+        .unwrap();
+        let mut data = vec![0; len];
+        let mut mint = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let config = mint.init_extension::<InterestBearingConfig>(true).unwrap();
+        config.current_rate = rate.into();
+        mint.base.is_initialized = true;
+        mint.pack_base();
+        mint.init_account_type().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_interest_multiplier() {
+        let mint = interest_bearing_mint(500);
+        assert_eq!(interest_multiplier(&mint, 0), Ok(Some(1.0)));
+        // 5% compounded continuously over a year
+        let multiplier = interest_multiplier(&mint, SECONDS_PER_YEAR)
+            .unwrap()
+            .unwrap();
+        assert!((multiplier - 0.05f64.exp()).abs() < 1e-9);
+        assert_eq!(to_ui_amount(1_000_000, multiplier), 1_051_271);
+        assert_eq!(to_raw_amount(1_051_271, multiplier), 999_999);
+
+        let mut plain_mint = vec![0; Mint::LEN];
+        Mint {
+            is_initialized: true,
+            ..Mint::default()
+        }
+        .pack_into_slice(&mut plain_mint);
+        assert_eq!(interest_multiplier(&plain_mint, SECONDS_PER_YEAR), Ok(None));
+        assert_eq!(check_reward_mint_extensions(&plain_mint), Ok(()));
+        assert_eq!(check_reward_mint_extensions(&mint), Ok(()));
+    }
+}
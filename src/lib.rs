@@ -1,8 +1,48 @@
+//! Staking reward pool program. Only the account layouts and reward math of
+//! `state`, with `error`, `event`, `math`, `merkle` and `simulator`, build for
+//! wasm32 frontends.
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod accounting;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod automation;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod context;
 pub mod entrypoint;
 pub mod error;
+pub mod event;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod governance;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod guard;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod history;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hook;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod instruction;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod interest;
+pub mod math;
+pub mod merkle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod metadata;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod oracle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pause;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pda;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod processor;
+#[cfg(feature = "serde")]
+pub mod serde_fields;
+pub mod simulator;
 pub mod state;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transfer_hook;
 
 pub use solana_program;
 
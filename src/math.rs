@@ -0,0 +1,255 @@
+//! Reward accrual and basis point math of the program, on plain integers
+//! with only `core`, for simulators, fuzzers and risk tools. The state
+//! methods wrap these, turning `MathError` into `CalculationFailure`.
+
+use core::convert::TryFrom;
+
+/// Basis points denominator for percentage options
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Arithmetic failures
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MathError {
+    /// The result doesn't fit its type
+    Overflow,
+    /// A denominator is zero
+    DivisionByZero,
+}
+
+/// How the final division of reward accrual rounds
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rounding {
+    /// Round down, the pool keeping the dust
+    #[default]
+    Floor,
+    /// Round to the nearest unit, halves up
+    HalfUp,
+    /// Round up, the pool absorbing the dust
+    Ceil,
+}
+
+impl Rounding {
+    pub fn from_u8(rounding: u8) -> Option<Self> {
+        match rounding {
+            0 => Some(Self::Floor),
+            1 => Some(Self::HalfUp),
+            2 => Some(Self::Ceil),
+            _ => None,
+        }
+    }
+
+    /// `numerator / denominator`, rounded, for a non-zero denominator
+    fn div(self, numerator: u128, denominator: u128) -> u128 {
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        let round_up = match self {
+            Self::Floor => false,
+            Self::HalfUp => remainder >= denominator - denominator / 2,
+            Self::Ceil => remainder != 0,
+        };
+        quotient + u128::from(round_up)
+    }
+}
+
+/// Seconds elapsed between two timestamps, clamped to zero when going backwards
+pub fn elapsed(from_ts: i64, to_ts: i64) -> u64 {
+    if to_ts > from_ts {
+        (to_ts as i128 - from_ts as i128) as u64
+    } else {
+        0
+    }
+}
+
+/// `value * numerator / denominator`, rounding down
+pub fn mul_div(value: u64, numerator: u64, denominator: u64) -> Result<u64, MathError> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    u64::try_from(value as u128 * numerator as u128 / denominator as u128)
+        .map_err(|_| MathError::Overflow)
+}
+
+/// `value * numerator / denominator`, rounding up
+pub fn mul_div_ceil(value: u64, numerator: u64, denominator: u64) -> Result<u64, MathError> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    u64::try_from((value as u128 * numerator as u128).div_ceil(denominator as u128))
+        .map_err(|_| MathError::Overflow)
+}
+
+/// Share of an amount in basis points, rounding down. Shares over
+/// `BPS_DENOMINATOR` scale the amount up, e.g. for boosts.
+pub fn bps_of(amount: u64, bps: u64) -> Result<u64, MathError> {
+    mul_div(amount, bps, BPS_DENOMINATOR)
+}
+
+/// Split an amount into the rest and a cut in basis points, rounding the
+/// cut down
+pub fn split_bps(amount: u64, bps: u64) -> Result<(u64, u64), MathError> {
+    let cut = bps_of(amount, bps)?;
+    let rest = amount.checked_sub(cut).ok_or(MathError::Overflow)?;
+    Ok((rest, cut))
+}
+
+/// Reward accrued by a stake over `period` seconds at a reward ratio of
+/// `numerator / denominator` per `reward_period` seconds. The rounding,
+/// first of the ratio of the stake and then of the period, is the program's.
+pub fn accrue(
+    stake_amount: u64,
+    numerator: u64,
+    denominator: u64,
+    period: u64,
+    reward_period: u64,
+) -> Result<u64, MathError> {
+    numerator
+        .checked_mul(stake_amount)
+        .ok_or(MathError::Overflow)?
+        .checked_div(denominator)
+        .ok_or(MathError::DivisionByZero)?
+        .checked_mul(period)
+        .ok_or(MathError::Overflow)?
+        .checked_div(reward_period)
+        .ok_or(MathError::DivisionByZero)
+}
+
+/// Reward accrued like `accrue`, with a rounding. Floor keeps the program's
+/// rounding of the ratio then the period, the others round the exact reward
+/// once, so small stakes aren't short-changed by the first division.
+pub fn accrue_rounded(
+    stake_amount: u64,
+    numerator: u64,
+    denominator: u64,
+    period: u64,
+    reward_period: u64,
+    rounding: Rounding,
+) -> Result<u64, MathError> {
+    if rounding == Rounding::Floor {
+        return accrue(stake_amount, numerator, denominator, period, reward_period);
+    }
+    if denominator == 0 || reward_period == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    let reward = (numerator as u128)
+        .checked_mul(stake_amount as u128)
+        .and_then(|reward| reward.checked_mul(period as u128))
+        .ok_or(MathError::Overflow)?;
+    u64::try_from(rounding.div(reward, denominator as u128 * reward_period as u128))
+        .map_err(|_| MathError::Overflow)
+}
+
+/// Cumulative stake-seconds advanced by a stake held between two timestamps
+pub fn add_stake_seconds(
+    cumulative: u128,
+    stake: u64,
+    from_ts: i64,
+    to_ts: i64,
+) -> Result<u128, MathError> {
+    (stake as u128)
+        .checked_mul(elapsed(from_ts, to_ts) as u128)
+        .and_then(|stake_seconds| stake_seconds.checked_add(cumulative))
+        .ok_or(MathError::Overflow)
+}
+
+/// Convert an amount of quote units into tokens of the same decimals at a
+/// price of `price * 10^expo` quote units per token, rounding down
+pub fn quote_to_tokens(quote: u64, price: u64, expo: i32) -> Result<u64, MathError> {
+    let scale = 10u128
+        .checked_pow(expo.unsigned_abs())
+        .ok_or(MathError::Overflow)?;
+    let tokens = if expo < 0 {
+        (quote as u128)
+            .checked_mul(scale)
+            .ok_or(MathError::Overflow)?
+            .checked_div(price as u128)
+    } else {
+        (quote as u128).checked_div(
+            (price as u128)
+                .checked_mul(scale)
+                .ok_or(MathError::Overflow)?,
+        )
+    };
+    u64::try_from(tokens.ok_or(MathError::DivisionByZero)?).map_err(|_| MathError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bps() {
+        assert_eq!(bps_of(1_000, 250), Ok(25));
+        assert_eq!(bps_of(999, 1), Ok(0));
+        assert_eq!(
+            bps_of(u64::MAX, BPS_DENOMINATOR + 1),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(split_bps(1_000, 250), Ok((975, 25)));
+        assert_eq!(
+            split_bps(1_000, 2 * BPS_DENOMINATOR),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(mul_div_ceil(10, 1, 3), Ok(4));
+        assert_eq!(mul_div(10, 1, 0), Err(MathError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_accrue() {
+        // 1 token per 10 staked per day, for half a day
+        assert_eq!(accrue(1_000, 1, 10, 43_200, 86_400), Ok(50));
+        // the ratio rounds down before the period
+        assert_eq!(accrue(19, 1, 10, 86_400, 86_400), Ok(1));
+        assert_eq!(accrue(1, 1, 0, 1, 1), Err(MathError::DivisionByZero));
+        assert_eq!(accrue(u64::MAX, 2, 1, 1, 1), Err(MathError::Overflow));
+        assert_eq!(elapsed(10, 5), 0);
+        assert_eq!(add_stake_seconds(1, 2, 0, 3), Ok(7));
+    }
+
+    #[test]
+    fn test_accrue_rounded() {
+        // 19 staked at 1 per 10 a day earn 1.9 over a day, 0.95 over half
+        let day = |rounding| accrue_rounded(19, 1, 10, 86_400, 86_400, rounding);
+        let half_day = |rounding| accrue_rounded(19, 1, 10, 43_200, 86_400, rounding);
+        assert_eq!(day(Rounding::Floor), Ok(1));
+        assert_eq!(day(Rounding::HalfUp), Ok(2));
+        assert_eq!(day(Rounding::Ceil), Ok(2));
+        assert_eq!(half_day(Rounding::Floor), Ok(0));
+        assert_eq!(half_day(Rounding::HalfUp), Ok(1));
+        assert_eq!(half_day(Rounding::Ceil), Ok(1));
+        // exact rewards round to themselves, halves round up
+        assert_eq!(
+            accrue_rounded(20, 1, 10, 86_400, 86_400, Rounding::Ceil),
+            Ok(2)
+        );
+        assert_eq!(
+            accrue_rounded(5, 1, 10, 86_400, 86_400, Rounding::HalfUp),
+            Ok(1)
+        );
+        assert_eq!(
+            accrue_rounded(4, 1, 10, 86_400, 86_400, Rounding::HalfUp),
+            Ok(0)
+        );
+
+        assert_eq!(
+            accrue_rounded(1, 1, 0, 1, 1, Rounding::Ceil),
+            Err(MathError::DivisionByZero)
+        );
+        assert_eq!(
+            accrue_rounded(u64::MAX, u64::MAX, 1, 2, 1, Rounding::HalfUp),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(Rounding::from_u8(2), Some(Rounding::Ceil));
+        assert_eq!(Rounding::from_u8(3), None);
+    }
+
+    #[test]
+    fn test_quote_to_tokens() {
+        // $2.50 per token at 6 decimals
+        assert_eq!(quote_to_tokens(5_000_000, 250, -2), Ok(2_000_000));
+        assert_eq!(quote_to_tokens(5_000_000, 5, 0), Ok(1_000_000));
+        assert_eq!(quote_to_tokens(1, 0, -2), Err(MathError::DivisionByZero));
+        assert_eq!(quote_to_tokens(1, 1, 40), Err(MathError::Overflow));
+    }
+}
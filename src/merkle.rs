@@ -0,0 +1,147 @@
+//! Merkle trees of `(owner, amount)` leaves, built incrementally on-chain for
+//! stake snapshots and verified against published roots for bonus claims.
+//!
+//! Leaves and nodes are SHA-256 hashes prefixed with a domain byte, so a node
+//! can't pass for a leaf. Proofs list the sibling hashes from the leaf up, the
+//! leaf index telling on which side each sibling sits.
+
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+/// Depth of the stake snapshot trees, holding up to about a million leaves
+pub const SNAPSHOT_DEPTH: usize = 20;
+
+/// Left siblings of the next leaf of a tree being built
+pub type Branch = [[u8; 32]; SNAPSHOT_DEPTH];
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Hash of a leaf crediting an amount to an owner
+pub fn leaf_hash(owner: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, owner.as_ref(), &amount.to_le_bytes()]).to_bytes()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+/// Roots of empty subtrees by height, an empty leaf being all zeros
+fn zero_hashes() -> Branch {
+    let mut zeros = [[0; 32]; SNAPSHOT_DEPTH];
+    for height in 1..SNAPSHOT_DEPTH {
+        zeros[height] = node_hash(&zeros[height - 1], &zeros[height - 1]);
+    }
+    zeros
+}
+
+/// Append the leaf at index `count` to a tree kept as the left siblings of its
+/// next leaf. Returns false when the tree is full.
+pub fn append(branch: &mut Branch, count: u64, leaf: [u8; 32]) -> bool {
+    if count >= 1 << SNAPSHOT_DEPTH {
+        return false;
+    }
+    let mut node = leaf;
+    let mut size = count + 1;
+    for sibling in branch.iter_mut() {
+        if size & 1 == 1 {
+            *sibling = node;
+            return true;
+        }
+        node = node_hash(sibling, &node);
+        size >>= 1;
+    }
+    // the full tree was refused above
+    unreachable!()
+}
+
+/// Root of a tree of `count` leaves kept as by `append`, padded with empty
+/// leaves
+pub fn root(branch: &Branch, count: u64) -> [u8; 32] {
+    let zeros = zero_hashes();
+    let mut node = [0; 32];
+    let mut size = count;
+    for (sibling, zero) in branch.iter().zip(zeros.iter()) {
+        node = if size & 1 == 1 {
+            node_hash(sibling, &node)
+        } else {
+            node_hash(&node, zero)
+        };
+        size >>= 1;
+    }
+    node
+}
+
+/// Proof of the leaf at `index` in a tree of the given leaves, padded with
+/// empty leaves to `depth`
+pub fn proof(leaves: &[[u8; 32]], index: usize, depth: usize) -> Vec<[u8; 32]> {
+    let mut level = leaves.to_vec();
+    let mut zero = [0; 32];
+    let mut index = index;
+    let mut proof = Vec::with_capacity(depth);
+    for _ in 0..depth {
+        proof.push(level.get(index ^ 1).copied().unwrap_or(zero));
+        level = level
+            .chunks(2)
+            .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&zero)))
+            .collect();
+        zero = node_hash(&zero, &zero);
+        index >>= 1;
+    }
+    proof
+}
+
+/// Whether a proof links the leaf at `index` to the root
+pub fn verify(root: &[u8; 32], leaf: [u8; 32], index: u64, proof: &[[u8; 32]]) -> bool {
+    if proof.len() < 64 && index >> proof.len() != 0 {
+        return false;
+    }
+    let node = proof
+        .iter()
+        .enumerate()
+        .fold(leaf, |node, (height, sibling)| {
+            if (index >> height) & 1 == 1 {
+                node_hash(sibling, &node)
+            } else {
+                node_hash(&node, sibling)
+            }
+        });
+    node == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_tree() {
+        let leaves: Vec<[u8; 32]> = (0..5u64)
+            .map(|amount| leaf_hash(&Pubkey::new_unique(), amount))
+            .collect();
+
+        let mut branch = [[0; 32]; SNAPSHOT_DEPTH];
+        let empty = root(&branch, 0);
+        assert!(verify(&empty, [0; 32], 0, &proof(&[], 0, SNAPSHOT_DEPTH)));
+
+        for (count, leaf) in leaves.iter().enumerate() {
+            assert!(append(&mut branch, count as u64, *leaf));
+        }
+        let root = root(&branch, leaves.len() as u64);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = proof(&leaves, index, SNAPSHOT_DEPTH);
+            assert!(verify(&root, *leaf, index as u64, &proof));
+            // not at another index or for another leaf
+            assert!(!verify(&root, *leaf, index as u64 ^ 1, &proof));
+            assert!(!verify(
+                &root,
+                leaves[(index + 1) % 5],
+                index as u64,
+                &proof
+            ));
+        }
+        // nor past the depth of the proof
+        let proof = proof(&leaves, 0, SNAPSHOT_DEPTH);
+        assert!(!verify(&root, leaves[0], 1 << SNAPSHOT_DEPTH, &proof));
+
+        assert!(!append(&mut branch, 1 << SNAPSHOT_DEPTH, leaves[0]));
+    }
+}
@@ -0,0 +1,180 @@
+//! Metaplex NFT metadata, read to check the collection of NFTs registered
+//! for reward boosts.
+//!
+//! Only the fields up to the collection are read, walking the Borsh layout of
+//! `Metadata` accounts, so the program doesn't depend on the Metaplex crates.
+
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::convert::TryInto;
+
+use crate::error::CustomError;
+
+/// Account key of v1 metadata accounts
+pub const METADATA_V1_KEY: u8 = 4;
+
+/// Bytes of each creator of the creator list
+const CREATOR_LEN: usize = 34;
+
+/// Collection of an NFT, only trusted once verified by the collection
+/// authority
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+/// Fields of a metadata account the program reads
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NftMetadata {
+    pub mint: Pubkey,
+    pub collection: Option<Collection>,
+}
+
+impl NftMetadata {
+    /// Read a Metaplex metadata account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader = Reader(data);
+        if reader.read(1)?[0] != METADATA_V1_KEY {
+            return Err(CustomError::InvalidCollection.into());
+        }
+        // update authority
+        reader.read(32)?;
+        let mint = reader.read_pubkey()?;
+        // name, symbol and uri
+        for _ in 0..3 {
+            let len = reader.read_u32()?;
+            reader.read(len as usize)?;
+        }
+        // seller fee basis points
+        reader.read(2)?;
+        if reader.read_option()? {
+            let count = reader.read_u32()? as usize;
+            reader.read(
+                count
+                    .checked_mul(CREATOR_LEN)
+                    .ok_or(CustomError::InvalidCollection)?,
+            )?;
+        }
+        // primary sale happened and is mutable
+        reader.read(2)?;
+        // edition nonce and token standard
+        for _ in 0..2 {
+            if reader.read_option()? {
+                reader.read(1)?;
+            }
+        }
+        let collection = if reader.read_option()? {
+            let verified = reader.read(1)?[0] != 0;
+            let key = reader.read_pubkey()?;
+            Some(Collection { verified, key })
+        } else {
+            None
+        };
+
+        Ok(Self { mint, collection })
+    }
+
+    /// Whether the NFT is a verified member of `collection`
+    pub fn is_verified_in(&self, collection: &Pubkey) -> bool {
+        matches!(self.collection, Some(Collection { verified: true, key }) if key == *collection)
+    }
+}
+
+/// Cursor over Borsh data, failing with `InvalidCollection` past the end
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8], ProgramError> {
+        if self.0.len() < len {
+            return Err(CustomError::InvalidCollection.into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ProgramError> {
+        Ok(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+    }
+
+    fn read_pubkey(&mut self) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::new_from_array(self.read(32)?.try_into().unwrap()))
+    }
+
+    fn read_option(&mut self) -> Result<bool, ProgramError> {
+        match self.read(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(CustomError::InvalidCollection.into()),
+        }
+    }
+}
+
+/// Data of a metadata account of `mint`, for tests
+#[cfg(test)]
+pub(crate) fn nft_metadata_account(mint: &Pubkey, collection: Option<Collection>) -> Vec<u8> {
+    let mut data = vec![METADATA_V1_KEY];
+    data.extend_from_slice(Pubkey::new_unique().as_ref());
+    data.extend_from_slice(mint.as_ref());
+    for field in [&b"Staker #1"[..], b"STK", b"https://example.com/1.json"] {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field);
+    }
+    data.extend_from_slice(&500u16.to_le_bytes());
+    // one creator
+    data.push(1);
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&[7u8; CREATOR_LEN]);
+    data.extend_from_slice(&[1, 1]);
+    // edition nonce, no token standard
+    data.extend_from_slice(&[1, 255, 0]);
+    match collection {
+        Some(collection) => {
+            data.extend_from_slice(&[1, collection.verified as u8]);
+            data.extend_from_slice(collection.key.as_ref());
+        }
+        None => data.push(0),
+    }
+    // uses and the rest of the account
+    data.resize(679, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nft_metadata() {
+        let mint = Pubkey::new_unique();
+        let collection = Pubkey::new_unique();
+        let data = nft_metadata_account(
+            &mint,
+            Some(Collection {
+                verified: true,
+                key: collection,
+            }),
+        );
+        let metadata = NftMetadata::unpack(&data).unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert!(metadata.is_verified_in(&collection));
+        assert!(!metadata.is_verified_in(&Pubkey::new_unique()));
+
+        let unverified = NftMetadata::unpack(&nft_metadata_account(
+            &mint,
+            Some(Collection {
+                verified: false,
+                key: collection,
+            }),
+        ))
+        .unwrap();
+        assert!(!unverified.is_verified_in(&collection));
+        let none = NftMetadata::unpack(&nft_metadata_account(&mint, None)).unwrap();
+        assert_eq!(none.collection, None);
+
+        assert_eq!(
+            NftMetadata::unpack(&data[..80]),
+            Err(CustomError::InvalidCollection.into())
+        );
+    }
+}
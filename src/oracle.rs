@@ -0,0 +1,167 @@
+//! Oracle prices of oracle-priced pools, whose rewards are owed in a quote
+//! currency (e.g. USD) and converted to reward tokens when claimed.
+//!
+//! Prices are read from Pyth v2 price accounts. The pool pins the price
+//! account by key, so the account owner is not checked, keeping the same
+//! program usable against the Pyth deployments of each cluster.
+
+use arrayref::array_ref;
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError};
+use std::convert::TryFrom;
+
+use crate::{
+    error::CustomError,
+    math::{self, BPS_DENOMINATOR},
+};
+
+/// Magic number opening every Pyth account
+pub const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+
+/// Supported version of the Pyth account layout
+pub const PYTH_VERSION: u32 = 2;
+
+/// Pyth account type of price accounts
+pub const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+
+/// Pyth status of a price currently trading
+pub const PYTH_STATUS_TRADING: u32 = 1;
+
+/// Bytes of a Pyth price account read, up to the aggregate price
+pub const PYTH_PRICE_ACCOUNT_LEN: usize = 240;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+
+/// Aggregate price of a price account, worth `price * 10^expo` quote units
+/// per whole token
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Price {
+    pub price: i64,
+    /// Confidence interval around the price, in the same units
+    pub conf: u64,
+    pub expo: i32,
+    pub status: u32,
+    /// Unix timestamp the aggregate price was published at
+    pub publish_time: UnixTimestamp,
+}
+
+impl Price {
+    /// Read the aggregate price of a Pyth price account
+    pub fn unpack_pyth(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < PYTH_PRICE_ACCOUNT_LEN {
+            return Err(CustomError::InvalidPriceOracle.into());
+        }
+        let read_u32 = |offset| u32::from_le_bytes(*array_ref![data, offset, 4]);
+        if read_u32(MAGIC_OFFSET) != PYTH_MAGIC
+            || read_u32(VERSION_OFFSET) != PYTH_VERSION
+            || read_u32(ACCOUNT_TYPE_OFFSET) != PYTH_PRICE_ACCOUNT_TYPE
+        {
+            return Err(CustomError::InvalidPriceOracle.into());
+        }
+
+        Ok(Self {
+            price: i64::from_le_bytes(*array_ref![data, AGG_PRICE_OFFSET, 8]),
+            conf: u64::from_le_bytes(*array_ref![data, AGG_CONF_OFFSET, 8]),
+            expo: i32::from_le_bytes(*array_ref![data, EXPO_OFFSET, 4]),
+            status: read_u32(AGG_STATUS_OFFSET),
+            publish_time: i64::from_le_bytes(*array_ref![data, TIMESTAMP_OFFSET, 8]),
+        })
+    }
+
+    /// Check the price is trading, positive, at most `max_age` seconds old and
+    /// with a confidence interval of at most `max_conf_bps` of the price
+    pub fn check(
+        &self,
+        now: UnixTimestamp,
+        max_age: u64,
+        max_conf_bps: u16,
+    ) -> Result<(), ProgramError> {
+        if self.status != PYTH_STATUS_TRADING || self.price <= 0 {
+            return Err(CustomError::StalePrice.into());
+        }
+        let age = now.saturating_sub(self.publish_time);
+        if age < 0 || age as u64 > max_age {
+            return Err(CustomError::StalePrice.into());
+        }
+        if self.conf as u128 * BPS_DENOMINATOR as u128 > self.price as u128 * max_conf_bps as u128 {
+            return Err(CustomError::PriceConfidenceTooWide.into());
+        }
+        Ok(())
+    }
+
+    /// Convert an amount of quote units into tokens of the same decimals,
+    /// rounding down
+    pub fn quote_to_tokens(&self, quote: u64) -> Result<u64, ProgramError> {
+        let price = u64::try_from(self.price).map_err(|_| CustomError::StalePrice)?;
+        Ok(math::quote_to_tokens(quote, price, self.expo)?)
+    }
+}
+
+/// Data of a trading Pyth price account, for tests
+#[cfg(test)]
+pub(crate) fn pyth_price_account(price: i64, conf: u64, expo: i32, publish_time: i64) -> Vec<u8> {
+    let mut data = vec![0u8; 3312];
+    data[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+    data[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+    data[ACCOUNT_TYPE_OFFSET..ACCOUNT_TYPE_OFFSET + 4]
+        .copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+    data[EXPO_OFFSET..EXPO_OFFSET + 4].copy_from_slice(&expo.to_le_bytes());
+    data[TIMESTAMP_OFFSET..TIMESTAMP_OFFSET + 8].copy_from_slice(&publish_time.to_le_bytes());
+    data[AGG_PRICE_OFFSET..AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+    data[AGG_CONF_OFFSET..AGG_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+    data[AGG_STATUS_OFFSET..AGG_STATUS_OFFSET + 4]
+        .copy_from_slice(&PYTH_STATUS_TRADING.to_le_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyth_price() {
+        // $2.50 with a confidence of 1 cent
+        let data = pyth_price_account(250_000_000, 1_000_000, -8, 1_000);
+        let price = Price::unpack_pyth(&data).unwrap();
+        assert_eq!(price.price, 250_000_000);
+        assert_eq!(price.expo, -8);
+        assert_eq!(price.publish_time, 1_000);
+
+        // $10 buys 4 tokens
+        assert_eq!(price.quote_to_tokens(10_000_000).unwrap(), 4_000_000);
+
+        assert_eq!(price.check(1_030, 30, 100), Ok(()));
+        assert_eq!(
+            price.check(1_031, 30, 100),
+            Err(CustomError::StalePrice.into())
+        );
+        assert_eq!(
+            price.check(1_000, 30, 10),
+            Err(CustomError::PriceConfidenceTooWide.into())
+        );
+
+        let mut not_trading = price;
+        not_trading.status = 0;
+        assert_eq!(
+            not_trading.check(1_000, 30, 100),
+            Err(CustomError::StalePrice.into())
+        );
+
+        let mut data = data;
+        data[MAGIC_OFFSET] ^= 1;
+        assert_eq!(
+            Price::unpack_pyth(&data),
+            Err(CustomError::InvalidPriceOracle.into())
+        );
+        assert_eq!(
+            Price::unpack_pyth(&[0u8; 16]),
+            Err(CustomError::InvalidPriceOracle.into())
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! Protocol-wide pause of stakes and claims. The guardian of the global config
+//! halts them across all pools at once, unstakes without auto-claim staying
+//! open so stakers can always leave.
+//!
+//! Pausable instructions take the global config as their last account, after
+//! the instructions sysvar of top-level-only pools. It is required even before
+//! the config is created, so the pause can't be dodged by leaving it out.
+
+use solana_program::{
+    account_info::AccountInfo, msg, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+
+use crate::{error::CustomError, pda::find_global_config_address, state::GlobalConfig};
+
+/// Split the global config off the accounts of a pausable instruction, where
+/// it is the last account, and check that stakes and claims aren't paused
+pub fn split_global_config<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<&'a [AccountInfo<'b>], ProgramError> {
+    let (global_config_info, rest) = match accounts.split_last() {
+        Some((last, rest)) if *last.key == find_global_config_address(program_id).0 => (last, rest),
+        _ => {
+            msg!("Global config missing");
+            return Err(CustomError::InvalidGlobalConfig.into());
+        }
+    };
+
+    // a config not created yet pauses nothing
+    if global_config_info.owner == program_id
+        && GlobalConfig::unpack(&global_config_info.data.borrow())?.paused
+    {
+        return Err(CustomError::ProtocolPaused.into());
+    }
+    Ok(rest)
+}
@@ -0,0 +1,121 @@
+//! Program derived addresses of a stake pool
+
+use solana_program::{bpf_loader_upgradeable, pubkey, pubkey::Pubkey};
+
+use crate::state::{
+    ClaimSplit, DistributionReport, GlobalConfig, Pool, StakeUser, BONUS_AUTHORITY_SEED,
+    BOOST_ESCROW_SEED, FEE_VAULT_SEED, RENT_VAULT_SEED,
+};
+
+/// Associated token account program, owning the canonical token account of
+/// each wallet and mint
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Find the authority of the stake pool, owning its token accounts and
+/// minting rewards. The bump seed goes into `InitData` when creating the pool.
+pub fn find_pool_authority_address(
+    program_id: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pool::authority(program_id, stake_pool_pubkey)
+}
+
+/// Find the stake user of an owner in a stake pool, created by the program
+/// with CreateStakeUserIdempotent
+pub fn find_stake_user_address(
+    program_id: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    StakeUser::address(program_id, stake_pool_pubkey, owner_pubkey)
+}
+
+/// Find the address of the program-wide config
+pub fn find_global_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    GlobalConfig::address(program_id)
+}
+
+/// Find the program data account of a program deployed with the upgradeable
+/// loader, holding its upgrade authority
+pub fn find_program_data_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id())
+}
+
+/// Find the lamport fee vault address of the stake pool
+pub fn find_fee_vault_address(program_id: &Pubkey, stake_pool_pubkey: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[stake_pool_pubkey.as_ref(), FEE_VAULT_SEED], program_id)
+}
+
+/// Find the lamport vault of the stake pool paying the rent of sponsored stake
+/// users
+pub fn find_rent_vault_address(program_id: &Pubkey, stake_pool_pubkey: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[stake_pool_pubkey.as_ref(), RENT_VAULT_SEED], program_id)
+}
+
+/// Find the authority owning the vault of a bonus campaign
+pub fn find_bonus_authority_address(
+    program_id: &Pubkey,
+    bonus_campaign_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[bonus_campaign_pubkey.as_ref(), BONUS_AUTHORITY_SEED],
+        program_id,
+    )
+}
+
+/// Find the authority of the boost NFT escrow of a stake user, owning the
+/// escrow apart from the pool authority so Sweep can't reach it
+pub fn find_boost_escrow_authority_address(
+    program_id: &Pubkey,
+    stake_user_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[stake_user_pubkey.as_ref(), BOOST_ESCROW_SEED], program_id)
+}
+
+/// Find the distribution report of the stake pool for an epoch, created by
+/// FinalizeDistributionReport
+pub fn find_distribution_report_address(
+    program_id: &Pubkey,
+    stake_pool_pubkey: &Pubkey,
+    epoch: u64,
+) -> (Pubkey, u8) {
+    DistributionReport::address(program_id, stake_pool_pubkey, epoch)
+}
+
+/// Find the claim split of a stake user, created by SetClaimSplit
+pub fn find_claim_split_address(program_id: &Pubkey, stake_user_pubkey: &Pubkey) -> (Pubkey, u8) {
+    ClaimSplit::address(program_id, stake_user_pubkey)
+}
+
+/// Find the associated token account of a wallet for a mint
+pub fn find_associated_token_address(
+    wallet_pubkey: &Pubkey,
+    mint_pubkey: &Pubkey,
+    token_program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            wallet_pubkey.as_ref(),
+            token_program_id.as_ref(),
+            mint_pubkey.as_ref(),
+        ],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+}
+
+/// Metaplex token metadata program, owning the metadata of NFTs
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey =
+    pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// Find the Metaplex metadata account of a mint
+pub fn find_metadata_address(mint_pubkey: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            TOKEN_METADATA_PROGRAM_ID.as_ref(),
+            mint_pubkey.as_ref(),
+        ],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+}
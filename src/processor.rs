@@ -1,38 +1,251 @@
+use arrayref::array_ref;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     clock::Clock,
     entrypoint::ProgramResult,
     instruction::Instruction,
-    program::{invoke, invoke_signed},
+    msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_option::COption,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
     rent::Rent,
+    system_instruction, system_program,
     sysvar::Sysvar,
 };
 use spl_token::state::{Account, Mint};
+use spl_token_2022::extension::{
+    non_transferable::NonTransferable, transfer_hook, BaseStateWithExtensions, StateWithExtensions,
+};
+use spl_transfer_hook_interface::onchain::add_cpi_accounts_for_execute;
+use std::convert::TryFrom;
 
 use crate::{
+    context::{
+        AdminAccounts, ClaimAccounts, ClaimAndStakeAccounts, MintBadgeAccounts, PairedAccounts,
+        RegisterBoostAccounts, RewardAccounts, StakeAccounts, UnregisterBoostAccounts,
+        UnstakeAccounts,
+    },
     error::CustomError,
-    instruction::{InitData, InstructionType, StakeData},
-    state::{InitStakeUserParams, Pool, StakeUser},
+    event::{Event, Invariant},
+    guard::{assert_caller, split_instructions_sysvar, split_instructions_sysvar_at},
+    hook::{invoke_hook, split_hook_accounts, HookAction, HookData},
+    instruction::{
+        AdminData, BadgeMintData, BonusClaimData, BonusData, BoostCollectionData, ClaimBurnData,
+        ClaimSplitData, DeprecateData, EpochRateData, FundData, GcDelayData, GlobalConfigData,
+        HarvestAllData, HookProgramData, InitData, InstructionType, LockTiersData,
+        MaxRewardSupplyData, MaxStakePerUserData, PairRatioData, PairStakeData, PoolInfo,
+        PriceOracleData, ProtocolPauseData, RateControllerData, RateData, RefreshCounts,
+        ReleaseRewardMintData, RelockData, RentVaultWithdrawData, RewardBudgetData, RoleData,
+        RoundingData, StakeData, StakeMintData, StakeSnapshotData, StakeSnapshotUpdateData,
+        StreakBonusData, StreamDurationData, SweepData, TiersData, TopLevelOnlyData, WarmUpData,
+        MAX_REFRESH_BATCH,
+    },
+    interest, math, merkle,
+    oracle::Price,
+    pause::split_global_config,
+    pda::{
+        find_bonus_authority_address, find_claim_split_address, find_distribution_report_address,
+        find_fee_vault_address, find_global_config_address, find_program_data_address,
+        find_rent_vault_address, find_stake_user_address,
+    },
+    state::{
+        BonusCampaign, ClaimShare, ClaimSplit, DistributionReport, GlobalConfig,
+        InitStakeUserParams, LockTier, Pool, PoolSnapshot, RewardMode, Role, Rounding,
+        StakeSnapshot, StakeUser, Tier, BONUS_AUTHORITY_SEED, BPS_DENOMINATOR, CLAIM_SPLIT_SEED,
+        DISTRIBUTION_REPORT_SEED, GLOBAL_CONFIG_SEED, MAX_CLAIM_SHARES, MAX_INTEGRATORS,
+        MAX_LOCK_TIERS, MAX_TIERS, POOL_FLAG_NFT_BOOST, POOL_FLAG_ORACLE_PRICED, RENT_VAULT_SEED,
+        STAKE_USER_SEED,
+    },
+    transfer_hook::split_transfer_hook_accounts,
 };
 
+/// Log via `msg!` when built with the `trace` feature, compiled out otherwise
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace")]
+        msg!($($arg)*);
+    };
+}
+
 pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
     let instruction = InstructionType::unpack(input)?;
+    let accounts = if instruction.is_pausable() {
+        split_global_config(program_id, accounts)?
+    } else {
+        accounts
+    };
 
     match instruction {
         InstructionType::CreatePool(init_data) => {
             process_create_stake_pool(program_id, accounts, init_data)
         }
         InstructionType::CreateStakeUser => process_create_stake_user(program_id, accounts),
-        InstructionType::Stake(StakeData { amount }) => process_stake(program_id, accounts, amount),
-        InstructionType::Unstake(StakeData { amount }) => {
-            process_unstake(program_id, accounts, amount)
+        InstructionType::Stake(StakeData { amount, auto_claim }) => {
+            process_stake(program_id, accounts, amount, auto_claim, false)
+        }
+        InstructionType::Unstake(StakeData { amount, auto_claim }) => {
+            process_unstake(program_id, accounts, amount, auto_claim)
         }
 
-        InstructionType::Claim => process_claim(program_id, accounts),
+        InstructionType::Claim => process_claim(program_id, accounts, false),
         InstructionType::Refresh => process_refresh(program_id, accounts),
+        InstructionType::RefreshBatch => process_refresh_batch(program_id, accounts),
+        InstructionType::SetTopLevelOnly(TopLevelOnlyData {
+            top_level_only,
+            integrators,
+        }) => process_set_top_level_only(program_id, accounts, top_level_only, &integrators),
+        InstructionType::RepairUser => process_repair_user(program_id, accounts),
+        InstructionType::AddRewardBudget(RewardBudgetData { amount }) => {
+            process_add_reward_budget(program_id, accounts, amount)
+        }
+        InstructionType::SetMaxRewardSupply(MaxRewardSupplyData { max_reward_supply }) => {
+            process_set_max_reward_supply(program_id, accounts, max_reward_supply)
+        }
+        InstructionType::ReleaseRewardMint(ReleaseRewardMintData { new_authority }) => {
+            process_release_reward_mint(program_id, accounts, new_authority)
+        }
+        InstructionType::SetGlobalConfig(data) => {
+            process_set_global_config(program_id, accounts, data)
+        }
+        InstructionType::CreateStakeUserIdempotent => {
+            process_create_stake_user_idempotent(program_id, accounts)
+        }
+        InstructionType::HarvestAll(HarvestAllData { account_counts }) => {
+            process_harvest_all(program_id, accounts, &account_counts)
+        }
+        InstructionType::GetPoolInfo => process_get_pool_info(program_id, accounts),
+        InstructionType::SetStreamDuration(StreamDurationData { stream_duration }) => {
+            process_set_stream_duration(program_id, accounts, stream_duration)
+        }
+        InstructionType::WithdrawFromStream => process_withdraw_from_stream(program_id, accounts),
+        InstructionType::SetWarmUp(WarmUpData {
+            warm_up_duration,
+            warm_up_rate_bps,
+        }) => process_set_warm_up(program_id, accounts, warm_up_duration, warm_up_rate_bps),
+        InstructionType::SetRateController(data) => {
+            process_set_rate_controller(program_id, accounts, data)
+        }
+        InstructionType::RebalanceRate => process_rebalance_rate(program_id, accounts),
+        InstructionType::MergePositions => process_merge_positions(program_id, accounts),
+        InstructionType::SetMaxStakePerUser(MaxStakePerUserData { max_stake_per_user }) => {
+            process_set_max_stake_per_user(program_id, accounts, max_stake_per_user)
+        }
+        InstructionType::SetGcDelay(GcDelayData { gc_delay }) => {
+            process_set_gc_delay(program_id, accounts, gc_delay)
+        }
+        InstructionType::GcUser => process_gc_user(program_id, accounts),
+        InstructionType::FundRentVault(FundData { amount }) => {
+            process_fund_rent_vault(program_id, accounts, amount)
+        }
+        InstructionType::WithdrawRentVault(RentVaultWithdrawData { amount }) => {
+            process_withdraw_rent_vault(program_id, accounts, amount)
+        }
+        InstructionType::CreateSponsoredStakeUser => {
+            process_create_sponsored_stake_user(program_id, accounts)
+        }
+        InstructionType::SetProtocolPause(ProtocolPauseData { paused }) => {
+            process_set_protocol_pause(program_id, accounts, paused)
+        }
+        InstructionType::SetRole(RoleData { role, holder }) => {
+            process_set_role(program_id, accounts, role, holder)
+        }
+        InstructionType::SetRounding(RoundingData { rounding }) => {
+            process_set_rounding(program_id, accounts, rounding)
+        }
+        InstructionType::ClaimIfAny => process_claim(program_id, accounts, true),
+        InstructionType::FundRewards(FundData { amount }) => {
+            process_fund_rewards(program_id, accounts, amount)
+        }
+        InstructionType::Sweep(SweepData { amount }) => process_sweep(program_id, accounts, amount),
+        InstructionType::SyncReserve => process_sync_reserve(program_id, accounts),
+        InstructionType::Deprecate(DeprecateData { successor }) => {
+            process_deprecate(program_id, accounts, successor)
+        }
+        InstructionType::SetClaimBurn(ClaimBurnData { claim_burn_bps }) => {
+            process_set_claim_burn(program_id, accounts, claim_burn_bps)
+        }
+        InstructionType::SetEpochRate(epoch_rate_data) => {
+            process_set_epoch_rate(program_id, accounts, epoch_rate_data)
+        }
+        InstructionType::SetRewardRate(rate_data) => {
+            process_set_reward_rate(program_id, accounts, rate_data)
+        }
+        InstructionType::SnapshotPool => process_snapshot_pool(program_id, accounts),
+        InstructionType::FinalizeDistributionReport => {
+            process_finalize_distribution_report(program_id, accounts)
+        }
+        InstructionType::SetClaimSplit(ClaimSplitData { shares }) => {
+            process_set_claim_split(program_id, accounts, shares)
+        }
+        InstructionType::SetLockTiers(LockTiersData { lock_tiers }) => {
+            process_set_lock_tiers(program_id, accounts, lock_tiers)
+        }
+        InstructionType::Relock(RelockData { lock_tier }) => {
+            process_relock(program_id, accounts, lock_tier)
+        }
+        InstructionType::VerifyInvariants => process_verify_invariants(program_id, accounts),
+        InstructionType::StakeWithDelegate(StakeData { amount, auto_claim }) => {
+            process_stake(program_id, accounts, amount, auto_claim, true)
+        }
+        InstructionType::ClaimFor => process_claim_for(program_id, accounts),
+        InstructionType::ClaimAndStakeInto => process_claim_and_stake_into(program_id, accounts),
+        InstructionType::SetHookProgram(HookProgramData { hook_program }) => {
+            process_set_hook_program(program_id, accounts, hook_program)
+        }
+        InstructionType::SetPriceOracle(data) => {
+            process_set_price_oracle(program_id, accounts, data)
+        }
+        InstructionType::AddStakeMint(StakeMintData { weight_bps }) => {
+            process_add_stake_mint(program_id, accounts, weight_bps)
+        }
+        InstructionType::SetPairRatio(PairRatioData {
+            pair_numerator,
+            pair_denominator,
+        }) => process_set_pair_ratio(program_id, accounts, pair_numerator, pair_denominator),
+        InstructionType::StakePair(PairStakeData { amount }) => {
+            process_stake_pair(program_id, accounts, amount)
+        }
+        InstructionType::UnstakePair(PairStakeData { amount }) => {
+            process_unstake_pair(program_id, accounts, amount)
+        }
+        InstructionType::SetBoostCollection(BoostCollectionData {
+            boost_collection,
+            boost_bps,
+        }) => process_set_boost_collection(program_id, accounts, boost_collection, boost_bps),
+        InstructionType::RegisterBoost => process_register_boost(program_id, accounts),
+        InstructionType::UnregisterBoost => process_unregister_boost(program_id, accounts),
+        InstructionType::SetTiers(TiersData { tiers }) => {
+            process_set_tiers(program_id, accounts, tiers)
+        }
+        InstructionType::SetBadgeMint(BadgeMintData { badge_mint }) => {
+            process_set_badge_mint(program_id, accounts, badge_mint)
+        }
+        InstructionType::MintBadge => process_mint_badge(program_id, accounts),
+        InstructionType::SetStreakBonus(StreakBonusData {
+            streak_duration,
+            streak_bonus_bps,
+        }) => process_set_streak_bonus(program_id, accounts, streak_duration, streak_bonus_bps),
+        InstructionType::CloseStakeUser => process_close_stake_user(program_id, accounts),
+        InstructionType::CreateStakeSnapshot(StakeSnapshotData { timestamp }) => {
+            process_create_stake_snapshot(program_id, accounts, timestamp)
+        }
+        InstructionType::UpdateStakeSnapshot(StakeSnapshotUpdateData { finalize }) => {
+            process_update_stake_snapshot(program_id, accounts, finalize)
+        }
+        InstructionType::PublishBonus(BonusData { root, leaf_count }) => {
+            process_publish_bonus(program_id, accounts, root, leaf_count)
+        }
+        InstructionType::ClaimBonus(BonusClaimData {
+            index,
+            amount,
+            proof,
+        }) => process_claim_bonus(program_id, accounts, index, amount, &proof),
+        InstructionType::SetAdmin(AdminData { admin }) => {
+            process_set_admin(program_id, accounts, admin)
+        }
     }
 }
 
@@ -51,29 +264,84 @@ pub fn process_create_stake_pool(
     let rent_info = next_account_info(account_info_iter)?;
     let rent = &Rent::from_account_info(rent_info)?;
     let token_program_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let global_config_info = next_account_info(account_info_iter)?;
+    let treasury_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let reward_token_vault_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "CreatePool: {:?} pool {} admin {} stake mint {} reward mint {}",
+        init_data,
+        stake_pool_info.key,
+        admin_info.key,
+        staking_token_mint_info.key,
+        reward_token_mint_info.key
+    );
 
-    if stake_pool_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
-    }
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_signer(accounts, admin_info)?;
 
     assert_rent_exempt(rent, stake_pool_info)?;
     let mut stake_pool = assert_uninitialized::<Pool>(stake_pool_info)?;
-
-    let authority_signer_seeds = &[stake_pool_info.key.as_ref(), &[init_data.bump_seed]];
-    if *stake_pool_authority_info.key
-        != Pubkey::create_program_address(authority_signer_seeds, program_id)?
-    {
-        return Err(CustomError::InvalidPoolAuthority.into());
+    if !Pool::is_valid_reward_period(init_data.reward_period) {
+        return Err(CustomError::InvalidRewardPeriod.into());
+    }
+    if init_data.reward_denominator == 0 {
+        return Err(CustomError::InvalidRewardRate.into());
     }
+    let stake_mint = unpack_mint(staking_token_mint_info, token_program_info.key)?;
+    if init_data.reject_freeze_authority && stake_mint.freeze_authority.is_some() {
+        return Err(reject_account(
+            accounts,
+            staking_token_mint_info,
+            "stake mint with a freeze authority",
+            CustomError::InvalidFreezeAuthority,
+        ));
+    }
+    let global_config = load_global_config(program_id, accounts, global_config_info)?;
+    global_config.check_reward_rate(
+        init_data.reward_numerator,
+        init_data.reward_denominator,
+        init_data.reward_period,
+    )?;
+
+    // a bump seed off the curve derives no address at all
+    let authority_signer_seeds = &Pool::authority_seeds(stake_pool_info.key, &init_data.bump_seed);
+    let authority_pubkey = Pubkey::create_program_address(authority_signer_seeds, program_id)
+        .map_err(|_| CustomError::InvalidPoolAuthority)?;
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &authority_pubkey,
+        CustomError::InvalidPoolAuthority,
+    )?;
+
+    pay_pool_creation_fee(
+        accounts,
+        &global_config,
+        payer_info,
+        treasury_info,
+        system_program_info,
+    )?;
+
+    let stake_decimals = stake_mint.decimals;
 
     stake_pool.is_initialized = true;
     stake_pool.bump_seed = init_data.bump_seed;
     stake_pool.stake_token_mint = *staking_token_mint_info.key;
+    stake_pool.stake_decimals = stake_decimals;
     stake_pool.reserved = *staking_token_reserve_info.key;
     stake_pool.reward_mint = *reward_token_mint_info.key;
     stake_pool.reward_numerator = init_data.reward_numerator;
     stake_pool.reward_denominator = init_data.reward_denominator;
-    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+    stake_pool.reward_period = init_data.reward_period;
+    stake_pool.claim_burn_bps = global_config.default_claim_burn_bps;
+    stake_pool.admin = *admin_info.key;
+    if let Some(reward_token_vault_info) = reward_token_vault_info {
+        stake_pool.reward_mode = RewardMode::TransferFromVault;
+        stake_pool.reward_vault = *reward_token_vault_info.key;
+    }
 
     spl_token_init_account(TokenInitializeAccountParams {
         account: staking_token_reserve_info.clone(),
@@ -83,13 +351,48 @@ pub fn process_create_stake_pool(
         token_program: token_program_info.clone(),
     })?;
 
-    spl_token_init_mint(TokenInitializeMintParams {
-        mint: reward_token_mint_info.clone(),
-        authority: stake_pool_authority_info.key,
-        rent: rent_info.clone(),
-        decimals: 9,
-        token_program: token_program_info.clone(),
-    })?;
+    if let Some(reward_token_vault_info) = reward_token_vault_info {
+        stake_pool.reward_decimals =
+            unpack_mint(reward_token_mint_info, token_program_info.key)?.decimals;
+        spl_token_init_account(TokenInitializeAccountParams {
+            account: reward_token_vault_info.clone(),
+            mint: reward_token_mint_info.clone(),
+            owner: stake_pool_authority_info.clone(),
+            rent: rent_info.clone(),
+            token_program: token_program_info.clone(),
+        })?;
+    } else if reward_token_mint_info.owner == &spl_token_2022::id()
+        && unpack_mint(reward_token_mint_info, &spl_token_2022::id()).is_ok()
+    {
+        // Token-2022 mint extensions must be initialized before the mint, so
+        // the client creates the mint with the pool authority minting
+        let data = reward_token_mint_info.data.borrow();
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+            .map_err(|_| CustomError::InvalidTokenMint)?;
+        if mint.base.mint_authority != COption::Some(*stake_pool_authority_info.key)
+            || mint.base.supply != 0
+            || interest::check_reward_mint_extensions(&data).is_err()
+        {
+            return Err(reject_account(
+                accounts,
+                reward_token_mint_info,
+                "not an unminted Token-2022 mint of the pool authority with supported extensions",
+                CustomError::InvalidTokenMint,
+            ));
+        }
+        stake_pool.reward_decimals = mint.base.decimals;
+    } else {
+        // rewards mirror the decimals of the stake token, so both read alike
+        spl_token_init_mint(TokenInitializeMintParams {
+            mint: reward_token_mint_info.clone(),
+            authority: stake_pool_authority_info.key,
+            rent: rent_info.clone(),
+            decimals: stake_decimals,
+            token_program: token_program_info.clone(),
+        })?;
+        stake_pool.reward_decimals = stake_decimals;
+    }
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
 
     Ok(())
 }
@@ -101,722 +404,12197 @@ pub fn process_create_stake_user(program_id: &Pubkey, accounts: &[AccountInfo])
     let stake_user_info = next_account_info(account_info_iter)?;
     let stake_owner_info = next_account_info(account_info_iter)?;
     let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "CreateStakeUser: pool {} user {} owner {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        stake_owner_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
 
-    if stake_pool_info.owner != program_id || stake_user_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    if stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
     }
 
     assert_rent_exempt(rent, stake_user_info)?;
     let mut stake_user = assert_uninitialized::<StakeUser>(stake_user_info)?;
 
-    if !stake_owner_info.is_signer {
-        return Err(CustomError::InvalidSigner.into());
-    }
+    assert_signer(accounts, stake_owner_info)?;
 
     stake_user.init(InitStakeUserParams {
         pool_pubkey: *stake_pool_info.key,
         owner: *stake_owner_info.key,
+        current_ts: Clock::get()?.unix_timestamp,
     });
     StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+    stake_pool.user_count = stake_pool
+        .user_count
+        .checked_add(1)
+        .ok_or(CustomError::CalculationFailure)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
 
     Ok(())
 }
 
-/// For Task 1: do stake
-pub fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+/// Create the stake user of an owner at its program address, or check the
+/// existing one, like the idempotent create of associated token accounts
+pub fn process_create_stake_user_idempotent(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let stake_pool_info = next_account_info(account_info_iter)?;
     let stake_user_info = next_account_info(account_info_iter)?;
-    let user_transfer_authority_info = next_account_info(account_info_iter)?;
     let stake_owner_info = next_account_info(account_info_iter)?;
-    let source_info = next_account_info(account_info_iter)?;
-    let destination_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
-    let token_program_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "CreateStakeUserIdempotent: pool {} user {} owner {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        stake_owner_info.key
+    );
 
-    if stake_pool_info.owner != program_id || stake_user_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
-    }
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    let (stake_user_pubkey, bump_seed) =
+        find_stake_user_address(program_id, stake_pool_info.key, stake_owner_info.key);
+    assert_key(
+        accounts,
+        stake_user_info,
+        &stake_user_pubkey,
+        CustomError::InvalidStakeUser,
+    )?;
 
-    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow_mut())?;
-    if !stake_owner_info.is_signer {
-        return Err(CustomError::InvalidSigner.into());
-    }
-    if stake_user.owner != *stake_owner_info.key {
-        return Err(CustomError::InvalidStakeOwner.into());
-    }
-    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow_mut())?;
-    if stake_pool.reserved != *destination_info.key {
-        return Err(CustomError::InvalidTokenAccount.into());
-    }
-    let source_token = unpack_token_account(source_info, token_program_info.key)?;
-    let destination_token = unpack_token_account(destination_info, token_program_info.key)?;
-    if source_token.mint != destination_token.mint {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    if source_token.mint != stake_pool.stake_token_mint {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    if source_token.amount < amount {
-        return Err(CustomError::InsufficientFunds.into());
+    if stake_user_info.owner == program_id {
+        let data = stake_user_info.data.borrow();
+        if StakeUser::unpack_pool_pubkey(&data)? != *stake_pool_info.key
+            || StakeUser::unpack_owner(&data)? != *stake_owner_info.key
+        {
+            return Err(CustomError::InvalidStakeUser.into());
+        }
+        return Ok(());
     }
 
-    if stake_user.stake_amount != 0 {
-        stake_user.update_reward_owed(
-            stake_pool.reward_numerator,
-            stake_pool.reward_denominator,
-            clock.unix_timestamp,
-        )?;
-    } else {
-        stake_user.last_update = clock.unix_timestamp
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    if stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
     }
 
-    stake_user.stake(amount)?;
+    create_pda_account(
+        rent,
+        payer_info,
+        stake_user_info,
+        system_program_info,
+        StakeUser::LEN,
+        program_id,
+        &[
+            stake_pool_info.key.as_ref(),
+            stake_owner_info.key.as_ref(),
+            STAKE_USER_SEED,
+            &[bump_seed],
+        ],
+    )?;
+    let mut stake_user = StakeUser::default();
+    stake_user.init(InitStakeUserParams {
+        pool_pubkey: *stake_pool_info.key,
+        owner: *stake_owner_info.key,
+        current_ts: Clock::get()?.unix_timestamp,
+    });
     StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
-
-    spl_token_transfer(TokenTransferParams {
-        source: source_info.clone(),
-        destination: destination_info.clone(),
-        amount,
-        authority: user_transfer_authority_info.clone(),
-        authority_signer_seeds: &[],
-        token_program: token_program_info.clone(),
-    })?;
+    stake_pool.user_count = stake_pool
+        .user_count
+        .checked_add(1)
+        .ok_or(CustomError::CalculationFailure)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
 
     Ok(())
 }
 
-/// For Task 1: do unstake
-pub fn process_unstake(
+/// Create the stake user of an owner at its program address, its rent paid by
+/// the rent vault of the pool
+pub fn process_create_sponsored_stake_user(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    amount: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let stake_pool_info = next_account_info(account_info_iter)?;
     let stake_user_info = next_account_info(account_info_iter)?;
-    let stake_pool_authority_info = next_account_info(account_info_iter)?;
     let stake_owner_info = next_account_info(account_info_iter)?;
-    let source_info = next_account_info(account_info_iter)?;
-    let destination_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
-    let token_program_info = next_account_info(account_info_iter)?;
-
-    if stake_pool_info.owner != program_id || stake_user_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
-    }
+    let rent_vault_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "CreateSponsoredStakeUser: pool {} user {} owner {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        stake_owner_info.key
+    );
 
-    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow_mut())?;
-    if !stake_owner_info.is_signer {
-        return Err(CustomError::InvalidSigner.into());
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    // owners sign so the vault only pays for stake users someone stakes with
+    assert_signer(accounts, stake_owner_info)?;
+    let (stake_user_pubkey, bump_seed) =
+        find_stake_user_address(program_id, stake_pool_info.key, stake_owner_info.key);
+    assert_key(
+        accounts,
+        stake_user_info,
+        &stake_user_pubkey,
+        CustomError::InvalidStakeUser,
+    )?;
+    let (rent_vault_pubkey, rent_vault_bump_seed) =
+        find_rent_vault_address(program_id, stake_pool_info.key);
+    assert_key(
+        accounts,
+        rent_vault_info,
+        &rent_vault_pubkey,
+        CustomError::InvalidRentVault,
+    )?;
+    if stake_user_info.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
     }
-    if stake_user.owner != *stake_owner_info.key {
-        return Err(CustomError::InvalidStakeOwner.into());
+    if !system_program::check_id(system_program_info.key) {
+        return Err(ProgramError::IncorrectProgramId);
     }
 
-    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow_mut())?;
-    let stake_pool_authority_signer_seeds =
-        &[stake_pool_info.key.as_ref(), &[stake_pool.bump_seed]];
-    if *stake_pool_authority_info.key
-        != Pubkey::create_program_address(stake_pool_authority_signer_seeds, program_id)?
-    {
-        return Err(CustomError::InvalidPoolAuthority.into());
-    }
-    let source_token = unpack_token_account(source_info, token_program_info.key)?;
-    let destination_token = unpack_token_account(destination_info, token_program_info.key)?;
-    if source_token.mint != destination_token.mint {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    if destination_token.mint != stake_pool.stake_token_mint {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    if source_token.amount < amount {
-        return Err(CustomError::InsufficientLiquidity.into());
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    if stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
     }
 
-    if stake_user.stake_amount != 0 {
-        stake_user.update_reward_owed(
-            stake_pool.reward_numerator,
-            stake_pool.reward_denominator,
-            clock.unix_timestamp,
+    // the rent sent up front, creating the account only allocates and assigns
+    let required = rent
+        .minimum_balance(StakeUser::LEN)
+        .saturating_sub(stake_user_info.lamports());
+    if required > 0 {
+        invoke_signed(
+            &system_instruction::transfer(rent_vault_info.key, stake_user_info.key, required),
+            &[
+                rent_vault_info.clone(),
+                stake_user_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[&[
+                stake_pool_info.key.as_ref(),
+                RENT_VAULT_SEED,
+                &[rent_vault_bump_seed],
+            ]],
         )?;
     }
-
-    stake_user.unstake(amount)?;
+    create_pda_account(
+        rent,
+        rent_vault_info,
+        stake_user_info,
+        system_program_info,
+        StakeUser::LEN,
+        program_id,
+        &[
+            stake_pool_info.key.as_ref(),
+            stake_owner_info.key.as_ref(),
+            STAKE_USER_SEED,
+            &[bump_seed],
+        ],
+    )?;
+    let mut stake_user = StakeUser::default();
+    stake_user.init(InitStakeUserParams {
+        pool_pubkey: *stake_pool_info.key,
+        owner: *stake_owner_info.key,
+        current_ts: Clock::get()?.unix_timestamp,
+    });
+    stake_user.rent_sponsored = true;
     StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+    stake_pool.user_count = stake_pool
+        .user_count
+        .checked_add(1)
+        .ok_or(CustomError::CalculationFailure)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
 
-    spl_token_transfer(TokenTransferParams {
-        source: source_info.clone(),
-        destination: destination_info.clone(),
+    Ok(())
+}
+
+/// Top up the rent vault of the pool with lamports of a funder
+pub fn process_fund_rent_vault(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let funder_info = next_account_info(account_info_iter)?;
+    let rent_vault_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "FundRentVault: amount {} pool {} funder {}",
         amount,
-        authority: stake_pool_authority_info.clone(),
-        authority_signer_seeds: stake_pool_authority_signer_seeds,
-        token_program: token_program_info.clone(),
-    })?;
+        stake_pool_info.key,
+        funder_info.key
+    );
 
-    Ok(())
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_signer(accounts, funder_info)?;
+    assert_key(
+        accounts,
+        rent_vault_info,
+        &find_rent_vault_address(program_id, stake_pool_info.key).0,
+        CustomError::InvalidRentVault,
+    )?;
+    if !system_program::check_id(system_program_info.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke(
+        &system_instruction::transfer(funder_info.key, rent_vault_info.key, amount),
+        &[
+            funder_info.clone(),
+            rent_vault_info.clone(),
+            system_program_info.clone(),
+        ],
+    )
+}
+
+/// Withdraw lamports of the rent vault of the pool to a destination
+pub fn process_withdraw_rent_vault(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Treasurer)?;
+    let account_info_iter = &mut remaining.iter();
+    let rent_vault_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "WithdrawRentVault: amount {} pool {} destination {}",
+        amount,
+        stake_pool_info.key,
+        destination_info.key
+    );
+
+    let (rent_vault_pubkey, bump_seed) = find_rent_vault_address(program_id, stake_pool_info.key);
+    assert_key(
+        accounts,
+        rent_vault_info,
+        &rent_vault_pubkey,
+        CustomError::InvalidRentVault,
+    )?;
+    if !system_program::check_id(system_program_info.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke_signed(
+        &system_instruction::transfer(rent_vault_info.key, destination_info.key, amount),
+        &[
+            rent_vault_info.clone(),
+            destination_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[&[stake_pool_info.key.as_ref(), RENT_VAULT_SEED, &[bump_seed]]],
+    )
 }
 
-/// For task 2: Claim rewards owed
-pub fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// Close an empty stake user and its claim split, if any, refunding their
+/// rent to the destination
+pub fn process_close_stake_user(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let stake_pool_info = next_account_info(account_info_iter)?;
     let stake_user_info = next_account_info(account_info_iter)?;
     let stake_owner_info = next_account_info(account_info_iter)?;
-    let stake_pool_authority_info = next_account_info(account_info_iter)?;
-    let reward_mint_info = next_account_info(account_info_iter)?;
-    let reward_token_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
-    let token_program_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let claim_split_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "CloseStakeUser: pool {} user {} owner {} destination {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        stake_owner_info.key,
+        destination_info.key
+    );
 
-    if stake_pool_info.owner != program_id || stake_user_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
-    }
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+    assert_signer(accounts, stake_owner_info)?;
 
-    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow_mut())?;
-    if stake_user.pool_pubkey != *stake_pool_info.key {
-        return Err(CustomError::InvalidStakeOwner.into());
-    }
-    if stake_user.owner != *stake_owner_info.key {
-        return Err(CustomError::InvalidStakeOwner.into());
-    }
-    if !stake_owner_info.is_signer {
-        return Err(CustomError::InvalidSigner.into());
-    }
-    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
-    let reward_token = unpack_token_account(reward_token_info, token_program_info.key)?;
-    if stake_pool.reward_mint != *reward_mint_info.key {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    if reward_token_info.owner == stake_pool_authority_info.key {
-        return Err(CustomError::InvalidAccountOwner.into());
-    }
-    if reward_token.mint != *reward_mint_info.key {
-        return Err(CustomError::InvalidTokenMint.into());
-    }
-    let stake_pool_authority_signer_seeds =
-        &[stake_pool_info.key.as_ref(), &[stake_pool.bump_seed]];
-    if *stake_pool_authority_info.key
-        != Pubkey::create_program_address(stake_pool_authority_signer_seeds, program_id)?
-    {
-        return Err(CustomError::InvalidPoolAuthority.into());
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    let stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+    assert_key(
+        accounts,
+        stake_owner_info,
+        &stake_user.owner,
+        CustomError::InvalidStakeOwner,
+    )?;
+    if !stake_user.is_empty() {
+        return Err(CustomError::StakeUserNotEmpty.into());
     }
+    assert_rent_refund(
+        program_id,
+        accounts,
+        stake_pool_info,
+        &stake_user,
+        destination_info,
+    )?;
+
+    stake_pool.user_count = stake_pool.user_count.saturating_sub(1);
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    close_claim_split(
+        program_id,
+        accounts,
+        stake_user_info,
+        &stake_user,
+        claim_split_info,
+        destination_info,
+    )?;
+    close_account(stake_user_info, destination_info)
+}
+
+/// Close a stake user left empty for the pool's collection delay, and its
+/// claim split, if any, refunding their rent to the protocol treasury, or to
+/// the rent vault of the pool if it paid the stake user. Anyone can call it.
+pub fn process_gc_user(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let global_config_info = next_account_info(account_info_iter)?;
+    let treasury_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let claim_split_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "GcUser: pool {} user {} treasury {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        treasury_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+    assert_key(
+        accounts,
+        global_config_info,
+        &find_global_config_address(program_id).0,
+        CustomError::InvalidGlobalConfig,
+    )?;
+    assert_owner(accounts, global_config_info, program_id)?;
 
-    if stake_user.stake_amount != 0 {
-        stake_user.update_reward_owed(
-            stake_pool.reward_numerator,
-            stake_pool.reward_denominator,
-            clock.unix_timestamp,
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    let stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+    if stake_user.rent_sponsored {
+        assert_rent_refund(
+            program_id,
+            accounts,
+            stake_pool_info,
+            &stake_user,
+            treasury_info,
+        )?;
+    } else {
+        let global_config = GlobalConfig::unpack(&global_config_info.data.borrow())?;
+        assert_key(
+            accounts,
+            treasury_info,
+            &global_config.treasury,
+            CustomError::InvalidTreasury,
         )?;
     }
+    if !stake_user.is_empty() {
+        return Err(CustomError::StakeUserNotEmpty.into());
+    }
+    if !stake_user.is_stale(&stake_pool, clock.unix_timestamp) {
+        return Err(CustomError::StakeUserNotStale.into());
+    }
 
-    let amount = stake_user.claim()?;
-    StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
-
-    spl_token_mint_to(TokenMintToParams {
-        mint: reward_mint_info.clone(),
-        destination: reward_token_info.clone(),
-        amount,
-        authority: stake_pool_authority_info.clone(),
-        authority_signer_seeds: stake_pool_authority_signer_seeds,
-        token_program: token_program_info.clone(),
-    })?;
+    stake_pool.user_count = stake_pool.user_count.saturating_sub(1);
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
 
-    Ok(())
+    close_claim_split(
+        program_id,
+        accounts,
+        stake_user_info,
+        &stake_user,
+        claim_split_info,
+        treasury_info,
+    )?;
+    close_account(stake_user_info, treasury_info)
 }
 
-pub fn process_refresh(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// Merge a stake user into another of the same owner and pool, closing it and
+/// its claim split, if any
+pub fn process_merge_positions(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let source_stake_user_info = next_account_info(account_info_iter)?;
+    let stake_owner_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
     let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let claim_split_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "MergePositions: pool {} user {} source {} owner {}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        source_stake_user_info.key,
+        stake_owner_info.key
+    );
 
-    if stake_pool_info.owner != program_id {
-        return Err(CustomError::InvalidAccountOwner.into());
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+    assert_owner(accounts, source_stake_user_info, program_id)?;
+    assert_signer(accounts, stake_owner_info)?;
+    if stake_user_info.key == source_stake_user_info.key {
+        return Err(ProgramError::InvalidArgument);
     }
 
-    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
-
-    for stake_user_info in account_info_iter {
-        if stake_user_info.owner != program_id {
-            continue;
-        }
-        let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow_mut())?;
-        if stake_user.pool_pubkey != *stake_pool_info.key {
-            continue;
-        }
-        if stake_user.stake_amount != 0 {
-            stake_user.update_reward_owed(
-                stake_pool.reward_numerator,
-                stake_pool.reward_denominator,
-                clock.unix_timestamp,
-            )?;
-            StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
-        }
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    // the snapshot being built may have taken in either stake user already
+    if stake_pool.snapshot != Pubkey::default() {
+        return Err(CustomError::SnapshotPending.into());
+    }
+    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    let mut source_stake_user = StakeUser::unpack(&source_stake_user_info.data.borrow())?;
+    for stake_user in [&stake_user, &source_stake_user] {
+        assert_key(
+            accounts,
+            stake_pool_info,
+            &stake_user.pool_pubkey,
+            CustomError::InvalidStakeOwner,
+        )?;
+        assert_key(
+            accounts,
+            stake_owner_info,
+            &stake_user.owner,
+            CustomError::InvalidStakeOwner,
+        )?;
     }
 
-    Ok(())
-}
+    assert_rent_refund(
+        program_id,
+        accounts,
+        stake_pool_info,
+        &source_stake_user,
+        destination_info,
+    )?;
 
-pub fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
-    if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
-        Err(CustomError::NotRentExempt.into())
-    } else {
-        Ok(())
-    }
-}
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+    source_stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+    stake_user.merge(&source_stake_user, &stake_pool, clock.unix_timestamp)?;
+    stake_user.check_stake_cap(&stake_pool)?;
+    StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
 
-pub fn assert_uninitialized<T: Pack + IsInitialized>(
-    account_info: &AccountInfo,
-) -> Result<T, ProgramError> {
-    let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
-    if account.is_initialized() {
-        Err(CustomError::AlreadyInUse.into())
-    } else {
-        Ok(account)
+    stake_pool.user_count = stake_pool.user_count.saturating_sub(1);
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    close_claim_split(
+        program_id,
+        accounts,
+        source_stake_user_info,
+        &source_stake_user,
+        claim_split_info,
+        destination_info,
+    )?;
+    close_account(source_stake_user_info, destination_info)
+}
+
+/// Close the claim split of a stake user being closed, refunding its rent to
+/// the destination. Stake users with a claim split must be given it.
+fn close_claim_split(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stake_user_info: &AccountInfo,
+    stake_user: &StakeUser,
+    claim_split_info: Option<&AccountInfo>,
+    destination_info: &AccountInfo,
+) -> ProgramResult {
+    if !stake_user.claim_split {
+        return Ok(());
     }
+    let claim_split_info = claim_split_info.ok_or_else(|| {
+        reject_account(
+            accounts,
+            stake_user_info,
+            "has a claim split, which must close with it",
+            CustomError::InvalidClaimSplit,
+        )
+    })?;
+    assert_key(
+        accounts,
+        claim_split_info,
+        &find_claim_split_address(program_id, stake_user_info.key).0,
+        CustomError::InvalidClaimSplit,
+    )?;
+    assert_owner(accounts, claim_split_info, program_id)?;
+    close_account(claim_split_info, destination_info)
 }
 
-pub fn unpack_mint(
-    account_info: &AccountInfo,
-    token_program_id: &Pubkey,
-) -> Result<Mint, ProgramError> {
-    if account_info.owner != token_program_id {
-        Err(CustomError::InvalidAccountOwner.into())
+/// For Task 1: do stake
+pub fn process_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    auto_claim: bool,
+    delegated: bool,
+) -> ProgramResult {
+    let (accounts, instructions_info) = split_instructions_sysvar(program_id, accounts)?;
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let (accounts, transfer_hook_accounts) = split_transfer_hook_accounts(program_id, accounts)?;
+    let mut ctx = if delegated {
+        // rewards only go where the owner says
+        if auto_claim {
+            return Err(CustomError::InvalidSigner.into());
+        }
+        StakeAccounts::load_delegated(accounts, program_id)?
     } else {
-        Mint::unpack(&account_info.data.borrow()).map_err(|_| CustomError::InvalidTokenMint.into())
+        StakeAccounts::load(accounts, program_id, auto_claim)?
+    };
+    trace!(
+        "Stake: amount {} auto_claim {} pool {} user {} source {}",
+        amount,
+        auto_claim,
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.source_info.key
+    );
+
+    assert_caller(program_id, &ctx.stake_pool, instructions_info)?;
+    check_auto_claim_split(accounts, ctx.stake_user_info, &ctx.stake_user, &ctx.rewards)?;
+    if ctx.stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+    if ctx.stake_pool.is_pair_pool() {
+        return Err(CustomError::PairedStakeRequired.into());
+    }
+    if ctx.source_token.amount < amount {
+        return Err(CustomError::InsufficientFunds.into());
     }
-}
 
-pub fn unpack_token_account(
-    account_info: &AccountInfo,
-    token_program_id: &Pubkey,
-) -> Result<Account, ProgramError> {
-    if account_info.owner != token_program_id {
-        Err(CustomError::InvalidAccountOwner.into())
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    ctx.stake_pool.observe_stake_seconds(clock.unix_timestamp)?;
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
     } else {
-        spl_token::state::Account::unpack(&account_info.data.borrow())
-            .map_err(|_| CustomError::InvalidTokenAccount.into())
+        ctx.stake_user
+            .begin_stake(&ctx.stake_pool, clock.unix_timestamp);
     }
-}
+    let claimed = match &ctx.rewards {
+        Some(rewards) => claim_owed(&mut ctx.stake_pool, &mut ctx.stake_user, rewards, clock)?,
+        None => (0, 0),
+    };
 
-struct TokenInitializeAccountParams<'a> {
-    account: AccountInfo<'a>,
-    mint: AccountInfo<'a>,
-    owner: AccountInfo<'a>,
-    rent: AccountInfo<'a>,
-    token_program: AccountInfo<'a>,
-}
+    let weighted = match ctx.mint_index {
+        Some(index) => {
+            ctx.stake_user.stake_mint(index, amount)?;
+            ctx.stake_pool.stake_mint(index, amount)?;
+            ctx.stake_pool.stake_mints[index].weighted(amount)
+        }
+        None => {
+            ctx.stake_user.stake(amount)?;
+            ctx.stake_pool.stake(amount)?;
+            amount
+        }
+    };
+    ctx.stake_user.check_stake_cap(&ctx.stake_pool)?;
+    ctx.stake_user
+        .start_warm_up(&ctx.stake_pool, weighted, clock.unix_timestamp)?;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
 
-struct TokenInitializeMintParams<'a: 'b, 'b> {
-    mint: AccountInfo<'a>,
-    rent: AccountInfo<'a>,
-    authority: &'b Pubkey,
-    decimals: u8,
-    token_program: AccountInfo<'a>,
-}
+    transfer_stake_tokens(
+        TokenTransferParams {
+            source: ctx.source_info.clone(),
+            mint: None,
+            destination: ctx.reserve_info.clone(),
+            amount,
+            authority: ctx.user_transfer_authority_info.clone(),
+            authority_signer_seeds: &[],
+            token_program: ctx.token_program_info.clone(),
+        },
+        &ctx.source_token.mint,
+        transfer_hook_accounts,
+    )?;
 
-struct TokenTransferParams<'a: 'b, 'b> {
-    source: AccountInfo<'a>,
-    destination: AccountInfo<'a>,
-    amount: u64,
-    authority: AccountInfo<'a>,
-    authority_signer_seeds: &'b [&'b [u8]],
-    token_program: AccountInfo<'a>,
+    if let Some(rewards) = &ctx.rewards {
+        pay_out_rewards(
+            rewards,
+            ctx.stake_pool_info,
+            &ctx.stake_pool,
+            ctx.token_program_info,
+            claimed,
+        )?;
+    }
+
+    invoke_hook(
+        hook_accounts,
+        ctx.stake_pool_info,
+        ctx.stake_user_info,
+        HookData {
+            action: HookAction::Stake { amount },
+            owner: ctx.stake_user.owner,
+            stake_amount: ctx.stake_user.stake_amount,
+            timestamp: ctx.clock.unix_timestamp,
+        },
+    )
 }
 
-struct TokenMintToParams<'a: 'b, 'b> {
-    mint: AccountInfo<'a>,
-    destination: AccountInfo<'a>,
+/// For Task 1: do unstake
+pub fn process_unstake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
     amount: u64,
-    authority: AccountInfo<'a>,
-    authority_signer_seeds: &'b [&'b [u8]],
-    token_program: AccountInfo<'a>,
-}
+    auto_claim: bool,
+) -> ProgramResult {
+    let (accounts, instructions_info) = split_instructions_sysvar(program_id, accounts)?;
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let (accounts, transfer_hook_accounts) = split_transfer_hook_accounts(program_id, accounts)?;
+    let mut ctx = UnstakeAccounts::load(accounts, program_id, auto_claim)?;
+    trace!(
+        "Unstake: amount {} auto_claim {} pool {} user {} destination {}",
+        amount,
+        auto_claim,
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.destination_info.key
+    );
 
-fn spl_token_init_account(params: TokenInitializeAccountParams<'_>) -> ProgramResult {
-    let TokenInitializeAccountParams {
-        account,
-        mint,
-        owner,
-        rent,
-        token_program,
-    } = params;
-    let ix = spl_token::instruction::initialize_account(
-        token_program.key,
-        account.key,
-        mint.key,
-        owner.key,
+    assert_caller(program_id, &ctx.stake_pool, instructions_info)?;
+    check_auto_claim_split(accounts, ctx.stake_user_info, &ctx.stake_user, &ctx.rewards)?;
+    if ctx.stake_pool.is_pair_pool() {
+        return Err(CustomError::PairedStakeRequired.into());
+    }
+    if ctx
+        .stake_user
+        .is_locked(&ctx.stake_pool, ctx.clock.unix_timestamp)
+    {
+        return Err(CustomError::StakeLocked.into());
+    }
+    if ctx.reserve_token.amount < amount {
+        return Err(CustomError::InsufficientLiquidity.into());
+    }
+
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    ctx.stake_pool.observe_stake_seconds(clock.unix_timestamp)?;
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
+    }
+    let claimed = match &ctx.rewards {
+        Some(rewards) => claim_owed(&mut ctx.stake_pool, &mut ctx.stake_user, rewards, clock)?,
+        None => (0, 0),
+    };
+    ctx.stake_user.end_streak(clock.unix_timestamp);
+
+    match ctx.mint_index {
+        Some(index) => {
+            ctx.stake_user.unstake_mint(index, amount)?;
+            ctx.stake_pool.unstake_mint(index, amount)?;
+        }
+        None => {
+            ctx.stake_user.unstake(amount)?;
+            ctx.stake_pool.unstake(amount)?;
+        }
+    }
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
     )?;
-    let result = invoke(&ix, &[account, mint, owner, rent, token_program]);
-    result.map_err(|_| CustomError::TokenInitializeAccountFailed.into())
-}
 
-fn spl_token_init_mint(params: TokenInitializeMintParams<'_, '_>) -> ProgramResult {
-    let TokenInitializeMintParams {
-        mint,
-        rent,
-        authority,
-        token_program,
-        decimals,
-    } = params;
-    let ix = spl_token::instruction::initialize_mint(
-        token_program.key,
-        mint.key,
-        authority,
-        None,
-        decimals,
+    transfer_stake_tokens(
+        TokenTransferParams {
+            source: ctx.reserve_info.clone(),
+            mint: None,
+            destination: ctx.destination_info.clone(),
+            amount,
+            authority: ctx.stake_pool_authority_info.clone(),
+            authority_signer_seeds: &ctx.authority_signer_seeds(),
+            token_program: ctx.token_program_info.clone(),
+        },
+        &ctx.reserve_token.mint,
+        transfer_hook_accounts,
     )?;
-    let result = invoke(&ix, &[mint, rent, token_program]);
-    result.map_err(|_| CustomError::TokenInitializeMintFailed.into())
+
+    if let Some(rewards) = &ctx.rewards {
+        pay_out_rewards(
+            rewards,
+            ctx.stake_pool_info,
+            &ctx.stake_pool,
+            ctx.token_program_info,
+            claimed,
+        )?;
+    }
+
+    invoke_hook(
+        hook_accounts,
+        ctx.stake_pool_info,
+        ctx.stake_user_info,
+        HookData {
+            action: HookAction::Unstake { amount },
+            owner: ctx.stake_user.owner,
+            stake_amount: ctx.stake_user.stake_amount,
+            timestamp: ctx.clock.unix_timestamp,
+        },
+    )
 }
 
-fn spl_token_transfer(params: TokenTransferParams<'_, '_>) -> ProgramResult {
-    let TokenTransferParams {
-        source,
-        destination,
-        authority,
-        token_program,
+/// Stake both legs of a pair pool, the paired amount following the ratio
+pub fn process_stake_pair(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let (accounts, instructions_info) = split_instructions_sysvar(program_id, accounts)?;
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let mut ctx = StakeAccounts::load(accounts, program_id, false)?;
+    assert_caller(program_id, &ctx.stake_pool, instructions_info)?;
+    let paired = PairedAccounts::load(accounts, &ctx.stake_pool, ctx.token_program_info, false)?;
+    trace!(
+        "StakePair: amount {} pool {} user {} source {} paired source {}",
         amount,
-        authority_signer_seeds,
-    } = params;
-    let result = invoke_optionally_signed(
-        &spl_token::instruction::transfer(
-            token_program.key,
-            source.key,
-            destination.key,
-            authority.key,
-            &[],
-            amount,
-        )?,
-        &[source, destination, authority, token_program],
-        authority_signer_seeds,
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.source_info.key,
+        paired.user_info.key
     );
-    result.map_err(|_| CustomError::TokenTransferFailed.into())
-}
 
-fn spl_token_mint_to(params: TokenMintToParams<'_, '_>) -> ProgramResult {
-    let TokenMintToParams {
-        mint,
-        destination,
-        authority,
-        token_program,
+    if ctx.mint_index.is_some() {
+        return Err(CustomError::InvalidTokenAccount.into());
+    }
+    if ctx.stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+    let paired_amount = ctx.stake_pool.paired_amount(amount)?;
+    if ctx.source_token.amount < amount || paired.user_token.amount < paired_amount {
+        return Err(CustomError::InsufficientFunds.into());
+    }
+
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    ctx.stake_pool.observe_stake_seconds(clock.unix_timestamp)?;
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
+    } else {
+        ctx.stake_user
+            .begin_stake(&ctx.stake_pool, clock.unix_timestamp);
+    }
+
+    ctx.stake_user.stake(amount)?;
+    ctx.stake_pool.stake(amount)?;
+    ctx.stake_user.stake_mint(0, paired_amount)?;
+    ctx.stake_pool.stake_mint(0, paired_amount)?;
+    let weighted = ctx.stake_pool.stake_mints[0]
+        .weighted(paired_amount)
+        .checked_add(amount)
+        .ok_or(CustomError::CalculationFailure)?;
+    ctx.stake_user.check_stake_cap(&ctx.stake_pool)?;
+    ctx.stake_user
+        .start_warm_up(&ctx.stake_pool, weighted, clock.unix_timestamp)?;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: ctx.source_info.clone(),
+        mint: None,
+        destination: ctx.reserve_info.clone(),
         amount,
-        authority_signer_seeds,
-    } = params;
-    let result = invoke_optionally_signed(
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            mint.key,
-            destination.key,
-            authority.key,
-            &[],
-            amount,
-        )?,
-        &[mint, destination, authority, token_program],
-        authority_signer_seeds,
-    );
-    result.map_err(|_| CustomError::TokenMintToFailed.into())
+        authority: ctx.user_transfer_authority_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: ctx.token_program_info.clone(),
+    })?;
+    spl_token_transfer(TokenTransferParams {
+        source: paired.user_info.clone(),
+        mint: None,
+        destination: paired.reserve_info.clone(),
+        amount: paired_amount,
+        authority: ctx.user_transfer_authority_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: ctx.token_program_info.clone(),
+    })?;
+
+    invoke_hook(
+        hook_accounts,
+        ctx.stake_pool_info,
+        ctx.stake_user_info,
+        HookData {
+            action: HookAction::Stake { amount },
+            owner: ctx.stake_user.owner,
+            stake_amount: ctx.stake_user.stake_amount,
+            timestamp: ctx.clock.unix_timestamp,
+        },
+    )
 }
 
-fn invoke_optionally_signed(
-    instruction: &Instruction,
-    account_infos: &[AccountInfo],
-    authority_signer_seeds: &[&[u8]],
+/// Unstake both legs of a pair pool, releasing the same share of the paired
+/// tokens as of the primary stake
+pub fn process_unstake_pair(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
 ) -> ProgramResult {
-    if authority_signer_seeds.is_empty() {
-        invoke(instruction, account_infos)
-    } else {
-        invoke_signed(instruction, account_infos, &[authority_signer_seeds])
+    let (accounts, instructions_info) = split_instructions_sysvar(program_id, accounts)?;
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let mut ctx = UnstakeAccounts::load(accounts, program_id, false)?;
+    assert_caller(program_id, &ctx.stake_pool, instructions_info)?;
+    let paired = PairedAccounts::load(accounts, &ctx.stake_pool, ctx.token_program_info, true)?;
+    trace!(
+        "UnstakePair: amount {} pool {} user {} destination {} paired destination {}",
+        amount,
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.destination_info.key,
+        paired.user_info.key
+    );
+
+    if ctx.mint_index.is_some() {
+        return Err(CustomError::InvalidTokenAccount.into());
+    }
+    if ctx
+        .stake_user
+        .is_locked(&ctx.stake_pool, ctx.clock.unix_timestamp)
+    {
+        return Err(CustomError::StakeLocked.into());
+    }
+    let paired_amount = ctx.stake_user.paired_share(amount)?;
+    if ctx.reserve_token.amount < amount || paired.reserve_token.amount < paired_amount {
+        return Err(CustomError::InsufficientLiquidity.into());
+    }
+
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    ctx.stake_pool.observe_stake_seconds(clock.unix_timestamp)?;
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
     }
+    ctx.stake_user.end_streak(clock.unix_timestamp);
+
+    ctx.stake_user.unstake(amount)?;
+    ctx.stake_pool.unstake(amount)?;
+    ctx.stake_user.unstake_mint(0, paired_amount)?;
+    ctx.stake_pool.unstake_mint(0, paired_amount)?;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: ctx.reserve_info.clone(),
+        mint: None,
+        destination: ctx.destination_info.clone(),
+        amount,
+        authority: ctx.stake_pool_authority_info.clone(),
+        authority_signer_seeds: &ctx.authority_signer_seeds(),
+        token_program: ctx.token_program_info.clone(),
+    })?;
+    spl_token_transfer(TokenTransferParams {
+        source: paired.reserve_info.clone(),
+        mint: None,
+        destination: paired.user_info.clone(),
+        amount: paired_amount,
+        authority: ctx.stake_pool_authority_info.clone(),
+        authority_signer_seeds: &ctx.authority_signer_seeds(),
+        token_program: ctx.token_program_info.clone(),
+    })?;
+
+    invoke_hook(
+        hook_accounts,
+        ctx.stake_pool_info,
+        ctx.stake_user_info,
+        HookData {
+            action: HookAction::Unstake { amount },
+            owner: ctx.stake_user.owner,
+            stake_amount: ctx.stake_user.stake_amount,
+            timestamp: ctx.clock.unix_timestamp,
+        },
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::instruction::create_stake_pool;
+/// Escrow an NFT of the boost collection, boosting the owner's rewards from
+/// now on
+pub fn process_register_boost(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut ctx = RegisterBoostAccounts::load(accounts, program_id)?;
+    trace!(
+        "RegisterBoost: pool {} user {} nft {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.nft_mint_info.key
+    );
 
-    use solana_program::program_stubs;
-    use solana_sdk::account::{create_account_for_test, create_is_signer_account_infos, Account};
-    use spl_token::instruction::{initialize_account, initialize_mint};
+    if ctx.stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+    if ctx.stake_user.has_boost() {
+        return Err(CustomError::InvalidBoost.into());
+    }
 
-    const STAKE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([3u8; 32]);
+    // rewards owed so far accrue unboosted
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
+    }
+    ctx.stake_user.boost_mint = *ctx.nft_mint_info.key;
+    ctx.stake_user.boost_bps = ctx.stake_pool.boost_bps;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
 
-    struct TestSyscallStubs {}
-    impl program_stubs::SyscallStubs for TestSyscallStubs {
-        fn sol_invoke_signed(
-            &self,
-            instruction: &Instruction,
-            account_infos: &[AccountInfo],
-            signers_seeds: &[&[&[u8]]],
-        ) -> ProgramResult {
-            let mut new_account_infos = vec![];
+    spl_token_transfer(TokenTransferParams {
+        source: ctx.source_info.clone(),
+        mint: Some(ctx.nft_mint_info.clone()),
+        destination: ctx.escrow_info.clone(),
+        amount: 1,
+        authority: ctx.stake_owner_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: ctx.token_program_info.clone(),
+    })
+}
 
-            // mimic check for token program in accounts
-            if !account_infos.iter().any(|x| *x.key == spl_token::id()) {
-                return Err(ProgramError::InvalidAccountData);
-            }
+/// Return the escrowed boost NFT, ending the boost
+pub fn process_unregister_boost(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut ctx = UnregisterBoostAccounts::load(accounts, program_id)?;
+    trace!(
+        "UnregisterBoost: pool {} user {} destination {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.destination_info.key
+    );
 
-            for meta in instruction.accounts.iter() {
-                for account_info in account_infos.iter() {
-                    if meta.pubkey == *account_info.key {
-                        let mut new_account_info = account_info.clone();
-                        for seeds in signers_seeds.iter() {
-                            let signer =
-                                Pubkey::create_program_address(seeds, &STAKE_PROGRAM_ID).unwrap();
-                            if *account_info.key == signer {
-                                new_account_info.is_signer = true;
-                            }
-                        }
-                        new_account_infos.push(new_account_info);
-                    }
-                }
+    // rewards owed so far accrue boosted
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
+    }
+    ctx.stake_user.boost_mint = Pubkey::default();
+    ctx.stake_user.boost_bps = 0;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: ctx.escrow_info.clone(),
+        mint: None,
+        destination: ctx.destination_info.clone(),
+        amount: 1,
+        authority: ctx.escrow_authority_info.clone(),
+        authority_signer_seeds: &ctx.escrow_authority_signer_seeds(),
+        token_program: ctx.token_program_info.clone(),
+    })
+}
+
+/// Mint the staking badge of a staker, or the badge tokens of the tiers they
+/// reached since, succeeding as a no-op when nothing is due. The badge is
+/// never taken back on unstaking.
+pub fn process_mint_badge(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut ctx = MintBadgeAccounts::load(accounts, program_id)?;
+    trace!(
+        "MintBadge: pool {} user {} badge {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.badge_token_info.key
+    );
+
+    if !ctx.stake_user.has_stake() {
+        return Ok(());
+    }
+    let badge_amount = ctx
+        .stake_user
+        .reached_tier(&ctx.stake_pool, ctx.clock.unix_timestamp)?
+        .saturating_add(1);
+    if badge_amount <= ctx.stake_user.badge_amount {
+        return Ok(());
+    }
+    let amount = u64::from(badge_amount - ctx.stake_user.badge_amount);
+    ctx.stake_user.badge_amount = badge_amount;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+
+    spl_token_mint_to(TokenMintToParams {
+        mint: ctx.badge_mint_info.clone(),
+        destination: ctx.badge_token_info.clone(),
+        amount,
+        authority: ctx.stake_pool_authority_info.clone(),
+        authority_signer_seeds: &ctx.authority_signer_seeds(),
+        token_program: ctx.token_program_info.clone(),
+    })
+}
+
+/// For task 2: Claim rewards owed, succeeding as a no-op if nothing is owed
+/// and `if_any` is set. The claimed amount is reported via return data.
+pub fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo], if_any: bool) -> ProgramResult {
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let ctx = ClaimAccounts::load(accounts, program_id)?;
+    trace!(
+        "Claim: if_any {} pool {} user {} destination {}",
+        if_any,
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.rewards.reward_token_info.key
+    );
+
+    claim_rewards(ctx, if_any, hook_accounts).map(|_| ())
+}
+
+/// Claim for the stake user owner into their associated reward token account
+pub fn process_claim_for(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let ctx = ClaimAccounts::load_for_owner(accounts, program_id)?;
+    trace!(
+        "ClaimFor: pool {} user {} destination {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        ctx.rewards.reward_token_info.key
+    );
+
+    claim_rewards(ctx, false, hook_accounts).map(|_| ())
+}
+
+/// Claim from several pools of the same owner, each claim like ClaimIfAny on
+/// its own run of accounts. The amounts claimed are reported via return data.
+pub fn process_harvest_all(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account_counts: &[u8],
+) -> ProgramResult {
+    trace!("HarvestAll: claims {:?}", account_counts);
+
+    let mut stake_owner = None;
+    let mut amounts = Vec::with_capacity(account_counts.len());
+    let mut rest = accounts;
+    for &count in account_counts {
+        if rest.len() < usize::from(count) {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (claim_accounts, next) = rest.split_at(usize::from(count));
+        rest = next;
+
+        let (claim_accounts, hook_accounts) = split_hook_accounts(program_id, claim_accounts)?;
+        let ctx = match ClaimAccounts::load(claim_accounts, program_id) {
+            // the rewards of a position paying into a frozen account stay owed
+            Err(error) if error == CustomError::AccountFrozen.into() => {
+                amounts.push(0);
+                continue;
+            }
+            result => result?,
+        };
+        let stake_owner_info = ctx
+            .stake_owner_info
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        match stake_owner {
+            None => stake_owner = Some(stake_owner_info.key),
+            Some(stake_owner) if stake_owner != stake_owner_info.key => {
+                return Err(reject_account(
+                    accounts,
+                    stake_owner_info,
+                    "not the owner of the other claims",
+                    CustomError::InvalidStakeOwner,
+                ));
+            }
+            Some(_) => {}
+        }
+        amounts.push(claim_rewards(ctx, true, hook_accounts)?);
+    }
+    // writing to a vector can't fail
+    set_return_data(&borsh::to_vec(&amounts).unwrap());
+
+    Ok(())
+}
+
+/// Claim rewards and stake them into a pool of the reward token, paying out
+/// straight into its reserve
+pub fn process_claim_and_stake_into(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    // the target pool is the one staked into
+    let (accounts, instructions_info) = split_instructions_sysvar_at(program_id, accounts, 3)?;
+    let (accounts, hook_accounts) = split_hook_accounts(program_id, accounts)?;
+    let ClaimAndStakeAccounts {
+        claim: ctx,
+        target_pool_info,
+        target_stake_user_info,
+        mut target_pool,
+        mut target_stake_user,
+    } = ClaimAndStakeAccounts::load(accounts, program_id)?;
+    trace!(
+        "ClaimAndStakeInto: pool {} user {} target pool {} target user {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key,
+        target_pool_info.key,
+        target_stake_user_info.key
+    );
+
+    assert_caller(program_id, &target_pool, instructions_info)?;
+    if target_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+    let clock = ctx.clock.clone();
+    let amount = claim_rewards(ctx, false, hook_accounts)?;
+
+    target_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    target_pool.observe_stake_seconds(clock.unix_timestamp)?;
+    if target_stake_user.has_stake() {
+        target_stake_user.update_reward_owed(&target_pool, clock.unix_timestamp)?;
+    } else {
+        target_stake_user.begin_stake(&target_pool, clock.unix_timestamp);
+    }
+    target_stake_user.stake(amount)?;
+    target_stake_user.check_stake_cap(&target_pool)?;
+    target_stake_user.start_warm_up(&target_pool, amount, clock.unix_timestamp)?;
+    StakeUser::pack(
+        target_stake_user,
+        &mut target_stake_user_info.data.borrow_mut(),
+    )?;
+    target_pool.stake(amount)?;
+    Pool::pack(target_pool, &mut target_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Refuse auto-claims of stake users with a claim split, which they can't pay
+/// the shares of
+fn check_auto_claim_split(
+    accounts: &[AccountInfo],
+    stake_user_info: &AccountInfo,
+    stake_user: &StakeUser,
+    rewards: &Option<RewardAccounts>,
+) -> ProgramResult {
+    if stake_user.claim_split && rewards.is_some() {
+        return Err(reject_account(
+            accounts,
+            stake_user_info,
+            "has a claim split auto-claims don't pay",
+            CustomError::InvalidClaimSplit,
+        ));
+    }
+    Ok(())
+}
+
+/// Accrue and pay out the rewards owed to a stake user, setting the claimed
+/// amount as return data and returning it
+fn claim_rewards<'a>(
+    mut ctx: ClaimAccounts<'_, 'a>,
+    if_any: bool,
+    hook_accounts: &[AccountInfo<'a>],
+) -> Result<u64, ProgramError> {
+    let clock = &ctx.clock;
+    ctx.stake_pool
+        .observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    if ctx.stake_user.has_stake() {
+        ctx.stake_user
+            .update_reward_owed(&ctx.stake_pool, clock.unix_timestamp)?;
+    }
+
+    if if_any && ctx.stake_user.reward_owed == 0 {
+        StakeUser::pack(
+            ctx.stake_user.clone(),
+            &mut ctx.stake_user_info.data.borrow_mut(),
+        )?;
+        Pool::pack(
+            ctx.stake_pool.clone(),
+            &mut ctx.stake_pool_info.data.borrow_mut(),
+        )?;
+        set_return_data(&0u64.to_le_bytes());
+        return Ok(0);
+    }
+
+    let owed = ctx.stake_user.claim()?;
+    let (amount, burn) = pay_owed(
+        &mut ctx.stake_pool,
+        &mut ctx.stake_user,
+        &ctx.rewards,
+        &ctx.clock,
+        owed,
+    )?;
+    if amount == 0 && burn == 0 && ctx.stake_pool.is_reward_budget_exhausted() {
+        return Err(CustomError::RewardBudgetExhausted.into());
+    }
+    // Nothing paid within the budget while still owed: the mint is capped
+    if amount == 0 && burn == 0 && ctx.stake_user.reward_owed > 0 {
+        return Err(CustomError::RewardSupplyCapReached.into());
+    }
+    let amount = stream_claimed(&ctx.stake_pool, &mut ctx.stake_user, &ctx.clock, amount)?;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+    Pool::pack(
+        ctx.stake_pool.clone(),
+        &mut ctx.stake_pool_info.data.borrow_mut(),
+    )?;
+
+    let rest = pay_out_claim_split(&ctx, amount)?;
+    pay_out_rewards(
+        &ctx.rewards,
+        ctx.stake_pool_info,
+        &ctx.stake_pool,
+        ctx.token_program_info,
+        (rest, burn),
+    )?;
+    set_return_data(&amount.to_le_bytes());
+
+    invoke_hook(
+        hook_accounts,
+        ctx.stake_pool_info,
+        ctx.stake_user_info,
+        HookData {
+            action: HookAction::Claim { amount },
+            owner: ctx.stake_user.owner,
+            stake_amount: ctx.stake_user.stake_amount,
+            timestamp: ctx.clock.unix_timestamp,
+        },
+    )?;
+
+    Ok(amount)
+}
+
+/// Take the rewards owed by an auto-claiming stake or unstake, split into the
+/// amounts paid out and burned. Nothing owed claims nothing.
+fn claim_owed(
+    stake_pool: &mut Pool,
+    stake_user: &mut StakeUser,
+    rewards: &RewardAccounts,
+    clock: &Clock,
+) -> Result<(u64, u64), ProgramError> {
+    if stake_user.reward_owed == 0 {
+        return Ok((0, 0));
+    }
+    let owed = stake_user.claim()?;
+    let (amount, burn) = pay_owed(stake_pool, stake_user, rewards, clock, owed)?;
+    Ok((stream_claimed(stake_pool, stake_user, clock, amount)?, burn))
+}
+
+/// Move the reward tokens a claim of a streamed pool pays out into the stake
+/// user's stream, leaving nothing to pay out until withdrawn
+fn stream_claimed(
+    stake_pool: &Pool,
+    stake_user: &mut StakeUser,
+    clock: &Clock,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    if !stake_pool.is_streamed() || amount == 0 {
+        return Ok(amount);
+    }
+    stake_user.open_stream(stake_pool, amount, clock.unix_timestamp)?;
+    Ok(0)
+}
+
+/// Split an amount taken from the rewards owed into the reward tokens paid
+/// out and burned, within the reward budget and the maximum reward supply.
+/// The share they don't cover stays owed.
+fn pay_owed(
+    stake_pool: &mut Pool,
+    stake_user: &mut StakeUser,
+    rewards: &RewardAccounts,
+    clock: &Clock,
+    owed: u64,
+) -> Result<(u64, u64), ProgramError> {
+    let tokens = owed_in_tokens(rewards, stake_pool, clock, owed)?;
+    let mintable = mintable_reward(rewards, stake_pool, tokens)?;
+    let budgeted = stake_pool.draw_reward_budget(mintable);
+    if budgeted < tokens {
+        stake_user.reward_owed = math::mul_div(owed, tokens - budgeted, tokens)?;
+    }
+    let (amount, burn) = stake_pool.split_claim_burn(budgeted)?;
+    stake_user.record_claim(amount, clock.unix_timestamp);
+    Ok((amount, burn))
+}
+
+/// Up to an amount of reward tokens minting keeps within the maximum reward
+/// supply, checked against the reward mint's current supply. Burned shares
+/// count toward it too.
+fn mintable_reward(
+    rewards: &RewardAccounts,
+    stake_pool: &Pool,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    if stake_pool.max_reward_supply == 0 || stake_pool.is_vault_funded() {
+        return Ok(amount);
+    }
+    let data = rewards.reward_source_info.data.borrow();
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+        .map_err(|_| CustomError::InvalidTokenMint)?;
+    Ok(stake_pool.mintable_reward(mint.base.supply, amount))
+}
+
+/// Reward tokens paying out an amount owed, converted at the oracle price for
+/// oracle-priced pools. Prices are of UI amounts, so the tokens of
+/// interest-bearing reward mints are scaled down to raw amounts.
+fn owed_in_tokens(
+    rewards: &RewardAccounts,
+    stake_pool: &Pool,
+    clock: &Clock,
+    owed: u64,
+) -> Result<u64, ProgramError> {
+    let price_oracle_info = match rewards.price_oracle_info {
+        Some(price_oracle_info) => price_oracle_info,
+        None => return Ok(owed),
+    };
+    let price = Price::unpack_pyth(&price_oracle_info.data.borrow())?;
+    price.check(
+        clock.unix_timestamp,
+        stake_pool.max_price_age,
+        stake_pool.max_price_conf_bps,
+    )?;
+    let tokens = price.quote_to_tokens(owed)?;
+    if stake_pool.is_vault_funded() {
+        return Ok(tokens);
+    }
+    let multiplier = interest::interest_multiplier(
+        &rewards.reward_source_info.data.borrow(),
+        clock.unix_timestamp,
+    )?;
+    Ok(multiplier.map_or(tokens, |multiplier| {
+        interest::to_raw_amount(tokens, multiplier)
+    }))
+}
+
+/// Move stake tokens of Stake and Unstake. Token-2022 pools transfer with
+/// `transfer_checked`, taking the stake mint and its transfer hook accounts
+/// split off the instruction.
+fn transfer_stake_tokens<'a>(
+    params: TokenTransferParams<'a, '_>,
+    mint: &Pubkey,
+    transfer_hook_accounts: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let (mint_info, transfer_hook_accounts) = match transfer_hook_accounts.split_first() {
+        Some(split) => split,
+        None => return spl_token_transfer(params),
+    };
+    if mint_info.key != mint {
+        msg!("Stake mint: expected {}, got {}", mint, mint_info.key);
+        return Err(CustomError::InvalidTokenMint.into());
+    }
+    let decimals = unpack_mint(mint_info, params.token_program.key)?.decimals;
+    spl_token_transfer_checked(TokenTransferCheckedParams {
+        source: params.source,
+        mint: mint_info.clone(),
+        destination: params.destination,
+        amount: params.amount,
+        decimals,
+        authority: params.authority,
+        authority_signer_seeds: params.authority_signer_seeds,
+        token_program: params.token_program,
+        transfer_hook_accounts,
+    })
+}
+
+/// Mint or transfer claimed rewards to the reward token account depending on
+/// the reward mode, burning the burned share out of the vault of vault-funded
+/// pools
+fn pay_out_rewards<'a>(
+    rewards: &RewardAccounts<'_, 'a>,
+    stake_pool_info: &AccountInfo,
+    stake_pool: &Pool,
+    token_program_info: &AccountInfo<'a>,
+    (amount, burn): (u64, u64),
+) -> ProgramResult {
+    if amount > 0 {
+        pay_reward_tokens(
+            rewards,
+            rewards.reward_token_info,
+            stake_pool_info,
+            stake_pool,
+            token_program_info,
+            amount,
+        )?;
+    }
+    if stake_pool.reward_mode == RewardMode::TransferFromVault && burn > 0 {
+        let burn_mint_info = rewards
+            .burn_mint_info
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        spl_token_burn(TokenBurnParams {
+            mint: burn_mint_info.clone(),
+            source: rewards.reward_source_info.clone(),
+            amount: burn,
+            authority: rewards.stake_pool_authority_info.clone(),
+            authority_signer_seeds: &Pool::authority_seeds(
+                stake_pool_info.key,
+                &stake_pool.bump_seed,
+            ),
+            token_program: token_program_info.clone(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Pay the shares of a claim into the destinations of its claim split, if
+/// given, returning the rest of the amount for the reward token account
+fn pay_out_claim_split<'a>(ctx: &ClaimAccounts<'_, 'a>, amount: u64) -> Result<u64, ProgramError> {
+    let claim_split = match &ctx.claim_split {
+        Some(claim_split) => claim_split,
+        None => return Ok(amount),
+    };
+    let (amounts, rest) = claim_split.claim_split.split(amount)?;
+    for (destination_info, share_amount) in claim_split.destination_infos.iter().zip(amounts) {
+        if share_amount > 0 {
+            pay_reward_tokens(
+                &ctx.rewards,
+                destination_info,
+                ctx.stake_pool_info,
+                &ctx.stake_pool,
+                ctx.token_program_info,
+                share_amount,
+            )?;
+        }
+    }
+    Ok(rest)
+}
+
+/// Mint or transfer reward tokens into a token account depending on the
+/// reward mode
+fn pay_reward_tokens<'a>(
+    rewards: &RewardAccounts<'_, 'a>,
+    destination_info: &AccountInfo<'a>,
+    stake_pool_info: &AccountInfo,
+    stake_pool: &Pool,
+    token_program_info: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let authority_signer_seeds = &Pool::authority_seeds(stake_pool_info.key, &stake_pool.bump_seed);
+    match stake_pool.reward_mode {
+        RewardMode::MintToUser => spl_token_mint_to(TokenMintToParams {
+            mint: rewards.reward_source_info.clone(),
+            destination: destination_info.clone(),
+            amount,
+            authority: rewards.stake_pool_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_info.clone(),
+        }),
+        RewardMode::TransferFromVault => spl_token_transfer(TokenTransferParams {
+            source: rewards.reward_source_info.clone(),
+            mint: rewards.burn_mint_info.cloned(),
+            destination: destination_info.clone(),
+            amount,
+            authority: rewards.stake_pool_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_info.clone(),
+        }),
+    }
+}
+
+pub fn process_refresh(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "Refresh: pool {} users {}",
+        stake_pool_info.key,
+        accounts.len().saturating_sub(2)
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+
+    let mut counts = RefreshCounts::default();
+    for stake_user_info in account_info_iter {
+        if stake_user_info.owner != program_id
+            || StakeUser::unpack_pool_pubkey(&stake_user_info.data.borrow())?
+                != *stake_pool_info.key
+        {
+            counts.skipped += 1;
+            continue;
+        }
+        let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+        if !stake_user.has_stake() {
+            counts.skipped += 1;
+            continue;
+        }
+        stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+        StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+        counts.updated += 1;
+    }
+
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+    set_refresh_return_data(&counts);
+
+    Ok(())
+}
+
+/// Refresh a bounded batch of stake users in increasing order of addresses,
+/// for automation networks replaying the same accounts
+pub fn process_refresh_batch(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let stake_user_infos = account_info_iter.as_slice();
+    trace!(
+        "RefreshBatch: pool {} users {}",
+        stake_pool_info.key,
+        stake_user_infos.len()
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    if stake_user_infos.len() > MAX_REFRESH_BATCH
+        || stake_user_infos
+            .windows(2)
+            .any(|pair| pair[0].key >= pair[1].key)
+    {
+        return Err(CustomError::InvalidRefreshBatch.into());
+    }
+
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+
+    let mut counts = RefreshCounts::default();
+    for stake_user_info in stake_user_infos {
+        // stake users closed since the thread was registered stay in its list
+        if stake_user_info.owner != program_id {
+            counts.skipped += 1;
+            continue;
+        }
+        match StakeUser::unpack_pool_pubkey(&stake_user_info.data.borrow()) {
+            Ok(pool_pubkey) if pool_pubkey == *stake_pool_info.key => {}
+            _ => {
+                counts.skipped += 1;
+                continue;
             }
+        }
+        let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+        if !stake_user.has_stake() {
+            counts.skipped += 1;
+            continue;
+        }
+        stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+        StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+        counts.updated += 1;
+    }
+
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+    set_refresh_return_data(&counts);
+
+    Ok(())
+}
+
+/// Pay out the streamed rewards released so far, within the maximum reward
+/// supply
+pub fn process_withdraw_from_stream(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let mut ctx = ClaimAccounts::load(accounts, program_id)?;
+    trace!(
+        "WithdrawFromStream: pool {} user {}",
+        ctx.stake_pool_info.key,
+        ctx.stake_user_info.key
+    );
+
+    let current_ts = ctx.clock.unix_timestamp;
+    let withdrawable = ctx.stake_user.stream_withdrawable(current_ts)?;
+    if withdrawable == 0 {
+        return Err(CustomError::InsufficientClaimAmount.into());
+    }
+    let amount = mintable_reward(&ctx.rewards, &ctx.stake_pool, withdrawable)?;
+    if amount == 0 {
+        return Err(CustomError::RewardSupplyCapReached.into());
+    }
+    ctx.stake_user.withdraw_stream(amount, current_ts)?;
+    StakeUser::pack(
+        ctx.stake_user.clone(),
+        &mut ctx.stake_user_info.data.borrow_mut(),
+    )?;
+
+    let rest = pay_out_claim_split(&ctx, amount)?;
+    pay_out_rewards(
+        &ctx.rewards,
+        ctx.stake_pool_info,
+        &ctx.stake_pool,
+        ctx.token_program_info,
+        (rest, 0),
+    )?;
+    set_return_data(&amount.to_le_bytes());
+
+    Ok(())
+}
+
+/// Report the key parameters of a pool via return data
+pub fn process_get_pool_info(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    trace!("GetPoolInfo: pool {}", stake_pool_info.key);
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    let (reward_numerator, reward_denominator) = stake_pool.current_rate();
+    let pool_info = PoolInfo {
+        stake_token_mint: stake_pool.stake_token_mint,
+        reward_mint: stake_pool.reward_mint,
+        reward_mode: stake_pool.reward_mode as u8,
+        reward_numerator,
+        reward_denominator,
+        reward_period: stake_pool.reward_period,
+        total_staked: stake_pool.total_staked,
+        user_count: stake_pool.user_count,
+        deprecated: stake_pool.deprecated,
+        pool_flags: stake_pool.pool_flags,
+        stream_duration: stake_pool.stream_duration,
+        stake_decimals: stake_pool.stake_decimals,
+        reward_decimals: stake_pool.reward_decimals,
+    };
+    // writing to a vector can't fail
+    set_return_data(&borsh::to_vec(&pool_info).unwrap());
+
+    Ok(())
+}
+
+/// Report the stake users a refresh updated and skipped via return data
+fn set_refresh_return_data(counts: &RefreshCounts) {
+    // writing to a vector can't fail
+    set_return_data(&borsh::to_vec(counts).unwrap());
+}
+
+/// Donate reward tokens to the reward vault, or lamports to the fee vault
+pub fn process_fund_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let funder_info = next_account_info(account_info_iter)?;
+    let vault_info = next_account_info(account_info_iter)?;
+    let program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "FundRewards: amount {} pool {} funder {} vault {}",
+        amount,
+        stake_pool_info.key,
+        funder_info.key,
+        vault_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_signer(accounts, funder_info)?;
+
+    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    if stake_pool.is_vault_funded() {
+        let source_info = next_account_info(account_info_iter)?;
+        assert_key(
+            accounts,
+            vault_info,
+            &stake_pool.reward_vault,
+            CustomError::InvalidTokenAccount,
+        )?;
+        let source_token = unpack_token_account(source_info, program_info.key)?;
+        assert_field(
+            accounts,
+            source_info,
+            "mint",
+            &stake_pool.reward_mint,
+            &source_token.mint,
+            CustomError::InvalidTokenMint,
+        )?;
+        if source_token.amount < amount {
+            return Err(CustomError::InsufficientFunds.into());
+        }
+
+        spl_token_transfer(TokenTransferParams {
+            source: source_info.clone(),
+            mint: None,
+            destination: vault_info.clone(),
+            amount,
+            authority: funder_info.clone(),
+            authority_signer_seeds: &[],
+            token_program: program_info.clone(),
+        })?;
+    } else {
+        let (fee_vault_pubkey, _) = find_fee_vault_address(program_id, stake_pool_info.key);
+        assert_key(
+            accounts,
+            vault_info,
+            &fee_vault_pubkey,
+            CustomError::InvalidFeeVault,
+        )?;
+        if !system_program::check_id(program_info.key) {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        invoke(
+            &system_instruction::transfer(funder_info.key, vault_info.key, amount),
+            &[
+                funder_info.clone(),
+                vault_info.clone(),
+                program_info.clone(),
+            ],
+        )?;
+    }
+
+    Event::FundRewards {
+        pool: *stake_pool_info.key,
+        funder: *funder_info.key,
+        amount,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Sweep tokens which don't back any stake out of pool authority accounts
+pub fn process_sweep(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Treasurer)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_pool_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "Sweep: amount {} pool {} source {} destination {}",
+        amount,
+        stake_pool_info.key,
+        source_info.key,
+        destination_info.key
+    );
+
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &stake_pool.authority_address(program_id, stake_pool_info.key)?,
+        CustomError::InvalidPoolAuthority,
+    )?;
+    if stake_pool.reward_vault == *source_info.key {
+        return Err(reject_account(
+            accounts,
+            source_info,
+            "is the reward vault",
+            CustomError::InvalidTokenAccount,
+        ));
+    }
+    let source_token = unpack_token_account(source_info, token_program_info.key)?;
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &source_token.owner,
+        CustomError::InvalidTokenAccount,
+    )?;
+
+    let staked = match stake_pool.stake_mint_index(source_info.key) {
+        Some(index) => stake_pool.stake_mints[index].total_staked,
+        None if stake_pool.reserved == *source_info.key => stake_pool.total_staked,
+        None => 0,
+    };
+    let sweepable = source_token
+        .amount
+        .checked_sub(staked)
+        .ok_or(CustomError::CalculationFailure)?;
+    if amount > sweepable {
+        return Err(CustomError::InsufficientFunds.into());
+    }
+
+    spl_token_transfer(TokenTransferParams {
+        source: source_info.clone(),
+        mint: None,
+        destination: destination_info.clone(),
+        amount,
+        authority: stake_pool_authority_info.clone(),
+        authority_signer_seeds: &Pool::authority_seeds(stake_pool_info.key, &stake_pool.bump_seed),
+        token_program: token_program_info.clone(),
+    })?;
+
+    if stake_pool.reserved == *source_info.key {
+        stake_pool.protocol_owned = stake_pool.protocol_owned.saturating_sub(amount);
+        Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+    }
+
+    Event::Sweep {
+        pool: *stake_pool_info.key,
+        source: *source_info.key,
+        destination: *destination_info.key,
+        amount,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Attribute reserve tokens untracked by accounting to a stake user or the protocol
+pub fn process_sync_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "SyncReserve: pool {} reserve {} user {:?}",
+        stake_pool_info.key,
+        reserve_info.key,
+        stake_user_info.map(|info| info.key)
+    );
+
+    assert_key(
+        accounts,
+        reserve_info,
+        &stake_pool.reserved,
+        CustomError::InvalidTokenAccount,
+    )?;
+    assert_token_program(accounts, token_program_info)?;
+    let reserve = unpack_token_account(reserve_info, token_program_info.key)?;
+    let surplus = reserve
+        .amount
+        .checked_sub(stake_pool.total_staked)
+        .and_then(|amount| amount.checked_sub(stake_pool.protocol_owned))
+        .ok_or(CustomError::CalculationFailure)?;
+    if surplus == 0 {
+        return Ok(());
+    }
+
+    if let Some(stake_user_info) = stake_user_info {
+        assert_owner(accounts, stake_user_info, program_id)?;
+        let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+        assert_key(
+            accounts,
+            stake_pool_info,
+            &stake_user.pool_pubkey,
+            CustomError::InvalidStakeOwner,
+        )?;
+        // credited like a stake of the surplus
+        if stake_pool.deprecated {
+            return Err(CustomError::PoolDeprecated.into());
+        }
+        if stake_pool.is_pair_pool() {
+            return Err(CustomError::PairedStakeRequired.into());
+        }
+        stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+        stake_pool.observe_stake_seconds(clock.unix_timestamp)?;
+        if stake_user.has_stake() {
+            stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+        } else {
+            stake_user.begin_stake(&stake_pool, clock.unix_timestamp);
+        }
+        stake_user.stake(surplus)?;
+        stake_pool.stake(surplus)?;
+        stake_user.check_stake_cap(&stake_pool)?;
+        stake_user.start_warm_up(&stake_pool, surplus, clock.unix_timestamp)?;
+        StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+
+        Event::SyncReserve {
+            pool: *stake_pool_info.key,
+            stake_user: Some(*stake_user_info.key),
+            amount: surplus,
+        }
+        .emit();
+    } else {
+        stake_pool.protocol_owned = stake_pool
+            .protocol_owned
+            .checked_add(surplus)
+            .ok_or(CustomError::CalculationFailure)?;
+
+        Event::SyncReserve {
+            pool: *stake_pool_info.key,
+            stake_user: None,
+            amount: surplus,
+        }
+        .emit();
+    }
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Deprecate the pool, pointing stakers to its successor
+pub fn process_deprecate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    successor: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Pauser)?;
+    trace!(
+        "Deprecate: successor {} pool {}",
+        successor,
+        stake_pool_info.key
+    );
+
+    if successor == *stake_pool_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    stake_pool.deprecated = true;
+    stake_pool.successor = successor;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Event::Deprecate {
+        pool: *stake_pool_info.key,
+        successor,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Set the program called after stakes, unstakes and claims of the pool, or
+/// clear it with the default pubkey
+pub fn process_set_hook_program(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    hook_program: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Upgrader)?;
+    trace!(
+        "SetHookProgram: hook {} pool {}",
+        hook_program,
+        stake_pool_info.key
+    );
+
+    if hook_program == *program_id {
+        return Err(CustomError::InvalidHookProgram.into());
+    }
+
+    stake_pool.hook_program = hook_program;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Changing the pricing of a pool converts the rewards already owed as well,
+/// so pools should be priced before users accrue rewards
+pub fn process_set_price_oracle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: PriceOracleData,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Upgrader)?;
+    trace!(
+        "SetPriceOracle: oracle {} max age {} max conf {} pool {}",
+        data.price_oracle,
+        data.max_price_age,
+        data.max_price_conf_bps,
+        stake_pool_info.key
+    );
+
+    if data.price_oracle != Pubkey::default() {
+        let price_oracle_info = remaining
+            .first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        assert_key(
+            accounts,
+            price_oracle_info,
+            &data.price_oracle,
+            CustomError::InvalidPriceOracle,
+        )?;
+        Price::unpack_pyth(&price_oracle_info.data.borrow())?;
+        if u64::from(data.max_price_conf_bps) > BPS_DENOMINATOR {
+            return Err(CustomError::InvalidBasisPoints.into());
+        }
+    }
+
+    stake_pool.price_oracle = data.price_oracle;
+    stake_pool.set_flags(
+        POOL_FLAG_ORACLE_PRICED,
+        data.price_oracle != Pubkey::default(),
+    );
+    stake_pool.max_price_age = data.max_price_age;
+    stake_pool.max_price_conf_bps = data.max_price_conf_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_add_stake_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    weight_bps: u16,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_pool_authority_info = next_account_info(account_info_iter)?;
+    let stake_mint_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "AddStakeMint: weight {} mint {} reserve {} pool {}",
+        weight_bps,
+        stake_mint_info.key,
+        reserve_info.key,
+        stake_pool_info.key
+    );
+
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &stake_pool.authority_address(program_id, stake_pool_info.key)?,
+        CustomError::InvalidPoolAuthority,
+    )?;
+    if stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+    unpack_mint(stake_mint_info, token_program_info.key)?;
+    stake_pool.add_stake_mint(*stake_mint_info.key, *reserve_info.key, weight_bps)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    spl_token_init_account(TokenInitializeAccountParams {
+        account: reserve_info.clone(),
+        mint: stake_mint_info.clone(),
+        owner: stake_pool_authority_info.clone(),
+        rent: rent_info.clone(),
+        token_program: token_program_info.clone(),
+    })
+}
+
+pub fn process_set_pair_ratio(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pair_numerator: u64,
+    pair_denominator: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetPairRatio: ratio {}/{} pool {}",
+        pair_numerator,
+        pair_denominator,
+        stake_pool_info.key
+    );
+
+    stake_pool.set_pair_ratio(pair_numerator, pair_denominator)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_boost_collection(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    boost_collection: Pubkey,
+    boost_bps: u16,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetBoostCollection: collection {} bps {} pool {}",
+        boost_collection,
+        boost_bps,
+        stake_pool_info.key
+    );
+
+    if u64::from(boost_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+
+    stake_pool.boost_collection = boost_collection;
+    stake_pool.set_flags(POOL_FLAG_NFT_BOOST, boost_collection != Pubkey::default());
+    stake_pool.boost_bps = boost_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_tiers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tiers: [Tier; MAX_TIERS],
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!("SetTiers: {:?} pool {}", tiers, stake_pool_info.key);
+
+    stake_pool.set_tiers(tiers)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Set the locks stakers of the pool can take, making it a locked pool while
+/// it offers one
+pub fn process_set_lock_tiers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_tiers: [LockTier; MAX_LOCK_TIERS],
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetLockTiers: {:?} pool {}",
+        lock_tiers,
+        stake_pool_info.key
+    );
+
+    stake_pool.set_lock_tiers(lock_tiers)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Lock the stake of a stake user for a lock tier of the pool, or extend its
+/// running lock, without unstaking
+pub fn process_relock(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_tier: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let stake_owner_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "Relock: tier {} pool {} user {}",
+        lock_tier,
+        stake_pool_info.key,
+        stake_user_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+    assert_signer(accounts, stake_owner_info)?;
+
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+    assert_key(
+        accounts,
+        stake_owner_info,
+        &stake_user.owner,
+        CustomError::InvalidStakeOwner,
+    )?;
+    if stake_pool.deprecated {
+        return Err(CustomError::PoolDeprecated.into());
+    }
+
+    // rewards owed so far accrue under the previous lock
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    if stake_user.has_stake() {
+        stake_user.update_reward_owed(&stake_pool, clock.unix_timestamp)?;
+    }
+    stake_user.relock(&stake_pool, lock_tier, clock.unix_timestamp)?;
+    StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Restrict Stake and Unstake to top-level calls and the integrators listed
+pub fn process_set_top_level_only(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    top_level_only: bool,
+    integrators: &[Pubkey],
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetTopLevelOnly: top_level_only {} integrators {} pool {}",
+        top_level_only,
+        integrators.len(),
+        stake_pool_info.key
+    );
+
+    if integrators.len() > MAX_INTEGRATORS {
+        return Err(CustomError::InstructionUnpackError.into());
+    }
+    stake_pool.top_level_only = top_level_only;
+    stake_pool.integrators = [Pubkey::default(); MAX_INTEGRATORS];
+    stake_pool.integrators[..integrators.len()].copy_from_slice(integrators);
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Clamp the accrual timestamp of a stake user left in the future to now
+pub fn process_repair_user(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "RepairUser: pool {} user {}",
+        stake_pool_info.key,
+        stake_user_info.key
+    );
+
+    assert_owner(accounts, stake_user_info, program_id)?;
+    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+    if stake_user.last_update <= clock.unix_timestamp {
+        return Err(CustomError::NothingToRepair.into());
+    }
+
+    let last_update = stake_user.last_update;
+    stake_user.last_update = clock.unix_timestamp;
+    StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+
+    Event::StakeUserRepaired {
+        pool: *stake_pool_info.key,
+        stake_user: *stake_user_info.key,
+        last_update,
+        repaired_to: clock.unix_timestamp,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Top up the reward budget capping claims
+pub fn process_add_reward_budget(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::Treasurer)?;
+    trace!(
+        "AddRewardBudget: amount {} pool {}",
+        amount,
+        stake_pool_info.key
+    );
+
+    stake_pool.add_reward_budget(amount)?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_max_reward_supply(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_reward_supply: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetMaxRewardSupply: max {} pool {}",
+        max_reward_supply,
+        stake_pool_info.key
+    );
+
+    stake_pool.max_reward_supply = max_reward_supply;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_max_stake_per_user(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_stake_per_user: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetMaxStakePerUser: max {} pool {}",
+        max_stake_per_user,
+        stake_pool_info.key
+    );
+
+    stake_pool.max_stake_per_user = max_stake_per_user;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_gc_delay(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    gc_delay: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetGcDelay: delay {} pool {}",
+        gc_delay,
+        stake_pool_info.key
+    );
+
+    stake_pool.gc_delay = gc_delay;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_stream_duration(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stream_duration: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetStreamDuration: duration {} pool {}",
+        stream_duration,
+        stake_pool_info.key
+    );
+
+    if stream_duration > i64::MAX as u64 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    stake_pool.stream_duration = stream_duration;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_warm_up(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    warm_up_duration: u64,
+    warm_up_rate_bps: u16,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetWarmUp: duration {} bps {} pool {}",
+        warm_up_duration,
+        warm_up_rate_bps,
+        stake_pool_info.key
+    );
+
+    if warm_up_duration > i64::MAX as u64 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if u64::from(warm_up_rate_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+    stake_pool.warm_up_duration = warm_up_duration;
+    stake_pool.warm_up_rate_bps = warm_up_rate_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_rate_controller(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    rate_controller_data: RateControllerData,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::RateSetter)?;
+    let account_info_iter = &mut remaining.iter();
+    let global_config_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetRateController: {:?} pool {}",
+        rate_controller_data,
+        stake_pool_info.key
+    );
+
+    if rate_controller_data.min_reward_numerator > rate_controller_data.max_reward_numerator {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if u64::from(rate_controller_data.max_rate_step_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+    load_global_config(program_id, accounts, global_config_info)?.check_reward_rate(
+        rate_controller_data.max_reward_numerator,
+        stake_pool.current_rate().1,
+        stake_pool.reward_period,
+    )?;
+    stake_pool.target_tvl = rate_controller_data.target_tvl;
+    stake_pool.min_reward_numerator = rate_controller_data.min_reward_numerator;
+    stake_pool.max_reward_numerator = rate_controller_data.max_reward_numerator;
+    stake_pool.max_rate_step_bps = rate_controller_data.max_rate_step_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Move the reward rate toward the target TVL, from the current timestamp on
+pub fn process_rebalance_rate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!("RebalanceRate: pool {}", stake_pool_info.key);
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    if !stake_pool.is_rebalance_due(clock.unix_timestamp) {
+        return Err(CustomError::RebalanceNotDue.into());
+    }
+
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    let (numerator, denominator) = stake_pool.current_rate();
+    let reward_numerator = stake_pool.rebalanced_numerator();
+    if reward_numerator != numerator {
+        stake_pool.checkpoint_rate(clock.unix_timestamp, reward_numerator, denominator)?;
+    }
+    stake_pool.last_rebalance_ts = clock.unix_timestamp;
+    Pool::pack(stake_pool.clone(), &mut stake_pool_info.data.borrow_mut())?;
+
+    Event::RateRebalanced {
+        pool: *stake_pool_info.key,
+        total_staked: stake_pool.total_staked,
+        reward_numerator,
+        reward_denominator: denominator,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Hand the reward mint authority of a deprecated pool over, or revoke it
+pub fn process_release_reward_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_pool_authority_info = next_account_info(account_info_iter)?;
+    let reward_mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "ReleaseRewardMint: new authority {} pool {}",
+        new_authority,
+        stake_pool_info.key
+    );
+
+    if !stake_pool.deprecated {
+        return Err(CustomError::PoolNotDeprecated.into());
+    }
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &stake_pool.authority_address(program_id, stake_pool_info.key)?,
+        CustomError::InvalidPoolAuthority,
+    )?;
+    assert_key(
+        accounts,
+        reward_mint_info,
+        &stake_pool.reward_mint,
+        CustomError::InvalidTokenMint,
+    )?;
+
+    spl_token_set_authority(TokenSetAuthorityParams {
+        mint: reward_mint_info.clone(),
+        new_authority: Some(new_authority).filter(|key| *key != Pubkey::default()),
+        authority: stake_pool_authority_info.clone(),
+        authority_signer_seeds: &Pool::authority_seeds(stake_pool_info.key, &stake_pool.bump_seed),
+        token_program: token_program_info.clone(),
+    })?;
+
+    Event::RewardMintReleased {
+        pool: *stake_pool_info.key,
+        reward_mint: *reward_mint_info.key,
+        new_authority,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Global config of the program, the default one, with no fees nor caps,
+/// until it is created
+fn load_global_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    global_config_info: &AccountInfo,
+) -> Result<GlobalConfig, ProgramError> {
+    assert_key(
+        accounts,
+        global_config_info,
+        &find_global_config_address(program_id).0,
+        CustomError::InvalidGlobalConfig,
+    )?;
+    if global_config_info.owner != program_id {
+        return Ok(GlobalConfig::default());
+    }
+    GlobalConfig::unpack(&global_config_info.data.borrow())
+}
+
+/// Pay the pool creation fee of the global config to the protocol treasury
+fn pay_pool_creation_fee<'a>(
+    accounts: &[AccountInfo],
+    global_config: &GlobalConfig,
+    payer_info: &AccountInfo<'a>,
+    treasury_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    if global_config.pool_creation_fee == 0 {
+        return Ok(());
+    }
+    assert_key(
+        accounts,
+        treasury_info,
+        &global_config.treasury,
+        CustomError::InvalidTreasury,
+    )?;
+    if !system_program::check_id(system_program_info.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke(
+        &system_instruction::transfer(
+            payer_info.key,
+            treasury_info.key,
+            global_config.pool_creation_fee,
+        ),
+        &[
+            payer_info.clone(),
+            treasury_info.clone(),
+            system_program_info.clone(),
+        ],
+    )
+}
+
+/// Set the program-wide config, created by the upgrade authority of the
+/// program on first use
+pub fn process_set_global_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: GlobalConfigData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let global_config_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let program_data_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetGlobalConfig: {:?} authority {}",
+        data,
+        authority_info.key
+    );
+
+    assert_signer(accounts, authority_info)?;
+    if u64::from(data.default_claim_burn_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+    let (global_config_pubkey, bump_seed) = find_global_config_address(program_id);
+    assert_key(
+        accounts,
+        global_config_info,
+        &global_config_pubkey,
+        CustomError::InvalidGlobalConfig,
+    )?;
+
+    let mut global_config = if global_config_info.owner == program_id {
+        let global_config = GlobalConfig::unpack(&global_config_info.data.borrow())?;
+        assert_key(
+            accounts,
+            authority_info,
+            &global_config.authority,
+            CustomError::InvalidAdmin,
+        )?;
+        global_config
+    } else {
+        assert_key(
+            accounts,
+            program_data_info,
+            &find_program_data_address(program_id).0,
+            CustomError::InvalidGlobalConfig,
+        )?;
+        if upgrade_authority(program_data_info)? != Some(*authority_info.key) {
+            return Err(reject_account(
+                accounts,
+                authority_info,
+                "not the upgrade authority of the program",
+                CustomError::InvalidAdmin,
+            ));
+        }
+        create_pda_account(
+            rent,
+            payer_info,
+            global_config_info,
+            system_program_info,
+            GlobalConfig::LEN,
+            program_id,
+            &[GLOBAL_CONFIG_SEED, &[bump_seed]],
+        )?;
+        GlobalConfig {
+            is_initialized: true,
+            bump_seed,
+            ..GlobalConfig::default()
+        }
+    };
+    global_config.authority = data.authority;
+    global_config.treasury = data.treasury;
+    global_config.pool_creation_fee = data.pool_creation_fee;
+    global_config.guardian = data.guardian;
+    global_config.default_claim_burn_bps = data.default_claim_burn_bps;
+    global_config.max_daily_reward_bps = data.max_daily_reward_bps;
+    GlobalConfig::pack(global_config, &mut global_config_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Halt or resume stakes and claims of all pools, by the guardian or the
+/// authority of the global config
+pub fn process_set_protocol_pause(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let global_config_info = next_account_info(account_info_iter)?;
+    let guardian_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetProtocolPause: paused {} guardian {}",
+        paused,
+        guardian_info.key
+    );
+
+    assert_key(
+        accounts,
+        global_config_info,
+        &find_global_config_address(program_id).0,
+        CustomError::InvalidGlobalConfig,
+    )?;
+    assert_owner(accounts, global_config_info, program_id)?;
+    assert_signer(accounts, guardian_info)?;
+
+    let mut global_config = GlobalConfig::unpack(&global_config_info.data.borrow())?;
+    if *guardian_info.key != global_config.guardian && *guardian_info.key != global_config.authority
+    {
+        return Err(reject_account(
+            accounts,
+            guardian_info,
+            "neither the guardian nor the authority of the global config",
+            CustomError::InvalidAdmin,
+        ));
+    }
+    global_config.paused = paused;
+    GlobalConfig::pack(global_config, &mut global_config_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Upgrade authority held by the program data account of a program, `None`
+/// once the program is immutable
+fn upgrade_authority(program_data_info: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+    if !bpf_loader_upgradeable::check_id(program_data_info.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = program_data_info.data.borrow();
+    // bincode layout of `UpgradeableLoaderState::ProgramData`: a u32 variant
+    // tag, the deployment slot and an optional authority
+    match data.get(..UpgradeableLoaderState::size_of_programdata_metadata()) {
+        Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 0, ..]) => Ok(None),
+        Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 1, authority @ ..]) => {
+            Ok(Some(Pubkey::new_from_array(*array_ref![authority, 0, 32])))
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Check that the rent of a stake user the rent vault of its pool paid goes
+/// back to the vault, so owners can't pocket it
+fn assert_rent_refund(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    stake_pool_info: &AccountInfo,
+    stake_user: &StakeUser,
+    destination_info: &AccountInfo,
+) -> ProgramResult {
+    if !stake_user.rent_sponsored {
+        return Ok(());
+    }
+    assert_key(
+        accounts,
+        destination_info,
+        &find_rent_vault_address(program_id, stake_pool_info.key).0,
+        CustomError::InvalidRentVault,
+    )
+}
+
+/// Close an account of the program, moving its lamports to a destination and
+/// zeroing its data. The destination can't be the account itself, which
+/// would keep its rent and leave it zeroed instead of closed.
+fn close_account(account_info: &AccountInfo, destination_info: &AccountInfo) -> ProgramResult {
+    if account_info.key == destination_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let lamports = account_info.lamports();
+    **account_info.lamports.borrow_mut() = 0;
+    **destination_info.lamports.borrow_mut() = destination_info
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(CustomError::CalculationFailure)?;
+    account_info.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+/// Create an account at a program address of the program, paid by a payer.
+/// Lamports already sent to the address count toward its rent.
+fn create_pda_account<'a>(
+    rent: &Rent,
+    payer_info: &AccountInfo<'a>,
+    new_account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if !system_program::check_id(system_program_info.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let required = rent
+        .minimum_balance(space)
+        .saturating_sub(new_account_info.lamports());
+    if new_account_info.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                new_account_info.key,
+                required,
+                space as u64,
+                owner,
+            ),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[signer_seeds],
+        );
+    }
+
+    if required > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, new_account_info.key, required),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(new_account_info.key, space as u64),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(new_account_info.key, owner),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Hand the pool over to a new admin, which must be able to sign
+pub fn process_set_admin(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    admin: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!("SetAdmin: admin {} pool {}", admin, stake_pool_info.key);
+
+    // nobody signs for the default pubkey, locking the pool settings for good
+    if admin == Pubkey::default() {
+        return Err(CustomError::InvalidAdmin.into());
+    }
+
+    stake_pool.admin = admin;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Delegate a role of the pool admin to a key
+pub fn process_set_role(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    role: u8,
+    holder: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let role = Role::try_from(role)?;
+    trace!(
+        "SetRole: {:?} holder {} pool {}",
+        role,
+        holder,
+        stake_pool_info.key
+    );
+
+    stake_pool.set_role_holder(role, holder);
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_rounding(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    rounding: u8,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let rounding = Rounding::from_u8(rounding).ok_or(ProgramError::InvalidArgument)?;
+    trace!("SetRounding: {:?} pool {}", rounding, stake_pool_info.key);
+
+    stake_pool.rounding = rounding;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_streak_bonus(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    streak_duration: u64,
+    streak_bonus_bps: u16,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetStreakBonus: duration {} bps {} pool {}",
+        streak_duration,
+        streak_bonus_bps,
+        stake_pool_info.key
+    );
+
+    if u64::from(streak_bonus_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+
+    stake_pool.streak_duration = streak_duration;
+    stake_pool.streak_bonus_bps = streak_bonus_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Set the badge mint, a Token-2022 mint with the non-transferable extension,
+/// no decimals and the pool authority as mint authority
+pub fn process_set_badge_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    badge_mint: Pubkey,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetBadgeMint: mint {} pool {}",
+        badge_mint,
+        stake_pool_info.key
+    );
+
+    if badge_mint != Pubkey::default() {
+        let badge_mint_info = next_account_info(&mut remaining.iter())?;
+        assert_key(
+            accounts,
+            badge_mint_info,
+            &badge_mint,
+            CustomError::InvalidBadgeMint,
+        )?;
+        assert_owner(accounts, badge_mint_info, &spl_token_2022::id())?;
+        let data = badge_mint_info.data.borrow();
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+            .map_err(|_| CustomError::InvalidBadgeMint)?;
+        let authority = stake_pool.authority_address(program_id, stake_pool_info.key)?;
+        if mint.get_extension::<NonTransferable>().is_err()
+            || mint.base.decimals != 0
+            || mint.base.mint_authority != COption::Some(authority)
+        {
+            return Err(reject_account(
+                accounts,
+                badge_mint_info,
+                "not a non-transferable mint of the pool authority",
+                CustomError::InvalidBadgeMint,
+            ));
+        }
+    }
+
+    stake_pool.badge_mint = badge_mint;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_claim_burn(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    claim_burn_bps: u16,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    trace!(
+        "SetClaimBurn: bps {} pool {}",
+        claim_burn_bps,
+        stake_pool_info.key
+    );
+
+    if u64::from(claim_burn_bps) > BPS_DENOMINATOR {
+        return Err(CustomError::InvalidBasisPoints.into());
+    }
+
+    stake_pool.claim_burn_bps = claim_burn_bps;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Schedule the reward rate of an upcoming epoch
+pub fn process_set_epoch_rate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    epoch_rate_data: EpochRateData,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::RateSetter)?;
+    let account_info_iter = &mut remaining.iter();
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let global_config_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetEpochRate: {:?} pool {}",
+        epoch_rate_data,
+        stake_pool_info.key
+    );
+
+    if epoch_rate_data.epoch <= clock.epoch {
+        return Err(CustomError::InvalidEpoch.into());
+    }
+    load_global_config(program_id, accounts, global_config_info)?.check_reward_rate(
+        epoch_rate_data.reward_numerator,
+        epoch_rate_data.reward_denominator,
+        stake_pool.reward_period,
+    )?;
+
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    stake_pool.set_epoch_rate(
+        epoch_rate_data.epoch,
+        epoch_rate_data.reward_numerator,
+        epoch_rate_data.reward_denominator,
+    )?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Change the reward rate from the current timestamp on
+pub fn process_set_reward_rate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    rate_data: RateData,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load_role(accounts, program_id, Role::RateSetter)?;
+    let account_info_iter = &mut remaining.iter();
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let global_config_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetRewardRate: {:?} pool {}",
+        rate_data,
+        stake_pool_info.key
+    );
+
+    load_global_config(program_id, accounts, global_config_info)?.check_reward_rate(
+        rate_data.reward_numerator,
+        rate_data.reward_denominator,
+        stake_pool.reward_period,
+    )?;
+
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    stake_pool.checkpoint_rate(
+        clock.unix_timestamp,
+        rate_data.reward_numerator,
+        rate_data.reward_denominator,
+    )?;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Record a snapshot of the pool accounting for audits
+pub fn process_snapshot_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let pool_snapshot_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let reward_token_mint_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "SnapshotPool: pool {} snapshot {}",
+        stake_pool_info.key,
+        pool_snapshot_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, pool_snapshot_info, program_id)?;
+
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    assert_key(
+        accounts,
+        reserve_info,
+        &stake_pool.reserved,
+        CustomError::InvalidTokenAccount,
+    )?;
+    assert_key(
+        accounts,
+        reward_token_mint_info,
+        &stake_pool.reward_mint,
+        CustomError::InvalidTokenMint,
+    )?;
+    let reserve = unpack_token_account(reserve_info, token_program_of(reserve_info))?;
+    let reward_mint = unpack_mint(
+        reward_token_mint_info,
+        token_program_of(reward_token_mint_info),
+    )?;
+
+    assert_rent_exempt(rent, pool_snapshot_info)?;
+    assert_uninitialized::<PoolSnapshot>(pool_snapshot_info)?;
+
+    stake_pool.observe_epoch(clock.epoch, clock.epoch_start_timestamp);
+    let (reward_numerator, reward_denominator) = stake_pool.current_rate();
+    let pool_snapshot = PoolSnapshot {
+        is_initialized: true,
+        pool_pubkey: *stake_pool_info.key,
+        timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+        total_staked: stake_pool.total_staked,
+        reserve_balance: reserve.amount,
+        reward_supply: reward_mint.supply,
+        reward_numerator,
+        reward_denominator,
+        reward_period: stake_pool.reward_period,
+    };
+    PoolSnapshot::pack(pool_snapshot, &mut pool_snapshot_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Record the emissions of the pool in its distribution report of the current
+/// epoch, with the deltas since the last report of the pool if any
+pub fn process_finalize_distribution_report(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let report_info = next_account_info(account_info_iter)?;
+    let reward_token_mint_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let previous_report_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "FinalizeDistributionReport: epoch {} pool {} report {}",
+        clock.epoch,
+        stake_pool_info.key,
+        report_info.key
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    let (report_pubkey, bump_seed) =
+        find_distribution_report_address(program_id, stake_pool_info.key, clock.epoch);
+    assert_key(
+        accounts,
+        report_info,
+        &report_pubkey,
+        CustomError::InvalidDistributionReport,
+    )?;
+    if report_info.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let mut stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    assert_key(
+        accounts,
+        reward_token_mint_info,
+        &stake_pool.reward_mint,
+        CustomError::InvalidTokenMint,
+    )?;
+    let reward_mint = unpack_mint(
+        reward_token_mint_info,
+        token_program_of(reward_token_mint_info),
+    )?;
+
+    // reports chain: each one is since the last, so none can be skipped
+    let previous_report = match (stake_pool.has_distribution_report, previous_report_info) {
+        (true, Some(previous_report_info)) => {
+            let (previous_pubkey, _) = find_distribution_report_address(
+                program_id,
+                stake_pool_info.key,
+                stake_pool.last_report_epoch,
+            );
+            assert_key(
+                accounts,
+                previous_report_info,
+                &previous_pubkey,
+                CustomError::InvalidDistributionReport,
+            )?;
+            assert_owner(accounts, previous_report_info, program_id)?;
+            Some(DistributionReport::unpack(
+                &previous_report_info.data.borrow(),
+            )?)
+        }
+        (true, None) => {
+            msg!(
+                "Distribution report of epoch {} missing",
+                stake_pool.last_report_epoch
+            );
+            return Err(CustomError::InvalidDistributionReport.into());
+        }
+        (false, Some(previous_report_info)) => {
+            return Err(reject_account(
+                accounts,
+                previous_report_info,
+                "the pool has no distribution report yet",
+                CustomError::InvalidDistributionReport,
+            ));
+        }
+        (false, None) => None,
+    };
+    let report = DistributionReport::new(
+        *stake_pool_info.key,
+        &stake_pool,
+        reward_mint.supply,
+        clock,
+        previous_report.as_ref(),
+    )?;
+
+    create_pda_account(
+        rent,
+        payer_info,
+        report_info,
+        system_program_info,
+        DistributionReport::LEN,
+        program_id,
+        &[
+            stake_pool_info.key.as_ref(),
+            DISTRIBUTION_REPORT_SEED,
+            &clock.epoch.to_le_bytes(),
+            &[bump_seed],
+        ],
+    )?;
+    DistributionReport::pack(report, &mut report_info.data.borrow_mut())?;
+
+    stake_pool.has_distribution_report = true;
+    stake_pool.last_report_epoch = clock.epoch;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Set the destinations the claims of a stake user are split between,
+/// creating its claim split on first use
+pub fn process_set_claim_split(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shares: [ClaimShare; MAX_CLAIM_SHARES],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_user_info = next_account_info(account_info_iter)?;
+    let stake_owner_info = next_account_info(account_info_iter)?;
+    let claim_split_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    trace!(
+        "SetClaimSplit: pool {} user {} shares {:?}",
+        stake_pool_info.key,
+        stake_user_info.key,
+        shares
+    );
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+    assert_owner(accounts, stake_user_info, program_id)?;
+    assert_signer(accounts, stake_owner_info)?;
+    let mut stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_info,
+        &stake_user.pool_pubkey,
+        CustomError::InvalidStakeOwner,
+    )?;
+    assert_key(
+        accounts,
+        stake_owner_info,
+        &stake_user.owner,
+        CustomError::InvalidStakeOwner,
+    )?;
+    let (claim_split_pubkey, bump_seed) = find_claim_split_address(program_id, stake_user_info.key);
+    assert_key(
+        accounts,
+        claim_split_info,
+        &claim_split_pubkey,
+        CustomError::InvalidClaimSplit,
+    )?;
+
+    let exists = claim_split_info.owner == program_id;
+    let mut claim_split = if exists {
+        ClaimSplit::unpack(&claim_split_info.data.borrow())?
+    } else {
+        ClaimSplit {
+            is_initialized: true,
+            stake_user_pubkey: *stake_user_info.key,
+            ..ClaimSplit::default()
+        }
+    };
+    claim_split.set_shares(shares)?;
+
+    if !exists {
+        create_pda_account(
+            rent,
+            payer_info,
+            claim_split_info,
+            system_program_info,
+            ClaimSplit::LEN,
+            program_id,
+            &[stake_user_info.key.as_ref(), CLAIM_SPLIT_SEED, &[bump_seed]],
+        )?;
+        stake_user.claim_split = true;
+        StakeUser::pack(stake_user, &mut stake_user_info.data.borrow_mut())?;
+    }
+    ClaimSplit::pack(claim_split, &mut claim_split_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Start a merkle snapshot of the stakes held at a timestamp
+pub fn process_create_stake_snapshot(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    timestamp: i64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_snapshot_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "CreateStakeSnapshot: timestamp {} pool {} snapshot {}",
+        timestamp,
+        stake_pool_info.key,
+        stake_snapshot_info.key
+    );
+
+    assert_owner(accounts, stake_snapshot_info, program_id)?;
+    assert_rent_exempt(rent, stake_snapshot_info)?;
+    assert_uninitialized::<StakeSnapshot>(stake_snapshot_info)?;
+
+    if stake_pool.snapshot != Pubkey::default() {
+        return Err(CustomError::SnapshotPending.into());
+    }
+    // stakes changed before now can't be recovered
+    if timestamp < clock.unix_timestamp {
+        return Err(CustomError::InvalidSnapshot.into());
+    }
+
+    let stake_snapshot = StakeSnapshot {
+        is_initialized: true,
+        pool_pubkey: *stake_pool_info.key,
+        timestamp,
+        ..StakeSnapshot::default()
+    };
+    StakeSnapshot::pack(stake_snapshot, &mut stake_snapshot_info.data.borrow_mut())?;
+    stake_pool.snapshot = *stake_snapshot_info.key;
+    stake_pool.snapshot_ts = timestamp;
+    Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Add stake users to the snapshot being built, finalizing it if asked
+pub fn process_update_stake_snapshot(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    finalize: bool,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        mut stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let stake_snapshot_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "UpdateStakeSnapshot: finalize {} pool {} snapshot {}",
+        finalize,
+        stake_pool_info.key,
+        stake_snapshot_info.key
+    );
+
+    assert_key(
+        accounts,
+        stake_snapshot_info,
+        &stake_pool.snapshot,
+        CustomError::InvalidSnapshot,
+    )?;
+    assert_owner(accounts, stake_snapshot_info, program_id)?;
+    let mut stake_snapshot = StakeSnapshot::unpack(&stake_snapshot_info.data.borrow())?;
+    // stakes may still change within the snapshot second
+    if clock.unix_timestamp <= stake_snapshot.timestamp {
+        return Err(CustomError::SnapshotPending.into());
+    }
+
+    for stake_user_info in account_info_iter {
+        assert_owner(accounts, stake_user_info, program_id)?;
+        let stake_user = StakeUser::unpack(&stake_user_info.data.borrow())?;
+        stake_snapshot.add_stake_user(stake_user_info.key, &stake_user)?;
+    }
+
+    if finalize {
+        stake_snapshot.finalize();
+        stake_pool.snapshot = Pubkey::default();
+        Pool::pack(stake_pool, &mut stake_pool_info.data.borrow_mut())?;
+    }
+    StakeSnapshot::pack(stake_snapshot, &mut stake_snapshot_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Publish a bonus campaign, its vault owned by the bonus authority
+pub fn process_publish_bonus(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    root: [u8; 32],
+    leaf_count: u64,
+) -> ProgramResult {
+    let AdminAccounts {
+        stake_pool_info,
+        remaining,
+        stake_pool,
+        ..
+    } = AdminAccounts::load(accounts, program_id)?;
+    let account_info_iter = &mut remaining.iter();
+    let bonus_campaign_info = next_account_info(account_info_iter)?;
+    let bonus_vault_info = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    trace!(
+        "PublishBonus: leaves {} pool {} campaign {} vault {}",
+        leaf_count,
+        stake_pool_info.key,
+        bonus_campaign_info.key,
+        bonus_vault_info.key
+    );
+
+    assert_owner(accounts, bonus_campaign_info, program_id)?;
+    assert_rent_exempt(rent, bonus_campaign_info)?;
+    let mut data = bonus_campaign_info.data.borrow_mut();
+    if leaf_count == 0 || data.len() < BonusCampaign::account_len(leaf_count) {
+        return Err(reject_account(
+            accounts,
+            bonus_campaign_info,
+            "too small for the claimed bitmap",
+            CustomError::InvalidBonusCampaign,
+        ));
+    }
+    let (header, claimed) = data.split_at_mut(BonusCampaign::LEN);
+    if BonusCampaign::unpack_unchecked(header)?.is_initialized {
+        return Err(CustomError::AlreadyInUse.into());
+    }
+
+    let (authority, authority_bump) =
+        find_bonus_authority_address(program_id, bonus_campaign_info.key);
+    let bonus_vault = unpack_token_account(bonus_vault_info, token_program_of(bonus_vault_info))?;
+    if bonus_vault.owner != authority || bonus_vault.mint != stake_pool.reward_mint {
+        return Err(reject_account(
+            accounts,
+            bonus_vault_info,
+            "not a reward token account of the bonus authority",
+            CustomError::InvalidBonusCampaign,
+        ));
+    }
+
+    claimed.fill(0);
+    BonusCampaign::pack(
+        BonusCampaign {
+            is_initialized: true,
+            pool_pubkey: *stake_pool_info.key,
+            root,
+            leaf_count,
+            vault: *bonus_vault_info.key,
+            authority_bump,
+            total_claimed: 0,
+        },
+        header,
+    )?;
+
+    Ok(())
+}
+
+/// Pay out the bonus of a leaf proven against the campaign root, once
+pub fn process_claim_bonus(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u64,
+    amount: u64,
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let bonus_campaign_info = next_account_info(account_info_iter)?;
+    let bonus_authority_info = next_account_info(account_info_iter)?;
+    let bonus_vault_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let reward_token_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let reward_mint_info = next_account_info(account_info_iter).ok();
+    trace!(
+        "ClaimBonus: index {} amount {} campaign {} owner {}",
+        index,
+        amount,
+        bonus_campaign_info.key,
+        owner_info.key
+    );
+
+    assert_owner(accounts, bonus_campaign_info, program_id)?;
+    assert_signer(accounts, owner_info)?;
+
+    let authority_bump = {
+        let mut data = bonus_campaign_info.data.borrow_mut();
+        if data.len() < BonusCampaign::LEN {
+            return Err(CustomError::InvalidBonusCampaign.into());
+        }
+        let (header, claimed) = data.split_at_mut(BonusCampaign::LEN);
+        let mut bonus_campaign = BonusCampaign::unpack(header)?;
+        assert_key(
+            accounts,
+            bonus_vault_info,
+            &bonus_campaign.vault,
+            CustomError::InvalidBonusCampaign,
+        )?;
+        unpack_token_account(bonus_vault_info, token_program_info.key)?;
+
+        let leaf = merkle::leaf_hash(owner_info.key, amount);
+        if index >= bonus_campaign.leaf_count
+            || !merkle::verify(&bonus_campaign.root, leaf, index, proof)
+        {
+            return Err(CustomError::InvalidProof.into());
+        }
+        if !BonusCampaign::claim_leaf(claimed, index) {
+            return Err(CustomError::BonusClaimed.into());
+        }
+
+        bonus_campaign.total_claimed = bonus_campaign
+            .total_claimed
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        let authority_bump = bonus_campaign.authority_bump;
+        BonusCampaign::pack(bonus_campaign, header)?;
+        authority_bump
+    };
+
+    let authority_signer_seeds: &[&[u8]] = &[
+        bonus_campaign_info.key.as_ref(),
+        BONUS_AUTHORITY_SEED,
+        &[authority_bump],
+    ];
+    let authority = Pubkey::create_program_address(authority_signer_seeds, program_id)?;
+    assert_key(
+        accounts,
+        bonus_authority_info,
+        &authority,
+        CustomError::InvalidBonusCampaign,
+    )?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: bonus_vault_info.clone(),
+        mint: reward_mint_info.cloned(),
+        destination: reward_token_info.clone(),
+        amount,
+        authority: bonus_authority_info.clone(),
+        authority_signer_seeds,
+        token_program: token_program_info.clone(),
+    })
+}
+
+/// Check the pool accounting against its token accounts
+pub fn process_verify_invariants(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_pool_info = next_account_info(account_info_iter)?;
+    let stake_pool_authority_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let reward_token_info = next_account_info(account_info_iter)?;
+    trace!("VerifyInvariants: pool {}", stake_pool_info.key);
+
+    assert_owner(accounts, stake_pool_info, program_id)?;
+
+    let stake_pool = Pool::unpack(&stake_pool_info.data.borrow())?;
+    assert_key(
+        accounts,
+        stake_pool_authority_info,
+        &stake_pool.authority_address(program_id, stake_pool_info.key)?,
+        CustomError::InvalidPoolAuthority,
+    )?;
+    assert_key(
+        accounts,
+        reserve_info,
+        &stake_pool.reserved,
+        CustomError::InvalidTokenAccount,
+    )?;
+
+    let mut violations = 0;
+    let mut violation = |invariant| {
+        Event::InvariantViolated {
+            pool: *stake_pool_info.key,
+            invariant,
+        }
+        .emit();
+        violations += 1;
+    };
+
+    let reserve = unpack_token_account(reserve_info, token_program_of(reserve_info))?;
+    let tracked = stake_pool
+        .total_staked
+        .checked_add(stake_pool.protocol_owned)
+        .ok_or(CustomError::CalculationFailure)?;
+    if reserve.amount < tracked {
+        violation(Invariant::ReserveBalance);
+    }
+    if reserve.delegate.is_some() {
+        violation(Invariant::ReserveDelegate);
+    }
+
+    if stake_pool.is_vault_funded() {
+        assert_key(
+            accounts,
+            reward_token_info,
+            &stake_pool.reward_vault,
+            CustomError::InvalidTokenAccount,
+        )?;
+        let vault = unpack_token_account(reward_token_info, token_program_of(reward_token_info))?;
+        if vault.owner != *stake_pool_authority_info.key {
+            violation(Invariant::VaultOwner);
+        }
+        if vault.delegate.is_some() {
+            violation(Invariant::VaultDelegate);
+        }
+    } else {
+        assert_key(
+            accounts,
+            reward_token_info,
+            &stake_pool.reward_mint,
+            CustomError::InvalidTokenMint,
+        )?;
+        let reward_mint = unpack_mint(reward_token_info, token_program_of(reward_token_info))?;
+        if reward_mint.mint_authority != COption::Some(*stake_pool_authority_info.key) {
+            violation(Invariant::MintAuthority);
+        }
+    }
+
+    if violations != 0 {
+        return Err(CustomError::InvariantViolated.into());
+    }
+
+    Ok(())
+}
+
+pub fn assert_admin(
+    accounts: &[AccountInfo],
+    stake_pool: &Pool,
+    admin_info: &AccountInfo,
+) -> ProgramResult {
+    assert_signer(accounts, admin_info)?;
+    assert_key(
+        accounts,
+        admin_info,
+        &stake_pool.admin,
+        CustomError::InvalidAdmin,
+    )?;
+    Ok(())
+}
+
+/// Check that the admin or the holder of a role of the pool signed
+pub fn assert_role(
+    accounts: &[AccountInfo],
+    stake_pool: &Pool,
+    role: Role,
+    signer_info: &AccountInfo,
+) -> ProgramResult {
+    assert_signer(accounts, signer_info)?;
+    if !stake_pool.has_role(role, signer_info.key) {
+        return Err(reject_account(
+            accounts,
+            signer_info,
+            "neither the admin nor the holder of the role",
+            CustomError::InvalidAdmin,
+        ));
+    }
+    Ok(())
+}
+
+/// Position of an account in the instruction accounts, for error reports
+fn account_index(accounts: &[AccountInfo], account_info: &AccountInfo) -> usize {
+    accounts
+        .iter()
+        .position(|info| info.key == account_info.key)
+        .unwrap_or(accounts.len())
+}
+
+/// Log the failing account and why before returning `error`
+pub fn reject_account(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    reason: &str,
+    error: CustomError,
+) -> ProgramError {
+    msg!(
+        "Account {} {}: {}",
+        account_index(accounts, account_info),
+        account_info.key,
+        reason
+    );
+    error.into()
+}
+
+/// Check a pubkey held by or about an account, logging the account index with
+/// the expected and actual values on mismatch
+pub fn assert_field(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    field: &str,
+    expected: &Pubkey,
+    actual: &Pubkey,
+    error: CustomError,
+) -> ProgramResult {
+    if expected != actual {
+        msg!(
+            "Account {} {}: expected {} {}, got {}",
+            account_index(accounts, account_info),
+            account_info.key,
+            field,
+            expected,
+            actual
+        );
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+pub fn assert_key(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    expected: &Pubkey,
+    error: CustomError,
+) -> ProgramResult {
+    assert_field(
+        accounts,
+        account_info,
+        "key",
+        expected,
+        account_info.key,
+        error,
+    )
+}
+
+/// Check that a token account isn't frozen, which would fail transfers in or
+/// out of it with an opaque error
+pub fn assert_not_frozen(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    token_account: &Account,
+) -> ProgramResult {
+    if token_account.is_frozen() {
+        return Err(reject_account(
+            accounts,
+            account_info,
+            "frozen",
+            CustomError::AccountFrozen,
+        ));
+    }
+    Ok(())
+}
+
+pub fn assert_owner(
+    accounts: &[AccountInfo],
+    account_info: &AccountInfo,
+    owner: &Pubkey,
+) -> ProgramResult {
+    assert_field(
+        accounts,
+        account_info,
+        "owner",
+        owner,
+        account_info.owner,
+        CustomError::InvalidAccountOwner,
+    )
+}
+
+/// Check that an account is the spl-token or the Token-2022 program, for
+/// token accounts read without a transfer through their program
+pub fn assert_token_program(accounts: &[AccountInfo], account_info: &AccountInfo) -> ProgramResult {
+    if *account_info.key != spl_token::id() && *account_info.key != spl_token_2022::id() {
+        return Err(reject_account(
+            accounts,
+            account_info,
+            "not a token program",
+            CustomError::InvalidTokenProgram,
+        ));
+    }
+    Ok(())
+}
+
+pub fn assert_signer(accounts: &[AccountInfo], account_info: &AccountInfo) -> ProgramResult {
+    if !account_info.is_signer {
+        return Err(reject_account(
+            accounts,
+            account_info,
+            "expected signer",
+            CustomError::InvalidSigner,
+        ));
+    }
+    Ok(())
+}
+
+pub fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
+    if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
+        Err(CustomError::NotRentExempt.into())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn assert_uninitialized<T: Pack + IsInitialized>(
+    account_info: &AccountInfo,
+) -> Result<T, ProgramError> {
+    let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
+    if account.is_initialized() {
+        Err(CustomError::AlreadyInUse.into())
+    } else {
+        Ok(account)
+    }
+}
+
+/// Token program of a mint or token account, Token-2022 for the accounts it
+/// owns and spl-token otherwise, for `unpack_mint` or `unpack_token_account`
+/// to check
+pub fn token_program_of(account_info: &AccountInfo) -> &'static Pubkey {
+    if account_info.owner == &spl_token_2022::id() {
+        &spl_token_2022::ID
+    } else {
+        &spl_token::ID
+    }
+}
+
+pub fn unpack_mint(
+    account_info: &AccountInfo,
+    token_program_id: &Pubkey,
+) -> Result<Mint, ProgramError> {
+    if account_info.owner != token_program_id {
+        msg!(
+            "Account {}: expected owner {}, got {}",
+            account_info.key,
+            token_program_id,
+            account_info.owner
+        );
+        Err(CustomError::InvalidAccountOwner.into())
+    } else {
+        let data = account_info.data.borrow();
+        // Token-2022 mints with extensions share the base layout
+        if data.len() > Mint::LEN {
+            StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+                .map_err(|_| CustomError::InvalidTokenMint)?;
+        }
+        Mint::unpack(data.get(..Mint::LEN).unwrap_or(&data))
+            .map_err(|_| CustomError::InvalidTokenMint.into())
+    }
+}
+
+pub fn unpack_token_account(
+    account_info: &AccountInfo,
+    token_program_id: &Pubkey,
+) -> Result<Account, ProgramError> {
+    if account_info.owner != token_program_id {
+        msg!(
+            "Account {}: expected owner {}, got {}",
+            account_info.key,
+            token_program_id,
+            account_info.owner
+        );
+        Err(CustomError::InvalidAccountOwner.into())
+    } else {
+        let data = account_info.data.borrow();
+        // Token-2022 accounts with extensions share the base layout
+        if data.len() > Account::LEN {
+            StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)
+                .map_err(|_| CustomError::InvalidTokenAccount)?;
+        }
+        Account::unpack(data.get(..Account::LEN).unwrap_or(&data))
+            .map_err(|_| CustomError::InvalidTokenAccount.into())
+    }
+}
+
+struct TokenInitializeAccountParams<'a> {
+    account: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    owner: AccountInfo<'a>,
+    rent: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+}
+
+struct TokenInitializeMintParams<'a: 'b, 'b> {
+    mint: AccountInfo<'a>,
+    rent: AccountInfo<'a>,
+    authority: &'b Pubkey,
+    decimals: u8,
+    token_program: AccountInfo<'a>,
+}
+
+struct TokenTransferParams<'a: 'b, 'b> {
+    source: AccountInfo<'a>,
+    /// Mint of the tokens, which Token-2022 transfers need
+    mint: Option<AccountInfo<'a>>,
+    destination: AccountInfo<'a>,
+    amount: u64,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+}
+
+struct TokenTransferCheckedParams<'a: 'b, 'b> {
+    source: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    amount: u64,
+    decimals: u8,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+    /// Accounts of the transfer hook of the mint, if any
+    transfer_hook_accounts: &'b [AccountInfo<'a>],
+}
+
+struct TokenMintToParams<'a: 'b, 'b> {
+    mint: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    amount: u64,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+}
+
+struct TokenBurnParams<'a: 'b, 'b> {
+    mint: AccountInfo<'a>,
+    source: AccountInfo<'a>,
+    amount: u64,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+}
+
+struct TokenSetAuthorityParams<'a: 'b, 'b> {
+    mint: AccountInfo<'a>,
+    new_authority: Option<Pubkey>,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+}
+
+fn spl_token_init_account(params: TokenInitializeAccountParams<'_>) -> ProgramResult {
+    let TokenInitializeAccountParams {
+        account,
+        mint,
+        owner,
+        rent,
+        token_program,
+    } = params;
+    let ix = spl_token_2022::instruction::initialize_account(
+        token_program.key,
+        account.key,
+        mint.key,
+        owner.key,
+    )?;
+    let result = invoke(&ix, &[account, mint, owner, rent, token_program]);
+    result.map_err(|_| CustomError::TokenInitializeAccountFailed.into())
+}
+
+fn spl_token_init_mint(params: TokenInitializeMintParams<'_, '_>) -> ProgramResult {
+    let TokenInitializeMintParams {
+        mint,
+        rent,
+        authority,
+        token_program,
+        decimals,
+    } = params;
+    let ix = spl_token_2022::instruction::initialize_mint(
+        token_program.key,
+        mint.key,
+        authority,
+        None,
+        decimals,
+    )?;
+    let result = invoke(&ix, &[mint, rent, token_program]);
+    result.map_err(|_| CustomError::TokenInitializeMintFailed.into())
+}
+
+/// Transfer tokens with `transfer_checked` of the token program when the
+/// mint is at hand, falling back to a plain spl-token transfer otherwise
+fn spl_token_transfer(params: TokenTransferParams<'_, '_>) -> ProgramResult {
+    let TokenTransferParams {
+        source,
+        mint,
+        destination,
+        authority,
+        token_program,
+        amount,
+        authority_signer_seeds,
+    } = params;
+    let mint = match mint {
+        Some(mint) => mint,
+        None if *token_program.key == spl_token::id() => {
+            let result = invoke_optionally_signed(
+                &spl_token::instruction::transfer(
+                    token_program.key,
+                    source.key,
+                    destination.key,
+                    authority.key,
+                    &[],
+                    amount,
+                )?,
+                &[source, destination, authority, token_program],
+                authority_signer_seeds,
+            );
+            return result.map_err(|_| CustomError::TokenTransferFailed.into());
+        }
+        None => {
+            msg!("Mint of {} missing", source.key);
+            return Err(CustomError::InvalidTokenMint.into());
+        }
+    };
+    let decimals = unpack_mint(&mint, token_program.key)?.decimals;
+    spl_token_transfer_checked(TokenTransferCheckedParams {
+        source,
+        mint,
+        destination,
+        amount,
+        decimals,
+        authority,
+        authority_signer_seeds,
+        token_program,
+        transfer_hook_accounts: &[],
+    })
+}
+
+fn spl_token_transfer_checked(params: TokenTransferCheckedParams<'_, '_>) -> ProgramResult {
+    let TokenTransferCheckedParams {
+        source,
+        mint,
+        destination,
+        amount,
+        decimals,
+        authority,
+        authority_signer_seeds,
+        token_program,
+        transfer_hook_accounts,
+    } = params;
+    let mut ix = spl_token_2022::instruction::transfer_checked(
+        token_program.key,
+        source.key,
+        mint.key,
+        destination.key,
+        authority.key,
+        &[],
+        amount,
+        decimals,
+    )?;
+    let mut account_infos = vec![source, mint.clone(), destination, authority];
+    let transfer_hook_program = {
+        let data = mint.data.borrow();
+        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+            .ok()
+            .and_then(|mint| transfer_hook::get_program_id(&mint))
+    };
+    if let Some(transfer_hook_program) = transfer_hook_program {
+        add_cpi_accounts_for_execute(
+            &mut ix,
+            &mut account_infos,
+            mint.key,
+            &transfer_hook_program,
+            transfer_hook_accounts,
+        )?;
+    }
+    account_infos.push(token_program);
+    let result = invoke_optionally_signed(&ix, &account_infos, authority_signer_seeds);
+    result.map_err(|_| CustomError::TokenTransferFailed.into())
+}
+
+fn spl_token_mint_to(params: TokenMintToParams<'_, '_>) -> ProgramResult {
+    let TokenMintToParams {
+        mint,
+        destination,
+        authority,
+        token_program,
+        amount,
+        authority_signer_seeds,
+    } = params;
+    let result = invoke_optionally_signed(
+        &spl_token_2022::instruction::mint_to(
+            token_program.key,
+            mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+        )?,
+        &[mint, destination, authority, token_program],
+        authority_signer_seeds,
+    );
+    result.map_err(|_| CustomError::TokenMintToFailed.into())
+}
+
+fn spl_token_burn(params: TokenBurnParams<'_, '_>) -> ProgramResult {
+    let TokenBurnParams {
+        mint,
+        source,
+        authority,
+        token_program,
+        amount,
+        authority_signer_seeds,
+    } = params;
+    let decimals = unpack_mint(&mint, token_program.key)?.decimals;
+    let result = invoke_optionally_signed(
+        &spl_token_2022::instruction::burn_checked(
+            token_program.key,
+            source.key,
+            mint.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[source, mint, authority, token_program],
+        authority_signer_seeds,
+    );
+    result.map_err(|_| CustomError::TokenBurnFailed.into())
+}
+
+fn spl_token_set_authority(params: TokenSetAuthorityParams<'_, '_>) -> ProgramResult {
+    let TokenSetAuthorityParams {
+        mint,
+        new_authority,
+        authority,
+        authority_signer_seeds,
+        token_program,
+    } = params;
+    let result = invoke_optionally_signed(
+        &spl_token_2022::instruction::set_authority(
+            token_program.key,
+            mint.key,
+            new_authority.as_ref(),
+            spl_token_2022::instruction::AuthorityType::MintTokens,
+            authority.key,
+            &[],
+        )?,
+        &[mint, authority, token_program],
+        authority_signer_seeds,
+    );
+    result.map_err(|_| CustomError::TokenSetAuthorityFailed.into())
+}
+
+fn invoke_optionally_signed(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    authority_signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if authority_signer_seeds.is_empty() {
+        invoke(instruction, account_infos)
+    } else {
+        invoke_signed(instruction, account_infos, &[authority_signer_seeds])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use crate::{
+        governance::{find_governance_address, proposal_instructions},
+        instruction::{
+            add_reward_budget, add_stake_mint, claim, claim_and_stake_into, claim_bonus, claim_for,
+            claim_if_any, close_stake_user, create_sponsored_stake_user, create_stake_pool,
+            create_stake_snapshot, create_stake_user, create_stake_user_idempotent,
+            create_vault_funded_stake_pool, deprecate, finalize_distribution_report,
+            fund_rent_vault, fund_rewards, gc_user, get_pool_info, harvest_all, merge_positions,
+            mint_badge, publish_bonus, rebalance_rate, refresh, refresh_batch, register_boost,
+            release_reward_mint, relock, repair_user, set_admin, set_badge_mint,
+            set_boost_collection, set_claim_burn, set_claim_split, set_gc_delay, set_global_config,
+            set_hook_program, set_lock_tiers, set_max_reward_supply, set_max_stake_per_user,
+            set_pair_ratio, set_price_oracle, set_protocol_pause, set_rate_controller,
+            set_reward_rate, set_role, set_rounding, set_streak_bonus, set_stream_duration,
+            set_tiers, set_top_level_only, set_warm_up, snapshot_pool, stake, stake_pair,
+            stake_with_delegate, sweep, sync_reserve, unregister_boost, unstake, unstake_pair,
+            update_stake_snapshot, verify_invariants, with_auto_claim, with_claim_split,
+            with_closed_claim_split, with_global_config, with_hook, with_instructions_sysvar,
+            with_price_oracle, with_reward_token_program, with_stake_mint, withdraw_from_stream,
+            withdraw_rent_vault,
+        },
+        merkle,
+        metadata::{nft_metadata_account, Collection},
+        oracle::pyth_price_account,
+        pda::{
+            find_associated_token_address, find_boost_escrow_authority_address,
+            TOKEN_METADATA_PROGRAM_ID,
+        },
+        state::{LockTier, DAILY_TS, LEGACY_STAKE_USER_SIZE, MAX_STAKE_MINTS},
+    };
+
+    use borsh::BorshDeserialize;
+    use solana_program::{
+        instruction::AccountMeta,
+        program::get_return_data,
+        program_stubs,
+        program_utils::limited_deserialize,
+        system_instruction::SystemInstruction,
+        sysvar::instructions::{construct_instructions_data, BorrowedInstruction},
+    };
+    use solana_sdk::account::{create_account_for_test, create_is_signer_account_infos, Account};
+    use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+    use spl_token::instruction::{approve, initialize_account, initialize_mint, mint_to};
+    use spl_token_2022::{
+        extension::{
+            interest_bearing_mint::InterestBearingConfig, ExtensionType, StateWithExtensionsMut,
+        },
+        instruction::{initialize_immutable_owner, initialize_non_transferable_mint},
+    };
+    use spl_transfer_hook_interface::{
+        get_extra_account_metas_address,
+        instruction::{ExecuteInstruction, TransferHookInstruction},
+    };
+
+    const STAKE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([3u8; 32]);
+    const TEST_HOOK_PROGRAM_ID: Pubkey = Pubkey::new_from_array([4u8; 32]);
+    const TEST_TRANSFER_HOOK_PROGRAM_ID: Pubkey = Pubkey::new_from_array([5u8; 32]);
+
+    thread_local! {
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+        static STACK_HEIGHT: RefCell<u64> = const { RefCell::new(0) };
+        static HOOK_ACCOUNTS: RefCell<Vec<Pubkey>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct TestSyscallStubs {}
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_set_return_data(&self, data: &[u8]) {
+            RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+        }
+
+        fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+            RETURN_DATA.with(|return_data| Some((STAKE_PROGRAM_ID, return_data.borrow().clone())))
+        }
+
+        fn sol_get_stack_height(&self) -> u64 {
+            STACK_HEIGHT.with(|stack_height| *stack_height.borrow())
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock::default() };
+            solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let mut new_account_infos = vec![];
+
+            // test hook refusing unstakes, recording the accounts it got
+            if instruction.program_id == TEST_HOOK_PROGRAM_ID {
+                HOOK_ACCOUNTS.with(|hook_accounts| {
+                    *hook_accounts.borrow_mut() = instruction
+                        .accounts
+                        .iter()
+                        .map(|meta| meta.pubkey)
+                        .collect()
+                });
+                return match HookData::decode(&instruction.data)?.action {
+                    HookAction::Unstake { .. } => Err(ProgramError::InvalidArgument),
+                    _ => Ok(()),
+                };
+            }
+
+            // test transfer hook refusing transfers over 1_000
+            if instruction.program_id == TEST_TRANSFER_HOOK_PROGRAM_ID {
+                return match TransferHookInstruction::unpack(&instruction.data)? {
+                    TransferHookInstruction::Execute { amount } if amount > 1_000 => {
+                        Err(ProgramError::InvalidArgument)
+                    }
+                    _ => Ok(()),
+                };
+            }
+
+            // mimic check for token program in accounts
+            if !account_infos
+                .iter()
+                .any(|x| *x.key == instruction.program_id)
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            for meta in instruction.accounts.iter() {
+                for account_info in account_infos.iter() {
+                    if meta.pubkey == *account_info.key {
+                        let mut new_account_info = account_info.clone();
+                        for seeds in signers_seeds.iter() {
+                            let signer =
+                                Pubkey::create_program_address(seeds, &STAKE_PROGRAM_ID).unwrap();
+                            if *account_info.key == signer {
+                                new_account_info.is_signer = true;
+                            }
+                        }
+                        new_account_infos.push(new_account_info);
+                    }
+                }
+            }
+
+            if instruction.program_id == spl_token_2022::id() {
+                return spl_token_2022::processor::Processor::process(
+                    &instruction.program_id,
+                    &new_account_infos,
+                    &instruction.data,
+                );
+            }
+            if system_program::check_id(&instruction.program_id) {
+                return process_system_instruction(&new_account_infos, &instruction.data);
+            }
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &new_account_infos,
+                &instruction.data,
+            )
+        }
+    }
+
+    /// Mimic the system program instructions the program calls. Test accounts
+    /// are allocated up front, allocating only checks their size.
+    fn process_system_instruction(account_infos: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        let transfer = |from: &AccountInfo, to: &AccountInfo, lamports: u64| {
+            if !from.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let from_lamports = from.lamports();
+            **from.try_borrow_mut_lamports()? = from_lamports
+                .checked_sub(lamports)
+                .ok_or(ProgramError::InsufficientFunds)?;
+            **to.try_borrow_mut_lamports()? += lamports;
+            Ok(())
+        };
+        let allocate = |account: &AccountInfo, space: u64| {
+            if !account.is_signer || account.data_len() as u64 != space {
+                return Err(ProgramError::InvalidArgument);
+            }
+            Ok(())
+        };
+        match limited_deserialize(data, 1024).map_err(|_| ProgramError::InvalidInstructionData)? {
+            SystemInstruction::Transfer { lamports } => {
+                transfer(&account_infos[0], &account_infos[1], lamports)
+            }
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                if account_infos[1].lamports() != 0 {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                transfer(&account_infos[0], &account_infos[1], lamports)?;
+                allocate(&account_infos[1], space)?;
+                account_infos[1].assign(&owner);
+                Ok(())
+            }
+            SystemInstruction::Allocate { space } => allocate(&account_infos[0], space),
+            SystemInstruction::Assign { owner } => {
+                account_infos[0].assign(&owner);
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    struct StakePoolInfo {
+        bump_seed: u8,
+        admin_key: Pubkey,
+        authority_key: Pubkey,
+        stake_pool_key: Pubkey,
+        stake_pool_account: Account,
+        stake_token_mint_key: Pubkey,
+        stake_token_mint_account: Account,
+        reserved_key: Pubkey,
+        reserved_account: Account,
+        reward_mint_key: Pubkey,
+        reward_mint_account: Account,
+    }
+
+    impl StakePoolInfo {
+        pub fn new(user_key: Pubkey) -> Self {
+            let stake_pool_key = Pubkey::new_unique();
+            let stake_pool_account = Account::new(0, Pool::LEN, &STAKE_PROGRAM_ID);
+            let (authority_key, bump_seed) = Pool::authority(&STAKE_PROGRAM_ID, &stake_pool_key);
+
+            let (stake_token_mint_key, stake_token_mint_account) =
+                create_mint(&spl_token::id(), &user_key, None);
+            let reserved_key = Pubkey::new_unique();
+            let reserved_account = Account::new(
+                account_minimum_balance(),
+                spl_token::state::Account::get_packed_len(),
+                &spl_token::id(),
+            );
+            let reward_mint_key = Pubkey::new_unique();
+            let reward_mint_account = Account::new(
+                mint_minimum_balance(),
+                spl_token::state::Mint::get_packed_len(),
+                &spl_token::id(),
+            );
+
+            StakePoolInfo {
+                bump_seed,
+                admin_key: Pubkey::new_unique(),
+                authority_key,
+                stake_pool_key,
+                stake_pool_account,
+                stake_token_mint_key,
+                stake_token_mint_account,
+                reserved_key,
+                reserved_account,
+                reward_mint_key,
+                reward_mint_account,
+            }
+        }
+
+        pub fn initialize_stake_pool(
+            &mut self,
+            reward_numerator: u64,
+            reward_denominator: u64,
+        ) -> ProgramResult {
+            do_process_instruction(
+                create_stake_pool(
+                    STAKE_PROGRAM_ID,
+                    self.stake_pool_key,
+                    self.authority_key,
+                    self.stake_token_mint_key,
+                    self.reserved_key,
+                    self.reward_mint_key,
+                    self.admin_key,
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                    InitData {
+                        bump_seed: self.bump_seed,
+                        reward_numerator,
+                        reward_denominator,
+                        reward_period: DAILY_TS,
+                        reject_freeze_authority: false,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.stake_pool_account,
+                    &mut Account::default(),
+                    &mut self.stake_token_mint_account,
+                    &mut self.reserved_account,
+                    &mut self.reward_mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        }
+    }
+
+    impl StakePoolInfo {
+        pub fn pool(&self) -> Pool {
+            Pool::unpack(&self.stake_pool_account.data).unwrap()
+        }
+
+        pub fn create_stake_user(&mut self, owner_key: Pubkey) -> (Pubkey, Account) {
+            let stake_user_key = Pubkey::new_unique();
+            let mut stake_user_account = Account::new(0, StakeUser::LEN, &STAKE_PROGRAM_ID);
+
+            do_process_instruction(
+                create_stake_user(
+                    STAKE_PROGRAM_ID,
+                    self.stake_pool_key,
+                    stake_user_key,
+                    owner_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut self.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+
+            (stake_user_key, stake_user_account)
+        }
+    }
+
+    fn test_syscall_stubs() {
+        use std::sync::Once;
+        static ONCE: Once = Once::new();
+
+        ONCE.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs {}));
+        });
+    }
+
+    fn mint_minimum_balance() -> u64 {
+        Rent::default().minimum_balance(spl_token::state::Mint::get_packed_len())
+    }
+
+    fn account_minimum_balance() -> u64 {
+        Rent::default().minimum_balance(spl_token::state::Account::get_packed_len())
+    }
+
+    fn do_process_instruction(
+        mut instruction: Instruction,
+        accounts: Vec<&mut Account>,
+    ) -> ProgramResult {
+        test_syscall_stubs();
+
+        // stakes and claims end with the global config, which tests only pass
+        // when they set one up
+        let mut global_config_account = Account::default();
+        let mut accounts: Vec<&mut Account> = accounts;
+        let global_config_key = find_global_config_address(&STAKE_PROGRAM_ID).0;
+        if instruction.program_id == STAKE_PROGRAM_ID
+            && InstructionType::unpack(&instruction.data).is_ok_and(|ix| ix.is_pausable())
+            && !(accounts.len() == instruction.accounts.len()
+                && instruction.accounts.last().map(|meta| meta.pubkey) == Some(global_config_key))
+        {
+            let len = accounts.len().min(instruction.accounts.len());
+            accounts.truncate(len);
+            instruction.accounts.truncate(len);
+            instruction = with_global_config(STAKE_PROGRAM_ID, instruction);
+            accounts.push(&mut global_config_account);
+        }
+
+        let mut account_clones = accounts.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
+        let mut meta = instruction
+            .accounts
+            .iter()
+            .zip(account_clones.iter_mut())
+            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
+            .collect::<Vec<_>>();
+        let mut account_infos = create_is_signer_account_infos(&mut meta);
+        let res = if instruction.program_id == STAKE_PROGRAM_ID {
+            process(&instruction.program_id, &account_infos, &instruction.data)
+        } else if instruction.program_id == spl_token_2022::id() {
+            spl_token_2022::processor::Processor::process(
+                &instruction.program_id,
+                &account_infos,
+                &instruction.data,
+            )
+        } else {
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &account_infos,
+                &instruction.data,
+            )
+        };
+
+        if res.is_ok() {
+            // the runtime rejects changes to accounts not marked writable
+            for ((account_meta, account), account_info) in instruction
+                .accounts
+                .iter()
+                .zip(accounts.iter())
+                .zip(account_infos.iter())
+            {
+                let changed = account.lamports != **account_info.lamports.borrow()
+                    || account.data[..] != account_info.data.borrow()[..]
+                    || account.owner != *account_info.owner;
+                assert!(
+                    account_meta.is_writable || !changed,
+                    "account {} changed but not writable",
+                    account_meta.pubkey
+                );
+            }
+            let mut account_metas = instruction
+                .accounts
+                .iter()
+                .zip(accounts)
+                .map(|(account_meta, account)| (&account_meta.pubkey, account))
+                .collect::<Vec<_>>();
+            for account_info in account_infos.iter_mut() {
+                for account_meta in account_metas.iter_mut() {
+                    if account_info.key == account_meta.0 {
+                        let account = &mut account_meta.1;
+                        account.owner = *account_info.owner;
+                        account.lamports = **account_info.lamports.borrow();
+                        account.data = account_info.data.borrow().to_vec();
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    fn create_mint(
+        program_id: &Pubkey,
+        authority_key: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+    ) -> (Pubkey, Account) {
+        let mint_key = Pubkey::new_unique();
+        let mut mint_account = Account::new(
+            mint_minimum_balance(),
+            spl_token::state::Mint::get_packed_len(),
+            program_id,
+        );
+        let mut rent_sysvar_account = create_account_for_test(&Rent::free());
+
+        do_process_instruction(
+            initialize_mint(program_id, &mint_key, authority_key, freeze_authority, 2).unwrap(),
+            vec![&mut mint_account, &mut rent_sysvar_account],
+        )
+        .unwrap();
+
+        (mint_key, mint_account)
+    }
+
+    fn create_token_account(
+        mint_key: &Pubkey,
+        mint_account: &mut Account,
+        mint_authority_key: &Pubkey,
+        owner_key: &Pubkey,
+        amount: u64,
+    ) -> (Pubkey, Account) {
+        let account_key = Pubkey::new_unique();
+        let mut account_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+        let mut mint_authority_account = Account::default();
+        let mut rent_sysvar_account = create_account_for_test(&Rent::free());
+
+        do_process_instruction(
+            initialize_account(&spl_token::id(), &account_key, mint_key, owner_key).unwrap(),
+            vec![
+                &mut account_account,
+                mint_account,
+                &mut mint_authority_account,
+                &mut rent_sysvar_account,
+            ],
+        )
+        .unwrap();
+
+        if amount > 0 {
+            do_process_instruction(
+                mint_to(
+                    &spl_token::id(),
+                    mint_key,
+                    &account_key,
+                    mint_authority_key,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+                vec![
+                    mint_account,
+                    &mut account_account,
+                    &mut mint_authority_account,
+                ],
+            )
+            .unwrap();
+        }
+
+        (account_key, account_account)
+    }
+
+    #[test]
+    fn test_initialize() {
+        let user_key = Pubkey::new_unique();
+        let reward_numerator: u64 = 1;
+        let reward_denominator: u64 = 1_000;
+
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+
+        // reserved token account is already initialized
+        {
+            let old_account = stake_pool_info.reserved_account.clone();
+
+            do_process_instruction(
+                initialize_account(
+                    &spl_token::id(),
+                    &stake_pool_info.reserved_key,
+                    &stake_pool_info.stake_token_mint_key,
+                    &stake_pool_info.authority_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.reserved_account,
+                    &mut stake_pool_info.stake_token_mint_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(
+                Err(CustomError::TokenInitializeAccountFailed.into()),
+                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
+            );
+
+            stake_pool_info.reserved_account = old_account;
+        }
+
+        // reward token mint account is already initialized
+        {
+            let old_account = stake_pool_info.reward_mint_account.clone();
+
+            do_process_instruction(
+                initialize_mint(
+                    &spl_token::id(),
+                    &stake_pool_info.reward_mint_key,
+                    &stake_pool_info.authority_key,
+                    None,
+                    9,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(
+                Err(CustomError::TokenInitializeMintFailed.into()),
+                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
+            );
+
+            stake_pool_info.reward_mint_account = old_account;
+        }
+
+        // rewards without a denominator
+        assert_eq!(
+            Err(CustomError::InvalidRewardRate.into()),
+            stake_pool_info.initialize_stake_pool(reward_numerator, 0)
+        );
+
+        // bump seed deriving another authority
+        {
+            let bump_seed = stake_pool_info.bump_seed;
+            stake_pool_info.bump_seed = bump_seed.wrapping_sub(1);
+            assert_eq!(
+                Err(CustomError::InvalidPoolAuthority.into()),
+                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
+            );
+            stake_pool_info.bump_seed = bump_seed;
+        }
+
+        // initialized account correctly
+        {
+            assert_eq!(
+                Ok(()),
+                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_pool_decimals() {
+        fn create_mint_with_decimals(authority_key: &Pubkey, decimals: u8) -> (Pubkey, Account) {
+            let mint_key = Pubkey::new_unique();
+            let mut mint_account = Account::new(
+                mint_minimum_balance(),
+                spl_token::state::Mint::get_packed_len(),
+                &spl_token::id(),
+            );
+            do_process_instruction(
+                initialize_mint(&spl_token::id(), &mint_key, authority_key, None, decimals)
+                    .unwrap(),
+                vec![
+                    &mut mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+            (mint_key, mint_account)
+        }
+
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let (stake_mint_key, stake_mint_account) = create_mint_with_decimals(&user_key, 6);
+        stake_pool_info.stake_token_mint_key = stake_mint_key;
+
+        // the stake mint must be a mint of the token program
+        stake_pool_info.stake_token_mint_account = stake_mint_account.clone();
+        stake_pool_info.stake_token_mint_account.owner = Pubkey::new_unique();
+        assert_eq!(
+            Err(CustomError::InvalidAccountOwner.into()),
+            stake_pool_info.initialize_stake_pool(1, 1_000)
+        );
+        stake_pool_info.stake_token_mint_account = Account::new(
+            mint_minimum_balance(),
+            spl_token::state::Mint::get_packed_len(),
+            &spl_token::id(),
+        );
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
+            stake_pool_info.initialize_stake_pool(1, 1_000)
+        );
+
+        // the created reward mint takes the decimals of the stake mint
+        stake_pool_info.stake_token_mint_account = stake_mint_account;
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let stake_pool = stake_pool_info.pool();
+        assert_eq!(stake_pool.stake_decimals, 6);
+        assert_eq!(stake_pool.reward_decimals, 6);
+        let reward_mint = Mint::unpack(&stake_pool_info.reward_mint_account.data).unwrap();
+        assert_eq!(reward_mint.decimals, 6);
+
+        // while a vault-funded pool keeps the decimals of its reward mint
+        let mut vault_pool_info = StakePoolInfo::new(user_key);
+        let (stake_mint_key, mut stake_mint_account) = create_mint_with_decimals(&user_key, 6);
+        let (reward_mint_key, mut reward_mint_account) = create_mint_with_decimals(&user_key, 9);
+        let reward_vault_key = Pubkey::new_unique();
+        let mut reward_vault_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+        do_process_instruction(
+            create_vault_funded_stake_pool(
+                STAKE_PROGRAM_ID,
+                vault_pool_info.stake_pool_key,
+                vault_pool_info.authority_key,
+                stake_mint_key,
+                vault_pool_info.reserved_key,
+                reward_mint_key,
+                reward_vault_key,
+                vault_pool_info.admin_key,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                InitData {
+                    bump_seed: vault_pool_info.bump_seed,
+                    reward_numerator: 1,
+                    reward_denominator: 1_000,
+                    reward_period: DAILY_TS,
+                    reject_freeze_authority: false,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut vault_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_mint_account,
+                &mut vault_pool_info.reserved_account,
+                &mut reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+            ],
+        )
+        .unwrap();
+        let vault_pool = vault_pool_info.pool();
+        assert_eq!(vault_pool.stake_decimals, 6);
+        assert_eq!(vault_pool.reward_decimals, 9);
+        let reward_mint = Mint::unpack(&reward_mint_account.data).unwrap();
+        assert_eq!(reward_mint.decimals, 9);
+    }
+
+    #[test]
+    fn test_create_pool_stake_mint() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let (freezable_mint_key, freezable_mint_account) =
+            create_mint(&spl_token::id(), &user_key, Some(&user_key));
+        let create = |stake_pool_info: &mut StakePoolInfo,
+                      stake_token_mint_account: &mut Account,
+                      reject_freeze_authority| {
+            do_process_instruction(
+                create_stake_pool(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.authority_key,
+                    freezable_mint_key,
+                    stake_pool_info.reserved_key,
+                    stake_pool_info.reward_mint_key,
+                    stake_pool_info.admin_key,
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                    InitData {
+                        bump_seed: stake_pool_info.bump_seed,
+                        reward_numerator: 1,
+                        reward_denominator: 1_000,
+                        reward_period: DAILY_TS,
+                        reject_freeze_authority,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    stake_token_mint_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        };
+
+        // not a mint of the token program
+        assert_eq!(
+            Err(CustomError::InvalidAccountOwner.into()),
+            create(&mut stake_pool_info, &mut Account::default(), false)
+        );
+        // an uninitialized mint
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
+            create(
+                &mut stake_pool_info,
+                &mut Account::new(0, Mint::LEN, &spl_token::id()),
+                false
+            )
+        );
+        assert_eq!(
+            Err(CustomError::InvalidFreezeAuthority.into()),
+            create(
+                &mut stake_pool_info,
+                &mut freezable_mint_account.clone(),
+                true
+            )
+        );
+        // freezable stake mints are accepted unless rejected
+        create(
+            &mut stake_pool_info,
+            &mut freezable_mint_account.clone(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(stake_pool_info.pool().stake_token_mint, freezable_mint_key);
+    }
+
+    #[test]
+    fn test_fund_rewards() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let (reward_mint_key, mut reward_mint_account) =
+            create_mint(&spl_token::id(), &user_key, None);
+        stake_pool_info.reward_mint_key = reward_mint_key;
+        let reward_vault_key = Pubkey::new_unique();
+        let mut reward_vault_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+
+        do_process_instruction(
+            create_vault_funded_stake_pool(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.stake_token_mint_key,
+                stake_pool_info.reserved_key,
+                reward_mint_key,
+                reward_vault_key,
+                stake_pool_info.admin_key,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                InitData {
+                    bump_seed: stake_pool_info.bump_seed,
+                    reward_numerator: 1,
+                    reward_denominator: 1_000,
+                    reward_period: DAILY_TS,
+                    reject_freeze_authority: false,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut stake_pool_info.reserved_account,
+                &mut reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+            ],
+        )
+        .unwrap();
+
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.reward_mode, RewardMode::TransferFromVault);
+        assert!(stake_pool.is_vault_funded());
+        assert_eq!(stake_pool.reward_vault, reward_vault_key);
+
+        let funder_key = Pubkey::new_unique();
+        let (source_key, mut source_account) = create_token_account(
+            &reward_mint_key,
+            &mut reward_mint_account,
+            &user_key,
+            &funder_key,
+            1_000,
+        );
+
+        // funding through a wrong vault
+        {
+            let (wrong_vault_key, mut wrong_vault_account) = create_token_account(
+                &reward_mint_key,
+                &mut reward_mint_account,
+                &user_key,
+                &funder_key,
+                0,
+            );
+            assert_eq!(
+                Err(CustomError::InvalidTokenAccount.into()),
+                do_process_instruction(
+                    fund_rewards(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        funder_key,
+                        wrong_vault_key,
+                        source_key,
+                        400,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut Account::default(),
+                        &mut wrong_vault_account,
+                        &mut Account::default(),
+                        &mut source_account,
+                    ],
+                )
+            );
+        }
+
+        // funded correctly
+        {
+            do_process_instruction(
+                fund_rewards(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    funder_key,
+                    reward_vault_key,
+                    source_key,
+                    400,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut reward_vault_account,
+                    &mut Account::default(),
+                    &mut source_account,
+                ],
+            )
+            .unwrap();
+
+            let vault = spl_token::state::Account::unpack(&reward_vault_account.data).unwrap();
+            assert_eq!(vault.amount, 400);
+            let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+            assert_eq!(source.amount, 600);
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+
+        // tokens sent straight to the reserve
+        do_process_instruction(
+            mint_to(
+                &spl_token::id(),
+                &stake_pool_info.stake_token_mint_key,
+                &stake_pool_info.reserved_key,
+                &user_key,
+                &[],
+                500,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut stake_pool_info.reserved_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let (destination_key, mut destination_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            0,
+        );
+
+        // signed by someone else than the admin
+        {
+            assert_eq!(
+                Err(CustomError::InvalidAdmin.into()),
+                do_process_instruction(
+                    sweep(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        user_key,
+                        stake_pool_info.authority_key,
+                        stake_pool_info.reserved_key,
+                        destination_key,
+                        500,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut stake_pool_info.reserved_account,
+                        &mut destination_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // more than the surplus
+        {
+            let mut stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+            stake_pool.total_staked = 200;
+            Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+
+            assert_eq!(
+                Err(CustomError::InsufficientFunds.into()),
+                do_process_instruction(
+                    sweep(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        stake_pool_info.admin_key,
+                        stake_pool_info.authority_key,
+                        stake_pool_info.reserved_key,
+                        destination_key,
+                        400,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut stake_pool_info.reserved_account,
+                        &mut destination_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // swept the surplus correctly
+        {
+            do_process_instruction(
+                sweep(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reserved_key,
+                    destination_key,
+                    300,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut destination_account,
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            let reserved =
+                spl_token::state::Account::unpack(&stake_pool_info.reserved_account.data).unwrap();
+            assert_eq!(reserved.amount, 200);
+            let destination = spl_token::state::Account::unpack(&destination_account.data).unwrap();
+            assert_eq!(destination.amount, 300);
+        }
+    }
+
+    #[test]
+    fn test_sync_reserve() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+
+        let transfer_in = |stake_pool_info: &mut StakePoolInfo, amount| {
+            do_process_instruction(
+                mint_to(
+                    &spl_token::id(),
+                    &stake_pool_info.stake_token_mint_key,
+                    &stake_pool_info.reserved_key,
+                    &user_key,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_token_mint_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        };
+        let sync = |stake_pool_info: &mut StakePoolInfo,
+                    token_program_id: Pubkey,
+                    stake_user_account: Option<&mut Account>| {
+            let instruction = sync_reserve(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                stake_pool_info.reserved_key,
+                token_program_id,
+                stake_user_account.is_some().then_some(stake_user_key),
+            )
+            .unwrap();
+            let mut reserved_account = stake_pool_info.reserved_account.clone();
+            reserved_account.owner = token_program_id;
+            let mut admin_account = Account::default();
+            let mut clock_account = create_account_for_test(&Clock {
+                unix_timestamp: 100,
+                ..Clock::default()
+            });
+            let mut token_program_account = Account::default();
+            let mut accounts = vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut admin_account,
+                &mut reserved_account,
+                &mut clock_account,
+                &mut token_program_account,
+            ];
+            accounts.extend(stake_user_account);
+            do_process_instruction(instruction, accounts)
+        };
+        let update_pool = |stake_pool_info: &mut StakePoolInfo, update: &dyn Fn(&mut Pool)| {
+            let mut stake_pool = stake_pool_info.pool();
+            update(&mut stake_pool);
+            Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+        };
+
+        transfer_in(&mut stake_pool_info, 500);
+        for _ in 0..2 {
+            sync(&mut stake_pool_info, spl_token::id(), None).unwrap();
+
+            let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+            assert_eq!(stake_pool.protocol_owned, 500);
+            assert_eq!(stake_pool.total_staked, 0);
+        }
+
+        transfer_in(&mut stake_pool_info, 300);
+        // read through a program other than the token programs
+        assert_eq!(
+            sync(&mut stake_pool_info, Pubkey::new_unique(), None),
+            Err(CustomError::InvalidTokenProgram.into())
+        );
+        // credited like a stake, past the cap
+        update_pool(&mut stake_pool_info, &|pool| pool.max_stake_per_user = 299);
+        assert_eq!(
+            sync(
+                &mut stake_pool_info,
+                spl_token::id(),
+                Some(&mut stake_user_account)
+            ),
+            Err(CustomError::StakeCapExceeded.into())
+        );
+        // or into a deprecated pool
+        update_pool(&mut stake_pool_info, &|pool| {
+            pool.max_stake_per_user = 0;
+            pool.deprecated = true;
+        });
+        assert_eq!(
+            sync(
+                &mut stake_pool_info,
+                spl_token::id(),
+                Some(&mut stake_user_account)
+            ),
+            Err(CustomError::PoolDeprecated.into())
+        );
+
+        // the reserve of a Token-2022 pool, the credit warming up
+        update_pool(&mut stake_pool_info, &|pool| {
+            pool.deprecated = false;
+            pool.warm_up_duration = 1_000;
+            pool.warm_up_rate_bps = 5_000;
+        });
+        sync(
+            &mut stake_pool_info,
+            spl_token_2022::id(),
+            Some(&mut stake_user_account),
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.protocol_owned, 500);
+        assert_eq!(stake_pool.total_staked, 300);
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 300);
+        assert_eq!(stake_user.warming_stake, 300);
+        assert_eq!(stake_user.last_update, 100);
+    }
+
+    #[test]
+    fn test_deprecate() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let successor_key = Pubkey::new_unique();
+
+        do_process_instruction(
+            deprecate(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                successor_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert!(stake_pool.deprecated);
+        assert_eq!(stake_pool.successor, successor_key);
+
+        // no new stake users on a deprecated pool
+        {
+            let stake_user_key = Pubkey::new_unique();
+            let mut stake_user_account = Account::new(0, StakeUser::LEN, &STAKE_PROGRAM_ID);
+            assert_eq!(
+                Err(CustomError::PoolDeprecated.into()),
+                do_process_instruction(
+                    create_stake_user(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        stake_user_key,
+                        user_key,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut create_account_for_test(&Rent::free()),
+                    ],
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_reward_mint() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let new_authority = Pubkey::new_unique();
+        let release = release_reward_mint(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.admin_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            new_authority,
+        )
+        .unwrap();
+
+        // the pool keeps minting until deprecated
+        assert_eq!(
+            do_process_instruction(
+                release.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut Account::default(),
+                ],
+            ),
+            Err(CustomError::PoolNotDeprecated.into())
+        );
+
+        do_process_instruction(
+            deprecate(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                Pubkey::default(),
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            release,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let reward_mint = Mint::unpack(&stake_pool_info.reward_mint_account.data).unwrap();
+        assert_eq!(reward_mint.mint_authority, COption::Some(new_authority));
+    }
+
+    #[test]
+    fn test_pool_creation_fee() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let upgrade_authority_key = Pubkey::new_unique();
+        let mut program_data_account = Account::new(
+            0,
+            UpgradeableLoaderState::size_of_programdata_metadata(),
+            &bpf_loader_upgradeable::id(),
+        );
+        program_data_account.data[..4].copy_from_slice(&3u32.to_le_bytes());
+        program_data_account.data[12] = 1;
+        program_data_account.data[13..].copy_from_slice(upgrade_authority_key.as_ref());
+        let mut global_config_account = Account::new(0, GlobalConfig::LEN, &system_program::id());
+        // a sponsor pays for the config and the pools
+        let payer_key = Pubkey::new_unique();
+        let mut payer_account = Account::new(10_000_000, 0, &system_program::id());
+        let treasury_key = Pubkey::new_unique();
+        let mut treasury_account = Account::default();
+        let config = GlobalConfigData {
+            authority: upgrade_authority_key,
+            treasury: treasury_key,
+            pool_creation_fee: 300,
+            guardian: Pubkey::new_unique(),
+            default_claim_burn_bps: 250,
+            // the 1 / 1_000 daily rate of the pool
+            max_daily_reward_bps: 10,
+        };
+        let rent = Rent::default();
+        let mut set_config = |authority_key, data| {
+            do_process_instruction(
+                set_global_config(STAKE_PROGRAM_ID, authority_key, payer_key, data).unwrap(),
+                vec![
+                    &mut global_config_account,
+                    &mut Account::default(),
+                    &mut payer_account,
+                    &mut program_data_account,
+                    &mut create_account_for_test(&rent),
+                    &mut Account::default(),
+                ],
+            )
+        };
+
+        // only the upgrade authority of the program creates the config
+        assert_eq!(
+            set_config(Pubkey::new_unique(), config.clone()),
+            Err(CustomError::InvalidAdmin.into())
+        );
+        set_config(upgrade_authority_key, config.clone()).unwrap();
+        // the config authority takes over updating the config
+        let new_authority_key = Pubkey::new_unique();
+        let hand_over = GlobalConfigData {
+            authority: new_authority_key,
+            ..config
+        };
+        set_config(upgrade_authority_key, hand_over.clone()).unwrap();
+        assert_eq!(
+            set_config(upgrade_authority_key, hand_over),
+            Err(CustomError::InvalidAdmin.into())
+        );
+
+        let config_rent = rent.minimum_balance(GlobalConfig::LEN);
+        assert_eq!(global_config_account.owner, STAKE_PROGRAM_ID);
+        assert_eq!(global_config_account.lamports, config_rent);
+        assert_eq!(payer_account.lamports, 10_000_000 - config_rent);
+        let global_config = GlobalConfig::unpack(&global_config_account.data).unwrap();
+        assert_eq!(global_config.authority, new_authority_key);
+        assert_eq!(global_config.treasury, treasury_key);
+        assert_eq!(global_config.pool_creation_fee, 300);
+
+        let mut create = |treasury_key| {
+            do_process_instruction(
+                create_stake_pool(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.stake_token_mint_key,
+                    stake_pool_info.reserved_key,
+                    stake_pool_info.reward_mint_key,
+                    stake_pool_info.admin_key,
+                    payer_key,
+                    treasury_key,
+                    InitData {
+                        bump_seed: stake_pool_info.bump_seed,
+                        reward_numerator: 1,
+                        reward_denominator: 1_000,
+                        reward_period: DAILY_TS,
+                        reject_freeze_authority: false,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.stake_token_mint_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut payer_account,
+                    &mut global_config_account,
+                    &mut treasury_account,
+                    &mut Account::default(),
+                ],
+            )
+        };
+        assert_eq!(
+            create(Pubkey::new_unique()),
+            Err(CustomError::InvalidTreasury.into())
+        );
+        create(treasury_key).unwrap();
+        assert_eq!(payer_account.lamports, 10_000_000 - config_rent - 300);
+        assert_eq!(treasury_account.lamports, 300);
+        assert_eq!(stake_pool_info.pool().claim_burn_bps, 250);
+
+        // rates above the cap of the config
+        let set_rate = |reward_numerator| {
+            set_reward_rate(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                RateData {
+                    reward_numerator,
+                    reward_denominator: 1_000,
+                },
+            )
+            .unwrap()
+        };
+        let set_rate_above_cap = set_rate(2);
+        let set_rate_at_cap = set_rate(1);
+        assert_eq!(
+            do_process_instruction(
+                set_rate_above_cap,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut global_config_account,
+                ],
+            ),
+            Err(CustomError::RewardRateTooHigh.into())
+        );
+        do_process_instruction(
+            set_rate_at_cap,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Clock::default()),
+                &mut global_config_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            do_process_instruction(
+                set_rate_controller(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    RateControllerData {
+                        target_tvl: 1_000,
+                        min_reward_numerator: 1,
+                        max_reward_numerator: 2,
+                        max_rate_step_bps: 1_000,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut global_config_account,
+                ],
+            ),
+            Err(CustomError::RewardRateTooHigh.into())
+        );
+    }
+
+    #[test]
+    fn test_finalize_distribution_report() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let reward_mint_key = stake_pool_info.reward_mint_key;
+        let payer_key = Pubkey::new_unique();
+        let mut payer_account = Account::new(10_000_000, 0, &system_program::id());
+        let mut reports: Vec<Account> = (0..3)
+            .map(|_| Account::new(0, DistributionReport::LEN, &system_program::id()))
+            .collect();
+
+        // emissions of the pool up to the clock, then a report of its epoch
+        let mut finalize = |stake_pool_info: &mut StakePoolInfo,
+                            (total_staked, total_paid, total_burned): (u64, u64, u64),
+                            epoch: u64,
+                            report: usize,
+                            previous: Option<(u64, usize)>| {
+            let mut stake_pool = stake_pool_info.pool();
+            stake_pool.total_staked = total_staked;
+            stake_pool.total_rewards_paid = total_paid;
+            stake_pool.total_burned = total_burned;
+            Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+            // the supply counts tokens minted elsewhere too
+            let mut reward_mint = Mint::unpack(&stake_pool_info.reward_mint_account.data).unwrap();
+            reward_mint.supply = 10_000 * epoch;
+            Mint::pack(reward_mint, &mut stake_pool_info.reward_mint_account.data).unwrap();
+
+            let clock = Clock {
+                slot: 7 * epoch,
+                epoch,
+                unix_timestamp: 100 * epoch as i64,
+                ..Clock::default()
+            };
+            let instruction = finalize_distribution_report(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                reward_mint_key,
+                payer_key,
+                epoch,
+                previous.map(|(epoch, _)| epoch),
+            )
+            .unwrap();
+            let (report_account, previous_account) = match previous {
+                Some((_, index)) if index < report => {
+                    let (before, after) = reports.split_at_mut(report);
+                    (&mut after[0], Some(&mut before[index]))
+                }
+                Some((_, index)) => {
+                    let (before, after) = reports.split_at_mut(index);
+                    (&mut before[report], Some(&mut after[0]))
+                }
+                None => (&mut reports[report], None),
+            };
+            let mut rent_account = create_account_for_test(&Rent::default());
+            let mut clock_account = create_account_for_test(&clock);
+            let mut system_program_account = Account::default();
+            let mut accounts = vec![
+                &mut stake_pool_info.stake_pool_account,
+                report_account,
+                &mut stake_pool_info.reward_mint_account,
+                &mut payer_account,
+                &mut rent_account,
+                &mut clock_account,
+                &mut system_program_account,
+            ];
+            accounts.extend(previous_account);
+            do_process_instruction(instruction, accounts)
+        };
+
+        // the first report has no previous one
+        assert_eq!(
+            finalize(&mut stake_pool_info, (1_000, 200, 10), 1, 0, Some((0, 1))),
+            Err(CustomError::InvalidDistributionReport.into())
+        );
+        finalize(&mut stake_pool_info, (1_000, 200, 10), 1, 0, None).unwrap();
+        assert!(stake_pool_info.pool().has_distribution_report);
+        assert_eq!(stake_pool_info.pool().last_report_epoch, 1);
+        // one report per epoch
+        assert_eq!(
+            finalize(&mut stake_pool_info, (1_000, 200, 10), 1, 0, None),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        // later reports are since the last one, which can't be left out
+        assert_eq!(
+            finalize(&mut stake_pool_info, (400, 500, 25), 3, 1, None),
+            Err(CustomError::InvalidDistributionReport.into())
+        );
+        finalize(&mut stake_pool_info, (400, 500, 25), 3, 1, Some((1, 0))).unwrap();
+        assert_eq!(stake_pool_info.pool().last_report_epoch, 3);
+        // nor be skipped for an older one
+        assert_eq!(
+            finalize(&mut stake_pool_info, (400, 600, 25), 4, 2, Some((1, 0))),
+            Err(CustomError::InvalidDistributionReport.into())
+        );
+
+        assert_eq!(
+            DistributionReport::unpack(&reports[0].data).unwrap(),
+            DistributionReport {
+                is_initialized: true,
+                pool_pubkey: stake_pool_key,
+                epoch: 1,
+                timestamp: 100,
+                slot: 7,
+                has_previous: false,
+                previous_epoch: 0,
+                reward_supply: 10_000,
+                total_rewards_paid: 200,
+                rewards_paid: 200,
+                total_burned: 10,
+                fees_collected: 10,
+                total_staked: 1_000,
+                stake_delta: 1_000,
+            }
+        );
+        assert_eq!(
+            DistributionReport::unpack(&reports[1].data).unwrap(),
+            DistributionReport {
+                is_initialized: true,
+                pool_pubkey: stake_pool_key,
+                epoch: 3,
+                timestamp: 300,
+                slot: 21,
+                has_previous: true,
+                previous_epoch: 1,
+                reward_supply: 30_000,
+                total_rewards_paid: 500,
+                rewards_paid: 300,
+                total_burned: 25,
+                fees_collected: 15,
+                total_staked: 400,
+                stake_delta: -600,
+            }
+        );
+        assert_eq!(reports[1].owner, STAKE_PROGRAM_ID);
+        assert_eq!(
+            payer_account.lamports,
+            10_000_000 - 2 * Rent::default().minimum_balance(DistributionReport::LEN)
+        );
+    }
+
+    #[test]
+    fn test_create_stake_user_idempotent() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let payer_key = Pubkey::new_unique();
+        let mut payer_account = Account::new(10_000_000, 0, &system_program::id());
+        let mut stake_user_account = Account::new(0, StakeUser::LEN, &system_program::id());
+        let rent = Rent::default();
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let mut create = |owner_key| {
+            do_process_instruction(
+                create_stake_user_idempotent(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_key,
+                    owner_key,
+                    payer_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut payer_account,
+                    &mut create_account_for_test(&rent),
+                    &mut Account::default(),
+                ],
+            )
+        };
+
+        // the owner doesn't sign, and a second create is a no-op
+        create(user_key).unwrap();
+        create(user_key).unwrap();
+        // the stake user of another owner is at another address
+        assert_eq!(
+            create(Pubkey::new_unique()),
+            Err(CustomError::InvalidStakeUser.into())
+        );
+
+        assert_eq!(stake_pool_info.pool().user_count, 1);
+        assert_eq!(stake_user_account.owner, STAKE_PROGRAM_ID);
+        assert_eq!(
+            payer_account.lamports,
+            10_000_000 - rent.minimum_balance(StakeUser::LEN)
+        );
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.owner, user_key);
+        assert_eq!(stake_user.pool_pubkey, stake_pool_key);
+
+        // existing stake users are only checked by their pool and owner fields
+        let mut other_owner = stake_user_account.data.clone();
+        other_owner[StakeUser::OWNER_OFFSET..][..32].fill(7);
+        let mut other_pool = stake_user_account.data.clone();
+        other_pool[StakeUser::POOL_PUBKEY_OFFSET..][..32].fill(7);
+        let mut uninitialized = stake_user_account.data.clone();
+        uninitialized[0] = 0;
+        for (data, error) in [
+            (other_owner, CustomError::InvalidStakeUser.into()),
+            (other_pool, CustomError::InvalidStakeUser.into()),
+            (uninitialized, ProgramError::UninitializedAccount),
+            (vec![1], ProgramError::InvalidAccountData),
+        ] {
+            let mut tampered_account = stake_user_account.clone();
+            tampered_account.data = data;
+            assert_eq!(
+                Err(error),
+                do_process_instruction(
+                    create_stake_user_idempotent(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_key,
+                        user_key,
+                        payer_key,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut tampered_account,
+                        &mut Account::default(),
+                        &mut payer_account,
+                        &mut create_account_for_test(&rent),
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_reward_budget() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        let budget = |stake_pool_info: &mut StakePoolInfo, amount| {
+            do_process_instruction(
+                add_reward_budget(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        };
+        budget(&mut stake_pool_info, 150);
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let mut claim_at = |stake_pool_info: &mut StakePoolInfo, days: u64| {
+            let result = do_process_instruction(
+                claim(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
+                ],
+            );
+            let reward_token =
+                spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+            let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+            result.map(|()| (reward_token.amount, stake_user.reward_owed))
+        };
+
+        assert_eq!(claim_at(&mut stake_pool_info, 1), Ok((100, 0)));
+        // the budget left only covers half the claim, the rest stays owed
+        assert_eq!(claim_at(&mut stake_pool_info, 2), Ok((150, 50)));
+        assert_eq!(stake_pool_info.pool().reward_budget, 0);
+        assert_eq!(
+            claim_at(&mut stake_pool_info, 3),
+            Err(CustomError::RewardBudgetExhausted.into())
+        );
+
+        budget(&mut stake_pool_info, 50);
+        assert_eq!(claim_at(&mut stake_pool_info, 3), Ok((200, 100)));
+        assert!(stake_pool_info.pool().is_reward_budget_exhausted());
+    }
+
+    #[test]
+    fn test_max_reward_supply() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.stake_amount = 100;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            20,
+        );
+        let set_max = |stake_pool_info: &mut StakePoolInfo, max_reward_supply| {
+            do_process_instruction(
+                set_max_reward_supply(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    max_reward_supply,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        };
+        set_max(&mut stake_pool_info, 150);
+
+        let mut claim_at = |stake_pool_info: &mut StakePoolInfo, days: u64| {
+            let result = do_process_instruction(
+                claim(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
+                ],
+            );
+            let reward_token =
+                spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+            let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+            result.map(|()| (reward_token.amount, stake_user.reward_owed))
+        };
+
+        // 20 tokens already minted leave room for 130
+        assert_eq!(claim_at(&mut stake_pool_info, 1), Ok((120, 0)));
+        assert_eq!(claim_at(&mut stake_pool_info, 2), Ok((150, 70)));
+        assert_eq!(
+            claim_at(&mut stake_pool_info, 3),
+            Err(CustomError::RewardSupplyCapReached.into())
+        );
+
+        set_max(&mut stake_pool_info, 0);
+        assert_eq!(claim_at(&mut stake_pool_info, 3), Ok((320, 0)));
+    }
+
+    #[test]
+    fn test_reward_stream() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.stake_amount = 100;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        do_process_instruction(
+            set_stream_duration(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                10 * DAILY_TS,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let claim_instruction = claim(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            reward_token_key,
+        )
+        .unwrap();
+        let withdraw_instruction = withdraw_from_stream(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            reward_token_key,
+        )
+        .unwrap();
+        let mut run_at = |instruction: &Instruction, days: u64| {
+            let result = do_process_instruction(
+                instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
+                ],
+            );
+            let reward_token =
+                spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+            let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+            result.map(|()| (reward_token.amount, stake_user.stream_balance))
+        };
+
+        // the claim opens a stream instead of minting
+        assert_eq!(run_at(&claim_instruction, 1), Ok((0, 100)));
+        assert_eq!(
+            run_at(&withdraw_instruction, 1),
+            Err(CustomError::InsufficientClaimAmount.into())
+        );
+        assert_eq!(run_at(&withdraw_instruction, 6), Ok((50, 50)));
+        assert_eq!(
+            u64::deserialize(&mut get_return_data().unwrap().1.as_slice()).unwrap(),
+            50
+        );
+
+        // topping up restarts the stream of what is still locked
+        assert_eq!(run_at(&claim_instruction, 6), Ok((50, 550)));
+        assert_eq!(run_at(&withdraw_instruction, 11), Ok((325, 275)));
+        assert_eq!(run_at(&withdraw_instruction, 20), Ok((600, 0)));
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.total_claimed, 600);
+
+        // withdrawals are claims the guardian halts too
+        assert_eq!(
+            Err(CustomError::ProtocolPaused.into()),
+            do_process_instruction(
+                with_global_config(STAKE_PROGRAM_ID, withdraw_instruction),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    &mut paused_global_config_account(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            200,
+        );
+
+        assert_eq!(
+            Err(CustomError::InvalidBasisPoints.into()),
+            do_process_instruction(
+                set_warm_up(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    10 * DAILY_TS,
+                    10_001,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        do_process_instruction(
+            set_warm_up(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                10 * DAILY_TS,
+                2_500,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            100,
+        )
+        .unwrap();
+        for days in [0, 5] {
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        }
+
+        // the first deposit accrued at a quarter of the rate for five days,
+        // and both warm up until the end times averaged by stake
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 125);
+        assert_eq!(stake_user.warming_stake, 200);
+        assert_eq!(
+            stake_user.warm_up_end,
+            (12 * DAILY_TS + DAILY_TS / 2) as i64
+        );
+        let stake_pool = stake_pool_info.pool();
+        assert_eq!(
+            stake_user
+                .pending_reward(&stake_pool, 20 * DAILY_TS as i64)
+                .unwrap(),
+            125 + 375 + 1_500
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 10).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            19,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                19,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let mut set_rounding_instruction = set_rounding(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.admin_key,
+            Rounding::Ceil,
+        )
+        .unwrap();
+        // 1.9 reward tokens a day
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        let pending_reward = |stake_pool: &Pool| {
+            stake_user
+                .pending_reward(stake_pool, DAILY_TS as i64)
+                .unwrap()
+        };
+        assert_eq!(stake_pool_info.pool().rounding, Rounding::Floor);
+        assert_eq!(pending_reward(&stake_pool_info.pool()), 1);
+
+        do_process_instruction(
+            set_rounding_instruction.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(stake_pool_info.pool().rounding, Rounding::Ceil);
+        assert_eq!(pending_reward(&stake_pool_info.pool()), 2);
+
+        set_rounding_instruction.data[1] = 3;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(
+                set_rounding_instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_rate_controller() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(100, 1_000).unwrap();
+        let mut stake_pool = stake_pool_info.pool();
+        stake_pool.total_staked = 2_000;
+        Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+
+        let set_controller = |stake_pool_info: &mut StakePoolInfo, min, max| {
+            do_process_instruction(
+                set_rate_controller(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    RateControllerData {
+                        target_tvl: 1_000,
+                        min_reward_numerator: min,
+                        max_reward_numerator: max,
+                        max_rate_step_bps: 2_000,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        };
+        let rebalance_at = |stake_pool_info: &mut StakePoolInfo, days: u64| {
+            do_process_instruction(
+                rebalance_rate(STAKE_PROGRAM_ID, stake_pool_info.stake_pool_key).unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                ],
+            )
+            .map(|()| stake_pool_info.pool().current_rate())
+        };
+
+        assert_eq!(
+            rebalance_at(&mut stake_pool_info, 1),
+            Err(CustomError::RebalanceNotDue.into())
+        );
+        assert_eq!(
+            set_controller(&mut stake_pool_info, 10, 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        set_controller(&mut stake_pool_info, 10, 105).unwrap();
+
+        // twice the target halves the rate, a step at a time
+        assert_eq!(rebalance_at(&mut stake_pool_info, 1), Ok((80, 1_000)));
+        assert_eq!(
+            rebalance_at(&mut stake_pool_info, 1),
+            Err(CustomError::RebalanceNotDue.into())
+        );
+        assert_eq!(rebalance_at(&mut stake_pool_info, 2), Ok((64, 1_000)));
+
+        // an empty pool raises it up to the bound
+        let mut stake_pool = stake_pool_info.pool();
+        stake_pool.total_staked = 0;
+        Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(rebalance_at(&mut stake_pool_info, 3), Ok((76, 1_000)));
+        assert_eq!(rebalance_at(&mut stake_pool_info, 4), Ok((91, 1_000)));
+        assert_eq!(rebalance_at(&mut stake_pool_info, 5), Ok((105, 1_000)));
+    }
+
+    #[test]
+    fn test_max_stake_per_user() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            200,
+        );
+        do_process_instruction(
+            set_max_stake_per_user(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                150,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            100,
+        )
+        .unwrap();
+        let mut do_stake = || {
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        };
+        do_stake().unwrap();
+        assert_eq!(do_stake(), Err(CustomError::StakeCapExceeded.into()));
+        assert_eq!(stake_pool_info.pool().total_staked, 100);
+    }
+
+    #[test]
+    fn test_gc_user() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        stake_user_account.lamports = 1_000;
+        let treasury_key = Pubkey::new_unique();
+        let mut treasury_account = Account::default();
+        let mut global_config_account = Account::new(0, GlobalConfig::LEN, &STAKE_PROGRAM_ID);
+        GlobalConfig::pack(
+            GlobalConfig {
+                is_initialized: true,
+                treasury: treasury_key,
+                ..GlobalConfig::default()
+            },
+            &mut global_config_account.data,
+        )
+        .unwrap();
+        do_process_instruction(
+            set_gc_delay(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                30 * DAILY_TS,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let gc = gc_user(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            treasury_key,
+        )
+        .unwrap();
+        let mut gc_at = |stake_user_account: &mut Account, days: u64| {
+            do_process_instruction(
+                gc.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut global_config_account,
+                    &mut treasury_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp: (days * DAILY_TS) as i64,
+                        ..Clock::default()
+                    }),
+                ],
+            )
+        };
+
+        assert_eq!(
+            gc_at(&mut stake_user_account, 10),
+            Err(CustomError::StakeUserNotStale.into())
+        );
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 1;
+        let mut owed_account = stake_user_account.clone();
+        StakeUser::pack(stake_user, &mut owed_account.data).unwrap();
+        assert_eq!(
+            gc_at(&mut owed_account, 30),
+            Err(CustomError::StakeUserNotEmpty.into())
+        );
+
+        gc_at(&mut stake_user_account, 30).unwrap();
+        assert_eq!(treasury_account.lamports, 1_000);
+        assert_eq!(stake_user_account.lamports, 0);
+        assert_eq!(stake_pool_info.pool().user_count, 0);
+    }
+
+    #[test]
+    fn test_merge_positions() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_stake_user_key, mut source_stake_user_account) =
+            stake_pool_info.create_stake_user(user_key);
+        let (other_stake_user_key, mut other_stake_user_account) =
+            stake_pool_info.create_stake_user(Pubkey::new_unique());
+        for (account, stake_amount, reward_owed, staked_since) in [
+            (&mut stake_user_account, 100, 0, DAILY_TS as i64),
+            (&mut source_stake_user_account, 50, 7, 0),
+            (&mut other_stake_user_account, 10, 0, 0),
+        ] {
+            let mut stake_user = StakeUser::unpack(&account.data).unwrap();
+            stake_user.stake_amount = stake_amount;
+            stake_user.reward_owed = reward_owed;
+            stake_user.staked_since = staked_since;
+            StakeUser::pack(stake_user, &mut account.data).unwrap();
+        }
+        source_stake_user_account.lamports = 1_000;
+        let mut destination_account = Account::default();
+        let clock = create_account_for_test(&Clock {
+            unix_timestamp: 2 * DAILY_TS as i64,
+            ..Clock::default()
+        });
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let merge = |source_stake_user_key| {
+            merge_positions(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                stake_user_key,
+                source_stake_user_key,
+                user_key,
+                Pubkey::new_unique(),
+            )
+            .unwrap()
+        };
+
+        // only stake users of the owner merge
+        assert_eq!(
+            Err(CustomError::InvalidStakeOwner.into()),
+            do_process_instruction(
+                merge(other_stake_user_key),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut other_stake_user_account,
+                    &mut Account::default(),
+                    &mut destination_account,
+                    &mut clock.clone(),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            merge(source_stake_user_key),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut source_stake_user_account,
+                &mut Account::default(),
+                &mut destination_account,
+                &mut clock.clone(),
+            ],
+        )
+        .unwrap();
+
+        // both accrued two days before merging
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 150);
+        assert_eq!(stake_user.reward_owed, 200 + 100 + 7);
+        assert_eq!(stake_user.staked_since, 0);
+        assert_eq!(stake_user.last_update, 2 * DAILY_TS as i64);
+        assert_eq!(destination_account.lamports, 1_000);
+        assert_eq!(source_stake_user_account.lamports, 0);
+        assert!(source_stake_user_account.data.iter().all(|&byte| byte == 0));
+        assert_eq!(stake_pool_info.pool().user_count, 2);
+    }
+
+    #[test]
+    fn test_sponsored_stake_user() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let (rent_vault_key, _) = find_rent_vault_address(&STAKE_PROGRAM_ID, &stake_pool_key);
+        let mut rent_vault_account = Account::new(0, 0, &system_program::id());
+        let funder_key = Pubkey::new_unique();
+        let mut funder_account = Account::new(10_000_000, 0, &system_program::id());
+        let rent = Rent::default();
+        let rent_exempt = rent.minimum_balance(StakeUser::LEN);
+
+        do_process_instruction(
+            fund_rent_vault(STAKE_PROGRAM_ID, stake_pool_key, funder_key, 5_000_000).unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut funder_account,
+                &mut rent_vault_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(rent_vault_account.lamports, 5_000_000);
+        assert_eq!(funder_account.lamports, 5_000_000);
+
+        let (stake_user_key, _) =
+            find_stake_user_address(&STAKE_PROGRAM_ID, &stake_pool_key, &user_key);
+        let mut stake_user_account = Account::new(0, StakeUser::LEN, &system_program::id());
+        let create =
+            create_sponsored_stake_user(STAKE_PROGRAM_ID, stake_pool_key, user_key).unwrap();
+        let mut create_with = |stake_user_account: &mut Account,
+                               rent_vault_account: &mut Account| {
+            do_process_instruction(
+                create.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    rent_vault_account,
+                    &mut create_account_for_test(&rent),
+                    &mut Account::default(),
+                ],
+            )
+        };
+        create_with(&mut stake_user_account, &mut rent_vault_account).unwrap();
+        assert_eq!(
+            create_with(&mut stake_user_account, &mut rent_vault_account),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        assert_eq!(stake_user_account.owner, STAKE_PROGRAM_ID);
+        assert_eq!(stake_user_account.lamports, rent_exempt);
+        assert_eq!(rent_vault_account.lamports, 5_000_000 - rent_exempt);
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert!(stake_user.rent_sponsored);
+        assert_eq!(stake_user.owner, user_key);
+        assert_eq!(stake_pool_info.pool().user_count, 1);
+
+        // the rent goes back to the vault, not to the owner
+        let destination_key = Pubkey::new_unique();
+        let mut destination_account = Account::default();
+        assert_eq!(
+            do_process_instruction(
+                close_stake_user(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    destination_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut destination_account,
+                ],
+            ),
+            Err(CustomError::InvalidRentVault.into())
+        );
+        do_process_instruction(
+            close_stake_user(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                stake_user_key,
+                user_key,
+                rent_vault_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut rent_vault_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(rent_vault_account.lamports, 5_000_000);
+
+        // only the admin withdraws
+        let withdraw = |admin_key| {
+            withdraw_rent_vault(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                admin_key,
+                destination_key,
+                2_000_000,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            do_process_instruction(
+                withdraw(funder_key),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut funder_account,
+                    &mut rent_vault_account,
+                    &mut destination_account,
+                    &mut Account::default(),
+                ],
+            ),
+            Err(CustomError::InvalidAdmin.into())
+        );
+        do_process_instruction(
+            withdraw(stake_pool_info.admin_key),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut rent_vault_account,
+                &mut destination_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(rent_vault_account.lamports, 3_000_000);
+        assert_eq!(destination_account.lamports, 2_000_000);
+    }
+
+    /// Global config with stakes and claims paused
+    fn paused_global_config_account() -> Account {
+        let mut global_config_account = Account::new(0, GlobalConfig::LEN, &STAKE_PROGRAM_ID);
+        GlobalConfig::pack(
+            GlobalConfig {
+                is_initialized: true,
+                paused: true,
+                ..GlobalConfig::default()
+            },
+            &mut global_config_account.data,
+        )
+        .unwrap();
+        global_config_account
+    }
+
+    #[test]
+    fn test_protocol_pause() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let authority_key = Pubkey::new_unique();
+        let guardian_key = Pubkey::new_unique();
+        let mut global_config_account = Account::new(0, GlobalConfig::LEN, &STAKE_PROGRAM_ID);
+        GlobalConfig::pack(
+            GlobalConfig {
+                is_initialized: true,
+                authority: authority_key,
+                guardian: guardian_key,
+                ..GlobalConfig::default()
+            },
+            &mut global_config_account.data,
+        )
+        .unwrap();
+        let set_pause = |global_config_account: &mut Account, guardian_key, paused| {
+            do_process_instruction(
+                set_protocol_pause(STAKE_PROGRAM_ID, guardian_key, paused).unwrap(),
+                vec![global_config_account, &mut Account::default()],
+            )
+        };
+        assert_eq!(
+            set_pause(&mut global_config_account, Pubkey::new_unique(), true),
+            Err(CustomError::InvalidAdmin.into())
+        );
+        set_pause(&mut global_config_account, guardian_key, true).unwrap();
+        assert!(
+            GlobalConfig::unpack(&global_config_account.data)
+                .unwrap()
+                .paused
+        );
+
+        let stake = with_global_config(
+            STAKE_PROGRAM_ID,
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                50,
+            )
+            .unwrap(),
+        );
+        let do_stake = |stake_pool_info: &mut StakePoolInfo,
+                        stake_user_account: &mut Account,
+                        source_account: &mut Account,
+                        global_config_account: &mut Account| {
+            do_process_instruction(
+                stake.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    global_config_account,
+                ],
+            )
+        };
+        assert_eq!(
+            do_stake(
+                &mut stake_pool_info,
+                &mut stake_user_account,
+                &mut source_account,
+                &mut global_config_account
+            ),
+            Err(CustomError::ProtocolPaused.into())
+        );
+        // the authority resumes stakes too
+        set_pause(&mut global_config_account, authority_key, false).unwrap();
+        for _ in 0..2 {
+            do_stake(
+                &mut stake_pool_info,
+                &mut stake_user_account,
+                &mut source_account,
+                &mut global_config_account,
+            )
+            .unwrap();
+        }
+
+        // stakers can still leave
+        set_pause(&mut global_config_account, guardian_key, true).unwrap();
+        do_process_instruction(
+            unstake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                user_key,
+                stake_pool_info.reserved_key,
+                source_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source.amount, 100);
+    }
+
+    #[test]
+    fn test_roles() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let setter_key = Pubkey::new_unique();
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let set_rate = |admin: Pubkey| {
+            set_reward_rate(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                admin,
+                RateData {
+                    reward_numerator: 2,
+                    reward_denominator: 3,
+                },
+            )
+            .unwrap()
+        };
+        let set_rate_as_setter = set_rate(setter_key);
+
+        // only the admin grants roles
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_role(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    setter_key,
+                    Role::RateSetter,
+                    setter_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_rate_as_setter.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // unknown roles
+        {
+            let mut instruction = set_role(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                Role::RateSetter,
+                setter_key,
+            )
+            .unwrap();
+            instruction.data[1] = 4;
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                do_process_instruction(
+                    instruction,
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        do_process_instruction(
+            set_role(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                Role::RateSetter,
+                setter_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(stake_pool_info.pool().rate_setter, setter_key);
+
+        do_process_instruction(
+            set_rate_as_setter.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert!(stake_pool_info
+            .pool()
+            .rate_checkpoints
+            .iter()
+            .any(|rate| rate.reward_numerator == 2 && rate.reward_denominator == 3));
+
+        // the rate setter holds no other role
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                deprecate(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    setter_key,
+                    Pubkey::new_unique(),
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // revoked by granting the role to the default pubkey
+        do_process_instruction(
+            set_role(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                Role::RateSetter,
+                Pubkey::default(),
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_rate_as_setter,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // the admin keeps every role
+        do_process_instruction(
+            set_rate(stake_pool_info.admin_key),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_repair_user() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.stake_amount = 100;
+        stake_user.last_update = 10 * DAILY_TS as i64;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let now = create_account_for_test(&Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        });
+        let repair = repair_user(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.admin_key,
+            stake_user_key,
+        )
+        .unwrap();
+
+        do_process_instruction(
+            repair.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_user_account,
+                &mut now.clone(),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.last_update, DAILY_TS as i64);
+        assert_eq!(
+            stake_user
+                .pending_reward(&stake_pool_info.pool(), 2 * DAILY_TS as i64)
+                .unwrap(),
+            100
+        );
+
+        // only timestamps in the future are repaired
+        assert_eq!(
+            Err(CustomError::NothingToRepair.into()),
+            do_process_instruction(
+                repair,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut stake_user_account,
+                    &mut now.clone(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_admin() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (governance_key, _) = find_governance_address(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &stake_pool_info.stake_pool_key,
+        );
+
+        // can't be handed over to nobody
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_admin(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    Pubkey::default(),
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            set_admin(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                governance_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.admin, governance_key);
+
+        // the previous admin is out
+        assert_eq!(
+            Err(CustomError::InvalidAdmin.into()),
+            do_process_instruction(
+                deprecate(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    Pubkey::new_unique(),
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // while proposals of the governance go through
+        let instructions = proposal_instructions(vec![deprecate(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            governance_key,
+            Pubkey::new_unique(),
+        )
+        .unwrap()]);
+        do_process_instruction(
+            instructions[0].clone().into(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert!(stake_pool.deprecated);
+    }
+
+    #[test]
+    fn test_close_stake_user() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        stake_pool_info.create_stake_user(Pubkey::new_unique());
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.user_count, 2);
+
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let destination_key = Pubkey::new_unique();
+        let mut destination_account = Account::default();
+        stake_user_account.lamports = 1_000;
+        let close = close_stake_user(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            destination_key,
+        )
+        .unwrap();
+
+        // not while staking
+        assert_eq!(
+            Err(CustomError::StakeUserNotEmpty.into()),
+            do_process_instruction(
+                close.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut destination_account,
+                ],
+            )
+        );
+
+        do_process_instruction(
+            unstake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                user_key,
+                stake_pool_info.reserved_key,
+                source_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // the rent goes elsewhere
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(
+                close_stake_user(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_user_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            close,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut destination_account,
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(destination_account.lamports, 1_000);
+        assert_eq!(stake_user_account.lamports, 0);
+        assert!(stake_user_account.data.iter().all(|&byte| byte == 0));
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.user_count, 1);
+    }
+
+    #[test]
+    fn test_relock() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let day = DAILY_TS as i64;
+        let clock_at = |unix_timestamp| {
+            create_account_for_test(&Clock {
+                unix_timestamp,
+                ..Clock::default()
+            })
+        };
+        let stake_pool_key = stake_pool_info.stake_pool_key;
+        let relock_to = |lock_tier| {
+            relock(
+                STAKE_PROGRAM_ID,
+                stake_pool_key,
+                stake_user_key,
+                user_key,
+                lock_tier,
+            )
+            .unwrap()
+        };
+        let unstake_all = unstake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            stake_pool_info.authority_key,
+            user_key,
+            stake_pool_info.reserved_key,
+            source_key,
+            100,
+        )
+        .unwrap();
+
+        // no lock tiers, no locks
+        assert_eq!(
+            Err(CustomError::InvalidRelock.into()),
+            do_process_instruction(
+                relock_to(0),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut clock_at(0),
+                ],
+            )
+        );
+
+        let lock_tiers = [
+            LockTier {
+                duration: 7 * DAILY_TS,
+                bonus_bps: 1_000,
+            },
+            LockTier {
+                duration: 30 * DAILY_TS,
+                bonus_bps: 5_000,
+            },
+            LockTier::default(),
+        ];
+        do_process_instruction(
+            set_lock_tiers(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                lock_tiers,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert!(stake_pool.is_locked());
+        assert_eq!(stake_pool.lock_tiers, lock_tiers);
+
+        do_process_instruction(
+            relock_to(0),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut clock_at(0),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(CustomError::StakeLocked.into()),
+            do_process_instruction(
+                unstake_all.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut source_account,
+                    &mut clock_at(day),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // extending accrues what is owed under the previous lock first
+        do_process_instruction(
+            relock_to(1),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut clock_at(day),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.locked_until, 31 * day);
+        assert_eq!(stake_user.lock_bonus_bps, 5_000);
+        assert_eq!(stake_user.last_update, day);
+
+        // and can't shorten the lock
+        assert_eq!(
+            Err(CustomError::InvalidRelock.into()),
+            do_process_instruction(
+                relock_to(0),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut clock_at(2 * day),
+                ],
+            )
+        );
+
+        // only the owner relocks
+        assert_eq!(
+            Err(CustomError::InvalidStakeOwner.into()),
+            do_process_instruction(
+                relock(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    Pubkey::new_unique(),
+                    1,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut clock_at(2 * day),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            unstake_all,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut clock_at(31 * day),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 0);
+    }
+
+    #[test]
+    fn test_pda_owner() {
+        // a vault program staking its deposits from an escrow at its address,
+        // signing for it with invoke_signed
+        let vault_program_id = Pubkey::new_unique();
+        let (escrow_key, _) = Pubkey::find_program_address(&[b"escrow"], &vault_program_id);
+        let admin_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(admin_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) =
+            stake_pool_info.create_stake_user(escrow_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &admin_key,
+            &escrow_key,
+            100,
+        );
+        let mut stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            escrow_key,
+            escrow_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            100,
+        )
+        .unwrap();
+
+        // a wrapper that can't sign for the escrow
+        stake_instruction.accounts[2].is_signer = false;
+        stake_instruction.accounts[3].is_signer = false;
+        assert_eq!(
+            Err(CustomError::InvalidSigner.into()),
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        stake_instruction.accounts[2].is_signer = true;
+        stake_instruction.accounts[3].is_signer = true;
+        do_process_instruction(
+            stake_instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.owner, escrow_key);
+        assert_eq!(stake_user.stake_amount, 100);
+
+        do_process_instruction(
+            unstake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                escrow_key,
+                stake_pool_info.reserved_key,
+                source_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source.amount, 100);
+
+        // rent goes back to the escrow, which can hold data of its own
+        let mut escrow_account = Account::new(1, 8, &vault_program_id);
+        stake_user_account.lamports = 1_000;
+        do_process_instruction(
+            close_stake_user(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                escrow_key,
+                escrow_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut escrow_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(escrow_account.lamports, 1_001);
+    }
+
+    #[test]
+    fn test_stake_snapshot() {
+        fn stake_at(
+            stake_pool_info: &mut StakePoolInfo,
+            mint_authority_key: &Pubkey,
+            (owner_key, stake_user_key): (Pubkey, Pubkey),
+            stake_user_account: &mut Account,
+            amount: u64,
+            unix_timestamp: i64,
+        ) {
+            let (source_key, mut source_account) = create_token_account(
+                &stake_pool_info.stake_token_mint_key,
+                &mut stake_pool_info.stake_token_mint_account,
+                mint_authority_key,
+                &owner_key,
+                amount,
+            );
+            do_process_instruction(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    owner_key,
+                    owner_key,
+                    source_key,
+                    stake_pool_info.stake_token_mint_key,
+                    amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        }
+
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let mut stakers: Vec<(Pubkey, Pubkey, Account)> = [100, 50]
+            .iter()
+            .map(|&amount| {
+                let owner_key = Pubkey::new_unique();
+                let (stake_user_key, mut stake_user_account) =
+                    stake_pool_info.create_stake_user(owner_key);
+                stake_at(
+                    &mut stake_pool_info,
+                    &user_key,
+                    (owner_key, stake_user_key),
+                    &mut stake_user_account,
+                    amount,
+                    0,
+                );
+                (owner_key, stake_user_key, stake_user_account)
+            })
+            .collect();
+        let leaves: Vec<[u8; 32]> = stakers
+            .iter()
+            .zip([100, 50].iter())
+            .map(|((owner_key, ..), &amount)| merkle::leaf_hash(owner_key, amount))
+            .collect();
+
+        let stake_snapshot_key = Pubkey::new_unique();
+        let mut stake_snapshot_account = Account::new(0, StakeSnapshot::LEN, &STAKE_PROGRAM_ID);
+        let create = |stake_snapshot_key| {
+            create_stake_snapshot(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                stake_snapshot_key,
+                10,
+            )
+            .unwrap()
+        };
+        let (create_first, create_second) =
+            (create(stake_snapshot_key), create(Pubkey::new_unique()));
+        do_process_instruction(
+            create_first,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_snapshot_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut create_account_for_test(&Clock::default()),
+            ],
+        )
+        .unwrap();
+        // one snapshot at a time
+        assert_eq!(
+            Err(CustomError::SnapshotPending.into()),
+            do_process_instruction(
+                create_second,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::new(0, StakeSnapshot::LEN, &STAKE_PROGRAM_ID),
+                    &mut create_account_for_test(&Rent::free()),
+                    &mut create_account_for_test(&Clock::default()),
+                ],
+            )
+        );
+
+        // stakes changing after the timestamp are snapshot as they were
+        let (owner_key, stake_user_key, stake_user_account) = &mut stakers[0];
+        stake_at(
+            &mut stake_pool_info,
+            &user_key,
+            (*owner_key, *stake_user_key),
+            stake_user_account,
+            200,
+            20,
+        );
+
+        let mut update = |stakers: &mut [(Pubkey, Pubkey, Account)], finalize, unix_timestamp| {
+            let stake_user_keys: Vec<Pubkey> = stakers.iter().map(|(_, key, _)| *key).collect();
+            let mut admin_account = Account::default();
+            let mut clock_account = create_account_for_test(&Clock {
+                unix_timestamp,
+                ..Clock::default()
+            });
+            let mut accounts = vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut admin_account,
+                &mut stake_snapshot_account,
+                &mut clock_account,
+            ];
+            accounts.extend(stakers.iter_mut().map(|(.., account)| account));
+            do_process_instruction(
+                update_stake_snapshot(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    stake_snapshot_key,
+                    &stake_user_keys,
+                    finalize,
+                )
+                .unwrap(),
+                accounts,
+            )
+        };
+
+        // not within the snapshot second
+        assert_eq!(
+            Err(CustomError::SnapshotPending.into()),
+            update(&mut stakers[..0], false, 10)
+        );
+        // stake users come in increasing order
+        let (mut leaves, mut stakers) = (leaves, stakers);
+        if stakers[0].1 > stakers[1].1 {
+            stakers.swap(0, 1);
+            leaves.swap(0, 1);
+        }
+        update(&mut stakers[..1], false, 30).unwrap();
+        assert_eq!(
+            Err(CustomError::InvalidSnapshot.into()),
+            update(&mut stakers[..1], false, 30)
+        );
+        update(&mut stakers[1..], true, 30).unwrap();
+
+        let stake_snapshot = StakeSnapshot::unpack(&stake_snapshot_account.data).unwrap();
+        assert!(stake_snapshot.finalized);
+        assert_eq!(stake_snapshot.leaf_count, 2);
+        assert_eq!(stake_snapshot.total_staked, 150);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle::proof(&leaves, index, merkle::SNAPSHOT_DEPTH);
+            assert!(merkle::verify(
+                &stake_snapshot.root,
+                *leaf,
+                index as u64,
+                &proof
+            ));
+        }
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.snapshot, Pubkey::default());
+        assert_eq!(stake_pool.total_staked, 350);
+    }
+
+    #[test]
+    fn test_bonus_claims() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+
+        let bonus_campaign_key = Pubkey::new_unique();
+        let (bonus_authority_key, _) =
+            find_bonus_authority_address(&STAKE_PROGRAM_ID, &bonus_campaign_key);
+        let (bonus_vault_key, mut bonus_vault_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &bonus_authority_key,
+            1_000,
+        );
+
+        let owners = [(Pubkey::new_unique(), 300), (Pubkey::new_unique(), 200)];
+        let leaves: Vec<[u8; 32]> = owners
+            .iter()
+            .map(|(owner_key, amount)| merkle::leaf_hash(owner_key, *amount))
+            .collect();
+        let mut branch = [[0; 32]; merkle::SNAPSHOT_DEPTH];
+        for (count, leaf) in leaves.iter().enumerate() {
+            merkle::append(&mut branch, count as u64, *leaf);
+        }
+        let root = merkle::root(&branch, 2);
+
+        let publish = publish_bonus(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.admin_key,
+            bonus_campaign_key,
+            bonus_vault_key,
+            root,
+            2,
+        )
+        .unwrap();
+        // the claimed bitmap must fit
+        assert_eq!(
+            Err(CustomError::InvalidBonusCampaign.into()),
+            do_process_instruction(
+                publish.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::new(0, BonusCampaign::LEN, &STAKE_PROGRAM_ID),
+                    &mut bonus_vault_account,
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+        );
+        let mut bonus_campaign_account =
+            Account::new(0, BonusCampaign::account_len(2), &STAKE_PROGRAM_ID);
+        do_process_instruction(
+            publish,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut bonus_campaign_account,
+                &mut bonus_vault_account,
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+
+        let (owner_key, amount) = owners[0];
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &owner_key,
+            0,
+        );
+        let mut claim = |owner_key, index: usize, amount| {
+            do_process_instruction(
+                claim_bonus(
+                    STAKE_PROGRAM_ID,
+                    bonus_campaign_key,
+                    bonus_vault_key,
+                    owner_key,
+                    reward_token_key,
+                    index as u64,
+                    amount,
+                    merkle::proof(&leaves, index, merkle::SNAPSHOT_DEPTH),
+                )
+                .unwrap(),
+                vec![
+                    &mut bonus_campaign_account,
+                    &mut Account::default(),
+                    &mut bonus_vault_account,
+                    &mut Account::default(),
+                    &mut reward_token_account,
+                    &mut Account::default(),
+                ],
+            )
+        };
+
+        // only the amount and owner of the leaf
+        assert_eq!(
+            Err(CustomError::InvalidProof.into()),
+            claim(owner_key, 0, amount + 1)
+        );
+        assert_eq!(
+            Err(CustomError::InvalidProof.into()),
+            claim(owner_key, 1, owners[1].1)
+        );
+        claim(owner_key, 0, amount).unwrap();
+        // and once
+        assert_eq!(
+            Err(CustomError::BonusClaimed.into()),
+            claim(owner_key, 0, amount)
+        );
+
+        let reward_token = spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+        assert_eq!(reward_token.amount, 300);
+        let bonus_campaign =
+            BonusCampaign::unpack(&bonus_campaign_account.data[..BonusCampaign::LEN]).unwrap();
+        assert_eq!(bonus_campaign.total_claimed, 300);
+        assert_eq!(bonus_campaign_account.data[BonusCampaign::LEN], 1);
+
+        // bonus claims are halted with the others
+        let (owner_key, amount) = owners[1];
+        assert_eq!(
+            Err(CustomError::ProtocolPaused.into()),
+            do_process_instruction(
+                with_global_config(
+                    STAKE_PROGRAM_ID,
+                    claim_bonus(
+                        STAKE_PROGRAM_ID,
+                        bonus_campaign_key,
+                        bonus_vault_key,
+                        owner_key,
+                        reward_token_key,
+                        1,
+                        amount,
+                        merkle::proof(&leaves, 1, merkle::SNAPSHOT_DEPTH),
+                    )
+                    .unwrap(),
+                ),
+                vec![
+                    &mut bonus_campaign_account,
+                    &mut Account::default(),
+                    &mut bonus_vault_account,
+                    &mut Account::default(),
+                    &mut reward_token_account,
+                    &mut Account::default(),
+                    &mut paused_global_config_account(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_claim_if_any() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        let mut instruction = claim_if_any(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            reward_token_key,
+        )
+        .unwrap();
+
+        // nothing owed is a no-op
+        do_process_instruction(
+            instruction.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // while a plain claim fails
+        instruction.data = InstructionType::Claim.pack();
+        assert_eq!(
+            Err(CustomError::InsufficientClaimAmount.into()),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_harvest_all() {
+        let user_key = Pubkey::new_unique();
+        let mut pool_infos = vec![];
+        let mut claims = vec![];
+        for owed in [100, 40] {
+            let mut stake_pool_info = StakePoolInfo::new(user_key);
+            stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+            let (stake_user_key, mut stake_user_account) =
+                stake_pool_info.create_stake_user(user_key);
+            let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+            stake_user.reward_owed = owed;
+            StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+            let (reward_token_key, reward_token_account) = create_token_account(
+                &stake_pool_info.reward_mint_key,
+                &mut stake_pool_info.reward_mint_account,
+                &stake_pool_info.authority_key,
+                &user_key,
+                0,
+            );
+            claims.push(
+                claim_if_any(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                )
+                .unwrap(),
+            );
+            pool_infos.push((stake_pool_info, stake_user_account, reward_token_account));
+        }
+        let frozen_claim = claims[1].clone();
+        let frozen_token_account = &mut pool_infos[1].2;
+        let mut reward_token =
+            spl_token::state::Account::unpack(&frozen_token_account.data).unwrap();
+        reward_token.state = spl_token::state::AccountState::Frozen;
+        spl_token::state::Account::pack(reward_token, &mut frozen_token_account.data).unwrap();
+
+        // claims of another owner can't be bundled
+        let mut other_owner_claims = claims.clone();
+        other_owner_claims[1].accounts[2].pubkey = Pubkey::new_unique();
+        let mut harvest = |claims| {
+            // owner, pool authority, clock and token program of each claim
+            let mut shared: Vec<[Account; 4]> = pool_infos
+                .iter()
+                .map(|_| {
+                    [
+                        Account::default(),
+                        Account::default(),
+                        create_account_for_test(&Clock::default()),
+                        Account::default(),
+                    ]
+                })
+                .collect();
+            let mut accounts = vec![];
+            for ((stake_pool_info, stake_user_account, reward_token_account), shared) in
+                pool_infos.iter_mut().zip(&mut shared)
+            {
+                let [owner, authority, clock, token_program] = shared;
+                accounts.push(&mut stake_pool_info.stake_pool_account);
+                accounts.push(stake_user_account);
+                accounts.push(owner);
+                accounts.push(authority);
+                accounts.push(&mut stake_pool_info.reward_mint_account);
+                accounts.push(reward_token_account);
+                accounts.push(clock);
+                accounts.push(token_program);
+            }
+            do_process_instruction(harvest_all(STAKE_PROGRAM_ID, claims).unwrap(), accounts)
+        };
+        assert_eq!(
+            harvest(other_owner_claims),
+            Err(CustomError::InvalidStakeOwner.into())
+        );
+        // the claim into a frozen account is skipped
+        harvest(claims).unwrap();
+
+        let (_, return_data) = get_return_data().unwrap();
+        assert_eq!(
+            Vec::<u64>::try_from_slice(&return_data).unwrap(),
+            vec![100, 0]
+        );
+        for ((_, stake_user_account, reward_token_account), (claimed, owed)) in
+            pool_infos.iter().zip([(100, 0), (0, 40)])
+        {
+            let reward_token =
+                spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+            assert_eq!(reward_token.amount, claimed);
+            let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+            assert_eq!(stake_user.reward_owed, owed);
+        }
+
+        // and fails on its own
+        let (stake_pool_info, stake_user_account, reward_token_account) = &mut pool_infos[1];
+        assert_eq!(
+            Err(CustomError::AccountFrozen.into()),
+            do_process_instruction(
+                frozen_claim,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    reward_token_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_frozen_token_accounts() {
+        fn set_state(account: &mut Account, state: spl_token::state::AccountState) {
+            let mut token = spl_token::state::Account::unpack(&account.data).unwrap();
+            token.state = state;
+            spl_token::state::Account::pack(token, &mut account.data).unwrap();
+        }
+
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            100,
+        )
+        .unwrap();
+        let unstake_instruction = unstake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            stake_pool_info.authority_key,
+            user_key,
+            stake_pool_info.reserved_key,
+            source_key,
+            100,
+        )
+        .unwrap();
+
+        // staking out of a frozen account
+        set_state(&mut source_account, spl_token::state::AccountState::Frozen);
+        assert_eq!(
+            Err(CustomError::AccountFrozen.into()),
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        set_state(
+            &mut source_account,
+            spl_token::state::AccountState::Initialized,
+        );
+        do_process_instruction(
+            stake_instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // unstaking into a frozen account
+        set_state(&mut source_account, spl_token::state::AccountState::Frozen);
+        assert_eq!(
+            Err(CustomError::AccountFrozen.into()),
+            do_process_instruction(
+                unstake_instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut source_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 100);
+    }
+
+    #[test]
+    fn test_builders_round_trip() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        let day = create_account_for_test(&Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        });
+
+        for instruction in [
+            set_reward_rate(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                RateData {
+                    reward_numerator: 1,
+                    reward_denominator: 1,
+                },
+            )
+            .unwrap(),
+            set_streak_bonus(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                DAILY_TS * 7,
+                1_000,
+            )
+            .unwrap(),
+            set_claim_burn(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                2_000,
+            )
+            .unwrap(),
+        ] {
+            // accounts past the instruction's are ignored
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+        }
+        let stake_pool = stake_pool_info.pool();
+        assert_eq!(stake_pool.streak_bonus_bps, 1_000);
+        assert_eq!(stake_pool.claim_burn_bps, 2_000);
+
+        do_process_instruction(
+            set_claim_burn(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                0,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        do_process_instruction(
+            refresh(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                vec![stake_user_key],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut day.clone(),
+                &mut stake_user_account,
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 100);
+
+        do_process_instruction(
+            claim(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.reward_mint_key,
+                reward_token_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut day.clone(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let reward_token = spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+        assert_eq!(reward_token.amount, 100);
+
+        do_process_instruction(
+            unstake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                user_key,
+                stake_pool_info.reserved_key,
+                source_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut day.clone(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source.amount, 100);
+    }
+
+    #[test]
+    fn test_snapshot_pool() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+
+        do_process_instruction(
+            mint_to(
+                &spl_token::id(),
+                &stake_pool_info.stake_token_mint_key,
+                &stake_pool_info.reserved_key,
+                &user_key,
+                &[],
+                500,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut stake_pool_info.reserved_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let pool_snapshot_key = Pubkey::new_unique();
+        let mut pool_snapshot_account = Account::new(0, PoolSnapshot::LEN, &STAKE_PROGRAM_ID);
+        let instruction = snapshot_pool(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            pool_snapshot_key,
+            stake_pool_info.reserved_key,
+            stake_pool_info.reward_mint_key,
+        )
+        .unwrap();
+        let clock = Clock {
+            slot: 7,
+            unix_timestamp: 100,
+            ..Clock::default()
+        };
+
+        do_process_instruction(
+            instruction.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut pool_snapshot_account,
+                &mut stake_pool_info.reserved_account,
+                &mut stake_pool_info.reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut create_account_for_test(&clock),
+            ],
+        )
+        .unwrap();
+
+        let pool_snapshot = PoolSnapshot::unpack(&pool_snapshot_account.data).unwrap();
+        assert_eq!(
+            pool_snapshot,
+            PoolSnapshot {
+                is_initialized: true,
+                pool_pubkey: stake_pool_info.stake_pool_key,
+                timestamp: 100,
+                slot: 7,
+                total_staked: 0,
+                reserve_balance: 500,
+                reward_supply: 0,
+                reward_numerator: 1,
+                reward_denominator: 1_000,
+                reward_period: DAILY_TS,
+            }
+        );
+
+        // snapshots are never overwritten
+        assert_eq!(
+            Err(CustomError::AlreadyInUse.into()),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut pool_snapshot_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                    &mut create_account_for_test(&clock),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_verify_invariants() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let instruction = verify_invariants(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reserved_key,
+            stake_pool_info.reward_mint_key,
+        )
+        .unwrap();
+
+        do_process_instruction(
+            instruction.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut stake_pool_info.reward_mint_account,
+            ],
+        )
+        .unwrap();
+
+        // stakes not backed by the reserve
+        let mut stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        stake_pool.total_staked = 1;
+        Pool::pack(stake_pool, &mut stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(
+            Err(CustomError::InvariantViolated.into()),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut stake_pool_info.reward_mint_account,
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_get_pool_info() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        stake_pool_info.create_stake_user(user_key);
+
+        do_process_instruction(
+            get_pool_info(STAKE_PROGRAM_ID, stake_pool_info.stake_pool_key).unwrap(),
+            vec![&mut stake_pool_info.stake_pool_account],
+        )
+        .unwrap();
+
+        // newer programs may append fields
+        let (_, mut return_data) = get_return_data().unwrap();
+        return_data.push(0);
+        let pool_info = PoolInfo::deserialize(&mut return_data.as_slice()).unwrap();
+        assert_eq!(
+            pool_info,
+            PoolInfo {
+                stake_token_mint: stake_pool_info.stake_token_mint_key,
+                reward_mint: stake_pool_info.reward_mint_key,
+                reward_mode: RewardMode::MintToUser as u8,
+                reward_numerator: 1,
+                reward_denominator: 1_000,
+                reward_period: DAILY_TS,
+                total_staked: 0,
+                user_count: 1,
+                deprecated: false,
+                pool_flags: 0,
+                stream_duration: 0,
+                stake_decimals: 2,
+                reward_decimals: 2,
+            }
+        );
+        // the reward mint mirrors the decimals of the stake mint
+        let reward_mint = Mint::unpack(&stake_pool_info.reward_mint_account.data).unwrap();
+        assert_eq!(reward_mint.decimals, 2);
+    }
+
+    #[test]
+    fn test_refresh_batch() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let mut other_pool_info = StakePoolInfo::new(user_key);
+        other_pool_info.initialize_stake_pool(1, 1).unwrap();
+
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let (idle_user_key, mut idle_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (other_user_key, mut other_user_account) = other_pool_info.create_stake_user(user_key);
+        let closed_user_key = Pubkey::new_unique();
+
+        let instruction = refresh_batch(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            vec![
+                stake_user_key,
+                idle_user_key,
+                other_user_key,
+                closed_user_key,
+            ],
+        )
+        .unwrap();
+        let mut clock_account = create_account_for_test(&Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        });
+        let mut closed_user_account = Account::default();
+        let mut keyed: Vec<(Pubkey, &mut Account)> = vec![
+            (stake_user_key, &mut stake_user_account),
+            (idle_user_key, &mut idle_user_account),
+            (other_user_key, &mut other_user_account),
+            (closed_user_key, &mut closed_user_account),
+        ];
+        keyed.sort_by_key(|(key, _)| *key);
+
+        // stake users out of order
+        {
+            let mut reversed = instruction.clone();
+            reversed.accounts[2..].reverse();
+            let mut accounts = vec![&mut stake_pool_info.stake_pool_account, &mut clock_account];
+            accounts.extend(keyed.iter_mut().rev().map(|(_, account)| &mut **account));
+            assert_eq!(
+                Err(CustomError::InvalidRefreshBatch.into()),
+                do_process_instruction(reversed, accounts)
+            );
+        }
+
+        // more than a batch
+        assert_eq!(
+            Err(CustomError::InvalidRefreshBatch.into()),
+            refresh_batch(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                (0..=MAX_REFRESH_BATCH)
+                    .map(|_| Pubkey::new_unique())
+                    .collect(),
+            )
+        );
+
+        // foreign, idle and closed stake users are skipped
+        let mut accounts = vec![&mut stake_pool_info.stake_pool_account, &mut clock_account];
+        accounts.extend(keyed.iter_mut().map(|(_, account)| &mut **account));
+        do_process_instruction(instruction, accounts).unwrap();
+        let (_, return_data) = get_return_data().unwrap();
+        assert_eq!(
+            RefreshCounts::try_from_slice(&return_data).unwrap(),
+            RefreshCounts {
+                updated: 1,
+                skipped: 3,
+            }
+        );
+
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 100);
+        let idle_user = StakeUser::unpack(&idle_user_account.data).unwrap();
+        assert_eq!(idle_user.reward_owed, 0);
+        let other_user = StakeUser::unpack(&other_user_account.data).unwrap();
+        assert_eq!(other_user.reward_owed, 0);
+    }
+
+    #[test]
+    fn test_refresh_counts() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let mut other_pool_info = StakePoolInfo::new(user_key);
+        other_pool_info.initialize_stake_pool(1, 1).unwrap();
+
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let (idle_user_key, mut idle_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (other_user_key, mut other_user_account) = other_pool_info.create_stake_user(user_key);
+        let closed_user_key = Pubkey::new_unique();
+        let clock = Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        };
+
+        // pool of another program
+        let mut foreign_pool_account = stake_pool_info.stake_pool_account.clone();
+        foreign_pool_account.owner = Pubkey::new_unique();
+        assert_eq!(
+            Err(CustomError::InvalidAccountOwner.into()),
+            do_process_instruction(
+                refresh(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    vec![stake_user_key],
+                )
+                .unwrap(),
+                vec![
+                    &mut foreign_pool_account,
+                    &mut create_account_for_test(&clock),
+                    &mut stake_user_account.clone(),
+                ],
+            )
+        );
+
+        // program accounts that aren't stake users
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            do_process_instruction(
+                refresh(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    vec![stake_user_key, other_pool_info.stake_pool_key],
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account.clone(),
+                    &mut create_account_for_test(&clock),
+                    &mut stake_user_account.clone(),
+                    &mut other_pool_info.stake_pool_account,
+                ],
+            )
+        );
+
+        // foreign, idle and closed stake users are skipped, in any order
+        do_process_instruction(
+            refresh(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                vec![
+                    closed_user_key,
+                    stake_user_key,
+                    other_user_key,
+                    idle_user_key,
+                ],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut create_account_for_test(&clock),
+                &mut Account::default(),
+                &mut stake_user_account,
+                &mut other_user_account,
+                &mut idle_user_account,
+            ],
+        )
+        .unwrap();
+        let (_, return_data) = get_return_data().unwrap();
+        assert_eq!(
+            RefreshCounts::try_from_slice(&return_data).unwrap(),
+            RefreshCounts {
+                updated: 1,
+                skipped: 3,
+            }
+        );
+
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 100);
+        let idle_user = StakeUser::unpack(&idle_user_account.data).unwrap();
+        assert_eq!(idle_user.reward_owed, 0);
+        let other_user = StakeUser::unpack(&other_user_account.data).unwrap();
+        assert_eq!(other_user.reward_owed, 0);
+
+        // nothing left to skip
+        do_process_instruction(
+            refresh(STAKE_PROGRAM_ID, stake_pool_info.stake_pool_key, vec![]).unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut create_account_for_test(&clock),
+            ],
+        )
+        .unwrap();
+        let (_, return_data) = get_return_data().unwrap();
+        assert_eq!(
+            RefreshCounts::try_from_slice(&return_data).unwrap(),
+            RefreshCounts::default()
+        );
+    }
+
+    /// Instructions sysvar of a transaction of one top-level instruction
+    fn instructions_sysvar_account(top_level_program_id: &Pubkey) -> Account {
+        let mut account = Account::new(1, 0, &solana_program::sysvar::id());
+        account.data = construct_instructions_data(&[BorrowedInstruction {
+            program_id: top_level_program_id,
+            accounts: vec![],
+            data: &[],
+        }]);
+        account
+    }
+
+    #[test]
+    fn test_top_level_only() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let integrator_key = Pubkey::new_unique();
+        do_process_instruction(
+            set_top_level_only(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                true,
+                vec![integrator_key],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert!(stake_pool.top_level_only);
+        assert!(stake_pool.is_integrator(&integrator_key));
+        assert!(!stake_pool.is_integrator(&Pubkey::default()));
+
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            100,
+        )
+        .unwrap();
+
+        // without the instructions sysvar
+        assert_eq!(
+            Err(CustomError::CallerNotAllowed.into()),
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // wrapped by an unknown program, then by the integrator
+        for (top_level_program_id, result) in [
+            (
+                Pubkey::new_unique(),
+                Err(CustomError::CallerNotAllowed.into()),
+            ),
+            (integrator_key, Ok(())),
+        ] {
+            assert_eq!(
+                result,
+                do_process_instruction(
+                    with_instructions_sysvar(stake_instruction.clone()),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut source_account,
+                        &mut stake_pool_info.reserved_account,
+                        &mut create_account_for_test(&Clock::default()),
+                        &mut Account::default(),
+                        &mut instructions_sysvar_account(&top_level_program_id),
+                    ],
+                )
+            );
+        }
+
+        // the integrator calling through another program
+        STACK_HEIGHT.with(|stack_height| *stack_height.borrow_mut() = 3);
+        assert_eq!(
+            Err(CustomError::CallerNotAllowed.into()),
+            do_process_instruction(
+                with_instructions_sysvar(stake_instruction.clone()),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    &mut instructions_sysvar_account(&integrator_key),
+                ],
+            )
+        );
+        STACK_HEIGHT.with(|stack_height| *stack_height.borrow_mut() = 0);
+
+        // unstaking at the top level
+        do_process_instruction(
+            with_instructions_sysvar(
+                unstake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    user_key,
+                    stake_pool_info.reserved_key,
+                    source_key,
+                    100,
+                )
+                .unwrap(),
+            ),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut instructions_sysvar_account(&STAKE_PROGRAM_ID),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 0);
+    }
+
+    #[test]
+    fn test_stake_user_of_other_pool() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let mut other_pool_info = StakePoolInfo::new(user_key);
+        other_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = other_pool_info.create_stake_user(user_key);
+
+        assert_eq!(
+            Err(CustomError::InvalidStakeOwner.into()),
+            do_process_instruction(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    Pubkey::new_unique(),
+                    stake_pool_info.stake_token_mint_key,
+                    1,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_legacy_layout() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, _) = stake_pool_info.create_stake_user(user_key);
+        // a stake user of the first release, left under the upgraded program
+        let mut legacy_stake_user_account =
+            Account::new(0, LEGACY_STAKE_USER_SIZE, &STAKE_PROGRAM_ID);
+        legacy_stake_user_account.data[0] = 1;
+        let instruction = claim(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            Pubkey::new_unique(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Err(CustomError::LegacyAccountLayout.into()),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut legacy_stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_stake_with_delegate() {
+        let user_key = Pubkey::new_unique();
+        let delegate_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            1_000,
+        );
+
+        // source not approved to the delegate
+        assert_eq!(
+            Err(CustomError::InvalidDelegate.into()),
+            do_process_instruction(
+                stake_with_delegate(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    delegate_key,
+                    source_key,
+                    stake_pool_info.stake_token_mint_key,
+                    400,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            approve(
+                &spl_token::id(),
+                &source_key,
+                &delegate_key,
+                &user_key,
+                &[],
+                1_000,
+            )
+            .unwrap(),
+            vec![
+                &mut source_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // source of someone else than the stake user owner
+        {
+            let other_key = Pubkey::new_unique();
+            let (other_source_key, mut other_source_account) = create_token_account(
+                &stake_pool_info.stake_token_mint_key,
+                &mut stake_pool_info.stake_token_mint_account,
+                &user_key,
+                &other_key,
+                1_000,
+            );
+            do_process_instruction(
+                approve(
+                    &spl_token::id(),
+                    &other_source_key,
+                    &delegate_key,
+                    &other_key,
+                    &[],
+                    1_000,
+                )
+                .unwrap(),
+                vec![
+                    &mut other_source_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(
+                Err(CustomError::InvalidStakeOwner.into()),
+                do_process_instruction(
+                    stake_with_delegate(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        &stake_pool_info.pool(),
+                        stake_user_key,
+                        delegate_key,
+                        other_source_key,
+                        stake_pool_info.stake_token_mint_key,
+                        400,
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut other_source_account,
+                        &mut stake_pool_info.reserved_account,
+                        &mut create_account_for_test(&Clock::default()),
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // staked with the delegate as the only signer
+        do_process_instruction(
+            stake_with_delegate(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                delegate_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                400,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 400);
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.total_staked, 400);
+        let reserved =
+            spl_token::state::Account::unpack(&stake_pool_info.reserved_account.data).unwrap();
+        assert_eq!(reserved.amount, 400);
+    }
+
+    #[test]
+    fn test_claim_for() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 100;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+
+        // any token account of the owner, created at the associated address
+        let (other_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        let (associated_token_key, _) = find_associated_token_address(
+            &user_key,
+            &stake_pool_info.reward_mint_key,
+            &spl_token::id(),
+        );
+        let instruction = claim_for(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            stake_pool_info.reward_mint_key,
+        )
+        .unwrap();
+        assert_eq!(instruction.accounts[4].pubkey, associated_token_key);
+        assert!(instruction.accounts.iter().all(|meta| !meta.is_signer));
+
+        // not the associated token account
+        {
+            let mut instruction = instruction.clone();
+            instruction.accounts[4].pubkey = other_token_key;
+            assert_eq!(
+                Err(CustomError::InvalidTokenAccount.into()),
+                do_process_instruction(
+                    instruction,
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut stake_pool_info.reward_mint_account,
+                        &mut reward_token_account,
+                        &mut create_account_for_test(&Clock::default()),
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // claimed without any signer
+        do_process_instruction(
+            instruction.clone(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let reward_token = spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+        assert_eq!(reward_token.amount, 100);
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 0);
+
+        // nothing left to claim
+        assert_eq!(
+            Err(CustomError::InsufficientClaimAmount.into()),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_claim_split() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 1_000;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        let (treasury_key, mut treasury_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &Pubkey::new_unique(),
+            0,
+        );
+        let (contributor_key, mut contributor_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &Pubkey::new_unique(),
+            0,
+        );
+        let payer_key = Pubkey::new_unique();
+        let mut payer_account = Account::new(10_000_000, 0, &system_program::id());
+        let mut claim_split_account = Account::new(0, ClaimSplit::LEN, &system_program::id());
+
+        let mut set_split = |shares: [ClaimShare; MAX_CLAIM_SHARES],
+                             stake_pool_info: &mut StakePoolInfo,
+                             stake_user_account: &mut Account| {
+            do_process_instruction(
+                set_claim_split(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    payer_key,
+                    shares,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut claim_split_account,
+                    &mut payer_account,
+                    &mut create_account_for_test(&Rent::default()),
+                    &mut Account::default(),
+                ],
+            )
+        };
+        let share = |destination, bps| ClaimShare { destination, bps };
+
+        // a repeated destination
+        assert_eq!(
+            set_split(
+                [
+                    share(treasury_key, 2_500),
+                    share(treasury_key, 1_000),
+                    ClaimShare::default(),
+                    ClaimShare::default(),
+                ],
+                &mut stake_pool_info,
+                &mut stake_user_account,
+            ),
+            Err(CustomError::InvalidClaimShares.into())
+        );
+        set_split(
+            [
+                share(treasury_key, 2_500),
+                share(contributor_key, 1_000),
+                ClaimShare::default(),
+                ClaimShare::default(),
+            ],
+            &mut stake_pool_info,
+            &mut stake_user_account,
+        )
+        .unwrap();
+        assert_eq!(claim_split_account.owner, STAKE_PROGRAM_ID);
+        let claim_split = ClaimSplit::unpack(&claim_split_account.data).unwrap();
+        assert_eq!(claim_split.stake_user_pubkey, stake_user_key);
+        assert!(
+            StakeUser::unpack(&stake_user_account.data)
+                .unwrap()
+                .claim_split
+        );
+
+        // the stake user has a claim split, every claim takes it
+        assert_eq!(
+            Err(CustomError::InvalidClaimSplit.into()),
+            do_process_instruction(
+                claim(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        let instruction = with_claim_split(
+            STAKE_PROGRAM_ID,
+            claim(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.reward_mint_key,
+                reward_token_key,
+            )
+            .unwrap(),
+            &claim_split,
+        )
+        .unwrap();
+
+        // destinations out of order
+        {
+            let mut instruction = instruction.clone();
+            instruction.accounts.swap(9, 10);
+            assert_eq!(
+                Err(CustomError::InvalidClaimSplit.into()),
+                do_process_instruction(
+                    instruction,
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut stake_pool_info.reward_mint_account,
+                        &mut reward_token_account,
+                        &mut create_account_for_test(&Clock::default()),
+                        &mut Account::default(),
+                        &mut claim_split_account,
+                        &mut contributor_account,
+                        &mut treasury_account,
+                    ],
+                )
+            );
+        }
+
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut claim_split_account,
+                &mut treasury_account,
+                &mut contributor_account,
+            ],
+        )
+        .unwrap();
+        // the shares round down, the rest paid into the reward token account
+        for (account, amount) in [
+            (&treasury_account, 250),
+            (&contributor_account, 100),
+            (&reward_token_account, 650),
+        ] {
+            let token = spl_token::state::Account::unpack(&account.data).unwrap();
+            assert_eq!(token.amount, amount);
+        }
+        assert_eq!(get_return_data().unwrap().1, 1_000u64.to_le_bytes());
+
+        // the claim split closes with the stake user
+        let destination_key = Pubkey::new_unique();
+        let mut destination_account = Account::default();
+        let rent = stake_user_account.lamports + claim_split_account.lamports;
+        let instruction = close_stake_user(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            destination_key,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(CustomError::InvalidClaimSplit.into()),
+            do_process_instruction(
+                instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut destination_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            with_closed_claim_split(STAKE_PROGRAM_ID, instruction).unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut destination_account,
+                &mut claim_split_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(claim_split_account.lamports, 0);
+        assert_eq!(destination_account.lamports, rent);
+    }
+
+    #[test]
+    fn test_auto_claim() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            1_000,
+        );
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+
+        // nothing owed yet, claims nothing
+        do_process_instruction(
+            with_auto_claim(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_pool_info.stake_token_mint_key,
+                    400,
+                )
+                .unwrap(),
+                stake_pool_info.authority_key,
+                stake_pool_info.reward_mint_key,
+                reward_token_key,
+                None,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+            ],
+        )
+        .unwrap();
+
+        // unstaked and claimed a day of rewards at once
+        let clock = Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        };
+        do_process_instruction(
+            with_auto_claim(
+                unstake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    user_key,
+                    stake_pool_info.reserved_key,
+                    source_key,
+                    400,
+                )
+                .unwrap(),
+                stake_pool_info.authority_key,
+                stake_pool_info.reward_mint_key,
+                reward_token_key,
+                None,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&clock),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+            ],
+        )
+        .unwrap();
+
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 0);
+        assert_eq!(stake_user.reward_owed, 0);
+        let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source.amount, 1_000);
+        let reward_token = spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+        assert_eq!(reward_token.amount, 400);
+        // lifetime statistics outlive the stake
+        assert_eq!(stake_user.total_deposited, 400);
+        assert_eq!(stake_user.total_withdrawn, 400);
+        assert_eq!(stake_user.total_claimed, 400);
+        assert_eq!(stake_user.last_claim_ts, DAILY_TS as i64);
+
+        // the reward accounts must follow
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            do_process_instruction(
+                with_auto_claim(
+                    stake(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        &stake_pool_info.pool(),
+                        stake_user_key,
+                        user_key,
+                        user_key,
+                        source_key,
+                        stake_pool_info.stake_token_mint_key,
+                        400,
+                    )
+                    .unwrap(),
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                    None,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&clock),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // auto-claims don't pay the shares of a claim split
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.claim_split = true;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        assert_eq!(
+            Err(CustomError::InvalidClaimSplit.into()),
+            do_process_instruction(
+                with_auto_claim(
+                    stake(
+                        STAKE_PROGRAM_ID,
+                        stake_pool_info.stake_pool_key,
+                        &stake_pool_info.pool(),
+                        stake_user_key,
+                        user_key,
+                        user_key,
+                        source_key,
+                        stake_pool_info.stake_token_mint_key,
+                        400,
+                    )
+                    .unwrap(),
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    reward_token_key,
+                    None,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&clock),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_claim_and_stake_into() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 100;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+
+        // staking the reward token of the first pool
+        let mut target_pool_info = StakePoolInfo::new(user_key);
+        target_pool_info.stake_token_mint_key = stake_pool_info.reward_mint_key;
+        target_pool_info.stake_token_mint_account = stake_pool_info.reward_mint_account.clone();
+        target_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (target_user_key, mut target_user_account) =
+            target_pool_info.create_stake_user(user_key);
+
+        // into the same pool
+        assert_eq!(
+            Err(CustomError::InvalidTargetPool.into()),
+            do_process_instruction(
+                claim_and_stake_into(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    stake_pool_info.reward_mint_key,
+                    stake_pool_info.reserved_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account.clone(),
+                    &mut stake_user_account.clone(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // a top-level-only target pool checks the caller of the stake
+        do_process_instruction(
+            set_top_level_only(
+                STAKE_PROGRAM_ID,
+                target_pool_info.stake_pool_key,
+                target_pool_info.admin_key,
+                true,
+                vec![],
+            )
+            .unwrap(),
+            vec![
+                &mut target_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let instruction = claim_and_stake_into(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            target_pool_info.stake_pool_key,
+            target_user_key,
+            stake_pool_info.authority_key,
+            stake_pool_info.reward_mint_key,
+            target_pool_info.reserved_key,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(CustomError::CallerNotAllowed.into()),
+            do_process_instruction(
+                instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut target_pool_info.stake_pool_account,
+                    &mut target_user_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut target_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        for (top_level_program_id, result) in [
+            (
+                Pubkey::new_unique(),
+                Err(CustomError::CallerNotAllowed.into()),
+            ),
+            (STAKE_PROGRAM_ID, Ok(())),
+        ] {
+            assert_eq!(
+                result,
+                do_process_instruction(
+                    with_instructions_sysvar(instruction.clone()),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut target_pool_info.stake_pool_account,
+                        &mut target_user_account,
+                        &mut Account::default(),
+                        &mut stake_pool_info.reward_mint_account,
+                        &mut target_pool_info.reserved_account,
+                        &mut create_account_for_test(&Clock::default()),
+                        &mut Account::default(),
+                        &mut instructions_sysvar_account(&top_level_program_id),
+                    ],
+                )
+            );
+        }
+
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 0);
+        let target_user = StakeUser::unpack(&target_user_account.data).unwrap();
+        assert_eq!(target_user.stake_amount, 100);
+        let target_pool = Pool::unpack(&target_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(target_pool.total_staked, 100);
+        let reserved =
+            spl_token::state::Account::unpack(&target_pool_info.reserved_account.data).unwrap();
+        assert_eq!(reserved.amount, 100);
+    }
+
+    #[test]
+    fn test_hook_program() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            1_000,
+        );
+        let mut hook_program_account = Account {
+            executable: true,
+            ..Account::default()
+        };
+
+        // the pool can't be its own hook
+        assert_eq!(
+            Err(CustomError::InvalidHookProgram.into()),
+            do_process_instruction(
+                set_hook_program(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    STAKE_PROGRAM_ID,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        do_process_instruction(
+            set_hook_program(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                TEST_HOOK_PROGRAM_ID,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.hook_program, TEST_HOOK_PROGRAM_ID);
+
+        let stake_instruction = stake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            &stake_pool_info.pool(),
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.stake_token_mint_key,
+            400,
+        )
+        .unwrap();
+
+        // the hook program is required once registered
+        assert_eq!(
+            Err(CustomError::InvalidHookProgram.into()),
+            do_process_instruction(
+                stake_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // and must be executable
+        assert_eq!(
+            Err(CustomError::InvalidHookProgram.into()),
+            do_process_instruction(
+                with_hook(stake_instruction.clone(), TEST_HOOK_PROGRAM_ID, vec![]),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        do_process_instruction(
+            with_hook(stake_instruction, TEST_HOOK_PROGRAM_ID, vec![]),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut hook_program_account,
+            ],
+        )
+        .unwrap();
+
+        // a failing hook fails the action
+        let unstake_instruction = unstake(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            stake_pool_info.authority_key,
+            user_key,
+            stake_pool_info.reserved_key,
+            source_key,
+            400,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(
+                with_hook(unstake_instruction.clone(), TEST_HOOK_PROGRAM_ID, vec![]),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reserved_account,
+                    &mut source_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    &mut hook_program_account,
+                ],
+            )
+        );
+
+        // until the admin removes it
+        do_process_instruction(
+            set_hook_program(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                Pubkey::default(),
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            unstake_instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 0);
+    }
+
+    #[test]
+    fn test_oracle_priced_claim() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (reward_token_key, mut reward_token_account) = create_token_account(
+            &stake_pool_info.reward_mint_key,
+            &mut stake_pool_info.reward_mint_account,
+            &stake_pool_info.authority_key,
+            &user_key,
+            0,
+        );
+        // reward token at $2.50
+        let price_oracle_key = Pubkey::new_unique();
+        let mut price_oracle_account = Account::new(0, 0, &Pubkey::new_unique());
+        price_oracle_account.data = pyth_price_account(250_000_000, 1_000_000, -8, 0);
+
+        // only price accounts are accepted
+        assert_eq!(
+            Err(CustomError::InvalidPriceOracle.into()),
+            do_process_instruction(
+                set_price_oracle(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    stake_user_key,
+                    60,
+                    100,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut stake_user_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            set_price_oracle(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                price_oracle_key,
+                60,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut price_oracle_account,
+            ],
+        )
+        .unwrap();
+
+        // $10 owed
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 10_000_000;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let instruction = with_price_oracle(
+            claim_if_any(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.reward_mint_key,
+                reward_token_key,
+            )
+            .unwrap(),
+            price_oracle_key,
+        )
+        .unwrap();
+
+        // not with a stale price
+        let clock = Clock {
+            unix_timestamp: 61,
+            ..Clock::default()
+        };
+        assert_eq!(
+            Err(CustomError::StalePrice.into()),
+            do_process_instruction(
+                instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut stake_pool_info.reward_mint_account,
+                    &mut reward_token_account,
+                    &mut create_account_for_test(&clock),
+                    &mut Account::default(),
+                    &mut price_oracle_account,
+                ],
+            )
+        );
+
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut price_oracle_account,
+            ],
+        )
+        .unwrap();
+        let reward_token = spl_token::state::Account::unpack(&reward_token_account.data).unwrap();
+        assert_eq!(reward_token.amount, 4_000_000);
+    }
+
+    #[test]
+    fn test_interest_bearing_reward_mint() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let authority_key = stake_pool_info.authority_key;
+        // 5% a year, set up directly as initializing the extension reads the clock
+        let create_reward_mint = |mint_authority: &Pubkey| {
+            let mint_key = Pubkey::new_unique();
+            let mut mint_account = Account::new(
+                mint_minimum_balance(),
+                ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+                    ExtensionType::InterestBearingConfig,
+                ])
+                .unwrap(),
+                &spl_token_2022::id(),
+            );
+            let mut mint =
+                StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(
+                    &mut mint_account.data,
+                )
+                .unwrap();
+            let config = mint.init_extension::<InterestBearingConfig>(true).unwrap();
+            config.current_rate = 500.into();
+            config.pre_update_average_rate = 500.into();
+            do_process_instruction(
+                spl_token_2022::instruction::initialize_mint(
+                    &spl_token_2022::id(),
+                    &mint_key,
+                    mint_authority,
+                    None,
+                    6,
+                )
+                .unwrap(),
+                vec![
+                    &mut mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+            (mint_key, mint_account)
+        };
+
+        // the pool authority must mint the rewards
+        let (mint_key, mint_account) = create_reward_mint(&user_key);
+        stake_pool_info.reward_mint_key = mint_key;
+        stake_pool_info.reward_mint_account = mint_account;
+        assert_eq!(
+            stake_pool_info.initialize_stake_pool(1, 1_000),
+            Err(CustomError::InvalidTokenMint.into())
+        );
+        let (mint_key, mint_account) = create_reward_mint(&authority_key);
+        stake_pool_info.reward_mint_key = mint_key;
+        stake_pool_info.reward_mint_account = mint_account;
+        stake_pool_info.initialize_stake_pool(1, 1_000).unwrap();
+        // the mint created by the client keeps its own decimals
+        assert_eq!(stake_pool_info.pool().reward_decimals, 6);
+        assert_eq!(stake_pool_info.pool().stake_decimals, 2);
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+
+        let reward_token_key = Pubkey::new_unique();
+        let mut reward_token_account = Account::new(
+            account_minimum_balance(),
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+                ExtensionType::ImmutableOwner,
+            ])
+            .unwrap(),
+            &spl_token_2022::id(),
+        );
+        do_process_instruction(
+            initialize_immutable_owner(&spl_token_2022::id(), &reward_token_key).unwrap(),
+            vec![&mut reward_token_account],
+        )
+        .unwrap();
+        do_process_instruction(
+            spl_token_2022::instruction::initialize_account(
+                &spl_token_2022::id(),
+                &reward_token_key,
+                &mint_key,
+                &user_key,
+            )
+            .unwrap(),
+            vec![
+                &mut reward_token_account,
+                &mut stake_pool_info.reward_mint_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+
+        // reward token at $2.50 in UI amounts, a year after the mint started
+        // accruing interest
+        let one_year = 31_556_736;
+        let price_oracle_key = Pubkey::new_unique();
+        let mut price_oracle_account = Account::new(0, 0, &Pubkey::new_unique());
+        price_oracle_account.data = pyth_price_account(250_000_000, 1_000_000, -8, one_year);
+        do_process_instruction(
+            set_price_oracle(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                price_oracle_key,
+                60,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut price_oracle_account,
+            ],
+        )
+        .unwrap();
+
+        // $10 owed
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 10_000_000;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let instruction = with_reward_token_program(
+            with_price_oracle(
+                claim(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    authority_key,
+                    mint_key,
+                    reward_token_key,
+                )
+                .unwrap(),
+                price_oracle_key,
+            )
+            .unwrap(),
+            spl_token_2022::id(),
+        )
+        .unwrap();
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reward_mint_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock {
+                    unix_timestamp: one_year,
+                    ..Clock::default()
+                }),
+                &mut Account::default(),
+                &mut price_oracle_account,
+            ],
+        )
+        .unwrap();
+
+        // 4 tokens in UI amounts are fewer raw tokens once interest accrued
+        let reward_token = StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+            &reward_token_account.data,
+        )
+        .unwrap();
+        assert_eq!(reward_token.base.amount, 3_804_917);
+        let multiplier =
+            interest::interest_multiplier(&stake_pool_info.reward_mint_account.data, one_year)
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            interest::to_ui_amount(reward_token.base.amount, multiplier),
+            3_999_999
+        );
+    }
+
+    #[test]
+    fn test_transfer_hook_stake_mint() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let token_account_len = ExtensionType::try_calculate_account_len::<
+            spl_token_2022::state::Account,
+        >(&[ExtensionType::TransferHookAccount])
+        .unwrap();
+
+        // stake mint calling the test transfer hook
+        let stake_mint_key = Pubkey::new_unique();
+        let mut stake_mint_account = Account::new(
+            mint_minimum_balance(),
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+                ExtensionType::TransferHook,
+            ])
+            .unwrap(),
+            &spl_token_2022::id(),
+        );
+        do_process_instruction(
+            spl_token_2022::extension::transfer_hook::instruction::initialize(
+                &spl_token_2022::id(),
+                &stake_mint_key,
+                None,
+                Some(TEST_TRANSFER_HOOK_PROGRAM_ID),
+            )
+            .unwrap(),
+            vec![&mut stake_mint_account],
+        )
+        .unwrap();
+        do_process_instruction(
+            spl_token_2022::instruction::initialize_mint(
+                &spl_token_2022::id(),
+                &stake_mint_key,
+                &user_key,
+                None,
+                0,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+        let validation_key =
+            get_extra_account_metas_address(&stake_mint_key, &TEST_TRANSFER_HOOK_PROGRAM_ID);
+        let mut validation_account = Account::new(
+            0,
+            ExtraAccountMetaList::size_of(0).unwrap(),
+            &TEST_TRANSFER_HOOK_PROGRAM_ID,
+        );
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut validation_account.data, &[])
+            .unwrap();
+
+        stake_pool_info.stake_token_mint_key = stake_mint_key;
+        stake_pool_info.stake_token_mint_account = stake_mint_account;
+        stake_pool_info.reserved_account = Account::new(
+            account_minimum_balance(),
+            token_account_len,
+            &spl_token_2022::id(),
+        );
+        stake_pool_info.reward_mint_account = Account::new(
+            mint_minimum_balance(),
+            spl_token_2022::state::Mint::LEN,
+            &spl_token_2022::id(),
+        );
+        let mut instruction = create_stake_pool(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.authority_key,
+            stake_mint_key,
+            stake_pool_info.reserved_key,
+            stake_pool_info.reward_mint_key,
+            stake_pool_info.admin_key,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            InitData {
+                bump_seed: stake_pool_info.bump_seed,
+                reward_numerator: 1,
+                reward_denominator: 1,
+                reward_period: DAILY_TS,
+                reject_freeze_authority: false,
+            },
+        )
+        .unwrap();
+        instruction.accounts[6].pubkey = spl_token_2022::id();
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut stake_pool_info.reserved_account,
+                &mut stake_pool_info.reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+
+        let source_key = Pubkey::new_unique();
+        let mut source_account = Account::new(
+            account_minimum_balance(),
+            token_account_len,
+            &spl_token_2022::id(),
+        );
+        do_process_instruction(
+            spl_token_2022::instruction::initialize_account(
+                &spl_token_2022::id(),
+                &source_key,
+                &stake_mint_key,
+                &user_key,
+            )
+            .unwrap(),
+            vec![
+                &mut source_account,
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            spl_token_2022::instruction::mint_to(
+                &spl_token_2022::id(),
+                &stake_mint_key,
+                &source_key,
+                &user_key,
+                &[],
+                2_000,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut source_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let transfer_hook_accounts = vec![
+            AccountMeta::new_readonly(TEST_TRANSFER_HOOK_PROGRAM_ID, false),
+            AccountMeta::new_readonly(validation_key, false),
+        ];
+        let stake_ix = |stake_pool_info: &StakePoolInfo, amount| {
+            with_stake_mint(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_mint_key,
+                    amount,
+                )
+                .unwrap(),
+                stake_mint_key,
+                transfer_hook_accounts.clone(),
+            )
+            .unwrap()
+        };
+        let mut clock_account = create_account_for_test(&Clock::default());
+        let mut do_stake = |stake_pool_info: &mut StakePoolInfo,
+                            instruction: Instruction,
+                            source_account: &mut Account| {
+            // accounts past the instruction's are left out
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut clock_account,
+                    &mut Account::default(),
+                    &mut stake_pool_info.stake_token_mint_account,
+                    &mut Account::default(),
+                    &mut validation_account,
+                ],
+            )
+        };
+
+        // Token-2022 transfers need the stake mint
+        let mut instruction = stake_ix(&stake_pool_info, 500);
+        instruction.accounts.truncate(8);
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
+            do_stake(&mut stake_pool_info, instruction, &mut source_account)
+        );
+        // the transfer hook runs on staking
+        let instruction = stake_ix(&stake_pool_info, 1_500);
+        assert_eq!(
+            Err(CustomError::TokenTransferFailed.into()),
+            do_stake(&mut stake_pool_info, instruction, &mut source_account)
+        );
+        let instruction = stake_ix(&stake_pool_info, 500);
+        do_stake(&mut stake_pool_info, instruction, &mut source_account).unwrap();
+        let reserve = StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+            &stake_pool_info.reserved_account.data,
+        )
+        .unwrap();
+        assert_eq!(reserve.base.amount, 500);
+        assert_eq!(stake_pool_info.pool().total_staked, 500);
+
+        // and on unstaking, signed by the pool authority
+        do_process_instruction(
+            with_stake_mint(
+                unstake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    user_key,
+                    stake_pool_info.reserved_key,
+                    source_key,
+                    200,
+                )
+                .unwrap(),
+                stake_mint_key,
+                transfer_hook_accounts.clone(),
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut clock_account,
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut Account::default(),
+                &mut validation_account,
+            ],
+        )
+        .unwrap();
+        let source =
+            StateWithExtensions::<spl_token_2022::state::Account>::unpack(&source_account.data)
+                .unwrap();
+        assert_eq!(source.base.amount, 1_700);
 
-            spl_token::processor::Processor::process(
-                &instruction.program_id,
-                &new_account_infos,
-                &instruction.data,
+        // a pool hook listing the stake mint keeps all of its accounts
+        do_process_instruction(
+            set_hook_program(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                TEST_HOOK_PROGRAM_ID,
             )
-        }
-    }
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let hook_account_key = Pubkey::new_unique();
+        do_process_instruction(
+            with_hook(
+                stake_ix(&stake_pool_info, 100),
+                TEST_HOOK_PROGRAM_ID,
+                vec![
+                    AccountMeta::new_readonly(stake_mint_key, false),
+                    AccountMeta::new_readonly(hook_account_key, false),
+                ],
+            ),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut clock_account,
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account.clone(),
+                &mut Account::default(),
+                &mut validation_account,
+                &mut Account {
+                    executable: true,
+                    ..Account::default()
+                },
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            HOOK_ACCOUNTS.with(|hook_accounts| hook_accounts.borrow().clone()),
+            vec![
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_mint_key,
+                hook_account_key,
+            ]
+        );
+        assert_eq!(stake_pool_info.pool().total_staked, 400);
 
-    struct StakePoolInfo {
-        bump_seed: u8,
-        authority_key: Pubkey,
-        stake_pool_key: Pubkey,
-        stake_pool_account: Account,
-        stake_token_mint_key: Pubkey,
-        stake_token_mint_account: Account,
-        reserved_key: Pubkey,
-        reserved_account: Account,
-        reward_mint_key: Pubkey,
-        reward_mint_account: Account,
+        // the reserve is read through Token-2022
+        let mut pool_snapshot_account = Account::new(0, PoolSnapshot::LEN, &STAKE_PROGRAM_ID);
+        do_process_instruction(
+            snapshot_pool(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                Pubkey::new_unique(),
+                stake_pool_info.reserved_key,
+                stake_pool_info.reward_mint_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut pool_snapshot_account,
+                &mut stake_pool_info.reserved_account,
+                &mut stake_pool_info.reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut clock_account,
+            ],
+        )
+        .unwrap();
+        let pool_snapshot = PoolSnapshot::unpack(&pool_snapshot_account.data).unwrap();
+        assert_eq!(pool_snapshot.reserve_balance, 400);
+        do_process_instruction(
+            verify_invariants(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.reserved_key,
+                stake_pool_info.reward_mint_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut stake_pool_info.reward_mint_account,
+            ],
+        )
+        .unwrap();
     }
 
-    impl StakePoolInfo {
-        pub fn new(user_key: Pubkey) -> Self {
-            let stake_pool_key = Pubkey::new_unique();
-            let stake_pool_account = Account::new(0, Pool::LEN, &STAKE_PROGRAM_ID);
-            let (authority_key, bump_seed) =
-                Pubkey::find_program_address(&[&stake_pool_key.to_bytes()[..]], &STAKE_PROGRAM_ID);
-
-            let (stake_token_mint_key, stake_token_mint_account) =
-                create_mint(&spl_token::id(), &user_key, None);
-            let reserved_key = Pubkey::new_unique();
-            let reserved_account = Account::new(
-                account_minimum_balance(),
-                spl_token::state::Account::get_packed_len(),
-                &spl_token::id(),
-            );
-            let reward_mint_key = Pubkey::new_unique();
-            let reward_mint_account = Account::new(
+    #[test]
+    fn test_token_2022_vault_funded_pool() {
+        fn create_mint_2022(mint_authority_key: &Pubkey) -> (Pubkey, Account) {
+            let mint_key = Pubkey::new_unique();
+            let mut mint_account = Account::new(
                 mint_minimum_balance(),
-                spl_token::state::Mint::get_packed_len(),
-                &spl_token::id(),
+                spl_token_2022::state::Mint::LEN,
+                &spl_token_2022::id(),
+            );
+            do_process_instruction(
+                spl_token_2022::instruction::initialize_mint(
+                    &spl_token_2022::id(),
+                    &mint_key,
+                    mint_authority_key,
+                    None,
+                    6,
+                )
+                .unwrap(),
+                vec![
+                    &mut mint_account,
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+            (mint_key, mint_account)
+        }
+        fn create_account_2022(
+            mint_key: &Pubkey,
+            mint_account: &mut Account,
+            owner_key: &Pubkey,
+            amount: u64,
+        ) -> (Pubkey, Account) {
+            let account_key = Pubkey::new_unique();
+            let mut account = Account::new(
+                account_minimum_balance(),
+                spl_token_2022::state::Account::LEN,
+                &spl_token_2022::id(),
             );
+            do_process_instruction(
+                spl_token_2022::instruction::initialize_account(
+                    &spl_token_2022::id(),
+                    &account_key,
+                    mint_key,
+                    owner_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut account,
+                    mint_account,
+                    &mut Account::default(),
+                    &mut create_account_for_test(&Rent::free()),
+                ],
+            )
+            .unwrap();
+            mint_to_2022(mint_key, mint_account, &account_key, &mut account, amount);
+            (account_key, account)
+        }
+        fn mint_to_2022(
+            mint_key: &Pubkey,
+            mint_account: &mut Account,
+            account_key: &Pubkey,
+            account: &mut Account,
+            amount: u64,
+        ) {
+            let mint = spl_token_2022::state::Mint::unpack(&mint_account.data).unwrap();
+            do_process_instruction(
+                spl_token_2022::instruction::mint_to(
+                    &spl_token_2022::id(),
+                    mint_key,
+                    account_key,
+                    &mint.mint_authority.unwrap(),
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+                vec![mint_account, account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+        let amount_of = |account: &Account| {
+            spl_token_2022::state::Account::unpack(&account.data)
+                .unwrap()
+                .amount
+        };
 
-            StakePoolInfo {
-                bump_seed,
-                authority_key,
-                stake_pool_key,
-                stake_pool_account,
-                stake_token_mint_key,
-                stake_token_mint_account,
-                reserved_key,
-                reserved_account,
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        let (stake_mint_key, stake_mint_account) = create_mint_2022(&user_key);
+        let (reward_mint_key, mut reward_mint_account) = create_mint_2022(&user_key);
+        stake_pool_info.stake_token_mint_key = stake_mint_key;
+        stake_pool_info.stake_token_mint_account = stake_mint_account;
+        stake_pool_info.reserved_account = Account::new(
+            account_minimum_balance(),
+            spl_token_2022::state::Account::LEN,
+            &spl_token_2022::id(),
+        );
+        let reward_vault_key = Pubkey::new_unique();
+        let mut reward_vault_account = Account::new(
+            account_minimum_balance(),
+            spl_token_2022::state::Account::LEN,
+            &spl_token_2022::id(),
+        );
+        let mut instruction = create_vault_funded_stake_pool(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_pool_info.authority_key,
+            stake_mint_key,
+            stake_pool_info.reserved_key,
+            reward_mint_key,
+            reward_vault_key,
+            stake_pool_info.admin_key,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            InitData {
+                bump_seed: stake_pool_info.bump_seed,
+                reward_numerator: 1,
+                reward_denominator: 1_000,
+                reward_period: DAILY_TS,
+                reject_freeze_authority: false,
+            },
+        )
+        .unwrap();
+        instruction.accounts[6].pubkey = spl_token_2022::id();
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+                &mut stake_pool_info.reserved_account,
+                &mut reward_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+            ],
+        )
+        .unwrap();
+        mint_to_2022(
+            &reward_mint_key,
+            &mut reward_mint_account,
+            &reward_vault_key,
+            &mut reward_vault_account,
+            1_000,
+        );
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+        let (source_key, mut source_account) = create_account_2022(
+            &stake_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            1_000,
+        );
+        let (reward_token_key, mut reward_token_account) =
+            create_account_2022(&reward_mint_key, &mut reward_mint_account, &user_key, 0);
+
+        // stake and unstake with the stake mint
+        do_process_instruction(
+            with_stake_mint(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_mint_key,
+                    500,
+                )
+                .unwrap(),
+                stake_mint_key,
+                vec![],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            with_stake_mint(
+                unstake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    user_key,
+                    stake_pool_info.reserved_key,
+                    source_key,
+                    200,
+                )
+                .unwrap(),
+                stake_mint_key,
+                vec![],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut stake_pool_info.stake_token_mint_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(amount_of(&source_account), 700);
+        assert_eq!(amount_of(&stake_pool_info.reserved_account), 300);
+
+        // claims burn a fifth of 100 owed out of the vault
+        do_process_instruction(
+            set_claim_burn(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                2_000,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let mut stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        stake_user.reward_owed = 100;
+        StakeUser::pack(stake_user, &mut stake_user_account.data).unwrap();
+        let instruction = with_reward_token_program(
+            claim(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                stake_pool_info.authority_key,
+                reward_vault_key,
+                reward_token_key,
+            )
+            .unwrap(),
+            spl_token_2022::id(),
+        )
+        .unwrap();
+
+        // Token-2022 transfers out of the vault need the reward mint
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
+            do_process_instruction(
+                instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account.clone(),
+                    &mut stake_user_account.clone(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut reward_vault_account.clone(),
+                    &mut reward_token_account.clone(),
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        let mut instruction = instruction;
+        instruction
+            .accounts
+            .push(AccountMeta::new(reward_mint_key, false));
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+                &mut reward_token_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut reward_mint_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(amount_of(&reward_token_account), 80);
+        assert_eq!(amount_of(&reward_vault_account), 900);
+        let reward_mint = spl_token_2022::state::Mint::unpack(&reward_mint_account.data).unwrap();
+        assert_eq!(reward_mint.supply, 980);
+
+        // reports count the tokens paid out of the vault rather than minted
+        let payer_key = Pubkey::new_unique();
+        let mut report_account = Account::new(0, DistributionReport::LEN, &system_program::id());
+        do_process_instruction(
+            finalize_distribution_report(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
                 reward_mint_key,
-                reward_mint_account,
-            }
+                payer_key,
+                0,
+                None,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut report_account,
+                &mut reward_mint_account,
+                &mut Account::new(10_000_000, 0, &system_program::id()),
+                &mut create_account_for_test(&Rent::default()),
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let report = DistributionReport::unpack(&report_account.data).unwrap();
+        assert_eq!(report.reward_supply, 980);
+        assert_eq!((report.total_rewards_paid, report.rewards_paid), (80, 80));
+        assert_eq!((report.total_burned, report.fees_collected), (20, 20));
+
+        // the vault is read through Token-2022
+        do_process_instruction(
+            verify_invariants(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.authority_key,
+                stake_pool_info.reserved_key,
+                reward_vault_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut reward_vault_account,
+            ],
+        )
+        .unwrap();
+
+        // and so are bonus vaults, paying out with the reward mint
+        let bonus_campaign_key = Pubkey::new_unique();
+        let (bonus_authority_key, _) =
+            find_bonus_authority_address(&STAKE_PROGRAM_ID, &bonus_campaign_key);
+        let (bonus_vault_key, mut bonus_vault_account) = create_account_2022(
+            &reward_mint_key,
+            &mut reward_mint_account,
+            &bonus_authority_key,
+            50,
+        );
+        let leaf = merkle::leaf_hash(&user_key, 50);
+        let mut branch = [[0; 32]; merkle::SNAPSHOT_DEPTH];
+        merkle::append(&mut branch, 0, leaf);
+        let mut bonus_campaign_account =
+            Account::new(0, BonusCampaign::account_len(1), &STAKE_PROGRAM_ID);
+        do_process_instruction(
+            publish_bonus(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                bonus_campaign_key,
+                bonus_vault_key,
+                merkle::root(&branch, 1),
+                1,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut bonus_campaign_account,
+                &mut bonus_vault_account,
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+        let mut instruction = with_reward_token_program(
+            claim_bonus(
+                STAKE_PROGRAM_ID,
+                bonus_campaign_key,
+                bonus_vault_key,
+                user_key,
+                reward_token_key,
+                0,
+                50,
+                merkle::proof(&[leaf], 0, merkle::SNAPSHOT_DEPTH),
+            )
+            .unwrap(),
+            spl_token_2022::id(),
+        )
+        .unwrap();
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(reward_mint_key, false));
+        do_process_instruction(
+            instruction,
+            vec![
+                &mut bonus_campaign_account,
+                &mut Account::default(),
+                &mut bonus_vault_account,
+                &mut Account::default(),
+                &mut reward_token_account,
+                &mut Account::default(),
+                &mut reward_mint_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(amount_of(&reward_token_account), 130);
+        assert_eq!(amount_of(&bonus_vault_account), 0);
+    }
+
+    #[test]
+    fn test_multi_mint_stake() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+
+        // a second mint counting half toward accrual
+        let (stake_mint_key, mut stake_mint_account) =
+            create_mint(&spl_token::id(), &user_key, None);
+        let reserve_key = Pubkey::new_unique();
+        let mut reserve_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+        do_process_instruction(
+            add_stake_mint(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                stake_pool_info.authority_key,
+                stake_mint_key,
+                reserve_key,
+                5_000,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_mint_account,
+                &mut reserve_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        assert_eq!(stake_pool.stake_mint_index(&reserve_key), Some(0));
+
+        // the primary mint can't be added again
+        assert_eq!(
+            stake_pool.clone().add_stake_mint(
+                stake_pool_info.stake_token_mint_key,
+                Pubkey::new_unique(),
+                5_000
+            ),
+            Err(CustomError::InvalidTokenMint.into())
+        );
+        let mut full_pool = stake_pool.clone();
+        for _ in 1..MAX_STAKE_MINTS {
+            full_pool
+                .add_stake_mint(Pubkey::new_unique(), Pubkey::new_unique(), 5_000)
+                .unwrap();
         }
+        assert_eq!(
+            full_pool.add_stake_mint(Pubkey::new_unique(), Pubkey::new_unique(), 5_000),
+            Err(CustomError::StakeMintsFull.into())
+        );
 
-        pub fn initialize_stake_pool(
-            &mut self,
-            reward_numerator: u64,
-            reward_denominator: u64,
-        ) -> ProgramResult {
+        let (source_key, mut source_account) = create_token_account(
+            &stake_mint_key,
+            &mut stake_mint_account,
+            &user_key,
+            &user_key,
+            1_000,
+        );
+        // not into the primary reserve
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
             do_process_instruction(
-                create_stake_pool(
+                stake(
                     STAKE_PROGRAM_ID,
-                    self.stake_pool_key,
-                    self.authority_key,
-                    self.stake_token_mint_key,
-                    self.reserved_key,
-                    self.reward_mint_key,
-                    InitData {
-                        bump_seed: self.bump_seed,
-                        reward_numerator,
-                        reward_denominator,
-                    },
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_pool_info.stake_token_mint_key,
+                    400,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // nor for mints the pool doesn't accept
+        assert_eq!(
+            Err(CustomError::InvalidTokenMint.into()),
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                Pubkey::new_unique(),
+                400,
+            )
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_mint_key,
+                400,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut reserve_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_pool.total_staked, 0);
+        assert_eq!(stake_pool.stake_mints[0].total_staked, 400);
+        assert_eq!(stake_user.stake_amount, 0);
+        assert_eq!(stake_user.mint_balances, [400, 0, 0, 0]);
+
+        // a day accrues on half the stake
+        let clock = Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        };
+        do_process_instruction(
+            unstake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                user_key,
+                reserve_key,
+                source_key,
+                400,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reserve_account,
+                &mut source_account,
+                &mut create_account_for_test(&clock),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 200);
+        assert_eq!(stake_user.mint_balances, [0; MAX_STAKE_MINTS]);
+        let source_token = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source_token.amount, 1_000);
+    }
+
+    #[test]
+    fn test_pair_stake() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
+
+        // 1 token : 10 paired tokens, the paired leg counting a tenth toward accrual
+        let (paired_mint_key, mut paired_mint_account) =
+            create_mint(&spl_token::id(), &user_key, None);
+        let paired_reserve_key = Pubkey::new_unique();
+        let mut paired_reserve_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+        do_process_instruction(
+            add_stake_mint(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                stake_pool_info.authority_key,
+                paired_mint_key,
+                paired_reserve_key,
+                1_000,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut paired_mint_account,
+                &mut paired_reserve_account,
+                &mut create_account_for_test(&Rent::free()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            set_pair_ratio(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                10,
+                1,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        let (paired_source_key, mut paired_source_account) = create_token_account(
+            &paired_mint_key,
+            &mut paired_mint_account,
+            &user_key,
+            &user_key,
+            900,
+        );
+
+        // single legs are rejected
+        assert_eq!(
+            Err(CustomError::PairedStakeRequired.into()),
+            do_process_instruction(
+                stake(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    &stake_pool_info.pool(),
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_pool_info.stake_token_mint_key,
+                    40,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        do_process_instruction(
+            stake_pair(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.reserved_key,
+                paired_source_key,
+                paired_reserve_key,
+                40,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+                &mut paired_source_account,
+                &mut paired_reserve_account,
+            ],
+        )
+        .unwrap();
+        let stake_pool = Pool::unpack(&stake_pool_info.stake_pool_account.data).unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_pool.total_staked, 40);
+        assert_eq!(stake_pool.stake_mints[0].total_staked, 400);
+        assert_eq!(stake_user.stake_amount, 40);
+        assert_eq!(stake_user.mint_balances, [400, 0, 0, 0]);
+        assert_eq!(
+            stake_pool.clone().set_pair_ratio(20, 1),
+            Err(CustomError::InvalidPairRatio.into())
+        );
+
+        // not enough paired tokens for the ratio
+        assert_eq!(
+            Err(CustomError::InsufficientFunds.into()),
+            do_process_instruction(
+                stake_pair(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    user_key,
+                    source_key,
+                    stake_pool_info.reserved_key,
+                    paired_source_key,
+                    paired_reserve_key,
+                    60,
                 )
                 .unwrap(),
                 vec![
-                    &mut self.stake_pool_account,
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
                     &mut Account::default(),
-                    &mut self.stake_token_mint_account,
-                    &mut self.reserved_account,
-                    &mut self.reward_mint_account,
-                    &mut create_account_for_test(&Rent::free()),
                     &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                    &mut paired_source_account,
+                    &mut paired_reserve_account,
                 ],
             )
-        }
-    }
+        );
 
-    fn test_syscall_stubs() {
-        use std::sync::Once;
-        static ONCE: Once = Once::new();
+        // a day accrues on both legs, unstaking a quarter of each
+        let clock = Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        };
+        do_process_instruction(
+            unstake_pair(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                stake_pool_info.authority_key,
+                user_key,
+                stake_pool_info.reserved_key,
+                source_key,
+                paired_reserve_key,
+                paired_source_key,
+                10,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut stake_pool_info.reserved_account,
+                &mut source_account,
+                &mut create_account_for_test(&clock),
+                &mut Account::default(),
+                &mut paired_reserve_account,
+                &mut paired_source_account,
+            ],
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 80);
+        assert_eq!(stake_user.stake_amount, 30);
+        assert_eq!(stake_user.mint_balances, [300, 0, 0, 0]);
+        let paired_source_token =
+            spl_token::state::Account::unpack(&paired_source_account.data).unwrap();
+        assert_eq!(paired_source_token.amount, 600);
 
-        ONCE.call_once(|| {
-            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs {}));
-        });
+        // top-level-only pair pools check their caller like Stake and Unstake
+        do_process_instruction(
+            set_top_level_only(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                true,
+                vec![],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let stake_pair_instruction = stake_pair(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            user_key,
+            user_key,
+            source_key,
+            stake_pool_info.reserved_key,
+            paired_source_key,
+            paired_reserve_key,
+            10,
+        )
+        .unwrap();
+        let unstake_pair_instruction = unstake_pair(
+            STAKE_PROGRAM_ID,
+            stake_pool_info.stake_pool_key,
+            stake_user_key,
+            stake_pool_info.authority_key,
+            user_key,
+            stake_pool_info.reserved_key,
+            source_key,
+            paired_reserve_key,
+            paired_source_key,
+            10,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(CustomError::CallerNotAllowed.into()),
+            do_process_instruction(
+                stake_pair_instruction.clone(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&clock),
+                    &mut Account::default(),
+                    &mut paired_source_account,
+                    &mut paired_reserve_account,
+                ],
+            )
+        );
+        let wrapper_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(CustomError::CallerNotAllowed.into()),
+            do_process_instruction(
+                with_instructions_sysvar(stake_pair_instruction),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut stake_user_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut stake_pool_info.reserved_account,
+                    &mut create_account_for_test(&clock),
+                    &mut Account::default(),
+                    &mut paired_source_account,
+                    &mut paired_reserve_account,
+                    &mut instructions_sysvar_account(&wrapper_key),
+                ],
+            )
+        );
+        for (top_level_program_id, result) in [
+            (wrapper_key, Err(CustomError::CallerNotAllowed.into())),
+            (STAKE_PROGRAM_ID, Ok(())),
+        ] {
+            assert_eq!(
+                result,
+                do_process_instruction(
+                    with_instructions_sysvar(unstake_pair_instruction.clone()),
+                    vec![
+                        &mut stake_pool_info.stake_pool_account,
+                        &mut stake_user_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut stake_pool_info.reserved_account,
+                        &mut source_account,
+                        &mut create_account_for_test(&clock),
+                        &mut Account::default(),
+                        &mut paired_reserve_account,
+                        &mut paired_source_account,
+                        &mut instructions_sysvar_account(&top_level_program_id),
+                    ],
+                )
+            );
+        }
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.stake_amount, 20);
     }
 
-    fn mint_minimum_balance() -> u64 {
-        Rent::default().minimum_balance(spl_token::state::Mint::get_packed_len())
-    }
+    #[test]
+    fn test_nft_boost() {
+        let user_key = Pubkey::new_unique();
+        let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
 
-    fn account_minimum_balance() -> u64 {
-        Rent::default().minimum_balance(spl_token::state::Account::get_packed_len())
-    }
+        let collection_key = Pubkey::new_unique();
+        do_process_instruction(
+            set_boost_collection(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                collection_key,
+                2_500,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-    fn do_process_instruction(
-        instruction: Instruction,
-        accounts: Vec<&mut Account>,
-    ) -> ProgramResult {
-        test_syscall_stubs();
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-        let mut account_clones = accounts.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
-        let mut meta = instruction
-            .accounts
-            .iter()
-            .zip(account_clones.iter_mut())
-            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
-            .collect::<Vec<_>>();
-        let mut account_infos = create_is_signer_account_infos(&mut meta);
-        let res = if instruction.program_id == STAKE_PROGRAM_ID {
-            process(&instruction.program_id, &account_infos, &instruction.data)
-        } else {
-            spl_token::processor::Processor::process(
-                &instruction.program_id,
-                &account_infos,
-                &instruction.data,
+        // an NFT of the collection, and its escrow
+        let nft_mint_key = Pubkey::new_unique();
+        let mut nft_mint_account = Account::new(
+            mint_minimum_balance(),
+            spl_token::state::Mint::get_packed_len(),
+            &spl_token::id(),
+        );
+        do_process_instruction(
+            initialize_mint(&spl_token::id(), &nft_mint_key, &user_key, None, 0).unwrap(),
+            vec![
+                &mut nft_mint_account,
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+        let (nft_key, mut nft_account) = create_token_account(
+            &nft_mint_key,
+            &mut nft_mint_account,
+            &user_key,
+            &user_key,
+            1,
+        );
+        let (escrow_authority_key, _) =
+            find_boost_escrow_authority_address(&STAKE_PROGRAM_ID, &stake_user_key);
+        let (escrow_key, _) =
+            find_associated_token_address(&escrow_authority_key, &nft_mint_key, &spl_token::id());
+        let mut escrow_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &spl_token::id(),
+        );
+        do_process_instruction(
+            initialize_account(
+                &spl_token::id(),
+                &escrow_key,
+                &nft_mint_key,
+                &escrow_authority_key,
+            )
+            .unwrap(),
+            vec![
+                &mut escrow_account,
+                &mut nft_mint_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
+        let metadata_account = |collection: Collection| Account {
+            lamports: account_minimum_balance(),
+            data: nft_metadata_account(&nft_mint_key, Some(collection)),
+            owner: TOKEN_METADATA_PROGRAM_ID,
+            ..Account::default()
+        };
+        let register = |metadata_account: &mut Account,
+                        nft_account: &mut Account,
+                        escrow_account: &mut Account,
+                        stake_pool_info: &mut StakePoolInfo,
+                        stake_user_account: &mut Account| {
+            do_process_instruction(
+                register_boost(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    user_key,
+                    nft_mint_key,
+                    nft_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut nft_mint_account.clone(),
+                    metadata_account,
+                    nft_account,
+                    escrow_account,
+                    &mut create_account_for_test(&Clock::default()),
+                    &mut Account::default(),
+                ],
             )
         };
 
-        if res.is_ok() {
-            let mut account_metas = instruction
-                .accounts
-                .iter()
-                .zip(accounts)
-                .map(|(account_meta, account)| (&account_meta.pubkey, account))
-                .collect::<Vec<_>>();
-            for account_info in account_infos.iter_mut() {
-                for account_meta in account_metas.iter_mut() {
-                    if account_info.key == account_meta.0 {
-                        let account = &mut account_meta.1;
-                        account.owner = *account_info.owner;
-                        account.lamports = **account_info.lamports.borrow();
-                        account.data = account_info.data.borrow().to_vec();
-                    }
-                }
-            }
-        }
-        res
-    }
+        // only NFTs verified in the collection
+        assert_eq!(
+            Err(CustomError::InvalidCollection.into()),
+            register(
+                &mut metadata_account(Collection {
+                    verified: false,
+                    key: collection_key,
+                }),
+                &mut nft_account,
+                &mut escrow_account,
+                &mut stake_pool_info,
+                &mut stake_user_account,
+            )
+        );
+        register(
+            &mut metadata_account(Collection {
+                verified: true,
+                key: collection_key,
+            }),
+            &mut nft_account,
+            &mut escrow_account,
+            &mut stake_pool_info,
+            &mut stake_user_account,
+        )
+        .unwrap();
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.boost_mint, nft_mint_key);
+        assert_eq!(stake_user.boost_bps, 2_500);
+        let escrow_token = spl_token::state::Account::unpack(&escrow_account.data).unwrap();
+        assert_eq!(escrow_token.amount, 1);
 
-    fn create_mint(
-        program_id: &Pubkey,
-        authority_key: &Pubkey,
-        freeze_authority: Option<&Pubkey>,
-    ) -> (Pubkey, Account) {
-        let mint_key = Pubkey::new_unique();
-        let mut mint_account = Account::new(
-            mint_minimum_balance(),
-            spl_token::state::Mint::get_packed_len(),
-            program_id,
+        // the escrow isn't the pool authority's for the treasurer to sweep
+        assert_eq!(
+            Err(CustomError::InvalidTokenAccount.into()),
+            do_process_instruction(
+                sweep(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    stake_pool_info.authority_key,
+                    escrow_key,
+                    nft_key,
+                    1,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut escrow_account,
+                    &mut nft_account,
+                    &mut Account::default(),
+                ],
+            )
         );
-        let mut rent_sysvar_account = create_account_for_test(&Rent::free());
 
+        // a day accrues on the boosted stake
+        let clock = Clock {
+            unix_timestamp: DAILY_TS as i64,
+            ..Clock::default()
+        };
         do_process_instruction(
-            initialize_mint(program_id, &mint_key, authority_key, freeze_authority, 2).unwrap(),
-            vec![&mut mint_account, &mut rent_sysvar_account],
+            unregister_boost(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_user_key,
+                user_key,
+                nft_mint_key,
+                nft_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut escrow_account,
+                &mut nft_account,
+                &mut create_account_for_test(&clock),
+                &mut Account::default(),
+            ],
         )
         .unwrap();
-
-        (mint_key, mint_account)
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.reward_owed, 125);
+        assert!(!stake_user.has_boost());
+        let nft_token = spl_token::state::Account::unpack(&nft_account.data).unwrap();
+        assert_eq!(nft_token.amount, 1);
     }
 
     #[test]
-    fn test_initialize() {
+    fn test_staking_badge() {
         let user_key = Pubkey::new_unique();
-        let reward_numerator: u64 = 1;
-        let reward_denominator: u64 = 1_000;
-
         let mut stake_pool_info = StakePoolInfo::new(user_key);
+        stake_pool_info.initialize_stake_pool(1, 1).unwrap();
+        let (stake_user_key, mut stake_user_account) = stake_pool_info.create_stake_user(user_key);
 
-        // reserved token account is already initialized
-        {
-            let old_account = stake_pool_info.reserved_account.clone();
+        let tier = Tier {
+            min_stake: 100,
+            min_duration: DAILY_TS,
+        };
+        assert_eq!(
+            Pool::default().set_tiers([Tier::default(), tier, tier]),
+            Err(CustomError::InvalidTiers.into())
+        );
+        do_process_instruction(
+            set_tiers(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                [tier, Tier::default(), Tier::default()],
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
+        let authority_key = stake_pool_info.authority_key;
+        let create_badge_mint = |non_transferable: bool| {
+            let mint_key = Pubkey::new_unique();
+            let extensions: &[ExtensionType] = if non_transferable {
+                &[ExtensionType::NonTransferable]
+            } else {
+                &[]
+            };
+            let mut mint_account = Account::new(
+                mint_minimum_balance(),
+                ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extensions)
+                    .unwrap(),
+                &spl_token_2022::id(),
+            );
+            if non_transferable {
+                do_process_instruction(
+                    initialize_non_transferable_mint(&spl_token_2022::id(), &mint_key).unwrap(),
+                    vec![&mut mint_account],
+                )
+                .unwrap();
+            }
             do_process_instruction(
-                initialize_account(
-                    &spl_token::id(),
-                    &stake_pool_info.reserved_key,
-                    &stake_pool_info.stake_token_mint_key,
-                    &stake_pool_info.authority_key,
+                spl_token_2022::instruction::initialize_mint(
+                    &spl_token_2022::id(),
+                    &mint_key,
+                    &authority_key,
+                    None,
+                    0,
                 )
                 .unwrap(),
                 vec![
-                    &mut stake_pool_info.reserved_account,
-                    &mut stake_pool_info.stake_token_mint_account,
-                    &mut Account::default(),
+                    &mut mint_account,
                     &mut create_account_for_test(&Rent::free()),
                 ],
             )
             .unwrap();
+            (mint_key, mint_account)
+        };
 
-            assert_eq!(
-                Err(CustomError::TokenInitializeAccountFailed.into()),
-                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
-            );
-
-            stake_pool_info.reserved_account = old_account;
-        }
+        // badges must be soulbound
+        let (transferable_key, mut transferable_account) = create_badge_mint(false);
+        assert_eq!(
+            Err(CustomError::InvalidBadgeMint.into()),
+            do_process_instruction(
+                set_badge_mint(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_pool_info.admin_key,
+                    transferable_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut stake_pool_info.stake_pool_account,
+                    &mut Account::default(),
+                    &mut transferable_account,
+                ],
+            )
+        );
+        let (badge_mint_key, mut badge_mint_account) = create_badge_mint(true);
+        do_process_instruction(
+            set_badge_mint(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                stake_pool_info.admin_key,
+                badge_mint_key,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut Account::default(),
+                &mut badge_mint_account,
+            ],
+        )
+        .unwrap();
 
-        // reward token mint account is already initialized
-        {
-            let old_account = stake_pool_info.reward_mint_account.clone();
+        let badge_token_key = Pubkey::new_unique();
+        let mut badge_token_account = Account::new(
+            account_minimum_balance(),
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+                ExtensionType::ImmutableOwner,
+                ExtensionType::NonTransferableAccount,
+            ])
+            .unwrap(),
+            &spl_token_2022::id(),
+        );
+        do_process_instruction(
+            initialize_immutable_owner(&spl_token_2022::id(), &badge_token_key).unwrap(),
+            vec![&mut badge_token_account],
+        )
+        .unwrap();
+        do_process_instruction(
+            spl_token_2022::instruction::initialize_account(
+                &spl_token_2022::id(),
+                &badge_token_key,
+                &badge_mint_key,
+                &user_key,
+            )
+            .unwrap(),
+            vec![
+                &mut badge_token_account,
+                &mut badge_mint_account,
+                &mut Account::default(),
+                &mut create_account_for_test(&Rent::free()),
+            ],
+        )
+        .unwrap();
 
+        let mut mint_badge_at = |unix_timestamp: i64,
+                                 stake_pool_info: &mut StakePoolInfo,
+                                 stake_user_account: &mut Account| {
             do_process_instruction(
-                initialize_mint(
-                    &spl_token::id(),
-                    &stake_pool_info.reward_mint_key,
-                    &stake_pool_info.authority_key,
-                    None,
-                    9,
+                mint_badge(
+                    STAKE_PROGRAM_ID,
+                    stake_pool_info.stake_pool_key,
+                    stake_user_key,
+                    stake_pool_info.authority_key,
+                    badge_mint_key,
+                    badge_token_key,
                 )
                 .unwrap(),
                 vec![
-                    &mut stake_pool_info.reward_mint_account,
-                    &mut create_account_for_test(&Rent::free()),
+                    &mut stake_pool_info.stake_pool_account,
+                    stake_user_account,
+                    &mut Account::default(),
+                    &mut badge_mint_account,
+                    &mut badge_token_account,
+                    &mut create_account_for_test(&Clock {
+                        unix_timestamp,
+                        ..Clock::default()
+                    }),
+                    &mut Account::default(),
                 ],
             )
             .unwrap();
+            let badge_token = StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+                &badge_token_account.data,
+            )
+            .unwrap();
+            badge_token.base.amount
+        };
 
-            assert_eq!(
-                Err(CustomError::TokenInitializeMintFailed.into()),
-                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
-            );
+        // nothing before the first stake
+        assert_eq!(
+            mint_badge_at(0, &mut stake_pool_info, &mut stake_user_account),
+            0
+        );
 
-            stake_pool_info.reward_mint_account = old_account;
-        }
+        let (source_key, mut source_account) = create_token_account(
+            &stake_pool_info.stake_token_mint_key,
+            &mut stake_pool_info.stake_token_mint_account,
+            &user_key,
+            &user_key,
+            100,
+        );
+        do_process_instruction(
+            stake(
+                STAKE_PROGRAM_ID,
+                stake_pool_info.stake_pool_key,
+                &stake_pool_info.pool(),
+                stake_user_key,
+                user_key,
+                user_key,
+                source_key,
+                stake_pool_info.stake_token_mint_key,
+                100,
+            )
+            .unwrap(),
+            vec![
+                &mut stake_pool_info.stake_pool_account,
+                &mut stake_user_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut source_account,
+                &mut stake_pool_info.reserved_account,
+                &mut create_account_for_test(&Clock::default()),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-        // initialized account correctly
-        {
-            assert_eq!(
-                Ok(()),
-                stake_pool_info.initialize_stake_pool(reward_numerator, reward_denominator)
-            );
-        }
+        // the participation badge, then a token for the tier a day later
+        assert_eq!(
+            mint_badge_at(0, &mut stake_pool_info, &mut stake_user_account),
+            1
+        );
+        assert_eq!(
+            mint_badge_at(1, &mut stake_pool_info, &mut stake_user_account),
+            1
+        );
+        assert_eq!(
+            mint_badge_at(
+                DAILY_TS as i64,
+                &mut stake_pool_info,
+                &mut stake_user_account
+            ),
+            2
+        );
+        assert_eq!(
+            mint_badge_at(
+                DAILY_TS as i64,
+                &mut stake_pool_info,
+                &mut stake_user_account
+            ),
+            2
+        );
+        let stake_user = StakeUser::unpack(&stake_user_account.data).unwrap();
+        assert_eq!(stake_user.badge_amount, 2);
     }
 }
@@ -0,0 +1,67 @@
+//! Serde helpers of state fields, behind the `serde` feature, writing pubkeys
+//! as base58 strings and 128-bit integers as decimal strings, which JSON
+//! numbers can't hold
+
+/// Pubkeys as base58 strings
+pub mod pubkey {
+    use std::str::FromStr;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use solana_program::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        Pubkey::from_str(&encoded).map_err(D::Error::custom)
+    }
+}
+
+/// Arrays of pubkeys as arrays of base58 strings
+pub mod pubkeys {
+    use std::{convert::TryInto, str::FromStr};
+
+    use serde::{de::Error, ser::SerializeTuple, Deserialize, Deserializer, Serializer};
+    use solana_program::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        pubkeys: &[Pubkey; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for pubkey in pubkeys {
+            tuple.serialize_element(&pubkey.to_string())?;
+        }
+        tuple.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[Pubkey; N], D::Error> {
+        let pubkeys = Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|encoded| Pubkey::from_str(encoded).map_err(D::Error::custom))
+            .collect::<Result<Vec<_>, _>>()?;
+        let len = pubkeys.len();
+        pubkeys
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"an array of pubkeys"))
+    }
+}
+
+/// `u128` as a decimal string
+pub mod u128_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,489 @@
+//! Local what-if simulation of a pool, for modeling emission budgets before
+//! launch. The simulator takes the current `Pool` and `StakeUser` states and
+//! replays hypothetical operations at given timestamps through the same state
+//! methods the processor uses, projecting stakes, rewards owed and rewards
+//! paid out after each step. Nothing is read over RPC, so it also builds for
+//! wasm32 frontends.
+//!
+//! Only the primary stake mint is simulated. Claims of oracle-priced pools pay
+//! the amount owed as is, where the program converts it at the oracle price,
+//! and claims paying nothing because the reward budget or supply cap ran out
+//! leave the rewards owed, where the program fails them.
+
+use std::collections::BTreeMap;
+
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError, pubkey::Pubkey};
+use thiserror::Error;
+
+use crate::{
+    error::CustomError,
+    state::{InitStakeUserParams, Pool, StakeUser},
+};
+
+/// Hypothetical operation on the pool
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// Stake an amount of the primary stake token, creating the stake user of
+    /// an owner staking for the first time, like CreateStakeUser and Stake
+    Stake { owner: Pubkey, amount: u64 },
+    /// Unstake an amount, like Unstake
+    Unstake { owner: Pubkey, amount: u64 },
+    /// Claim the rewards owed, like ClaimIfAny
+    Claim { owner: Pubkey },
+    /// Accrue the rewards owed to all stake users, like Refresh
+    Refresh,
+    /// Accrue the rewards owed to the stake users of some owners, like Refresh
+    /// with their accounts, skipping owners without one
+    RefreshUsers { owners: Vec<Pubkey> },
+    /// Change the reward rate from the step on, like SetRewardRate
+    SetRewardRate {
+        reward_numerator: u64,
+        reward_denominator: u64,
+    },
+    /// Top up the reward budget, like AddRewardBudget
+    AddRewardBudget { amount: u64 },
+    /// Start an epoch at the step, bringing in its scheduled rate
+    StartEpoch { epoch: u64 },
+}
+
+/// Operation at a timestamp
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub timestamp: UnixTimestamp,
+    pub operation: Operation,
+}
+
+/// Step the simulation stopped at, with why
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum SimulationError {
+    #[error("step {0} is earlier than the step before")]
+    OutOfOrder(usize),
+    #[error("step {0} failed: {1}")]
+    Failed(usize, ProgramError),
+}
+
+/// Projected state of a stake user
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserProjection {
+    pub owner: Pubkey,
+    pub stake_amount: u64,
+    /// Rewards owed as of the projection, accrued or not
+    pub reward_owed: u64,
+    /// Reward tokens paid out over the lifetime of the stake user
+    pub total_claimed: u64,
+}
+
+/// Projected state of the pool and its stake users at a timestamp
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projection {
+    pub timestamp: UnixTimestamp,
+    pub total_staked: u64,
+    /// Rewards owed to all stake users as of the projection
+    pub reward_owed: u64,
+    /// Reward tokens paid out by the simulated claims
+    pub paid_out: u64,
+    /// Reward tokens burned by the simulated claims
+    pub burned: u64,
+    /// Reward budget left, `None` without a budget
+    pub reward_budget: Option<u64>,
+    /// Stake users by owner
+    pub users: Vec<UserProjection>,
+}
+
+/// Pool and stake users replaying hypothetical operations
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    pub pool_pubkey: Pubkey,
+    pub pool: Pool,
+    /// Stake users by owner
+    pub users: BTreeMap<Pubkey, StakeUser>,
+    /// Current supply of the reward mint, which the maximum reward supply
+    /// caps, raised by the simulated claims of pools minting rewards
+    pub reward_supply: u64,
+    /// Reward tokens paid out by the simulated claims
+    pub paid_out: u64,
+    /// Reward tokens burned by the simulated claims
+    pub burned: u64,
+    timestamp: UnixTimestamp,
+}
+
+impl Simulator {
+    /// Simulator of a pool from its current state and that of its stake users,
+    /// starting at a timestamp
+    pub fn new(
+        pool_pubkey: Pubkey,
+        pool: Pool,
+        users: impl IntoIterator<Item = StakeUser>,
+        timestamp: UnixTimestamp,
+    ) -> Self {
+        Self {
+            pool_pubkey,
+            pool,
+            users: users
+                .into_iter()
+                .map(|stake_user| (stake_user.owner, stake_user))
+                .collect(),
+            reward_supply: 0,
+            paid_out: 0,
+            burned: 0,
+            timestamp,
+        }
+    }
+
+    /// Apply the steps in order, projecting the state after each
+    pub fn run(&mut self, steps: &[Step]) -> Result<Vec<Projection>, SimulationError> {
+        steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                if step.timestamp < self.timestamp {
+                    return Err(SimulationError::OutOfOrder(index));
+                }
+                self.apply(step)
+                    .and_then(|_| self.project(step.timestamp))
+                    .map_err(|error| SimulationError::Failed(index, error))
+            })
+            .collect()
+    }
+
+    /// Apply a single step, returning the reward tokens paid out by claims.
+    /// A failed step may leave the simulator partially updated.
+    pub fn apply(&mut self, step: &Step) -> Result<u64, ProgramError> {
+        let timestamp = step.timestamp;
+        self.timestamp = timestamp;
+        match &step.operation {
+            Operation::Stake { owner, amount } => self.stake(*owner, *amount, timestamp),
+            Operation::Unstake { owner, amount } => self.unstake(*owner, *amount, timestamp),
+            Operation::Claim { owner } => self.claim(*owner, timestamp),
+            Operation::Refresh => {
+                for stake_user in self.users.values_mut() {
+                    if stake_user.has_stake() {
+                        stake_user.update_reward_owed(&self.pool, timestamp)?;
+                    }
+                }
+                Ok(0)
+            }
+            Operation::RefreshUsers { owners } => {
+                for owner in owners {
+                    match self.users.get_mut(owner) {
+                        Some(stake_user) if stake_user.has_stake() => {
+                            stake_user.update_reward_owed(&self.pool, timestamp)?;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(0)
+            }
+            Operation::SetRewardRate {
+                reward_numerator,
+                reward_denominator,
+            } => {
+                self.pool
+                    .checkpoint_rate(timestamp, *reward_numerator, *reward_denominator)?;
+                Ok(0)
+            }
+            Operation::AddRewardBudget { amount } => {
+                self.pool.add_reward_budget(*amount)?;
+                Ok(0)
+            }
+            Operation::StartEpoch { epoch } => {
+                self.pool.observe_epoch(*epoch, timestamp);
+                Ok(0)
+            }
+        }
+    }
+
+    fn stake(
+        &mut self,
+        owner: Pubkey,
+        amount: u64,
+        timestamp: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if self.pool.deprecated {
+            return Err(CustomError::PoolDeprecated.into());
+        }
+        if self.pool.is_pair_pool() {
+            return Err(CustomError::PairedStakeRequired.into());
+        }
+        if !self.users.contains_key(&owner) {
+            let mut stake_user = StakeUser::default();
+            stake_user.init(InitStakeUserParams {
+                pool_pubkey: self.pool_pubkey,
+                owner,
+                current_ts: timestamp,
+            });
+            self.users.insert(owner, stake_user);
+            self.pool.user_count = self.pool.user_count.saturating_add(1);
+        }
+        let stake_user = self.users.get_mut(&owner).unwrap();
+
+        self.pool.observe_stake_seconds(timestamp)?;
+        if stake_user.has_stake() {
+            stake_user.update_reward_owed(&self.pool, timestamp)?;
+        } else {
+            stake_user.begin_stake(&self.pool, timestamp);
+        }
+        stake_user.stake(amount)?;
+        self.pool.stake(amount)?;
+        stake_user.check_stake_cap(&self.pool)?;
+        stake_user.start_warm_up(&self.pool, amount, timestamp)?;
+        Ok(0)
+    }
+
+    fn unstake(
+        &mut self,
+        owner: Pubkey,
+        amount: u64,
+        timestamp: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if self.pool.is_pair_pool() {
+            return Err(CustomError::PairedStakeRequired.into());
+        }
+        let stake_user = self
+            .users
+            .get_mut(&owner)
+            .ok_or(ProgramError::UninitializedAccount)?;
+
+        self.pool.observe_stake_seconds(timestamp)?;
+        if stake_user.has_stake() {
+            stake_user.update_reward_owed(&self.pool, timestamp)?;
+        }
+        stake_user.end_streak(timestamp);
+        stake_user.unstake(amount)?;
+        self.pool.unstake(amount)?;
+        Ok(0)
+    }
+
+    /// Pay out the rewards owed within the reward budget and the maximum
+    /// reward supply, as the processor does
+    fn claim(&mut self, owner: Pubkey, timestamp: UnixTimestamp) -> Result<u64, ProgramError> {
+        let stake_user = self
+            .users
+            .get_mut(&owner)
+            .ok_or(ProgramError::UninitializedAccount)?;
+        if stake_user.has_stake() {
+            stake_user.update_reward_owed(&self.pool, timestamp)?;
+        }
+        if stake_user.reward_owed == 0 {
+            return Ok(0);
+        }
+
+        let owed = stake_user.claim()?;
+        let mintable = if self.pool.is_vault_funded() {
+            owed
+        } else {
+            self.pool.mintable_reward(self.reward_supply, owed)
+        };
+        let budgeted = self.pool.draw_reward_budget(mintable);
+        if budgeted < owed {
+            stake_user.reward_owed = owed - budgeted;
+        }
+        let (amount, burn) = self.pool.split_claim_burn(budgeted)?;
+        stake_user.record_claim(amount, timestamp);
+        if self.pool.is_streamed() && amount > 0 {
+            stake_user.open_stream(&self.pool, amount, timestamp)?;
+        }
+
+        if !self.pool.is_vault_funded() {
+            self.reward_supply = self.reward_supply.saturating_add(amount);
+        }
+        self.paid_out = self
+            .paid_out
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.burned = self
+            .burned
+            .checked_add(burn)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(amount)
+    }
+
+    /// Project the state at a timestamp, with the rewards owed accrued up to
+    /// it without changing the simulator
+    pub fn project(&self, timestamp: UnixTimestamp) -> Result<Projection, ProgramError> {
+        let users = self
+            .users
+            .values()
+            .map(|stake_user| {
+                let reward_owed = if stake_user.has_stake() {
+                    stake_user.pending_reward(&self.pool, timestamp)?
+                } else {
+                    stake_user.reward_owed
+                };
+                Ok(UserProjection {
+                    owner: stake_user.owner,
+                    stake_amount: stake_user.stake_amount,
+                    reward_owed,
+                    total_claimed: stake_user.total_claimed,
+                })
+            })
+            .collect::<Result<Vec<_>, ProgramError>>()?;
+        let reward_owed = users
+            .iter()
+            .try_fold(0u64, |total, user| total.checked_add(user.reward_owed))
+            .ok_or(CustomError::CalculationFailure)?;
+
+        Ok(Projection {
+            timestamp,
+            total_staked: self.pool.total_staked,
+            reward_owed,
+            paid_out: self.paid_out,
+            burned: self.burned,
+            reward_budget: self
+                .pool
+                .has_reward_budget
+                .then_some(self.pool.reward_budget),
+            users,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DAILY_TS;
+
+    const DAY: UnixTimestamp = DAILY_TS as UnixTimestamp;
+
+    fn step(timestamp: UnixTimestamp, operation: Operation) -> Step {
+        Step {
+            timestamp,
+            operation,
+        }
+    }
+
+    fn user(projection: &Projection, owner: &Pubkey) -> (u64, u64, u64) {
+        let user = projection
+            .users
+            .iter()
+            .find(|user| user.owner == *owner)
+            .unwrap();
+        (user.stake_amount, user.reward_owed, user.total_claimed)
+    }
+
+    #[test]
+    fn test_simulator() {
+        let pool = Pool {
+            is_initialized: true,
+            reward_numerator: 1,
+            reward_denominator: 10,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        let mut simulator = Simulator::new(Pubkey::new_unique(), pool, vec![], 0);
+
+        // 100 a day per 1_000 staked
+        let projections = simulator
+            .run(&[
+                step(
+                    0,
+                    Operation::Stake {
+                        owner: alice,
+                        amount: 1_000,
+                    },
+                ),
+                step(
+                    DAY,
+                    Operation::Stake {
+                        owner: bob,
+                        amount: 3_000,
+                    },
+                ),
+                step(2 * DAY, Operation::Refresh),
+            ])
+            .unwrap();
+        assert_eq!(projections.len(), 3);
+        assert_eq!(projections[1].reward_owed, 100);
+        assert_eq!(projections[2].total_staked, 4_000);
+        assert_eq!(projections[2].reward_owed, 500);
+        assert_eq!(user(&projections[2], &alice), (1_000, 200, 0));
+        assert_eq!(user(&projections[2], &bob), (3_000, 300, 0));
+        assert_eq!(simulator.pool.user_count, 2);
+
+        // projections accrue without changing the simulator
+        let later = simulator.project(3 * DAY).unwrap();
+        assert_eq!(later.reward_owed, 900);
+        assert_eq!(simulator.project(2 * DAY).unwrap(), projections[2]);
+
+        // a budget short of what is owed pays out what it covers, the rest
+        // stays owed
+        let projections = simulator
+            .run(&[
+                step(2 * DAY, Operation::AddRewardBudget { amount: 250 }),
+                step(2 * DAY, Operation::Claim { owner: alice }),
+                step(2 * DAY, Operation::Claim { owner: bob }),
+            ])
+            .unwrap();
+        assert_eq!(projections[0].reward_budget, Some(250));
+        assert_eq!(user(&projections[1], &alice), (1_000, 0, 200));
+        assert_eq!(user(&projections[2], &bob), (3_000, 250, 50));
+        assert_eq!(projections[2].paid_out, 250);
+        assert_eq!(projections[2].reward_budget, Some(0));
+        assert_eq!(simulator.reward_supply, 250);
+
+        // an exhausted budget stops accrual until topped up, then the new rate
+        // applies from the rate change on
+        let projections = simulator
+            .run(&[
+                step(3 * DAY, Operation::Refresh),
+                step(3 * DAY, Operation::AddRewardBudget { amount: 10_000 }),
+                step(
+                    3 * DAY,
+                    Operation::SetRewardRate {
+                        reward_numerator: 1,
+                        reward_denominator: 5,
+                    },
+                ),
+                step(
+                    4 * DAY,
+                    Operation::Unstake {
+                        owner: alice,
+                        amount: 1_000,
+                    },
+                ),
+                step(4 * DAY, Operation::Claim { owner: alice }),
+            ])
+            .unwrap();
+        assert_eq!(projections[0].reward_owed, 250);
+        assert_eq!(user(&projections[3], &alice), (0, 200, 200));
+        assert_eq!(user(&projections[3], &bob), (3_000, 850, 50));
+        assert_eq!(user(&projections[4], &alice), (0, 0, 400));
+        assert_eq!(projections[4].total_staked, 3_000);
+        assert_eq!(projections[4].paid_out, 450);
+
+        assert_eq!(
+            simulator.run(&[step(DAY, Operation::Refresh)]),
+            Err(SimulationError::OutOfOrder(0))
+        );
+        assert_eq!(
+            simulator.run(&[
+                step(5 * DAY, Operation::Refresh),
+                step(
+                    5 * DAY,
+                    Operation::Unstake {
+                        owner: alice,
+                        amount: 1
+                    }
+                ),
+            ]),
+            Err(SimulationError::Failed(
+                1,
+                CustomError::InsufficientLiquidity.into()
+            ))
+        );
+        assert_eq!(
+            simulator.run(&[step(
+                5 * DAY,
+                Operation::Claim {
+                    owner: Pubkey::new_unique()
+                }
+            )]),
+            Err(SimulationError::Failed(
+                0,
+                ProgramError::UninitializedAccount
+            ))
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSerialize};
+use shank::ShankAccount;
 use solana_program::{
-    clock::UnixTimestamp,
+    clock::{Clock, UnixTimestamp},
     entrypoint::ProgramResult,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
@@ -9,25 +11,1007 @@ use solana_program::{
 
 use std::convert::TryFrom;
 
-use crate::error::CustomError;
+pub use crate::math::{elapsed, Rounding, BPS_DENOMINATOR};
+use crate::{
+    error::CustomError,
+    math::{self, add_stake_seconds},
+    merkle::{self, Branch, SNAPSHOT_DEPTH},
+};
+
+/// Seed to derive the lamport fee vault of a stake pool
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+
+/// Seed to derive the lamport vault sponsoring the rent of stake users of a
+/// stake pool
+pub const RENT_VAULT_SEED: &[u8] = b"rent_vault";
+
+/// Seed to derive the authority of the vault of a bonus campaign
+pub const BONUS_AUTHORITY_SEED: &[u8] = b"bonus_authority";
+
+/// Seed to derive the authority of the boost NFT escrow of a stake user
+pub const BOOST_ESCROW_SEED: &[u8] = b"boost_escrow";
+
+/// Seed to derive the stake user of an owner created by the program
+pub const STAKE_USER_SEED: &[u8] = b"stake_user";
+
+/// Seed to derive the program-wide config
+pub const GLOBAL_CONFIG_SEED: &[u8] = b"global_config";
+
+/// Seed to derive the distribution report of a stake pool for an epoch
+pub const DISTRIBUTION_REPORT_SEED: &[u8] = b"distribution_report";
+
+/// Seed to derive the claim split of a stake user
+pub const CLAIM_SPLIT_SEED: &[u8] = b"claim_split";
+
+/// Capacity of the rate checkpoint table of a pool
+pub const MAX_RATE_CHECKPOINTS: usize = 8;
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateCheckpoint {
+    /// Solana epoch the rate applies from, 0 for a rate change at `start_ts`
+    pub epoch: u64,
+    /// Timestamp the rate applies from, 0 until a scheduled epoch is observed
+    pub start_ts: UnixTimestamp,
+    /// Reward ratio numerator per reward period
+    pub reward_numerator: u64,
+    /// Reward ratio denominator per reward period, 0 for an empty slot
+    pub reward_denominator: u64,
+}
+
+impl RateCheckpoint {
+    pub fn is_empty(&self) -> bool {
+        self.reward_denominator == 0
+    }
+
+    pub fn is_observed(&self) -> bool {
+        !self.is_empty() && self.start_ts != 0
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.is_empty() && self.start_ts == 0
+    }
+}
+
+const RATE_CHECKPOINT_SIZE: usize = 32; // 8 + 8 + 8 + 8
+const RATE_CHECKPOINTS_SIZE: usize = RATE_CHECKPOINT_SIZE * MAX_RATE_CHECKPOINTS;
+
+/// Capacity of the additional stake mint table of a pool
+pub const MAX_STAKE_MINTS: usize = 4;
+
+/// Stake mint accepted by a multi-mint pool besides the primary stake token
+/// mint, held in its own reserve
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StakeMint {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub mint: Pubkey,
+    /// Reserve token account of the mint, owned by the pool authority
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub reserve: Pubkey,
+    /// Weight of the staked tokens toward reward accrual, in basis points of
+    /// the primary stake token, 0 for an empty slot
+    pub weight_bps: u16,
+    /// Total amount of the mint staked
+    pub total_staked: u64,
+}
+
+impl StakeMint {
+    pub fn is_empty(&self) -> bool {
+        self.weight_bps == 0
+    }
+
+    /// Accruing stake of an amount of the mint
+    pub fn weighted(&self, amount: u64) -> u64 {
+        (amount as u128 * self.weight_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+const STAKE_MINT_SIZE: usize = 74; // 32 + 32 + 2 + 8
+const STAKE_MINTS_SIZE: usize = STAKE_MINT_SIZE * MAX_STAKE_MINTS;
+
+/// Pool flag of oracle-priced pools, owing rewards in a quote currency
+pub const POOL_FLAG_ORACLE_PRICED: u32 = 1 << 0;
+/// Pool flag of pair pools, pairing stakes of two mints in a fixed ratio
+pub const POOL_FLAG_PAIRED: u32 = 1 << 1;
+/// Pool flag of pools boosting stakes backed by NFTs of a collection
+pub const POOL_FLAG_NFT_BOOST: u32 = 1 << 2;
+/// Pool flag of locked pools, whose stakers lock their stake for a bonus
+pub const POOL_FLAG_LOCKED: u32 = 1 << 3;
+/// Pool flags this version of the program handles. Pools with any other flag
+/// are of a newer variant, which the program refuses to load rather than
+/// guess at.
+pub const KNOWN_POOL_FLAGS: u32 =
+    POOL_FLAG_ORACLE_PRICED | POOL_FLAG_PAIRED | POOL_FLAG_NFT_BOOST | POOL_FLAG_LOCKED;
+
+/// Number of integrator programs a top-level-only pool can allow
+pub const MAX_INTEGRATORS: usize = 4;
+
+const INTEGRATORS_SIZE: usize = PUBKEY_BYTES * MAX_INTEGRATORS;
+
+/// Number of tiers a pool can configure
+pub const MAX_TIERS: usize = 3;
+
+/// Tier reached by staking at least `min_stake` of accruing stake for at
+/// least `min_duration` seconds without fully unstaking
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tier {
+    pub min_stake: u64,
+    pub min_duration: u64,
+}
+
+impl Tier {
+    pub fn is_empty(&self) -> bool {
+        self.min_stake == 0 && self.min_duration == 0
+    }
+}
+
+/// Tiers as loyalty levels, by number of tiers reached
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoyaltyTier {
+    None,
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl From<u8> for LoyaltyTier {
+    fn from(tier: u8) -> Self {
+        match tier {
+            0 => Self::None,
+            1 => Self::Bronze,
+            2 => Self::Silver,
+            _ => Self::Gold,
+        }
+    }
+}
+
+/// How claims deliver reward tokens to stakers
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RewardMode {
+    /// Mint fresh reward tokens as the pool authority
+    #[default]
+    MintToUser,
+    /// Transfer reward tokens out of the pre-funded reward vault
+    TransferFromVault,
+}
+
+impl TryFrom<u8> for RewardMode {
+    type Error = ProgramError;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(Self::MintToUser),
+            1 => Ok(Self::TransferFromVault),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Powers of the pool admin it can delegate, each to a key of its own
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Sets the reward rate, the epoch rates and the rate controller
+    RateSetter,
+    /// Deprecates the pool
+    Pauser,
+    /// Sweeps tokens, adds reward budget and withdraws the rent vault
+    Treasurer,
+    /// Sets the hook program and the price oracle
+    Upgrader,
+}
+
+impl TryFrom<u8> for Role {
+    type Error = ProgramError;
+
+    fn try_from(role: u8) -> Result<Self, Self::Error> {
+        match role {
+            0 => Ok(Self::RateSetter),
+            1 => Ok(Self::Pauser),
+            2 => Ok(Self::Treasurer),
+            3 => Ok(Self::Upgrader),
+            _ => Err(ProgramError::InvalidArgument),
+        }
+    }
+}
+
+const TIER_SIZE: usize = 16; // 8 + 8
+const TIERS_SIZE: usize = TIER_SIZE * MAX_TIERS;
+
+/// Number of lock tiers a locked pool can offer
+pub const MAX_LOCK_TIERS: usize = 3;
+
+/// Lock of a stake for `duration` seconds, during which it can't be unstaked
+/// and accrues a reward bonus
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockTier {
+    /// Seconds the stake stays locked, 0 for an empty slot
+    pub duration: u64,
+    /// Reward bonus of the locked stake, in basis points
+    pub bonus_bps: u16,
+}
+
+impl LockTier {
+    pub fn is_empty(&self) -> bool {
+        self.duration == 0
+    }
+}
+
+const LOCK_TIER_SIZE: usize = 10; // 8 + 2
+const LOCK_TIERS_SIZE: usize = LOCK_TIER_SIZE * MAX_LOCK_TIERS;
 
 #[repr(C)]
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pool {
     /// Initialization state
     pub is_initialized: bool,
     /// bump_seed to generate program authority
     pub bump_seed: u8,
     /// spl token mint to be staked
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
     pub stake_token_mint: Pubkey,
     /// Reserved token account
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
     pub reserved: Pubkey,
     /// spl token mint to be minted
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
     pub reward_mint: Pubkey,
-    /// Daily reward ratio numerator
+    /// Reward ratio numerator per reward period
     pub reward_numerator: u64,
-    /// Daily reward ratio denominator
+    /// Reward ratio denominator per reward period
     pub reward_denominator: u64,
+    /// Reward token vault, default pubkey for pools minting rewards
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub reward_vault: Pubkey,
+    /// Admin allowed to manage the pool
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub admin: Pubkey,
+    /// Total amount staked in the pool
+    pub total_staked: u64,
+    /// Reserve tokens owned by the protocol rather than by stakers
+    pub protocol_owned: u64,
+    /// Deprecated pools only allow unstake and claim
+    pub deprecated: bool,
+    /// Successor stake pool of a deprecated pool
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub successor: Pubkey,
+    /// Share of each claim burned, in basis points
+    pub claim_burn_bps: u16,
+    /// Total reward tokens burned on claims
+    pub total_burned: u64,
+    /// Reward period in seconds the reward ratio applies to
+    pub reward_period: u64,
+    /// Rate changes and scheduled epoch rates, ordered by start. The length is
+    /// spelled out for the IDL and must stay `MAX_RATE_CHECKPOINTS`
+    pub rate_checkpoints: [RateCheckpoint; 8],
+    /// Program called after stakes, unstakes and claims, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub hook_program: Pubkey,
+    /// Price account converting rewards owed in a quote currency, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub price_oracle: Pubkey,
+    /// Maximum age in seconds of the oracle price
+    pub max_price_age: u64,
+    /// Maximum oracle price confidence interval, in basis points of the price
+    pub max_price_conf_bps: u16,
+    /// Stake mints besides the primary stake token mint, for multi-mint pools,
+    /// `MAX_STAKE_MINTS` long
+    pub stake_mints: [StakeMint; 4],
+    /// Paired tokens of the first additional stake mint per primary stake token, for pair pools
+    pub pair_numerator: u64,
+    /// Pair ratio denominator, zero if not a pair pool
+    pub pair_denominator: u64,
+    /// Verified collection whose NFTs boost rewards, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub boost_collection: Pubkey,
+    /// Reward boost of registered collection NFTs, in basis points
+    pub boost_bps: u16,
+    /// Token-2022 non-transferable badge mint, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub badge_mint: Pubkey,
+    /// Tiers stakers reach, ordered with empty tiers last, `MAX_TIERS` long
+    pub tiers: [Tier; 3],
+    /// Seconds without unstaking after which the streak bonus applies, 0 if none
+    pub streak_duration: u64,
+    /// Reward bonus of stakers on a streak, in basis points
+    pub streak_bonus_bps: u16,
+    /// Total stake multiplied by the seconds it was staked, as of stake_seconds_ts
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::u128_string"))]
+    pub cumulative_stake_seconds: u128,
+    /// Last time the cumulative stake-seconds advanced
+    pub stake_seconds_ts: UnixTimestamp,
+    /// Number of stake users created and not closed
+    pub user_count: u64,
+    /// Stake snapshot being built, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub snapshot: Pubkey,
+    /// Timestamp of the last stake snapshot
+    pub snapshot_ts: UnixTimestamp,
+    /// Stake and Unstake only run as top-level instructions or from integrators
+    pub top_level_only: bool,
+    /// Programs allowed to call Stake and Unstake of top-level-only pools,
+    /// default for empty slots, `MAX_INTEGRATORS` long
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkeys"))]
+    pub integrators: [Pubkey; 4],
+    /// Whether claims are capped by the reward budget
+    pub has_reward_budget: bool,
+    /// Reward tokens left to pay out or burn on claims, with a reward budget
+    pub reward_budget: u64,
+    /// Cap on the reward mint supply minted claims may reach, zero if none
+    pub max_reward_supply: u64,
+    /// How claims deliver reward tokens
+    pub reward_mode: RewardMode,
+    /// Variant of the pool, as `POOL_FLAG_*` bits
+    pub pool_flags: u32,
+    /// Seconds over which claimed rewards stream to stakers, 0 to pay claims
+    /// out at once
+    pub stream_duration: u64,
+    /// Seconds new deposits accrue at the warm-up rate before the full rate,
+    /// 0 if none
+    pub warm_up_duration: u64,
+    /// Rate of stake warming up, in basis points of the full rate
+    pub warm_up_rate_bps: u16,
+    /// Primary stake the rate controller steers the rate toward, 0 if none
+    pub target_tvl: u64,
+    /// Lowest reward numerator the rate controller sets
+    pub min_reward_numerator: u64,
+    /// Highest reward numerator the rate controller sets
+    pub max_reward_numerator: u64,
+    /// Largest change of the reward numerator per rebalance, in basis points
+    pub max_rate_step_bps: u16,
+    /// Time of the last rebalance of the rate
+    pub last_rebalance_ts: UnixTimestamp,
+    /// Cap on the weighted stake of each stake user, zero if none
+    pub max_stake_per_user: u64,
+    /// Seconds after which anyone can close empty stake users, zero if never
+    pub gc_delay: u64,
+    /// Key setting the reward rate and its controller besides the admin, the
+    /// default pubkey if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub rate_setter: Pubkey,
+    /// Key deprecating the pool besides the admin, the default pubkey if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub pauser: Pubkey,
+    /// Key moving the funds of the pool besides the admin, the default pubkey
+    /// if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub treasurer: Pubkey,
+    /// Key setting the programs and accounts the pool integrates besides the
+    /// admin, the default pubkey if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub upgrader: Pubkey,
+    /// Decimals of the primary stake token mint
+    pub stake_decimals: u8,
+    /// Decimals of the reward token mint
+    pub reward_decimals: u8,
+    /// Rounding of the final division of reward accrual
+    pub rounding: Rounding,
+    /// Total reward tokens paid out on claims, streamed ones once claimed
+    /// into their stream, burned shares left out
+    pub total_rewards_paid: u64,
+    /// Whether a distribution report of the pool was finalized
+    pub has_distribution_report: bool,
+    /// Epoch of the last distribution report of the pool
+    pub last_report_epoch: u64,
+    /// Locks stakers of a locked pool choose from, ordered by duration with
+    /// empty tiers last, `MAX_LOCK_TIERS` long
+    pub lock_tiers: [LockTier; 3],
+}
+
+impl Pool {
+    /// Key a role is delegated to, the default pubkey if only the admin holds
+    /// it
+    pub fn role_holder(&self, role: Role) -> Pubkey {
+        match role {
+            Role::RateSetter => self.rate_setter,
+            Role::Pauser => self.pauser,
+            Role::Treasurer => self.treasurer,
+            Role::Upgrader => self.upgrader,
+        }
+    }
+
+    /// Delegate a role to a key, the default pubkey to take it back
+    pub fn set_role_holder(&mut self, role: Role, holder: Pubkey) {
+        match role {
+            Role::RateSetter => self.rate_setter = holder,
+            Role::Pauser => self.pauser = holder,
+            Role::Treasurer => self.treasurer = holder,
+            Role::Upgrader => self.upgrader = holder,
+        }
+    }
+
+    /// Whether a key may exercise a role, which the admin always may
+    pub fn has_role(&self, role: Role, key: &Pubkey) -> bool {
+        *key == self.admin || (*key != Pubkey::default() && *key == self.role_holder(role))
+    }
+
+    /// Byte offsets of fields indexers filter pools by
+    pub const STAKE_TOKEN_MINT_OFFSET: usize = 2;
+    pub const RESERVED_OFFSET: usize = 34;
+    pub const REWARD_MINT_OFFSET: usize = 66;
+    pub const ADMIN_OFFSET: usize = 146;
+    pub const HOOK_PROGRAM_OFFSET: usize = 501;
+    pub const TOP_LEVEL_ONLY_OFFSET: usize = 1083;
+
+    /// Hook program of a packed pool, read without unpacking the whole pool
+    pub fn unpack_hook_program(src: &[u8]) -> Result<Option<Pubkey>, ProgramError> {
+        check_layout(src, POOL_SIZE, LEGACY_POOL_SIZE)?;
+        let hook_program =
+            Pubkey::new_from_array(*array_ref![src, Self::HOOK_PROGRAM_OFFSET, PUBKEY_BYTES]);
+        Ok((hook_program != Pubkey::default()).then_some(hook_program))
+    }
+
+    /// Bump seed of the authority of a packed pool, read without unpacking
+    /// the whole pool
+    pub fn unpack_bump_seed(src: &[u8]) -> Result<u8, ProgramError> {
+        check_layout(src, POOL_SIZE, LEGACY_POOL_SIZE)?;
+        Ok(src[1])
+    }
+
+    /// Whether a packed pool is top-level-only, read without unpacking the
+    /// whole pool
+    pub fn unpack_top_level_only(src: &[u8]) -> Result<bool, ProgramError> {
+        check_layout(src, POOL_SIZE, LEGACY_POOL_SIZE)?;
+        unpack_bool(array_ref![src, Self::TOP_LEVEL_ONLY_OFFSET, 1])
+    }
+
+    /// Find the authority of a pool, owning its token accounts and minting
+    /// rewards, with its bump seed
+    pub fn authority(program_id: &Pubkey, pool_pubkey: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[pool_pubkey.as_ref()], program_id)
+    }
+
+    /// Signer seeds of the authority of a pool
+    pub fn authority_seeds<'a>(pool_pubkey: &'a Pubkey, bump_seed: &'a u8) -> [&'a [u8]; 2] {
+        [pool_pubkey.as_ref(), std::slice::from_ref(bump_seed)]
+    }
+
+    /// Authority of this pool, derived with its bump seed
+    pub fn authority_address(
+        &self,
+        program_id: &Pubkey,
+        pool_pubkey: &Pubkey,
+    ) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &Self::authority_seeds(pool_pubkey, &self.bump_seed),
+            program_id,
+        )?)
+    }
+
+    /// Whether the reward period is one of the supported units
+    pub fn is_valid_reward_period(reward_period: u64) -> bool {
+        matches!(reward_period, HOURLY_TS | DAILY_TS | WEEKLY_TS)
+    }
+
+    /// Whether rewards are bucketed by Solana epoch
+    pub fn is_epoch_based(&self) -> bool {
+        self.rate_checkpoints
+            .iter()
+            .any(|rate| !rate.is_empty() && rate.epoch != 0)
+    }
+
+    /// Reward ratio currently applying
+    pub fn current_rate(&self) -> (u64, u64) {
+        self.rate_checkpoints
+            .iter()
+            .rev()
+            .find(|rate| rate.is_observed())
+            .map_or((self.reward_numerator, self.reward_denominator), |rate| {
+                (rate.reward_numerator, rate.reward_denominator)
+            })
+    }
+
+    /// Free a checkpoint slot when the table is full, folding the oldest
+    /// observed checkpoint into the base rate. Users who haven't accrued since
+    /// before that checkpoint get its rate for the older period too.
+    fn evict_oldest_checkpoint(&mut self) -> ProgramResult {
+        if self.rate_checkpoints.iter().all(|rate| !rate.is_empty()) {
+            let oldest = self.rate_checkpoints[0];
+            if !oldest.is_observed() {
+                return Err(CustomError::RateCheckpointsFull.into());
+            }
+            self.reward_numerator = oldest.reward_numerator;
+            self.reward_denominator = oldest.reward_denominator;
+            self.rate_checkpoints.rotate_left(1);
+            self.rate_checkpoints[MAX_RATE_CHECKPOINTS - 1] = RateCheckpoint::default();
+        }
+        Ok(())
+    }
+
+    /// Change the reward rate from `current_ts` on, keeping the old rate for
+    /// the period before so accrual stays correct for users not refreshed
+    pub fn checkpoint_rate(
+        &mut self,
+        current_ts: UnixTimestamp,
+        reward_numerator: u64,
+        reward_denominator: u64,
+    ) -> ProgramResult {
+        if reward_denominator == 0 {
+            return Err(CustomError::InvalidRewardRate.into());
+        }
+        if let Some(rate) = self
+            .rate_checkpoints
+            .iter_mut()
+            .rev()
+            .find(|rate| rate.is_observed())
+            .filter(|rate| rate.start_ts >= current_ts)
+        {
+            rate.reward_numerator = reward_numerator;
+            rate.reward_denominator = reward_denominator;
+            return Ok(());
+        }
+
+        self.evict_oldest_checkpoint()?;
+        let index = self
+            .rate_checkpoints
+            .iter()
+            .position(|rate| !rate.is_observed())
+            .ok_or(CustomError::RateCheckpointsFull)?;
+        self.rate_checkpoints[index..].rotate_right(1);
+        self.rate_checkpoints[index] = RateCheckpoint {
+            epoch: 0,
+            start_ts: current_ts,
+            reward_numerator,
+            reward_denominator,
+        };
+        Ok(())
+    }
+
+    /// Schedule the reward rate of an upcoming epoch
+    pub fn set_epoch_rate(
+        &mut self,
+        epoch: u64,
+        reward_numerator: u64,
+        reward_denominator: u64,
+    ) -> ProgramResult {
+        if reward_denominator == 0 {
+            return Err(CustomError::InvalidRewardRate.into());
+        }
+        if let Some(rate) = self
+            .rate_checkpoints
+            .iter_mut()
+            .find(|rate| rate.is_pending() && rate.epoch == epoch)
+        {
+            rate.reward_numerator = reward_numerator;
+            rate.reward_denominator = reward_denominator;
+            return Ok(());
+        }
+
+        self.evict_oldest_checkpoint()?;
+        let index = self
+            .rate_checkpoints
+            .iter()
+            .position(|rate| rate.is_empty() || (rate.is_pending() && rate.epoch > epoch))
+            .ok_or(CustomError::RateCheckpointsFull)?;
+        self.rate_checkpoints[index..].rotate_right(1);
+        self.rate_checkpoints[index] = RateCheckpoint {
+            epoch,
+            start_ts: 0,
+            reward_numerator,
+            reward_denominator,
+        };
+        Ok(())
+    }
+
+    /// Record the start timestamp of the current epoch. Scheduled epochs that
+    /// passed unobserved take effect from the current epoch start.
+    pub fn observe_epoch(&mut self, epoch: u64, epoch_start_ts: UnixTimestamp) {
+        for rate in self.rate_checkpoints.iter_mut() {
+            if rate.is_pending() && rate.epoch <= epoch {
+                rate.start_ts = epoch_start_ts;
+            }
+        }
+    }
+
+    /// Reward accrued by `stake_amount` between two timestamps, integrated
+    /// piecewise over the observed rate checkpoints. Non-positive periods, and
+    /// any period once the reward budget is exhausted, accrue nothing.
+    pub fn accrue(
+        &self,
+        stake_amount: u64,
+        from_ts: UnixTimestamp,
+        to_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if self.is_reward_budget_exhausted() {
+            return Ok(0);
+        }
+        let mut reward: u64 = 0;
+        let mut segment_start = from_ts;
+        let mut numerator = self.reward_numerator;
+        let mut denominator = self.reward_denominator;
+
+        for rate in self
+            .rate_checkpoints
+            .iter()
+            .filter(|rate| rate.is_observed())
+        {
+            if rate.start_ts >= to_ts {
+                break;
+            }
+            if rate.start_ts > segment_start {
+                reward = self
+                    .accrue_segment(
+                        stake_amount,
+                        numerator,
+                        denominator,
+                        elapsed(segment_start, rate.start_ts),
+                    )?
+                    .checked_add(reward)
+                    .ok_or(CustomError::CalculationFailure)?;
+                segment_start = rate.start_ts;
+            }
+            numerator = rate.reward_numerator;
+            denominator = rate.reward_denominator;
+        }
+
+        if to_ts > segment_start {
+            reward = self
+                .accrue_segment(
+                    stake_amount,
+                    numerator,
+                    denominator,
+                    elapsed(segment_start, to_ts),
+                )?
+                .checked_add(reward)
+                .ok_or(CustomError::CalculationFailure)?;
+        }
+        Ok(reward)
+    }
+
+    fn accrue_segment(
+        &self,
+        stake_amount: u64,
+        numerator: u64,
+        denominator: u64,
+        period: u64,
+    ) -> Result<u64, ProgramError> {
+        Ok(math::accrue_rounded(
+            stake_amount,
+            numerator,
+            denominator,
+            period,
+            self.reward_period,
+            self.rounding,
+        )?)
+    }
+
+    /// Whether rewards are paid out of a pre-funded vault instead of minted
+    pub fn is_vault_funded(&self) -> bool {
+        self.reward_mode == RewardMode::TransferFromVault
+    }
+
+    /// Whether claims stream rewards over the stream duration instead of
+    /// paying them out at once
+    pub fn is_streamed(&self) -> bool {
+        self.stream_duration != 0
+    }
+
+    /// Whether the rate controller steers the rate toward a target TVL
+    pub fn has_rate_controller(&self) -> bool {
+        self.target_tvl != 0
+    }
+
+    /// Whether the rate controller may rebalance at a time, once per reward
+    /// period
+    pub fn is_rebalance_due(&self, current_ts: UnixTimestamp) -> bool {
+        self.has_rate_controller()
+            && elapsed(self.last_rebalance_ts, current_ts) >= self.reward_period
+    }
+
+    /// Reward numerator the rate controller moves the current rate to. Scaling
+    /// the rate by the target over the total staked keeps emissions where they
+    /// would be at the target; each rebalance moves by at most the maximum
+    /// step, within the bounds.
+    pub fn rebalanced_numerator(&self) -> u64 {
+        let (numerator, _) = self.current_rate();
+        let target = match self.total_staked {
+            0 => u64::MAX,
+            total_staked => {
+                u64::try_from(numerator as u128 * self.target_tvl as u128 / total_staked as u128)
+                    .unwrap_or(u64::MAX)
+            }
+        };
+        let step =
+            (numerator as u128 * self.max_rate_step_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        target
+            .clamp(
+                numerator.saturating_sub(step),
+                numerator.saturating_add(step),
+            )
+            .clamp(self.min_reward_numerator, self.max_reward_numerator)
+    }
+
+    /// Whether rewards are owed in a quote currency and converted to reward
+    /// tokens at the oracle price when claimed
+    pub fn is_oracle_priced(&self) -> bool {
+        self.has_flags(POOL_FLAG_ORACLE_PRICED)
+    }
+
+    /// Whether the pool has all of the `POOL_FLAG_*` flags
+    pub fn has_flags(&self, flags: u32) -> bool {
+        self.pool_flags & flags == flags
+    }
+
+    /// Set or clear `POOL_FLAG_*` flags
+    pub fn set_flags(&mut self, flags: u32, enabled: bool) {
+        if enabled {
+            self.pool_flags |= flags;
+        } else {
+            self.pool_flags &= !flags;
+        }
+    }
+
+    /// Reserve holding the staked tokens of a mint, `None` if the pool
+    /// doesn't accept the mint
+    pub fn reserve_of(&self, mint: &Pubkey) -> Option<Pubkey> {
+        if *mint == self.stake_token_mint {
+            return Some(self.reserved);
+        }
+        self.stake_mints
+            .iter()
+            .find(|stake_mint| !stake_mint.is_empty() && stake_mint.mint == *mint)
+            .map(|stake_mint| stake_mint.reserve)
+    }
+
+    /// Slot of the additional stake mint held in `reserve`, `None` for the
+    /// primary reserve or unknown accounts
+    pub fn stake_mint_index(&self, reserve: &Pubkey) -> Option<usize> {
+        self.stake_mints
+            .iter()
+            .position(|stake_mint| !stake_mint.is_empty() && stake_mint.reserve == *reserve)
+    }
+
+    /// Accept another stake mint, weighted toward accrual. Weights are fixed
+    /// once added, as accrual isn't checkpointed per mint.
+    pub fn add_stake_mint(
+        &mut self,
+        mint: Pubkey,
+        reserve: Pubkey,
+        weight_bps: u16,
+    ) -> ProgramResult {
+        if weight_bps == 0 {
+            return Err(CustomError::InvalidBasisPoints.into());
+        }
+        if mint == self.stake_token_mint
+            || self
+                .stake_mints
+                .iter()
+                .any(|stake_mint| !stake_mint.is_empty() && stake_mint.mint == mint)
+        {
+            return Err(CustomError::InvalidTokenMint.into());
+        }
+        let stake_mint = self
+            .stake_mints
+            .iter_mut()
+            .find(|stake_mint| stake_mint.is_empty())
+            .ok_or(CustomError::StakeMintsFull)?;
+        *stake_mint = StakeMint {
+            mint,
+            reserve,
+            weight_bps,
+            total_staked: 0,
+        };
+        Ok(())
+    }
+
+    /// Cumulative stake-seconds of the primary stake token at `current_ts`.
+    /// The time-weighted average stake between two readings is their
+    /// difference divided by the seconds between them.
+    pub fn stake_seconds_at(&self, current_ts: UnixTimestamp) -> Result<u128, ProgramError> {
+        Ok(add_stake_seconds(
+            self.cumulative_stake_seconds,
+            self.total_staked,
+            self.stake_seconds_ts,
+            current_ts,
+        )?)
+    }
+
+    /// Advance the cumulative stake-seconds, before the total stake changes
+    pub fn observe_stake_seconds(&mut self, current_ts: UnixTimestamp) -> ProgramResult {
+        self.cumulative_stake_seconds = self.stake_seconds_at(current_ts)?;
+        self.stake_seconds_ts = self.stake_seconds_ts.max(current_ts);
+        Ok(())
+    }
+
+    /// Whether stakes pair the primary stake token with the first additional
+    /// stake mint in a fixed ratio
+    pub fn is_pair_pool(&self) -> bool {
+        self.has_flags(POOL_FLAG_PAIRED)
+    }
+
+    /// Pair the primary stake token with the first additional stake mint.
+    /// The ratio is fixed once set, and only set before anything is staked.
+    pub fn set_pair_ratio(&mut self, numerator: u64, denominator: u64) -> ProgramResult {
+        if numerator == 0 || denominator == 0 || self.is_pair_pool() {
+            return Err(CustomError::InvalidPairRatio.into());
+        }
+        if self.stake_mints[0].is_empty() {
+            return Err(CustomError::InvalidTokenMint.into());
+        }
+        if self.total_staked != 0
+            || self
+                .stake_mints
+                .iter()
+                .any(|stake_mint| stake_mint.total_staked != 0)
+        {
+            return Err(CustomError::InvalidPairRatio.into());
+        }
+        self.pair_numerator = numerator;
+        self.pair_denominator = denominator;
+        self.set_flags(POOL_FLAG_PAIRED, true);
+        Ok(())
+    }
+
+    /// Paired tokens required alongside an amount of the primary stake token,
+    /// rounding up
+    pub fn paired_amount(&self, amount: u64) -> Result<u64, ProgramError> {
+        if !self.is_pair_pool() {
+            return Err(CustomError::PairedStakeRequired.into());
+        }
+        Ok(math::mul_div_ceil(
+            amount,
+            self.pair_numerator,
+            self.pair_denominator,
+        )?)
+    }
+
+    /// Whether a program may call Stake and Unstake of a top-level-only pool
+    pub fn is_integrator(&self, program_id: &Pubkey) -> bool {
+        *program_id != Pubkey::default() && self.integrators.contains(program_id)
+    }
+
+    /// Replace the tiers, checked to be ordered with empty tiers last
+    pub fn set_tiers(&mut self, tiers: [Tier; MAX_TIERS]) -> ProgramResult {
+        for pair in tiers.windows(2) {
+            let (lower, upper) = (pair[0], pair[1]);
+            if upper.is_empty() {
+                continue;
+            }
+            if lower.is_empty()
+                || upper.min_stake < lower.min_stake
+                || upper.min_duration < lower.min_duration
+            {
+                return Err(CustomError::InvalidTiers.into());
+            }
+        }
+        self.tiers = tiers;
+        Ok(())
+    }
+
+    /// Whether stakers can lock their stake for a bonus
+    pub fn is_locked(&self) -> bool {
+        self.has_flags(POOL_FLAG_LOCKED)
+    }
+
+    /// Replace the lock tiers, checked to be ordered by duration with empty
+    /// tiers last and longer locks earning no lower bonus. Pools offering a
+    /// lock are locked pools.
+    pub fn set_lock_tiers(&mut self, lock_tiers: [LockTier; MAX_LOCK_TIERS]) -> ProgramResult {
+        for pair in lock_tiers.windows(2) {
+            let (shorter, longer) = (pair[0], pair[1]);
+            if longer.is_empty() {
+                continue;
+            }
+            if shorter.is_empty()
+                || longer.duration <= shorter.duration
+                || longer.bonus_bps < shorter.bonus_bps
+            {
+                return Err(CustomError::InvalidLockTiers.into());
+            }
+        }
+        if lock_tiers
+            .iter()
+            .any(|lock_tier| u64::from(lock_tier.bonus_bps) > BPS_DENOMINATOR)
+        {
+            return Err(CustomError::InvalidBasisPoints.into());
+        }
+        self.lock_tiers = lock_tiers;
+        self.set_flags(POOL_FLAG_LOCKED, !lock_tiers[0].is_empty());
+        Ok(())
+    }
+
+    /// Number of tiers reached by an accruing stake held for `held_for` seconds
+    pub fn tier(&self, stake: u64, held_for: u64) -> u8 {
+        self.tiers
+            .iter()
+            .take_while(|tier| {
+                !tier.is_empty() && stake >= tier.min_stake && held_for >= tier.min_duration
+            })
+            .count() as u8
+    }
+
+    pub fn stake(&mut self, amount: u64) -> ProgramResult {
+        self.total_staked = self
+            .total_staked
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    pub fn unstake(&mut self, amount: u64) -> ProgramResult {
+        self.total_staked = self
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Stake an amount of the additional stake mint in `index`
+    pub fn stake_mint(&mut self, index: usize, amount: u64) -> ProgramResult {
+        let stake_mint = &mut self.stake_mints[index];
+        stake_mint.total_staked = stake_mint
+            .total_staked
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    pub fn unstake_mint(&mut self, index: usize, amount: u64) -> ProgramResult {
+        let stake_mint = &mut self.stake_mints[index];
+        stake_mint.total_staked = stake_mint
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Whether claims used up the reward budget, stopping accrual until the
+    /// admin tops it up
+    pub fn is_reward_budget_exhausted(&self) -> bool {
+        self.has_reward_budget && self.reward_budget == 0
+    }
+
+    /// Top up the reward budget, capping claims from then on
+    pub fn add_reward_budget(&mut self, amount: u64) -> ProgramResult {
+        self.has_reward_budget = true;
+        self.reward_budget = self
+            .reward_budget
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Take up to an amount of reward tokens out of the reward budget, all
+    /// of it without a budget
+    pub fn draw_reward_budget(&mut self, amount: u64) -> u64 {
+        if !self.has_reward_budget {
+            return amount;
+        }
+        let drawn = amount.min(self.reward_budget);
+        self.reward_budget -= drawn;
+        drawn
+    }
+
+    /// Up to an amount of reward tokens minting keeps within the maximum
+    /// reward supply, given the current supply of the reward mint
+    pub fn mintable_reward(&self, supply: u64, amount: u64) -> u64 {
+        if self.max_reward_supply == 0 {
+            return amount;
+        }
+        amount.min(self.max_reward_supply.saturating_sub(supply))
+    }
+
+    /// Split a claimed amount into the part paid out and the part burned,
+    /// counting both toward the totals of the pool
+    pub fn split_claim_burn(&mut self, amount: u64) -> Result<(u64, u64), ProgramError> {
+        let (payout, burn) = math::split_bps(amount, self.claim_burn_bps as u64)?;
+        self.total_burned = self
+            .total_burned
+            .checked_add(burn)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.total_rewards_paid = self.total_rewards_paid.saturating_add(payout);
+        Ok((payout, burn))
+    }
 }
 
 impl Sealed for Pool {}
@@ -37,11 +1021,19 @@ impl IsInitialized for Pool {
     }
 }
 
-const POOL_SIZE: usize = 114; // 1 + 1 + 32 + 32 + 32 + 8 + 8
+/// Size of the pools of the first release, see `check_layout`
+pub const LEGACY_POOL_SIZE: usize = 114;
+
+const POOL_SIZE: usize = 1480; // 1 + 1 + 32 + 32 + 32 + 8 + 8 + 32 + 32 + 8 + 8 + 1 + 32 + 2 + 8 + 8 + 256 + 32 + 32 + 8 + 2 + 296 + 8 + 8 + 32 + 2 + 32 + 48 + 8 + 2 + 16 + 8 + 8 + 32 + 8 + 1 + 128 + 1 + 8 + 8 + 1 + 4 + 8 + 8 + 2 + 8 + 8 + 8 + 2 + 8 + 8 + 8 + 32 + 32 + 32 + 32 + 1 + 1 + 1 + 8 + 1 + 8 + 30
 
 impl Pack for Pool {
     const LEN: usize = POOL_SIZE;
 
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        check_layout(input, POOL_SIZE, LEGACY_POOL_SIZE)?;
+        Self::unpack_from_slice(input)
+    }
+
     fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
         let input = array_ref![src, 0, POOL_SIZE];
         #[allow(clippy::ptr_offset_with_cast)]
@@ -53,7 +1045,133 @@ impl Pack for Pool {
             reward_mint,
             reward_numerator,
             reward_denominator,
-        ) = array_refs![input, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8];
+            reward_vault,
+            admin,
+            total_staked,
+            protocol_owned,
+            deprecated,
+            successor,
+            claim_burn_bps,
+            total_burned,
+            reward_period,
+            rate_checkpoints,
+            hook_program,
+            price_oracle,
+            max_price_age,
+            max_price_conf_bps,
+            stake_mints,
+            pair_numerator,
+            pair_denominator,
+            boost_collection,
+            boost_bps,
+            badge_mint,
+            tiers,
+            streak_duration,
+            streak_bonus_bps,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            user_count,
+            snapshot,
+            snapshot_ts,
+            top_level_only,
+            integrators,
+            has_reward_budget,
+            reward_budget,
+            max_reward_supply,
+            reward_mode,
+            pool_flags,
+            stream_duration,
+            warm_up_duration,
+            warm_up_rate_bps,
+            target_tvl,
+            min_reward_numerator,
+            max_reward_numerator,
+            max_rate_step_bps,
+            last_rebalance_ts,
+            max_stake_per_user,
+            gc_delay,
+            rate_setter,
+            pauser,
+            treasurer,
+            upgrader,
+            stake_decimals,
+            reward_decimals,
+            rounding,
+            total_rewards_paid,
+            has_distribution_report,
+            last_report_epoch,
+            lock_tiers,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            1,
+            PUBKEY_BYTES,
+            2,
+            8,
+            8,
+            RATE_CHECKPOINTS_SIZE,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            2,
+            STAKE_MINTS_SIZE,
+            8,
+            8,
+            PUBKEY_BYTES,
+            2,
+            PUBKEY_BYTES,
+            TIERS_SIZE,
+            8,
+            2,
+            16,
+            8,
+            8,
+            PUBKEY_BYTES,
+            8,
+            1,
+            INTEGRATORS_SIZE,
+            1,
+            8,
+            8,
+            1,
+            4,
+            8,
+            8,
+            2,
+            8,
+            8,
+            8,
+            2,
+            8,
+            8,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            1,
+            8,
+            1,
+            8,
+            LOCK_TIERS_SIZE
+        ];
+
+        let pool_flags = u32::from_le_bytes(*pool_flags);
+        if pool_flags & !KNOWN_POOL_FLAGS != 0 {
+            return Err(CustomError::UnsupportedPoolType.into());
+        }
 
         Ok(Self {
             is_initialized: unpack_bool(is_initialized)?,
@@ -63,6 +1181,62 @@ impl Pack for Pool {
             reward_mint: Pubkey::new_from_array(*reward_mint),
             reward_numerator: u64::from_le_bytes(*reward_numerator),
             reward_denominator: u64::from_le_bytes(*reward_denominator),
+            reward_vault: Pubkey::new_from_array(*reward_vault),
+            admin: Pubkey::new_from_array(*admin),
+            total_staked: u64::from_le_bytes(*total_staked),
+            protocol_owned: u64::from_le_bytes(*protocol_owned),
+            deprecated: unpack_bool(deprecated)?,
+            successor: Pubkey::new_from_array(*successor),
+            claim_burn_bps: u16::from_le_bytes(*claim_burn_bps),
+            total_burned: u64::from_le_bytes(*total_burned),
+            reward_period: u64::from_le_bytes(*reward_period),
+            rate_checkpoints: unpack_rate_checkpoints(rate_checkpoints),
+            hook_program: Pubkey::new_from_array(*hook_program),
+            price_oracle: Pubkey::new_from_array(*price_oracle),
+            max_price_age: u64::from_le_bytes(*max_price_age),
+            max_price_conf_bps: u16::from_le_bytes(*max_price_conf_bps),
+            stake_mints: unpack_stake_mints(stake_mints),
+            pair_numerator: u64::from_le_bytes(*pair_numerator),
+            pair_denominator: u64::from_le_bytes(*pair_denominator),
+            boost_collection: Pubkey::new_from_array(*boost_collection),
+            boost_bps: u16::from_le_bytes(*boost_bps),
+            badge_mint: Pubkey::new_from_array(*badge_mint),
+            tiers: unpack_tiers(tiers),
+            streak_duration: u64::from_le_bytes(*streak_duration),
+            streak_bonus_bps: u16::from_le_bytes(*streak_bonus_bps),
+            cumulative_stake_seconds: u128::from_le_bytes(*cumulative_stake_seconds),
+            stake_seconds_ts: i64::from_le_bytes(*stake_seconds_ts),
+            user_count: u64::from_le_bytes(*user_count),
+            snapshot: Pubkey::new_from_array(*snapshot),
+            snapshot_ts: i64::from_le_bytes(*snapshot_ts),
+            top_level_only: unpack_bool(top_level_only)?,
+            integrators: unpack_integrators(integrators),
+            has_reward_budget: unpack_bool(has_reward_budget)?,
+            reward_budget: u64::from_le_bytes(*reward_budget),
+            max_reward_supply: u64::from_le_bytes(*max_reward_supply),
+            reward_mode: RewardMode::try_from(reward_mode[0])?,
+            pool_flags,
+            stream_duration: u64::from_le_bytes(*stream_duration),
+            warm_up_duration: u64::from_le_bytes(*warm_up_duration),
+            warm_up_rate_bps: u16::from_le_bytes(*warm_up_rate_bps),
+            target_tvl: u64::from_le_bytes(*target_tvl),
+            min_reward_numerator: u64::from_le_bytes(*min_reward_numerator),
+            max_reward_numerator: u64::from_le_bytes(*max_reward_numerator),
+            max_rate_step_bps: u16::from_le_bytes(*max_rate_step_bps),
+            last_rebalance_ts: i64::from_le_bytes(*last_rebalance_ts),
+            max_stake_per_user: u64::from_le_bytes(*max_stake_per_user),
+            gc_delay: u64::from_le_bytes(*gc_delay),
+            rate_setter: Pubkey::new_from_array(*rate_setter),
+            pauser: Pubkey::new_from_array(*pauser),
+            treasurer: Pubkey::new_from_array(*treasurer),
+            upgrader: Pubkey::new_from_array(*upgrader),
+            stake_decimals: u8::from_le_bytes(*stake_decimals),
+            reward_decimals: u8::from_le_bytes(*reward_decimals),
+            rounding: Rounding::from_u8(rounding[0]).ok_or(ProgramError::InvalidAccountData)?,
+            total_rewards_paid: u64::from_le_bytes(*total_rewards_paid),
+            has_distribution_report: unpack_bool(has_distribution_report)?,
+            last_report_epoch: u64::from_le_bytes(*last_report_epoch),
+            lock_tiers: unpack_lock_tiers(lock_tiers),
         })
     }
 
@@ -77,7 +1251,128 @@ impl Pack for Pool {
             reward_mint,
             reward_numerator,
             reward_denominator,
-        ) = mut_array_refs![output, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8];
+            reward_vault,
+            admin,
+            total_staked,
+            protocol_owned,
+            deprecated,
+            successor,
+            claim_burn_bps,
+            total_burned,
+            reward_period,
+            rate_checkpoints,
+            hook_program,
+            price_oracle,
+            max_price_age,
+            max_price_conf_bps,
+            stake_mints,
+            pair_numerator,
+            pair_denominator,
+            boost_collection,
+            boost_bps,
+            badge_mint,
+            tiers,
+            streak_duration,
+            streak_bonus_bps,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            user_count,
+            snapshot,
+            snapshot_ts,
+            top_level_only,
+            integrators,
+            has_reward_budget,
+            reward_budget,
+            max_reward_supply,
+            reward_mode,
+            pool_flags,
+            stream_duration,
+            warm_up_duration,
+            warm_up_rate_bps,
+            target_tvl,
+            min_reward_numerator,
+            max_reward_numerator,
+            max_rate_step_bps,
+            last_rebalance_ts,
+            max_stake_per_user,
+            gc_delay,
+            rate_setter,
+            pauser,
+            treasurer,
+            upgrader,
+            stake_decimals,
+            reward_decimals,
+            rounding,
+            total_rewards_paid,
+            has_distribution_report,
+            last_report_epoch,
+            lock_tiers,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            1,
+            PUBKEY_BYTES,
+            2,
+            8,
+            8,
+            RATE_CHECKPOINTS_SIZE,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            2,
+            STAKE_MINTS_SIZE,
+            8,
+            8,
+            PUBKEY_BYTES,
+            2,
+            PUBKEY_BYTES,
+            TIERS_SIZE,
+            8,
+            2,
+            16,
+            8,
+            8,
+            PUBKEY_BYTES,
+            8,
+            1,
+            INTEGRATORS_SIZE,
+            1,
+            8,
+            8,
+            1,
+            4,
+            8,
+            8,
+            2,
+            8,
+            8,
+            8,
+            2,
+            8,
+            8,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            1,
+            8,
+            1,
+            8,
+            LOCK_TIERS_SIZE
+        ];
 
         pack_bool(self.is_initialized, is_initialized);
         *bump_seed = self.bump_seed.to_le_bytes();
@@ -86,17 +1381,77 @@ impl Pack for Pool {
         reward_mint.copy_from_slice(self.reward_mint.as_ref());
         *reward_numerator = self.reward_numerator.to_le_bytes();
         *reward_denominator = self.reward_denominator.to_le_bytes();
+        reward_vault.copy_from_slice(self.reward_vault.as_ref());
+        admin.copy_from_slice(self.admin.as_ref());
+        *total_staked = self.total_staked.to_le_bytes();
+        *protocol_owned = self.protocol_owned.to_le_bytes();
+        pack_bool(self.deprecated, deprecated);
+        successor.copy_from_slice(self.successor.as_ref());
+        *claim_burn_bps = self.claim_burn_bps.to_le_bytes();
+        *total_burned = self.total_burned.to_le_bytes();
+        *reward_period = self.reward_period.to_le_bytes();
+        pack_rate_checkpoints(&self.rate_checkpoints, rate_checkpoints);
+        hook_program.copy_from_slice(self.hook_program.as_ref());
+        price_oracle.copy_from_slice(self.price_oracle.as_ref());
+        *max_price_age = self.max_price_age.to_le_bytes();
+        *max_price_conf_bps = self.max_price_conf_bps.to_le_bytes();
+        pack_stake_mints(&self.stake_mints, stake_mints);
+        *pair_numerator = self.pair_numerator.to_le_bytes();
+        *pair_denominator = self.pair_denominator.to_le_bytes();
+        boost_collection.copy_from_slice(self.boost_collection.as_ref());
+        *boost_bps = self.boost_bps.to_le_bytes();
+        badge_mint.copy_from_slice(self.badge_mint.as_ref());
+        pack_tiers(&self.tiers, tiers);
+        *streak_duration = self.streak_duration.to_le_bytes();
+        *streak_bonus_bps = self.streak_bonus_bps.to_le_bytes();
+        *cumulative_stake_seconds = self.cumulative_stake_seconds.to_le_bytes();
+        *stake_seconds_ts = self.stake_seconds_ts.to_le_bytes();
+        *user_count = self.user_count.to_le_bytes();
+        snapshot.copy_from_slice(self.snapshot.as_ref());
+        *snapshot_ts = self.snapshot_ts.to_le_bytes();
+        pack_bool(self.top_level_only, top_level_only);
+        pack_integrators(&self.integrators, integrators);
+        pack_bool(self.has_reward_budget, has_reward_budget);
+        *reward_budget = self.reward_budget.to_le_bytes();
+        *max_reward_supply = self.max_reward_supply.to_le_bytes();
+        reward_mode[0] = self.reward_mode as u8;
+        *pool_flags = self.pool_flags.to_le_bytes();
+        *stream_duration = self.stream_duration.to_le_bytes();
+        *warm_up_duration = self.warm_up_duration.to_le_bytes();
+        *warm_up_rate_bps = self.warm_up_rate_bps.to_le_bytes();
+        *target_tvl = self.target_tvl.to_le_bytes();
+        *min_reward_numerator = self.min_reward_numerator.to_le_bytes();
+        *max_reward_numerator = self.max_reward_numerator.to_le_bytes();
+        *max_rate_step_bps = self.max_rate_step_bps.to_le_bytes();
+        *last_rebalance_ts = self.last_rebalance_ts.to_le_bytes();
+        *max_stake_per_user = self.max_stake_per_user.to_le_bytes();
+        *gc_delay = self.gc_delay.to_le_bytes();
+        rate_setter.copy_from_slice(self.rate_setter.as_ref());
+        pauser.copy_from_slice(self.pauser.as_ref());
+        treasurer.copy_from_slice(self.treasurer.as_ref());
+        upgrader.copy_from_slice(self.upgrader.as_ref());
+        *stake_decimals = self.stake_decimals.to_le_bytes();
+        *reward_decimals = self.reward_decimals.to_le_bytes();
+        rounding[0] = self.rounding as u8;
+        *total_rewards_paid = self.total_rewards_paid.to_le_bytes();
+        pack_bool(self.has_distribution_report, has_distribution_report);
+        *last_report_epoch = self.last_report_epoch.to_le_bytes();
+        pack_lock_tiers(&self.lock_tiers, lock_tiers);
     }
 }
 
 #[repr(C)]
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StakeUser {
     /// Initialization state
     pub is_initialized: bool,
-    /// Owner pubkey related to user's wallet
+    /// Owner pubkey related to user's wallet, or a program derived address
+    /// of a protocol staking through CPI
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
     pub owner: Pubkey,
     /// Stake Pool pubkey
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
     pub pool_pubkey: Pubkey,
     /// Amount staked
     pub stake_amount: u64,
@@ -104,6 +1459,65 @@ pub struct StakeUser {
     pub reward_owed: u64,
     /// Last update timestamp
     pub last_update: UnixTimestamp,
+    /// Amounts staked of the additional stake mints of the pool, by slot,
+    /// `MAX_STAKE_MINTS` long
+    pub mint_balances: [u64; 4],
+    /// NFT escrowed for a reward boost, default if none
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub boost_mint: Pubkey,
+    /// Reward boost of the escrowed NFT, in basis points, fixed when registered
+    pub boost_bps: u16,
+    /// Timestamp staking started at, since the last full unstake
+    pub staked_since: UnixTimestamp,
+    /// Badge tokens minted, the participation badge and one per tier reached
+    pub badge_amount: u8,
+    /// Tier reached as of the last accrual, see `LoyaltyTier`
+    pub tier: u8,
+    /// Timestamp of the first stake or last unstake, starting the current streak
+    pub streak_start: UnixTimestamp,
+    /// Longest streak in seconds, as of the last accrual
+    pub longest_streak: u64,
+    /// Stake multiplied by the seconds it was staked, as of stake_seconds_ts
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::u128_string"))]
+    pub cumulative_stake_seconds: u128,
+    /// Last time the cumulative stake-seconds advanced
+    pub stake_seconds_ts: UnixTimestamp,
+    /// Reward tokens paid out, or streamed, over the lifetime of the stake
+    /// user, after burns
+    pub total_claimed: u64,
+    /// Time of the last claim paying out rewards
+    pub last_claim_ts: UnixTimestamp,
+    /// Primary stake tokens staked over the lifetime of the stake user
+    pub total_deposited: u64,
+    /// Primary stake tokens unstaked over the lifetime of the stake user
+    pub total_withdrawn: u64,
+    /// Stake held at the pool's snapshot timestamp, saved when it first changed after it
+    pub snapshot_stake: u64,
+    /// Claimed rewards of a streamed pool not withdrawn yet
+    pub stream_balance: u64,
+    /// Share of the stream balance still locked at stream_start, released
+    /// linearly until stream_end
+    pub stream_locked: u64,
+    /// Time the stream was last topped up
+    pub stream_start: UnixTimestamp,
+    /// Time the stream is fully released
+    pub stream_end: UnixTimestamp,
+    /// Accruing stake deposited within the pool's warm-up duration, before
+    /// the NFT boost
+    pub warming_stake: u64,
+    /// Time the warming stake starts accruing at the full rate
+    pub warm_up_end: UnixTimestamp,
+    /// Whether the rent vault of the pool paid the rent, which goes back to
+    /// it on close
+    pub rent_sponsored: bool,
+    /// Whether the stake user has a claim split, which its claims then pay
+    /// and its close closes
+    pub claim_split: bool,
+    /// Time the stake unlocks, see `Relock`
+    pub locked_until: UnixTimestamp,
+    /// Reward bonus of the stake until it unlocks, in basis points, fixed
+    /// when locked
+    pub lock_bonus_bps: u16,
 }
 
 impl Sealed for StakeUser {}
@@ -113,32 +1527,193 @@ impl IsInitialized for StakeUser {
     }
 }
 
-const STAKE_USER_SIZE: usize = 89; // 1 + 32 + 32 + 8 + 8 + 8
+/// Size of the stake users of the first release, see `check_layout`
+pub const LEGACY_STAKE_USER_SIZE: usize = 89;
+
+const STAKE_USER_SIZE: usize = 305; // 1 + 32 + 32 + 8 + 8 + 8 + 32 + 32 + 2 + 8 + 1 + 1 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 8 + 2
 
 impl Pack for StakeUser {
     const LEN: usize = STAKE_USER_SIZE;
 
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        check_layout(input, STAKE_USER_SIZE, LEGACY_STAKE_USER_SIZE)?;
+        Self::unpack_from_slice(input)
+    }
+
     fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
         let input = array_ref![src, 0, STAKE_USER_SIZE];
         #[allow(clippy::ptr_offset_with_cast)]
-        let (is_initialized, owner, pool_pubkey, stake_amount, reward_owed, last_update) =
-            array_refs![input, 1, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8, 8];
-
-        Ok(Self {
-            is_initialized: unpack_bool(is_initialized)?,
-            owner: Pubkey::new_from_array(*owner),
-            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
+        let (
+            is_initialized,
+            owner,
+            pool_pubkey,
+            stake_amount,
+            reward_owed,
+            last_update,
+            mint_balances,
+            boost_mint,
+            boost_bps,
+            staked_since,
+            badge_amount,
+            tier,
+            streak_start,
+            longest_streak,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            total_claimed,
+            last_claim_ts,
+            total_deposited,
+            total_withdrawn,
+            snapshot_stake,
+            stream_balance,
+            stream_locked,
+            stream_start,
+            stream_end,
+            warming_stake,
+            warm_up_end,
+            rent_sponsored,
+            claim_split,
+            locked_until,
+            lock_bonus_bps,
+        ) = array_refs![
+            input,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            8,
+            8 * MAX_STAKE_MINTS,
+            PUBKEY_BYTES,
+            2,
+            8,
+            1,
+            1,
+            8,
+            8,
+            16,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            1,
+            1,
+            8,
+            2
+        ];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            owner: Pubkey::new_from_array(*owner),
+            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
             stake_amount: u64::from_le_bytes(*stake_amount),
             reward_owed: u64::from_le_bytes(*reward_owed),
             last_update: i64::from_le_bytes(*last_update),
+            mint_balances: unpack_mint_balances(mint_balances),
+            boost_mint: Pubkey::new_from_array(*boost_mint),
+            boost_bps: u16::from_le_bytes(*boost_bps),
+            staked_since: i64::from_le_bytes(*staked_since),
+            badge_amount: u8::from_le_bytes(*badge_amount),
+            tier: u8::from_le_bytes(*tier),
+            streak_start: i64::from_le_bytes(*streak_start),
+            longest_streak: u64::from_le_bytes(*longest_streak),
+            cumulative_stake_seconds: u128::from_le_bytes(*cumulative_stake_seconds),
+            stake_seconds_ts: i64::from_le_bytes(*stake_seconds_ts),
+            total_claimed: u64::from_le_bytes(*total_claimed),
+            last_claim_ts: i64::from_le_bytes(*last_claim_ts),
+            total_deposited: u64::from_le_bytes(*total_deposited),
+            total_withdrawn: u64::from_le_bytes(*total_withdrawn),
+            snapshot_stake: u64::from_le_bytes(*snapshot_stake),
+            stream_balance: u64::from_le_bytes(*stream_balance),
+            stream_locked: u64::from_le_bytes(*stream_locked),
+            stream_start: i64::from_le_bytes(*stream_start),
+            stream_end: i64::from_le_bytes(*stream_end),
+            warming_stake: u64::from_le_bytes(*warming_stake),
+            warm_up_end: i64::from_le_bytes(*warm_up_end),
+            rent_sponsored: unpack_bool(rent_sponsored)?,
+            claim_split: unpack_bool(claim_split)?,
+            locked_until: i64::from_le_bytes(*locked_until),
+            lock_bonus_bps: u16::from_le_bytes(*lock_bonus_bps),
         })
     }
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let output = array_mut_ref![dst, 0, STAKE_USER_SIZE];
         #[allow(clippy::ptr_offset_with_cast)]
-        let (is_initialized, owner, pool_pubkey, stake_amount, reward_owed, last_update) =
-            mut_array_refs![output, 1, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8, 8];
+        let (
+            is_initialized,
+            owner,
+            pool_pubkey,
+            stake_amount,
+            reward_owed,
+            last_update,
+            mint_balances,
+            boost_mint,
+            boost_bps,
+            staked_since,
+            badge_amount,
+            tier,
+            streak_start,
+            longest_streak,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            total_claimed,
+            last_claim_ts,
+            total_deposited,
+            total_withdrawn,
+            snapshot_stake,
+            stream_balance,
+            stream_locked,
+            stream_start,
+            stream_end,
+            warming_stake,
+            warm_up_end,
+            rent_sponsored,
+            claim_split,
+            locked_until,
+            lock_bonus_bps,
+        ) = mut_array_refs![
+            output,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            8,
+            8,
+            8 * MAX_STAKE_MINTS,
+            PUBKEY_BYTES,
+            2,
+            8,
+            1,
+            1,
+            8,
+            8,
+            16,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            8,
+            1,
+            1,
+            8,
+            2
+        ];
 
         pack_bool(self.is_initialized, is_initialized);
         owner.copy_from_slice(self.owner.as_ref());
@@ -146,76 +1721,1601 @@ impl Pack for StakeUser {
         *stake_amount = self.stake_amount.to_le_bytes();
         *reward_owed = self.reward_owed.to_le_bytes();
         *last_update = self.last_update.to_le_bytes();
+        for (balance, dst) in self
+            .mint_balances
+            .iter()
+            .zip(mint_balances.chunks_exact_mut(8))
+        {
+            dst.copy_from_slice(&balance.to_le_bytes());
+        }
+        boost_mint.copy_from_slice(self.boost_mint.as_ref());
+        *boost_bps = self.boost_bps.to_le_bytes();
+        *staked_since = self.staked_since.to_le_bytes();
+        *badge_amount = self.badge_amount.to_le_bytes();
+        *tier = self.tier.to_le_bytes();
+        *streak_start = self.streak_start.to_le_bytes();
+        *longest_streak = self.longest_streak.to_le_bytes();
+        *cumulative_stake_seconds = self.cumulative_stake_seconds.to_le_bytes();
+        *stake_seconds_ts = self.stake_seconds_ts.to_le_bytes();
+        *total_claimed = self.total_claimed.to_le_bytes();
+        *last_claim_ts = self.last_claim_ts.to_le_bytes();
+        *total_deposited = self.total_deposited.to_le_bytes();
+        *total_withdrawn = self.total_withdrawn.to_le_bytes();
+        *snapshot_stake = self.snapshot_stake.to_le_bytes();
+        *stream_balance = self.stream_balance.to_le_bytes();
+        *stream_locked = self.stream_locked.to_le_bytes();
+        *stream_start = self.stream_start.to_le_bytes();
+        *stream_end = self.stream_end.to_le_bytes();
+        *warming_stake = self.warming_stake.to_le_bytes();
+        *warm_up_end = self.warm_up_end.to_le_bytes();
+        pack_bool(self.rent_sponsored, rent_sponsored);
+        pack_bool(self.claim_split, claim_split);
+        *locked_until = self.locked_until.to_le_bytes();
+        *lock_bonus_bps = self.lock_bonus_bps.to_le_bytes();
+    }
+}
+
+pub const HOURLY_TS: u64 = 3_600;
+pub const DAILY_TS: u64 = 86_400;
+pub const WEEKLY_TS: u64 = 604_800;
+
+pub struct InitStakeUserParams {
+    pub pool_pubkey: Pubkey,
+    pub owner: Pubkey,
+    /// Creation time, the last update until the first stake
+    pub current_ts: UnixTimestamp,
+}
+
+impl StakeUser {
+    /// Byte offsets of fields indexers filter stake users by
+    pub const OWNER_OFFSET: usize = 1;
+    pub const POOL_PUBKEY_OFFSET: usize = 33;
+    pub const TIER_OFFSET: usize = 164;
+
+    /// Owner of a packed stake user, read without unpacking the whole stake
+    /// user
+    pub fn unpack_owner(src: &[u8]) -> Result<Pubkey, ProgramError> {
+        Self::unpack_pubkey_at(src, Self::OWNER_OFFSET)
+    }
+
+    /// Pool of a packed stake user, read without unpacking the whole stake
+    /// user
+    pub fn unpack_pool_pubkey(src: &[u8]) -> Result<Pubkey, ProgramError> {
+        Self::unpack_pubkey_at(src, Self::POOL_PUBKEY_OFFSET)
+    }
+
+    /// Pubkey field of a packed stake user, failing like `unpack` on
+    /// uninitialized stake users
+    fn unpack_pubkey_at(src: &[u8], offset: usize) -> Result<Pubkey, ProgramError> {
+        check_layout(src, STAKE_USER_SIZE, LEGACY_STAKE_USER_SIZE)?;
+        if !unpack_bool(array_ref![src, 0, 1])? {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(Pubkey::new_from_array(*array_ref![
+            src,
+            offset,
+            PUBKEY_BYTES
+        ]))
+    }
+
+    /// Find the stake user of an owner in a pool the program creates with
+    /// CreateStakeUserIdempotent
+    pub fn address(program_id: &Pubkey, pool_pubkey: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[pool_pubkey.as_ref(), owner.as_ref(), STAKE_USER_SEED],
+            program_id,
+        )
+    }
+
+    pub fn init(&mut self, params: InitStakeUserParams) {
+        self.is_initialized = true;
+        self.pool_pubkey = params.pool_pubkey;
+        self.owner = params.owner;
+        self.last_update = params.current_ts;
+    }
+
+    /// Whether nothing is staked, owed, escrowed or streamed, so the stake
+    /// user can be closed
+    pub fn is_empty(&self) -> bool {
+        !self.has_stake() && self.reward_owed == 0 && !self.has_boost() && self.stream_balance == 0
+    }
+
+    /// Whether an empty stake user went untouched for the pool's collection
+    /// delay at a time, since its last update or claim
+    pub fn is_stale(&self, pool: &Pool, current_ts: UnixTimestamp) -> bool {
+        pool.gc_delay != 0
+            && self.is_empty()
+            && elapsed(self.last_update.max(self.last_claim_ts), current_ts) >= pool.gc_delay
+    }
+
+    /// Whether anything is staked, of any stake mint
+    pub fn has_stake(&self) -> bool {
+        self.stake_amount != 0 || self.mint_balances.iter().any(|&balance| balance != 0)
+    }
+
+    /// Cumulative stake-seconds of the primary stake token at `current_ts`
+    pub fn stake_seconds_at(&self, current_ts: UnixTimestamp) -> Result<u128, ProgramError> {
+        Ok(add_stake_seconds(
+            self.cumulative_stake_seconds,
+            self.stake_amount,
+            self.stake_seconds_ts,
+            current_ts,
+        )?)
+    }
+
+    /// Advance the cumulative stake-seconds, before the stake changes
+    pub fn observe_stake_seconds(&mut self, current_ts: UnixTimestamp) -> ProgramResult {
+        self.cumulative_stake_seconds = self.stake_seconds_at(current_ts)?;
+        self.stake_seconds_ts = self.stake_seconds_ts.max(current_ts);
+        Ok(())
+    }
+
+    /// Save the stake held at the pool's snapshot timestamp before it first
+    /// changes after it
+    fn save_snapshot_stake(&mut self, pool: &Pool, current_ts: UnixTimestamp) {
+        if self.stake_seconds_ts <= pool.snapshot_ts && pool.snapshot_ts < current_ts {
+            self.snapshot_stake = self.stake_amount;
+        }
+    }
+
+    /// Stake of the primary stake token held at a snapshot timestamp, the
+    /// last one of the pool
+    pub fn stake_at_snapshot(&self, snapshot_ts: UnixTimestamp) -> u64 {
+        if self.stake_seconds_ts <= snapshot_ts {
+            self.stake_amount
+        } else {
+            self.snapshot_stake
+        }
+    }
+
+    /// Start accruing, on a first stake or when staking again after a full
+    /// unstake
+    pub fn begin_stake(&mut self, pool: &Pool, current_ts: UnixTimestamp) {
+        self.save_snapshot_stake(pool, current_ts);
+        self.last_update = current_ts;
+        self.staked_since = current_ts;
+        self.streak_start = current_ts;
+        self.stake_seconds_ts = current_ts;
+        self.warming_stake = 0;
+    }
+
+    /// Tier of the pool reached at `current_ts`, none without a stake
+    pub fn reached_tier(&self, pool: &Pool, current_ts: UnixTimestamp) -> Result<u8, ProgramError> {
+        if !self.has_stake() {
+            return Ok(0);
+        }
+        Ok(pool.tier(
+            self.accruing_stake(pool)?,
+            elapsed(self.staked_since, current_ts),
+        ))
+    }
+
+    /// Whether an NFT is escrowed for a reward boost
+    pub fn has_boost(&self) -> bool {
+        self.boost_mint != Pubkey::default()
+    }
+
+    /// Stake accruing rewards, the primary stake plus the weighted stakes of
+    /// the additional stake mints, boosted by the escrowed NFT if any
+    pub fn accruing_stake(&self, pool: &Pool) -> Result<u64, ProgramError> {
+        self.boosted(self.weighted_stake(pool)?)
+    }
+
+    /// The primary stake plus the weighted stakes of the additional stake
+    /// mints, before the NFT boost
+    fn weighted_stake(&self, pool: &Pool) -> Result<u64, ProgramError> {
+        Ok(self
+            .mint_balances
+            .iter()
+            .zip(pool.stake_mints.iter())
+            .try_fold(self.stake_amount, |total, (&balance, stake_mint)| {
+                total.checked_add(stake_mint.weighted(balance))
+            })
+            .ok_or(CustomError::CalculationFailure)?)
+    }
+
+    /// Check the weighted stake against the pool's maximum stake per user
+    pub fn check_stake_cap(&self, pool: &Pool) -> ProgramResult {
+        if pool.max_stake_per_user != 0 && self.weighted_stake(pool)? > pool.max_stake_per_user {
+            return Err(CustomError::StakeCapExceeded.into());
+        }
+        Ok(())
+    }
+
+    fn boosted(&self, stake: u64) -> Result<u64, ProgramError> {
+        if !self.has_boost() {
+            return Ok(stake);
+        }
+        Ok(math::bps_of(
+            stake,
+            BPS_DENOMINATOR + self.boost_bps as u64,
+        )?)
+    }
+
+    /// Warm a deposit of weighted stake up over the pool's warm-up duration.
+    /// The deposit joins the stake still warming up, which then warms up
+    /// until the end times of both averaged by stake, so each deposit keeps
+    /// its share of time at the warm-up rate. Call after staking the deposit.
+    pub fn start_warm_up(
+        &mut self,
+        pool: &Pool,
+        stake: u64,
+        current_ts: UnixTimestamp,
+    ) -> ProgramResult {
+        if pool.warm_up_duration == 0 {
+            return Ok(());
+        }
+        self.add_warming_stake(pool, stake, pool.warm_up_duration, current_ts)
+    }
+
+    /// Add weighted stake, already staked, warming up for `duration` more
+    /// seconds
+    fn add_warming_stake(
+        &mut self,
+        pool: &Pool,
+        stake: u64,
+        duration: u64,
+        current_ts: UnixTimestamp,
+    ) -> ProgramResult {
+        if stake == 0 {
+            return Ok(());
+        }
+        let warming = self
+            .warming_stake(pool, current_ts)?
+            .min(self.weighted_stake(pool)?.saturating_sub(stake));
+        let total = warming
+            .checked_add(stake)
+            .ok_or(CustomError::CalculationFailure)?;
+        let duration = (warming as u128 * elapsed(current_ts, self.warm_up_end) as u128
+            + stake as u128 * duration as u128)
+            / total as u128;
+        self.warming_stake = total;
+        self.warm_up_end = i64::try_from(duration)
+            .ok()
+            .and_then(|duration| current_ts.checked_add(duration))
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Weighted stake still warming up at a time. Unstakes take the stake
+    /// that warmed up first.
+    pub fn warming_stake(
+        &self,
+        pool: &Pool,
+        current_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if current_ts >= self.warm_up_end {
+            return Ok(0);
+        }
+        Ok(self.warming_stake.min(self.weighted_stake(pool)?))
+    }
+
+    /// Rewards the warming stake forgoes between the last update and
+    /// `current_ts` for accruing at the warm-up rate
+    fn warm_up_discount(
+        &self,
+        pool: &Pool,
+        current_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        let warming = self.warming_stake(pool, self.last_update)?;
+        let to_ts = current_ts.min(self.warm_up_end);
+        if warming == 0 || elapsed(self.last_update, to_ts) == 0 {
+            return Ok(0);
+        }
+        let forgone = math::bps_of(
+            warming,
+            BPS_DENOMINATOR.saturating_sub(pool.warm_up_rate_bps as u64),
+        )?;
+        pool.accrue(self.boosted(forgone)?, self.last_update, to_ts)
+    }
+
+    pub fn stake(&mut self, amount: u64) -> ProgramResult {
+        self.stake_amount = self
+            .stake_amount
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.total_deposited = self.total_deposited.saturating_add(amount);
+        Ok(())
+    }
+
+    pub fn unstake(&mut self, amount: u64) -> ProgramResult {
+        if amount > self.stake_amount {
+            return Err(CustomError::InsufficientLiquidity.into());
+        }
+        self.stake_amount = self
+            .stake_amount
+            .checked_sub(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.total_withdrawn = self.total_withdrawn.saturating_add(amount);
+        self.clear_tier_if_unstaked();
+        Ok(())
+    }
+
+    /// Stake an amount of the additional stake mint in `index` of the pool
+    pub fn stake_mint(&mut self, index: usize, amount: u64) -> ProgramResult {
+        self.mint_balances[index] = self.mint_balances[index]
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    pub fn unstake_mint(&mut self, index: usize, amount: u64) -> ProgramResult {
+        self.mint_balances[index] = self.mint_balances[index]
+            .checked_sub(amount)
+            .ok_or(CustomError::InsufficientLiquidity)?;
+        self.clear_tier_if_unstaked();
+        Ok(())
+    }
+
+    /// A full unstake starts over from no tier
+    fn clear_tier_if_unstaked(&mut self) {
+        if !self.has_stake() {
+            self.tier = 0;
+        }
+    }
+
+    /// Paired tokens released alongside an amount of the primary stake, in
+    /// proportion to the stake so both legs empty together
+    pub fn paired_share(&self, amount: u64) -> Result<u64, ProgramError> {
+        if amount > self.stake_amount {
+            return Err(CustomError::InsufficientLiquidity.into());
+        }
+        if amount == self.stake_amount {
+            return Ok(self.mint_balances[0]);
+        }
+        Ok(math::mul_div(
+            self.mint_balances[0],
+            amount,
+            self.stake_amount,
+        )?)
+    }
+
+    /// Rewards owed as of `current_ts` without accruing them, with the formula
+    /// the program accrues by, for clients showing live reward counters
+    pub fn pending_reward(
+        &self,
+        pool: &Pool,
+        current_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        let mut stake_user = self.clone();
+        stake_user.update_reward_owed(pool, current_ts)?;
+        Ok(stake_user.reward_owed)
+    }
+
+    /// Accrue rewards up to `current_ts`. A `last_update` in the future, e.g.
+    /// after the clock went backwards, accrues nothing until the clock catches up.
+    pub fn update_reward_owed(&mut self, pool: &Pool, current_ts: UnixTimestamp) -> ProgramResult {
+        if elapsed(self.last_update, current_ts) > 0 {
+            let stake = self.accruing_stake(pool)?;
+            let lock_bonus = self.lock_bonus(pool, stake, current_ts)?;
+            self.reward_owed = pool
+                .accrue(stake, self.last_update, current_ts)?
+                .saturating_sub(self.warm_up_discount(pool, current_ts)?)
+                .checked_add(self.streak_bonus(pool, stake, current_ts)?)
+                .and_then(|reward| reward.checked_add(lock_bonus))
+                .and_then(|reward| reward.checked_add(self.reward_owed))
+                .ok_or(CustomError::CalculationFailure)?;
+
+            self.last_update = current_ts;
+            self.warming_stake = self.warming_stake(pool, current_ts)?;
+        }
+        self.save_snapshot_stake(pool, current_ts);
+        self.observe_stake_seconds(current_ts)?;
+        self.tier = self.reached_tier(pool, current_ts)?;
+        self.longest_streak = self
+            .longest_streak
+            .max(elapsed(self.streak_start, current_ts));
+        Ok(())
+    }
+
+    /// Bonus on the rewards accrued since the last update once the streak
+    /// lasted the pool's streak duration
+    fn streak_bonus(
+        &self,
+        pool: &Pool,
+        stake: u64,
+        current_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if pool.streak_bonus_bps == 0 {
+            return Ok(0);
+        }
+        let streak_ts = self
+            .streak_start
+            .saturating_add(i64::try_from(pool.streak_duration).unwrap_or(i64::MAX));
+        let from_ts = self.last_update.max(streak_ts);
+        if elapsed(from_ts, current_ts) == 0 {
+            return Ok(0);
+        }
+        let on_streak = pool.accrue(stake, from_ts, current_ts)?;
+        Ok(math::bps_of(on_streak, pool.streak_bonus_bps as u64)?)
+    }
+
+    /// Bonus on the rewards accrued since the last update while the stake was
+    /// locked
+    fn lock_bonus(
+        &self,
+        pool: &Pool,
+        stake: u64,
+        current_ts: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        let to_ts = current_ts.min(self.locked_until);
+        if self.lock_bonus_bps == 0 || elapsed(self.last_update, to_ts) == 0 {
+            return Ok(0);
+        }
+        let locked = pool.accrue(stake, self.last_update, to_ts)?;
+        Ok(math::bps_of(locked, self.lock_bonus_bps as u64)?)
+    }
+
+    /// Whether the stake is locked at a time. Deprecating the pool releases
+    /// all locks, so its stakers can always leave.
+    pub fn is_locked(&self, pool: &Pool, current_ts: UnixTimestamp) -> bool {
+        !pool.deprecated && current_ts < self.locked_until
+    }
+
+    /// Lock the stake for the lock tier at `lock_tier` of the pool from
+    /// `current_ts`, or extend the running lock, which can't end sooner or
+    /// drop to a lower bonus. Call after accruing up to `current_ts`.
+    pub fn relock(
+        &mut self,
+        pool: &Pool,
+        lock_tier: u8,
+        current_ts: UnixTimestamp,
+    ) -> ProgramResult {
+        let lock_tier = pool
+            .lock_tiers
+            .get(usize::from(lock_tier))
+            .filter(|lock_tier| !lock_tier.is_empty())
+            .ok_or(CustomError::InvalidRelock)?;
+        let locked_until = i64::try_from(lock_tier.duration)
+            .ok()
+            .and_then(|duration| current_ts.checked_add(duration))
+            .ok_or(CustomError::CalculationFailure)?;
+        if self.is_locked(pool, current_ts)
+            && (locked_until < self.locked_until || lock_tier.bonus_bps < self.lock_bonus_bps)
+        {
+            return Err(CustomError::InvalidRelock.into());
+        }
+        self.locked_until = locked_until;
+        self.lock_bonus_bps = lock_tier.bonus_bps;
+        Ok(())
+    }
+
+    /// End the current streak on an unstake, starting a new one
+    pub fn end_streak(&mut self, current_ts: UnixTimestamp) {
+        self.longest_streak = self
+            .longest_streak
+            .max(elapsed(self.streak_start, current_ts));
+        self.streak_start = current_ts;
+    }
+
+    /// Loyalty tier as of the last accrual
+    pub fn loyalty_tier(&self) -> LoyaltyTier {
+        LoyaltyTier::from(self.tier)
+    }
+
+    pub fn claim(&mut self) -> Result<u64, ProgramError> {
+        if self.reward_owed == 0 {
+            return Err(CustomError::InsufficientClaimAmount.into());
+        }
+        let ret = self.reward_owed;
+        self.reward_owed = 0;
+        Ok(ret)
+    }
+
+    /// Record rewards paid out by a claim in the lifetime statistics
+    pub fn record_claim(&mut self, amount: u64, current_ts: UnixTimestamp) {
+        self.total_claimed = self.total_claimed.saturating_add(amount);
+        self.last_claim_ts = current_ts;
+    }
+
+    /// Take over the stake, rewards and boost of another stake user of the
+    /// same owner and pool, both accrued up to `current_ts`. The merged stake
+    /// counts as held since the earlier of both, and stake still warming up
+    /// keeps what is left of its warm-up. Stake users with a stream can't be
+    /// merged from, two boosts can't be merged.
+    pub fn merge(
+        &mut self,
+        other: &StakeUser,
+        pool: &Pool,
+        current_ts: UnixTimestamp,
+    ) -> ProgramResult {
+        if other.stream_balance != 0 {
+            return Err(CustomError::StakeUserNotEmpty.into());
+        }
+        // the lock stays with the stake user it was taken on
+        if other.is_locked(pool, current_ts) {
+            return Err(CustomError::StakeLocked.into());
+        }
+        if other.has_boost() {
+            if self.has_boost() {
+                return Err(CustomError::InvalidBoost.into());
+            }
+            self.boost_mint = other.boost_mint;
+            self.boost_bps = other.boost_bps;
+        }
+        if other.has_stake() {
+            if self.has_stake() {
+                self.staked_since = self.staked_since.min(other.staked_since);
+                self.streak_start = self.streak_start.min(other.streak_start);
+            } else {
+                self.staked_since = other.staked_since;
+                self.streak_start = other.streak_start;
+            }
+        }
+        self.snapshot_stake = self
+            .stake_at_snapshot(pool.snapshot_ts)
+            .checked_add(other.stake_at_snapshot(pool.snapshot_ts))
+            .ok_or(CustomError::CalculationFailure)?;
+        let other_warming = other.warming_stake(pool, current_ts)?;
+
+        self.stake_amount = self
+            .stake_amount
+            .checked_add(other.stake_amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        for (balance, &other_balance) in self.mint_balances.iter_mut().zip(&other.mint_balances) {
+            *balance = balance
+                .checked_add(other_balance)
+                .ok_or(CustomError::CalculationFailure)?;
+        }
+        self.reward_owed = self
+            .reward_owed
+            .checked_add(other.reward_owed)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.cumulative_stake_seconds = self
+            .cumulative_stake_seconds
+            .checked_add(other.cumulative_stake_seconds)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.last_update = self.last_update.max(other.last_update);
+        self.stake_seconds_ts = self.stake_seconds_ts.max(other.stake_seconds_ts);
+        self.badge_amount = self.badge_amount.max(other.badge_amount);
+        self.longest_streak = self.longest_streak.max(other.longest_streak);
+        self.total_claimed = self.total_claimed.saturating_add(other.total_claimed);
+        self.last_claim_ts = self.last_claim_ts.max(other.last_claim_ts);
+        self.total_deposited = self.total_deposited.saturating_add(other.total_deposited);
+        self.total_withdrawn = self.total_withdrawn.saturating_add(other.total_withdrawn);
+
+        self.add_warming_stake(
+            pool,
+            other_warming,
+            elapsed(current_ts, other.warm_up_end),
+            current_ts,
+        )?;
+        self.tier = self.reached_tier(pool, current_ts)?;
+        Ok(())
+    }
+
+    /// Share of the stream balance still locked at a time, rounding up
+    pub fn stream_locked_at(&self, current_ts: UnixTimestamp) -> Result<u64, ProgramError> {
+        if current_ts >= self.stream_end {
+            return Ok(0);
+        }
+        if current_ts <= self.stream_start {
+            return Ok(self.stream_locked);
+        }
+        Ok(math::mul_div_ceil(
+            self.stream_locked,
+            elapsed(current_ts, self.stream_end),
+            elapsed(self.stream_start, self.stream_end),
+        )?)
+    }
+
+    /// Streamed rewards released and not withdrawn yet at a time
+    pub fn stream_withdrawable(&self, current_ts: UnixTimestamp) -> Result<u64, ProgramError> {
+        Ok(self
+            .stream_balance
+            .saturating_sub(self.stream_locked_at(current_ts)?))
+    }
+
+    /// Top the stream up with claimed rewards, restarting it so the new
+    /// rewards and those still locked release over the pool's stream duration
+    pub fn open_stream(
+        &mut self,
+        pool: &Pool,
+        amount: u64,
+        current_ts: UnixTimestamp,
+    ) -> ProgramResult {
+        let locked = self.stream_locked_at(current_ts)?;
+        self.stream_balance = self
+            .stream_balance
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.stream_locked = locked
+            .checked_add(amount)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.stream_start = current_ts;
+        self.stream_end = i64::try_from(pool.stream_duration)
+            .ok()
+            .and_then(|duration| current_ts.checked_add(duration))
+            .ok_or(CustomError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Take released rewards out of the stream
+    pub fn withdraw_stream(&mut self, amount: u64, current_ts: UnixTimestamp) -> ProgramResult {
+        if amount > self.stream_withdrawable(current_ts)? {
+            return Err(CustomError::InsufficientClaimAmount.into());
+        }
+        self.stream_balance -= amount;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolSnapshot {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// Stake Pool pubkey
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub pool_pubkey: Pubkey,
+    /// Timestamp the snapshot was taken at
+    pub timestamp: UnixTimestamp,
+    /// Slot the snapshot was taken at
+    pub slot: u64,
+    /// Total amount staked in the pool
+    pub total_staked: u64,
+    /// Staking token reserve balance
+    pub reserve_balance: u64,
+    /// Reward token mint supply, in raw amounts for interest-bearing mints
+    pub reward_supply: u64,
+    /// Reward numerator per reward period applying at the snapshot
+    pub reward_numerator: u64,
+    /// Reward denominator per reward period applying at the snapshot
+    pub reward_denominator: u64,
+    /// Reward period in seconds
+    pub reward_period: u64,
+}
+
+impl Sealed for PoolSnapshot {}
+impl IsInitialized for PoolSnapshot {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl PoolSnapshot {
+    /// Byte offset of the pool indexers filter snapshots by
+    pub const POOL_PUBKEY_OFFSET: usize = 1;
+}
+
+const POOL_SNAPSHOT_SIZE: usize = 97; // 1 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8
+
+impl Pack for PoolSnapshot {
+    const LEN: usize = POOL_SNAPSHOT_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, POOL_SNAPSHOT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            timestamp,
+            slot,
+            total_staked,
+            reserve_balance,
+            reward_supply,
+            reward_numerator,
+            reward_denominator,
+            reward_period,
+        ) = array_refs![input, 1, PUBKEY_BYTES, 8, 8, 8, 8, 8, 8, 8, 8];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
+            timestamp: i64::from_le_bytes(*timestamp),
+            slot: u64::from_le_bytes(*slot),
+            total_staked: u64::from_le_bytes(*total_staked),
+            reserve_balance: u64::from_le_bytes(*reserve_balance),
+            reward_supply: u64::from_le_bytes(*reward_supply),
+            reward_numerator: u64::from_le_bytes(*reward_numerator),
+            reward_denominator: u64::from_le_bytes(*reward_denominator),
+            reward_period: u64::from_le_bytes(*reward_period),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, POOL_SNAPSHOT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            timestamp,
+            slot,
+            total_staked,
+            reserve_balance,
+            reward_supply,
+            reward_numerator,
+            reward_denominator,
+            reward_period,
+        ) = mut_array_refs![output, 1, PUBKEY_BYTES, 8, 8, 8, 8, 8, 8, 8, 8];
+
+        pack_bool(self.is_initialized, is_initialized);
+        pool_pubkey.copy_from_slice(self.pool_pubkey.as_ref());
+        *timestamp = self.timestamp.to_le_bytes();
+        *slot = self.slot.to_le_bytes();
+        *total_staked = self.total_staked.to_le_bytes();
+        *reserve_balance = self.reserve_balance.to_le_bytes();
+        *reward_supply = self.reward_supply.to_le_bytes();
+        *reward_numerator = self.reward_numerator.to_le_bytes();
+        *reward_denominator = self.reward_denominator.to_le_bytes();
+        *reward_period = self.reward_period.to_le_bytes();
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DistributionReport {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// Stake Pool pubkey
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub pool_pubkey: Pubkey,
+    /// Epoch the report was finalized in
+    pub epoch: u64,
+    /// Timestamp the report was finalized at
+    pub timestamp: UnixTimestamp,
+    /// Slot the report was finalized at
+    pub slot: u64,
+    /// Whether the deltas are since a previous report rather than since the
+    /// pool was created
+    pub has_previous: bool,
+    /// Epoch of the previous report the deltas are since
+    pub previous_epoch: u64,
+    /// Reward token mint supply, in raw amounts for interest-bearing mints
+    pub reward_supply: u64,
+    /// Total reward tokens the pool paid out on claims
+    pub total_rewards_paid: u64,
+    /// Reward tokens the pool paid out on claims over the period
+    pub rewards_paid: u64,
+    /// Total claim burn of the pool
+    pub total_burned: u64,
+    /// Claim burn of the pool over the period
+    pub fees_collected: u64,
+    /// Total amount staked in the pool
+    pub total_staked: u64,
+    /// Change of the total amount staked over the period
+    pub stake_delta: i64,
+}
+
+impl Sealed for DistributionReport {}
+impl IsInitialized for DistributionReport {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl DistributionReport {
+    /// Byte offset of the pool indexers filter reports by
+    pub const POOL_PUBKEY_OFFSET: usize = 1;
+
+    /// Find the distribution report of a pool for an epoch
+    pub fn address(program_id: &Pubkey, pool_pubkey: &Pubkey, epoch: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                pool_pubkey.as_ref(),
+                DISTRIBUTION_REPORT_SEED,
+                &epoch.to_le_bytes(),
+            ],
+            program_id,
+        )
+    }
+
+    /// Report of a pool in an epoch with the deltas since the last report of
+    /// the pool, or since the pool was created without one
+    pub fn new(
+        pool_pubkey: Pubkey,
+        stake_pool: &Pool,
+        reward_supply: u64,
+        clock: &Clock,
+        previous: Option<&DistributionReport>,
+    ) -> Result<Self, ProgramError> {
+        let (previous_epoch, previous_paid, previous_burned, previous_staked) = match previous {
+            Some(previous) => (
+                previous.epoch,
+                previous.total_rewards_paid,
+                previous.total_burned,
+                previous.total_staked,
+            ),
+            None => (0, 0, 0, 0),
+        };
+        let stake_delta = i128::from(stake_pool.total_staked) - i128::from(previous_staked);
+        Ok(Self {
+            is_initialized: true,
+            pool_pubkey,
+            epoch: clock.epoch,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+            has_previous: previous.is_some(),
+            previous_epoch,
+            reward_supply,
+            total_rewards_paid: stake_pool.total_rewards_paid,
+            rewards_paid: stake_pool.total_rewards_paid.saturating_sub(previous_paid),
+            total_burned: stake_pool.total_burned,
+            fees_collected: stake_pool.total_burned.saturating_sub(previous_burned),
+            total_staked: stake_pool.total_staked,
+            stake_delta: i64::try_from(stake_delta).map_err(|_| CustomError::CalculationFailure)?,
+        })
+    }
+}
+
+const DISTRIBUTION_REPORT_SIZE: usize = 122; // 1 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8
+
+impl Pack for DistributionReport {
+    const LEN: usize = DISTRIBUTION_REPORT_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, DISTRIBUTION_REPORT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            epoch,
+            timestamp,
+            slot,
+            has_previous,
+            previous_epoch,
+            reward_supply,
+            total_rewards_paid,
+            rewards_paid,
+            total_burned,
+            fees_collected,
+            total_staked,
+            stake_delta,
+        ) = array_refs![input, 1, PUBKEY_BYTES, 8, 8, 8, 1, 8, 8, 8, 8, 8, 8, 8, 8];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
+            epoch: u64::from_le_bytes(*epoch),
+            timestamp: i64::from_le_bytes(*timestamp),
+            slot: u64::from_le_bytes(*slot),
+            has_previous: unpack_bool(has_previous)?,
+            previous_epoch: u64::from_le_bytes(*previous_epoch),
+            reward_supply: u64::from_le_bytes(*reward_supply),
+            total_rewards_paid: u64::from_le_bytes(*total_rewards_paid),
+            rewards_paid: u64::from_le_bytes(*rewards_paid),
+            total_burned: u64::from_le_bytes(*total_burned),
+            fees_collected: u64::from_le_bytes(*fees_collected),
+            total_staked: u64::from_le_bytes(*total_staked),
+            stake_delta: i64::from_le_bytes(*stake_delta),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, DISTRIBUTION_REPORT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            epoch,
+            timestamp,
+            slot,
+            has_previous,
+            previous_epoch,
+            reward_supply,
+            total_rewards_paid,
+            rewards_paid,
+            total_burned,
+            fees_collected,
+            total_staked,
+            stake_delta,
+        ) = mut_array_refs![output, 1, PUBKEY_BYTES, 8, 8, 8, 1, 8, 8, 8, 8, 8, 8, 8, 8];
+
+        pack_bool(self.is_initialized, is_initialized);
+        pool_pubkey.copy_from_slice(self.pool_pubkey.as_ref());
+        *epoch = self.epoch.to_le_bytes();
+        *timestamp = self.timestamp.to_le_bytes();
+        *slot = self.slot.to_le_bytes();
+        pack_bool(self.has_previous, has_previous);
+        *previous_epoch = self.previous_epoch.to_le_bytes();
+        *reward_supply = self.reward_supply.to_le_bytes();
+        *total_rewards_paid = self.total_rewards_paid.to_le_bytes();
+        *rewards_paid = self.rewards_paid.to_le_bytes();
+        *total_burned = self.total_burned.to_le_bytes();
+        *fees_collected = self.fees_collected.to_le_bytes();
+        *total_staked = self.total_staked.to_le_bytes();
+        *stake_delta = self.stake_delta.to_le_bytes();
+    }
+}
+
+/// Number of destinations a claim split can pay shares of claims into
+pub const MAX_CLAIM_SHARES: usize = 4;
+
+/// Share of the claims of a stake user paid into a token account of the
+/// reward mint
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClaimShare {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub destination: Pubkey,
+    /// Share of each claim in basis points, 0 for an empty slot
+    pub bps: u16,
+}
+
+impl ClaimShare {
+    pub fn is_empty(&self) -> bool {
+        self.bps == 0
+    }
+}
+
+const CLAIM_SHARE_SIZE: usize = 34; // 32 + 2
+const CLAIM_SHARES_SIZE: usize = CLAIM_SHARE_SIZE * MAX_CLAIM_SHARES;
+
+/// Destinations the owner of a stake user splits its claims between, the
+/// rest of each claim going to the reward token account of the claim
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClaimSplit {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// Stake user whose claims are split
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub stake_user_pubkey: Pubkey,
+    /// Shares ordered with empty shares last
+    pub shares: [ClaimShare; 4],
+}
+
+impl Sealed for ClaimSplit {}
+impl IsInitialized for ClaimSplit {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl ClaimSplit {
+    /// Find the claim split of a stake user
+    pub fn address(program_id: &Pubkey, stake_user_pubkey: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[stake_user_pubkey.as_ref(), CLAIM_SPLIT_SEED], program_id)
+    }
+
+    /// Replace the shares, checked to be ordered with empty shares last, into
+    /// distinct destinations and within a whole claim
+    pub fn set_shares(&mut self, shares: [ClaimShare; MAX_CLAIM_SHARES]) -> ProgramResult {
+        let count = shares.iter().take_while(|share| !share.is_empty()).count();
+        let (used, empty) = shares.split_at(count);
+        if empty.iter().any(|share| !share.is_empty()) {
+            return Err(CustomError::InvalidClaimShares.into());
+        }
+        for (index, share) in used.iter().enumerate() {
+            if used[..index]
+                .iter()
+                .any(|other| other.destination == share.destination)
+            {
+                return Err(CustomError::InvalidClaimShares.into());
+            }
+        }
+        let total: u64 = used.iter().map(|share| u64::from(share.bps)).sum();
+        if total > BPS_DENOMINATOR {
+            return Err(CustomError::InvalidClaimShares.into());
+        }
+        self.shares = shares;
+        Ok(())
+    }
+
+    /// Shares paying into a destination
+    pub fn used_shares(&self) -> impl Iterator<Item = &ClaimShare> {
+        self.shares.iter().take_while(|share| !share.is_empty())
+    }
+
+    /// Amounts of a claim paid into each used share, rounded down, and the
+    /// rest left for the reward token account of the claim
+    pub fn split(&self, amount: u64) -> Result<(Vec<u64>, u64), ProgramError> {
+        let mut rest = amount;
+        let amounts = self
+            .used_shares()
+            .map(|share| {
+                let share_amount = math::mul_div(amount, u64::from(share.bps), BPS_DENOMINATOR)?;
+                rest = rest
+                    .checked_sub(share_amount)
+                    .ok_or(CustomError::CalculationFailure)?;
+                Ok(share_amount)
+            })
+            .collect::<Result<_, ProgramError>>()?;
+        Ok((amounts, rest))
+    }
+}
+
+const CLAIM_SPLIT_SIZE: usize = 169; // 1 + 32 + 34 * 4
+
+impl Pack for ClaimSplit {
+    const LEN: usize = CLAIM_SPLIT_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, CLAIM_SPLIT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, stake_user_pubkey, shares) =
+            array_refs![input, 1, PUBKEY_BYTES, CLAIM_SHARES_SIZE];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            stake_user_pubkey: Pubkey::new_from_array(*stake_user_pubkey),
+            shares: unpack_claim_shares(shares),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, CLAIM_SPLIT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, stake_user_pubkey, shares) =
+            mut_array_refs![output, 1, PUBKEY_BYTES, CLAIM_SHARES_SIZE];
+
+        pack_bool(self.is_initialized, is_initialized);
+        stake_user_pubkey.copy_from_slice(self.stake_user_pubkey.as_ref());
+        pack_claim_shares(&self.shares, shares);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+pub struct StakeSnapshot {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// Stake Pool pubkey
+    pub pool_pubkey: Pubkey,
+    /// Timestamp the stakes are snapshot at
+    pub timestamp: UnixTimestamp,
+    /// Whether all stake users were added and the root is final
+    pub finalized: bool,
+    /// Number of stake users added
+    pub leaf_count: u64,
+    /// Sum of the stakes added
+    pub total_staked: u64,
+    /// Last stake user added, stake users being added in increasing order
+    pub last_stake_user: Pubkey,
+    /// Merkle root of the `(owner, stake)` leaves once finalized
+    pub root: [u8; 32],
+    /// Left siblings of the next leaf while building
+    pub branch: Branch,
+}
+
+impl Sealed for StakeSnapshot {}
+impl IsInitialized for StakeSnapshot {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl StakeSnapshot {
+    /// Byte offset of the pool indexers filter snapshots by
+    pub const POOL_PUBKEY_OFFSET: usize = 1;
+
+    /// Add the stake of a stake user at the snapshot timestamp, stake users
+    /// coming in increasing order of their addresses so none is added twice
+    pub fn add_stake_user(
+        &mut self,
+        stake_user_pubkey: &Pubkey,
+        stake_user: &StakeUser,
+    ) -> ProgramResult {
+        if self.finalized
+            || stake_user.pool_pubkey != self.pool_pubkey
+            || (self.leaf_count != 0 && *stake_user_pubkey <= self.last_stake_user)
+        {
+            return Err(CustomError::InvalidSnapshot.into());
+        }
+        let stake = stake_user.stake_at_snapshot(self.timestamp);
+        if !merkle::append(
+            &mut self.branch,
+            self.leaf_count,
+            merkle::leaf_hash(&stake_user.owner, stake),
+        ) {
+            return Err(CustomError::InvalidSnapshot.into());
+        }
+        self.leaf_count += 1;
+        self.total_staked = self
+            .total_staked
+            .checked_add(stake)
+            .ok_or(CustomError::CalculationFailure)?;
+        self.last_stake_user = *stake_user_pubkey;
+        Ok(())
+    }
+
+    /// Compute the root, no stake user can be added after
+    pub fn finalize(&mut self) {
+        self.root = merkle::root(&self.branch, self.leaf_count);
+        self.finalized = true;
+    }
+}
+
+const STAKE_SNAPSHOT_SIZE: usize = 762; // 1 + 32 + 8 + 1 + 8 + 8 + 32 + 32 + 32 * 20
+
+impl Pack for StakeSnapshot {
+    const LEN: usize = STAKE_SNAPSHOT_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, STAKE_SNAPSHOT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            timestamp,
+            finalized,
+            leaf_count,
+            total_staked,
+            last_stake_user,
+            root,
+            branch,
+        ) = array_refs![
+            input,
+            1,
+            PUBKEY_BYTES,
+            8,
+            1,
+            8,
+            8,
+            PUBKEY_BYTES,
+            32,
+            32 * SNAPSHOT_DEPTH
+        ];
+
+        let mut snapshot_branch = [[0; 32]; SNAPSHOT_DEPTH];
+        for (node, src) in snapshot_branch.iter_mut().zip(branch.chunks_exact(32)) {
+            node.copy_from_slice(src);
+        }
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
+            timestamp: i64::from_le_bytes(*timestamp),
+            finalized: unpack_bool(finalized)?,
+            leaf_count: u64::from_le_bytes(*leaf_count),
+            total_staked: u64::from_le_bytes(*total_staked),
+            last_stake_user: Pubkey::new_from_array(*last_stake_user),
+            root: *root,
+            branch: snapshot_branch,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, STAKE_SNAPSHOT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool_pubkey,
+            timestamp,
+            finalized,
+            leaf_count,
+            total_staked,
+            last_stake_user,
+            root,
+            branch,
+        ) = mut_array_refs![
+            output,
+            1,
+            PUBKEY_BYTES,
+            8,
+            1,
+            8,
+            8,
+            PUBKEY_BYTES,
+            32,
+            32 * SNAPSHOT_DEPTH
+        ];
+
+        pack_bool(self.is_initialized, is_initialized);
+        pool_pubkey.copy_from_slice(self.pool_pubkey.as_ref());
+        *timestamp = self.timestamp.to_le_bytes();
+        pack_bool(self.finalized, finalized);
+        *leaf_count = self.leaf_count.to_le_bytes();
+        *total_staked = self.total_staked.to_le_bytes();
+        last_stake_user.copy_from_slice(self.last_stake_user.as_ref());
+        *root = self.root;
+        for (node, dst) in self.branch.iter().zip(branch.chunks_exact_mut(32)) {
+            dst.copy_from_slice(node);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BonusCampaign {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// Stake Pool pubkey
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub pool_pubkey: Pubkey,
+    /// Merkle root of the `(owner, amount)` leaves published by the admin
+    pub root: [u8; 32],
+    /// Number of leaves, each tracked by a bit of the claimed bitmap following
+    /// the campaign
+    pub leaf_count: u64,
+    /// Reward token account paying out the bonuses, owned by the bonus
+    /// authority of the campaign
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub vault: Pubkey,
+    /// Bump seed of the bonus authority
+    pub authority_bump: u8,
+    /// Bonuses paid out so far
+    pub total_claimed: u64,
+}
+
+impl Sealed for BonusCampaign {}
+impl IsInitialized for BonusCampaign {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl BonusCampaign {
+    /// Byte offset of the pool indexers filter campaigns by
+    pub const POOL_PUBKEY_OFFSET: usize = 1;
+
+    /// Size of a campaign account with its claimed bitmap
+    pub fn account_len(leaf_count: u64) -> usize {
+        Self::LEN + leaf_count.div_ceil(8) as usize
+    }
+
+    /// Mark the leaf at `index` claimed in the bitmap, false if it already was
+    pub fn claim_leaf(claimed: &mut [u8], index: u64) -> bool {
+        let (byte, bit) = ((index / 8) as usize, 1 << (index % 8));
+        match claimed.get_mut(byte) {
+            Some(byte) if *byte & bit == 0 => {
+                *byte |= bit;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+const BONUS_CAMPAIGN_SIZE: usize = 114; // 1 + 32 + 32 + 8 + 32 + 1 + 8
+
+impl Pack for BonusCampaign {
+    const LEN: usize = BONUS_CAMPAIGN_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, BONUS_CAMPAIGN_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, pool_pubkey, root, leaf_count, vault, authority_bump, total_claimed) =
+            array_refs![input, 1, PUBKEY_BYTES, 32, 8, PUBKEY_BYTES, 1, 8];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            pool_pubkey: Pubkey::new_from_array(*pool_pubkey),
+            root: *root,
+            leaf_count: u64::from_le_bytes(*leaf_count),
+            vault: Pubkey::new_from_array(*vault),
+            authority_bump: authority_bump[0],
+            total_claimed: u64::from_le_bytes(*total_claimed),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, BONUS_CAMPAIGN_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, pool_pubkey, root, leaf_count, vault, authority_bump, total_claimed) =
+            mut_array_refs![output, 1, PUBKEY_BYTES, 32, 8, PUBKEY_BYTES, 1, 8];
+
+        pack_bool(self.is_initialized, is_initialized);
+        pool_pubkey.copy_from_slice(self.pool_pubkey.as_ref());
+        *root = self.root;
+        *leaf_count = self.leaf_count.to_le_bytes();
+        vault.copy_from_slice(self.vault.as_ref());
+        authority_bump[0] = self.authority_bump;
+        *total_claimed = self.total_claimed.to_le_bytes();
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, ShankAccount)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GlobalConfig {
+    /// Initialization state
+    pub is_initialized: bool,
+    /// bump_seed of the config address
+    pub bump_seed: u8,
+    /// Authority updating the config
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub authority: Pubkey,
+    /// Protocol treasury collecting the fees
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub treasury: Pubkey,
+    /// Lamports paid to the treasury by creating a pool
+    pub pool_creation_fee: u64,
+    /// Key pausing and unpausing stakes and claims of all pools, besides the
+    /// authority
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::pubkey"))]
+    pub guardian: Pubkey,
+    /// Whether stakes and claims of all pools are halted, unstakes staying
+    /// open
+    pub paused: bool,
+    /// Claim burn, in basis points, new pools start with
+    pub default_claim_burn_bps: u16,
+    /// Most rewards a pool may accrue per day, in basis points of the stake,
+    /// zero for no cap
+    pub max_daily_reward_bps: u64,
+}
+
+impl Sealed for GlobalConfig {}
+impl IsInitialized for GlobalConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl GlobalConfig {
+    /// Find the address of the program-wide config
+    pub fn address(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[GLOBAL_CONFIG_SEED], program_id)
+    }
+
+    /// Check a reward rate per reward period against the cap of the config
+    pub fn check_reward_rate(
+        &self,
+        reward_numerator: u64,
+        reward_denominator: u64,
+        reward_period: u64,
+    ) -> ProgramResult {
+        if self.max_daily_reward_bps == 0 {
+            return Ok(());
+        }
+        let daily_reward_bps = math::accrue(
+            BPS_DENOMINATOR,
+            reward_numerator,
+            reward_denominator,
+            DAILY_TS,
+            reward_period,
+        )
+        .unwrap_or(u64::MAX);
+        if daily_reward_bps > self.max_daily_reward_bps {
+            return Err(CustomError::RewardRateTooHigh.into());
+        }
+        Ok(())
+    }
+}
+
+const GLOBAL_CONFIG_SIZE: usize = 117; // 1 + 1 + 32 + 32 + 8 + 32 + 1 + 2 + 8
+
+impl Pack for GlobalConfig {
+    const LEN: usize = GLOBAL_CONFIG_SIZE;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let input = array_ref![src, 0, GLOBAL_CONFIG_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            bump_seed,
+            authority,
+            treasury,
+            pool_creation_fee,
+            guardian,
+            paused,
+            default_claim_burn_bps,
+            max_daily_reward_bps,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            PUBKEY_BYTES,
+            1,
+            2,
+            8
+        ];
+
+        Ok(Self {
+            is_initialized: unpack_bool(is_initialized)?,
+            bump_seed: bump_seed[0],
+            authority: Pubkey::new_from_array(*authority),
+            treasury: Pubkey::new_from_array(*treasury),
+            pool_creation_fee: u64::from_le_bytes(*pool_creation_fee),
+            guardian: Pubkey::new_from_array(*guardian),
+            paused: unpack_bool(paused)?,
+            default_claim_burn_bps: u16::from_le_bytes(*default_claim_burn_bps),
+            max_daily_reward_bps: u64::from_le_bytes(*max_daily_reward_bps),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, GLOBAL_CONFIG_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            bump_seed,
+            authority,
+            treasury,
+            pool_creation_fee,
+            guardian,
+            paused,
+            default_claim_burn_bps,
+            max_daily_reward_bps,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            PUBKEY_BYTES,
+            1,
+            2,
+            8
+        ];
+
+        pack_bool(self.is_initialized, is_initialized);
+        bump_seed[0] = self.bump_seed;
+        authority.copy_from_slice(self.authority.as_ref());
+        treasury.copy_from_slice(self.treasury.as_ref());
+        *pool_creation_fee = self.pool_creation_fee.to_le_bytes();
+        guardian.copy_from_slice(self.guardian.as_ref());
+        pack_bool(self.paused, paused);
+        *default_claim_burn_bps = self.default_claim_burn_bps.to_le_bytes();
+        *max_daily_reward_bps = self.max_daily_reward_bps.to_le_bytes();
+    }
+}
+
+fn unpack_rate_checkpoints(
+    src: &[u8; RATE_CHECKPOINTS_SIZE],
+) -> [RateCheckpoint; MAX_RATE_CHECKPOINTS] {
+    let mut rates = [RateCheckpoint::default(); MAX_RATE_CHECKPOINTS];
+    for (rate, src) in rates.iter_mut().zip(src.chunks_exact(RATE_CHECKPOINT_SIZE)) {
+        let src = array_ref![src, 0, RATE_CHECKPOINT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (epoch, start_ts, reward_numerator, reward_denominator) = array_refs![src, 8, 8, 8, 8];
+        *rate = RateCheckpoint {
+            epoch: u64::from_le_bytes(*epoch),
+            start_ts: i64::from_le_bytes(*start_ts),
+            reward_numerator: u64::from_le_bytes(*reward_numerator),
+            reward_denominator: u64::from_le_bytes(*reward_denominator),
+        };
+    }
+    rates
+}
+
+fn pack_rate_checkpoints(
+    rates: &[RateCheckpoint; MAX_RATE_CHECKPOINTS],
+    dst: &mut [u8; RATE_CHECKPOINTS_SIZE],
+) {
+    for (rate, dst) in rates.iter().zip(dst.chunks_exact_mut(RATE_CHECKPOINT_SIZE)) {
+        let dst = array_mut_ref![dst, 0, RATE_CHECKPOINT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (epoch, start_ts, reward_numerator, reward_denominator) =
+            mut_array_refs![dst, 8, 8, 8, 8];
+        *epoch = rate.epoch.to_le_bytes();
+        *start_ts = rate.start_ts.to_le_bytes();
+        *reward_numerator = rate.reward_numerator.to_le_bytes();
+        *reward_denominator = rate.reward_denominator.to_le_bytes();
+    }
+}
+
+fn unpack_stake_mints(src: &[u8; STAKE_MINTS_SIZE]) -> [StakeMint; MAX_STAKE_MINTS] {
+    let mut stake_mints = [StakeMint::default(); MAX_STAKE_MINTS];
+    for (stake_mint, src) in stake_mints
+        .iter_mut()
+        .zip(src.chunks_exact(STAKE_MINT_SIZE))
+    {
+        let src = array_ref![src, 0, STAKE_MINT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (mint, reserve, weight_bps, total_staked) =
+            array_refs![src, PUBKEY_BYTES, PUBKEY_BYTES, 2, 8];
+        *stake_mint = StakeMint {
+            mint: Pubkey::new_from_array(*mint),
+            reserve: Pubkey::new_from_array(*reserve),
+            weight_bps: u16::from_le_bytes(*weight_bps),
+            total_staked: u64::from_le_bytes(*total_staked),
+        };
+    }
+    stake_mints
+}
+
+fn pack_stake_mints(stake_mints: &[StakeMint; MAX_STAKE_MINTS], dst: &mut [u8; STAKE_MINTS_SIZE]) {
+    for (stake_mint, dst) in stake_mints
+        .iter()
+        .zip(dst.chunks_exact_mut(STAKE_MINT_SIZE))
+    {
+        let dst = array_mut_ref![dst, 0, STAKE_MINT_SIZE];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (mint, reserve, weight_bps, total_staked) =
+            mut_array_refs![dst, PUBKEY_BYTES, PUBKEY_BYTES, 2, 8];
+        mint.copy_from_slice(stake_mint.mint.as_ref());
+        reserve.copy_from_slice(stake_mint.reserve.as_ref());
+        *weight_bps = stake_mint.weight_bps.to_le_bytes();
+        *total_staked = stake_mint.total_staked.to_le_bytes();
+    }
+}
+
+fn unpack_integrators(src: &[u8; INTEGRATORS_SIZE]) -> [Pubkey; MAX_INTEGRATORS] {
+    let mut integrators = [Pubkey::default(); MAX_INTEGRATORS];
+    for (integrator, src) in integrators.iter_mut().zip(src.chunks_exact(PUBKEY_BYTES)) {
+        *integrator = Pubkey::new_from_array(*array_ref![src, 0, PUBKEY_BYTES]);
     }
+    integrators
 }
 
-const DAILY_TS: i64 = 86_400;
+fn pack_integrators(integrators: &[Pubkey; MAX_INTEGRATORS], dst: &mut [u8; INTEGRATORS_SIZE]) {
+    for (integrator, dst) in integrators.iter().zip(dst.chunks_exact_mut(PUBKEY_BYTES)) {
+        dst.copy_from_slice(integrator.as_ref());
+    }
+}
 
-pub struct InitStakeUserParams {
-    pub pool_pubkey: Pubkey,
-    pub owner: Pubkey,
+fn unpack_tiers(src: &[u8; TIERS_SIZE]) -> [Tier; MAX_TIERS] {
+    let mut tiers = [Tier::default(); MAX_TIERS];
+    for (tier, src) in tiers.iter_mut().zip(src.chunks_exact(TIER_SIZE)) {
+        let src = array_ref![src, 0, TIER_SIZE];
+        let (min_stake, min_duration) = array_refs![src, 8, 8];
+        *tier = Tier {
+            min_stake: u64::from_le_bytes(*min_stake),
+            min_duration: u64::from_le_bytes(*min_duration),
+        };
+    }
+    tiers
 }
 
-impl StakeUser {
-    pub fn init(&mut self, params: InitStakeUserParams) {
-        self.is_initialized = true;
-        self.pool_pubkey = params.pool_pubkey;
-        self.owner = params.owner;
+fn pack_tiers(tiers: &[Tier; MAX_TIERS], dst: &mut [u8; TIERS_SIZE]) {
+    for (tier, dst) in tiers.iter().zip(dst.chunks_exact_mut(TIER_SIZE)) {
+        let dst = array_mut_ref![dst, 0, TIER_SIZE];
+        let (min_stake, min_duration) = mut_array_refs![dst, 8, 8];
+        *min_stake = tier.min_stake.to_le_bytes();
+        *min_duration = tier.min_duration.to_le_bytes();
     }
+}
 
-    pub fn stake(&mut self, amount: u64) -> ProgramResult {
-        self.stake_amount = self
-            .stake_amount
-            .checked_add(amount)
-            .ok_or(CustomError::CalculationFailure)?;
-        Ok(())
+fn unpack_lock_tiers(src: &[u8; LOCK_TIERS_SIZE]) -> [LockTier; MAX_LOCK_TIERS] {
+    let mut lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+    for (lock_tier, src) in lock_tiers.iter_mut().zip(src.chunks_exact(LOCK_TIER_SIZE)) {
+        let src = array_ref![src, 0, LOCK_TIER_SIZE];
+        let (duration, bonus_bps) = array_refs![src, 8, 2];
+        *lock_tier = LockTier {
+            duration: u64::from_le_bytes(*duration),
+            bonus_bps: u16::from_le_bytes(*bonus_bps),
+        };
     }
+    lock_tiers
+}
 
-    pub fn unstake(&mut self, amount: u64) -> ProgramResult {
-        if amount > self.stake_amount {
-            return Err(CustomError::InsufficientLiquidity.into());
-        }
-        self.stake_amount = self
-            .stake_amount
-            .checked_sub(amount)
-            .ok_or(CustomError::CalculationFailure)?;
-        Ok(())
+fn pack_lock_tiers(lock_tiers: &[LockTier; MAX_LOCK_TIERS], dst: &mut [u8; LOCK_TIERS_SIZE]) {
+    for (lock_tier, dst) in lock_tiers.iter().zip(dst.chunks_exact_mut(LOCK_TIER_SIZE)) {
+        let dst = array_mut_ref![dst, 0, LOCK_TIER_SIZE];
+        let (duration, bonus_bps) = mut_array_refs![dst, 8, 2];
+        *duration = lock_tier.duration.to_le_bytes();
+        *bonus_bps = lock_tier.bonus_bps.to_le_bytes();
     }
+}
 
-    pub fn update_reward_owed(
-        &mut self,
-        numerator: u64,
-        denominator: u64,
-        current_ts: UnixTimestamp,
-    ) -> ProgramResult {
-        let calc_period = current_ts
-            .checked_sub(self.last_update)
-            .ok_or(CustomError::CalculationFailure)?;
-        if calc_period > 0 {
-            self.reward_owed = numerator
-                .checked_mul(self.stake_amount)
-                .ok_or(CustomError::CalculationFailure)?
-                .checked_div(denominator)
-                .ok_or(CustomError::CalculationFailure)?
-                .checked_mul(u64::try_from(calc_period).unwrap())
-                .ok_or(CustomError::CalculationFailure)?
-                .checked_div(u64::try_from(DAILY_TS).unwrap())
-                .ok_or(CustomError::CalculationFailure)?
-                .checked_add(self.reward_owed)
-                .ok_or(CustomError::CalculationFailure)?;
+fn unpack_claim_shares(src: &[u8; CLAIM_SHARES_SIZE]) -> [ClaimShare; MAX_CLAIM_SHARES] {
+    let mut shares = [ClaimShare::default(); MAX_CLAIM_SHARES];
+    for (share, src) in shares.iter_mut().zip(src.chunks_exact(CLAIM_SHARE_SIZE)) {
+        let src = array_ref![src, 0, CLAIM_SHARE_SIZE];
+        let (destination, bps) = array_refs![src, PUBKEY_BYTES, 2];
+        *share = ClaimShare {
+            destination: Pubkey::new_from_array(*destination),
+            bps: u16::from_le_bytes(*bps),
+        };
+    }
+    shares
+}
 
-            self.last_update = current_ts;
-        }
-        Ok(())
+fn pack_claim_shares(shares: &[ClaimShare; MAX_CLAIM_SHARES], dst: &mut [u8; CLAIM_SHARES_SIZE]) {
+    for (share, dst) in shares.iter().zip(dst.chunks_exact_mut(CLAIM_SHARE_SIZE)) {
+        let dst = array_mut_ref![dst, 0, CLAIM_SHARE_SIZE];
+        let (destination, bps) = mut_array_refs![dst, PUBKEY_BYTES, 2];
+        destination.copy_from_slice(share.destination.as_ref());
+        *bps = share.bps.to_le_bytes();
     }
+}
 
-    pub fn claim(&mut self) -> Result<u64, ProgramError> {
-        if self.reward_owed == 0 {
-            return Err(CustomError::InsufficientClaimAmount.into());
-        }
-        let ret = self.reward_owed;
-        self.reward_owed = 0;
-        Ok(ret)
+fn unpack_mint_balances(src: &[u8; 8 * MAX_STAKE_MINTS]) -> [u64; MAX_STAKE_MINTS] {
+    let mut balances = [0; MAX_STAKE_MINTS];
+    for (balance, src) in balances.iter_mut().zip(src.chunks_exact(8)) {
+        *balance = u64::from_le_bytes(*array_ref![src, 0, 8]);
+    }
+    balances
+}
+
+/// Check a packed pool or stake user has the current layout. Those of the
+/// first release are smaller and aren't migrated, the program is deployed
+/// anew instead, so they fail with their own error rather than as invalid.
+fn check_layout(src: &[u8], size: usize, legacy_size: usize) -> ProgramResult {
+    match src.len() {
+        len if len == size => Ok(()),
+        len if len == legacy_size => Err(CustomError::LegacyAccountLayout.into()),
+        _ => Err(ProgramError::InvalidAccountData),
     }
 }
 
@@ -235,6 +3335,7 @@ pub fn unpack_bool(src: &[u8; 1]) -> Result<bool, ProgramError> {
 mod tests {
 
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_pool_packing() {
@@ -248,6 +3349,91 @@ mod tests {
         let reward_mint = Pubkey::new_from_array(reward_mint_key_raw);
         let reward_numerator: u64 = 1;
         let reward_denominator: u64 = 1_000;
+        let reward_vault = Pubkey::new_from_array([4u8; 32]);
+        let admin = Pubkey::new_from_array([5u8; 32]);
+        let total_staked: u64 = 20_000_000_000;
+        let protocol_owned: u64 = 300_000_000;
+        let deprecated: bool = true;
+        let successor = Pubkey::new_from_array([6u8; 32]);
+        let claim_burn_bps: u16 = 250;
+        let total_burned: u64 = 5_000_000;
+        let reward_period: u64 = WEEKLY_TS;
+        let rate_checkpoints: [RateCheckpoint; MAX_RATE_CHECKPOINTS] = [RateCheckpoint {
+            epoch: 300,
+            start_ts: 1_650_000_000,
+            reward_numerator: 2,
+            reward_denominator: 1_000,
+        };
+            MAX_RATE_CHECKPOINTS];
+        let hook_program = Pubkey::new_unique();
+        let price_oracle = Pubkey::new_unique();
+        let max_price_age: u64 = 60;
+        let max_price_conf_bps: u16 = 200;
+        let stake_mints = [StakeMint {
+            mint: Pubkey::new_unique(),
+            reserve: Pubkey::new_unique(),
+            weight_bps: 5_000,
+            total_staked: 1_000,
+        }; MAX_STAKE_MINTS];
+        let pair_numerator: u64 = 10;
+        let pair_denominator: u64 = 1;
+        let boost_collection = Pubkey::new_unique();
+        let boost_bps: u16 = 2_500;
+        let badge_mint = Pubkey::new_unique();
+        let tiers: [Tier; 3] = [Tier {
+            min_stake: 1_000,
+            min_duration: DAILY_TS,
+        }; MAX_TIERS];
+        let streak_duration: u64 = 30 * DAILY_TS;
+        let streak_bonus_bps: u16 = 500;
+        let cumulative_stake_seconds: u128 = 7_000_000_000;
+        let stake_seconds_ts: UnixTimestamp = 1_650_000_000;
+        let user_count: u64 = 1_234;
+        let snapshot = Pubkey::new_unique();
+        let snapshot_ts: UnixTimestamp = 1_650_000_300;
+        let top_level_only: bool = true;
+        let integrators = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            Pubkey::default(),
+        ];
+        let has_reward_budget: bool = true;
+        let reward_budget: u64 = 9_000;
+        let max_reward_supply: u64 = 1_000_000;
+        let reward_mode: RewardMode = RewardMode::TransferFromVault;
+        let pool_flags: u32 = POOL_FLAG_ORACLE_PRICED | POOL_FLAG_NFT_BOOST;
+        let stream_duration: u64 = WEEKLY_TS;
+        let warm_up_duration: u64 = 7 * DAILY_TS;
+        let warm_up_rate_bps: u16 = 2_500;
+        let target_tvl: u64 = 5_000_000;
+        let min_reward_numerator: u64 = 1;
+        let max_reward_numerator: u64 = 10;
+        let max_rate_step_bps: u16 = 1_000;
+        let last_rebalance_ts: UnixTimestamp = 1_650_000_000;
+        let max_stake_per_user: u64 = 1_000_000;
+        let gc_delay: u64 = 90 * DAILY_TS;
+        let rate_setter = Pubkey::new_from_array([12u8; 32]);
+        let pauser = Pubkey::new_from_array([13u8; 32]);
+        let treasurer = Pubkey::new_from_array([14u8; 32]);
+        let upgrader = Pubkey::new_from_array([15u8; 32]);
+        let stake_decimals: u8 = 6;
+        let reward_decimals: u8 = 6;
+        let rounding = Rounding::HalfUp;
+        let total_rewards_paid: u64 = 150_000;
+        let has_distribution_report = true;
+        let last_report_epoch: u64 = 420;
+        let lock_tiers = [
+            LockTier {
+                duration: 2_592_000,
+                bonus_bps: 1_000,
+            },
+            LockTier {
+                duration: 31_536_000,
+                bonus_bps: 5_000,
+            },
+            LockTier::default(),
+        ];
 
         let pool = Pool {
             is_initialized,
@@ -257,6 +3443,62 @@ mod tests {
             reward_mint,
             reward_numerator,
             reward_denominator,
+            reward_vault,
+            admin,
+            total_staked,
+            protocol_owned,
+            deprecated,
+            successor,
+            claim_burn_bps,
+            total_burned,
+            reward_period,
+            rate_checkpoints,
+            hook_program,
+            price_oracle,
+            max_price_age,
+            max_price_conf_bps,
+            stake_mints,
+            pair_numerator,
+            pair_denominator,
+            boost_collection,
+            boost_bps,
+            badge_mint,
+            tiers,
+            streak_duration,
+            streak_bonus_bps,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            user_count,
+            snapshot,
+            snapshot_ts,
+            top_level_only,
+            integrators,
+            has_reward_budget,
+            reward_budget,
+            max_reward_supply,
+            reward_mode,
+            pool_flags,
+            stream_duration,
+            warm_up_duration,
+            warm_up_rate_bps,
+            target_tvl,
+            min_reward_numerator,
+            max_reward_numerator,
+            max_rate_step_bps,
+            last_rebalance_ts,
+            max_stake_per_user,
+            gc_delay,
+            rate_setter,
+            pauser,
+            treasurer,
+            upgrader,
+            stake_decimals,
+            reward_decimals,
+            rounding,
+            total_rewards_paid,
+            has_distribution_report,
+            last_report_epoch,
+            lock_tiers,
         };
 
         let mut packed = [0u8; Pool::LEN];
@@ -275,6 +3517,31 @@ mod tests {
         let stake_amount: u64 = 10_000_000_000; // Decimal = 9
         let reward_owed: u64 = 100_000_000;
         let last_update: UnixTimestamp = 100;
+        let mint_balances = [1_000, 0, 0, 2_000];
+        let boost_mint = Pubkey::new_unique();
+        let boost_bps: u16 = 2_500;
+        let staked_since: UnixTimestamp = 1_650_000_000;
+        let badge_amount: u8 = 2;
+        let tier: u8 = 2;
+        let streak_start: UnixTimestamp = 1_650_000_000;
+        let longest_streak: u64 = DAILY_TS;
+        let cumulative_stake_seconds: u128 = 3_000_000;
+        let stake_seconds_ts: UnixTimestamp = 1_650_000_100;
+        let total_claimed: u64 = 40_000;
+        let last_claim_ts: UnixTimestamp = 1_650_000_200;
+        let total_deposited: u64 = 9_000_000;
+        let total_withdrawn: u64 = 4_000_000;
+        let snapshot_stake: u64 = 2_000_000;
+        let stream_balance: u64 = 30_000;
+        let stream_locked: u64 = 20_000;
+        let stream_start: UnixTimestamp = 1_650_000_300;
+        let stream_end: UnixTimestamp = 1_650_604_800;
+        let warming_stake: u64 = 1_000_000;
+        let warm_up_end: UnixTimestamp = 1_650_604_800;
+        let rent_sponsored = true;
+        let claim_split = true;
+        let locked_until: UnixTimestamp = 1_681_536_000;
+        let lock_bonus_bps: u16 = 5_000;
 
         let stake_user = StakeUser {
             is_initialized,
@@ -283,6 +3550,31 @@ mod tests {
             stake_amount,
             reward_owed,
             last_update,
+            mint_balances,
+            boost_mint,
+            boost_bps,
+            staked_since,
+            badge_amount,
+            tier,
+            streak_start,
+            longest_streak,
+            cumulative_stake_seconds,
+            stake_seconds_ts,
+            total_claimed,
+            last_claim_ts,
+            total_deposited,
+            total_withdrawn,
+            snapshot_stake,
+            stream_balance,
+            stream_locked,
+            stream_start,
+            stream_end,
+            warming_stake,
+            warm_up_end,
+            rent_sponsored,
+            claim_split,
+            locked_until,
+            lock_bonus_bps,
         };
 
         let mut packed = [0u8; StakeUser::LEN];
@@ -290,4 +3582,1211 @@ mod tests {
         let unpacked = StakeUser::unpack(&packed).unwrap();
         assert_eq!(stake_user, unpacked);
     }
+
+    #[test]
+    fn test_pool_snapshot_packing() {
+        let pool_snapshot = PoolSnapshot {
+            is_initialized: true,
+            pool_pubkey: Pubkey::new_from_array([1u8; 32]),
+            timestamp: 100,
+            slot: 7,
+            total_staked: 10_000_000_000,
+            reserve_balance: 10_000_000_500,
+            reward_supply: 100_000_000,
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_period: DAILY_TS,
+        };
+
+        let mut packed = [0u8; PoolSnapshot::LEN];
+        PoolSnapshot::pack_into_slice(&pool_snapshot, &mut packed);
+        let unpacked = PoolSnapshot::unpack(&packed).unwrap();
+        assert_eq!(pool_snapshot, unpacked);
+    }
+
+    #[test]
+    fn test_distribution_report_packing() {
+        let distribution_report = DistributionReport {
+            is_initialized: true,
+            pool_pubkey: Pubkey::new_from_array([1u8; 32]),
+            epoch: 500,
+            timestamp: 100,
+            slot: 7,
+            has_previous: true,
+            previous_epoch: 499,
+            reward_supply: 100_000_000,
+            total_rewards_paid: 40_000_000,
+            rewards_paid: 1_000_000,
+            total_burned: 5_000_000,
+            fees_collected: 25_000,
+            total_staked: 10_000_000_000,
+            stake_delta: -2_000_000,
+        };
+
+        let mut packed = [0u8; DistributionReport::LEN];
+        DistributionReport::pack_into_slice(&distribution_report, &mut packed);
+        let unpacked = DistributionReport::unpack(&packed).unwrap();
+        assert_eq!(distribution_report, unpacked);
+    }
+
+    #[test]
+    fn test_claim_split_packing() {
+        let mut claim_split = ClaimSplit {
+            is_initialized: true,
+            stake_user_pubkey: Pubkey::new_from_array([1u8; 32]),
+            ..ClaimSplit::default()
+        };
+        let share = |seed, bps| ClaimShare {
+            destination: Pubkey::new_from_array([seed; 32]),
+            bps,
+        };
+        claim_split
+            .set_shares([
+                share(2, 2_500),
+                share(3, 1_000),
+                ClaimShare::default(),
+                ClaimShare::default(),
+            ])
+            .unwrap();
+
+        let mut packed = [0u8; ClaimSplit::LEN];
+        ClaimSplit::pack_into_slice(&claim_split, &mut packed);
+        let unpacked = ClaimSplit::unpack(&packed).unwrap();
+        assert_eq!(claim_split, unpacked);
+
+        // shares round down, the rest staying with the claim
+        assert_eq!(claim_split.split(1_001).unwrap(), (vec![250, 100], 651));
+        assert_eq!(claim_split.split(0).unwrap(), (vec![0, 0], 0));
+
+        for shares in [
+            [
+                share(2, 2_500),
+                ClaimShare::default(),
+                share(3, 1_000),
+                ClaimShare::default(),
+            ],
+            [
+                share(2, 2_500),
+                share(2, 1_000),
+                ClaimShare::default(),
+                ClaimShare::default(),
+            ],
+            [
+                share(2, 5_000),
+                share(3, 5_001),
+                ClaimShare::default(),
+                ClaimShare::default(),
+            ],
+        ] {
+            assert_eq!(
+                claim_split.clone().set_shares(shares),
+                Err(CustomError::InvalidClaimShares.into())
+            );
+        }
+        // the whole claim can be split away
+        claim_split
+            .set_shares([
+                share(2, 5_000),
+                share(3, 5_000),
+                ClaimShare::default(),
+                ClaimShare::default(),
+            ])
+            .unwrap();
+        assert_eq!(claim_split.split(7).unwrap(), (vec![3, 3], 1));
+    }
+
+    #[test]
+    fn test_stake_snapshot_packing() {
+        let mut stake_snapshot = StakeSnapshot {
+            is_initialized: true,
+            pool_pubkey: Pubkey::new_from_array([1u8; 32]),
+            timestamp: 100,
+            last_stake_user: Pubkey::new_from_array([2u8; 32]),
+            ..StakeSnapshot::default()
+        };
+        let stake_user = StakeUser {
+            pool_pubkey: stake_snapshot.pool_pubkey,
+            stake_amount: 1_000,
+            ..StakeUser::default()
+        };
+        stake_snapshot
+            .add_stake_user(&Pubkey::new_from_array([3u8; 32]), &stake_user)
+            .unwrap();
+        stake_snapshot.finalize();
+
+        let mut packed = [0u8; StakeSnapshot::LEN];
+        StakeSnapshot::pack_into_slice(&stake_snapshot, &mut packed);
+        let unpacked = StakeSnapshot::unpack(&packed).unwrap();
+        assert_eq!(stake_snapshot, unpacked);
+        assert_eq!(unpacked.total_staked, 1_000);
+    }
+
+    fn zeros(len: usize) -> String {
+        "00".repeat(len)
+    }
+
+    /// Packed pool of the current layout, field by field, for indexers and
+    /// clients in other languages to check their decoding against
+    fn pool_golden_vector() -> (Pool, Vec<(&'static str, String)>) {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        let mut rate_checkpoints = [RateCheckpoint::default(); MAX_RATE_CHECKPOINTS];
+        rate_checkpoints[0] = RateCheckpoint {
+            epoch: 0,
+            start_ts: 1_650_000_000,
+            reward_numerator: 2,
+            reward_denominator: 1_000,
+        };
+        rate_checkpoints[1] = RateCheckpoint {
+            epoch: 300,
+            start_ts: 0,
+            reward_numerator: 3,
+            reward_denominator: 1_000,
+        };
+        let mut stake_mints = [StakeMint::default(); MAX_STAKE_MINTS];
+        stake_mints[0] = StakeMint {
+            mint: key(9),
+            reserve: key(10),
+            weight_bps: 5_000,
+            total_staked: 1_000,
+        };
+        let pool = Pool {
+            is_initialized: true,
+            bump_seed: 254,
+            stake_token_mint: key(1),
+            reserved: key(2),
+            reward_mint: key(3),
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_vault: key(4),
+            admin: key(5),
+            total_staked: 20_000_000_000,
+            protocol_owned: 300_000_000,
+            deprecated: true,
+            successor: key(6),
+            claim_burn_bps: 250,
+            total_burned: 5_000_000,
+            reward_period: WEEKLY_TS,
+            rate_checkpoints,
+            hook_program: key(7),
+            price_oracle: key(8),
+            max_price_age: 60,
+            max_price_conf_bps: 200,
+            stake_mints,
+            pair_numerator: 10,
+            pair_denominator: 1,
+            boost_collection: key(11),
+            boost_bps: 2_500,
+            badge_mint: key(12),
+            tiers: [
+                Tier {
+                    min_stake: 1_000,
+                    min_duration: DAILY_TS,
+                },
+                Tier {
+                    min_stake: 10_000,
+                    min_duration: WEEKLY_TS,
+                },
+                Tier::default(),
+            ],
+            streak_duration: 30 * DAILY_TS,
+            streak_bonus_bps: 500,
+            cumulative_stake_seconds: 7_000_000_000,
+            stake_seconds_ts: 1_650_000_000,
+            user_count: 1_234,
+            snapshot: key(13),
+            snapshot_ts: 1_650_000_300,
+            top_level_only: true,
+            integrators: [
+                key(14),
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+            ],
+            has_reward_budget: true,
+            reward_budget: 9_000,
+            max_reward_supply: 1_000_000,
+            reward_mode: RewardMode::TransferFromVault,
+            pool_flags: POOL_FLAG_ORACLE_PRICED | POOL_FLAG_NFT_BOOST | POOL_FLAG_LOCKED,
+            stream_duration: WEEKLY_TS,
+            warm_up_duration: WEEKLY_TS,
+            warm_up_rate_bps: 2_500,
+            target_tvl: 5_000_000,
+            min_reward_numerator: 1,
+            max_reward_numerator: 10,
+            max_rate_step_bps: 1_000,
+            last_rebalance_ts: 1_650_000_000,
+            max_stake_per_user: 1_000_000,
+            gc_delay: 90 * DAILY_TS,
+            rate_setter: key(15),
+            pauser: key(16),
+            treasurer: key(17),
+            upgrader: key(18),
+            stake_decimals: 6,
+            reward_decimals: 9,
+            rounding: Rounding::HalfUp,
+            total_rewards_paid: 150_000,
+            has_distribution_report: true,
+            last_report_epoch: 420,
+            lock_tiers: [
+                LockTier {
+                    duration: 2_592_000,
+                    bonus_bps: 1_000,
+                },
+                LockTier {
+                    duration: 31_536_000,
+                    bonus_bps: 5_000,
+                },
+                LockTier::default(),
+            ],
+        };
+        #[rustfmt::skip]
+        let fields = vec![
+            ("is_initialized", "01".to_string()),
+            ("bump_seed", "fe".to_string()),
+            ("stake_token_mint", "0101010101010101010101010101010101010101010101010101010101010101".to_string()),
+            ("reserved", "0202020202020202020202020202020202020202020202020202020202020202".to_string()),
+            ("reward_mint", "0303030303030303030303030303030303030303030303030303030303030303".to_string()),
+            ("reward_numerator", "0100000000000000".to_string()),
+            ("reward_denominator", "e803000000000000".to_string()),
+            ("reward_vault", "0404040404040404040404040404040404040404040404040404040404040404".to_string()),
+            ("admin", "0505050505050505050505050505050505050505050505050505050505050505".to_string()),
+            ("total_staked", "00c817a804000000".to_string()),
+            ("protocol_owned", "00a3e11100000000".to_string()),
+            ("deprecated", "01".to_string()),
+            ("successor", "0606060606060606060606060606060606060606060606060606060606060606".to_string()),
+            ("claim_burn_bps", "fa00".to_string()),
+            ("total_burned", "404b4c0000000000".to_string()),
+            ("reward_period", "803a090000000000".to_string()),
+            ("rate_checkpoints[0]", "000000000000000080005962000000000200000000000000e803000000000000".to_string()),
+            ("rate_checkpoints[1]", "2c0100000000000000000000000000000300000000000000e803000000000000".to_string()),
+            ("rate_checkpoints[2..8]", zeros(192)),
+            ("hook_program", "0707070707070707070707070707070707070707070707070707070707070707".to_string()),
+            ("price_oracle", "0808080808080808080808080808080808080808080808080808080808080808".to_string()),
+            ("max_price_age", "3c00000000000000".to_string()),
+            ("max_price_conf_bps", "c800".to_string()),
+            ("stake_mints[0]", "09090909090909090909090909090909090909090909090909090909090909090a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a8813e803000000000000".to_string()),
+            ("stake_mints[1..4]", zeros(222)),
+            ("pair_numerator", "0a00000000000000".to_string()),
+            ("pair_denominator", "0100000000000000".to_string()),
+            ("boost_collection", "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b".to_string()),
+            ("boost_bps", "c409".to_string()),
+            ("badge_mint", "0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c".to_string()),
+            ("tiers[0]", "e8030000000000008051010000000000".to_string()),
+            ("tiers[1]", "1027000000000000803a090000000000".to_string()),
+            ("tiers[2]", zeros(16)),
+            ("streak_duration", "008d270000000000".to_string()),
+            ("streak_bonus_bps", "f401".to_string()),
+            ("cumulative_stake_seconds", "00863ba1010000000000000000000000".to_string()),
+            ("stake_seconds_ts", "8000596200000000".to_string()),
+            ("user_count", "d204000000000000".to_string()),
+            ("snapshot", "0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d".to_string()),
+            ("snapshot_ts", "ac01596200000000".to_string()),
+            ("top_level_only", "01".to_string()),
+            ("integrators[0]", "0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e".to_string()),
+            ("integrators[1..4]", zeros(96)),
+            ("has_reward_budget", "01".to_string()),
+            ("reward_budget", "2823000000000000".to_string()),
+            ("max_reward_supply", "40420f0000000000".to_string()),
+            ("reward_mode", "01".to_string()),
+            ("pool_flags", "0d000000".to_string()),
+            ("stream_duration", "803a090000000000".to_string()),
+            ("warm_up_duration", "803a090000000000".to_string()),
+            ("warm_up_rate_bps", "c409".to_string()),
+            ("target_tvl", "404b4c0000000000".to_string()),
+            ("min_reward_numerator", "0100000000000000".to_string()),
+            ("max_reward_numerator", "0a00000000000000".to_string()),
+            ("max_rate_step_bps", "e803".to_string()),
+            ("last_rebalance_ts", "8000596200000000".to_string()),
+            ("max_stake_per_user", "40420f0000000000".to_string()),
+            ("gc_delay", "00a7760000000000".to_string()),
+            ("rate_setter", "0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f".to_string()),
+            ("pauser", "1010101010101010101010101010101010101010101010101010101010101010".to_string()),
+            ("treasurer", "1111111111111111111111111111111111111111111111111111111111111111".to_string()),
+            ("upgrader", "1212121212121212121212121212121212121212121212121212121212121212".to_string()),
+            ("stake_decimals", "06".to_string()),
+            ("reward_decimals", "09".to_string()),
+            ("rounding", "01".to_string()),
+            ("total_rewards_paid", "f049020000000000".to_string()),
+            ("has_distribution_report", "01".to_string()),
+            ("last_report_epoch", "a401000000000000".to_string()),
+            ("lock_tiers[0]", "008d270000000000e803".to_string()),
+            ("lock_tiers[1]", "8033e101000000008813".to_string()),
+            ("lock_tiers[2]", zeros(10)),
+        ];
+        (pool, fields)
+    }
+
+    /// Packed stake user of the current layout, field by field
+    fn stake_user_golden_vector() -> (StakeUser, Vec<(&'static str, String)>) {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        let stake_user = StakeUser {
+            is_initialized: true,
+            owner: key(1),
+            pool_pubkey: key(2),
+            stake_amount: 10_000_000_000,
+            reward_owed: 100_000_000,
+            last_update: 1_650_000_000,
+            mint_balances: [1_000, 0, 0, 2_000],
+            boost_mint: key(3),
+            boost_bps: 2_500,
+            staked_since: 1_650_000_000,
+            badge_amount: 2,
+            tier: 2,
+            streak_start: 1_650_000_000,
+            longest_streak: DAILY_TS,
+            cumulative_stake_seconds: 3_000_000,
+            stake_seconds_ts: 1_650_000_100,
+            total_claimed: 40_000,
+            last_claim_ts: 1_650_000_200,
+            total_deposited: 9_000_000,
+            total_withdrawn: 4_000_000,
+            snapshot_stake: 2_000_000,
+            stream_balance: 30_000,
+            stream_locked: 20_000,
+            stream_start: 1_650_000_300,
+            stream_end: 1_650_604_800,
+            warming_stake: 1_000_000,
+            warm_up_end: 1_650_604_800,
+            rent_sponsored: true,
+            claim_split: true,
+            locked_until: 1_681_536_000,
+            lock_bonus_bps: 5_000,
+        };
+        #[rustfmt::skip]
+        let fields = vec![
+            ("is_initialized", "01".to_string()),
+            ("owner", "0101010101010101010101010101010101010101010101010101010101010101".to_string()),
+            ("pool_pubkey", "0202020202020202020202020202020202020202020202020202020202020202".to_string()),
+            ("stake_amount", "00e40b5402000000".to_string()),
+            ("reward_owed", "00e1f50500000000".to_string()),
+            ("last_update", "8000596200000000".to_string()),
+            ("mint_balances[0]", "e803000000000000".to_string()),
+            ("mint_balances[1..3]", zeros(16)),
+            ("mint_balances[3]", "d007000000000000".to_string()),
+            ("boost_mint", "0303030303030303030303030303030303030303030303030303030303030303".to_string()),
+            ("boost_bps", "c409".to_string()),
+            ("staked_since", "8000596200000000".to_string()),
+            ("badge_amount", "02".to_string()),
+            ("tier", "02".to_string()),
+            ("streak_start", "8000596200000000".to_string()),
+            ("longest_streak", "8051010000000000".to_string()),
+            ("cumulative_stake_seconds", "c0c62d00000000000000000000000000".to_string()),
+            ("stake_seconds_ts", "e400596200000000".to_string()),
+            ("total_claimed", "409c000000000000".to_string()),
+            ("last_claim_ts", "4801596200000000".to_string()),
+            ("total_deposited", "4054890000000000".to_string()),
+            ("total_withdrawn", "00093d0000000000".to_string()),
+            ("snapshot_stake", "80841e0000000000".to_string()),
+            ("stream_balance", "3075000000000000".to_string()),
+            ("stream_locked", "204e000000000000".to_string()),
+            ("stream_start", "ac01596200000000".to_string()),
+            ("stream_end", "003b626200000000".to_string()),
+            ("warming_stake", "40420f0000000000".to_string()),
+            ("warm_up_end", "003b626200000000".to_string()),
+            ("rent_sponsored", "01".to_string()),
+            ("claim_split", "01".to_string()),
+            ("locked_until", "00343a6400000000".to_string()),
+            ("lock_bonus_bps", "8813".to_string()),
+        ];
+        (stake_user, fields)
+    }
+
+    /// Check a value packs to the bytes of its golden vector and back,
+    /// returning the offsets of the fields
+    fn check_golden_vector<T: Pack + IsInitialized + PartialEq + std::fmt::Debug>(
+        value: T,
+        fields: &[(&'static str, String)],
+    ) -> Vec<(&'static str, usize)> {
+        let mut expected = vec![];
+        let mut offsets = vec![];
+        for (field, hex) in fields {
+            offsets.push((*field, expected.len()));
+            let bytes: Vec<u8> = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect();
+            expected.extend(bytes);
+        }
+        assert_eq!(expected.len(), T::LEN);
+
+        let mut packed = vec![0u8; T::LEN];
+        T::pack_into_slice(&value, &mut packed);
+        for (index, (field, offset)) in offsets.iter().enumerate() {
+            let end = offsets.get(index + 1).map_or(T::LEN, |(_, end)| *end);
+            assert_eq!(packed[*offset..end], expected[*offset..end], "{}", field);
+        }
+        assert_eq!(T::unpack(&expected).unwrap(), value);
+        offsets
+    }
+
+    #[test]
+    fn test_pool_golden_vector() {
+        let (pool, fields) = pool_golden_vector();
+        let offsets = check_golden_vector(pool, &fields);
+        let offset = |name: &str| offsets.iter().find(|(field, _)| *field == name).unwrap().1;
+        assert_eq!(offset("stake_token_mint"), Pool::STAKE_TOKEN_MINT_OFFSET);
+        assert_eq!(offset("reserved"), Pool::RESERVED_OFFSET);
+        assert_eq!(offset("reward_mint"), Pool::REWARD_MINT_OFFSET);
+        assert_eq!(offset("admin"), Pool::ADMIN_OFFSET);
+        assert_eq!(offset("hook_program"), Pool::HOOK_PROGRAM_OFFSET);
+        assert_eq!(offset("top_level_only"), Pool::TOP_LEVEL_ONLY_OFFSET);
+    }
+
+    #[test]
+    fn test_stake_user_golden_vector() {
+        let (stake_user, fields) = stake_user_golden_vector();
+        let offsets = check_golden_vector(stake_user, &fields);
+        let offset = |name: &str| offsets.iter().find(|(field, _)| *field == name).unwrap().1;
+        assert_eq!(offset("owner"), StakeUser::OWNER_OFFSET);
+        assert_eq!(offset("pool_pubkey"), StakeUser::POOL_PUBKEY_OFFSET);
+        assert_eq!(offset("tier"), StakeUser::TIER_OFFSET);
+    }
+
+    #[test]
+    fn test_legacy_layout() {
+        // an initialized pool and stake user of the first release
+        let mut legacy_pool = [0u8; LEGACY_POOL_SIZE];
+        legacy_pool[0] = 1;
+        legacy_pool[1] = 255;
+        let mut legacy_stake_user = [0u8; LEGACY_STAKE_USER_SIZE];
+        legacy_stake_user[0] = 1;
+
+        let legacy = Some(ProgramError::from(CustomError::LegacyAccountLayout));
+        assert_eq!(Pool::unpack(&legacy_pool).err(), legacy);
+        assert_eq!(Pool::unpack_bump_seed(&legacy_pool).err(), legacy);
+        assert_eq!(Pool::unpack_hook_program(&legacy_pool).err(), legacy);
+        assert_eq!(Pool::unpack_top_level_only(&legacy_pool).err(), legacy);
+        assert_eq!(StakeUser::unpack(&legacy_stake_user).err(), legacy);
+        assert_eq!(StakeUser::unpack_owner(&legacy_stake_user).err(), legacy);
+        assert_eq!(
+            StakeUser::unpack_pool_pubkey(&legacy_stake_user).err(),
+            legacy
+        );
+
+        // other sizes are plainly invalid
+        assert_eq!(
+            Pool::unpack(&[0u8; LEGACY_POOL_SIZE + 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            StakeUser::unpack(&[0u8; LEGACY_STAKE_USER_SIZE - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let pool = Pool {
+            stake_token_mint: Pubkey::new_unique(),
+            integrators: [Pubkey::new_unique(); MAX_INTEGRATORS],
+            cumulative_stake_seconds: u128::MAX,
+            ..Pool::default()
+        };
+        let json = serde_json::to_value(&pool).unwrap();
+        assert_eq!(
+            json["stake_token_mint"],
+            pool.stake_token_mint.to_string().as_str()
+        );
+        assert_eq!(
+            json["integrators"][3],
+            pool.integrators[3].to_string().as_str()
+        );
+        assert_eq!(serde_json::from_value::<Pool>(json).unwrap(), pool);
+
+        let stake_user = StakeUser {
+            owner: Pubkey::new_unique(),
+            stake_amount: 100,
+            ..StakeUser::default()
+        };
+        let json = serde_json::to_string(&stake_user).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StakeUser>(&json).unwrap(),
+            stake_user
+        );
+    }
+
+    #[test]
+    fn test_pool_authority() {
+        let program_id = Pubkey::new_unique();
+        let pool_pubkey = Pubkey::new_unique();
+        let (authority, bump_seed) = Pool::authority(&program_id, &pool_pubkey);
+        let pool = Pool {
+            bump_seed,
+            ..Pool::default()
+        };
+        assert_eq!(
+            pool.authority_address(&program_id, &pool_pubkey).unwrap(),
+            authority
+        );
+        assert_eq!(
+            Pubkey::create_program_address(
+                &Pool::authority_seeds(&pool_pubkey, &bump_seed),
+                &program_id
+            )
+            .unwrap(),
+            authority
+        );
+    }
+
+    #[test]
+    fn test_pool_flags() {
+        let mut pool = Pool::default();
+        pool.set_flags(POOL_FLAG_ORACLE_PRICED | POOL_FLAG_PAIRED, true);
+        pool.set_flags(POOL_FLAG_PAIRED, false);
+        assert!(pool.is_oracle_priced());
+        assert!(!pool.is_pair_pool());
+
+        // pools of a newer variant don't load
+        let mut packed = vec![0; Pool::LEN];
+        pool.pool_flags |= 1 << 31;
+        Pool::pack(pool, &mut packed).unwrap();
+        assert_eq!(
+            Pool::unpack_unchecked(&packed),
+            Err(CustomError::UnsupportedPoolType.into())
+        );
+    }
+
+    #[test]
+    fn test_filter_offsets() {
+        let key = |offset: usize| Pubkey::new_from_array([offset as u8; 32]);
+        let at =
+            |packed: &[u8], offset: usize| Pubkey::new_from_array(*array_ref![packed, offset, 32]);
+
+        let pool = Pool {
+            stake_token_mint: key(Pool::STAKE_TOKEN_MINT_OFFSET),
+            reserved: key(Pool::RESERVED_OFFSET),
+            reward_mint: key(Pool::REWARD_MINT_OFFSET),
+            admin: key(Pool::ADMIN_OFFSET),
+            hook_program: key(Pool::HOOK_PROGRAM_OFFSET),
+            top_level_only: true,
+            bump_seed: 254,
+            ..Pool::default()
+        };
+        let mut packed = [0u8; Pool::LEN];
+        Pool::pack_into_slice(&pool, &mut packed);
+        for offset in [
+            Pool::STAKE_TOKEN_MINT_OFFSET,
+            Pool::RESERVED_OFFSET,
+            Pool::REWARD_MINT_OFFSET,
+            Pool::ADMIN_OFFSET,
+            Pool::HOOK_PROGRAM_OFFSET,
+        ] {
+            assert_eq!(at(&packed, offset), key(offset));
+        }
+        assert_eq!(
+            Pool::unpack_hook_program(&packed),
+            Ok(Some(key(Pool::HOOK_PROGRAM_OFFSET)))
+        );
+        assert_eq!(Pool::unpack_top_level_only(&packed), Ok(true));
+        assert_eq!(Pool::unpack_bump_seed(&packed), Ok(254));
+        assert_eq!(
+            Pool::unpack_bump_seed(&packed[..Pool::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let stake_user = StakeUser {
+            owner: key(StakeUser::OWNER_OFFSET),
+            pool_pubkey: key(StakeUser::POOL_PUBKEY_OFFSET),
+            tier: 3,
+            ..StakeUser::default()
+        };
+        let mut packed = [0u8; StakeUser::LEN];
+        StakeUser::pack_into_slice(&stake_user, &mut packed);
+        for offset in [StakeUser::OWNER_OFFSET, StakeUser::POOL_PUBKEY_OFFSET] {
+            assert_eq!(at(&packed, offset), key(offset));
+        }
+        assert_eq!(packed[StakeUser::TIER_OFFSET], 3);
+        assert_eq!(
+            StakeUser::unpack_owner(&packed),
+            Err(ProgramError::UninitializedAccount)
+        );
+        packed[0] = 1;
+        assert_eq!(
+            StakeUser::unpack_owner(&packed),
+            Ok(key(StakeUser::OWNER_OFFSET))
+        );
+        assert_eq!(
+            StakeUser::unpack_pool_pubkey(&packed),
+            Ok(key(StakeUser::POOL_PUBKEY_OFFSET))
+        );
+        assert_eq!(
+            StakeUser::unpack_pool_pubkey(&packed[..StakeUser::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let pool_snapshot = PoolSnapshot {
+            pool_pubkey: key(PoolSnapshot::POOL_PUBKEY_OFFSET),
+            ..PoolSnapshot::default()
+        };
+        let mut packed = [0u8; PoolSnapshot::LEN];
+        PoolSnapshot::pack_into_slice(&pool_snapshot, &mut packed);
+        assert_eq!(
+            at(&packed, PoolSnapshot::POOL_PUBKEY_OFFSET),
+            key(PoolSnapshot::POOL_PUBKEY_OFFSET)
+        );
+
+        let distribution_report = DistributionReport {
+            pool_pubkey: key(DistributionReport::POOL_PUBKEY_OFFSET),
+            ..DistributionReport::default()
+        };
+        let mut packed = [0u8; DistributionReport::LEN];
+        DistributionReport::pack_into_slice(&distribution_report, &mut packed);
+        assert_eq!(
+            at(&packed, DistributionReport::POOL_PUBKEY_OFFSET),
+            key(DistributionReport::POOL_PUBKEY_OFFSET)
+        );
+    }
+
+    #[test]
+    fn test_split_claim_burn() {
+        let mut pool = Pool {
+            claim_burn_bps: 250,
+            ..Pool::default()
+        };
+
+        assert_eq!(pool.split_claim_burn(10_000).unwrap(), (9_750, 250));
+        assert_eq!(pool.split_claim_burn(u64::MAX).unwrap().1, u64::MAX / 40);
+        assert_eq!(pool.total_burned, 250 + u64::MAX / 40);
+        assert_eq!(pool.total_rewards_paid, 9_750 + (u64::MAX - u64::MAX / 40));
+
+        pool.claim_burn_bps = 0;
+        assert_eq!(pool.split_claim_burn(10_000).unwrap(), (10_000, 0));
+    }
+
+    #[test]
+    fn test_pair_ratio() {
+        let mut pool = Pool::default();
+        assert_eq!(
+            pool.set_pair_ratio(10, 1),
+            Err(CustomError::InvalidTokenMint.into())
+        );
+        pool.add_stake_mint(Pubkey::new_unique(), Pubkey::new_unique(), 10_000)
+            .unwrap();
+        assert_eq!(
+            pool.set_pair_ratio(0, 1),
+            Err(CustomError::InvalidPairRatio.into())
+        );
+        pool.set_pair_ratio(10, 3).unwrap();
+        assert_eq!(
+            pool.set_pair_ratio(10, 1),
+            Err(CustomError::InvalidPairRatio.into())
+        );
+        assert_eq!(pool.paired_amount(3).unwrap(), 10);
+        assert_eq!(pool.paired_amount(4).unwrap(), 14);
+
+        let mut stake_user = StakeUser {
+            stake_amount: 3,
+            ..StakeUser::default()
+        };
+        stake_user.mint_balances[0] = 10;
+        assert_eq!(stake_user.paired_share(1).unwrap(), 3);
+        assert_eq!(stake_user.paired_share(3).unwrap(), 10);
+        assert_eq!(
+            stake_user.paired_share(4),
+            Err(CustomError::InsufficientLiquidity.into())
+        );
+    }
+
+    #[test]
+    fn test_loyalty_tiers() {
+        let mut pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        pool.set_tiers([
+            Tier {
+                min_stake: 100,
+                min_duration: 0,
+            },
+            Tier {
+                min_stake: 100,
+                min_duration: DAILY_TS,
+            },
+            Tier {
+                min_stake: 1_000,
+                min_duration: WEEKLY_TS,
+            },
+        ])
+        .unwrap();
+
+        let mut stake_user = StakeUser::default();
+        stake_user.begin_stake(&pool, 0);
+        stake_user.stake(500).unwrap();
+        stake_user.update_reward_owed(&pool, 1).unwrap();
+        assert_eq!(stake_user.loyalty_tier(), LoyaltyTier::Bronze);
+        stake_user
+            .update_reward_owed(&pool, WEEKLY_TS as i64)
+            .unwrap();
+        assert_eq!(stake_user.loyalty_tier(), LoyaltyTier::Silver);
+        stake_user.stake(500).unwrap();
+        stake_user
+            .update_reward_owed(&pool, WEEKLY_TS as i64 + 1)
+            .unwrap();
+        assert_eq!(stake_user.loyalty_tier(), LoyaltyTier::Gold);
+
+        // a full unstake starts over
+        stake_user.unstake(1_000).unwrap();
+        assert_eq!(stake_user.loyalty_tier(), LoyaltyTier::None);
+    }
+
+    #[test]
+    fn test_reward_stream() {
+        let pool = Pool {
+            stream_duration: 100,
+            ..Pool::default()
+        };
+        let mut stake_user = StakeUser::default();
+        stake_user.open_stream(&pool, 1_000, 0).unwrap();
+        assert_eq!(stake_user.stream_withdrawable(0).unwrap(), 0);
+        assert_eq!(stake_user.stream_withdrawable(25).unwrap(), 250);
+        stake_user.withdraw_stream(250, 25).unwrap();
+        assert_eq!(
+            stake_user.withdraw_stream(1, 25),
+            Err(CustomError::InsufficientClaimAmount.into())
+        );
+
+        // topping up keeps what was released and restarts what is still locked
+        stake_user.open_stream(&pool, 250, 50).unwrap();
+        assert_eq!(stake_user.stream_balance, 1_000);
+        assert_eq!(stake_user.stream_locked, 750);
+        assert_eq!(stake_user.stream_withdrawable(50).unwrap(), 250);
+        assert_eq!(stake_user.stream_withdrawable(100).unwrap(), 625);
+        // locked shares round up
+        assert_eq!(stake_user.stream_withdrawable(51).unwrap(), 257);
+        assert_eq!(stake_user.stream_withdrawable(150).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn test_stake_seconds() {
+        let pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let mut total = pool.clone();
+
+        let mut stake_user = StakeUser::default();
+        stake_user.begin_stake(&pool, 100);
+        stake_user.stake(1_000).unwrap();
+        total.observe_stake_seconds(100).unwrap();
+        total.stake(1_000).unwrap();
+
+        stake_user.update_reward_owed(&pool, 200).unwrap();
+        stake_user.unstake(500).unwrap();
+        total.observe_stake_seconds(200).unwrap();
+        total.unstake(500).unwrap();
+        assert_eq!(stake_user.cumulative_stake_seconds, 100_000);
+
+        // readings don't need a mutation, the average is their difference over time
+        assert_eq!(stake_user.stake_seconds_at(300).unwrap(), 150_000);
+        assert_eq!(total.stake_seconds_at(300).unwrap(), 150_000);
+        assert_eq!(stake_user.stake_seconds_at(300).unwrap() / 200, 750);
+        // going backwards adds nothing
+        assert_eq!(total.stake_seconds_at(50).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn test_streak_bonus() {
+        let pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1,
+            reward_period: DAILY_TS,
+            streak_duration: 2 * DAILY_TS,
+            streak_bonus_bps: 1_000,
+            ..Pool::default()
+        };
+        let day = DAILY_TS as i64;
+
+        let mut stake_user = StakeUser::default();
+        stake_user.begin_stake(&pool, 0);
+        stake_user.stake(1_000).unwrap();
+        stake_user.update_reward_owed(&pool, day).unwrap();
+        assert_eq!(stake_user.reward_owed, 1_000);
+        // the bonus applies from the second day on
+        stake_user.update_reward_owed(&pool, 3 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 3_100);
+        assert_eq!(stake_user.longest_streak, 3 * DAILY_TS);
+
+        // unstaking resets the streak, keeping the longest
+        stake_user.end_streak(3 * day);
+        stake_user.unstake(500).unwrap();
+        stake_user.update_reward_owed(&pool, 5 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 4_100);
+        assert_eq!(stake_user.longest_streak, 3 * DAILY_TS);
+    }
+
+    #[test]
+    fn test_lock_tiers() {
+        let mut pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let day = DAILY_TS as i64;
+        let week = LockTier {
+            duration: WEEKLY_TS,
+            bonus_bps: 1_000,
+        };
+        let month = LockTier {
+            duration: 30 * DAILY_TS,
+            bonus_bps: 5_000,
+        };
+        assert_eq!(
+            pool.set_lock_tiers([month, week, LockTier::default()]),
+            Err(CustomError::InvalidLockTiers.into())
+        );
+        assert_eq!(
+            pool.set_lock_tiers([LockTier::default(), week, month]),
+            Err(CustomError::InvalidLockTiers.into())
+        );
+        assert_eq!(
+            pool.set_lock_tiers([
+                week,
+                LockTier {
+                    duration: 30 * DAILY_TS,
+                    bonus_bps: 10_001,
+                },
+                LockTier::default()
+            ]),
+            Err(CustomError::InvalidBasisPoints.into())
+        );
+        pool.set_lock_tiers([week, month, LockTier::default()])
+            .unwrap();
+        assert!(pool.is_locked());
+
+        let mut stake_user = StakeUser::default();
+        stake_user.begin_stake(&pool, 0);
+        stake_user.stake(1_000).unwrap();
+        assert_eq!(
+            stake_user.relock(&pool, 2, 0),
+            Err(CustomError::InvalidRelock.into())
+        );
+        stake_user.relock(&pool, 0, 0).unwrap();
+        assert!(stake_user.is_locked(&pool, 6 * day));
+        assert!(!stake_user.is_locked(&pool, 7 * day));
+
+        // the bonus applies while locked only
+        stake_user.update_reward_owed(&pool, 2 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 2_200);
+        stake_user.update_reward_owed(&pool, 8 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 2_200 + 5_500 + 1_000);
+
+        // a running lock can't end sooner or drop its bonus
+        stake_user.relock(&pool, 1, 8 * day).unwrap();
+        assert_eq!(stake_user.locked_until, 38 * day);
+        assert_eq!(
+            stake_user.relock(&pool, 0, 9 * day),
+            Err(CustomError::InvalidRelock.into())
+        );
+        stake_user.relock(&pool, 1, 9 * day).unwrap();
+        assert_eq!(stake_user.locked_until, 39 * day);
+
+        // deprecating the pool releases the lock
+        pool.deprecated = true;
+        assert!(!stake_user.is_locked(&pool, 9 * day));
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1,
+            reward_period: DAILY_TS,
+            warm_up_duration: 4 * DAILY_TS,
+            warm_up_rate_bps: 5_000,
+            ..Pool::default()
+        };
+        let day = DAILY_TS as i64;
+
+        let mut stake_user = StakeUser::default();
+        stake_user.begin_stake(&pool, 0);
+        stake_user.stake(1_000).unwrap();
+        stake_user.start_warm_up(&pool, 1_000, 0).unwrap();
+        stake_user.update_reward_owed(&pool, 2 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 1_000);
+
+        // unstakes take the stake that warmed up first
+        stake_user.unstake(500).unwrap();
+        assert_eq!(stake_user.warming_stake(&pool, 2 * day).unwrap(), 500);
+        stake_user.update_reward_owed(&pool, 6 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 1_000 + 500 + 1_000);
+        assert_eq!(stake_user.warming_stake, 0);
+
+        // deposits after a full unstake warm up on their own
+        stake_user.unstake(500).unwrap();
+        stake_user.begin_stake(&pool, 6 * day);
+        stake_user.stake(100).unwrap();
+        stake_user.start_warm_up(&pool, 100, 6 * day).unwrap();
+        assert_eq!(stake_user.warm_up_end, 10 * day);
+    }
+
+    #[test]
+    fn test_accrue_over_epoch_boundaries() {
+        let mut pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let stake_amount = 1_000_000;
+        let day = DAILY_TS as i64;
+
+        // time-based: 1_000 per day
+        assert!(!pool.is_epoch_based());
+        assert_eq!(pool.accrue(stake_amount, 0, 2 * day).unwrap(), 2_000);
+
+        pool.set_epoch_rate(11, 2, 1_000).unwrap();
+        pool.set_epoch_rate(10, 4, 1_000).unwrap();
+        assert!(pool.is_epoch_based());
+        assert_eq!(pool.rate_checkpoints[0].epoch, 10);
+        assert_eq!(pool.rate_checkpoints[1].epoch, 11);
+
+        // unobserved epochs don't change accrual yet
+        assert_eq!(pool.accrue(stake_amount, 0, 2 * day).unwrap(), 2_000);
+
+        pool.observe_epoch(10, day);
+        pool.observe_epoch(11, 3 * day);
+        // 1 day at base rate, 2 days in epoch 10, 1 day in epoch 11
+        assert_eq!(
+            pool.accrue(stake_amount, 0, 4 * day).unwrap(),
+            1_000 + 8_000 + 2_000
+        );
+        // starting inside epoch 10
+        assert_eq!(
+            pool.accrue(stake_amount, 2 * day, 4 * day).unwrap(),
+            4_000 + 2_000
+        );
+
+        let mut stake_user = StakeUser {
+            stake_amount,
+            last_update: 0,
+            ..StakeUser::default()
+        };
+        stake_user.update_reward_owed(&pool, 4 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 11_000);
+        assert_eq!(stake_user.last_update, 4 * day);
+    }
+
+    #[test]
+    fn test_rate_checkpoints_eviction() {
+        let mut pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        for epoch in 0..MAX_RATE_CHECKPOINTS as u64 {
+            pool.set_epoch_rate(epoch + 1, epoch + 1, 1_000).unwrap();
+        }
+        assert_eq!(
+            pool.set_epoch_rate(100, 1, 1_000),
+            Err(CustomError::RateCheckpointsFull.into())
+        );
+
+        pool.observe_epoch(1, 100);
+        pool.set_epoch_rate(100, 1, 1_000).unwrap();
+        assert_eq!(pool.reward_numerator, 1);
+        assert_eq!(pool.rate_checkpoints[0].epoch, 2);
+        assert_eq!(pool.rate_checkpoints[MAX_RATE_CHECKPOINTS - 1].epoch, 100);
+    }
+
+    #[test]
+    fn test_update_reward_owed_clock_backwards() {
+        let pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let mut stake_user = StakeUser {
+            stake_amount: 1_000_000,
+            reward_owed: 5,
+            last_update: 200,
+            ..StakeUser::default()
+        };
+
+        // last_update in the future
+        stake_user.update_reward_owed(&pool, 100).unwrap();
+        assert_eq!(stake_user.reward_owed, 5);
+        assert_eq!(stake_user.last_update, 200);
+
+        // extreme timestamps never panic
+        stake_user.update_reward_owed(&pool, i64::MIN).unwrap();
+        assert_eq!(stake_user.reward_owed, 5);
+        assert_eq!(pool.accrue(1, i64::MIN, i64::MAX).unwrap(), 0);
+        assert_eq!(pool.accrue(1_000_000, i64::MAX, i64::MIN).unwrap(), 0);
+        assert_eq!(elapsed(i64::MIN, i64::MAX), u64::MAX);
+
+        // accrues again once the clock caught up
+        stake_user
+            .update_reward_owed(&pool, 200 + DAILY_TS as i64)
+            .unwrap();
+        assert_eq!(stake_user.reward_owed, 1_005);
+        assert_eq!(stake_user.last_update, 200 + DAILY_TS as i64);
+    }
+
+    #[test]
+    fn test_rate_change_checkpoints() {
+        let mut pool = Pool {
+            reward_numerator: 1,
+            reward_denominator: 1_000,
+            reward_period: DAILY_TS,
+            ..Pool::default()
+        };
+        let stake_amount = 1_000_000;
+        let day = DAILY_TS as i64;
+
+        pool.checkpoint_rate(2 * day, 3, 1_000).unwrap();
+        assert_eq!(pool.current_rate(), (3, 1_000));
+        assert!(!pool.is_epoch_based());
+
+        // a user not refreshed since day 0 earns the old rate for the old period
+        let mut stake_user = StakeUser {
+            stake_amount,
+            ..StakeUser::default()
+        };
+        stake_user.update_reward_owed(&pool, 3 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 2_000 + 3_000);
+
+        // a scheduled epoch goes after the rate change
+        pool.set_epoch_rate(5, 2, 1_000).unwrap();
+        pool.checkpoint_rate(4 * day, 4, 1_000).unwrap();
+        assert_eq!(pool.rate_checkpoints[1].start_ts, 4 * day);
+        assert!(pool.rate_checkpoints[2].is_pending());
+        pool.observe_epoch(5, 5 * day);
+        assert_eq!(pool.current_rate(), (2, 1_000));
+        stake_user.update_reward_owed(&pool, 6 * day).unwrap();
+        assert_eq!(stake_user.reward_owed, 5_000 + 3_000 + 4_000 + 2_000);
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Stake(u64),
+        Unstake(u64),
+        Claim,
+        Refresh,
+        Advance(i64),
+    }
+
+    fn op_strategy() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            prop_oneof![0..1_000_000_000u64, Just(u64::MAX), any::<u64>()].prop_map(Op::Stake),
+            any::<u64>().prop_map(Op::Unstake),
+            Just(Op::Claim),
+            Just(Op::Refresh),
+            // up to five years, and the clock occasionally going backwards
+            (-(WEEKLY_TS as i64)..5 * 365 * DAILY_TS as i64).prop_map(Op::Advance),
+        ]
+    }
+
+    fn pool_strategy() -> impl Strategy<Value = Pool> {
+        (
+            0..10_000u64,
+            1..10_000u64,
+            prop_oneof![Just(HOURLY_TS), Just(DAILY_TS), Just(WEEKLY_TS)],
+        )
+            .prop_map(
+                |(reward_numerator, reward_denominator, reward_period)| Pool {
+                    reward_numerator,
+                    reward_denominator,
+                    reward_period,
+                    ..Pool::default()
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_accrual_refresh_never_earns_more(
+            pool in pool_strategy(),
+            stake_amount in prop_oneof![0..1_000_000_000u64, Just(u64::MAX), any::<u64>()],
+            from_ts in 0..i64::MAX / 2,
+            first in 0..10 * 365 * DAILY_TS as i64,
+            second in 0..10 * 365 * DAILY_TS as i64,
+        ) {
+            let mid_ts = from_ts + first;
+            let to_ts = mid_ts + second;
+            // overflowing accruals fail instead of panicking or wrapping
+            if let Ok(whole) = pool.accrue(stake_amount, from_ts, to_ts) {
+                let split = pool.accrue(stake_amount, from_ts, mid_ts).unwrap()
+                    + pool.accrue(stake_amount, mid_ts, to_ts).unwrap();
+                prop_assert!(split <= whole);
+            }
+        }
+
+        #[test]
+        fn prop_pending_reward_matches_accrual(
+            pool in pool_strategy(),
+            stake_amount in 0..1_000_000_000u64,
+            from_ts in 0..i64::MAX / 2,
+            elapsed in 0..10 * 365 * DAILY_TS as i64,
+        ) {
+            let stake_user = StakeUser {
+                stake_amount,
+                last_update: from_ts,
+                ..StakeUser::default()
+            };
+            let mut accrued = stake_user.clone();
+            if accrued.update_reward_owed(&pool, from_ts + elapsed).is_ok() {
+                prop_assert_eq!(
+                    stake_user.pending_reward(&pool, from_ts + elapsed).unwrap(),
+                    accrued.reward_owed
+                );
+            }
+        }
+
+        #[test]
+        fn prop_accrual_operation_sequences(
+            pool in pool_strategy(),
+            start_ts in prop_oneof![Just(0i64), 0..i64::MAX / 2],
+            ops in prop::collection::vec(op_strategy(), 1..50),
+        ) {
+            let mut pool = pool;
+            let mut stake_user = StakeUser {
+                last_update: start_ts,
+                ..StakeUser::default()
+            };
+            let mut now = start_ts;
+            let mut accrued: u128 = 0;
+            let mut claimed: u128 = 0;
+
+            for op in ops {
+                if let Op::Advance(secs) = op {
+                    now = now.saturating_add(secs);
+                    continue;
+                }
+
+                // failed instructions leave the accounts untouched
+                let (pool_before, user_before) = (pool.clone(), stake_user.clone());
+                let owed_before = stake_user.reward_owed;
+                let result = stake_user.update_reward_owed(&pool, now).and_then(|_| match op {
+                    Op::Stake(amount) => stake_user.stake(amount).and_then(|_| pool.stake(amount)).map(|_| 0),
+                    Op::Unstake(amount) => stake_user.unstake(amount).and_then(|_| pool.unstake(amount)).map(|_| 0),
+                    Op::Claim => stake_user.claim(),
+                    _ => Ok(0),
+                });
+                match result {
+                    Ok(payout) => {
+                        let owed_after = stake_user.reward_owed as u128 + payout as u128;
+                        // rewards only grow until claimed
+                        prop_assert!(owed_after >= owed_before as u128);
+                        accrued += owed_after - owed_before as u128;
+                        claimed += payout as u128;
+                    }
+                    Err(_) => {
+                        pool = pool_before;
+                        stake_user = user_before;
+                    }
+                }
+
+                prop_assert_eq!(pool.total_staked, stake_user.stake_amount);
+                prop_assert_eq!(accrued, claimed + stake_user.reward_owed as u128);
+            }
+        }
+    }
 }
@@ -0,0 +1,46 @@
+//! Stake mints of Token-2022 pools, which Stake and Unstake move with
+//! `transfer_checked`. A mint with a transfer hook has Token-2022 call the
+//! hook program on every transfer, with the extra accounts its validation
+//! account lists, so those come with the instruction too.
+//!
+//! The stake mint precedes the pool hook program and its accounts, if any,
+//! which may list the stake mint themselves, and the instructions sysvar of
+//! top-level-only pools. The transfer hook program, its validation account and
+//! the extra accounts it lists follow the mint, in any order.
+
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+
+use crate::state::Pool;
+
+/// Split the accounts of a `Stake` or `Unstake`, the pool hook accounts split
+/// off already, into its own accounts and the stake mint followed by the
+/// transfer hook accounts, starting at the first stake mint of the pool. The
+/// pool is the first account.
+pub fn split_transfer_hook_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<(&'a [AccountInfo<'b>], &'a [AccountInfo<'b>]), ProgramError> {
+    let stake_pool = match accounts.first() {
+        Some(stake_pool_info) if stake_pool_info.owner == program_id => {
+            Pool::unpack(&stake_pool_info.data.borrow())?
+        }
+        _ => return Ok((accounts, &[])),
+    };
+
+    let is_stake_mint = |key: &Pubkey| {
+        *key == stake_pool.stake_token_mint
+            || stake_pool
+                .stake_mints
+                .iter()
+                .any(|stake_mint| !stake_mint.is_empty() && stake_mint.mint == *key)
+    };
+    match accounts
+        .iter()
+        .position(|account_info| is_stake_mint(account_info.key))
+    {
+        Some(position) => Ok(accounts.split_at(position)),
+        None => Ok((accounts, &[])),
+    }
+}